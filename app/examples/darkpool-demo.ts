@@ -15,7 +15,7 @@ async function submitDarkOrder() {
   const provider = new AnchorProvider(connection, wallet, {});
 
   // Program IDs (these would be actual deployed program IDs)
-  const darkpoolProgramId = new PublicKey('DarkP00LMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
+  const darkpoolProgramId = new PublicKey('BbtSLsMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
   const perpetualsProgramId = new PublicKey('E4digHpb1shbPFxdQTd6D6tnDnNYznrnNPoduHDtEn9H');
   const arciumProgramId = new PublicKey('Arc1um1111111111111111111111111111111111111');
 
@@ -93,7 +93,7 @@ async function initializeDarkpool() {
   const wallet = new Wallet(authority);
   const provider = new AnchorProvider(connection, wallet, {});
 
-  const darkpoolProgramId = new PublicKey('DarkP00LMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
+  const darkpoolProgramId = new PublicKey('BbtSLsMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
   const perpetualsProgramId = new PublicKey('E4digHpb1shbPFxdQTd6D6tnDnNYznrnNPoduHDtEn9H');
   const arciumProgramId = new PublicKey('Arc1um1111111111111111111111111111111111111');
 
@@ -142,7 +142,7 @@ async function matchOrders() {
   const wallet = new Wallet(matcher);
   const provider = new AnchorProvider(connection, wallet, {});
 
-  const darkpoolProgramId = new PublicKey('DarkP00LMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
+  const darkpoolProgramId = new PublicKey('BbtSLsMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL');
   const perpetualsProgramId = new PublicKey('E4digHpb1shbPFxdQTd6D6tnDnNYznrnNPoduHDtEn9H');
   const arciumProgramId = new PublicKey('Arc1um1111111111111111111111111111111111111');
 
@@ -157,20 +157,19 @@ async function matchOrders() {
 
     console.log('🔄 Triggering order matching...');
     
-    // In a real implementation, this would contain encrypted orders from the order book
-    const mockEncryptedOrders = Buffer.alloc(1024); // Placeholder for encrypted order batch
-    const arciumPublicKey = new PublicKey('Arc1umPubK3y111111111111111111111111111111');
-
-    const txid = await darkpoolClient.matchDarkOrders(
-      matcher,
-      mockEncryptedOrders,
-      arciumPublicKey
+    // Encrypted orders are read by the MPC computation from the market order book
+    const orderBook = darkpoolClient.getOrderBookAddress(
+      new PublicKey('PoOL1111111111111111111111111111111111111'), // Example pool
+      new PublicKey('Custd111111111111111111111111111111111111'), // SOL custody
+      new PublicKey('USDc111111111111111111111111111111111111') // USDC custody
     );
 
+    const txid = await darkpoolClient.matchDarkOrders(matcher, orderBook);
+
     console.log('✅ Order matching initiated!');
     console.log('Transaction ID:', txid);
     console.log('⏳ MPC network is processing encrypted orders...');
-    console.log('Results will be available via callback when matching completes.');
+    console.log('Matched trades are recorded in the settlement queue when matching completes.');
 
   } catch (error) {
     console.error('❌ Error matching orders:', error);
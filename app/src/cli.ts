@@ -101,6 +101,8 @@ async function addCustody(
     maxUtilization: new BN(10_000),
     maxPositionLockedUsd: new BN(1_000_000_000),
     maxTotalLockedUsd: new BN(1_000_000_000),
+    priceImpactMult: new BN(0),
+    maxPriceImpact: new BN(0),
   };
   const permissions: Permissions = {
     allowSwap: true,
@@ -244,10 +246,11 @@ async function getUserPosition(
   wallet: PublicKey,
   poolName: string,
   tokenMint: PublicKey,
-  side: PositionSide
+  side: PositionSide,
+  index: number
 ): Promise<void> {
   client.prettyPrint(
-    await client.getUserPosition(wallet, poolName, tokenMint, side)
+    await client.getUserPosition(wallet, poolName, tokenMint, side, index)
   );
 }
 
@@ -310,10 +313,17 @@ async function getExitPriceAndFee(
   wallet: PublicKey,
   poolName: string,
   tokenMint: PublicKey,
-  side: PositionSide
+  side: PositionSide,
+  index: number
 ): Promise<void> {
   client.prettyPrint(
-    await client.getExitPriceAndFee(wallet, poolName, tokenMint, side)
+    await client.getExitPriceAndFee(
+      wallet,
+      poolName,
+      tokenMint,
+      side,
+      index
+    )
   );
 }
 
@@ -340,6 +350,7 @@ async function getLiquidationPrice(
   poolName: string,
   tokenMint: PublicKey,
   side: PositionSide,
+  index: number,
   addCollateral: BN,
   removeCollateral: BN
 ): Promise<void> {
@@ -348,8 +359,15 @@ async function getLiquidationPrice(
       wallet,
       poolName,
       tokenMint,
-      await client.getCollateralCustodyMint(wallet, poolName, tokenMint, side),
+      await client.getCollateralCustodyMint(
+        wallet,
+        poolName,
+        tokenMint,
+        side,
+        index
+      ),
       side,
+      index,
       addCollateral,
       removeCollateral
     )
@@ -360,15 +378,23 @@ async function getLiquidationState(
   wallet: PublicKey,
   poolName: string,
   tokenMint: PublicKey,
-  side: PositionSide
+  side: PositionSide,
+  index: number
 ): Promise<void> {
   client.prettyPrint(
     await client.getLiquidationState(
       wallet,
       poolName,
       tokenMint,
-      await client.getCollateralCustodyMint(wallet, poolName, tokenMint, side),
-      side
+      await client.getCollateralCustodyMint(
+        wallet,
+        poolName,
+        tokenMint,
+        side,
+        index
+      ),
+      side,
+      index
     )
   );
 }
@@ -377,15 +403,23 @@ async function getPnl(
   wallet: PublicKey,
   poolName: string,
   tokenMint: PublicKey,
-  side: PositionSide
+  side: PositionSide,
+  index: number
 ): Promise<void> {
   client.prettyPrint(
     await client.getPnl(
       wallet,
       poolName,
       tokenMint,
-      await client.getCollateralCustodyMint(wallet, poolName, tokenMint, side),
-      side
+      await client.getCollateralCustodyMint(
+        wallet,
+        poolName,
+        tokenMint,
+        side,
+        index
+      ),
+      side,
+      index
     )
   );
 }
@@ -625,12 +659,14 @@ async function getAum(poolName: string): Promise<void> {
     .argument("<string>", "Pool name")
     .argument("<pubkey>", "Token mint")
     .argument("<string>", "Position side (long / short)")
-    .action(async (wallet, poolName, tokenMint, side) => {
+    .option("-i, --index <int>", "Position index", "0")
+    .action(async (wallet, poolName, tokenMint, side, options) => {
       await getUserPosition(
         new PublicKey(wallet),
         poolName,
         new PublicKey(tokenMint),
-        side
+        side,
+        parseInt(options.index)
       );
     });
 
@@ -713,12 +749,14 @@ async function getAum(poolName: string): Promise<void> {
     .argument("<string>", "Pool name")
     .argument("<pubkey>", "Token mint")
     .argument("<string>", "Position side (long / short)")
-    .action(async (wallet, poolName, tokenMint, side) => {
+    .option("-i, --index <int>", "Position index", "0")
+    .action(async (wallet, poolName, tokenMint, side, options) => {
       await getExitPriceAndFee(
         new PublicKey(wallet),
         poolName,
         new PublicKey(tokenMint),
-        side
+        side,
+        parseInt(options.index)
       );
     });
 
@@ -756,6 +794,7 @@ async function getAum(poolName: string): Promise<void> {
     .argument("<string>", "Pool name")
     .argument("<pubkey>", "Token mint")
     .argument("<string>", "Position side (long / short)")
+    .option("-i, --index <int>", "Position index", "0")
     .option("-a, --add-collateral <bigint>", "Collateral to add")
     .option("-r, --remove-collateral <bigint>", "Collateral to remove")
     .action(async (wallet, poolName, tokenMint, side, options) => {
@@ -764,6 +803,7 @@ async function getAum(poolName: string): Promise<void> {
         poolName,
         new PublicKey(tokenMint),
         side,
+        parseInt(options.index),
         new BN(options.addCollateral),
         new BN(options.removeCollateral)
       );
@@ -776,12 +816,14 @@ async function getAum(poolName: string): Promise<void> {
     .argument("<string>", "Pool name")
     .argument("<pubkey>", "Token mint")
     .argument("<string>", "Position side (long / short)")
-    .action(async (wallet, poolName, tokenMint, side) => {
+    .option("-i, --index <int>", "Position index", "0")
+    .action(async (wallet, poolName, tokenMint, side, options) => {
       await getLiquidationState(
         new PublicKey(wallet),
         poolName,
         new PublicKey(tokenMint),
-        side
+        side,
+        parseInt(options.index)
       );
     });
 
@@ -792,12 +834,14 @@ async function getAum(poolName: string): Promise<void> {
     .argument("<string>", "Pool name")
     .argument("<pubkey>", "Token mint")
     .argument("<string>", "Position side (long / short)")
-    .action(async (wallet, poolName, tokenMint, side) => {
+    .option("-i, --index <int>", "Position index", "0")
+    .action(async (wallet, poolName, tokenMint, side, options) => {
       await getPnl(
         new PublicKey(wallet),
         poolName,
         new PublicKey(tokenMint),
-        side
+        side,
+        parseInt(options.index)
       );
     });
 
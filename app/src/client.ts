@@ -160,6 +160,13 @@ export class PerpetualsClient {
     ]).publicKey;
   };
 
+  getCustodyTokenProgram = async (
+    poolName: string,
+    tokenMint: PublicKey
+  ): Promise<PublicKey> => {
+    return (await this.getCustody(poolName, tokenMint)).tokenProgram;
+  };
+
   getCustodyOracleAccountKey = async (
     poolName: string,
     tokenMint: PublicKey
@@ -232,10 +239,11 @@ export class PerpetualsClient {
     wallet: PublicKey,
     poolName: string,
     tokenMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ): Promise<PublicKey> => {
     const custodyAccount = (
-      await this.getUserPosition(wallet, poolName, tokenMint, side)
+      await this.getUserPosition(wallet, poolName, tokenMint, side, index)
     ).collateralCustody;
 
     return (await this.program.account.custody.fetch(custodyAccount)).mint;
@@ -249,7 +257,8 @@ export class PerpetualsClient {
     wallet: PublicKey,
     poolName: string,
     tokenMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ): PublicKey => {
     const pool = this.getPoolKey(poolName);
     const custody = this.getCustodyKey(poolName, tokenMint);
@@ -258,7 +267,8 @@ export class PerpetualsClient {
       wallet,
       pool,
       custody,
-      side === "long" ? [1] : [0],
+      side === "long" ? [1] : [2],
+      new BN(index).toArray("le", 8),
     ]).publicKey;
   };
 
@@ -266,13 +276,26 @@ export class PerpetualsClient {
     wallet: PublicKey,
     poolName: string,
     tokenMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ) => {
     return this.program.account.position.fetch(
-      this.getPositionKey(wallet, poolName, tokenMint, side)
+      this.getPositionKey(wallet, poolName, tokenMint, side, index)
     );
   };
 
+  getPositionCounterKey = (wallet: PublicKey): PublicKey => {
+    return this.findProgramAddress("position_counter", [wallet]).publicKey;
+  };
+
+  getNextPositionIndex = async (wallet: PublicKey): Promise<number> => {
+    const positionCounter =
+      await this.program.account.positionCounter.fetchNullable(
+        this.getPositionCounterKey(wallet)
+      );
+    return positionCounter ? positionCounter.nextIndex.toNumber() : 0;
+  };
+
   getUserPositions = async (wallet: PublicKey) => {
     const data = encode(
       Buffer.concat([
@@ -284,7 +307,7 @@ export class PerpetualsClient {
     const positions = await this.provider.connection.getProgramAccounts(
       this.program.programId,
       {
-        filters: [{ dataSize: 232 }, { memcmp: { bytes: data, offset: 0 } }],
+        filters: [{ dataSize: 240 }, { memcmp: { bytes: data, offset: 0 } }],
       }
     );
 
@@ -304,7 +327,7 @@ export class PerpetualsClient {
     const positions = await this.provider.connection.getProgramAccounts(
       this.program.programId,
       {
-        filters: [{ dataSize: 232 }, { memcmp: { bytes: data, offset: 40 } }],
+        filters: [{ dataSize: 240 }, { memcmp: { bytes: data, offset: 40 } }],
       }
     );
 
@@ -414,7 +437,10 @@ export class PerpetualsClient {
     }
   };
 
-  addPool = async (name: string): Promise<void> => {
+  addPool = async (
+    name: string,
+    lpTokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<void> => {
     await this.program.methods
       .addPool({ name })
       .accounts({
@@ -425,7 +451,7 @@ export class PerpetualsClient {
         pool: this.getPoolKey(name),
         lpTokenMint: this.getPoolLpTokenKey(name),
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram: lpTokenProgram,
         rent: SYSVAR_RENT_PUBKEY,
       })
       .signers([this.admin])
@@ -467,6 +493,11 @@ export class PerpetualsClient {
     borrowRate: BorrowRateParams,
     ratios: TokenRatio[]
   ): Promise<void> => {
+    // SPL Token or Token-2022, whichever owns the mint
+    const tokenProgram = (
+      await this.provider.connection.getAccountInfo(tokenMint)
+    ).owner;
+
     await this.program.methods
       .addCustody({
         isStable,
@@ -491,7 +522,7 @@ export class PerpetualsClient {
         ),
         custodyTokenMint: tokenMint,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram,
         rent: SYSVAR_RENT_PUBKEY,
       })
       .signers([this.admin])
@@ -521,7 +552,7 @@ export class PerpetualsClient {
           tokenMint
         ),
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram: await this.getCustodyTokenProgram(poolName, tokenMint),
       })
       .signers([this.admin])
       .rpc()
@@ -586,6 +617,8 @@ export class PerpetualsClient {
     minLpAmountOut: BN
   ): Promise<void> => {
     const lpTokenMint = this.getPoolLpTokenKey(poolName);
+    const lpTokenProgram = (await this.getPool(poolName)).lpTokenProgram;
+    const tokenProgram = await this.getCustodyTokenProgram(poolName, tokenMint);
 
     await this.program.methods
       .addLiquidity({ amountIn, minLpAmountOut })
@@ -593,11 +626,15 @@ export class PerpetualsClient {
         owner: this.provider.wallet.publicKey,
         fundingAccount: await getAssociatedTokenAddress(
           tokenMint,
-          this.provider.wallet.publicKey
+          this.provider.wallet.publicKey,
+          false,
+          tokenProgram
         ),
         lpTokenAccount: await getAssociatedTokenAddress(
           lpTokenMint,
-          this.provider.wallet.publicKey
+          this.provider.wallet.publicKey,
+          false,
+          lpTokenProgram
         ),
         transferAuthority: this.authority.publicKey,
         perpetuals: this.perpetuals.publicKey,
@@ -611,8 +648,10 @@ export class PerpetualsClient {
           poolName,
           tokenMint
         ),
+        custodyTokenMint: tokenMint,
         lpTokenMint,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram,
+        lpTokenProgram,
       })
       .remainingAccounts(await this.getCustodyMetas(poolName))
       .rpc()
@@ -628,6 +667,7 @@ export class PerpetualsClient {
     tokenMint: PublicKey,
     collateralMint: PublicKey,
     side: PositionSide,
+    index: number,
     receivingAccount: PublicKey,
     rewardsReceivingAccount: PublicKey
   ): Promise<void> => {
@@ -640,7 +680,13 @@ export class PerpetualsClient {
         transferAuthority: this.authority.publicKey,
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
-        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        position: this.getPositionKey(
+          wallet,
+          poolName,
+          tokenMint,
+          side,
+          index
+        ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
           poolName,
@@ -655,7 +701,11 @@ export class PerpetualsClient {
           poolName,
           collateralMint
         ),
-        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralCustodyTokenMint: collateralMint,
+        tokenProgram: await this.getCustodyTokenProgram(
+          poolName,
+          collateralMint
+        ),
       })
       .rpc()
       .catch((err) => {
@@ -673,6 +723,11 @@ export class PerpetualsClient {
     collateral: BN,
     size: BN
   ): Promise<void> => {
+    const tokenProgram = await this.getCustodyTokenProgram(
+      poolName,
+      collateralMint
+    );
+
     await this.program.methods
       .openPosition({
         price,
@@ -681,19 +736,26 @@ export class PerpetualsClient {
         side: side === "long" ? { long: {} } : { short: {} },
       })
       .accounts({
+        authority: this.provider.wallet.publicKey,
         owner: this.provider.wallet.publicKey,
         fundingAccount: await getAssociatedTokenAddress(
           collateralMint,
-          this.provider.wallet.publicKey
+          this.provider.wallet.publicKey,
+          false,
+          tokenProgram
         ),
         transferAuthority: this.authority.publicKey,
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
+        positionCounter: this.getPositionCounterKey(
+          this.provider.wallet.publicKey
+        ),
         position: this.getPositionKey(
           this.provider.wallet.publicKey,
           poolName,
           tokenMint,
-          side
+          side,
+          await this.getNextPositionIndex(this.provider.wallet.publicKey)
         ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
@@ -709,8 +771,10 @@ export class PerpetualsClient {
           poolName,
           collateralMint
         ),
+        collateralCustodyTokenMint: collateralMint,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram,
+        tradingDelegate: null,
       })
       .rpc()
       .catch((err) => {
@@ -825,6 +889,7 @@ export class PerpetualsClient {
           poolName,
           collateralMint
         ),
+        owner: this.provider.wallet.publicKey,
       })
       .view()
       .catch((err) => {
@@ -837,14 +902,21 @@ export class PerpetualsClient {
     wallet: PublicKey,
     poolName: string,
     tokenMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ): Promise<PriceAndFee> => {
     return this.program.methods
       .getExitPriceAndFee({})
       .accounts({
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
-        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        position: this.getPositionKey(
+          wallet,
+          poolName,
+          tokenMint,
+          side,
+          index
+        ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
           poolName,
@@ -864,6 +936,7 @@ export class PerpetualsClient {
     tokenMint: PublicKey,
     collateralMint: PublicKey,
     side: PositionSide,
+    index: number,
     addCollateral: BN,
     removeCollateral: BN
   ): Promise<BN> => {
@@ -875,7 +948,13 @@ export class PerpetualsClient {
       .accounts({
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
-        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        position: this.getPositionKey(
+          wallet,
+          poolName,
+          tokenMint,
+          side,
+          index
+        ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
           poolName,
@@ -899,14 +978,21 @@ export class PerpetualsClient {
     poolName: string,
     tokenMint: PublicKey,
     collateralMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ): Promise<number> => {
     return this.program.methods
       .getLiquidationState({})
       .accounts({
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
-        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        position: this.getPositionKey(
+          wallet,
+          poolName,
+          tokenMint,
+          side,
+          index
+        ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
           poolName,
@@ -930,14 +1016,21 @@ export class PerpetualsClient {
     poolName: string,
     tokenMint: PublicKey,
     collateralMint: PublicKey,
-    side: PositionSide
+    side: PositionSide,
+    index: number
   ): Promise<ProfitAndLoss> => {
     return this.program.methods
       .getPnl({})
       .accounts({
         perpetuals: this.perpetuals.publicKey,
         pool: this.getPoolKey(poolName),
-        position: this.getPositionKey(wallet, poolName, tokenMint, side),
+        position: this.getPositionKey(
+          wallet,
+          poolName,
+          tokenMint,
+          side,
+          index
+        ),
         custody: this.getCustodyKey(poolName, tokenMint),
         custodyOracleAccount: await this.getCustodyOracleAccountKey(
           poolName,
@@ -14,7 +14,6 @@ import {
   web3,
   utils,
 } from '@coral-xyz/anchor';
-import { TOKEN_PROGRAM_ID } from '@solana/spl-token';
 import * as crypto from 'crypto';
 
 // Types for darkpool orders
@@ -33,18 +32,38 @@ export interface DarkOrder {
 }
 
 export interface EncryptedOrder {
-  encryptedData: Buffer;
+  // One ciphertext per secret order field: side, sizeUsd, collateralAmount,
+  // maxPrice, leverage, timestamp, nonce
+  ciphertexts: number[][];
   pubKey: Buffer;
   nonce: number;
 }
 
+// Order validation reason codes, encrypted for the order owner in the
+// DarkOrderValidated event. Invalid orders never match and should be
+// cancelled to free their slot.
+export enum DarkOrderReason {
+  Valid = 0,
+  InvalidSide = 1,
+  InvalidSize = 2,
+  InvalidPrice = 3,
+  InsufficientCollateral = 4,
+  LeverageTooLow = 5,
+  LeverageTooHigh = 6,
+  InsufficientEscrow = 7,
+}
+
+// Trade revealed by a matching computation, recorded in the settlement queue
+// of its order book
 export interface TradeSettlement {
-  traderA: PublicKey;
-  traderB: PublicKey;
+  tradeId: BN;
+  traderA: PublicKey; // Long
+  traderB: PublicKey; // Short
   sizeUsd: BN;
   price: BN;
-  pool: PublicKey;
-  custody: PublicKey;
+  collateralA: BN;
+  collateralB: BN;
+  timestamp: BN;
 }
 
 export interface MatchResult {
@@ -102,6 +121,199 @@ export class DarkpoolClient {
     return await this.provider.sendAndConfirm(tx, [authority]);
   }
 
+  /**
+   * Initialize the encrypted order book of a market
+   */
+  async initializeOrderBook(
+    authority: Keypair,
+    params: {
+      pool: PublicKey;
+      custody: PublicKey;
+      collateralCustody: PublicKey;
+      priceBandBps: number;
+    }
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .initializeOrderBook({ ...params, priceBandBps: new BN(params.priceBandBps) })
+      .accounts({
+        authority: authority.publicKey,
+        darkpool: darkpoolPda,
+        orderBook: this.getOrderBookAddress(
+          params.pool,
+          params.custody,
+          params.collateralCustody
+        ),
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [authority]);
+  }
+
+  /**
+   * Get the order book PDA of a market
+   */
+  getOrderBookAddress(
+    pool: PublicKey,
+    custody: PublicKey,
+    collateralCustody: PublicKey
+  ): PublicKey {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    return PublicKey.findProgramAddressSync(
+      [
+        Buffer.from('order_book'),
+        darkpoolPda.toBuffer(),
+        pool.toBuffer(),
+        custody.toBuffer(),
+        collateralCustody.toBuffer(),
+      ],
+      this.darkpoolProgram.programId
+    )[0];
+  }
+
+  /**
+   * Set the maximum distance of execution prices from the oracle price
+   */
+  async setPriceBand(
+    authority: Keypair,
+    orderBook: PublicKey,
+    priceBandBps: number
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .setPriceBand({ priceBandBps: new BN(priceBandBps) })
+      .accounts({
+        authority: authority.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [authority]);
+  }
+
+  /**
+   * Initialize the batch auction epochs of an order book
+   */
+  async initializeAuctionEpoch(
+    authority: Keypair,
+    orderBook: PublicKey,
+    epochDuration: number
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .initializeAuctionEpoch({ epochDuration: new BN(epochDuration) })
+      .accounts({
+        authority: authority.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+        auctionEpoch: this.getAuctionEpochAddress(orderBook),
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [authority]);
+  }
+
+  /**
+   * Get the auction epoch PDA of an order book
+   */
+  getAuctionEpochAddress(orderBook: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('auction_epoch'), orderBook.toBuffer()],
+      this.darkpoolProgram.programId
+    )[0];
+  }
+
+  /**
+   * Initialize the settlement queue of an order book, required for matching
+   */
+  async initializeSettlementQueue(
+    authority: Keypair,
+    orderBook: PublicKey
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .initializeSettlementQueue()
+      .accounts({
+        authority: authority.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+        settlementQueue: this.getSettlementQueueAddress(orderBook),
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [authority]);
+  }
+
+  /**
+   * Get the settlement queue PDA of an order book
+   */
+  getSettlementQueueAddress(orderBook: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('settlement_queue'), orderBook.toBuffer()],
+      this.darkpoolProgram.programId
+    )[0];
+  }
+
+  /**
+   * Get the collateral escrow PDA of an owner on an order book
+   */
+  getEscrowAddress(orderBook: PublicKey, owner: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('escrow'), orderBook.toBuffer(), owner.toBuffer()],
+      this.darkpoolProgram.programId
+    )[0];
+  }
+
+  /**
+   * Get the matched trades of an order book waiting to be settled
+   */
+  async getPendingTrades(orderBook: PublicKey): Promise<TradeSettlement[]> {
+    const settlementQueue = await this.darkpoolProgram.account.settlementQueue.fetch(
+      this.getSettlementQueueAddress(orderBook)
+    );
+
+    return (settlementQueue.trades as any[])
+      .filter((trade) => trade.pending)
+      .map((trade) => ({
+        tradeId: trade.tradeId,
+        traderA: trade.traderA,
+        traderB: trade.traderB,
+        sizeUsd: trade.sizeUsd,
+        price: trade.price,
+        collateralA: trade.collateralA,
+        collateralB: trade.collateralB,
+        timestamp: trade.timestamp,
+      }));
+  }
+
   /**
    * Initialize computation definitions for darkpool
    */
@@ -135,7 +347,21 @@ export class DarkpoolClient {
       })
       .instruction();
 
-    for (const ix of [submitOrderCompDefIx, matchOrdersCompDefIx, batchProcessCompDefIx]) {
+    // Initialize run_batch_auction computation definition
+    const batchAuctionCompDefIx = await this.darkpoolProgram.methods
+      .initRunBatchAuctionCompDef()
+      .accounts({
+        payer: payer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+    for (const ix of [
+      submitOrderCompDefIx,
+      matchOrdersCompDefIx,
+      batchProcessCompDefIx,
+      batchAuctionCompDefIx,
+    ]) {
       const tx = new Transaction().add(ix);
       const txid = await this.provider.sendAndConfirm(tx, [payer]);
       txs.push(txid);
@@ -149,28 +375,35 @@ export class DarkpoolClient {
    */
   encryptOrder(order: DarkOrder, arciumPublicKey: PublicKey): EncryptedOrder {
     // Serialize the order
-    const orderData = this.serializeOrder(order);
-    
+    const orderFields = this.serializeOrder(order);
+
     // Simple encryption for demo purposes
     // In production, use proper Arcium encryption
     const nonce = Date.now();
-    const encryptedData = Buffer.alloc(256);
-    orderData.copy(encryptedData);
+    const ciphertexts = orderFields.map((field) => {
+      const ciphertext = Buffer.alloc(32);
+      field.copy(ciphertext);
+      return Array.from(ciphertext);
+    });
 
     return {
-      encryptedData,
+      ciphertexts,
       pubKey: Buffer.from(arciumPublicKey.toBytes()),
       nonce,
     };
   }
 
   /**
-   * Submit an encrypted order to the darkpool
+   * Submit an encrypted order to the darkpool, expiresAt is a unix timestamp
+   * (0 keeps the order until it is cancelled). escrowAmount collateral tokens
+   * are deposited in the owner's escrow and must cover the order collateral.
    */
   async submitDarkOrder(
     owner: Keypair,
     order: DarkOrder,
-    arciumPublicKey: PublicKey
+    arciumPublicKey: PublicKey,
+    expiresAt: number = 0,
+    escrowAmount: BN = order.collateralAmount
   ): Promise<string> {
     const encryptedOrder = this.encryptOrder(order, arciumPublicKey);
     const computationOffset = Date.now(); // Unique computation ID
@@ -190,16 +423,49 @@ export class DarkpoolClient {
     const feePoolAccount = await this.getFeePoolAccount();
     const clockAccount = await this.getClockAccount();
 
+    // Margin is checked against the custody leverage limits, with the
+    // collateral valued at the collateral custody oracle price
+    const [perpetualsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('perpetuals')],
+      this.perpetualsProgram.programId
+    );
+    const orderBook = this.getOrderBookAddress(
+      order.pool,
+      order.custody,
+      order.collateralCustody
+    );
+    const collateralCustodyAccount = await this.perpetualsProgram.account.custody.fetch(
+      order.collateralCustody
+    );
+
     const ix = await this.darkpoolProgram.methods
       .submitDarkOrder(
         new BN(computationOffset),
-        Array.from(encryptedOrder.encryptedData),
+        encryptedOrder.ciphertexts,
         Array.from(encryptedOrder.pubKey),
-        new BN(encryptedOrder.nonce)
+        new BN(encryptedOrder.nonce),
+        new BN(expiresAt),
+        escrowAmount
       )
       .accounts({
         owner: owner.publicKey,
         darkpool: darkpoolPda,
+        orderBook,
+        perpetualsProgram: this.perpetualsProgram.programId,
+        perpetuals: perpetualsPda,
+        pool: order.pool,
+        custody: order.custody,
+        collateralCustody: order.collateralCustody,
+        collateralCustodyOracleAccount: await this.getOracleAccount(
+          order.collateralCustody
+        ),
+        fundingAccount: await this.getFundingAccount(
+          owner.publicKey,
+          collateralCustodyAccount.mint
+        ),
+        escrow: this.getEscrowAddress(orderBook, owner.publicKey),
+        collateralMint: collateralCustodyAccount.mint,
+        tokenProgram: collateralCustodyAccount.tokenProgram,
         mxeAccount,
         mempoolAccount,
         executingPool: execPoolAccount,
@@ -218,15 +484,68 @@ export class DarkpoolClient {
   }
 
   /**
-   * Match dark orders (typically called by authorized matchers)
+   * Cancel an active order, free its order book slot and refund the escrowed
+   * collateral no longer backing an order or a pending trade
    */
-  async matchDarkOrders(
-    matcher: Keypair,
-    encryptedOrders: Buffer,
-    arciumPublicKey: PublicKey
+  async cancelDarkOrder(
+    owner: Keypair,
+    orderBook: PublicKey,
+    slot: number
   ): Promise<string> {
+    const ix = await this.darkpoolProgram.methods
+      .cancelDarkOrder(slot)
+      .accounts(await this.getEscrowRefundAccounts(owner.publicKey, orderBook))
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [owner]);
+  }
+
+  /**
+   * Withdraw the escrowed collateral left over by filled, expired or
+   * rejected orders
+   */
+  async withdrawEscrow(owner: Keypair, orderBook: PublicKey): Promise<string> {
+    const ix = await this.darkpoolProgram.methods
+      .withdrawEscrow()
+      .accounts(await this.getEscrowRefundAccounts(owner.publicKey, orderBook))
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [owner]);
+  }
+
+  /**
+   * Remove an expired order from an order book, can be called by anyone
+   */
+  async expireDarkOrder(
+    caller: Keypair,
+    orderBook: PublicKey,
+    slot: number
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .expireDarkOrder(slot)
+      .accounts({
+        caller: caller.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [caller]);
+  }
+
+  /**
+   * Match the orders of a market order book (typically called by authorized matchers)
+   */
+  async matchDarkOrders(matcher: Keypair, orderBook: PublicKey): Promise<string> {
     const computationOffset = Date.now();
-    const nonce = Date.now();
 
     const [darkpoolPda] = PublicKey.findProgramAddressSync(
       [Buffer.from('darkpool')],
@@ -243,16 +562,27 @@ export class DarkpoolClient {
     const feePoolAccount = await this.getFeePoolAccount();
     const clockAccount = await this.getClockAccount();
 
+    // Execution prices are checked against the custody oracle price
+    const orderBookAccount = await this.darkpoolProgram.account.darkOrderBook.fetch(orderBook);
+    const [perpetualsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('perpetuals')],
+      this.perpetualsProgram.programId
+    );
+
     const ix = await this.darkpoolProgram.methods
-      .matchDarkOrders(
-        new BN(computationOffset),
-        Array.from(encryptedOrders),
-        Array.from(arciumPublicKey.toBuffer()),
-        new BN(nonce)
-      )
+      .matchDarkOrders(new BN(computationOffset))
       .accounts({
         matcher: matcher.publicKey,
         darkpool: darkpoolPda,
+        orderBook,
+        settlementQueue: this.getSettlementQueueAddress(orderBook),
+        perpetualsProgram: this.perpetualsProgram.programId,
+        perpetuals: perpetualsPda,
+        pool: orderBookAccount.pool,
+        custody: orderBookAccount.custody,
+        custodyOracleAccount: await this.getOracleAccount(
+          orderBookAccount.custody as PublicKey
+        ),
         mxeAccount,
         mempoolAccount,
         executingPool: execPoolAccount,
@@ -271,107 +601,188 @@ export class DarkpoolClient {
   }
 
   /**
-   * Settle darkpool trades with the perpetuals program
+   * Clear all orders of an order book at a single price, once the current
+   * auction epoch has ended
    */
-  async settleDarkPoolTrade(
-    authority: Keypair,
-    tradeData: {
-      traderA: PublicKey;
-      traderB: PublicKey;
-      sideA: number;
-      sideB: number;
-      sizeUsd: BN;
-      price: BN;
-      pool: PublicKey;
-      custody: PublicKey;
-      collateralCustody: PublicKey;
-      timestamp: BN;
-      darkpoolSignature: number[];
-    },
-    collateralAmountA: BN,
-    collateralAmountB: BN,
-    maxPriceSlippage: number = 100 // 1% in bps
-  ): Promise<string> {
-    // Get position PDAs
-    const [positionA] = PublicKey.findProgramAddressSync(
-      [
-        Buffer.from('position'),
-        tradeData.traderA.toBuffer(),
-        tradeData.pool.toBuffer(),
-        tradeData.custody.toBuffer(),
-        Buffer.from([tradeData.sideA])
-      ],
-      this.perpetualsProgram.programId
+  async runBatchAuction(matcher: Keypair, orderBook: PublicKey): Promise<string> {
+    const computationOffset = Date.now();
+
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
     );
 
-    const [positionB] = PublicKey.findProgramAddressSync(
-      [
-        Buffer.from('position'),
-        tradeData.traderB.toBuffer(),
-        tradeData.pool.toBuffer(),
-        tradeData.custody.toBuffer(),
-        Buffer.from([tradeData.sideB])
-      ],
+    // Get Arcium accounts
+    const mxeAccount = await this.getMxeAccount();
+    const mempoolAccount = await this.getMempoolAccount();
+    const execPoolAccount = await this.getExecPoolAccount();
+    const computationAccount = await this.getComputationAccount(computationOffset);
+    const compDefAccount = await this.getCompDefAccount('run_batch_auction');
+    const clusterAccount = await this.getClusterAccount();
+    const feePoolAccount = await this.getFeePoolAccount();
+    const clockAccount = await this.getClockAccount();
+
+    // The clearing price is checked against the custody oracle price
+    const orderBookAccount = await this.darkpoolProgram.account.darkOrderBook.fetch(orderBook);
+    const [perpetualsPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('perpetuals')],
       this.perpetualsProgram.programId
     );
 
-    // Get other required accounts
+    const ix = await this.darkpoolProgram.methods
+      .runBatchAuction(new BN(computationOffset))
+      .accounts({
+        matcher: matcher.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+        auctionEpoch: this.getAuctionEpochAddress(orderBook),
+        settlementQueue: this.getSettlementQueueAddress(orderBook),
+        perpetualsProgram: this.perpetualsProgram.programId,
+        perpetuals: perpetualsPda,
+        pool: orderBookAccount.pool,
+        custody: orderBookAccount.custody,
+        custodyOracleAccount: await this.getOracleAccount(
+          orderBookAccount.custody as PublicKey
+        ),
+        mxeAccount,
+        mempoolAccount,
+        executingPool: execPoolAccount,
+        computationAccount,
+        compDefAccount,
+        clusterAccount,
+        poolAccount: feePoolAccount,
+        clockAccount,
+        systemProgram: SystemProgram.programId,
+        arciumProgram: this.arciumProgram.programId,
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [matcher]);
+  }
+
+  /**
+   * Drop a trade still pending a day after it was matched, can be called by
+   * anyone. The collateral backing it is then refundable with withdrawEscrow.
+   */
+  async expireDarkTrade(
+    caller: Keypair,
+    orderBook: PublicKey,
+    tradeId: BN
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+
+    const ix = await this.darkpoolProgram.methods
+      .expireDarkTrade(tradeId)
+      .accounts({
+        caller: caller.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+        settlementQueue: this.getSettlementQueueAddress(orderBook),
+      })
+      .instruction();
+
+    const tx = new Transaction().add(ix);
+    return await this.provider.sendAndConfirm(tx, [caller]);
+  }
+
+  /**
+   * Settle a matched trade, the darkpool program opens both positions on the
+   * perpetuals program. Traders must have approved the darkpool PDA as delegate
+   * of their collateral accounts.
+   */
+  async settleDarkPoolTrade(
+    settler: Keypair,
+    orderBook: PublicKey,
+    trade: TradeSettlement
+  ): Promise<string> {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
     const [perpetualsPda] = PublicKey.findProgramAddressSync(
       [Buffer.from('perpetuals')],
       this.perpetualsProgram.programId
     );
 
-    const [transferAuthority] = PublicKey.findProgramAddressSync(
-      [Buffer.from('transfer_authority')],
-      this.perpetualsProgram.programId
+    const book = await this.darkpoolProgram.account.darkOrderBook.fetch(orderBook);
+    const pool = book.pool as PublicKey;
+    const custody = book.custody as PublicKey;
+    const collateralCustody = book.collateralCustody as PublicKey;
+    const collateralCustodyAccount = await this.perpetualsProgram.account.custody.fetch(
+      collateralCustody
     );
 
-    // Get funding accounts (would need to be passed in or derived)
-    const fundingAccountA = await this.getFundingAccount(tradeData.traderA, tradeData.collateralCustody);
-    const fundingAccountB = await this.getFundingAccount(tradeData.traderB, tradeData.collateralCustody);
+    // New positions take the next index of their owner's position counter
+    const getPosition = async (owner: PublicKey, side: number) => {
+      const [positionCounter] = PublicKey.findProgramAddressSync(
+        [Buffer.from('position_counter'), owner.toBuffer()],
+        this.perpetualsProgram.programId
+      );
+      const counter = await this.perpetualsProgram.account.positionCounter.fetchNullable(
+        positionCounter
+      );
+      const index = counter ? (counter.nextIndex as BN) : new BN(0);
+      const [position] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('position'),
+          owner.toBuffer(),
+          pool.toBuffer(),
+          custody.toBuffer(),
+          Buffer.from([side]),
+          index.toArrayLike(Buffer, 'le', 8),
+        ],
+        this.perpetualsProgram.programId
+      );
+      return { positionCounter, position };
+    };
+    // Side enum of the perpetuals program: 1 = long, 2 = short
+    const long = await getPosition(trade.traderA, 1);
+    const short = await getPosition(trade.traderB, 2);
 
-    // Get custody token account
     const [custodyTokenAccount] = PublicKey.findProgramAddressSync(
       [
         Buffer.from('custody_token_account'),
-        tradeData.pool.toBuffer(),
-        tradeData.collateralCustody.toBuffer()
+        pool.toBuffer(),
+        collateralCustodyAccount.mint.toBuffer(),
       ],
       this.perpetualsProgram.programId
     );
 
-    const params = {
-      tradeData,
-      expectedDarkpoolProgram: this.darkpoolProgram.programId,
-      collateralAmountA,
-      collateralAmountB,
-      maxPriceSlippage,
-    };
-
-    const ix = await this.perpetualsProgram.methods
-      .settleDarkPoolTrade(params)
+    const ix = await this.darkpoolProgram.methods
+      .settleDarkPoolTrade(trade.tradeId)
       .accounts({
-        authority: authority.publicKey,
-        transferAuthority,
+        settler: settler.publicKey,
+        darkpool: darkpoolPda,
+        orderBook,
+        settlementQueue: this.getSettlementQueueAddress(orderBook),
+        longOwner: trade.traderA,
+        shortOwner: trade.traderB,
+        escrowLong: this.getEscrowAddress(orderBook, trade.traderA),
+        escrowShort: this.getEscrowAddress(orderBook, trade.traderB),
+        perpetualsProgram: this.perpetualsProgram.programId,
         perpetuals: perpetualsPda,
-        pool: tradeData.pool,
-        custody: tradeData.custody,
-        collateralCustody: tradeData.collateralCustody,
-        custodyOracleAccount: await this.getOracleAccount(tradeData.custody),
-        collateralCustodyOracleAccount: await this.getOracleAccount(tradeData.collateralCustody),
-        positionA,
-        positionB,
-        fundingAccountA,
-        fundingAccountB,
+        pool,
+        positionCounterLong: long.positionCounter,
+        positionLong: long.position,
+        positionCounterShort: short.positionCounter,
+        positionShort: short.position,
+        custody,
+        custodyOracleAccount: await this.getOracleAccount(custody),
+        collateralCustody,
+        collateralCustodyOracleAccount: await this.getOracleAccount(collateralCustody),
         collateralCustodyTokenAccount: custodyTokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralCustodyTokenMint: collateralCustodyAccount.mint,
+        tokenProgram: collateralCustodyAccount.tokenProgram,
         systemProgram: SystemProgram.programId,
-        darkpoolProgram: this.darkpoolProgram.programId,
       })
       .instruction();
 
     const tx = new Transaction().add(ix);
-    return await this.provider.sendAndConfirm(tx, [authority]);
+    return await this.provider.sendAndConfirm(tx, [settler]);
   }
 
   /**
@@ -419,60 +830,51 @@ export class DarkpoolClient {
     return new PublicKey('11111111111111111111111111111111'); // Placeholder
   }
 
-  private async getOracleAccount(custody: PublicKey): Promise<PublicKey> {
-    return new PublicKey('11111111111111111111111111111111'); // Placeholder
-  }
-
-  private serializeOrder(order: DarkOrder): Buffer {
-    // Serialize the order struct to bytes
-    const buffer = Buffer.alloc(256);
-    let offset = 0;
-
-    // owner (32 bytes)
-    Buffer.from(order.owner.toBytes()).copy(buffer, offset);
-    offset += 32;
-
-    // side (1 byte)
-    buffer.writeUInt8(order.side, offset);
-    offset += 1;
-
-    // sizeUsd (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.sizeUsd.toString()), offset);
-    offset += 8;
-
-    // collateralAmount (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.collateralAmount.toString()), offset);
-    offset += 8;
-
-    // maxPrice (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.maxPrice.toString()), offset);
-    offset += 8;
-
-    // leverage (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.leverage.toString()), offset);
-    offset += 8;
-
-    // pool (32 bytes)
-    Buffer.from(order.pool.toBytes()).copy(buffer, offset);
-    offset += 32;
-
-    // custody (32 bytes)
-    Buffer.from(order.custody.toBytes()).copy(buffer, offset);
-    offset += 32;
+  private async getEscrowRefundAccounts(owner: PublicKey, orderBook: PublicKey) {
+    const [darkpoolPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('darkpool')],
+      this.darkpoolProgram.programId
+    );
+    const book = await this.darkpoolProgram.account.darkOrderBook.fetch(orderBook);
+    const collateralCustodyAccount = await this.perpetualsProgram.account.custody.fetch(
+      book.collateralCustody as PublicKey
+    );
 
-    // collateralCustody (32 bytes)
-    Buffer.from(order.collateralCustody.toBytes()).copy(buffer, offset);
-    offset += 32;
+    return {
+      owner,
+      darkpool: darkpoolPda,
+      orderBook,
+      settlementQueue: this.getSettlementQueueAddress(orderBook),
+      escrow: this.getEscrowAddress(orderBook, owner),
+      receivingAccount: await this.getFundingAccount(owner, collateralCustodyAccount.mint),
+      collateralMint: collateralCustodyAccount.mint,
+      tokenProgram: collateralCustodyAccount.tokenProgram,
+    };
+  }
 
-    // timestamp (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.timestamp.toString()), offset);
-    offset += 8;
+  private async getOracleAccount(custody: PublicKey): Promise<PublicKey> {
+    const custodyAccount = await this.perpetualsProgram.account.custody.fetch(custody);
+    return (custodyAccount as any).oracle.oracleAccount;
+  }
 
-    // nonce (8 bytes)
-    buffer.writeBigUInt64LE(BigInt(order.nonce.toString()), offset);
-    offset += 8;
+  private serializeOrder(order: DarkOrder): Buffer[] {
+    // Serialize the secret order fields, owner and market accounts are public
+    // and taken from the order book
+    const u64 = (value: BN): Buffer => {
+      const buffer = Buffer.alloc(8);
+      buffer.writeBigUInt64LE(BigInt(value.toString()));
+      return buffer;
+    };
 
-    return buffer;
+    return [
+      Buffer.from([order.side]),
+      u64(order.sizeUsd),
+      u64(order.collateralAmount),
+      u64(order.maxPrice),
+      u64(order.leverage),
+      u64(order.timestamp),
+      u64(order.nonce),
+    ];
   }
 }
 
@@ -41,7 +41,8 @@ async function processLiquidations(
       poolName,
       tokenMint,
       collateralMint,
-      positionSide
+      positionSide,
+      position.index.toNumber()
     );
 
     if (state === 1) {
@@ -64,6 +65,7 @@ async function processLiquidations(
           tokenMint,
           collateralMint,
           positionSide,
+          position.index.toNumber(),
           userTokenAccount,
           rewardReceivingAccount
         );
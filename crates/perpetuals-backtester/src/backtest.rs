@@ -0,0 +1,613 @@
+//! Replays price series through the simulated market
+//!
+//! Every step the oracle keeper publishes the last known price of each asset,
+//! the liquidation keeper liquidates over-leveraged positions, traders close
+//! positions they no longer want to hold and open new ones. Steps are aligned
+//! to price data, gaps between price files are skipped, so positions held over
+//! a gap accrue borrow interest for its whole duration.
+
+use {
+    crate::{
+        config::{AssetConfig, BacktestConfig, TradersConfig},
+        market::Market,
+        prices::PriceSeries,
+        report::{AssetSnapshot, PoolSnapshot, Report, Summary, TradeRecord},
+    },
+    anchor_lang::prelude::{Error, Pubkey},
+    anyhow::{anyhow, bail, Result},
+    log::debug,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        math,
+        state::{
+            custody::Custody,
+            oracle::OracleType,
+            perpetuals::{Permissions, Perpetuals},
+            pool::{AumCalcMode, ExitAmounts, FeeTier, Pool, TokenRatios},
+            position::{Position, Side},
+            trading_stats::TradingStats,
+        },
+    },
+    rand::{rngs::StdRng, Rng, SeedableRng},
+};
+
+struct Asset {
+    symbol: String,
+    custody: Pubkey,
+    decimals: u8,
+    is_stable: bool,
+    transfer_fee_bps: u16,
+    liquidity: u64,
+    prices: PriceSeries,
+}
+
+struct OpenPosition {
+    position: Position,
+    open_fee_usd: u64,
+    close_time: i64,
+}
+
+struct Trader {
+    owner: Pubkey,
+    trading_stats: TradingStats,
+    position: Option<OpenPosition>,
+}
+
+pub struct Backtest {
+    market: Market,
+    assets: Vec<Asset>,
+    traders: Vec<Trader>,
+    traders_config: TradersConfig,
+    rng: StdRng,
+    step_sec: i64,
+    report_interval_sec: i64,
+    aum_calc_mode: AumCalcMode,
+    report: Report,
+}
+
+impl Backtest {
+    /// Loads price series and custody configs and initializes the pool
+    pub fn load(config: &BacktestConfig) -> Result<Self> {
+        let mut assets = Vec::with_capacity(config.custodies.len());
+        let mut custodies = Vec::with_capacity(config.custodies.len());
+        for asset_config in &config.custodies {
+            let prices = PriceSeries::load(&asset_config.prices)?;
+            let custody = new_custody(asset_config)?;
+            assets.push(Asset {
+                symbol: asset_config.symbol.clone(),
+                custody: Pubkey::new_unique(),
+                decimals: asset_config.decimals,
+                is_stable: custody.is_stable,
+                transfer_fee_bps: asset_config.transfer_fee_bps,
+                liquidity: to_token_amount(asset_config.liquidity, asset_config.decimals),
+                prices,
+            });
+            custodies.push(custody);
+        }
+
+        let fee_tiers = config
+            .load_fee_tiers()?
+            .map(|fee_tiers| {
+                fee_tiers
+                    .fee_tiers
+                    .into_iter()
+                    .map(|tier| FeeTier {
+                        min_volume_usd: tier.min_volume_usd,
+                        discount: tier.discount,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self::new(assets, custodies, fee_tiers, config)
+    }
+
+    fn new(
+        assets: Vec<Asset>,
+        mut custodies: Vec<Custody>,
+        fee_tiers: Vec<FeeTier>,
+        config: &BacktestConfig,
+    ) -> Result<Self> {
+        let start_time = assets
+            .iter()
+            .filter_map(|asset| asset.prices.start_time())
+            .max()
+            .ok_or_else(|| anyhow!("No prices"))?;
+
+        let pool_key = Pubkey::new_unique();
+        let pool = Pool {
+            name: "Backtest".to_string(),
+            custodies: assets.iter().map(|asset| asset.custody).collect(),
+            ratios: get_equal_ratios(assets.len()),
+            inception_time: start_time,
+            fee_tiers,
+            ..Pool::default()
+        };
+        if !pool.validate() {
+            bail!("Invalid pool config");
+        }
+
+        let mut oracles = Vec::with_capacity(assets.len());
+        for (asset, custody) in assets.iter().zip(custodies.iter_mut()) {
+            custody.pool = pool_key;
+            custody.assets.owned = asset.liquidity;
+            custody.borrow_rate_state.last_update = start_time;
+            let price = asset
+                .prices
+                .price_at(start_time)
+                .ok_or_else(|| anyhow!("No price for {} at start", asset.symbol))?;
+            oracles.push(price.to_oracle(start_time));
+        }
+
+        let permissions = Permissions {
+            allow_swap: true,
+            allow_add_liquidity: true,
+            allow_remove_liquidity: true,
+            allow_open_position: true,
+            allow_close_position: true,
+            allow_pnl_withdrawal: true,
+            allow_collateral_withdrawal: true,
+            allow_size_change: true,
+        };
+        let transfer_fees = assets.iter().map(|asset| asset.transfer_fee_bps).collect();
+        let market = Market::new(
+            permissions,
+            pool_key,
+            pool,
+            custodies,
+            oracles,
+            transfer_fees,
+        )?;
+
+        let report = Report {
+            symbols: assets.iter().map(|asset| asset.symbol.clone()).collect(),
+            ..Report::default()
+        };
+        let traders = (0..config.traders.count)
+            .map(|_| Trader {
+                owner: Pubkey::new_unique(),
+                trading_stats: TradingStats::default(),
+                position: None,
+            })
+            .collect();
+
+        Ok(Self {
+            market,
+            assets,
+            traders,
+            traders_config: config.traders,
+            rng: StdRng::seed_from_u64(config.traders.seed),
+            step_sec: config.step_sec,
+            report_interval_sec: config.report_interval_sec,
+            aum_calc_mode: config.aum_calc_mode.into(),
+            report,
+        })
+    }
+
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    /// Runs the backtest over the period covered by all price series, open
+    /// positions are closed at the end
+    pub fn run(mut self) -> Result<Report> {
+        let start_time = self
+            .assets
+            .iter()
+            .filter_map(|asset| asset.prices.start_time())
+            .max()
+            .ok_or_else(|| anyhow!("No prices"))?;
+        let end_time = self
+            .assets
+            .iter()
+            .filter_map(|asset| asset.prices.end_time())
+            .min()
+            .ok_or_else(|| anyhow!("No prices"))?;
+        if end_time < start_time {
+            bail!("Price series don't overlap");
+        }
+
+        let mut times: Vec<i64> = self
+            .assets
+            .iter()
+            .flat_map(|asset| asset.prices.points().iter().map(|point| point.time))
+            .filter(|time| (start_time..=end_time).contains(time))
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+
+        self.update_oracles(start_time)?;
+        let initial_aum_usd = self.get_aum_usd(start_time)?;
+        self.report.summary = Summary {
+            start_time,
+            end_time,
+            initial_aum_usd,
+            ..Summary::default()
+        };
+        self.snapshot(start_time)?;
+
+        let mut next_step = start_time;
+        let mut next_report = start_time + self.report_interval_sec;
+        for time in times {
+            if time < next_step {
+                continue;
+            }
+            next_step = time + self.step_sec;
+
+            self.update_oracles(time)?;
+            self.liquidate_positions(time);
+            self.close_positions(time, false);
+            self.open_positions(time)?;
+
+            if time >= next_report {
+                self.snapshot(time)?;
+                next_report = time + self.report_interval_sec;
+            }
+        }
+
+        self.update_oracles(end_time)?;
+        self.close_positions(end_time, true);
+        if self.report.snapshots.last().map(|snapshot| snapshot.time) == Some(end_time) {
+            self.report.snapshots.pop();
+        }
+        self.snapshot(end_time)?;
+
+        let summary = &mut self.report.summary;
+        let last = self
+            .report
+            .snapshots
+            .last()
+            .ok_or_else(|| anyhow!("No snapshots"))?;
+        summary.final_aum_usd = last.aum_usd;
+        summary.final_hodl_usd = last.hodl_usd;
+        summary.fees_usd = last.fees_usd;
+        summary.trader_pnl_usd = last.trader_pnl_usd;
+        summary.liquidations = last.liquidations;
+        summary.trades = self.report.trades.len() as u64;
+
+        Ok(self.report)
+    }
+
+    fn update_oracles(&mut self, time: i64) -> Result<()> {
+        for asset in &self.assets {
+            // the oracle publishes the last known price every step
+            if let Some(price) = asset.prices.price_at(time) {
+                self.market
+                    .set_oracle(&asset.custody, price.to_oracle(time))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn liquidate_positions(&mut self, time: i64) {
+        for idx in 0..self.traders.len() {
+            let Some(open) = &self.traders[idx].position else {
+                continue;
+            };
+            match self.market.is_liquidatable(&open.position, time) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) => {
+                    self.reject("liquidate", err);
+                    continue;
+                }
+            }
+            let position = open.position.clone();
+            match self.market.liquidate(&position, time) {
+                Ok(settled) => self.record_settlement(idx, time, settled, true),
+                Err(err) => self.reject("liquidate", err),
+            }
+        }
+    }
+
+    fn close_positions(&mut self, time: i64, close_all: bool) {
+        for idx in 0..self.traders.len() {
+            let trader = &mut self.traders[idx];
+            let Some(open) = &trader.position else {
+                continue;
+            };
+            if !close_all && open.close_time > time {
+                continue;
+            }
+            // traders accept any exit price
+            let params = ClosePositionParams {
+                price: if open.position.side == Side::Long {
+                    1
+                } else {
+                    u64::MAX
+                },
+            };
+            let position = open.position.clone();
+            match self
+                .market
+                .close_position(&position, &mut trader.trading_stats, &params, time)
+            {
+                Ok(settled) => self.record_settlement(idx, time, settled, false),
+                Err(err) => self.reject("close_position", err),
+            }
+        }
+    }
+
+    fn open_positions(&mut self, time: i64) -> Result<()> {
+        let tradable: Vec<usize> = self
+            .assets
+            .iter()
+            .enumerate()
+            .filter(|(_, asset)| !asset.is_stable)
+            .map(|(idx, _)| idx)
+            .collect();
+        let stable = self.assets.iter().position(|asset| {
+            asset.is_stable
+                && self
+                    .market
+                    .get_custody(&asset.custody)
+                    .is_ok_and(|custody| !custody.is_virtual)
+        });
+        if tradable.is_empty() {
+            return Ok(());
+        }
+
+        let config = self.traders_config;
+        for idx in 0..self.traders.len() {
+            if self.traders[idx].position.is_some() || !self.rng.gen_bool(config.open_probability) {
+                continue;
+            }
+
+            let asset_idx = tradable[self.rng.gen_range(0..tradable.len())];
+            let custody = self.market.get_custody(&self.assets[asset_idx].custody)?;
+            let side = if stable.is_some() && !self.rng.gen_bool(config.long_probability) {
+                Side::Short
+            } else {
+                Side::Long
+            };
+            let collateral_idx = if side == Side::Short || custody.is_virtual {
+                match stable {
+                    Some(stable) => stable,
+                    None => continue,
+                }
+            } else {
+                asset_idx
+            };
+            let collateral_usd = self
+                .rng
+                .gen_range(config.min_collateral_usd..=config.max_collateral_usd);
+            let leverage = self
+                .rng
+                .gen_range(config.min_leverage..=config.max_leverage);
+            let hold_sec = self
+                .rng
+                .gen_range(config.min_hold_sec..=config.max_hold_sec);
+
+            let asset = &self.assets[asset_idx];
+            let collateral_asset = &self.assets[collateral_idx];
+            let (Some(price), Some(collateral_price)) = (
+                asset.prices.price_at(time),
+                collateral_asset.prices.price_at(time),
+            ) else {
+                continue;
+            };
+
+            // traders accept any entry price
+            let params = OpenPositionParams {
+                price: if side == Side::Long { u64::MAX } else { 1 },
+                collateral: to_token_amount(
+                    collateral_usd / collateral_price.price,
+                    collateral_asset.decimals,
+                ),
+                size: to_token_amount(collateral_usd * leverage / price.price, asset.decimals),
+                side,
+            };
+            let custody_key = asset.custody;
+            let collateral_custody_key = collateral_asset.custody;
+
+            let trader = &mut self.traders[idx];
+            match self.market.open_position(
+                &trader.owner,
+                &mut trader.trading_stats,
+                &custody_key,
+                &collateral_custody_key,
+                &params,
+                time,
+            ) {
+                Ok(opened) => {
+                    trader.position = Some(OpenPosition {
+                        position: opened.position,
+                        open_fee_usd: opened.fee_amount_usd,
+                        close_time: time + hold_sec,
+                    });
+                    self.report.summary.positions_opened += 1;
+                }
+                Err(err) => self.reject("open_position", err),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_settlement(
+        &mut self,
+        trader_idx: usize,
+        time: i64,
+        settled: ExitAmounts,
+        liquidated: bool,
+    ) {
+        let Some(open) = self.traders[trader_idx].position.take() else {
+            return;
+        };
+        let position = open.position;
+        let symbol = self
+            .assets
+            .iter()
+            .find(|asset| asset.custody == position.custody)
+            .map(|asset| asset.symbol.clone())
+            .unwrap_or_default();
+
+        // liquidation rewards are paid out of the amount returned to the owner
+        let reward_usd = self
+            .market
+            .get_oracle_prices(&position.collateral_custody, time)
+            .and_then(|(price, _)| {
+                let decimals = self
+                    .market
+                    .get_custody(&position.collateral_custody)?
+                    .decimals;
+                price.get_asset_amount_usd(settled.reward, decimals)
+            })
+            .unwrap_or_default();
+
+        let pnl_usd = settled.profit_usd as i64
+            - settled.loss_usd as i64
+            - open.open_fee_usd as i64
+            - reward_usd as i64;
+
+        self.report.trades.push(TradeRecord {
+            trader: trader_idx,
+            symbol,
+            side: position.side,
+            open_time: position.open_time,
+            close_time: time,
+            entry_price: position.price,
+            exit_price: settled.price,
+            size_usd: position.size_usd,
+            collateral_usd: position.collateral_usd,
+            open_fee_usd: open.open_fee_usd,
+            close_fee_usd: settled.fee_amount_usd,
+            interest_usd: settled.interest_usd,
+            reward_usd,
+            pnl_usd,
+            liquidated,
+        });
+    }
+
+    fn reject(&mut self, instruction: &str, err: Error) {
+        let error_name = match &err {
+            Error::AnchorError(err) => err.error_name.clone(),
+            Error::ProgramError(err) => format!("{:?}", err.program_error),
+        };
+        debug!("{instruction} failed: {error_name}");
+        *self
+            .report
+            .summary
+            .rejected
+            .entry(format!("{instruction}: {error_name}"))
+            .or_default() += 1;
+    }
+
+    fn get_aum_usd(&self, time: i64) -> Result<u128> {
+        Ok(self
+            .market
+            .get_assets_under_management_usd(self.aum_calc_mode, time)?)
+    }
+
+    fn snapshot(&mut self, time: i64) -> Result<()> {
+        let aum_usd = self.get_aum_usd(time)?;
+
+        let mut hodl_usd = 0u128;
+        let mut assets = Vec::with_capacity(self.assets.len());
+        for asset in &self.assets {
+            let custody = self.market.get_custody(&asset.custody)?;
+            let (price, _) = self.market.get_oracle_prices(&asset.custody, time)?;
+            hodl_usd = math::checked_add(
+                hodl_usd,
+                price.get_asset_amount_usd(asset.liquidity, asset.decimals)? as u128,
+            )?;
+            let utilization = if custody.assets.owned > 0 {
+                math::checked_div(
+                    math::checked_mul(custody.assets.locked as u128, Perpetuals::BPS_POWER)?,
+                    custody.assets.owned as u128,
+                )? as u64
+            } else {
+                0
+            };
+            assets.push(AssetSnapshot {
+                price: price
+                    .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
+                    .price,
+                utilization,
+                borrow_rate: custody.borrow_rate_state.current_rate,
+            });
+        }
+
+        let custodies = &self.market.custodies;
+        let fees_usd = custodies
+            .iter()
+            .map(|custody| {
+                let fees = &custody.collected_fees;
+                fees.open_position_usd
+                    .wrapping_add(fees.close_position_usd)
+                    .wrapping_add(fees.liquidation_usd)
+            })
+            .fold(0u64, u64::wrapping_add);
+        let trades = &self.report.trades;
+
+        self.report.snapshots.push(PoolSnapshot {
+            time,
+            aum_usd,
+            hodl_usd,
+            oi_long_usd: custodies
+                .iter()
+                .map(|custody| custody.trade_stats.oi_long_usd)
+                .sum(),
+            oi_short_usd: custodies
+                .iter()
+                .map(|custody| custody.trade_stats.oi_short_usd)
+                .sum(),
+            open_positions: self
+                .traders
+                .iter()
+                .filter(|trader| trader.position.is_some())
+                .count() as u64,
+            fees_usd,
+            trader_pnl_usd: trades.iter().map(|trade| trade.pnl_usd).sum(),
+            liquidations: trades.iter().filter(|trade| trade.liquidated).count() as u64,
+            assets,
+        });
+
+        Ok(())
+    }
+}
+
+fn new_custody(config: &AssetConfig) -> Result<Custody> {
+    let custody_config = config.load_custody_config()?;
+    let mut custody = Custody {
+        mint: Pubkey::new_unique(),
+        token_account: Pubkey::new_unique(),
+        decimals: config.decimals,
+        is_stable: custody_config.is_stable,
+        is_virtual: custody_config.is_virtual,
+        oracle: custody_config.oracle.into(),
+        pricing: custody_config.pricing.into(),
+        permissions: custody_config.permissions.into(),
+        fees: custody_config.fees.into(),
+        borrow_rate: custody_config.borrow_rate.into(),
+        ..Custody::default()
+    };
+    // prices are replayed through a simulated custom oracle
+    custody.oracle.oracle_type = OracleType::Custom;
+    custody.oracle.oracle_account = Pubkey::new_unique();
+    custody.borrow_rate_state.current_rate = custody.borrow_rate.base_rate;
+    if !custody.validate() {
+        bail!("Invalid custody config for {}", config.symbol);
+    }
+    Ok(custody)
+}
+
+fn get_equal_ratios(num_tokens: usize) -> Vec<TokenRatios> {
+    let target = Perpetuals::BPS_POWER as u64 / num_tokens as u64;
+    (0..num_tokens)
+        .map(|idx| TokenRatios {
+            // the last token takes the remainder so targets add up to 1
+            target: if idx + 1 == num_tokens {
+                Perpetuals::BPS_POWER as u64 - target * (num_tokens as u64 - 1)
+            } else {
+                target
+            },
+            min: 0,
+            max: Perpetuals::BPS_POWER as u64,
+        })
+        .collect()
+}
+
+fn to_token_amount(amount: f64, decimals: u8) -> u64 {
+    (amount * 10f64.powi(decimals as i32)) as u64
+}
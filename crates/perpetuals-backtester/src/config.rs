@@ -0,0 +1,250 @@
+//! Backtest config
+//!
+//! Custodies are configured with the same custody config files that
+//! `perpetuals-cli` uses for `add-custody` and `set-custody-config`, so params
+//! tuned here can be proposed as is. `overrides` is a partial custody config
+//! merged over the file, which is convenient for sweeping a few params.
+
+use {
+    anyhow::{anyhow, bail, Context, Result},
+    perpetuals::state::pool::AumCalcMode,
+    perpetuals_client::config::{self as cli_config, CustodyConfig, FeeTiersConfig},
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    std::path::{Path, PathBuf},
+};
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AumCalcModeConfig {
+    Min,
+    Max,
+    Last,
+    Ema,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AssetConfig {
+    pub symbol: String,
+    pub decimals: u8,
+    // initial pool liquidity, in tokens
+    pub liquidity: f64,
+    // CSV files or directories with CSV files
+    pub prices: Vec<PathBuf>,
+    // custody config file in perpetuals-cli format
+    pub config: PathBuf,
+    #[serde(default)]
+    pub overrides: Option<Value>,
+    // Token-2022 transfer fee of the mint, in basis points
+    #[serde(default)]
+    pub transfer_fee_bps: u16,
+}
+
+/// Randomized traders, every step each trader without a position opens one
+/// with `open_probability` and holds it for a random time unless liquidated
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TradersConfig {
+    pub seed: u64,
+    pub count: usize,
+    pub open_probability: f64,
+    // probability that a new position is long
+    pub long_probability: f64,
+    pub min_collateral_usd: f64,
+    pub max_collateral_usd: f64,
+    pub min_leverage: f64,
+    pub max_leverage: f64,
+    pub min_hold_sec: i64,
+    pub max_hold_sec: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BacktestConfig {
+    // interval between simulation steps, prices are sampled at every step
+    pub step_sec: i64,
+    // interval between pool snapshots in the report
+    pub report_interval_sec: i64,
+    pub aum_calc_mode: AumCalcModeConfig,
+    // fee tiers file in perpetuals-cli format
+    #[serde(default)]
+    pub fee_tiers: Option<PathBuf>,
+    pub traders: TradersConfig,
+    pub custodies: Vec<AssetConfig>,
+}
+
+impl From<AumCalcModeConfig> for AumCalcMode {
+    fn from(mode: AumCalcModeConfig) -> Self {
+        match mode {
+            AumCalcModeConfig::Min => Self::Min,
+            AumCalcModeConfig::Max => Self::Max,
+            AumCalcModeConfig::Last => Self::Last,
+            AumCalcModeConfig::Ema => Self::EMA,
+        }
+    }
+}
+
+impl BacktestConfig {
+    /// Loads the config, relative paths are resolved against the config file
+    /// directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut config: Self = cli_config::load_config(cli_config::read_config_file(path)?, None)
+            .with_context(|| format!("Invalid config {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.resolve_paths(base_dir);
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn resolve_paths(&mut self, base_dir: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = base_dir.join(&*path);
+            }
+        };
+        if let Some(fee_tiers) = self.fee_tiers.as_mut() {
+            resolve(fee_tiers);
+        }
+        for asset in &mut self.custodies {
+            asset.prices.iter_mut().for_each(resolve);
+            resolve(&mut asset.config);
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let traders = &self.traders;
+        if self.step_sec <= 0 || self.report_interval_sec <= 0 {
+            bail!("step_sec and report_interval_sec must be positive");
+        }
+        if !(0.0..=1.0).contains(&traders.open_probability)
+            || !(0.0..=1.0).contains(&traders.long_probability)
+        {
+            bail!("Trader probabilities must be between 0 and 1");
+        }
+        if traders.min_collateral_usd <= 0.0
+            || traders.min_collateral_usd > traders.max_collateral_usd
+            || traders.min_leverage <= 0.0
+            || traders.min_leverage > traders.max_leverage
+            || traders.min_hold_sec < 0
+            || traders.min_hold_sec > traders.max_hold_sec
+        {
+            bail!("Invalid trader collateral, leverage or hold time range");
+        }
+        if self.custodies.is_empty() {
+            bail!("No custodies configured");
+        }
+        for asset in &self.custodies {
+            if asset.liquidity < 0.0 {
+                bail!("Negative liquidity for {}", asset.symbol);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_fee_tiers(&self) -> Result<Option<FeeTiersConfig>> {
+        self.fee_tiers
+            .as_ref()
+            .map(|path| {
+                cli_config::load_config(cli_config::read_config_file(path)?, None)
+                    .with_context(|| format!("Invalid fee tiers {}", path.display()))
+            })
+            .transpose()
+    }
+}
+
+impl AssetConfig {
+    /// Reads the custody config file and applies overrides
+    pub fn load_custody_config(&self) -> Result<CustodyConfig> {
+        let mut value = cli_config::read_config_file(&self.config)?;
+        if let Some(overrides) = &self.overrides {
+            if !overrides.is_object() {
+                return Err(anyhow!("{} overrides must be a table", self.symbol));
+            }
+            cli_config::merge(&mut value, overrides.clone());
+        }
+        cli_config::load_config(value, None)
+            .with_context(|| format!("Invalid custody config for {}", self.symbol))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, perpetuals_client::config::ConfigFormat};
+
+    const CONFIG: &str = r#"
+step_sec = 60
+report_interval_sec = 3600
+aum_calc_mode = "ema"
+
+[traders]
+seed = 1
+count = 10
+open_probability = 0.05
+long_probability = 0.5
+min_collateral_usd = 100.0
+max_collateral_usd = 1000.0
+min_leverage = 1.0
+max_leverage = 20.0
+min_hold_sec = 600
+max_hold_sec = 3600
+
+[[custodies]]
+symbol = "SOL"
+decimals = 9
+liquidity = 1000.0
+prices = ["data/sol-1.csv"]
+config = "custody.toml"
+
+[custodies.overrides.pricing]
+max_leverage = 500000
+"#;
+
+    #[test]
+    fn test_config() {
+        let value = cli_config::parse_config_str(CONFIG, ConfigFormat::Toml).unwrap();
+        let mut config: BacktestConfig = cli_config::load_config(value, None).unwrap();
+        config.validate().unwrap();
+        assert_eq!(AumCalcMode::from(config.aum_calc_mode), AumCalcMode::EMA);
+
+        config.resolve_paths(Path::new("/tmp/backtest"));
+        let asset = &config.custodies[0];
+        assert_eq!(
+            asset.prices,
+            vec![PathBuf::from("/tmp/backtest/data/sol-1.csv")]
+        );
+        assert_eq!(asset.config, PathBuf::from("/tmp/backtest/custody.toml"));
+        assert_eq!(
+            asset.overrides.as_ref().unwrap()["pricing"]["max_leverage"],
+            500000
+        );
+
+        config.traders.min_leverage = 30.0;
+        assert!(config.validate().is_err());
+
+        let value =
+            cli_config::parse_config_str(&format!("unknown = 1\n{CONFIG}"), ConfigFormat::Toml)
+                .unwrap();
+        assert!(cli_config::load_config::<BacktestConfig>(value, None).is_err());
+    }
+
+    #[test]
+    fn test_custody_overrides() {
+        let mut config: BacktestConfig = cli_config::load_config(
+            cli_config::parse_config_str(CONFIG, ConfigFormat::Toml).unwrap(),
+            None,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../perpetuals-cli/configs"
+        )));
+
+        let custody = config.custodies[0].load_custody_config().unwrap();
+        assert_eq!(custody.pricing.max_leverage, 500000);
+        assert_eq!(custody.pricing.max_initial_leverage, 1000000);
+
+        config.custodies[0].overrides = Some(serde_json::json!({"pricing": {"unknown": 1}}));
+        assert!(config.custodies[0].load_custody_config().is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Backtester for the Solana Perpetuals program, replays historical prices
+//! through the program's own trading and borrow rate math
+
+#![allow(clippy::result_large_err)]
+
+pub mod backtest;
+pub mod config;
+pub mod market;
+pub mod prices;
+pub mod report;
+
+pub use {
+    backtest::Backtest,
+    config::BacktestConfig,
+    market::Market,
+    report::{Report, Summary},
+};
@@ -0,0 +1,77 @@
+//! Backtester command line interface
+
+use {
+    anchor_lang::solana_program::program_stubs::{self, SyscallStubs},
+    anyhow::{Context, Result},
+    clap::{Arg, Command},
+    log::{debug, info},
+    perpetuals_backtester::{Backtest, BacktestConfig},
+    std::{
+        fs::{self, File},
+        io::BufWriter,
+        path::Path,
+    },
+};
+
+// forwards program logs to the debug log instead of stdout
+struct LogStubs;
+
+impl SyscallStubs for LogStubs {
+    fn sol_log(&self, message: &str) {
+        debug!("Program log: {message}");
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    program_stubs::set_syscall_stubs(Box::new(LogStubs));
+
+    let matches = Command::new("perpetuals-backtester")
+        .about("Replays historical prices through the Solana Perpetuals trading math")
+        .arg(
+            Arg::new("config")
+                .required(true)
+                .takes_value(true)
+                .help("Backtest config file, TOML or JSON"),
+        )
+        .arg(
+            Arg::new("output-dir")
+                .short('o')
+                .long("output-dir")
+                .takes_value(true)
+                .default_value("backtest")
+                .help("Directory for pool.csv and trades.csv"),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("Overrides the traders random seed"),
+        )
+        .get_matches();
+
+    let mut config = BacktestConfig::load(Path::new(matches.value_of("config").unwrap()))?;
+    if let Some(seed) = matches.value_of("seed") {
+        config.traders.seed = seed.parse().context("Invalid seed")?;
+    }
+
+    info!("Loading prices and custody configs");
+    let report = Backtest::load(&config)?.run()?;
+
+    let output_dir = Path::new(matches.value_of("output-dir").unwrap());
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create {}", output_dir.display()))?;
+    let pool_path = output_dir.join("pool.csv");
+    report.write_snapshots_csv(&mut BufWriter::new(File::create(&pool_path)?))?;
+    let trades_path = output_dir.join("trades.csv");
+    report.write_trades_csv(&mut BufWriter::new(File::create(&trades_path)?))?;
+
+    print!("{}", report.summary);
+    info!(
+        "Results written to {} and {}",
+        pool_path.display(),
+        trades_path.display()
+    );
+
+    Ok(())
+}
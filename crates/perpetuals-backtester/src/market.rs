@@ -0,0 +1,474 @@
+//! Simulated pool that executes trading instructions on in-memory accounts
+//!
+//! Instructions run the same `Pool` methods as their on-chain handlers, only
+//! token transfers are left out. Accounts are updated on copies that are
+//! written back once the instruction succeeds, so a failed instruction leaves
+//! the market untouched, like a failed transaction would.
+
+use {
+    anchor_lang::{prelude::*, AccountSerialize},
+    anchor_spl::token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{TransferFee, TransferFeeConfig},
+            ExtensionType, StateWithExtensionsMut,
+        },
+        state::Mint,
+    },
+    perpetuals::{
+        error::PerpetualsError,
+        instructions::{ClosePositionParams, OpenPositionParams},
+        math,
+        state::{
+            custody::Custody,
+            oracle::{CustomOracle, OraclePrice},
+            perpetuals::{Permissions, Perpetuals},
+            pool::{AumCalcMode, ExitAmounts, Pool},
+            position::{Position, Side},
+            trading_stats::TradingStats,
+        },
+    },
+};
+
+#[derive(Clone, Default, Debug)]
+pub struct OpenedPosition {
+    pub position: Position,
+    // fee amount is in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Market {
+    pub permissions: Permissions,
+    pub pool_key: Pubkey,
+    pub pool: Pool,
+    // custodies and their oracles, in pool order
+    pub custodies: Vec<Custody>,
+    pub oracles: Vec<CustomOracle>,
+    // Token-2022 transfer fees of custody mints, in basis points
+    pub transfer_fees: Vec<u16>,
+}
+
+impl Market {
+    pub fn new(
+        permissions: Permissions,
+        pool_key: Pubkey,
+        pool: Pool,
+        custodies: Vec<Custody>,
+        oracles: Vec<CustomOracle>,
+        transfer_fees: Vec<u16>,
+    ) -> Result<Self> {
+        require!(
+            pool.custodies.len() == custodies.len()
+                && custodies.len() == oracles.len()
+                && oracles.len() == transfer_fees.len(),
+            PerpetualsError::InvalidPoolConfig
+        );
+        Ok(Self {
+            permissions,
+            pool_key,
+            pool,
+            custodies,
+            oracles,
+            transfer_fees,
+        })
+    }
+
+    pub fn get_custody(&self, custody_key: &Pubkey) -> Result<&Custody> {
+        Ok(&self.custodies[self.pool.get_token_id(custody_key)?])
+    }
+
+    pub fn set_oracle(&mut self, custody_key: &Pubkey, oracle: CustomOracle) -> Result<()> {
+        let token_id = self.pool.get_token_id(custody_key)?;
+        self.oracles[token_id] = oracle;
+        Ok(())
+    }
+
+    /// Returns spot and EMA (if enabled for the custody) oracle prices
+    pub fn get_oracle_prices(
+        &self,
+        custody_key: &Pubkey,
+        curtime: i64,
+    ) -> Result<(OraclePrice, OraclePrice)> {
+        let token_id = self.pool.get_token_id(custody_key)?;
+        let custody = &self.custodies[token_id];
+        let mut account = SimulatedAccount::new(
+            custody.oracle.oracle_account,
+            to_account_data(&self.oracles[token_id])?,
+        );
+        let oracle_account = account.account_info();
+
+        let token_price =
+            OraclePrice::new_from_oracle(&oracle_account, &custody.oracle, curtime, false)?;
+        let token_ema_price = OraclePrice::new_from_oracle(
+            &oracle_account,
+            &custody.oracle,
+            curtime,
+            custody.pricing.use_ema,
+        )?;
+
+        Ok((token_price, token_ema_price))
+    }
+
+    /// Pool value computed by `Pool::get_assets_under_management_usd`
+    pub fn get_assets_under_management_usd(
+        &self,
+        aum_calc_mode: AumCalcMode,
+        curtime: i64,
+    ) -> Result<u128> {
+        let mut accounts = Vec::with_capacity(self.custodies.len() * 2);
+        for (key, custody) in self.pool.custodies.iter().zip(self.custodies.iter()) {
+            accounts.push(SimulatedAccount::new(*key, to_account_data(custody)?));
+        }
+        for (custody, oracle) in self.custodies.iter().zip(self.oracles.iter()) {
+            accounts.push(SimulatedAccount::new(
+                custody.oracle.oracle_account,
+                to_account_data(oracle)?,
+            ));
+        }
+        let account_infos: Vec<AccountInfo> = accounts
+            .iter_mut()
+            .map(SimulatedAccount::account_info)
+            .collect();
+
+        self.pool
+            .get_assets_under_management_usd(aum_calc_mode, &account_infos, curtime)
+    }
+
+    /// Same checks as `get_liquidation_state`
+    pub fn is_liquidatable(&self, position: &Position, curtime: i64) -> Result<bool> {
+        let custody = self.get_custody(&position.custody)?;
+        let collateral_custody = self.get_custody(&position.collateral_custody)?;
+        let (token_price, token_ema_price) = self.get_oracle_prices(&position.custody, curtime)?;
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_oracle_prices(&position.collateral_custody, curtime)?;
+
+        Ok(!self.pool.check_leverage(
+            position,
+            &token_price,
+            &token_ema_price,
+            custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            false,
+        )?)
+    }
+
+    /// Amount withheld by the mint of the custody when `amount` tokens are transferred
+    pub fn get_transfer_fee(&self, custody_key: &Pubkey, amount: u64) -> Result<u64> {
+        let token_id = self.pool.get_token_id(custody_key)?;
+        let mut account = SimulatedAccount::new(
+            self.custodies[token_id].mint,
+            new_mint_data(self.transfer_fees[token_id])?,
+        );
+        account.owner = spl_token_2022::ID;
+
+        // the simulated fee is the same in every epoch
+        Perpetuals::get_epoch_transfer_fee(&account.account_info(), 0, amount)
+    }
+
+    /// Follows the `open_position` handler
+    pub fn open_position(
+        &mut self,
+        owner: &Pubkey,
+        trading_stats: &mut TradingStats,
+        custody_key: &Pubkey,
+        collateral_custody_key: &Pubkey,
+        params: &OpenPositionParams,
+        curtime: i64,
+    ) -> Result<OpenedPosition> {
+        let custody_id = self.pool.get_token_id(custody_key)?;
+        let collateral_custody_id = self.pool.get_token_id(collateral_custody_key)?;
+        let mut custody = self.custodies[custody_id].clone();
+        let mut collateral_custody = self.custodies[collateral_custody_id].clone();
+        let mut stats = trading_stats.clone();
+
+        // check permissions
+        require!(
+            self.permissions.allow_open_position
+                && custody.permissions.allow_open_position
+                && !custody.is_stable,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        // validate inputs
+        if params.price == 0
+            || params.collateral == 0
+            || params.size == 0
+            || params.side == Side::None
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if params.side == Side::Short || custody.is_virtual {
+            require_keys_neq!(*custody_key, *collateral_custody_key);
+            require!(
+                collateral_custody.is_stable && !collateral_custody.is_virtual,
+                PerpetualsError::InvalidCollateralCustody
+            );
+        } else {
+            require_keys_eq!(*custody_key, *collateral_custody_key);
+        };
+
+        // compute position price
+        let (token_price, token_ema_price) = self.get_oracle_prices(custody_key, curtime)?;
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_oracle_prices(collateral_custody_key, curtime)?;
+
+        let min_collateral_price = collateral_token_price
+            .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
+
+        let fee_discount = self
+            .pool
+            .get_fee_discount(stats.get_rolling_volume_usd(curtime)?);
+        let entry = self.pool.get_entry_amounts(
+            params.side,
+            params.size,
+            &token_price,
+            &token_ema_price,
+            &custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            &collateral_custody,
+            fee_discount,
+        )?;
+
+        if params.side == Side::Long {
+            require_gte!(params.price, entry.price, PerpetualsError::MaxPriceSlippage);
+        } else {
+            require_gte!(entry.price, params.price, PerpetualsError::MaxPriceSlippage);
+        }
+
+        // tokens withheld by the mint's transfer fee are taken from the collateral
+        let transfer_amount = math::checked_add(params.collateral, entry.fee_amount)?;
+        let transfer_fee = self.get_transfer_fee(collateral_custody_key, transfer_amount)?;
+        let collateral = math::checked_sub(params.collateral, transfer_fee)?;
+        let collateral_usd =
+            min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
+
+        // init new position
+        let position = Position {
+            owner: *owner,
+            pool: self.pool_key,
+            custody: *custody_key,
+            collateral_custody: *collateral_custody_key,
+            open_time: curtime,
+            update_time: 0,
+            side: params.side,
+            price: entry.price,
+            size_usd: entry.size_usd,
+            borrow_size_usd: entry.borrow_size_usd,
+            collateral_usd,
+            unrealized_profit_usd: 0,
+            unrealized_loss_usd: 0,
+            cumulative_interest_snapshot: collateral_custody.get_cumulative_interest(curtime)?,
+            locked_amount: entry.locked_amount,
+            collateral_amount: collateral,
+            index: 0,
+            bump: 0,
+        };
+
+        // check position risk
+        require!(
+            position.locked_amount > 0,
+            PerpetualsError::InsufficientAmountReturned
+        );
+        require!(
+            self.pool.check_leverage(
+                &position,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                &collateral_token_price,
+                &collateral_token_ema_price,
+                &collateral_custody,
+                curtime,
+                true
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+
+        // update custody stats
+        self.pool.add_position(
+            &position,
+            &entry,
+            &token_ema_price,
+            &mut custody,
+            &mut collateral_custody,
+            curtime,
+        )?;
+
+        // update trader's volume for fee tiers
+        stats.add_volume(entry.size_usd, curtime)?;
+
+        self.custodies[custody_id] = custody;
+        self.custodies[collateral_custody_id] = collateral_custody;
+        *trading_stats = stats;
+
+        Ok(OpenedPosition {
+            position,
+            fee_amount: entry.fee_amount,
+            fee_amount_usd: entry.fee_amount_usd,
+        })
+    }
+
+    /// Follows the `close_position` handler
+    pub fn close_position(
+        &mut self,
+        position: &Position,
+        trading_stats: &mut TradingStats,
+        params: &ClosePositionParams,
+        curtime: i64,
+    ) -> Result<ExitAmounts> {
+        if params.price == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        let mut stats = trading_stats.clone();
+        let fee_discount = self
+            .pool
+            .get_fee_discount(stats.get_rolling_volume_usd(curtime)?);
+
+        let exit =
+            self.settle_position(position, Some(params.price), fee_discount, curtime, false)?;
+
+        // update trader's volume for fee tiers
+        stats.add_volume(position.size_usd, curtime)?;
+        *trading_stats = stats;
+
+        Ok(exit)
+    }
+
+    /// Follows the `liquidate` handler, the reward is paid out of the amount
+    /// returned to the owner
+    pub fn liquidate(&mut self, position: &Position, curtime: i64) -> Result<ExitAmounts> {
+        require!(
+            self.is_liquidatable(position, curtime)?,
+            PerpetualsError::InvalidPositionState
+        );
+
+        self.settle_position(position, None, 0, curtime, true)
+    }
+
+    fn settle_position(
+        &mut self,
+        position: &Position,
+        price: Option<u64>,
+        fee_discount: u64,
+        curtime: i64,
+        liquidation: bool,
+    ) -> Result<ExitAmounts> {
+        let custody_id = self.pool.get_token_id(&position.custody)?;
+        let collateral_custody_id = self.pool.get_token_id(&position.collateral_custody)?;
+        let mut custody = self.custodies[custody_id].clone();
+        let mut collateral_custody = self.custodies[collateral_custody_id].clone();
+
+        // check permissions
+        require!(
+            self.permissions.allow_close_position && custody.permissions.allow_close_position,
+            PerpetualsError::InstructionNotAllowed
+        );
+
+        // settle position
+        let (token_price, token_ema_price) = self.get_oracle_prices(&position.custody, curtime)?;
+        let (collateral_token_price, collateral_token_ema_price) =
+            self.get_oracle_prices(&position.collateral_custody, curtime)?;
+
+        let exit = self.pool.get_exit_amounts(
+            position,
+            &token_price,
+            &token_ema_price,
+            &custody,
+            &collateral_token_price,
+            &collateral_token_ema_price,
+            &collateral_custody,
+            curtime,
+            liquidation,
+            fee_discount,
+        )?;
+
+        if let Some(price) = price {
+            if position.side == Side::Long {
+                require_gte!(exit.price, price, PerpetualsError::MaxPriceSlippage);
+            } else {
+                require_gte!(price, exit.price, PerpetualsError::MaxPriceSlippage);
+            }
+        }
+
+        // unlock pool funds and update custody stats
+        self.pool.remove_position(
+            position,
+            &exit,
+            &mut custody,
+            &mut collateral_custody,
+            curtime,
+            liquidation,
+        )?;
+
+        self.custodies[custody_id] = custody;
+        self.custodies[collateral_custody_id] = collateral_custody;
+
+        Ok(exit)
+    }
+}
+
+/// Owned account data that can be lent to the program as `AccountInfo`
+struct SimulatedAccount {
+    key: Pubkey,
+    owner: Pubkey,
+    lamports: u64,
+    data: Vec<u8>,
+}
+
+impl SimulatedAccount {
+    fn new(key: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key,
+            owner: perpetuals::ID,
+            lamports: 1,
+            data,
+        }
+    }
+
+    fn account_info(&mut self) -> AccountInfo<'_> {
+        AccountInfo::new(
+            &self.key,
+            false,
+            false,
+            &mut self.lamports,
+            &mut self.data,
+            &self.owner,
+            false,
+            0,
+        )
+    }
+}
+
+fn to_account_data<T: AccountSerialize>(account: &T) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    account.try_serialize(&mut data)?;
+    Ok(data)
+}
+
+/// Token-2022 mint with the same transfer fee in every epoch
+fn new_mint_data(transfer_fee_bps: u16) -> Result<Vec<u8>> {
+    let mut data =
+        vec![0; ExtensionType::get_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])];
+    let mut mint = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data)?;
+
+    let transfer_fee = TransferFee {
+        epoch: 0.into(),
+        maximum_fee: u64::MAX.into(),
+        transfer_fee_basis_points: transfer_fee_bps.into(),
+    };
+    let transfer_fee_config = mint.init_extension::<TransferFeeConfig>(true)?;
+    transfer_fee_config.older_transfer_fee = transfer_fee;
+    transfer_fee_config.newer_transfer_fee = transfer_fee;
+
+    mint.base.is_initialized = true;
+    mint.pack_base();
+    mint.init_account_type()?;
+
+    Ok(data)
+}
@@ -0,0 +1,226 @@
+//! Historical price series
+//!
+//! Reads the CSV exports in `simulations/data`. Oracle exports (`Btc`, `Eth`,
+//! `Sol`) provide `price`, `confidence` and `emaPrice` columns, exchange
+//! candles (`Stables`) only provide the close price `c`, which is then used as
+//! both the spot and the EMA price with zero confidence.
+
+use {
+    anyhow::{anyhow, bail, Context, Result},
+    chrono::NaiveDateTime,
+    perpetuals::state::oracle::CustomOracle,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// exponent of prices stored in simulated oracle accounts
+pub const PRICE_EXPONENT: i32 = -8;
+
+pub const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PricePoint {
+    // unix timestamp
+    pub time: i64,
+    pub price: f64,
+    pub conf: f64,
+    pub ema: f64,
+}
+
+impl PricePoint {
+    /// Writes the price to a custom oracle account published at the given time
+    pub fn to_oracle(&self, publish_time: i64) -> CustomOracle {
+        let mut oracle = CustomOracle::default();
+        oracle.set(
+            scale_price(self.price),
+            PRICE_EXPONENT,
+            scale_price(self.conf),
+            scale_price(self.ema),
+            publish_time,
+        );
+        oracle
+    }
+}
+
+/// Time-ordered prices of a single asset
+#[derive(Clone, Default, Debug)]
+pub struct PriceSeries {
+    points: Vec<PricePoint>,
+}
+
+impl PriceSeries {
+    pub fn new(mut points: Vec<PricePoint>) -> Self {
+        points.sort_by_key(|point| point.time);
+        points.dedup_by_key(|point| point.time);
+        Self { points }
+    }
+
+    /// Loads prices from CSV files, directories are expanded to the CSV files
+    /// they contain
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut points = vec![];
+        for path in paths {
+            if path.is_dir() {
+                let mut files = fs::read_dir(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                files.retain(|file| file.extension().is_some_and(|ext| ext == "csv"));
+                files.sort();
+                for file in files {
+                    points.extend(read_csv_file(&file)?);
+                }
+            } else {
+                points.extend(read_csv_file(path)?);
+            }
+        }
+        if points.is_empty() {
+            bail!("No prices found in {:?}", paths);
+        }
+        Ok(Self::new(points))
+    }
+
+    pub fn points(&self) -> &[PricePoint] {
+        &self.points
+    }
+
+    pub fn start_time(&self) -> Option<i64> {
+        self.points.first().map(|point| point.time)
+    }
+
+    pub fn end_time(&self) -> Option<i64> {
+        self.points.last().map(|point| point.time)
+    }
+
+    /// Returns the last price published at or before the given time
+    pub fn price_at(&self, time: i64) -> Option<&PricePoint> {
+        match self.points.partition_point(|point| point.time <= time) {
+            0 => None,
+            idx => Some(&self.points[idx - 1]),
+        }
+    }
+}
+
+pub fn read_csv_file(path: &Path) -> Result<Vec<PricePoint>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_csv(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+pub fn parse_csv(content: &str) -> Result<Vec<PricePoint>> {
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| anyhow!("Missing header"))?
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|column| *column == name);
+
+    let time_idx = column("t").ok_or_else(|| anyhow!("Missing time column \"t\""))?;
+    let price_idx = column("price")
+        .or_else(|| column("c"))
+        .ok_or_else(|| anyhow!("Missing price column \"price\" or \"c\""))?;
+    let conf_idx = column("confidence");
+    let ema_idx = column("emaPrice");
+
+    let mut points = vec![];
+    for (row, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let field = |idx: usize| {
+            fields
+                .get(idx)
+                .copied()
+                .ok_or_else(|| anyhow!("Row {}: missing column {}", row + 1, header[idx]))
+        };
+        let number = |idx: usize| -> Result<f64> {
+            let value = field(idx)?;
+            value
+                .parse()
+                .map_err(|_| anyhow!("Row {}: invalid {} {value:?}", row + 1, header[idx]))
+        };
+
+        let time = NaiveDateTime::parse_from_str(field(time_idx)?, TIME_FORMAT)
+            .map_err(|err| anyhow!("Row {}: invalid time: {err}", row + 1))?
+            .and_utc()
+            .timestamp();
+        let price = number(price_idx)?;
+        let conf = conf_idx.map(number).transpose()?.unwrap_or_default();
+        let ema = ema_idx.map(number).transpose()?.unwrap_or(price);
+        if price <= 0.0 || ema <= 0.0 || conf < 0.0 {
+            bail!("Row {}: price out of range", row + 1);
+        }
+
+        points.push(PricePoint {
+            time,
+            price,
+            conf,
+            ema,
+        });
+    }
+
+    Ok(points)
+}
+
+pub fn scale_price(price: f64) -> u64 {
+    (price * 10f64.powi(-PRICE_EXPONENT)).round() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ORACLE_CSV: &str = "\
+s,t,c,o,h,l,v,time,slot,publishedSlot,price,confidence,emaPrice,emaDifference%,maxprice,minprice,spread,spot_confidence%,use_spread,spread%,problem_indicator
+o,2022-05-11 00:01:00,30990.85,30991.15,31006.96,30985.7,0,2022-05-11 00:01:00.483,133330588,133330588,30991.15,9.84,30935.488,0.17,30991.15,30991.15,0.0,0.03,False,0.0,no problemo
+,2022-05-11 00:00:00,30980.0,30981.0,31006.96,30985.7,0,2022-05-11 00:00:00.483,133330580,133330580,30981.0,8.5,30935.0,0.17,30981.0,30981.0,0.0,0.03,False,0.0,no problemo
+";
+
+    const CANDLE_CSV: &str = "\
+s,t,c,o,h,l,v
+o,2022-05-11 00:00:00,1.00002525,1.0,1.00005,1.0,0
+";
+
+    #[test]
+    fn test_parse_csv() {
+        let series = PriceSeries::new(parse_csv(ORACLE_CSV).unwrap());
+        assert_eq!(series.start_time(), Some(1_652_227_200));
+        assert_eq!(series.end_time(), Some(1_652_227_260));
+        assert_eq!(
+            series.points()[0],
+            PricePoint {
+                time: 1_652_227_200,
+                price: 30981.0,
+                conf: 8.5,
+                ema: 30935.0,
+            }
+        );
+
+        let points = parse_csv(CANDLE_CSV).unwrap();
+        assert_eq!(points[0].price, 1.00002525);
+        assert_eq!(points[0].ema, 1.00002525);
+        assert_eq!(points[0].conf, 0.0);
+
+        assert!(parse_csv("s,t,o\n,2022-05-11 00:00:00,1.0\n").is_err());
+        assert!(parse_csv("t,c\nyesterday,1.0\n").is_err());
+        assert!(parse_csv("t,c\n2022-05-11 00:00:00,0\n").is_err());
+    }
+
+    #[test]
+    fn test_price_at() {
+        let series = PriceSeries::new(parse_csv(ORACLE_CSV).unwrap());
+        assert_eq!(series.price_at(1_652_227_199), None);
+        assert_eq!(series.price_at(1_652_227_200).unwrap().price, 30981.0);
+        assert_eq!(series.price_at(1_652_227_259).unwrap().price, 30981.0);
+        assert_eq!(series.price_at(1_652_227_300).unwrap().price, 30991.15);
+
+        let oracle = series.points()[0].to_oracle(1_652_227_230);
+        assert_eq!(oracle.price, 3_098_100_000_000);
+        assert_eq!(oracle.expo, -8);
+        assert_eq!(oracle.conf, 850_000_000);
+        assert_eq!(oracle.ema, 3_093_500_000_000);
+        assert_eq!(oracle.publish_time, 1_652_227_230);
+    }
+}
@@ -0,0 +1,319 @@
+//! Backtest results
+//!
+//! USD values have implied USD_DECIMALS decimals and prices PRICE_DECIMALS
+//! decimals like in the program, CSV output is scaled to whole units.
+
+use {
+    crate::prices::TIME_FORMAT,
+    chrono::DateTime,
+    perpetuals::state::{perpetuals::Perpetuals, position::Side},
+    std::{
+        collections::BTreeMap,
+        fmt,
+        io::{self, Write},
+    },
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct AssetSnapshot {
+    pub price: u64,
+    // locked / owned, with implied BPS_DECIMALS decimals
+    pub utilization: u64,
+    // with implied RATE_DECIMALS decimals
+    pub borrow_rate: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct PoolSnapshot {
+    pub time: i64,
+    pub aum_usd: u128,
+    // value of the initial liquidity at current prices
+    pub hodl_usd: u128,
+    pub oi_long_usd: u64,
+    pub oi_short_usd: u64,
+    pub open_positions: u64,
+    // cumulative values since the start of the backtest
+    pub fees_usd: u64,
+    pub trader_pnl_usd: i64,
+    pub liquidations: u64,
+    // in pool custody order
+    pub assets: Vec<AssetSnapshot>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TradeRecord {
+    pub trader: usize,
+    pub symbol: String,
+    pub side: Side,
+    pub open_time: i64,
+    pub close_time: i64,
+    pub entry_price: u64,
+    pub exit_price: u64,
+    pub size_usd: u64,
+    pub collateral_usd: u64,
+    pub open_fee_usd: u64,
+    pub close_fee_usd: u64,
+    pub interest_usd: u64,
+    pub reward_usd: u64,
+    // net of fees, interest and liquidation reward
+    pub pnl_usd: i64,
+    pub liquidated: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct Summary {
+    pub start_time: i64,
+    pub end_time: i64,
+    pub initial_aum_usd: u128,
+    pub final_aum_usd: u128,
+    pub final_hodl_usd: u128,
+    pub positions_opened: u64,
+    pub trades: u64,
+    pub liquidations: u64,
+    pub fees_usd: u64,
+    pub trader_pnl_usd: i64,
+    // failed instructions by instruction and error name
+    pub rejected: BTreeMap<String, u64>,
+}
+
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Report {
+    // in pool custody order
+    pub symbols: Vec<String>,
+    pub summary: Summary,
+    pub snapshots: Vec<PoolSnapshot>,
+    pub trades: Vec<TradeRecord>,
+}
+
+impl Report {
+    /// Writes pool snapshots: LP returns, open interest, fees, trader PnL,
+    /// liquidations and per-asset utilization and borrow rates
+    pub fn write_snapshots_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "time,aum_usd,hodl_usd,lp_return,lp_return_vs_hodl,oi_long_usd,oi_short_usd,\
+             open_positions,fees_usd,trader_pnl_usd,liquidations"
+        )?;
+        for symbol in &self.symbols {
+            write!(
+                writer,
+                ",{symbol}_price,{symbol}_utilization,{symbol}_borrow_rate"
+            )?;
+        }
+        writeln!(writer)?;
+
+        for snapshot in &self.snapshots {
+            write!(
+                writer,
+                "{},{},{},{:.6},{:.6},{},{},{},{},{},{}",
+                format_time(snapshot.time),
+                format_usd(snapshot.aum_usd as i128),
+                format_usd(snapshot.hodl_usd as i128),
+                get_return(snapshot.aum_usd, self.summary.initial_aum_usd),
+                get_return(snapshot.aum_usd, snapshot.hodl_usd),
+                format_usd(snapshot.oi_long_usd as i128),
+                format_usd(snapshot.oi_short_usd as i128),
+                snapshot.open_positions,
+                format_usd(snapshot.fees_usd as i128),
+                format_usd(snapshot.trader_pnl_usd as i128),
+                snapshot.liquidations,
+            )?;
+            for asset in &snapshot.assets {
+                write!(
+                    writer,
+                    ",{},{},{}",
+                    format_decimal(asset.price as i128, Perpetuals::PRICE_DECIMALS),
+                    format_decimal(asset.utilization as i128, Perpetuals::BPS_DECIMALS),
+                    format_decimal(asset.borrow_rate as i128, Perpetuals::RATE_DECIMALS),
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes closed and liquidated positions
+    pub fn write_trades_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "trader,symbol,side,open_time,close_time,entry_price,exit_price,size_usd,\
+             collateral_usd,open_fee_usd,close_fee_usd,interest_usd,reward_usd,pnl_usd,liquidated"
+        )?;
+        for trade in &self.trades {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                trade.trader,
+                trade.symbol,
+                if trade.side == Side::Long {
+                    "long"
+                } else {
+                    "short"
+                },
+                format_time(trade.open_time),
+                format_time(trade.close_time),
+                format_decimal(trade.entry_price as i128, Perpetuals::PRICE_DECIMALS),
+                format_decimal(trade.exit_price as i128, Perpetuals::PRICE_DECIMALS),
+                format_usd(trade.size_usd as i128),
+                format_usd(trade.collateral_usd as i128),
+                format_usd(trade.open_fee_usd as i128),
+                format_usd(trade.close_fee_usd as i128),
+                format_usd(trade.interest_usd as i128),
+                format_usd(trade.reward_usd as i128),
+                format_usd(trade.pnl_usd as i128),
+                trade.liquidated,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Period: {} - {}",
+            format_time(self.start_time),
+            format_time(self.end_time)
+        )?;
+        writeln!(
+            f,
+            "Pool value: {} -> {} USD, LP return {:.4}%, vs HODL {:.4}%",
+            format_usd(self.initial_aum_usd as i128),
+            format_usd(self.final_aum_usd as i128),
+            get_return(self.final_aum_usd, self.initial_aum_usd) * 100.0,
+            get_return(self.final_aum_usd, self.final_hodl_usd) * 100.0,
+        )?;
+        writeln!(
+            f,
+            "Positions opened: {}, closed: {}, liquidated: {}",
+            self.positions_opened,
+            self.trades.saturating_sub(self.liquidations),
+            self.liquidations
+        )?;
+        writeln!(
+            f,
+            "Fees collected: {} USD",
+            format_usd(self.fees_usd as i128)
+        )?;
+        writeln!(
+            f,
+            "Trader PnL: {} USD",
+            format_usd(self.trader_pnl_usd as i128)
+        )?;
+        if !self.rejected.is_empty() {
+            writeln!(f, "Rejected instructions:")?;
+            for (error, count) in &self.rejected {
+                writeln!(f, "  {error}: {count}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn format_time(time: i64) -> String {
+    DateTime::from_timestamp(time, 0)
+        .map(|time| time.format(TIME_FORMAT).to_string())
+        .unwrap_or_else(|| time.to_string())
+}
+
+pub fn format_usd(amount: i128) -> String {
+    format_decimal(amount, Perpetuals::USD_DECIMALS)
+}
+
+pub fn format_decimal(amount: i128, decimals: u8) -> String {
+    if decimals == 0 {
+        return format!("{amount}");
+    }
+    let power = 10i128.pow(decimals as u32);
+    let sign = if amount < 0 { "-" } else { "" };
+    format!(
+        "{sign}{}.{:0width$}",
+        amount.unsigned_abs() / power as u128,
+        amount.unsigned_abs() % power as u128,
+        width = decimals as usize
+    )
+}
+
+fn get_return(value: u128, base: u128) -> f64 {
+    if base == 0 {
+        0.0
+    } else {
+        value as f64 / base as f64 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(format_usd(1_234_567), "1.234567");
+        assert_eq!(format_usd(-5), "-0.000005");
+        assert_eq!(format_decimal(12_345, 4), "1.2345");
+        assert_eq!(format_decimal(-7, 0), "-7");
+        assert_eq!(format_time(1_652_227_200), "2022-05-11 00:00:00");
+    }
+
+    #[test]
+    fn test_csv() {
+        let report = Report {
+            symbols: vec!["SOL".to_string()],
+            summary: Summary {
+                initial_aum_usd: 100_000_000,
+                ..Summary::default()
+            },
+            snapshots: vec![PoolSnapshot {
+                time: 1_652_227_200,
+                aum_usd: 110_000_000,
+                hodl_usd: 100_000_000,
+                assets: vec![AssetSnapshot {
+                    price: 66_630_964,
+                    utilization: 2_500,
+                    borrow_rate: 10_000,
+                }],
+                ..PoolSnapshot::default()
+            }],
+            trades: vec![TradeRecord {
+                trader: 3,
+                symbol: "SOL".to_string(),
+                side: Side::Short,
+                open_time: 1_652_227_200,
+                close_time: 1_652_227_260,
+                entry_price: 66_000_000,
+                exit_price: 67_000_000,
+                size_usd: 10_000_000,
+                collateral_usd: 1_000_000,
+                open_fee_usd: 10_000,
+                close_fee_usd: 10_000,
+                interest_usd: 0,
+                reward_usd: 0,
+                pnl_usd: -171_515,
+                liquidated: false,
+            }],
+        };
+
+        let mut output = vec![];
+        report.write_snapshots_csv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with(",SOL_price,SOL_utilization,SOL_borrow_rate"));
+        assert_eq!(
+            lines[1],
+            "2022-05-11 00:00:00,110.000000,100.000000,0.100000,0.100000,0.000000,0.000000,\
+             0,0.000000,0.000000,0,66.630964,0.2500,0.000010000"
+        );
+
+        let mut output = vec![];
+        report.write_trades_csv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().nth(1).unwrap(),
+            "3,SOL,short,2022-05-11 00:00:00,2022-05-11 00:01:00,66.000000,67.000000,\
+             10.000000,1.000000,0.010000,0.010000,0.000000,0.000000,-0.171515,false"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use {
+    anchor_lang::prelude::Pubkey,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::{pool::AumCalcMode, position::Side, trading_stats::TradingStats},
+    },
+    perpetuals_backtester::{
+        config::{AssetConfig, AumCalcModeConfig, TradersConfig},
+        Backtest, BacktestConfig,
+    },
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+const START_TIME: i64 = 1_652_227_200; // 2022-05-11 00:00:00
+
+fn get_temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "perpetuals-backtester-{}-{name}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// writes one price per minute in exchange candle format
+fn write_prices(path: &Path, prices: &[f64]) {
+    let mut content = "t,c\n".to_string();
+    for (minute, price) in prices.iter().enumerate() {
+        let time = chrono::DateTime::from_timestamp(START_TIME + minute as i64 * 60, 0).unwrap();
+        content += &format!("{},{price}\n", time.format("%Y-%m-%d %H:%M:%S"));
+    }
+    fs::write(path, content).unwrap();
+}
+
+fn get_config(dir: &Path, sol_prices: &[f64], traders: TradersConfig) -> BacktestConfig {
+    write_prices(&dir.join("sol.csv"), sol_prices);
+    write_prices(&dir.join("usdc.csv"), &vec![1.0; sol_prices.len()]);
+    let custody_config =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../perpetuals-cli/configs/custody.toml");
+
+    BacktestConfig {
+        step_sec: 60,
+        report_interval_sec: 600,
+        aum_calc_mode: AumCalcModeConfig::Last,
+        fee_tiers: None,
+        traders,
+        custodies: vec![
+            AssetConfig {
+                symbol: "SOL".to_string(),
+                decimals: 9,
+                liquidity: 10_000.0,
+                prices: vec![dir.join("sol.csv")],
+                config: custody_config.clone(),
+                overrides: None,
+                transfer_fee_bps: 0,
+            },
+            AssetConfig {
+                symbol: "USDC".to_string(),
+                decimals: 6,
+                liquidity: 1_000_000.0,
+                prices: vec![dir.join("usdc.csv")],
+                config: custody_config,
+                overrides: Some(serde_json::json!({"is_stable": true})),
+                transfer_fee_bps: 0,
+            },
+        ],
+    }
+}
+
+fn get_traders(count: usize, leverage: f64) -> TradersConfig {
+    TradersConfig {
+        seed: 1,
+        count,
+        open_probability: 1.0,
+        long_probability: 1.0,
+        min_collateral_usd: 1_000.0,
+        max_collateral_usd: 1_000.0,
+        min_leverage: leverage,
+        max_leverage: leverage,
+        min_hold_sec: 3_600,
+        max_hold_sec: 3_600,
+    }
+}
+
+#[test]
+fn test_market() {
+    let dir = get_temp_dir("market");
+    let config = get_config(&dir, &[100.0; 10], get_traders(0, 1.0));
+    let backtest = Backtest::load(&config).unwrap();
+    let mut market = backtest.market().clone();
+    let sol = market.pool.custodies[0];
+    let usdc = market.pool.custodies[1];
+    let owner = Pubkey::new_unique();
+    let mut trading_stats = TradingStats::default();
+
+    // pool value is computed with the program's aum math
+    assert_eq!(
+        market
+            .get_assets_under_management_usd(AumCalcMode::Last, START_TIME)
+            .unwrap(),
+        2_000_000_000_000
+    );
+
+    // open and close a long at the same price, the pool keeps the fees
+    let owned = market.get_custody(&sol).unwrap().assets.owned;
+    let opened = market
+        .open_position(
+            &owner,
+            &mut trading_stats,
+            &sol,
+            &sol,
+            &OpenPositionParams {
+                price: u64::MAX,
+                collateral: 10_000_000_000,
+                size: 50_000_000_000,
+                side: Side::Long,
+            },
+            START_TIME,
+        )
+        .unwrap();
+    let custody = market.get_custody(&sol).unwrap();
+    assert_eq!(custody.assets.collateral, 10_000_000_000);
+    assert_eq!(custody.assets.locked, opened.position.locked_amount);
+    assert_eq!(custody.trade_stats.oi_long_usd, opened.position.size_usd);
+    assert_eq!(custody.long_positions.open_positions, 1);
+    assert!(opened.fee_amount_usd > 0);
+
+    let settled = market
+        .close_position(
+            &opened.position,
+            &mut trading_stats,
+            &ClosePositionParams { price: 1 },
+            START_TIME,
+        )
+        .unwrap();
+    assert_eq!(settled.profit_usd, 0);
+    assert!(settled.loss_usd > 0);
+    assert!(settled.amount_out < opened.position.collateral_amount);
+    let custody = market.get_custody(&sol).unwrap();
+    assert_eq!(custody.assets.collateral, 0);
+    assert_eq!(custody.assets.locked, 0);
+    assert_eq!(custody.trade_stats.oi_long_usd, 0);
+    assert_eq!(custody.long_positions.open_positions, 0);
+    assert!(custody.assets.owned > owned);
+    assert_eq!(
+        trading_stats.get_rolling_volume_usd(START_TIME).unwrap(),
+        opened.position.size_usd * 2
+    );
+
+    // failed instructions leave the market untouched
+    let custodies = market.custodies.clone();
+    let params = OpenPositionParams {
+        price: 1,
+        collateral: 1_000_000_000,
+        size: 1_000_000_000,
+        side: Side::Short,
+    };
+    assert!(market
+        .open_position(&owner, &mut trading_stats, &sol, &sol, &params, START_TIME)
+        .is_err());
+    let params = OpenPositionParams {
+        collateral: 1_000_000,
+        size: 1_000_000_000_000,
+        ..params
+    };
+    assert!(market
+        .open_position(&owner, &mut trading_stats, &sol, &usdc, &params, START_TIME)
+        .is_err());
+    assert_eq!(market.custodies, custodies);
+
+    // the mint's transfer fee is taken from the collateral
+    market.transfer_fees[0] = 100;
+    let params = OpenPositionParams {
+        price: u64::MAX,
+        collateral: 10_000_000_000,
+        size: 50_000_000_000,
+        side: Side::Long,
+    };
+    let opened = market
+        .open_position(&owner, &mut trading_stats, &sol, &sol, &params, START_TIME)
+        .unwrap();
+    let transfer_fee = (params.collateral + opened.fee_amount) / 100;
+    assert_eq!(
+        opened.position.collateral_amount,
+        params.collateral - transfer_fee
+    );
+    assert_eq!(
+        market.get_custody(&sol).unwrap().assets.collateral,
+        opened.position.collateral_amount
+    );
+}
+
+#[test]
+fn test_liquidation() {
+    let dir = get_temp_dir("liquidation");
+    // SOL drops 6% after 10 minutes
+    let mut prices = vec![100.0; 10];
+    prices.extend(vec![94.0; 20]);
+    let config = get_config(&dir, &prices, get_traders(1, 20.0));
+
+    let report = Backtest::load(&config).unwrap().run().unwrap();
+    assert_eq!(report.summary.liquidations, 1);
+    assert_eq!(report.summary.positions_opened, 2);
+    assert_eq!(report.trades.len(), 2);
+
+    // the first position is liquidated as soon as the price drops
+    let liquidated = &report.trades[0];
+    assert!(liquidated.liquidated);
+    assert_eq!(liquidated.symbol, "SOL");
+    assert_eq!(liquidated.side, Side::Long);
+    assert_eq!(liquidated.open_time, START_TIME);
+    assert_eq!(liquidated.close_time, START_TIME + 600);
+    assert!(liquidated.pnl_usd < 0);
+
+    // the trader opens a new position that is closed at the end
+    let closed = &report.trades[1];
+    assert!(!closed.liquidated);
+    assert_eq!(closed.open_time, START_TIME + 600);
+    assert_eq!(closed.close_time, START_TIME + 29 * 60);
+
+    let last = report.snapshots.last().unwrap();
+    assert_eq!(last.open_positions, 0);
+    assert_eq!(last.oi_long_usd, 0);
+    assert_eq!(last.liquidations, 1);
+    assert_eq!(
+        last.trader_pnl_usd,
+        report.trades.iter().map(|trade| trade.pnl_usd).sum::<i64>()
+    );
+    // trader losses and fees go to the pool
+    assert!(last.aum_usd > last.hodl_usd);
+}
+
+#[test]
+fn test_example_config() {
+    let config =
+        BacktestConfig::load(&Path::new(env!("CARGO_MANIFEST_DIR")).join("configs/backtest.toml"))
+            .unwrap();
+
+    let report = Backtest::load(&config).unwrap().run().unwrap();
+    assert!(report.summary.trades > 0);
+    assert_eq!(report.summary.trades, report.summary.positions_opened);
+    assert_eq!(report.symbols, vec!["BTC", "ETH", "SOL", "USDC"]);
+    assert_eq!(report.snapshots.len(), 25);
+
+    let last = report.snapshots.last().unwrap();
+    assert_eq!(last.open_positions, 0);
+    assert!(last.assets.iter().all(|asset| asset.utilization == 0));
+
+    // runs are reproducible for a given seed
+    assert_eq!(Backtest::load(&config).unwrap().run().unwrap(), report);
+}
@@ -0,0 +1,735 @@
+//! Command line arguments
+
+use {
+    clap::{Arg, Command},
+    perpetuals_cli::config::PermissionsConfig,
+};
+
+const DELEGATE_PERMISSIONS: [&str; 4] = [
+    "open-position",
+    "close-position",
+    "add-collateral",
+    "remove-collateral",
+];
+
+const ROLES: [&str; 5] = [
+    "admin",
+    "guardian",
+    "risk-manager",
+    "treasurer",
+    "oracle-operator",
+];
+
+pub fn build() -> Command<'static> {
+    Command::new("perpetuals-cli")
+        .about("Solana Perpetuals admin and trading command line interface")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            Arg::new("url")
+                .short('u')
+                .long("url")
+                .takes_value(true)
+                .global(true)
+                .default_value("https://api.devnet.solana.com")
+                .help("RPC endpoint, use http://localhost:8899 for a local validator"),
+        )
+        .arg(
+            Arg::new("keypair")
+                .short('k')
+                .long("keypair")
+                .takes_value(true)
+                .global(true)
+                .help("Local signer keypair, required"),
+        )
+        .arg(
+            Arg::new("admin")
+                .long("admin")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true)
+                .help(
+                    "Multisig signer the admin instruction is signed by, repeat to collect \
+                     several signatures in one transaction [default: keypair]",
+                ),
+        )
+        .arg(
+            Arg::new("role")
+                .long("role")
+                .takes_value(true)
+                .global(true)
+                .possible_values(ROLES)
+                .default_value("admin")
+                .help("Signer set authorizing role gated admin instructions"),
+        )
+        .arg(Arg::new("propose").long("propose").global(true).help(
+            "Create a timelock proposal for the admin instruction instead of \
+                     executing it, other admins approve the proposal",
+        ))
+        .arg(
+            Arg::new("proposal-id")
+                .long("proposal-id")
+                .takes_value(true)
+                .global(true)
+                .help("Id of the proposal to create, required offline [default: next id]"),
+        )
+        .arg(
+            Arg::new("fee-payer")
+                .long("fee-payer")
+                .takes_value(true)
+                .global(true)
+                .help("Transaction fee payer [default: first admin or keypair]"),
+        )
+        .arg(
+            Arg::new("blockhash")
+                .long("blockhash")
+                .takes_value(true)
+                .global(true)
+                .help("Use the given blockhash (or nonce value) instead of fetching one"),
+        )
+        .arg(
+            Arg::new("nonce")
+                .long("nonce")
+                .takes_value(true)
+                .global(true)
+                .help("Durable nonce account, for signatures that don't expire"),
+        )
+        .arg(
+            Arg::new("nonce-authority")
+                .long("nonce-authority")
+                .takes_value(true)
+                .global(true)
+                .requires("nonce")
+                .help("Nonce account authority [default: fee payer]"),
+        )
+        .arg(
+            Arg::new("sign-only")
+                .long("sign-only")
+                .global(true)
+                .requires("blockhash")
+                .help("Sign the transaction offline and print signatures instead of sending it"),
+        )
+        .arg(
+            Arg::new("signer")
+                .long("signer")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .global(true)
+                .help("PUBKEY=SIGNATURE pair printed by --sign-only"),
+        )
+        // admin instructions
+        .subcommand(
+            Command::new("init")
+                .about("Initialize the on-chain program, signed by the upgrade authority")
+                .arg(min_signatures_arg())
+                .arg(signers_arg()),
+        )
+        .subcommand(
+            Command::new("upgrade-multisig")
+                .about("Upgrade the multisig account, signed by the upgrade authority"),
+        )
+        .subcommand(
+            Command::new("set-admin-signers")
+                .about("Set admin signers and minimum signatures")
+                .arg(min_signatures_arg())
+                .arg(signers_arg()),
+        )
+        .subcommand(
+            Command::new("set-role-signers")
+                .about("Set signers of a restricted admin role")
+                .arg(
+                    Arg::new("target-role")
+                        .required(true)
+                        .possible_values(&ROLES[1..])
+                        .help("Role to set signers for"),
+                )
+                .arg(min_signatures_arg())
+                .arg(signers_arg()),
+        )
+        .subcommand(
+            Command::new("set-timelock-delay")
+                .about("Set the delay between proposal approval and execution, 0 disables it")
+                .arg(Arg::new("delay").required(true).help("Delay in seconds")),
+        )
+        .subcommand(
+            Command::new("approve-proposal")
+                .about("Approve a timelock proposal")
+                .arg(proposal_arg()),
+        )
+        .subcommand(
+            Command::new("cancel-proposal")
+                .about("Cancel a timelock proposal, queued proposals require the approval quorum")
+                .arg(proposal_arg()),
+        )
+        .subcommand(
+            Command::new("execute-proposal")
+                .about("Execute an approved proposal once the timelock has passed")
+                .arg(proposal_arg()),
+        )
+        .subcommand(
+            Command::new("set-permissions")
+                .about("Set protocol permissions, unchanged permissions keep their current value")
+                .arg(config_arg(false))
+                .arg(permission_arg("enable"))
+                .arg(permission_arg("disable")),
+        )
+        .subcommand(
+            Command::new("add-pool")
+                .about("Create a new pool")
+                .arg(pool_arg())
+                .arg(
+                    Arg::new("token-2022")
+                        .long("token-2022")
+                        .help("Create the LP token mint with the Token-2022 program"),
+                ),
+        )
+        .subcommand(
+            Command::new("remove-pool")
+                .about("Remove the pool")
+                .arg(pool_arg()),
+        )
+        .subcommand(
+            Command::new("add-custody")
+                .about("Add a custody to the pool, ratios are set for all pool custodies")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("remove-custody")
+                .about("Remove a custody from the pool")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("set-custody-config")
+                .about(
+                    "Update custody config, fields missing in the config keep their current value",
+                )
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("set-custody-risk")
+                .about("Update custody pricing, fees and borrow rate, allowed to the risk manager")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("diff-custody-config")
+                .about("Compare the config file with the on-chain custody config")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("get-custody-config")
+                .about("Print the on-chain custody config in the config file format")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["toml", "json"])
+                        .default_value("toml"),
+                ),
+        )
+        .subcommand(
+            Command::new("upgrade-custody")
+                .about("Upgrade a deprecated custody account")
+                .arg(pool_arg())
+                .arg(mint_arg()),
+        )
+        .subcommand(
+            Command::new("migrate-custody")
+                .about("Migrate a custody account created before price impact params")
+                .arg(pool_arg())
+                .arg(mint_arg()),
+        )
+        .subcommand(
+            Command::new("set-fee-tiers")
+                .about("Set pool fee discount tiers")
+                .arg(pool_arg())
+                .arg(config_arg(true)),
+        )
+        .subcommand(
+            Command::new("withdraw-fees")
+                .about("Withdraw protocol fees")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(amount_arg("amount", "Token amount"))
+                .arg(receiving_account_arg(true)),
+        )
+        .subcommand(
+            Command::new("withdraw-sol-fees")
+                .about("Withdraw SOL fees from the transfer authority")
+                .arg(amount_arg("amount", "Lamports amount"))
+                .arg(receiving_account_arg(true)),
+        )
+        .subcommand(
+            Command::new("set-custom-oracle-price")
+                .about("Set custom oracle price")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .args(oracle_price_args()),
+        )
+        .subcommand(
+            Command::new("set-custom-oracle-price-permissionless")
+                .about("Publish a custom oracle price signed by the oracle authority")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .args(oracle_price_args())
+                .arg(
+                    Arg::new("oracle-authority")
+                        .long("oracle-authority")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Oracle authority keypair"),
+                ),
+        )
+        .subcommand(
+            Command::new("set-test-time")
+                .about("Set the program time, test builds only")
+                .arg(Arg::new("time").required(true).help("Unix timestamp")),
+        )
+        // trading instructions
+        .subcommand(
+            Command::new("swap")
+                .about("Swap tokens")
+                .arg(pool_arg())
+                .arg(Arg::new("mint-in").required(true).help("Token mint in"))
+                .arg(Arg::new("mint-out").required(true).help("Token mint out"))
+                .arg(amount_arg("amount-in", "Token amount to be swapped"))
+                .arg(amount_arg("min-amount-out", "Minimum token amount out")),
+        )
+        .subcommand(
+            Command::new("add-liquidity")
+                .about("Deposit tokens to the pool")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(amount_arg("amount-in", "Token amount to deposit"))
+                .arg(amount_arg("min-lp-amount-out", "Minimum LP tokens out")),
+        )
+        .subcommand(
+            Command::new("remove-liquidity")
+                .about("Withdraw tokens from the pool")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(amount_arg("lp-amount-in", "LP token amount to burn"))
+                .arg(amount_arg("min-amount-out", "Minimum token amount out")),
+        )
+        .subcommand(
+            Command::new("open-position")
+                .about("Open a position")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(collateral_mint_arg())
+                .arg(side_arg())
+                .arg(amount_arg("price", "Worst acceptable entry price"))
+                .arg(amount_arg("collateral", "Collateral amount"))
+                .arg(amount_arg("size", "Position size"))
+                .arg(owner_arg()),
+        )
+        .subcommand(
+            Command::new("add-collateral")
+                .about("Add collateral to the position")
+                .args(position_args(false))
+                .arg(amount_arg("collateral", "Collateral amount"))
+                .arg(owner_arg()),
+        )
+        .subcommand(
+            Command::new("remove-collateral")
+                .about("Remove collateral from the position")
+                .args(position_args(false))
+                .arg(amount_arg("collateral-usd", "Collateral amount in USD"))
+                .arg(owner_arg()),
+        )
+        .subcommand(
+            Command::new("close-position")
+                .about("Close the position")
+                .args(position_args(false))
+                .arg(amount_arg("price", "Worst acceptable exit price"))
+                .arg(owner_arg()),
+        )
+        .subcommand(
+            Command::new("transfer-position")
+                .about("Transfer the position to another wallet")
+                .args(position_args(false))
+                .arg(
+                    Arg::new("new-owner")
+                        .long("new-owner")
+                        .takes_value(true)
+                        .required(true)
+                        .help("New position owner"),
+                ),
+        )
+        .subcommand(
+            Command::new("set-trading-delegate")
+                .about("Allow another key to trade positions of the keypair")
+                .arg(pool_arg())
+                .arg(
+                    Arg::new("delegate")
+                        .required(true)
+                        .help("Delegate public key"),
+                )
+                .arg(
+                    Arg::new("allow")
+                        .long("allow")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .possible_values(DELEGATE_PERMISSIONS)
+                        .help("Instruction the delegate can sign, can be repeated [default: all]"),
+                )
+                .arg(
+                    Arg::new("max-size-usd")
+                        .long("max-size-usd")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Maximum position size in USD, 0 for no limit"),
+                )
+                .arg(
+                    Arg::new("max-leverage")
+                        .long("max-leverage")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Maximum position leverage in BPS, 0 for no limit"),
+                )
+                .arg(
+                    Arg::new("expiration-time")
+                        .long("expiration-time")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Unix time the delegate expires at, 0 for never"),
+                ),
+        )
+        .subcommand(
+            Command::new("remove-trading-delegate")
+                .about("Revoke the trading delegate of the keypair")
+                .arg(pool_arg()),
+        )
+        .subcommand(
+            Command::new("liquidate")
+                .about("Liquidate the position, rewards are paid to the keypair")
+                .arg(Arg::new("position").required(true).help("Position address")),
+        )
+        .subcommand(
+            Command::new("update-pool-aum")
+                .about("Recompute pool assets under management")
+                .arg(pool_arg()),
+        )
+        // darkpool instructions
+        .subcommand(
+            Command::new("settle-dark-pool-trade")
+                .about("Settle a trade queued by the darkpool, opening both positions")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(collateral_mint_arg())
+                .arg(amount_arg("order-book", "Darkpool order book of the trade"))
+                .arg(amount_arg("trade-id", "Trade id in the settlement queue"))
+                .arg(amount_arg("long-owner", "Owner of the long order"))
+                .arg(amount_arg("short-owner", "Owner of the short order")),
+        )
+        // views
+        .subcommand(Command::new("get-perpetuals").about("Print the perpetuals account"))
+        .subcommand(Command::new("get-multisig").about("Print the signer set of --role"))
+        .subcommand(
+            Command::new("get-proposal")
+                .about("Print the timelock proposal")
+                .arg(proposal_arg()),
+        )
+        .subcommand(
+            Command::new("get-pool")
+                .about("Print the pool account")
+                .arg(pool_arg()),
+        )
+        .subcommand(
+            Command::new("get-custody")
+                .about("Print the custody account")
+                .arg(pool_arg())
+                .arg(mint_arg()),
+        )
+        .subcommand(
+            Command::new("get-position")
+                .about("Print the position account")
+                .args(position_args(true)),
+        )
+        .subcommand(
+            Command::new("get-positions")
+                .about("Print all positions of the owner")
+                .arg(Arg::new("owner").required(true).help("Position owner")),
+        )
+        .subcommand(
+            Command::new("get-trading-delegate")
+                .about("Print the trading delegate account")
+                .arg(Arg::new("owner").required(true).help("Position owner"))
+                .arg(pool_arg()),
+        )
+        .subcommand(
+            Command::new("get-add-liquidity-amount-and-fee")
+                .about("Compute LP tokens out and fee for a deposit")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(amount_arg("amount", "Token amount")),
+        )
+        .subcommand(
+            Command::new("get-remove-liquidity-amount-and-fee")
+                .about("Compute tokens out and fee for a withdrawal")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(amount_arg("amount", "LP token amount")),
+        )
+        .subcommand(
+            Command::new("get-entry-price-and-fee")
+                .about("Compute entry price, liquidation price and fee of a new position")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(collateral_mint_arg())
+                .arg(side_arg())
+                .arg(amount_arg("collateral", "Collateral amount"))
+                .arg(amount_arg("size", "Position size"))
+                .arg(
+                    Arg::new("trader")
+                        .long("trader")
+                        .takes_value(true)
+                        .help("Trader to apply the fee discount of, defaults to the signer"),
+                ),
+        )
+        .subcommand(
+            Command::new("get-exit-price-and-fee")
+                .about("Compute exit price and fee of the position")
+                .args(position_args(true)),
+        )
+        .subcommand(
+            Command::new("get-pnl")
+                .about("Compute profit and loss of the position")
+                .args(position_args(true)),
+        )
+        .subcommand(
+            Command::new("get-liquidation-price")
+                .about("Compute liquidation price of the position")
+                .args(position_args(true))
+                .arg(
+                    Arg::new("add-collateral")
+                        .long("add-collateral")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Collateral to add"),
+                )
+                .arg(
+                    Arg::new("remove-collateral")
+                        .long("remove-collateral")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Collateral to remove in USD"),
+                ),
+        )
+        .subcommand(
+            Command::new("get-liquidation-state")
+                .about("Check whether the position can be liquidated")
+                .args(position_args(true)),
+        )
+        .subcommand(
+            Command::new("get-oracle-price")
+                .about("Print the custody oracle price")
+                .arg(pool_arg())
+                .arg(mint_arg())
+                .arg(Arg::new("ema").long("ema").help("Return EMA price")),
+        )
+        .subcommand(
+            Command::new("get-swap-amount-and-fees")
+                .about("Compute swap amount out and fees")
+                .arg(pool_arg())
+                .arg(Arg::new("mint-in").required(true).help("Token mint in"))
+                .arg(Arg::new("mint-out").required(true).help("Token mint out"))
+                .arg(amount_arg("amount-in", "Token amount to be swapped")),
+        )
+        .subcommand(
+            Command::new("get-aum")
+                .about("Compute pool assets under management")
+                .arg(pool_arg()),
+        )
+        .subcommand(
+            Command::new("get-lp-token-price")
+                .about("Compute LP token price")
+                .arg(pool_arg()),
+        )
+}
+
+fn pool_arg() -> Arg<'static> {
+    Arg::new("pool").required(true).help("Pool name")
+}
+
+fn mint_arg() -> Arg<'static> {
+    Arg::new("mint").required(true).help("Token mint")
+}
+
+fn collateral_mint_arg() -> Arg<'static> {
+    Arg::new("collateral-mint")
+        .required(true)
+        .help("Collateral token mint")
+}
+
+fn side_arg() -> Arg<'static> {
+    Arg::new("side")
+        .required(true)
+        .possible_values(["long", "short"])
+        .help("Position side")
+}
+
+fn proposal_arg() -> Arg<'static> {
+    Arg::new("id").required(true).help("Proposal id")
+}
+
+fn min_signatures_arg() -> Arg<'static> {
+    Arg::new("min-signatures")
+        .short('m')
+        .long("min-signatures")
+        .takes_value(true)
+        .required(true)
+        .help("Minimum signatures")
+}
+
+fn signers_arg() -> Arg<'static> {
+    Arg::new("signers")
+        .required(true)
+        .multiple_values(true)
+        .help("Signer public keys")
+}
+
+fn amount_arg(name: &'static str, help: &'static str) -> Arg<'static> {
+    Arg::new(name)
+        .long(name)
+        .takes_value(true)
+        .required(true)
+        .help(help)
+}
+
+fn config_arg(required: bool) -> Arg<'static> {
+    Arg::new("config")
+        .short('c')
+        .long("config")
+        .takes_value(true)
+        .required(required)
+        .help("TOML or JSON config file")
+}
+
+fn receiving_account_arg(required: bool) -> Arg<'static> {
+    Arg::new("receiving-account")
+        .long("receiving-account")
+        .takes_value(true)
+        .required(required)
+        .help("Account to receive the funds")
+}
+
+fn permission_arg(name: &'static str) -> Arg<'static> {
+    Arg::new(name)
+        .long(name)
+        .takes_value(true)
+        .multiple_occurrences(true)
+        .possible_values(PermissionsConfig::NAMES)
+        .help("Permission to change, can be repeated")
+}
+
+fn oracle_price_args() -> Vec<Arg<'static>> {
+    vec![
+        amount_arg("price", "Price as integer"),
+        Arg::new("exponent")
+            .long("exponent")
+            .takes_value(true)
+            .required(true)
+            .allow_hyphen_values(true)
+            .help("Price exponent"),
+        amount_arg("confidence", "Confidence"),
+        amount_arg("ema", "EMA price as integer"),
+        Arg::new("publish-time")
+            .long("publish-time")
+            .takes_value(true)
+            .help("Publish time [default: now]"),
+    ]
+}
+
+/// Position pool, custody mint and side, trading instructions are signed by
+/// the owner so it is only an argument for views
+fn owner_arg() -> Arg<'static> {
+    Arg::new("owner")
+        .long("owner")
+        .takes_value(true)
+        .help("Position owner, when trading as its delegate [default: keypair]")
+}
+
+fn position_args(with_owner: bool) -> Vec<Arg<'static>> {
+    let mut args = vec![];
+    if with_owner {
+        args.push(Arg::new("owner").required(true).help("Position owner"));
+    }
+    args.extend([
+        pool_arg(),
+        mint_arg(),
+        side_arg(),
+        Arg::new("index")
+            .long("index")
+            .takes_value(true)
+            .default_value("0")
+            .help("Position index, see get-positions"),
+    ]);
+    args
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_app() {
+        build().debug_assert();
+
+        let matches = build()
+            .try_get_matches_from([
+                "perpetuals-cli",
+                "set-permissions",
+                "--disable",
+                "swap",
+                "--disable",
+                "open-position",
+                "--admin",
+                "11111111111111111111111111111111",
+                "--sign-only",
+                "--blockhash",
+                "11111111111111111111111111111111",
+            ])
+            .unwrap();
+        assert!(matches.is_present("sign-only"));
+        let (_, args) = matches.subcommand().unwrap();
+        assert_eq!(
+            args.values_of("disable").unwrap().collect::<Vec<_>>(),
+            vec!["swap", "open-position"]
+        );
+
+        assert!(build()
+            .try_get_matches_from([
+                "perpetuals-cli",
+                "settle-dark-pool-trade",
+                "main_pool",
+                "11111111111111111111111111111111",
+                "11111111111111111111111111111111",
+                "--order-book",
+                "11111111111111111111111111111111",
+                "--trade-id",
+                "3",
+                "--long-owner",
+                "11111111111111111111111111111111",
+                "--short-owner",
+                "11111111111111111111111111111111",
+            ])
+            .is_ok());
+
+        // signatures collected offline require a fixed blockhash
+        assert!(build()
+            .try_get_matches_from(["perpetuals-cli", "get-perpetuals", "--sign-only"])
+            .is_err());
+    }
+}
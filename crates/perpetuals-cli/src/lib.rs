@@ -0,0 +1,7 @@
+//! Admin command line interface for the Solana Perpetuals program
+
+#![allow(clippy::result_large_err)]
+
+pub mod transaction;
+
+pub use perpetuals_client::config;
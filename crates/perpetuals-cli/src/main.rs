@@ -0,0 +1,1449 @@
+//! Perpetuals admin command line interface
+
+#![allow(clippy::result_large_err)]
+
+mod app;
+
+use {
+    anchor_lang::{AccountDeserialize, AnchorDeserialize, AnchorSerialize, Discriminator},
+    anyhow::{anyhow, bail, Result},
+    base64::{engine::general_purpose::STANDARD, Engine},
+    clap::ArgMatches,
+    perpetuals::{
+        instructions::*,
+        state::{
+            custody::Custody,
+            multisig::{AdminRole, Multisig},
+            oracle::OraclePrice,
+            perpetuals::{
+                AmountAndFee, NewPositionPricesAndFee, Perpetuals, PriceAndFee, ProfitAndLoss,
+                SwapAmountAndFees,
+            },
+            pool::Pool,
+            position::{Position, Side},
+            position_counter::PositionCounter,
+            proposal::{Proposal, ProposalAccount},
+            trading_delegate::{DelegatePermissions, TradingDelegate},
+        },
+    },
+    perpetuals_cli::{
+        config::{
+            self, ConfigFormat, CustodyConfig, FeeTiersConfig, PermissionsConfig, PoolRatiosConfig,
+        },
+        transaction::{self, NonceConfig},
+    },
+    perpetuals_client::{accounts, instructions, pda, CustodyKeys},
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        nonce_utils,
+        rpc_client::RpcClient,
+        rpc_config::{
+            RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSimulateTransactionConfig,
+        },
+        rpc_filter::{Memcmp, RpcFilterType},
+    },
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        ed25519_instruction,
+        hash::Hash,
+        instruction::Instruction,
+        message::Message,
+        pubkey::Pubkey,
+        signature::{read_keypair_file, Keypair, Signature, Signer},
+        transaction::Transaction,
+    },
+    spl_associated_token_account::{
+        get_associated_token_address_with_program_id,
+        instruction::create_associated_token_account_idempotent,
+    },
+    std::{
+        path::Path,
+        str::FromStr,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+// return data is padded, the runtime strips trailing zeros
+const MAX_RETURN_DATA_LEN: usize = 64;
+
+fn main() -> Result<()> {
+    let matches = app::build().get_matches();
+    let cli = Cli::new(&matches)?;
+    let (command, args) = matches
+        .subcommand()
+        .ok_or_else(|| anyhow!("Missing command"))?;
+    cli.process_command(command, args)
+}
+
+struct Cli {
+    rpc: RpcClient,
+    keypair: Keypair,
+    // multisig signers the admin instruction is built for
+    admins: Vec<Pubkey>,
+    role: AdminRole,
+    propose: bool,
+    proposal_id: Option<u64>,
+    fee_payer: Pubkey,
+    blockhash: Option<Hash>,
+    nonce: Option<NonceConfig>,
+    sign_only: bool,
+    presigners: Vec<(Pubkey, Signature)>,
+}
+
+impl Cli {
+    fn new(matches: &ArgMatches) -> Result<Self> {
+        let url = matches.value_of("url").unwrap();
+        let keypair = read_keypair(
+            matches
+                .value_of("keypair")
+                .ok_or_else(|| anyhow!("--keypair is required"))?,
+        )?;
+        let admins = if matches.is_present("admin") {
+            matches
+                .values_of("admin")
+                .unwrap()
+                .map(parse_pubkey)
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            vec![keypair.pubkey()]
+        };
+        let fee_payer = match matches.value_of("fee-payer") {
+            Some(fee_payer) => parse_pubkey(fee_payer)?,
+            None if matches.is_present("admin") => admins[0],
+            None => keypair.pubkey(),
+        };
+        let nonce = matches
+            .value_of("nonce")
+            .map(|account| -> Result<NonceConfig> {
+                Ok(NonceConfig {
+                    account: parse_pubkey(account)?,
+                    authority: matches
+                        .value_of("nonce-authority")
+                        .map_or(Ok(fee_payer), parse_pubkey)?,
+                })
+            })
+            .transpose()?;
+        let presigners = matches
+            .values_of("signer")
+            .map(|signers| {
+                signers
+                    .map(transaction::parse_presigner)
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Self {
+            rpc: RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed()),
+            keypair,
+            admins,
+            role: parse_role(matches.value_of("role").unwrap())?,
+            propose: matches.is_present("propose"),
+            proposal_id: matches
+                .is_present("proposal-id")
+                .then(|| matches.value_of_t("proposal-id"))
+                .transpose()?,
+            fee_payer,
+            blockhash: matches
+                .value_of("blockhash")
+                .map(|blockhash| {
+                    Hash::from_str(blockhash)
+                        .map_err(|err| anyhow!("Invalid blockhash {blockhash}: {err}"))
+                })
+                .transpose()?,
+            nonce,
+            sign_only: matches.is_present("sign-only"),
+            presigners,
+        })
+    }
+
+    fn process_command(&self, command: &str, args: &ArgMatches) -> Result<()> {
+        match command {
+            "init" => self.init(args),
+            "upgrade-multisig" => self.upgrade_multisig(),
+            "set-admin-signers" => self.set_admin_signers(args),
+            "set-role-signers" => self.set_role_signers(args),
+            "set-timelock-delay" => self.set_timelock_delay(args),
+            "approve-proposal" => self.approve_proposal(args),
+            "cancel-proposal" => self.cancel_proposal(args),
+            "execute-proposal" => self.execute_proposal(args),
+            "set-permissions" => self.set_permissions(args),
+            "add-pool" => self.add_pool(args),
+            "remove-pool" => self.remove_pool(args),
+            "add-custody" => self.add_custody(args),
+            "remove-custody" => self.remove_custody(args),
+            "set-custody-config" => self.set_custody_config(args),
+            "set-custody-risk" => self.set_custody_risk(args),
+            "diff-custody-config" => self.diff_custody_config(args),
+            "get-custody-config" => self.get_custody_config(args),
+            "upgrade-custody" => self.upgrade_custody(args),
+            "migrate-custody" => self.migrate_custody(args),
+            "set-fee-tiers" => self.set_fee_tiers(args),
+            "withdraw-fees" => self.withdraw_fees(args),
+            "withdraw-sol-fees" => self.withdraw_sol_fees(args),
+            "set-custom-oracle-price" => self.set_custom_oracle_price(args),
+            "set-custom-oracle-price-permissionless" => {
+                self.set_custom_oracle_price_permissionless(args)
+            }
+            "set-test-time" => self.set_test_time(args),
+            "swap" => self.swap(args),
+            "add-liquidity" => self.add_liquidity(args),
+            "remove-liquidity" => self.remove_liquidity(args),
+            "open-position" => self.open_position(args),
+            "add-collateral" => self.add_collateral(args),
+            "remove-collateral" => self.remove_collateral(args),
+            "close-position" => self.close_position(args),
+            "transfer-position" => self.transfer_position(args),
+            "set-trading-delegate" => self.set_trading_delegate(args),
+            "remove-trading-delegate" => self.remove_trading_delegate(args),
+            "liquidate" => self.liquidate(args),
+            "update-pool-aum" => self.update_pool_aum(args),
+            "settle-dark-pool-trade" => self.settle_dark_pool_trade(args),
+            "get-perpetuals" => self.print_account::<Perpetuals>(&pda::get_perpetuals_pda().0),
+            "get-multisig" => self.get_multisig(),
+            "get-proposal" => self.print_account::<Proposal>(
+                &pda::get_proposal_pda(self.role, args.value_of_t("id")?).0,
+            ),
+            "get-pool" => self.print_account::<Pool>(&get_pool(args).1),
+            "get-custody" => {
+                let (_, pool, mint) = get_custody_args(args)?;
+                self.print_account::<Custody>(&pda::get_custody_pda(&pool, &mint).0)
+            }
+            "get-position" => {
+                let (position, _) = self.get_position(args, None)?;
+                self.print_account::<Position>(&position)
+            }
+            "get-positions" => {
+                self.print_positions(&parse_pubkey(args.value_of("owner").unwrap())?)
+            }
+            "get-trading-delegate" => {
+                let owner = parse_pubkey(args.value_of("owner").unwrap())?;
+                let pool = get_pool(args).1;
+                self.print_account::<TradingDelegate>(
+                    &pda::get_trading_delegate_pda(&owner, &pool).0,
+                )
+            }
+            "get-add-liquidity-amount-and-fee" => self.get_add_liquidity_amount_and_fee(args),
+            "get-remove-liquidity-amount-and-fee" => self.get_remove_liquidity_amount_and_fee(args),
+            "get-entry-price-and-fee" => self.get_entry_price_and_fee(args),
+            "get-exit-price-and-fee" => self.get_exit_price_and_fee(args),
+            "get-pnl" => self.get_pnl(args),
+            "get-liquidation-price" => self.get_liquidation_price(args),
+            "get-liquidation-state" => self.get_liquidation_state(args),
+            "get-oracle-price" => self.get_oracle_price(args),
+            "get-swap-amount-and-fees" => self.get_swap_amount_and_fees(args),
+            "get-aum" => self.get_aum(args),
+            "get-lp-token-price" => self.get_lp_token_price(args),
+            _ => Err(anyhow!("Unknown command: {command}")),
+        }
+    }
+
+    // admin instructions
+
+    fn init(&self, args: &ArgMatches) -> Result<()> {
+        let signers = get_signers(args)?;
+        self.process_transaction(&[instructions::init(
+            &self.keypair.pubkey(),
+            &signers,
+            InitParams {
+                min_signatures: args.value_of_t("min-signatures")?,
+                allow_swap: true,
+                allow_add_liquidity: true,
+                allow_remove_liquidity: true,
+                allow_open_position: true,
+                allow_close_position: true,
+                allow_pnl_withdrawal: true,
+                allow_collateral_withdrawal: true,
+                allow_size_change: true,
+            },
+        )])
+    }
+
+    fn upgrade_multisig(&self) -> Result<()> {
+        self.process_transaction(&[instructions::upgrade_multisig(
+            &self.keypair.pubkey(),
+            UpgradeMultisigParams {},
+        )])
+    }
+
+    fn set_admin_signers(&self, args: &ArgMatches) -> Result<()> {
+        let signers = get_signers(args)?;
+        let min_signatures = args.value_of_t("min-signatures")?;
+        self.process_admin_instruction(|admin| {
+            instructions::set_admin_signers(
+                admin,
+                &signers,
+                SetAdminSignersParams { min_signatures },
+            )
+        })
+    }
+
+    fn set_role_signers(&self, args: &ArgMatches) -> Result<()> {
+        let signers = get_signers(args)?;
+        let role = parse_role(args.value_of("target-role").unwrap())?;
+        let min_signatures = args.value_of_t("min-signatures")?;
+        self.process_admin_instruction(|admin| {
+            instructions::set_role_signers(
+                admin,
+                &signers,
+                SetRoleSignersParams {
+                    role,
+                    min_signatures,
+                },
+            )
+        })
+    }
+
+    fn set_timelock_delay(&self, args: &ArgMatches) -> Result<()> {
+        let timelock_delay = args.value_of_t("delay")?;
+        self.process_admin_instruction(|admin| {
+            instructions::set_timelock_delay(admin, SetTimelockDelayParams { timelock_delay })
+        })
+    }
+
+    fn approve_proposal(&self, args: &ArgMatches) -> Result<()> {
+        let id = args.value_of_t("id")?;
+        self.process_transaction(
+            &self
+                .admins
+                .iter()
+                .map(|admin| {
+                    instructions::approve_proposal(admin, self.role, id, ApproveProposalParams {})
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn cancel_proposal(&self, args: &ArgMatches) -> Result<()> {
+        let id = args.value_of_t("id")?;
+        let proposal: Proposal = self.get_account(&pda::get_proposal_pda(self.role, id).0)?;
+        // a pending proposal is closed by the first cancellation, a queued one
+        // requires the approval quorum
+        let admins = if proposal.eta > 0 {
+            &self.admins[..]
+        } else {
+            &self.admins[..1]
+        };
+        self.process_transaction(
+            &admins
+                .iter()
+                .map(|admin| {
+                    instructions::cancel_proposal(
+                        admin,
+                        self.role,
+                        id,
+                        &proposal.proposer,
+                        CancelProposalParams {},
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn execute_proposal(&self, args: &ArgMatches) -> Result<()> {
+        let proposal: Proposal =
+            self.get_account(&pda::get_proposal_pda(self.role, args.value_of_t("id")?).0)?;
+        self.process_transaction(&[instructions::execute_proposal(
+            &self.keypair.pubkey(),
+            self.role,
+            &proposal,
+            ExecuteProposalParams {},
+        )])
+    }
+
+    fn set_permissions(&self, args: &ArgMatches) -> Result<()> {
+        let mut permissions = match args.value_of("config") {
+            Some(path) => config::load_config(config::read_config_file(Path::new(path))?, None)?,
+            None if self.sign_only => bail!("Offline signers must provide the full config"),
+            None => {
+                let perpetuals: Perpetuals = self.get_account(&pda::get_perpetuals_pda().0)?;
+                PermissionsConfig::from(perpetuals.permissions)
+            }
+        };
+        for (arg, allow) in [("enable", true), ("disable", false)] {
+            for name in args.values_of(arg).into_iter().flatten() {
+                permissions.set(name, allow)?;
+            }
+        }
+        println!(
+            "{}",
+            config::write_config(&permissions, ConfigFormat::Toml)?
+        );
+        self.process_admin_instruction(|admin| {
+            instructions::set_permissions(admin, self.role, permissions.into())
+        })
+    }
+
+    fn add_pool(&self, args: &ArgMatches) -> Result<()> {
+        let (name, _) = get_pool(args);
+        let lp_token_program = if args.is_present("token-2022") {
+            anchor_spl::token_2022::ID
+        } else {
+            anchor_spl::token::ID
+        };
+        self.process_admin_instruction(|admin| {
+            instructions::add_pool(
+                admin,
+                &lp_token_program,
+                AddPoolParams {
+                    name: name.to_string(),
+                },
+            )
+        })
+    }
+
+    fn remove_pool(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        self.process_admin_instruction(|admin| {
+            instructions::remove_pool(admin, &pool, RemovePoolParams {})
+        })
+    }
+
+    fn add_custody(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let custody_config: CustodyConfig = config::load_config(read_config_arg(args)?, None)?;
+        let token_program = self.get_mint_token_program(&mint)?;
+        self.process_admin_instruction(|admin| {
+            instructions::add_custody(
+                admin,
+                &pool,
+                &mint,
+                &token_program,
+                custody_config.clone().into(),
+            )
+        })
+    }
+
+    fn remove_custody(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let ratios: PoolRatiosConfig = config::load_config(read_config_arg(args)?, None)?;
+        let custody = self.get_custody_keys(&pool, &mint)?;
+        self.process_admin_instruction(|admin| {
+            instructions::remove_custody(
+                admin,
+                &pool,
+                &mint,
+                &custody.token_program,
+                RemoveCustodyParams {
+                    ratios: ratios.ratios.iter().copied().map(Into::into).collect(),
+                },
+            )
+        })
+    }
+
+    fn set_custody_config(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let value = read_config_arg(args)?;
+        // offline signers must provide the full config
+        let custody_config: CustodyConfig = if self.sign_only {
+            config::load_config(value, None)?
+        } else {
+            let current = self.get_current_custody_config(&pool, &mint)?;
+            let proposed = config::load_config(value, Some(&current))?;
+            print_changes(&current, &proposed)?;
+            proposed
+        };
+        self.process_admin_instruction(|admin| {
+            instructions::set_custody_config(
+                admin,
+                self.role,
+                &pool,
+                &mint,
+                custody_config.clone().into(),
+            )
+        })
+    }
+
+    fn set_custody_risk(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let value = read_config_arg(args)?;
+        // offline signers must provide the full config
+        let custody_config: CustodyConfig = if self.sign_only {
+            config::load_config(value, None)?
+        } else {
+            let current = self.get_current_custody_config(&pool, &mint)?;
+            let proposed = config::load_config(value, Some(&current))?;
+            print_changes(&current, &proposed)?;
+            let risk_only = CustodyConfig {
+                pricing: current.pricing,
+                fees: current.fees,
+                borrow_rate: current.borrow_rate,
+                ..proposed.clone()
+            } == current;
+            if !risk_only {
+                bail!("Only pricing, fees and borrow_rate can be updated, use set-custody-config");
+            }
+            proposed
+        };
+        self.process_admin_instruction(|admin| {
+            instructions::set_custody_risk(
+                admin,
+                self.role,
+                &pool,
+                &mint,
+                custody_config.clone().into(),
+            )
+        })
+    }
+
+    fn diff_custody_config(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let current = self.get_current_custody_config(&pool, &mint)?;
+        let proposed = config::load_config(read_config_arg(args)?, Some(&current))?;
+        print_changes(&current, &proposed)
+    }
+
+    fn get_custody_config(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let current = self.get_current_custody_config(&pool, &mint)?;
+        print!(
+            "{}",
+            config::write_config(&current, args.value_of_t("format")?)?
+        );
+        Ok(())
+    }
+
+    fn upgrade_custody(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let custody = pda::get_custody_pda(&pool, &mint).0;
+        self.process_admin_instruction(|admin| {
+            instructions::upgrade_custody(admin, &pool, &custody, UpgradeCustodyParams {})
+        })
+    }
+
+    fn migrate_custody(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let custody = pda::get_custody_pda(&pool, &mint).0;
+        self.process_admin_instruction(|admin| {
+            instructions::migrate_custody(admin, &pool, &custody, MigrateCustodyParams {})
+        })
+    }
+
+    fn set_fee_tiers(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        let fee_tiers: FeeTiersConfig = config::load_config(read_config_arg(args)?, None)?;
+        self.process_admin_instruction(|admin| {
+            instructions::set_fee_tiers(
+                admin,
+                self.role,
+                &pool,
+                SetFeeTiersParams {
+                    fee_tiers: fee_tiers
+                        .fee_tiers
+                        .iter()
+                        .copied()
+                        .map(Into::into)
+                        .collect(),
+                },
+            )
+        })
+    }
+
+    fn withdraw_fees(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let receiving_account = parse_pubkey(args.value_of("receiving-account").unwrap())?;
+        let amount = args.value_of_t("amount")?;
+        let custody = self.get_custody_keys(&pool, &mint)?;
+        self.process_admin_instruction(|admin| {
+            instructions::withdraw_fees(
+                admin,
+                self.role,
+                &pool,
+                &mint,
+                &custody.token_program,
+                &receiving_account,
+                WithdrawFeesParams { amount },
+            )
+        })
+    }
+
+    fn withdraw_sol_fees(&self, args: &ArgMatches) -> Result<()> {
+        let receiving_account = parse_pubkey(args.value_of("receiving-account").unwrap())?;
+        let amount = args.value_of_t("amount")?;
+        self.process_admin_instruction(|admin| {
+            instructions::withdraw_sol_fees(
+                admin,
+                self.role,
+                &receiving_account,
+                WithdrawSolFeesParams { amount },
+            )
+        })
+    }
+
+    fn set_custom_oracle_price(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let params = SetCustomOraclePriceParams {
+            price: args.value_of_t("price")?,
+            expo: args.value_of_t("exponent")?,
+            conf: args.value_of_t("confidence")?,
+            ema: args.value_of_t("ema")?,
+            publish_time: get_publish_time(args)?,
+        };
+        self.process_admin_instruction(|admin| {
+            instructions::set_custom_oracle_price(admin, self.role, &pool, &mint, params)
+        })
+    }
+
+    fn set_custom_oracle_price_permissionless(&self, args: &ArgMatches) -> Result<()> {
+        // the program expects the signature verification to be the first instruction
+        if self.nonce.is_some() {
+            bail!("Durable nonce can't be used with permissionless oracle updates");
+        }
+        let (_, pool, mint) = get_custody_args(args)?;
+        let oracle_authority = read_keypair(args.value_of("oracle-authority").unwrap())?;
+        let params = SetCustomOraclePricePermissionlessParams {
+            custody_account: pda::get_custody_pda(&pool, &mint).0,
+            price: args.value_of_t("price")?,
+            expo: args.value_of_t("exponent")?,
+            conf: args.value_of_t("confidence")?,
+            ema: args.value_of_t("ema")?,
+            publish_time: get_publish_time(args)?,
+        };
+        let signer = ed25519_dalek::Keypair::from_bytes(&oracle_authority.to_bytes())
+            .map_err(|err| anyhow!("Invalid oracle authority keypair: {err}"))?;
+        self.process_transaction(&[
+            ed25519_instruction::new_ed25519_instruction(&signer, &params.try_to_vec()?),
+            instructions::set_custom_oracle_price_permissionless(&pool, &mint, params),
+        ])
+    }
+
+    fn set_test_time(&self, args: &ArgMatches) -> Result<()> {
+        let time = args.value_of_t("time")?;
+        self.process_admin_instruction(|admin| {
+            instructions::set_test_time(admin, SetTestTimeParams { time })
+        })
+    }
+
+    // trading instructions
+
+    fn swap(&self, args: &ArgMatches) -> Result<()> {
+        let owner = self.keypair.pubkey();
+        let (_, pool) = get_pool(args);
+        let mint_in = parse_pubkey(args.value_of("mint-in").unwrap())?;
+        let mint_out = parse_pubkey(args.value_of("mint-out").unwrap())?;
+        let receiving_custody = self.get_custody_keys(&pool, &mint_in)?;
+        let dispensing_custody = self.get_custody_keys(&pool, &mint_out)?;
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &mint_out,
+                &dispensing_custody.token_program,
+            ),
+            instructions::swap(
+                &owner,
+                &get_token_address(&owner, &receiving_custody),
+                &get_token_address(&owner, &dispensing_custody),
+                &pool,
+                &receiving_custody,
+                &dispensing_custody,
+                SwapParams {
+                    amount_in: args.value_of_t("amount-in")?,
+                    min_amount_out: args.value_of_t("min-amount-out")?,
+                },
+            ),
+        ])
+    }
+
+    fn add_liquidity(&self, args: &ArgMatches) -> Result<()> {
+        let owner = self.keypair.pubkey();
+        let (_, pool, mint) = get_custody_args(args)?;
+        let lp_token_mint = pda::get_lp_token_mint_pda(&pool).0;
+        let lp_token_program = self.get_account::<Pool>(&pool)?.lp_token_program;
+        let custody = self.get_custody_keys(&pool, &mint)?;
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &lp_token_mint,
+                &lp_token_program,
+            ),
+            instructions::add_liquidity(
+                &owner,
+                &get_token_address(&owner, &custody),
+                &get_associated_token_address_with_program_id(
+                    &owner,
+                    &lp_token_mint,
+                    &lp_token_program,
+                ),
+                &pool,
+                &lp_token_program,
+                &custody,
+                &self.get_pool_custodies(&pool)?,
+                AddLiquidityParams {
+                    amount_in: args.value_of_t("amount-in")?,
+                    min_lp_amount_out: args.value_of_t("min-lp-amount-out")?,
+                },
+            ),
+        ])
+    }
+
+    fn remove_liquidity(&self, args: &ArgMatches) -> Result<()> {
+        let owner = self.keypair.pubkey();
+        let (_, pool, mint) = get_custody_args(args)?;
+        let lp_token_mint = pda::get_lp_token_mint_pda(&pool).0;
+        let lp_token_program = self.get_account::<Pool>(&pool)?.lp_token_program;
+        let custody = self.get_custody_keys(&pool, &mint)?;
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &mint,
+                &custody.token_program,
+            ),
+            instructions::remove_liquidity(
+                &owner,
+                &get_token_address(&owner, &custody),
+                &get_associated_token_address_with_program_id(
+                    &owner,
+                    &lp_token_mint,
+                    &lp_token_program,
+                ),
+                &pool,
+                &lp_token_program,
+                &custody,
+                &self.get_pool_custodies(&pool)?,
+                RemoveLiquidityParams {
+                    lp_amount_in: args.value_of_t("lp-amount-in")?,
+                    min_amount_out: args.value_of_t("min-amount-out")?,
+                },
+            ),
+        ])
+    }
+
+    fn open_position(&self, args: &ArgMatches) -> Result<()> {
+        let authority = self.keypair.pubkey();
+        let owner = self.get_owner(args)?;
+        let (_, pool, mint) = get_custody_args(args)?;
+        let collateral_mint = parse_pubkey(args.value_of("collateral-mint").unwrap())?;
+        let collateral_custody = self.get_custody_keys(&pool, &collateral_mint)?;
+        self.process_transaction(&[instructions::open_position(
+            &authority,
+            &owner,
+            &get_token_address(&owner, &collateral_custody),
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            &collateral_custody,
+            self.get_next_position_index(&owner)?,
+            OpenPositionParams {
+                price: args.value_of_t("price")?,
+                collateral: args.value_of_t("collateral")?,
+                size: args.value_of_t("size")?,
+                side: parse_side(args.value_of("side").unwrap())?,
+            },
+        )])
+    }
+
+    fn add_collateral(&self, args: &ArgMatches) -> Result<()> {
+        let authority = self.keypair.pubkey();
+        let owner = self.get_owner(args)?;
+        let (position, custody, collateral_custody) = self.get_position_custodies(args, owner)?;
+        self.process_transaction(&[instructions::add_collateral(
+            &authority,
+            &owner,
+            &get_token_address(&owner, &collateral_custody),
+            &position.pool,
+            &custody,
+            &collateral_custody,
+            position.side,
+            position.index,
+            AddCollateralParams {
+                collateral: args.value_of_t("collateral")?,
+            },
+        )])
+    }
+
+    fn remove_collateral(&self, args: &ArgMatches) -> Result<()> {
+        let authority = self.keypair.pubkey();
+        let owner = self.get_owner(args)?;
+        let (position, custody, collateral_custody) = self.get_position_custodies(args, owner)?;
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &authority,
+                &owner,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            instructions::remove_collateral(
+                &authority,
+                &owner,
+                &get_token_address(&owner, &collateral_custody),
+                &position.pool,
+                &custody,
+                &collateral_custody,
+                position.side,
+                position.index,
+                RemoveCollateralParams {
+                    collateral_usd: args.value_of_t("collateral-usd")?,
+                },
+            ),
+        ])
+    }
+
+    fn close_position(&self, args: &ArgMatches) -> Result<()> {
+        let authority = self.keypair.pubkey();
+        let owner = self.get_owner(args)?;
+        let (position, custody, collateral_custody) = self.get_position_custodies(args, owner)?;
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &authority,
+                &owner,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            instructions::close_position(
+                &authority,
+                &owner,
+                &get_token_address(&owner, &collateral_custody),
+                &position.pool,
+                &custody,
+                &collateral_custody,
+                position.side,
+                position.index,
+                ClosePositionParams {
+                    price: args.value_of_t("price")?,
+                },
+            ),
+        ])
+    }
+
+    fn transfer_position(&self, args: &ArgMatches) -> Result<()> {
+        let owner = self.keypair.pubkey();
+        let new_owner = parse_pubkey(args.value_of("new-owner").unwrap())?;
+        let (_, position) = self.get_position(args, Some(owner))?;
+        self.process_transaction(&[instructions::transfer_position(
+            &owner,
+            &new_owner,
+            &position.pool,
+            &position.custody,
+            position.side,
+            position.index,
+            self.get_next_position_index(&new_owner)?,
+        )])
+    }
+
+    fn set_trading_delegate(&self, args: &ArgMatches) -> Result<()> {
+        // everything is allowed unless listed explicitly
+        let allow = |name: &str| match args.values_of("allow") {
+            Some(mut values) => values.any(|value| value == name),
+            None => true,
+        };
+        self.process_transaction(&[instructions::set_trading_delegate(
+            &self.keypair.pubkey(),
+            &get_pool(args).1,
+            SetTradingDelegateParams {
+                delegate: parse_pubkey(args.value_of("delegate").unwrap())?,
+                permissions: DelegatePermissions {
+                    allow_open_position: allow("open-position"),
+                    allow_close_position: allow("close-position"),
+                    allow_add_collateral: allow("add-collateral"),
+                    allow_remove_collateral: allow("remove-collateral"),
+                },
+                max_size_usd: args.value_of_t("max-size-usd")?,
+                max_leverage: args.value_of_t("max-leverage")?,
+                expiration_time: args.value_of_t("expiration-time")?,
+            },
+        )])
+    }
+
+    fn remove_trading_delegate(&self, args: &ArgMatches) -> Result<()> {
+        self.process_transaction(&[instructions::remove_trading_delegate(
+            &self.keypair.pubkey(),
+            &get_pool(args).1,
+        )])
+    }
+
+    fn liquidate(&self, args: &ArgMatches) -> Result<()> {
+        let signer = self.keypair.pubkey();
+        let position_key = parse_pubkey(args.value_of("position").unwrap())?;
+        let position: Position = self.get_account(&position_key)?;
+        let custody: Custody = self.get_account(&position.custody)?;
+        let collateral_custody: Custody = self.get_account(&position.collateral_custody)?;
+        let collateral_custody = CustodyKeys::from(&collateral_custody);
+        self.process_transaction(&[
+            create_associated_token_account_idempotent(
+                &signer,
+                &position.owner,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            create_associated_token_account_idempotent(
+                &signer,
+                &signer,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            instructions::liquidate(
+                &signer,
+                &get_token_address(&position.owner, &collateral_custody),
+                &get_token_address(&signer, &collateral_custody),
+                &position.pool,
+                &position_key,
+                &CustodyKeys::from(&custody),
+                &collateral_custody,
+                LiquidateParams {},
+            ),
+        ])
+    }
+
+    fn update_pool_aum(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        self.process_transaction(&[instructions::update_pool_aum(
+            &self.keypair.pubkey(),
+            &pool,
+            &self.get_pool_custodies(&pool)?,
+        )])
+    }
+
+    // darkpool instructions
+
+    fn settle_dark_pool_trade(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let collateral_mint = parse_pubkey(args.value_of("collateral-mint").unwrap())?;
+        let long_owner = parse_pubkey(args.value_of("long-owner").unwrap())?;
+        let short_owner = parse_pubkey(args.value_of("short-owner").unwrap())?;
+        self.process_transaction(&[instructions::settle_queued_dark_pool_trade(
+            &self.keypair.pubkey(),
+            &parse_pubkey(args.value_of("order-book").unwrap())?,
+            &long_owner,
+            &short_owner,
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            &self.get_custody_keys(&pool, &collateral_mint)?,
+            self.get_next_position_index(&long_owner)?,
+            self.get_next_position_index(&short_owner)?,
+            args.value_of_t("trade-id")?,
+        )])
+    }
+
+    // views
+
+    fn get_multisig(&self) -> Result<()> {
+        let key = pda::get_role_multisig_pda(self.role).0;
+        let data = self.rpc.get_account_data(&key)?;
+        let multisig: Multisig = accounts::deserialize_multisig(&data)?;
+        let num_signers = multisig.num_signers as usize;
+        let signers = multisig.signers;
+        let signed = multisig.signed;
+        println!("Multisig: {key}");
+        println!(
+            "Min signatures: {} of {}",
+            multisig.min_signatures, num_signers
+        );
+        println!("Timelock delay: {} sec", { multisig.timelock_delay });
+        println!("Proposals: {}", { multisig.num_proposals });
+        println!("Pending instruction signatures: {}", multisig.num_signed);
+        for (signer, signed) in signers.iter().zip(signed.iter()).take(num_signers) {
+            println!("  {signer}{}", if *signed == 1 { " (signed)" } else { "" });
+        }
+        Ok(())
+    }
+
+    fn get_add_liquidity_amount_and_fee(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        self.simulate_and_print::<AmountAndFee>(instructions::get_add_liquidity_amount_and_fee(
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            &self.get_pool_custodies(&pool)?,
+            GetAddLiquidityAmountAndFeeParams {
+                amount_in: args.value_of_t("amount")?,
+            },
+        ))
+    }
+
+    fn get_remove_liquidity_amount_and_fee(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        self.simulate_and_print::<AmountAndFee>(instructions::get_remove_liquidity_amount_and_fee(
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            &self.get_pool_custodies(&pool)?,
+            GetRemoveLiquidityAmountAndFeeParams {
+                lp_amount_in: args.value_of_t("amount")?,
+            },
+        ))
+    }
+
+    fn get_entry_price_and_fee(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        let collateral_mint = parse_pubkey(args.value_of("collateral-mint").unwrap())?;
+        let owner = match args.value_of("trader") {
+            Some(trader) => parse_pubkey(trader)?,
+            None => self.keypair.pubkey(),
+        };
+        let trading_stats = pda::get_trading_stats_pda(&owner, &pool).0;
+        let trading_stats = self
+            .rpc
+            .get_account(&trading_stats)
+            .is_ok()
+            .then_some(trading_stats);
+        self.simulate_and_print::<NewPositionPricesAndFee>(instructions::get_entry_price_and_fee(
+            &owner,
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            &self.get_custody_keys(&pool, &collateral_mint)?,
+            trading_stats,
+            GetEntryPriceAndFeeParams {
+                collateral: args.value_of_t("collateral")?,
+                size: args.value_of_t("size")?,
+                side: parse_side(args.value_of("side").unwrap())?,
+            },
+        ))
+    }
+
+    fn get_exit_price_and_fee(&self, args: &ArgMatches) -> Result<()> {
+        let (position_key, position) = self.get_position(args, None)?;
+        let trading_stats = pda::get_trading_stats_pda(&position.owner, &position.pool).0;
+        let trading_stats = self
+            .rpc
+            .get_account(&trading_stats)
+            .is_ok()
+            .then_some(trading_stats);
+        let (custody, collateral_custody) = self.get_custody_keys_of(&position)?;
+        self.simulate_and_print::<PriceAndFee>(instructions::get_exit_price_and_fee(
+            &position.pool,
+            &position_key,
+            &custody,
+            &collateral_custody,
+            trading_stats,
+            GetExitPriceAndFeeParams {},
+        ))
+    }
+
+    fn get_pnl(&self, args: &ArgMatches) -> Result<()> {
+        let (position_key, position) = self.get_position(args, None)?;
+        let (custody, collateral_custody) = self.get_custody_keys_of(&position)?;
+        self.simulate_and_print::<ProfitAndLoss>(instructions::get_pnl(
+            &position.pool,
+            &position_key,
+            &custody,
+            &collateral_custody,
+            GetPnlParams {},
+        ))
+    }
+
+    fn get_liquidation_price(&self, args: &ArgMatches) -> Result<()> {
+        let (position_key, position) = self.get_position(args, None)?;
+        let (custody, collateral_custody) = self.get_custody_keys_of(&position)?;
+        self.simulate_and_print::<u64>(instructions::get_liquidation_price(
+            &position.pool,
+            &position_key,
+            &custody,
+            &collateral_custody,
+            GetLiquidationPriceParams {
+                add_collateral: args.value_of_t("add-collateral")?,
+                remove_collateral: args.value_of_t("remove-collateral")?,
+            },
+        ))
+    }
+
+    fn get_liquidation_state(&self, args: &ArgMatches) -> Result<()> {
+        let (position_key, position) = self.get_position(args, None)?;
+        let (custody, collateral_custody) = self.get_custody_keys_of(&position)?;
+        let state: u8 = self.simulate(instructions::get_liquidation_state(
+            &position.pool,
+            &position_key,
+            &custody,
+            &collateral_custody,
+            GetLiquidationStateParams {},
+        ))?;
+        println!(
+            "{}",
+            if state == 0 {
+                "Position is healthy"
+            } else {
+                "Position can be liquidated"
+            }
+        );
+        Ok(())
+    }
+
+    fn get_oracle_price(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool, mint) = get_custody_args(args)?;
+        self.simulate_and_print::<OraclePrice>(instructions::get_oracle_price(
+            &pool,
+            &self.get_custody_keys(&pool, &mint)?,
+            GetOraclePriceParams {
+                ema: args.is_present("ema"),
+            },
+        ))
+    }
+
+    fn get_swap_amount_and_fees(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        let mint_in = parse_pubkey(args.value_of("mint-in").unwrap())?;
+        let mint_out = parse_pubkey(args.value_of("mint-out").unwrap())?;
+        self.simulate_and_print::<SwapAmountAndFees>(instructions::get_swap_amount_and_fees(
+            &pool,
+            &self.get_custody_keys(&pool, &mint_in)?,
+            &self.get_custody_keys(&pool, &mint_out)?,
+            GetSwapAmountAndFeesParams {
+                amount_in: args.value_of_t("amount-in")?,
+            },
+        ))
+    }
+
+    fn get_aum(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        self.simulate_and_print::<u128>(instructions::get_assets_under_management(
+            &pool,
+            &self.get_pool_custodies(&pool)?,
+            GetAssetsUnderManagementParams {},
+        ))
+    }
+
+    fn get_lp_token_price(&self, args: &ArgMatches) -> Result<()> {
+        let (_, pool) = get_pool(args);
+        self.simulate_and_print::<u64>(instructions::get_lp_token_price(
+            &pool,
+            &self.get_pool_custodies(&pool)?,
+            GetLpTokenPriceParams {},
+        ))
+    }
+
+    // helpers
+
+    /// Builds the admin instruction for every admin signer, or wraps it into a
+    /// timelock proposal that is created by the first admin and approved by the rest
+    fn process_admin_instruction(&self, build: impl Fn(&Pubkey) -> Instruction) -> Result<()> {
+        if !self.propose {
+            return self.process_transaction(&self.admins.iter().map(&build).collect::<Vec<_>>());
+        }
+
+        let instruction = build(&pda::get_timelock_authority_pda(self.role).0);
+        let id = match self.proposal_id {
+            Some(id) => id,
+            None => {
+                let data = self
+                    .rpc
+                    .get_account_data(&pda::get_role_multisig_pda(self.role).0)?;
+                accounts::deserialize_multisig(&data)?.num_proposals
+            }
+        };
+        println!("Proposal: {id}");
+
+        let (proposer, approvers) = self.admins.split_first().unwrap();
+        let mut ixs = vec![instructions::create_proposal(
+            proposer,
+            self.role,
+            CreateProposalParams {
+                id,
+                instruction_data: instruction.data,
+                instruction_accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|account| ProposalAccount {
+                        pubkey: account.pubkey,
+                        is_writable: account.is_writable,
+                    })
+                    .collect(),
+            },
+        )];
+        ixs.extend(approvers.iter().map(|admin| {
+            instructions::approve_proposal(admin, self.role, id, ApproveProposalParams {})
+        }));
+        self.process_transaction(&ixs)
+    }
+
+    /// Signs the transaction with the local keypair and collected signatures,
+    /// then either prints signatures (--sign-only) or sends it
+    fn process_transaction(&self, instructions: &[Instruction]) -> Result<()> {
+        let blockhash = self.get_blockhash()?;
+        let message = transaction::build_message(
+            instructions,
+            &self.fee_payer,
+            self.nonce.as_ref(),
+            &blockhash,
+        );
+        let transaction = transaction::sign_message(message, &[&self.keypair], &self.presigners)?;
+
+        if self.sign_only {
+            print!("{}", transaction::format_sign_only(&transaction));
+            return Ok(());
+        }
+
+        let absent_signers = transaction::get_absent_signers(&transaction);
+        if !absent_signers.is_empty() {
+            bail!(
+                "Missing signatures of {}, collect them with --sign-only and pass with --signer",
+                absent_signers
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let signature = self
+            .rpc
+            .send_and_confirm_transaction_with_spinner(&transaction)?;
+        println!("Signature: {signature}");
+        Ok(())
+    }
+
+    fn get_blockhash(&self) -> Result<Hash> {
+        if let Some(blockhash) = self.blockhash {
+            return Ok(blockhash);
+        }
+        if let Some(nonce) = &self.nonce {
+            let account = nonce_utils::get_account_with_commitment(
+                &self.rpc,
+                &nonce.account,
+                self.rpc.commitment(),
+            )?;
+            return Ok(nonce_utils::data_from_account(&account)?.blockhash());
+        }
+        Ok(self.rpc.get_latest_blockhash()?)
+    }
+
+    /// Simulates the view instruction and decodes its return data
+    fn simulate<T: AnchorDeserialize>(&self, instruction: Instruction) -> Result<T> {
+        let message = Message::new_with_blockhash(
+            &[instruction],
+            Some(&self.keypair.pubkey()),
+            &Hash::default(),
+        );
+        let result = self
+            .rpc
+            .simulate_transaction_with_config(
+                &Transaction::new_unsigned(message),
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(self.rpc.commitment()),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )?
+            .value;
+        if let Some(err) = result.err {
+            bail!(
+                "Simulation failed: {err}\n{}",
+                result.logs.unwrap_or_default().join("\n")
+            );
+        }
+        let return_data = result
+            .return_data
+            .ok_or_else(|| anyhow!("Instruction returned no data"))?;
+        let mut data = STANDARD.decode(return_data.data.0)?;
+        data.resize(data.len().max(MAX_RETURN_DATA_LEN), 0);
+        Ok(T::deserialize(&mut data.as_slice())?)
+    }
+
+    fn simulate_and_print<T: AnchorDeserialize + std::fmt::Debug>(
+        &self,
+        instruction: Instruction,
+    ) -> Result<()> {
+        println!("{:#?}", self.simulate::<T>(instruction)?);
+        Ok(())
+    }
+
+    fn get_account<T: AccountDeserialize>(&self, key: &Pubkey) -> Result<T> {
+        let data = self
+            .rpc
+            .get_account_data(key)
+            .map_err(|err| anyhow!("Failed to fetch {key}: {err}"))?;
+        Ok(accounts::deserialize(&data)?)
+    }
+
+    fn print_account<T: AccountDeserialize + std::fmt::Debug>(&self, key: &Pubkey) -> Result<()> {
+        println!("{key}: {:#?}", self.get_account::<T>(key)?);
+        Ok(())
+    }
+
+    fn get_current_custody_config(&self, pool: &Pubkey, mint: &Pubkey) -> Result<CustodyConfig> {
+        let pool_account: Pool = self.get_account(pool)?;
+        let custody: Custody = self.get_account(&pda::get_custody_pda(pool, mint).0)?;
+        Ok(CustodyConfig::new(&custody, &pool_account))
+    }
+
+    /// Returns the token program that owns the mint, SPL Token or Token-2022
+    fn get_mint_token_program(&self, mint: &Pubkey) -> Result<Pubkey> {
+        let account = self
+            .rpc
+            .get_account(mint)
+            .map_err(|err| anyhow!("Failed to fetch {mint}: {err}"))?;
+        Ok(account.owner)
+    }
+
+    fn get_custody_keys(&self, pool: &Pubkey, mint: &Pubkey) -> Result<CustodyKeys> {
+        let custody: Custody = self.get_account(&pda::get_custody_pda(pool, mint).0)?;
+        Ok(CustodyKeys::from(&custody))
+    }
+
+    fn get_custody_keys_of(&self, position: &Position) -> Result<(CustodyKeys, CustodyKeys)> {
+        let custody: Custody = self.get_account(&position.custody)?;
+        let collateral_custody: Custody = self.get_account(&position.collateral_custody)?;
+        Ok((
+            CustodyKeys::from(&custody),
+            CustodyKeys::from(&collateral_custody),
+        ))
+    }
+
+    /// Returns keys of all pool custodies, in pool order
+    fn get_pool_custodies(&self, pool: &Pubkey) -> Result<Vec<CustodyKeys>> {
+        let pool: Pool = self.get_account(pool)?;
+        pool.custodies
+            .iter()
+            .map(|custody| {
+                let custody: Custody = self.get_account(custody)?;
+                Ok(CustodyKeys::from(&custody))
+            })
+            .collect()
+    }
+
+    /// Returns the position address and data, the owner is read from arguments
+    /// unless provided
+    fn get_position(&self, args: &ArgMatches, owner: Option<Pubkey>) -> Result<(Pubkey, Position)> {
+        let owner = match owner {
+            Some(owner) => owner,
+            None => parse_pubkey(args.value_of("owner").unwrap())?,
+        };
+        let (_, pool, mint) = get_custody_args(args)?;
+        let side = parse_side(args.value_of("side").unwrap())?;
+        let position = pda::get_position_pda(
+            &owner,
+            &pool,
+            &pda::get_custody_pda(&pool, &mint).0,
+            side,
+            args.value_of_t("index")?,
+        )
+        .0;
+        Ok((position, self.get_account(&position)?))
+    }
+
+    /// Returns the index of the next position opened by the owner
+    fn get_next_position_index(&self, owner: &Pubkey) -> Result<u64> {
+        let counter = pda::get_position_counter_pda(owner).0;
+        match self
+            .rpc
+            .get_account_with_commitment(&counter, self.rpc.commitment())?
+            .value
+        {
+            Some(account) => {
+                Ok(accounts::deserialize::<PositionCounter>(&account.data)?.next_index)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn print_positions(&self, owner: &Pubkey) -> Result<()> {
+        let mut positions = self
+            .rpc
+            .get_program_accounts_with_config(
+                &perpetuals::id(),
+                RpcProgramAccountsConfig {
+                    filters: Some(vec![
+                        RpcFilterType::DataSize(Position::LEN as u64),
+                        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                            0,
+                            Position::DISCRIMINATOR.to_vec(),
+                        )),
+                        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(8, owner.to_bytes().to_vec())),
+                    ]),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        ..RpcAccountInfoConfig::default()
+                    },
+                    ..RpcProgramAccountsConfig::default()
+                },
+            )?
+            .into_iter()
+            .map(|(key, account)| Ok((key, accounts::deserialize::<Position>(&account.data)?)))
+            .collect::<Result<Vec<_>>>()?;
+        positions.sort_by_key(|(_, position)| position.index);
+
+        for (key, position) in positions {
+            println!("{key}: {position:#?}");
+        }
+        Ok(())
+    }
+
+    /// Returns the owner of traded positions, the keypair unless trading as a delegate
+    fn get_owner(&self, args: &ArgMatches) -> Result<Pubkey> {
+        match args.value_of("owner") {
+            Some(owner) => parse_pubkey(owner),
+            None => Ok(self.keypair.pubkey()),
+        }
+    }
+
+    fn get_position_custodies(
+        &self,
+        args: &ArgMatches,
+        owner: Pubkey,
+    ) -> Result<(Position, CustodyKeys, CustodyKeys)> {
+        let (_, position) = self.get_position(args, Some(owner))?;
+        let (custody, collateral_custody) = self.get_custody_keys_of(&position)?;
+        Ok((position, custody, collateral_custody))
+    }
+}
+
+fn print_changes(current: &CustodyConfig, proposed: &CustodyConfig) -> Result<()> {
+    let changes = config::diff(current, proposed)?;
+    if changes.is_empty() {
+        println!("No changes");
+    }
+    for change in changes {
+        println!("{change}");
+    }
+    Ok(())
+}
+
+/// Returns the associated token account of `owner` for the custody mint
+fn get_token_address(owner: &Pubkey, custody: &CustodyKeys) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, &custody.mint, &custody.token_program)
+}
+
+fn read_keypair(path: &str) -> Result<Keypair> {
+    read_keypair_file(path).map_err(|err| anyhow!("Failed to read keypair {path}: {err}"))
+}
+
+fn read_config_arg(args: &ArgMatches) -> Result<serde_json::Value> {
+    config::read_config_file(Path::new(args.value_of("config").unwrap()))
+}
+
+fn parse_pubkey(s: &str) -> Result<Pubkey> {
+    Pubkey::from_str(s).map_err(|err| anyhow!("Invalid pubkey {s}: {err}"))
+}
+
+fn parse_side(s: &str) -> Result<Side> {
+    match s {
+        "long" => Ok(Side::Long),
+        "short" => Ok(Side::Short),
+        _ => Err(anyhow!("Invalid side: {s}")),
+    }
+}
+
+fn parse_role(s: &str) -> Result<AdminRole> {
+    match s {
+        "admin" => Ok(AdminRole::Admin),
+        "guardian" => Ok(AdminRole::Guardian),
+        "risk-manager" => Ok(AdminRole::RiskManager),
+        "treasurer" => Ok(AdminRole::Treasurer),
+        "oracle-operator" => Ok(AdminRole::OracleOperator),
+        _ => Err(anyhow!("Invalid role: {s}")),
+    }
+}
+
+fn get_signers(args: &ArgMatches) -> Result<Vec<Pubkey>> {
+    args.values_of("signers")
+        .unwrap()
+        .map(parse_pubkey)
+        .collect()
+}
+
+fn get_pool(args: &ArgMatches) -> (&str, Pubkey) {
+    let name = args.value_of("pool").unwrap();
+    (name, pda::get_pool_pda(name).0)
+}
+
+fn get_custody_args(args: &ArgMatches) -> Result<(&str, Pubkey, Pubkey)> {
+    let (name, pool) = get_pool(args);
+    Ok((name, pool, parse_pubkey(args.value_of("mint").unwrap())?))
+}
+
+fn get_publish_time(args: &ArgMatches) -> Result<i64> {
+    if args.is_present("publish-time") {
+        Ok(args.value_of_t("publish-time")?)
+    } else {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64)
+    }
+}
@@ -0,0 +1,220 @@
+//! Transaction building and offline signing
+//!
+//! Multisig admin instructions are signed by several admins. To collect the
+//! signatures without sharing keys, every admin builds the same message from
+//! identical arguments and a fixed blockhash (or durable nonce), signs it with
+//! `--sign-only` and passes the printed `PUBKEY=SIGNATURE` pair to whoever
+//! submits the transaction.
+
+use {
+    anyhow::{anyhow, bail, Result},
+    solana_sdk::{
+        hash::Hash,
+        instruction::Instruction,
+        message::Message,
+        pubkey::Pubkey,
+        signature::{Keypair, Signature, Signer},
+        system_instruction,
+        transaction::Transaction,
+    },
+    std::{fmt::Write, str::FromStr},
+};
+
+/// Durable nonce used instead of a recent blockhash
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct NonceConfig {
+    pub account: Pubkey,
+    pub authority: Pubkey,
+}
+
+/// Builds the transaction message, a nonce advance instruction is prepended if
+/// a durable nonce is used, in which case the blockhash is the nonce value
+pub fn build_message(
+    instructions: &[Instruction],
+    fee_payer: &Pubkey,
+    nonce: Option<&NonceConfig>,
+    blockhash: &Hash,
+) -> Message {
+    let mut ixs = Vec::with_capacity(instructions.len() + 1);
+    if let Some(nonce) = nonce {
+        ixs.push(system_instruction::advance_nonce_account(
+            &nonce.account,
+            &nonce.authority,
+        ));
+    }
+    ixs.extend_from_slice(instructions);
+    Message::new_with_blockhash(&ixs, Some(fee_payer), blockhash)
+}
+
+/// Signs the message with local keypairs that are required signers and adds
+/// signatures collected offline, missing signatures are left empty
+pub fn sign_message(
+    message: Message,
+    keypairs: &[&Keypair],
+    presigners: &[(Pubkey, Signature)],
+) -> Result<Transaction> {
+    let mut transaction = Transaction::new_unsigned(message);
+    let message_data = transaction.message_data();
+    let signers = get_required_signers(&transaction).to_vec();
+
+    for (pubkey, signature) in presigners {
+        let idx = signers
+            .iter()
+            .position(|signer| signer == pubkey)
+            .ok_or_else(|| anyhow!("{pubkey} is not a required signer"))?;
+        if !signature.verify(pubkey.as_ref(), &message_data) {
+            bail!(
+                "Invalid signature for {pubkey}, was the message built with different arguments?"
+            );
+        }
+        transaction.signatures[idx] = *signature;
+    }
+
+    for keypair in keypairs {
+        if let Some(idx) = signers
+            .iter()
+            .position(|signer| signer == &keypair.pubkey())
+        {
+            transaction.signatures[idx] = keypair.sign_message(&message_data);
+        }
+    }
+
+    Ok(transaction)
+}
+
+/// Returns signers whose signatures are still missing
+pub fn get_absent_signers(transaction: &Transaction) -> Vec<Pubkey> {
+    get_required_signers(transaction)
+        .iter()
+        .zip(transaction.signatures.iter())
+        .filter(|(_, signature)| **signature == Signature::default())
+        .map(|(pubkey, _)| *pubkey)
+        .collect()
+}
+
+/// Parses `PUBKEY=SIGNATURE` pairs printed by `--sign-only`
+pub fn parse_presigner(s: &str) -> Result<(Pubkey, Signature)> {
+    let (pubkey, signature) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected PUBKEY=SIGNATURE, got {s}"))?;
+    Ok((
+        Pubkey::from_str(pubkey).map_err(|err| anyhow!("Invalid pubkey {pubkey}: {err}"))?,
+        Signature::from_str(signature)
+            .map_err(|err| anyhow!("Invalid signature {signature}: {err}"))?,
+    ))
+}
+
+/// Formats signatures of a partially signed transaction, to be passed to the
+/// submitter with `--signer`
+pub fn format_sign_only(transaction: &Transaction) -> String {
+    let mut output = String::new();
+    let _ = writeln!(
+        output,
+        "Blockhash: {}",
+        transaction.message.recent_blockhash
+    );
+    let _ = writeln!(output, "Signers (Pubkey=Signature):");
+    for (pubkey, signature) in get_required_signers(transaction)
+        .iter()
+        .zip(transaction.signatures.iter())
+        .filter(|(_, signature)| **signature != Signature::default())
+    {
+        let _ = writeln!(output, "  {pubkey}={signature}");
+    }
+    let absent_signers = get_absent_signers(transaction);
+    if !absent_signers.is_empty() {
+        let _ = writeln!(output, "Absent Signers (Pubkey):");
+        for pubkey in absent_signers {
+            let _ = writeln!(output, "  {pubkey}");
+        }
+    }
+    output
+}
+
+fn get_required_signers(transaction: &Transaction) -> &[Pubkey] {
+    &transaction.message.account_keys[..transaction.message.header.num_required_signatures as usize]
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        perpetuals_client::{instructions, params::SetTimelockDelayParams},
+    };
+
+    fn get_instructions(admins: &[&Keypair]) -> Vec<Instruction> {
+        admins
+            .iter()
+            .map(|admin| {
+                instructions::set_timelock_delay(
+                    &admin.pubkey(),
+                    SetTimelockDelayParams {
+                        timelock_delay: 3_600,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_offline_signing() {
+        let admin1 = Keypair::new();
+        let admin2 = Keypair::new();
+        let blockhash = Hash::new_unique();
+        let ixs = get_instructions(&[&admin1, &admin2]);
+
+        // first admin signs offline
+        let message = build_message(&ixs, &admin1.pubkey(), None, &blockhash);
+        let transaction = sign_message(message, &[&admin1], &[]).unwrap();
+        assert_eq!(get_absent_signers(&transaction), vec![admin2.pubkey()]);
+
+        let output = format_sign_only(&transaction);
+        let presigner = output
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(&format!("{}=", admin1.pubkey())))
+            .map(|signature| format!("{}={signature}", admin1.pubkey()))
+            .unwrap();
+        let presigner = parse_presigner(&presigner).unwrap();
+
+        // second admin rebuilds the same message and submits
+        let message = build_message(&ixs, &admin1.pubkey(), None, &blockhash);
+        let transaction = sign_message(message, &[&admin2], &[presigner]).unwrap();
+        assert!(get_absent_signers(&transaction).is_empty());
+        assert!(transaction.verify().is_ok());
+
+        // signatures don't match a message built with different arguments
+        let message = build_message(&ixs, &admin1.pubkey(), None, &Hash::new_unique());
+        assert!(sign_message(message, &[&admin2], &[presigner]).is_err());
+
+        // only required signers can sign
+        let message = build_message(&ixs[..1], &admin1.pubkey(), None, &blockhash);
+        assert!(sign_message(message, &[], &[(admin2.pubkey(), presigner.1)]).is_err());
+    }
+
+    #[test]
+    fn test_durable_nonce() {
+        let admin = Keypair::new();
+        let nonce = NonceConfig {
+            account: Pubkey::new_unique(),
+            authority: admin.pubkey(),
+        };
+        let ixs = get_instructions(&[&admin]);
+        let message = build_message(&ixs, &admin.pubkey(), Some(&nonce), &Hash::new_unique());
+        assert_eq!(message.instructions.len(), 2);
+        assert_eq!(message.program_id(0), Some(&solana_sdk::system_program::ID));
+        let transaction = sign_message(message, &[&admin], &[]).unwrap();
+        assert!(transaction.verify().is_ok());
+    }
+
+    #[test]
+    fn test_parse_presigner() {
+        let pubkey = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+        assert_eq!(
+            parse_presigner(&format!("{pubkey}={signature}")).unwrap(),
+            (pubkey, signature)
+        );
+        assert!(parse_presigner(&pubkey.to_string()).is_err());
+        assert!(parse_presigner(&format!("{pubkey}=abc")).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Deserializers for the perpetuals program accounts
+
+pub use perpetuals::state::{
+    custody::Custody, oracle::CustomOracle, perpetuals::Perpetuals, pool::Pool, position::Position,
+    position_counter::PositionCounter, proposal::Proposal, trading_delegate::TradingDelegate,
+    trading_stats::TradingStats,
+};
+use {
+    anchor_lang::{error::ErrorCode, prelude::*, AccountDeserialize, Discriminator},
+    perpetuals::state::multisig::Multisig,
+};
+
+/// Deserializes account data, checking the account discriminator
+pub fn deserialize<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+/// Deserializes zero-copy multisig account data. Unlike `deserialize`, it doesn't
+/// require the buffer to be aligned, e.g. when the data comes from an RPC response.
+pub fn deserialize_multisig(data: &[u8]) -> Result<Multisig> {
+    if data.len() < 8 {
+        return err!(ErrorCode::AccountDiscriminatorNotFound);
+    }
+    if data[..8] != Multisig::DISCRIMINATOR {
+        return err!(ErrorCode::AccountDiscriminatorMismatch);
+    }
+    if data.len() < Multisig::LEN {
+        return err!(ErrorCode::AccountDidNotDeserialize);
+    }
+    Ok(bytemuck::pod_read_unaligned(&data[8..Multisig::LEN]))
+}
@@ -0,0 +1,814 @@
+//! Admin configs, read from TOML or JSON files
+//!
+//! Config files mirror instruction params with human readable values: keys are
+//! base58 strings and enums are lowercase names. A file may only list the
+//! fields that change, the rest is taken from on-chain state before the config
+//! is validated.
+
+use {
+    anchor_lang::prelude::Pubkey,
+    anyhow::{anyhow, bail, Result},
+    perpetuals::{
+        instructions::{
+            AddCustodyParams, SetCustodyConfigParams, SetCustodyRiskParams, SetPermissionsParams,
+        },
+        state::{
+            custody::{BorrowRateParams, Custody, Fees, FeesMode, PricingParams},
+            oracle::{OracleParams, OracleType},
+            perpetuals::Permissions,
+            pool::{FeeTier, Pool, TokenRatios},
+        },
+    },
+    serde::{de::DeserializeOwned, Deserialize, Serialize},
+    serde_json::{Map, Value},
+    std::{fmt, fs, path::Path, str::FromStr},
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Detects the format from the file extension, TOML is the default
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "toml" => Ok(Self::Toml),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Unsupported config format: {s}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OracleTypeConfig {
+    None,
+    Custom,
+    Pyth,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FeesModeConfig {
+    Fixed,
+    Linear,
+    Optimal,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OracleConfig {
+    #[serde(with = "pubkey_string")]
+    pub oracle_account: Pubkey,
+    pub oracle_type: OracleTypeConfig,
+    #[serde(with = "pubkey_string")]
+    pub oracle_authority: Pubkey,
+    pub max_price_error: u64,
+    pub max_price_age_sec: u32,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfig {
+    pub use_ema: bool,
+    pub use_unrealized_pnl_in_aum: bool,
+    pub trade_spread_long: u64,
+    pub trade_spread_short: u64,
+    pub swap_spread: u64,
+    pub min_initial_leverage: u64,
+    pub max_initial_leverage: u64,
+    pub max_leverage: u64,
+    pub max_payoff_mult: u64,
+    pub max_utilization: u64,
+    pub max_position_locked_usd: u64,
+    pub max_total_locked_usd: u64,
+    pub price_impact_mult: u64,
+    pub max_price_impact: u64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PermissionsConfig {
+    pub allow_swap: bool,
+    pub allow_add_liquidity: bool,
+    pub allow_remove_liquidity: bool,
+    pub allow_open_position: bool,
+    pub allow_close_position: bool,
+    pub allow_pnl_withdrawal: bool,
+    pub allow_collateral_withdrawal: bool,
+    pub allow_size_change: bool,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FeesConfig {
+    pub mode: FeesModeConfig,
+    pub ratio_mult: u64,
+    pub utilization_mult: u64,
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub stable_swap_in: u64,
+    pub stable_swap_out: u64,
+    pub add_liquidity: u64,
+    pub remove_liquidity: u64,
+    pub open_position: u64,
+    pub close_position: u64,
+    pub liquidation: u64,
+    pub protocol_share: u64,
+    pub fee_max: u64,
+    pub fee_optimal: u64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BorrowRateConfig {
+    pub base_rate: u64,
+    pub slope1: u64,
+    pub slope2: u64,
+    pub optimal_utilization: u64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RatiosConfig {
+    pub target: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTierConfig {
+    pub min_volume_usd: u64,
+    pub discount: u64,
+}
+
+/// Custody config, ratios are listed for all pool custodies in pool order
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CustodyConfig {
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: OracleConfig,
+    pub pricing: PricingConfig,
+    pub permissions: PermissionsConfig,
+    pub fees: FeesConfig,
+    pub borrow_rate: BorrowRateConfig,
+    pub ratios: Vec<RatiosConfig>,
+}
+
+/// Token ratios of all pool custodies, used when a custody is removed
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PoolRatiosConfig {
+    pub ratios: Vec<RatiosConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTiersConfig {
+    pub fee_tiers: Vec<FeeTierConfig>,
+}
+
+/// Single field that differs between two configs
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfigChange {
+    pub path: String,
+    pub current: Option<Value>,
+    pub proposed: Option<Value>,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format_value = |value: &Option<Value>| {
+            value
+                .as_ref()
+                .map_or_else(|| "<none>".to_string(), Value::to_string)
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            format_value(&self.current),
+            format_value(&self.proposed)
+        )
+    }
+}
+
+/// Reads a TOML or JSON config file into a generic value
+pub fn read_config_file(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| anyhow!("Failed to read {}: {err}", path.display()))?;
+    parse_config_str(&content, ConfigFormat::from_path(path))
+        .map_err(|err| anyhow!("Failed to parse {}: {err}", path.display()))
+}
+
+pub fn parse_config_str(content: &str, format: ConfigFormat) -> Result<Value> {
+    Ok(match format {
+        ConfigFormat::Toml => serde_json::to_value(toml::from_str::<toml::Value>(content)?)?,
+        ConfigFormat::Json => serde_json::from_str(content)?,
+    })
+}
+
+/// Builds a config from file contents, fields missing in the file are taken
+/// from the base config if provided
+pub fn load_config<T: Serialize + DeserializeOwned>(value: Value, base: Option<&T>) -> Result<T> {
+    let value = if let Some(base) = base {
+        let mut merged = serde_json::to_value(base)?;
+        merge(&mut merged, value);
+        merged
+    } else {
+        value
+    };
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Serializes the config in the given format
+pub fn write_config<T: Serialize>(config: &T, format: ConfigFormat) -> Result<String> {
+    Ok(match format {
+        ConfigFormat::Toml => toml::to_string_pretty(config)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(config)?,
+    })
+}
+
+/// Recursively overrides base fields with the overlay, arrays are replaced as a whole
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Returns fields that differ between the current and proposed configs
+pub fn diff<T: Serialize>(current: &T, proposed: &T) -> Result<Vec<ConfigChange>> {
+    let mut changes = vec![];
+    diff_values(
+        "",
+        Some(&serde_json::to_value(current)?),
+        Some(&serde_json::to_value(proposed)?),
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+fn diff_values(
+    path: &str,
+    current: Option<&Value>,
+    proposed: Option<&Value>,
+    changes: &mut Vec<ConfigChange>,
+) {
+    let join = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match (current, proposed) {
+        (Some(Value::Object(current)), Some(Value::Object(proposed))) => {
+            let empty = Map::new();
+            for key in current.keys() {
+                diff_values(&join(key), current.get(key), proposed.get(key), changes);
+            }
+            for key in proposed.keys().filter(|key| !current.contains_key(*key)) {
+                diff_values(&join(key), empty.get(key), proposed.get(key), changes);
+            }
+        }
+        (Some(Value::Array(current)), Some(Value::Array(proposed))) => {
+            for idx in 0..current.len().max(proposed.len()) {
+                diff_values(
+                    &format!("{path}[{idx}]"),
+                    current.get(idx),
+                    proposed.get(idx),
+                    changes,
+                );
+            }
+        }
+        (current, proposed) if current != proposed => changes.push(ConfigChange {
+            path: path.to_string(),
+            current: current.cloned(),
+            proposed: proposed.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+impl CustodyConfig {
+    /// Returns the current config of the custody
+    pub fn new(custody: &Custody, pool: &Pool) -> Self {
+        Self {
+            is_stable: custody.is_stable,
+            is_virtual: custody.is_virtual,
+            oracle: custody.oracle.into(),
+            pricing: custody.pricing.into(),
+            permissions: custody.permissions.into(),
+            fees: custody.fees.into(),
+            borrow_rate: custody.borrow_rate.into(),
+            ratios: pool.ratios.iter().copied().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<CustodyConfig> for AddCustodyParams {
+    fn from(config: CustodyConfig) -> Self {
+        Self {
+            is_stable: config.is_stable,
+            is_virtual: config.is_virtual,
+            oracle: config.oracle.into(),
+            pricing: config.pricing.into(),
+            permissions: config.permissions.into(),
+            fees: config.fees.into(),
+            borrow_rate: config.borrow_rate.into(),
+            ratios: config.ratios.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<CustodyConfig> for SetCustodyConfigParams {
+    fn from(config: CustodyConfig) -> Self {
+        Self {
+            is_stable: config.is_stable,
+            is_virtual: config.is_virtual,
+            oracle: config.oracle.into(),
+            pricing: config.pricing.into(),
+            permissions: config.permissions.into(),
+            fees: config.fees.into(),
+            borrow_rate: config.borrow_rate.into(),
+            ratios: config.ratios.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<CustodyConfig> for SetCustodyRiskParams {
+    fn from(config: CustodyConfig) -> Self {
+        Self {
+            pricing: config.pricing.into(),
+            fees: config.fees.into(),
+            borrow_rate: config.borrow_rate.into(),
+        }
+    }
+}
+
+impl From<OracleParams> for OracleConfig {
+    fn from(params: OracleParams) -> Self {
+        Self {
+            oracle_account: params.oracle_account,
+            oracle_type: match params.oracle_type {
+                OracleType::None => OracleTypeConfig::None,
+                OracleType::Custom => OracleTypeConfig::Custom,
+                OracleType::Pyth => OracleTypeConfig::Pyth,
+            },
+            oracle_authority: params.oracle_authority,
+            max_price_error: params.max_price_error,
+            max_price_age_sec: params.max_price_age_sec,
+        }
+    }
+}
+
+impl From<OracleConfig> for OracleParams {
+    fn from(config: OracleConfig) -> Self {
+        Self {
+            oracle_account: config.oracle_account,
+            oracle_type: match config.oracle_type {
+                OracleTypeConfig::None => OracleType::None,
+                OracleTypeConfig::Custom => OracleType::Custom,
+                OracleTypeConfig::Pyth => OracleType::Pyth,
+            },
+            oracle_authority: config.oracle_authority,
+            max_price_error: config.max_price_error,
+            max_price_age_sec: config.max_price_age_sec,
+        }
+    }
+}
+
+impl From<PricingParams> for PricingConfig {
+    fn from(params: PricingParams) -> Self {
+        Self {
+            use_ema: params.use_ema,
+            use_unrealized_pnl_in_aum: params.use_unrealized_pnl_in_aum,
+            trade_spread_long: params.trade_spread_long,
+            trade_spread_short: params.trade_spread_short,
+            swap_spread: params.swap_spread,
+            min_initial_leverage: params.min_initial_leverage,
+            max_initial_leverage: params.max_initial_leverage,
+            max_leverage: params.max_leverage,
+            max_payoff_mult: params.max_payoff_mult,
+            max_utilization: params.max_utilization,
+            max_position_locked_usd: params.max_position_locked_usd,
+            max_total_locked_usd: params.max_total_locked_usd,
+            price_impact_mult: params.price_impact_mult,
+            max_price_impact: params.max_price_impact,
+        }
+    }
+}
+
+impl From<PricingConfig> for PricingParams {
+    fn from(config: PricingConfig) -> Self {
+        Self {
+            use_ema: config.use_ema,
+            use_unrealized_pnl_in_aum: config.use_unrealized_pnl_in_aum,
+            trade_spread_long: config.trade_spread_long,
+            trade_spread_short: config.trade_spread_short,
+            swap_spread: config.swap_spread,
+            min_initial_leverage: config.min_initial_leverage,
+            max_initial_leverage: config.max_initial_leverage,
+            max_leverage: config.max_leverage,
+            max_payoff_mult: config.max_payoff_mult,
+            max_utilization: config.max_utilization,
+            max_position_locked_usd: config.max_position_locked_usd,
+            max_total_locked_usd: config.max_total_locked_usd,
+            price_impact_mult: config.price_impact_mult,
+            max_price_impact: config.max_price_impact,
+        }
+    }
+}
+
+impl From<Permissions> for PermissionsConfig {
+    fn from(params: Permissions) -> Self {
+        Self {
+            allow_swap: params.allow_swap,
+            allow_add_liquidity: params.allow_add_liquidity,
+            allow_remove_liquidity: params.allow_remove_liquidity,
+            allow_open_position: params.allow_open_position,
+            allow_close_position: params.allow_close_position,
+            allow_pnl_withdrawal: params.allow_pnl_withdrawal,
+            allow_collateral_withdrawal: params.allow_collateral_withdrawal,
+            allow_size_change: params.allow_size_change,
+        }
+    }
+}
+
+impl From<PermissionsConfig> for Permissions {
+    fn from(config: PermissionsConfig) -> Self {
+        Self {
+            allow_swap: config.allow_swap,
+            allow_add_liquidity: config.allow_add_liquidity,
+            allow_remove_liquidity: config.allow_remove_liquidity,
+            allow_open_position: config.allow_open_position,
+            allow_close_position: config.allow_close_position,
+            allow_pnl_withdrawal: config.allow_pnl_withdrawal,
+            allow_collateral_withdrawal: config.allow_collateral_withdrawal,
+            allow_size_change: config.allow_size_change,
+        }
+    }
+}
+
+impl From<PermissionsConfig> for SetPermissionsParams {
+    fn from(config: PermissionsConfig) -> Self {
+        Self {
+            allow_swap: config.allow_swap,
+            allow_add_liquidity: config.allow_add_liquidity,
+            allow_remove_liquidity: config.allow_remove_liquidity,
+            allow_open_position: config.allow_open_position,
+            allow_close_position: config.allow_close_position,
+            allow_pnl_withdrawal: config.allow_pnl_withdrawal,
+            allow_collateral_withdrawal: config.allow_collateral_withdrawal,
+            allow_size_change: config.allow_size_change,
+        }
+    }
+}
+
+impl PermissionsConfig {
+    pub const NAMES: [&'static str; 8] = [
+        "swap",
+        "add-liquidity",
+        "remove-liquidity",
+        "open-position",
+        "close-position",
+        "pnl-withdrawal",
+        "collateral-withdrawal",
+        "size-change",
+    ];
+
+    /// Sets a single permission by its command line name
+    pub fn set(&mut self, name: &str, allow: bool) -> Result<()> {
+        let permission = match name {
+            "swap" => &mut self.allow_swap,
+            "add-liquidity" => &mut self.allow_add_liquidity,
+            "remove-liquidity" => &mut self.allow_remove_liquidity,
+            "open-position" => &mut self.allow_open_position,
+            "close-position" => &mut self.allow_close_position,
+            "pnl-withdrawal" => &mut self.allow_pnl_withdrawal,
+            "collateral-withdrawal" => &mut self.allow_collateral_withdrawal,
+            "size-change" => &mut self.allow_size_change,
+            _ => bail!("Unknown permission: {name}"),
+        };
+        *permission = allow;
+        Ok(())
+    }
+}
+
+impl From<Fees> for FeesConfig {
+    fn from(params: Fees) -> Self {
+        Self {
+            mode: match params.mode {
+                FeesMode::Fixed => FeesModeConfig::Fixed,
+                FeesMode::Linear => FeesModeConfig::Linear,
+                FeesMode::Optimal => FeesModeConfig::Optimal,
+            },
+            ratio_mult: params.ratio_mult,
+            utilization_mult: params.utilization_mult,
+            swap_in: params.swap_in,
+            swap_out: params.swap_out,
+            stable_swap_in: params.stable_swap_in,
+            stable_swap_out: params.stable_swap_out,
+            add_liquidity: params.add_liquidity,
+            remove_liquidity: params.remove_liquidity,
+            open_position: params.open_position,
+            close_position: params.close_position,
+            liquidation: params.liquidation,
+            protocol_share: params.protocol_share,
+            fee_max: params.fee_max,
+            fee_optimal: params.fee_optimal,
+        }
+    }
+}
+
+impl From<FeesConfig> for Fees {
+    fn from(config: FeesConfig) -> Self {
+        Self {
+            mode: match config.mode {
+                FeesModeConfig::Fixed => FeesMode::Fixed,
+                FeesModeConfig::Linear => FeesMode::Linear,
+                FeesModeConfig::Optimal => FeesMode::Optimal,
+            },
+            ratio_mult: config.ratio_mult,
+            utilization_mult: config.utilization_mult,
+            swap_in: config.swap_in,
+            swap_out: config.swap_out,
+            stable_swap_in: config.stable_swap_in,
+            stable_swap_out: config.stable_swap_out,
+            add_liquidity: config.add_liquidity,
+            remove_liquidity: config.remove_liquidity,
+            open_position: config.open_position,
+            close_position: config.close_position,
+            liquidation: config.liquidation,
+            protocol_share: config.protocol_share,
+            fee_max: config.fee_max,
+            fee_optimal: config.fee_optimal,
+        }
+    }
+}
+
+impl From<BorrowRateParams> for BorrowRateConfig {
+    fn from(params: BorrowRateParams) -> Self {
+        Self {
+            base_rate: params.base_rate,
+            slope1: params.slope1,
+            slope2: params.slope2,
+            optimal_utilization: params.optimal_utilization,
+        }
+    }
+}
+
+impl From<BorrowRateConfig> for BorrowRateParams {
+    fn from(config: BorrowRateConfig) -> Self {
+        Self {
+            base_rate: config.base_rate,
+            slope1: config.slope1,
+            slope2: config.slope2,
+            optimal_utilization: config.optimal_utilization,
+        }
+    }
+}
+
+impl From<TokenRatios> for RatiosConfig {
+    fn from(params: TokenRatios) -> Self {
+        Self {
+            target: params.target,
+            min: params.min,
+            max: params.max,
+        }
+    }
+}
+
+impl From<RatiosConfig> for TokenRatios {
+    fn from(config: RatiosConfig) -> Self {
+        Self {
+            target: config.target,
+            min: config.min,
+            max: config.max,
+        }
+    }
+}
+
+impl From<FeeTier> for FeeTierConfig {
+    fn from(params: FeeTier) -> Self {
+        Self {
+            min_volume_usd: params.min_volume_usd,
+            discount: params.discount,
+        }
+    }
+}
+
+impl From<FeeTierConfig> for FeeTier {
+    fn from(config: FeeTierConfig) -> Self {
+        Self {
+            min_volume_usd: config.min_volume_usd,
+            discount: config.discount,
+        }
+    }
+}
+
+mod pubkey_string {
+    use {
+        anchor_lang::prelude::Pubkey,
+        serde::{de::Error, Deserialize, Deserializer, Serializer},
+        std::str::FromStr,
+    };
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(pubkey)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Pubkey::from_str(&s).map_err(|err| D::Error::custom(format!("{s}: {err}")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_custody_config() -> CustodyConfig {
+        let custody = Custody {
+            oracle: OracleParams {
+                oracle_account: Pubkey::new_unique(),
+                oracle_type: OracleType::Pyth,
+                oracle_authority: Pubkey::default(),
+                max_price_error: 10_000,
+                max_price_age_sec: 60,
+            },
+            pricing: PricingParams {
+                max_leverage: 1_000_000,
+                ..PricingParams::default()
+            },
+            fees: Fees {
+                mode: FeesMode::Linear,
+                ..Fees::default()
+            },
+            ..Custody::default()
+        };
+        let pool = Pool {
+            ratios: vec![
+                TokenRatios {
+                    target: 5_000,
+                    min: 10,
+                    max: 10_000,
+                },
+                TokenRatios {
+                    target: 5_000,
+                    min: 10,
+                    max: 10_000,
+                },
+            ],
+            ..Pool::default()
+        };
+        CustodyConfig::new(&custody, &pool)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let config = get_custody_config();
+        for format in [ConfigFormat::Toml, ConfigFormat::Json] {
+            let content = write_config(&config, format).unwrap();
+            let value = parse_config_str(&content, format).unwrap();
+            assert_eq!(load_config::<CustodyConfig>(value, None).unwrap(), config);
+        }
+
+        let params: SetCustodyConfigParams = config.clone().into();
+        assert_eq!(params.oracle.oracle_type, OracleType::Pyth);
+        assert_eq!(params.fees.mode, FeesMode::Linear);
+        assert_eq!(params.ratios.len(), 2);
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let current = get_custody_config();
+        let value = parse_config_str(
+            "[pricing]\nmax_leverage = 500000\n\n[fees]\nmode = \"optimal\"\n",
+            ConfigFormat::Toml,
+        )
+        .unwrap();
+
+        // missing fields require a base config
+        assert!(load_config::<CustodyConfig>(value.clone(), None).is_err());
+
+        let proposed = load_config(value, Some(&current)).unwrap();
+        assert_eq!(proposed.pricing.max_leverage, 500_000);
+        assert_eq!(proposed.fees.mode, FeesModeConfig::Optimal);
+        assert_eq!(proposed.oracle, current.oracle);
+
+        // unknown fields are rejected
+        let value =
+            parse_config_str("{\"pricing\": {\"max_leverag\": 1}}", ConfigFormat::Json).unwrap();
+        assert!(load_config(value, Some(&current)).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let current = get_custody_config();
+        let mut proposed = current.clone();
+        assert!(diff(&current, &proposed).unwrap().is_empty());
+
+        proposed.pricing.max_leverage = 500_000;
+        proposed.fees.mode = FeesModeConfig::Optimal;
+        proposed.ratios.pop();
+
+        let changes: Vec<String> = diff(&current, &proposed)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                "fees.mode: \"linear\" -> \"optimal\"",
+                "pricing.max_leverage: 1000000 -> 500000",
+                "ratios[1]: {\"max\":10000,\"min\":10,\"target\":5000} -> <none>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_example_configs() {
+        let current = get_custody_config();
+        let custody: CustodyConfig = load_config(
+            parse_config_str(
+                include_str!("../../perpetuals-cli/configs/custody.toml"),
+                ConfigFormat::Toml,
+            )
+            .unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(custody.ratios.len(), 1);
+
+        let pricing: CustodyConfig = load_config(
+            parse_config_str(
+                include_str!("../../perpetuals-cli/configs/pricing.toml"),
+                ConfigFormat::Toml,
+            )
+            .unwrap(),
+            Some(&current),
+        )
+        .unwrap();
+        assert_eq!(diff(&current, &pricing).unwrap().len(), 3);
+
+        let fee_tiers: FeeTiersConfig = load_config(
+            parse_config_str(
+                include_str!("../../perpetuals-cli/configs/fee-tiers.json"),
+                ConfigFormat::Json,
+            )
+            .unwrap(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(fee_tiers.fee_tiers.len(), 2);
+
+        let permissions: PermissionsConfig = load_config(
+            parse_config_str(
+                include_str!("../../perpetuals-cli/configs/permissions.toml"),
+                ConfigFormat::Toml,
+            )
+            .unwrap(),
+            None,
+        )
+        .unwrap();
+        assert!(!permissions.allow_open_position);
+    }
+
+    #[test]
+    fn test_permissions() {
+        let mut permissions = PermissionsConfig::from(Permissions::default());
+        for name in PermissionsConfig::NAMES {
+            permissions.set(name, true).unwrap();
+        }
+        let params = SetPermissionsParams::from(permissions);
+        assert!(params.allow_swap && params.allow_size_change);
+        assert!(permissions.set("withdraw", true).is_err());
+    }
+}
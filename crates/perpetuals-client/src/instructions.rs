@@ -0,0 +1,1465 @@
+//! Instruction builders for every perpetuals program instruction
+//!
+//! Builders derive all program addresses from the arguments, so callers only
+//! provide keys that can't be derived: signers, user token accounts, mints and
+//! oracle accounts. Admin instructions must be signed by each multisig signer
+//! with identical arguments until enough signatures are collected.
+
+use {
+    crate::pda,
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        system_program, Discriminator, InstructionData, ToAccountMetas,
+    },
+    perpetuals::{
+        accounts, instruction,
+        instructions::*,
+        state::{custody::Custody, multisig::AdminRole, position::Side, proposal::Proposal},
+    },
+    solana_program::{instruction::Instruction, sysvar},
+};
+
+/// Custody keys that can't be derived from the pool address
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct CustodyKeys {
+    pub mint: Pubkey,
+    pub oracle_account: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl CustodyKeys {
+    pub fn new(mint: Pubkey, oracle_account: Pubkey, token_program: Pubkey) -> Self {
+        Self {
+            mint,
+            oracle_account,
+            token_program,
+        }
+    }
+
+    pub fn custody(&self, pool: &Pubkey) -> Pubkey {
+        pda::get_custody_pda(pool, &self.mint).0
+    }
+
+    pub fn token_account(&self, pool: &Pubkey) -> Pubkey {
+        pda::get_custody_token_account_pda(pool, &self.mint).0
+    }
+}
+
+impl From<&Custody> for CustodyKeys {
+    fn from(custody: &Custody) -> Self {
+        Self::new(
+            custody.mint,
+            custody.oracle.oracle_account,
+            custody.token_program,
+        )
+    }
+}
+
+/// Returns pool custodies followed by their oracle accounts, as expected by
+/// instructions that compute assets under management. Custodies must be listed
+/// in the same order as in the pool account.
+pub fn get_pool_remaining_accounts(pool: &Pubkey, custodies: &[CustodyKeys]) -> Vec<AccountMeta> {
+    custodies
+        .iter()
+        .map(|custody| custody.custody(pool))
+        .chain(custodies.iter().map(|custody| custody.oracle_account))
+        .map(|key| AccountMeta::new_readonly(key, false))
+        .collect()
+}
+
+fn get_signer_remaining_accounts(signers: &[Pubkey]) -> Vec<AccountMeta> {
+    signers
+        .iter()
+        .map(|signer| AccountMeta::new_readonly(*signer, false))
+        .collect()
+}
+
+// Trading delegate account is only passed when the instruction isn't signed by the owner
+fn get_trading_delegate(authority: &Pubkey, owner: &Pubkey, pool: &Pubkey) -> Option<Pubkey> {
+    (authority != owner).then(|| pda::get_trading_delegate_pda(owner, pool).0)
+}
+
+fn build_instruction(
+    accounts: impl ToAccountMetas,
+    remaining_accounts: Vec<AccountMeta>,
+    data: impl InstructionData,
+) -> Instruction {
+    let mut accounts = accounts.to_account_metas(None);
+    accounts.extend(remaining_accounts);
+    Instruction {
+        program_id: perpetuals::id(),
+        accounts,
+        data: data.data(),
+    }
+}
+
+// admin instructions
+
+pub fn init(
+    upgrade_authority: &Pubkey,
+    admin_signers: &[Pubkey],
+    params: InitParams,
+) -> Instruction {
+    build_instruction(
+        accounts::Init {
+            upgrade_authority: *upgrade_authority,
+            multisig: pda::get_multisig_pda().0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            perpetuals_program_data: pda::get_program_data_pda().0,
+            perpetuals_program: perpetuals::id(),
+            system_program: system_program::ID,
+            token_program: anchor_spl::token::ID,
+        },
+        get_signer_remaining_accounts(admin_signers),
+        instruction::Init { params },
+    )
+}
+
+/// `lp_token_program` is either SPL Token or Token-2022, it owns the new LP token mint
+pub fn add_pool(admin: &Pubkey, lp_token_program: &Pubkey, params: AddPoolParams) -> Instruction {
+    let pool = pda::get_pool_pda(&params.name).0;
+    build_instruction(
+        accounts::AddPool {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool,
+            lp_token_mint: pda::get_lp_token_mint_pda(&pool).0,
+            system_program: system_program::ID,
+            token_program: *lp_token_program,
+            rent: sysvar::rent::ID,
+        },
+        vec![],
+        instruction::AddPool { params },
+    )
+}
+
+pub fn remove_pool(admin: &Pubkey, pool: &Pubkey, params: RemovePoolParams) -> Instruction {
+    build_instruction(
+        accounts::RemovePool {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::RemovePool { params },
+    )
+}
+
+pub fn add_custody(
+    admin: &Pubkey,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    custody_token_program: &Pubkey,
+    params: AddCustodyParams,
+) -> Instruction {
+    build_instruction(
+        accounts::AddCustody {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+            custody_token_account: pda::get_custody_token_account_pda(pool, custody_token_mint).0,
+            custody_token_mint: *custody_token_mint,
+            system_program: system_program::ID,
+            token_program: *custody_token_program,
+            rent: sysvar::rent::ID,
+        },
+        vec![],
+        instruction::AddCustody { params },
+    )
+}
+
+pub fn remove_custody(
+    admin: &Pubkey,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    custody_token_program: &Pubkey,
+    params: RemoveCustodyParams,
+) -> Instruction {
+    build_instruction(
+        accounts::RemoveCustody {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+            custody_token_account: pda::get_custody_token_account_pda(pool, custody_token_mint).0,
+            system_program: system_program::ID,
+            token_program: *custody_token_program,
+        },
+        vec![],
+        instruction::RemoveCustody { params },
+    )
+}
+
+pub fn set_admin_signers(
+    admin: &Pubkey,
+    admin_signers: &[Pubkey],
+    params: SetAdminSignersParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetAdminSigners {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+        },
+        get_signer_remaining_accounts(admin_signers),
+        instruction::SetAdminSigners { params },
+    )
+}
+
+pub fn set_role_signers(
+    admin: &Pubkey,
+    role_signers: &[Pubkey],
+    params: SetRoleSignersParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetRoleSigners {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            role_multisig: pda::get_role_multisig_pda(params.role).0,
+            system_program: system_program::ID,
+        },
+        get_signer_remaining_accounts(role_signers),
+        instruction::SetRoleSigners { params },
+    )
+}
+
+pub fn set_custody_config(
+    admin: &Pubkey,
+    role: AdminRole,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SetCustodyConfigParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetCustodyConfig {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+        },
+        vec![],
+        instruction::SetCustodyConfig { params },
+    )
+}
+
+pub fn set_custody_risk(
+    admin: &Pubkey,
+    role: AdminRole,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SetCustodyRiskParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetCustodyRisk {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+        },
+        vec![],
+        instruction::SetCustodyRisk { params },
+    )
+}
+
+pub fn set_permissions(
+    admin: &Pubkey,
+    role: AdminRole,
+    params: SetPermissionsParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetPermissions {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            perpetuals: pda::get_perpetuals_pda().0,
+        },
+        vec![],
+        instruction::SetPermissions { params },
+    )
+}
+
+pub fn withdraw_fees(
+    admin: &Pubkey,
+    role: AdminRole,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    custody_token_program: &Pubkey,
+    receiving_token_account: &Pubkey,
+    params: WithdrawFeesParams,
+) -> Instruction {
+    build_instruction(
+        accounts::WithdrawFees {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+            custody_token_account: pda::get_custody_token_account_pda(pool, custody_token_mint).0,
+            custody_token_mint: *custody_token_mint,
+            receiving_token_account: *receiving_token_account,
+            token_program: *custody_token_program,
+        },
+        vec![],
+        instruction::WithdrawFees { params },
+    )
+}
+
+pub fn withdraw_sol_fees(
+    admin: &Pubkey,
+    role: AdminRole,
+    receiving_account: &Pubkey,
+    params: WithdrawSolFeesParams,
+) -> Instruction {
+    build_instruction(
+        accounts::WithdrawSolFees {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            receiving_account: *receiving_account,
+        },
+        vec![],
+        instruction::WithdrawSolFees { params },
+    )
+}
+
+/// Migrates a custody account created by a previous program version,
+/// `custody` is passed as is because deprecated accounts can't be loaded
+pub fn upgrade_custody(
+    admin: &Pubkey,
+    pool: &Pubkey,
+    custody: &Pubkey,
+    params: UpgradeCustodyParams,
+) -> Instruction {
+    build_instruction(
+        accounts::UpgradeCustody {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            pool: *pool,
+            custody: *custody,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::UpgradeCustody { params },
+    )
+}
+
+/// Migrates a custody account created before price impact params were added
+pub fn migrate_custody(
+    admin: &Pubkey,
+    pool: &Pubkey,
+    custody: &Pubkey,
+    params: MigrateCustodyParams,
+) -> Instruction {
+    build_instruction(
+        accounts::MigrateCustody {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            pool: *pool,
+            custody: *custody,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::MigrateCustody { params },
+    )
+}
+
+pub fn upgrade_multisig(upgrade_authority: &Pubkey, params: UpgradeMultisigParams) -> Instruction {
+    build_instruction(
+        accounts::UpgradeMultisig {
+            upgrade_authority: *upgrade_authority,
+            multisig: pda::get_multisig_pda().0,
+            perpetuals_program_data: pda::get_program_data_pda().0,
+            perpetuals_program: perpetuals::id(),
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::UpgradeMultisig { params },
+    )
+}
+
+pub fn set_fee_tiers(
+    admin: &Pubkey,
+    role: AdminRole,
+    pool: &Pubkey,
+    params: SetFeeTiersParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetFeeTiers {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            pool: *pool,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::SetFeeTiers { params },
+    )
+}
+
+/// Role signer sets share the admin timelock delay and are passed whether
+/// they have been created or not
+pub fn set_timelock_delay(admin: &Pubkey, params: SetTimelockDelayParams) -> Instruction {
+    build_instruction(
+        accounts::SetTimelockDelay {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+        },
+        AdminRole::TIMELOCKED_ROLES
+            .iter()
+            .map(|role| AccountMeta::new(pda::get_role_multisig_pda(*role).0, false))
+            .collect(),
+        instruction::SetTimelockDelay { params },
+    )
+}
+
+pub fn create_proposal(
+    admin: &Pubkey,
+    role: AdminRole,
+    params: CreateProposalParams,
+) -> Instruction {
+    build_instruction(
+        accounts::CreateProposal {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            proposal: pda::get_proposal_pda(role, params.id).0,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::CreateProposal { params },
+    )
+}
+
+pub fn approve_proposal(
+    admin: &Pubkey,
+    role: AdminRole,
+    proposal_id: u64,
+    params: ApproveProposalParams,
+) -> Instruction {
+    build_instruction(
+        accounts::ApproveProposal {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            proposal: pda::get_proposal_pda(role, proposal_id).0,
+        },
+        vec![],
+        instruction::ApproveProposal { params },
+    )
+}
+
+pub fn cancel_proposal(
+    admin: &Pubkey,
+    role: AdminRole,
+    proposal_id: u64,
+    proposer: &Pubkey,
+    params: CancelProposalParams,
+) -> Instruction {
+    build_instruction(
+        accounts::CancelProposal {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            proposal: pda::get_proposal_pda(role, proposal_id).0,
+            proposer: *proposer,
+        },
+        vec![],
+        instruction::CancelProposal { params },
+    )
+}
+
+/// Executes the proposal, its instruction accounts are passed in the original order
+pub fn execute_proposal(
+    executor: &Pubkey,
+    role: AdminRole,
+    proposal: &Proposal,
+    params: ExecuteProposalParams,
+) -> Instruction {
+    build_instruction(
+        accounts::ExecuteProposal {
+            executor: *executor,
+            multisig: pda::get_role_multisig_pda(role).0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            proposal: pda::get_proposal_pda(role, proposal.id).0,
+            proposer: proposal.proposer,
+            timelock_authority: pda::get_timelock_authority_pda(role).0,
+            perpetuals_program: perpetuals::id(),
+        },
+        proposal
+            .instruction_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.pubkey,
+                is_signer: false,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        instruction::ExecuteProposal { params },
+    )
+}
+
+pub fn set_custom_oracle_price(
+    admin: &Pubkey,
+    role: AdminRole,
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SetCustomOraclePriceParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetCustomOraclePrice {
+            admin: *admin,
+            multisig: pda::get_role_multisig_pda(role).0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+            oracle_account: pda::get_custom_oracle_account_pda(pool, custody_token_mint).0,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::SetCustomOraclePrice { params },
+    )
+}
+
+// test instructions
+
+pub fn set_test_time(admin: &Pubkey, params: SetTestTimeParams) -> Instruction {
+    build_instruction(
+        accounts::SetTestTime {
+            admin: *admin,
+            multisig: pda::get_multisig_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+        },
+        vec![],
+        instruction::SetTestTime { params },
+    )
+}
+
+// public instructions
+
+pub fn swap(
+    owner: &Pubkey,
+    funding_account: &Pubkey,
+    receiving_account: &Pubkey,
+    pool: &Pubkey,
+    receiving_custody: &CustodyKeys,
+    dispensing_custody: &CustodyKeys,
+    params: SwapParams,
+) -> Instruction {
+    build_instruction(
+        accounts::Swap {
+            owner: *owner,
+            funding_account: *funding_account,
+            receiving_account: *receiving_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            receiving_custody: receiving_custody.custody(pool),
+            receiving_custody_oracle_account: receiving_custody.oracle_account,
+            receiving_custody_token_account: receiving_custody.token_account(pool),
+            receiving_custody_token_mint: receiving_custody.mint,
+            dispensing_custody: dispensing_custody.custody(pool),
+            dispensing_custody_oracle_account: dispensing_custody.oracle_account,
+            dispensing_custody_token_account: dispensing_custody.token_account(pool),
+            dispensing_custody_token_mint: dispensing_custody.mint,
+            token_program: receiving_custody.token_program,
+            dispensing_token_program: dispensing_custody.token_program,
+        },
+        vec![],
+        instruction::Swap { params },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_liquidity(
+    owner: &Pubkey,
+    funding_account: &Pubkey,
+    lp_token_account: &Pubkey,
+    pool: &Pubkey,
+    lp_token_program: &Pubkey,
+    custody: &CustodyKeys,
+    pool_custodies: &[CustodyKeys],
+    params: AddLiquidityParams,
+) -> Instruction {
+    build_instruction(
+        accounts::AddLiquidity {
+            owner: *owner,
+            funding_account: *funding_account,
+            lp_token_account: *lp_token_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            custody_token_account: custody.token_account(pool),
+            custody_token_mint: custody.mint,
+            lp_token_mint: pda::get_lp_token_mint_pda(pool).0,
+            token_program: custody.token_program,
+            lp_token_program: *lp_token_program,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::AddLiquidity { params },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn remove_liquidity(
+    owner: &Pubkey,
+    receiving_account: &Pubkey,
+    lp_token_account: &Pubkey,
+    pool: &Pubkey,
+    lp_token_program: &Pubkey,
+    custody: &CustodyKeys,
+    pool_custodies: &[CustodyKeys],
+    params: RemoveLiquidityParams,
+) -> Instruction {
+    build_instruction(
+        accounts::RemoveLiquidity {
+            owner: *owner,
+            receiving_account: *receiving_account,
+            lp_token_account: *lp_token_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            custody_token_account: custody.token_account(pool),
+            custody_token_mint: custody.mint,
+            lp_token_mint: pda::get_lp_token_mint_pda(pool).0,
+            token_program: custody.token_program,
+            lp_token_program: *lp_token_program,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::RemoveLiquidity { params },
+    )
+}
+
+/// Opens a position of `owner`, signed either by the owner or by its trading
+/// delegate. The same applies to the other position instructions. A delegate
+/// can only fund positions from owner token accounts it was approved for.
+///
+/// `index` must be the current `next_index` of the owner position counter, or
+/// zero if the counter doesn't exist yet.
+#[allow(clippy::too_many_arguments)]
+pub fn open_position(
+    authority: &Pubkey,
+    owner: &Pubkey,
+    funding_account: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    index: u64,
+    params: OpenPositionParams,
+) -> Instruction {
+    let custody_pda = custody.custody(pool);
+    build_instruction(
+        accounts::OpenPosition {
+            authority: *authority,
+            owner: *owner,
+            funding_account: *funding_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position_counter: pda::get_position_counter_pda(owner).0,
+            position: pda::get_position_pda(owner, pool, &custody_pda, params.side, index).0,
+            trading_stats: pda::get_trading_stats_pda(owner, pool).0,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            system_program: system_program::ID,
+            token_program: collateral_custody.token_program,
+            trading_delegate: get_trading_delegate(authority, owner, pool),
+        },
+        vec![],
+        instruction::OpenPosition { params },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn add_collateral(
+    authority: &Pubkey,
+    owner: &Pubkey,
+    funding_account: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    side: Side,
+    index: u64,
+    params: AddCollateralParams,
+) -> Instruction {
+    let custody_pda = custody.custody(pool);
+    build_instruction(
+        accounts::AddCollateral {
+            authority: *authority,
+            owner: *owner,
+            funding_account: *funding_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: pda::get_position_pda(owner, pool, &custody_pda, side, index).0,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            token_program: collateral_custody.token_program,
+            trading_delegate: get_trading_delegate(authority, owner, pool),
+        },
+        vec![],
+        instruction::AddCollateral { params },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn remove_collateral(
+    authority: &Pubkey,
+    owner: &Pubkey,
+    receiving_account: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    side: Side,
+    index: u64,
+    params: RemoveCollateralParams,
+) -> Instruction {
+    let custody_pda = custody.custody(pool);
+    build_instruction(
+        accounts::RemoveCollateral {
+            authority: *authority,
+            owner: *owner,
+            receiving_account: *receiving_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: pda::get_position_pda(owner, pool, &custody_pda, side, index).0,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            token_program: collateral_custody.token_program,
+            trading_delegate: get_trading_delegate(authority, owner, pool),
+        },
+        vec![],
+        instruction::RemoveCollateral { params },
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn close_position(
+    authority: &Pubkey,
+    owner: &Pubkey,
+    receiving_account: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    side: Side,
+    index: u64,
+    params: ClosePositionParams,
+) -> Instruction {
+    let custody_pda = custody.custody(pool);
+    build_instruction(
+        accounts::ClosePosition {
+            authority: *authority,
+            owner: *owner,
+            receiving_account: *receiving_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: pda::get_position_pda(owner, pool, &custody_pda, side, index).0,
+            trading_stats: pda::get_trading_stats_pda(owner, pool).0,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            system_program: system_program::ID,
+            token_program: collateral_custody.token_program,
+            trading_delegate: get_trading_delegate(authority, owner, pool),
+        },
+        vec![],
+        instruction::ClosePosition { params },
+    )
+}
+
+/// Moves `owner` position to `new_owner`, the position address changes.
+/// `new_index` must be the current `next_index` of the new owner position counter.
+pub fn transfer_position(
+    owner: &Pubkey,
+    new_owner: &Pubkey,
+    pool: &Pubkey,
+    custody: &Pubkey,
+    side: Side,
+    index: u64,
+    new_index: u64,
+) -> Instruction {
+    build_instruction(
+        accounts::TransferPosition {
+            owner: *owner,
+            new_owner: *new_owner,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: pda::get_position_pda(owner, pool, custody, side, index).0,
+            new_position_counter: pda::get_position_counter_pda(new_owner).0,
+            new_position: pda::get_position_pda(new_owner, pool, custody, side, new_index).0,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::TransferPosition {},
+    )
+}
+
+/// Re-creates a legacy position of `owner` under `index`, the owner's next
+/// position index. Anyone can pay for the upgrade
+pub fn upgrade_position(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    pool: &Pubkey,
+    custody: &Pubkey,
+    side: Side,
+    index: u64,
+) -> Instruction {
+    build_instruction(
+        accounts::UpgradePosition {
+            payer: *payer,
+            owner: *owner,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            legacy_position: pda::get_legacy_position_pda(owner, pool, custody, side).0,
+            position_counter: pda::get_position_counter_pda(owner).0,
+            position: pda::get_position_pda(owner, pool, custody, side, index).0,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::UpgradePosition {
+            params: UpgradePositionParams {
+                custody: *custody,
+                side,
+            },
+        },
+    )
+}
+
+pub fn set_trading_delegate(
+    owner: &Pubkey,
+    pool: &Pubkey,
+    params: SetTradingDelegateParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetTradingDelegate {
+            owner: *owner,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            trading_delegate: pda::get_trading_delegate_pda(owner, pool).0,
+            system_program: system_program::ID,
+        },
+        vec![],
+        instruction::SetTradingDelegate { params },
+    )
+}
+
+pub fn remove_trading_delegate(owner: &Pubkey, pool: &Pubkey) -> Instruction {
+    build_instruction(
+        accounts::RemoveTradingDelegate {
+            owner: *owner,
+            perpetuals: pda::get_perpetuals_pda().0,
+            trading_delegate: pda::get_trading_delegate_pda(owner, pool).0,
+        },
+        vec![],
+        instruction::RemoveTradingDelegate {},
+    )
+}
+
+/// Liquidates `position`, `receiving_account` must belong to the position owner
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate(
+    signer: &Pubkey,
+    receiving_account: &Pubkey,
+    rewards_receiving_account: &Pubkey,
+    pool: &Pubkey,
+    position: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    params: LiquidateParams,
+) -> Instruction {
+    build_instruction(
+        accounts::Liquidate {
+            signer: *signer,
+            receiving_account: *receiving_account,
+            rewards_receiving_account: *rewards_receiving_account,
+            transfer_authority: pda::get_transfer_authority_pda().0,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: *position,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            token_program: collateral_custody.token_program,
+        },
+        vec![],
+        instruction::Liquidate { params },
+    )
+}
+
+pub fn update_pool_aum(
+    payer: &Pubkey,
+    pool: &Pubkey,
+    pool_custodies: &[CustodyKeys],
+) -> Instruction {
+    build_instruction(
+        accounts::UpdatePoolAum {
+            payer: *payer,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::UpdatePoolAum {},
+    )
+}
+
+/// Updates custom oracle price with an off-chain signed message. The transaction
+/// must start with the ed25519 signature verification instruction.
+pub fn set_custom_oracle_price_permissionless(
+    pool: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: SetCustomOraclePricePermissionlessParams,
+) -> Instruction {
+    build_instruction(
+        accounts::SetCustomOraclePricePermissionless {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: pda::get_custody_pda(pool, custody_token_mint).0,
+            oracle_account: pda::get_custom_oracle_account_pda(pool, custody_token_mint).0,
+            ix_sysvar: sysvar::instructions::ID,
+        },
+        vec![],
+        instruction::SetCustomOraclePricePermissionless { params },
+    )
+}
+
+// darkpool instructions
+
+/// Opens both positions of a darkpool trade. The darkpool authority PDA must
+/// sign, so the instruction is only accepted when invoked by the darkpool
+/// program, see `settle_queued_dark_pool_trade`. Funding accounts must be
+/// transferable by the darkpool authority.
+///
+/// Position indexes follow the same rules as `open_position`.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_dark_pool_trade(
+    payer: &Pubkey,
+    long_owner: &Pubkey,
+    short_owner: &Pubkey,
+    funding_account_long: &Pubkey,
+    funding_account_short: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    index_long: u64,
+    index_short: u64,
+    params: SettleDarkPoolTradeParams,
+) -> Instruction {
+    let custody_pda = custody.custody(pool);
+    build_instruction(
+        accounts::SettleDarkPoolTrade {
+            payer: *payer,
+            darkpool_authority: pda::get_darkpool_authority_pda().0,
+            long_owner: *long_owner,
+            short_owner: *short_owner,
+            funding_account_long: *funding_account_long,
+            funding_account_short: *funding_account_short,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position_counter_long: pda::get_position_counter_pda(long_owner).0,
+            position_long: pda::get_position_pda(
+                long_owner,
+                pool,
+                &custody_pda,
+                Side::Long,
+                index_long,
+            )
+            .0,
+            position_counter_short: pda::get_position_counter_pda(short_owner).0,
+            position_short: pda::get_position_pda(
+                short_owner,
+                pool,
+                &custody_pda,
+                Side::Short,
+                index_short,
+            )
+            .0,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            collateral_custody_token_account: collateral_custody.token_account(pool),
+            collateral_custody_token_mint: collateral_custody.mint,
+            system_program: system_program::ID,
+            token_program: collateral_custody.token_program,
+        },
+        vec![],
+        instruction::SettleDarkPoolTrade { params },
+    )
+}
+
+/// Settles a trade queued by the darkpool program, which checks the trade
+/// against its settlement queue and invokes `settle_dark_pool_trade` with the
+/// escrowed collateral. Anyone can settle a queued trade, `pool`, `custody`
+/// and `collateral_custody` are the ones of the order book.
+#[allow(clippy::too_many_arguments)]
+pub fn settle_queued_dark_pool_trade(
+    settler: &Pubkey,
+    order_book: &Pubkey,
+    long_owner: &Pubkey,
+    short_owner: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    index_long: u64,
+    index_short: u64,
+    trade_id: u64,
+) -> Instruction {
+    // The darkpool program doesn't share this crate, accounts are listed in
+    // the order of its SettleDarkPoolTrade accounts
+    let custody_pda = custody.custody(pool);
+    let collateral_custody_pda = collateral_custody.custody(pool);
+    let accounts = vec![
+        AccountMeta::new(*settler, true),
+        AccountMeta::new(pda::get_darkpool_authority_pda().0, false),
+        AccountMeta::new_readonly(*order_book, false),
+        AccountMeta::new(pda::get_settlement_queue_pda(order_book).0, false),
+        AccountMeta::new_readonly(*long_owner, false),
+        AccountMeta::new_readonly(*short_owner, false),
+        AccountMeta::new(
+            pda::get_darkpool_escrow_pda(order_book, long_owner).0,
+            false,
+        ),
+        AccountMeta::new(
+            pda::get_darkpool_escrow_pda(order_book, short_owner).0,
+            false,
+        ),
+        AccountMeta::new_readonly(perpetuals::id(), false),
+        AccountMeta::new_readonly(pda::get_perpetuals_pda().0, false),
+        AccountMeta::new(*pool, false),
+        AccountMeta::new(pda::get_position_counter_pda(long_owner).0, false),
+        AccountMeta::new(
+            pda::get_position_pda(long_owner, pool, &custody_pda, Side::Long, index_long).0,
+            false,
+        ),
+        AccountMeta::new(pda::get_position_counter_pda(short_owner).0, false),
+        AccountMeta::new(
+            pda::get_position_pda(short_owner, pool, &custody_pda, Side::Short, index_short).0,
+            false,
+        ),
+        AccountMeta::new(custody_pda, false),
+        AccountMeta::new_readonly(custody.oracle_account, false),
+        AccountMeta::new(collateral_custody_pda, false),
+        AccountMeta::new_readonly(collateral_custody.oracle_account, false),
+        AccountMeta::new(collateral_custody.token_account(pool), false),
+        AccountMeta::new_readonly(collateral_custody.mint, false),
+        AccountMeta::new_readonly(collateral_custody.token_program, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+
+    // Both programs name the instruction settle_dark_pool_trade, so they
+    // share its discriminator
+    let mut data = instruction::SettleDarkPoolTrade::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&trade_id.to_le_bytes());
+
+    Instruction {
+        program_id: DARKPOOL_PROGRAM_ID,
+        accounts,
+        data,
+    }
+}
+
+// view instructions, to be used with transaction simulation
+
+pub fn get_add_liquidity_amount_and_fee(
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    pool_custodies: &[CustodyKeys],
+    params: GetAddLiquidityAmountAndFeeParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetAddLiquidityAmountAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            lp_token_mint: pda::get_lp_token_mint_pda(pool).0,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::GetAddLiquidityAmountAndFee { params },
+    )
+}
+
+pub fn get_remove_liquidity_amount_and_fee(
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    pool_custodies: &[CustodyKeys],
+    params: GetRemoveLiquidityAmountAndFeeParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetRemoveLiquidityAmountAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            lp_token_mint: pda::get_lp_token_mint_pda(pool).0,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::GetRemoveLiquidityAmountAndFee { params },
+    )
+}
+
+/// Quotes a new position for `owner`, fee tier discounts are applied if `trading_stats` is provided
+pub fn get_entry_price_and_fee(
+    owner: &Pubkey,
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    trading_stats: Option<Pubkey>,
+    params: GetEntryPriceAndFeeParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetEntryPriceAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            owner: *owner,
+            trading_stats,
+        },
+        vec![],
+        instruction::GetEntryPriceAndFee { params },
+    )
+}
+
+/// Quotes position close, fee tier discounts are applied if `trading_stats` is provided
+pub fn get_exit_price_and_fee(
+    pool: &Pubkey,
+    position: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    trading_stats: Option<Pubkey>,
+    params: GetExitPriceAndFeeParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetExitPriceAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: *position,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+            trading_stats,
+        },
+        vec![],
+        instruction::GetExitPriceAndFee { params },
+    )
+}
+
+pub fn get_pnl(
+    pool: &Pubkey,
+    position: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    params: GetPnlParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetPnl {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: *position,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+        },
+        vec![],
+        instruction::GetPnl { params },
+    )
+}
+
+pub fn get_liquidation_price(
+    pool: &Pubkey,
+    position: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    params: GetLiquidationPriceParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetLiquidationPrice {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: *position,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+        },
+        vec![],
+        instruction::GetLiquidationPrice { params },
+    )
+}
+
+pub fn get_liquidation_state(
+    pool: &Pubkey,
+    position: &Pubkey,
+    custody: &CustodyKeys,
+    collateral_custody: &CustodyKeys,
+    params: GetLiquidationStateParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetLiquidationState {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            position: *position,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+            collateral_custody: collateral_custody.custody(pool),
+            collateral_custody_oracle_account: collateral_custody.oracle_account,
+        },
+        vec![],
+        instruction::GetLiquidationState { params },
+    )
+}
+
+pub fn get_oracle_price(
+    pool: &Pubkey,
+    custody: &CustodyKeys,
+    params: GetOraclePriceParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetOraclePrice {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            custody: custody.custody(pool),
+            custody_oracle_account: custody.oracle_account,
+        },
+        vec![],
+        instruction::GetOraclePrice { params },
+    )
+}
+
+pub fn get_swap_amount_and_fees(
+    pool: &Pubkey,
+    receiving_custody: &CustodyKeys,
+    dispensing_custody: &CustodyKeys,
+    params: GetSwapAmountAndFeesParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetSwapAmountAndFees {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            receiving_custody: receiving_custody.custody(pool),
+            receiving_custody_oracle_account: receiving_custody.oracle_account,
+            dispensing_custody: dispensing_custody.custody(pool),
+            dispensing_custody_oracle_account: dispensing_custody.oracle_account,
+        },
+        vec![],
+        instruction::GetSwapAmountAndFees { params },
+    )
+}
+
+pub fn get_assets_under_management(
+    pool: &Pubkey,
+    pool_custodies: &[CustodyKeys],
+    params: GetAssetsUnderManagementParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetAssetsUnderManagement {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::GetAssetsUnderManagement { params },
+    )
+}
+
+pub fn get_lp_token_price(
+    pool: &Pubkey,
+    pool_custodies: &[CustodyKeys],
+    params: GetLpTokenPriceParams,
+) -> Instruction {
+    build_instruction(
+        accounts::GetLpTokenPrice {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool,
+            lp_token_mint: pda::get_lp_token_mint_pda(pool).0,
+        },
+        get_pool_remaining_accounts(pool, pool_custodies),
+        instruction::GetLpTokenPrice { params },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, anchor_lang::Discriminator};
+
+    #[test]
+    fn test_pool_remaining_accounts() {
+        let pool = pda::get_pool_pda("TestPool1").0;
+        let custodies = [
+            CustodyKeys::new(
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                anchor_spl::token::ID,
+            ),
+            CustodyKeys::new(
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+                anchor_spl::token::ID,
+            ),
+        ];
+
+        let ix = update_pool_aum(&Pubkey::new_unique(), &pool, &custodies);
+
+        assert_eq!(ix.program_id, perpetuals::id());
+        assert_eq!(ix.data, instruction::UpdatePoolAum::DISCRIMINATOR);
+        assert_eq!(ix.accounts.len(), 3 + 2 * custodies.len());
+        assert_eq!(ix.accounts[3].pubkey, custodies[0].custody(&pool));
+        assert_eq!(ix.accounts[4].pubkey, custodies[1].custody(&pool));
+        assert_eq!(ix.accounts[5].pubkey, custodies[0].oracle_account);
+        assert_eq!(ix.accounts[6].pubkey, custodies[1].oracle_account);
+        assert!(ix.accounts[3..].iter().all(|meta| !meta.is_writable));
+    }
+
+    #[test]
+    fn test_open_position() {
+        let owner = Pubkey::new_unique();
+        let pool = pda::get_pool_pda("TestPool1").0;
+        let custody = CustodyKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            anchor_spl::token::ID,
+        );
+        let params = OpenPositionParams {
+            price: 25_000_000_000,
+            collateral: 1_000_000_000,
+            size: 5_000_000_000,
+            side: Side::Short,
+        };
+
+        let ix = open_position(
+            &owner,
+            &owner,
+            &Pubkey::new_unique(),
+            &pool,
+            &custody,
+            &custody,
+            3,
+            params,
+        );
+
+        let position =
+            pda::get_position_pda(&owner, &pool, &custody.custody(&pool), Side::Short, 3).0;
+        assert!(ix.accounts[0].is_signer);
+        assert_eq!(ix.accounts[1].pubkey, owner);
+        assert_eq!(
+            ix.accounts[6].pubkey,
+            pda::get_position_counter_pda(&owner).0
+        );
+        assert_eq!(ix.accounts[7].pubkey, position);
+        assert!(ix.accounts[7].is_writable);
+        assert_eq!(
+            ix.accounts[8].pubkey,
+            pda::get_trading_stats_pda(&owner, &pool).0
+        );
+        // no trading delegate
+        assert_eq!(ix.accounts.last().unwrap().pubkey, perpetuals::id());
+        assert_eq!(ix.data[..8], instruction::OpenPosition::DISCRIMINATOR);
+    }
+
+    #[test]
+    fn test_swap_token_programs() {
+        let owner = Pubkey::new_unique();
+        let pool = pda::get_pool_pda("TestPool1").0;
+        let receiving_custody = CustodyKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            anchor_spl::token::ID,
+        );
+        let dispensing_custody = CustodyKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            anchor_spl::token_2022::ID,
+        );
+
+        let ix = swap(
+            &owner,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &pool,
+            &receiving_custody,
+            &dispensing_custody,
+            SwapParams {
+                amount_in: 1,
+                min_amount_out: 1,
+            },
+        );
+
+        assert_eq!(ix.accounts[9].pubkey, receiving_custody.mint);
+        assert_eq!(ix.accounts[13].pubkey, dispensing_custody.mint);
+        assert_eq!(ix.accounts[14].pubkey, anchor_spl::token::ID);
+        assert_eq!(ix.accounts[15].pubkey, anchor_spl::token_2022::ID);
+    }
+
+    #[test]
+    fn test_delegated_close_position() {
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let pool = pda::get_pool_pda("TestPool1").0;
+        let custody = CustodyKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            anchor_spl::token::ID,
+        );
+
+        let ix = close_position(
+            &delegate,
+            &owner,
+            &Pubkey::new_unique(),
+            &pool,
+            &custody,
+            &custody,
+            Side::Long,
+            1,
+            ClosePositionParams { price: 1 },
+        );
+
+        assert_eq!(ix.accounts[0].pubkey, delegate);
+        assert!(ix.accounts[0].is_signer);
+        assert_eq!(ix.accounts[1].pubkey, owner);
+        assert!(!ix.accounts[1].is_signer);
+        assert_eq!(
+            ix.accounts[6].pubkey,
+            pda::get_position_pda(&owner, &pool, &custody.custody(&pool), Side::Long, 1).0
+        );
+        assert_eq!(
+            ix.accounts.last().unwrap().pubkey,
+            pda::get_trading_delegate_pda(&owner, &pool).0
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! Rust client for the Solana Perpetuals program: program derived addresses,
+//! instruction builders, account deserializers and admin configs.
+
+#![allow(clippy::result_large_err)]
+
+pub mod accounts;
+pub mod config;
+pub mod instructions;
+pub mod pda;
+
+pub use {
+    instructions::CustodyKeys,
+    perpetuals::{self, instructions as params, ID},
+};
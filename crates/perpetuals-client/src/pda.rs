@@ -0,0 +1,184 @@
+//! Program derived addresses of the perpetuals accounts
+
+use {
+    anchor_lang::prelude::Pubkey,
+    perpetuals::{
+        instructions::DARKPOOL_PROGRAM_ID,
+        state::{multisig::AdminRole, position::Side},
+    },
+};
+
+pub fn get_multisig_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"multisig"], &perpetuals::id())
+}
+
+/// Returns the signer set of the role, admin role resolves to the root multisig
+pub fn get_role_multisig_pda(role: AdminRole) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"multisig", role.get_seed()], &perpetuals::id())
+}
+
+pub fn get_transfer_authority_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"transfer_authority"], &perpetuals::id())
+}
+
+/// Returns the signer of instructions from executed proposals of the role
+pub fn get_timelock_authority_pda(role: AdminRole) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"timelock_authority", role.get_seed()], &perpetuals::id())
+}
+
+pub fn get_perpetuals_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"perpetuals"], &perpetuals::id())
+}
+
+pub fn get_program_data_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[perpetuals::id().as_ref()],
+        &solana_program::bpf_loader_upgradeable::id(),
+    )
+}
+
+/// Returns the proposal of the role signer set, ids are counted per role
+pub fn get_proposal_pda(role: AdminRole, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"proposal", role.get_seed(), &id.to_le_bytes()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_pool_pda(name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"pool", name.as_bytes()], &perpetuals::id())
+}
+
+pub fn get_lp_token_mint_pda(pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"lp_token_mint", pool_pda.as_ref()], &perpetuals::id())
+}
+
+pub fn get_custody_pda(pool_pda: &Pubkey, custody_token_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"custody", pool_pda.as_ref(), custody_token_mint.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_custody_token_account_pda(
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"custody_token_account",
+            pool_pda.as_ref(),
+            custody_token_mint.as_ref(),
+        ],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_custom_oracle_account_pda(
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"oracle_account",
+            pool_pda.as_ref(),
+            custody_token_mint.as_ref(),
+        ],
+        &perpetuals::id(),
+    )
+}
+
+/// Positions are indexed with the owner position counter, a new position
+/// gets `PositionCounter::next_index`
+pub fn get_position_pda(
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    side: Side,
+    index: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"position",
+            owner.as_ref(),
+            pool_pda.as_ref(),
+            custody_pda.as_ref(),
+            &[side as u8],
+            &index.to_le_bytes(),
+        ],
+        &perpetuals::id(),
+    )
+}
+
+/// Address of a position opened before positions were indexed, see `upgrade_position`
+pub fn get_legacy_position_pda(
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    side: Side,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"position",
+            owner.as_ref(),
+            pool_pda.as_ref(),
+            custody_pda.as_ref(),
+            &[side as u8],
+        ],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_position_counter_pda(owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"position_counter", owner.as_ref()], &perpetuals::id())
+}
+
+pub fn get_trading_stats_pda(owner: &Pubkey, pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"trading_stats", owner.as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+pub fn get_trading_delegate_pda(owner: &Pubkey, pool_pda: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"trading_delegate", owner.as_ref(), pool_pda.as_ref()],
+        &perpetuals::id(),
+    )
+}
+
+// darkpool accounts
+
+/// Darkpool state account, signs trade settlements in the perpetuals program
+pub fn get_darkpool_authority_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"darkpool"], &DARKPOOL_PROGRAM_ID)
+}
+
+pub fn get_settlement_queue_pda(order_book: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"settlement_queue", order_book.as_ref()],
+        &DARKPOOL_PROGRAM_ID,
+    )
+}
+
+/// Collateral escrowed by the owner for its dark orders in the order book
+pub fn get_darkpool_escrow_pda(order_book: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"escrow", order_book.as_ref(), owner.as_ref()],
+        &DARKPOOL_PROGRAM_ID,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_admin_role_multisig() {
+        assert_eq!(get_multisig_pda(), get_role_multisig_pda(AdminRole::Admin));
+        assert_ne!(
+            get_multisig_pda().0,
+            get_role_multisig_pda(AdminRole::Guardian).0
+        );
+    }
+}
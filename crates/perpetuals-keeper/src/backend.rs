@@ -0,0 +1,84 @@
+//! Cluster access used by the keeper
+
+use {
+    anyhow::Result,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        rpc_client::RpcClient,
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::RpcFilterType,
+    },
+    solana_sdk::{
+        account::Account,
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::Signature,
+        signer::{keypair::Keypair, Signer},
+        transaction::Transaction,
+    },
+};
+
+// max number of accounts per getMultipleAccounts request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Minimal set of cluster queries the keeper depends on. Implemented for
+/// `RpcClient`, so it works against any cluster including a local validator;
+/// tests provide an in-memory stand-in.
+pub trait Backend {
+    fn get_account(&self, key: &Pubkey) -> Result<Option<Account>>;
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Account)>>;
+
+    fn send_transaction(&self, instructions: &[Instruction], payer: &Keypair) -> Result<Signature>;
+}
+
+impl Backend for RpcClient {
+    fn get_account(&self, key: &Pubkey) -> Result<Option<Account>> {
+        Ok(self
+            .get_account_with_commitment(key, self.commitment())?
+            .value)
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            accounts.extend(RpcClient::get_multiple_accounts(self, chunk)?);
+        }
+        Ok(accounts)
+    }
+
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(self.get_program_accounts_with_config(
+            program_id,
+            RpcProgramAccountsConfig {
+                filters: Some(filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(self.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )?)
+    }
+
+    fn send_transaction(&self, instructions: &[Instruction], payer: &Keypair) -> Result<Signature> {
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &[payer],
+            self.get_latest_blockhash()?,
+        );
+        Ok(self.send_and_confirm_transaction(&transaction)?)
+    }
+}
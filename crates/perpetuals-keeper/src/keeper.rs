@@ -0,0 +1,463 @@
+//! Liquidation keeper, finds over-leveraged positions and liquidates them
+
+use {
+    crate::backend::Backend,
+    anchor_lang::{prelude::AccountInfo, AccountDeserialize, Discriminator},
+    anyhow::{anyhow, Result},
+    log::{debug, info, warn},
+    perpetuals::{
+        instructions::LiquidateParams,
+        state::{
+            custody::Custody,
+            oracle::OraclePrice,
+            perpetuals::Perpetuals,
+            pool::Pool,
+            position::{LegacyPosition, Position},
+            position_counter::PositionCounter,
+        },
+    },
+    perpetuals_client::{accounts, instructions, pda, CustodyKeys},
+    solana_client::rpc_filter::{Memcmp, RpcFilterType},
+    solana_sdk::{
+        account::{self, Account},
+        compute_budget::ComputeBudgetInstruction,
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::Signature,
+        signer::{keypair::Keypair, Signer},
+        sysvar::{self, clock::Clock},
+    },
+    spl_associated_token_account::{
+        get_associated_token_address_with_program_id,
+        instruction::create_associated_token_account_idempotent,
+    },
+    std::collections::{BTreeSet, HashMap},
+};
+
+// offset of Position::pool in the account data
+const POSITION_POOL_OFFSET: usize = 8 + 32;
+
+#[derive(Clone, Debug, Default)]
+pub struct KeeperConfig {
+    // only scan positions of the given pool
+    pub pool: Option<Pubkey>,
+    // priority fee in micro-lamports per compute unit
+    pub priority_fee: u64,
+    pub compute_unit_limit: Option<u32>,
+    // evaluate positions but don't send liquidation transactions
+    pub dry_run: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct KeeperStats {
+    pub positions: usize,
+    pub undercollateralized: usize,
+    pub liquidated: usize,
+}
+
+pub struct Keeper<B: Backend> {
+    backend: B,
+    payer: Keypair,
+    config: KeeperConfig,
+}
+
+impl<B: Backend> Keeper<B> {
+    pub fn new(backend: B, payer: Keypair, config: KeeperConfig) -> Self {
+        Self {
+            backend,
+            payer,
+            config,
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Scans all positions once and liquidates the ones that exceed max leverage
+    pub fn run_once(&self) -> Result<KeeperStats> {
+        let mut stats = KeeperStats::default();
+
+        let perpetuals: Perpetuals = self.get_account(&pda::get_perpetuals_pda().0)?;
+        if !perpetuals.permissions.allow_close_position {
+            warn!("Liquidations are not allowed at this time");
+            return Ok(stats);
+        }
+        let curtime = self.get_time()?;
+
+        // load positions and everything required to evaluate them
+        let positions = self.get_positions()?;
+        let pools: HashMap<Pubkey, Pool> =
+            self.get_multiple_accounts(positions.iter().map(|(_, position, _)| position.pool))?;
+        let custodies: HashMap<Pubkey, Custody> = self.get_multiple_accounts(
+            positions
+                .iter()
+                .flat_map(|(_, position, _)| [position.custody, position.collateral_custody]),
+        )?;
+        let oracle_keys: BTreeSet<Pubkey> = custodies
+            .values()
+            .map(|custody| custody.oracle.oracle_account)
+            .collect();
+        let oracle_keys: Vec<Pubkey> = oracle_keys.into_iter().collect();
+        let oracles: HashMap<Pubkey, Account> = oracle_keys
+            .iter()
+            .zip(self.backend.get_multiple_accounts(&oracle_keys)?)
+            .filter_map(|(key, account)| account.map(|account| (*key, account)))
+            .collect();
+
+        for (position_key, position, legacy) in &positions {
+            stats.positions += 1;
+
+            let (Some(pool), Some(custody), Some(collateral_custody)) = (
+                pools.get(&position.pool),
+                custodies.get(&position.custody),
+                custodies.get(&position.collateral_custody),
+            ) else {
+                warn!("Missing pool or custody accounts for position {position_key}");
+                continue;
+            };
+
+            let (Some(custody_oracle), Some(collateral_custody_oracle)) = (
+                oracles.get(&custody.oracle.oracle_account),
+                oracles.get(&collateral_custody.oracle.oracle_account),
+            ) else {
+                warn!("Missing oracle accounts for position {position_key}");
+                continue;
+            };
+
+            match is_liquidatable(
+                pool,
+                position,
+                custody,
+                (&custody.oracle.oracle_account, custody_oracle),
+                collateral_custody,
+                (
+                    &collateral_custody.oracle.oracle_account,
+                    collateral_custody_oracle,
+                ),
+                curtime,
+            ) {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(err) => {
+                    warn!("Failed to evaluate position {position_key}: {err}");
+                    continue;
+                }
+            }
+            stats.undercollateralized += 1;
+
+            if !custody.permissions.allow_close_position {
+                debug!("Position {position_key} can't be liquidated, custody is paused");
+                continue;
+            }
+
+            if self.config.dry_run {
+                info!("Dry run: position {position_key} can be liquidated");
+                continue;
+            }
+
+            match self.liquidate(position_key, position, custody, collateral_custody, *legacy) {
+                Ok(signature) => {
+                    info!("Liquidated position {position_key}: {signature}");
+                    stats.liquidated += 1;
+                }
+                Err(err) => warn!("Failed to liquidate position {position_key}: {err}"),
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Returns instructions that liquidate the position, the liquidated collateral
+    /// is returned to the owner and rewards are paid to the keeper in collateral tokens
+    pub fn get_liquidate_instructions(
+        &self,
+        position_key: &Pubkey,
+        position: &Position,
+        custody: &Custody,
+        collateral_custody: &Custody,
+    ) -> Vec<Instruction> {
+        let payer = self.payer.pubkey();
+        let collateral_custody = CustodyKeys::from(collateral_custody);
+
+        let mut ixs = Vec::with_capacity(5);
+        if let Some(units) = self.config.compute_unit_limit {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+        }
+        if self.config.priority_fee > 0 {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_price(
+                self.config.priority_fee,
+            ));
+        }
+        ixs.push(create_associated_token_account_idempotent(
+            &payer,
+            &position.owner,
+            &collateral_custody.mint,
+            &collateral_custody.token_program,
+        ));
+        ixs.push(create_associated_token_account_idempotent(
+            &payer,
+            &payer,
+            &collateral_custody.mint,
+            &collateral_custody.token_program,
+        ));
+        ixs.push(instructions::liquidate(
+            &payer,
+            &get_associated_token_address_with_program_id(
+                &position.owner,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            &get_associated_token_address_with_program_id(
+                &payer,
+                &collateral_custody.mint,
+                &collateral_custody.token_program,
+            ),
+            &position.pool,
+            position_key,
+            &CustodyKeys::from(custody),
+            &collateral_custody,
+            LiquidateParams {},
+        ));
+        ixs
+    }
+
+    fn liquidate(
+        &self,
+        position_key: &Pubkey,
+        position: &Position,
+        custody: &Custody,
+        collateral_custody: &Custody,
+        legacy: bool,
+    ) -> Result<Signature> {
+        if !legacy {
+            let ixs = self.get_liquidate_instructions(
+                position_key,
+                position,
+                custody,
+                collateral_custody,
+            );
+            return self.backend.send_transaction(&ixs, &self.payer);
+        }
+
+        // legacy positions are re-created under the owner's next index first
+        let index = self.get_next_position_index(&position.owner)?;
+        let new_position_key = pda::get_position_pda(
+            &position.owner,
+            &position.pool,
+            &position.custody,
+            position.side,
+            index,
+        )
+        .0;
+        let mut ixs = self.get_liquidate_instructions(
+            &new_position_key,
+            position,
+            custody,
+            collateral_custody,
+        );
+        ixs.insert(
+            ixs.len() - 1,
+            instructions::upgrade_position(
+                &self.payer.pubkey(),
+                &position.owner,
+                &position.pool,
+                &position.custody,
+                position.side,
+                index,
+            ),
+        );
+        self.backend.send_transaction(&ixs, &self.payer)
+    }
+
+    /// Returns current and legacy positions, legacy ones are flagged and
+    /// converted to the current layout
+    fn get_positions(&self) -> Result<Vec<(Pubkey, Position, bool)>> {
+        let mut positions = vec![];
+        // both layouts pad to the same size, they are told apart by address
+        for len in BTreeSet::from([Position::LEN, LegacyPosition::LEN]) {
+            let mut filters = vec![
+                RpcFilterType::DataSize(len as u64),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, Position::DISCRIMINATOR.to_vec())),
+            ];
+            if let Some(pool) = self.config.pool {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    POSITION_POOL_OFFSET,
+                    pool.to_bytes().to_vec(),
+                )));
+            }
+
+            for (key, account) in self
+                .backend
+                .get_program_accounts(&perpetuals::id(), filters)?
+            {
+                match deserialize_position(&key, &account.data) {
+                    Ok((position, legacy)) => positions.push((key, position, legacy)),
+                    Err(err) => warn!("Failed to deserialize position {key}: {err}"),
+                }
+            }
+        }
+        Ok(positions)
+    }
+
+    fn get_next_position_index(&self, owner: &Pubkey) -> Result<u64> {
+        let counter_key = pda::get_position_counter_pda(owner).0;
+        match self.backend.get_account(&counter_key)? {
+            Some(account) => {
+                Ok(accounts::deserialize::<PositionCounter>(&account.data)?.next_index)
+            }
+            None => Ok(0),
+        }
+    }
+
+    fn get_time(&self) -> Result<i64> {
+        let clock = self
+            .backend
+            .get_account(&sysvar::clock::ID)?
+            .ok_or_else(|| anyhow!("Clock sysvar not found"))?;
+        let clock: Clock =
+            account::from_account(&clock).ok_or_else(|| anyhow!("Invalid clock sysvar"))?;
+        Ok(clock.unix_timestamp)
+    }
+
+    fn get_account<T: AccountDeserialize>(&self, key: &Pubkey) -> Result<T> {
+        let account = self
+            .backend
+            .get_account(key)?
+            .ok_or_else(|| anyhow!("Account {key} not found"))?;
+        Ok(accounts::deserialize(&account.data)?)
+    }
+
+    fn get_multiple_accounts<T: AccountDeserialize>(
+        &self,
+        keys: impl Iterator<Item = Pubkey>,
+    ) -> Result<HashMap<Pubkey, T>> {
+        let keys: Vec<Pubkey> = keys.collect::<BTreeSet<_>>().into_iter().collect();
+        let mut accounts = HashMap::with_capacity(keys.len());
+        for (key, account) in keys.iter().zip(self.backend.get_multiple_accounts(&keys)?) {
+            if let Some(account) = account {
+                accounts.insert(*key, accounts::deserialize(&account.data)?);
+            }
+        }
+        Ok(accounts)
+    }
+}
+
+/// Evaluates the position the same way `get_liquidation_state` does on-chain,
+/// using the program's own oracle parsing and `Pool::check_leverage` math
+pub fn is_liquidatable(
+    pool: &Pool,
+    position: &Position,
+    custody: &Custody,
+    custody_oracle: (&Pubkey, &Account),
+    collateral_custody: &Custody,
+    collateral_custody_oracle: (&Pubkey, &Account),
+    curtime: i64,
+) -> Result<bool> {
+    let (token_price, token_ema_price) = get_oracle_prices(custody, custody_oracle, curtime)?;
+    let (collateral_token_price, collateral_token_ema_price) =
+        get_oracle_prices(collateral_custody, collateral_custody_oracle, curtime)?;
+
+    Ok(!pool.check_leverage(
+        position,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        curtime,
+        false,
+    )?)
+}
+
+fn get_oracle_prices(
+    custody: &Custody,
+    (key, account): (&Pubkey, &Account),
+    curtime: i64,
+) -> Result<(OraclePrice, OraclePrice)> {
+    let mut lamports = account.lamports;
+    let mut data = account.data.clone();
+    let oracle_account = AccountInfo::new(
+        key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &account.owner,
+        account.executable,
+        account.rent_epoch,
+    );
+
+    let price = OraclePrice::new_from_oracle(&oracle_account, &custody.oracle, curtime, false)?;
+    let ema_price = OraclePrice::new_from_oracle(
+        &oracle_account,
+        &custody.oracle,
+        curtime,
+        custody.pricing.use_ema,
+    )?;
+
+    Ok((price, ema_price))
+}
+
+/// Decodes a position account, legacy positions share the discriminator of the
+/// current layout and are recognized by their address
+fn deserialize_position(key: &Pubkey, data: &[u8]) -> Result<(Position, bool)> {
+    let position = accounts::deserialize::<Position>(data)?;
+    let address = Pubkey::create_program_address(
+        &[
+            b"position",
+            position.owner.as_ref(),
+            position.pool.as_ref(),
+            position.custody.as_ref(),
+            &[position.side as u8],
+            &position.index.to_le_bytes(),
+            &[position.bump],
+        ],
+        &perpetuals::id(),
+    );
+    if address.as_ref() == Ok(key) {
+        return Ok((position, false));
+    }
+
+    let legacy_position = LegacyPosition::try_deserialize_unchecked(&mut &data[..])?;
+    let legacy_address = Pubkey::create_program_address(
+        &[
+            b"position",
+            legacy_position.owner.as_ref(),
+            legacy_position.pool.as_ref(),
+            legacy_position.custody.as_ref(),
+            &[legacy_position.side as u8],
+            &[legacy_position.bump],
+        ],
+        &perpetuals::id(),
+    );
+    if legacy_address.as_ref() == Ok(key) {
+        return Ok((from_legacy_position(legacy_position), true));
+    }
+
+    Err(anyhow!("Position address doesn't match its seeds"))
+}
+
+fn from_legacy_position(position: LegacyPosition) -> Position {
+    Position {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        open_time: position.open_time,
+        update_time: position.update_time,
+        side: position.side,
+        price: position.price,
+        size_usd: position.size_usd,
+        borrow_size_usd: position.borrow_size_usd,
+        collateral_usd: position.collateral_usd,
+        unrealized_profit_usd: position.unrealized_profit_usd,
+        unrealized_loss_usd: position.unrealized_loss_usd,
+        cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+        locked_amount: position.locked_amount,
+        collateral_amount: position.collateral_amount,
+        index: 0,
+        bump: position.bump,
+    }
+}
@@ -0,0 +1,11 @@
+//! Liquidation keeper for the Solana Perpetuals program
+
+#![allow(clippy::result_large_err)]
+
+pub mod backend;
+pub mod keeper;
+
+pub use {
+    backend::Backend,
+    keeper::{Keeper, KeeperConfig, KeeperStats},
+};
@@ -0,0 +1,118 @@
+//! Liquidation keeper command line interface
+
+use {
+    anyhow::{anyhow, Result},
+    clap::{Arg, Command},
+    log::{error, info},
+    perpetuals_client::pda,
+    perpetuals_keeper::{Keeper, KeeperConfig},
+    solana_client::rpc_client::RpcClient,
+    solana_sdk::{commitment_config::CommitmentConfig, signer::keypair::read_keypair_file},
+    std::{thread, time::Duration},
+};
+
+// delay before retrying after a failed scan
+const ERROR_DELAY: Duration = Duration::from_secs(10);
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let matches = Command::new("perpetuals-keeper")
+        .about("Liquidates over-leveraged Solana Perpetuals positions")
+        .arg(
+            Arg::new("url")
+                .short('u')
+                .long("url")
+                .takes_value(true)
+                .default_value("https://api.devnet.solana.com")
+                .help("RPC endpoint, use http://localhost:8899 for a local validator"),
+        )
+        .arg(
+            Arg::new("keypair")
+                .short('k')
+                .long("keypair")
+                .takes_value(true)
+                .required(true)
+                .help("Keeper wallet, pays for transactions and receives liquidation rewards"),
+        )
+        .arg(
+            Arg::new("pool")
+                .long("pool")
+                .takes_value(true)
+                .help("Only scan positions of the given pool name"),
+        )
+        .arg(
+            Arg::new("priority-fee")
+                .long("priority-fee")
+                .takes_value(true)
+                .default_value("0")
+                .help("Priority fee in micro-lamports per compute unit"),
+        )
+        .arg(
+            Arg::new("compute-unit-limit")
+                .long("compute-unit-limit")
+                .takes_value(true)
+                .help("Compute unit limit of liquidation transactions"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .takes_value(true)
+                .default_value("5")
+                .help("Delay between scans in seconds"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Report liquidatable positions without sending transactions"),
+        )
+        .arg(
+            Arg::new("once")
+                .long("once")
+                .help("Exit after a single scan"),
+        )
+        .get_matches();
+
+    let url = matches.value_of("url").unwrap();
+    let keypair_path = matches.value_of("keypair").unwrap();
+    let payer = read_keypair_file(keypair_path)
+        .map_err(|err| anyhow!("Failed to read keypair {keypair_path}: {err}"))?;
+    let config = KeeperConfig {
+        pool: matches
+            .value_of("pool")
+            .map(|name| pda::get_pool_pda(name).0),
+        priority_fee: matches.value_of_t("priority-fee")?,
+        compute_unit_limit: matches
+            .is_present("compute-unit-limit")
+            .then(|| matches.value_of_t("compute-unit-limit"))
+            .transpose()?,
+        dry_run: matches.is_present("dry-run"),
+    };
+    let interval = Duration::from_secs(matches.value_of_t("interval")?);
+    let once = matches.is_present("once");
+
+    let rpc = RpcClient::new_with_commitment(url.to_string(), CommitmentConfig::confirmed());
+    let keeper = Keeper::new(rpc, payer, config);
+
+    loop {
+        match keeper.run_once() {
+            Ok(stats) => info!(
+                "Liquidated: {} / {} of {} positions",
+                stats.liquidated, stats.undercollateralized, stats.positions
+            ),
+            Err(err) if once => return Err(err),
+            Err(err) => {
+                error!(
+                    "Scan failed: {err}, retrying in {} sec",
+                    ERROR_DELAY.as_secs()
+                );
+                thread::sleep(ERROR_DELAY);
+                continue;
+            }
+        }
+        if once {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
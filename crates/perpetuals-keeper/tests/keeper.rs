@@ -0,0 +1,540 @@
+use {
+    anchor_lang::{AccountSerialize, AnchorSerialize, Discriminator},
+    anyhow::Result,
+    perpetuals::state::{
+        custody::{Custody, Fees, FeesMode, PricingParams},
+        oracle::{CustomOracle, OracleParams, OracleType},
+        perpetuals::{Permissions, Perpetuals},
+        pool::{Pool, TokenRatios},
+        position::{LegacyPosition, Position, Side},
+        position_counter::PositionCounter,
+    },
+    perpetuals_client::{accounts::deserialize, pda},
+    perpetuals_keeper::{keeper::is_liquidatable, Backend, Keeper, KeeperConfig, KeeperStats},
+    solana_client::rpc_filter::RpcFilterType,
+    solana_sdk::{
+        account::{Account, AccountSharedData},
+        clock::Clock,
+        compute_budget,
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::Signature,
+        signer::keypair::Keypair,
+        sysvar,
+    },
+    spl_associated_token_account::get_associated_token_address,
+    std::{cell::RefCell, collections::BTreeMap},
+};
+
+const CURTIME: i64 = 1_700_000_000;
+
+/// In-memory stand-in for a cluster, records sent transactions instead of executing them
+#[derive(Default)]
+struct TestBackend {
+    accounts: BTreeMap<Pubkey, Account>,
+    transactions: RefCell<Vec<Vec<Instruction>>>,
+}
+
+impl Backend for TestBackend {
+    fn get_account(&self, key: &Pubkey) -> Result<Option<Account>> {
+        Ok(self.accounts.get(key).cloned())
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.accounts.get(key).cloned())
+            .collect())
+    }
+
+    fn get_program_accounts(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(self
+            .accounts
+            .iter()
+            .filter(|(_, account)| {
+                let shared = AccountSharedData::from((*account).clone());
+                account.owner == *program_id && filters.iter().all(|filter| filter.allows(&shared))
+            })
+            .map(|(key, account)| (*key, account.clone()))
+            .collect())
+    }
+
+    fn send_transaction(
+        &self,
+        instructions: &[Instruction],
+        _payer: &Keypair,
+    ) -> Result<Signature> {
+        self.transactions.borrow_mut().push(instructions.to_vec());
+        Ok(Signature::default())
+    }
+}
+
+impl TestBackend {
+    fn add_account<T: AccountSerialize>(&mut self, key: Pubkey, value: &T, len: usize) {
+        let mut data = Vec::with_capacity(len);
+        value.try_serialize(&mut data).unwrap();
+        data.resize(data.len().max(len), 0);
+        self.accounts.insert(
+            key,
+            Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: perpetuals::id(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+}
+
+struct Fixture {
+    backend: TestBackend,
+    pool: Pubkey,
+    usdc_mint: Pubkey,
+    healthy_position: Pubkey,
+    long_position: Pubkey,
+    virtual_position: Pubkey,
+}
+
+fn scale(amount: u64, decimals: u8) -> u64 {
+    amount * 10u64.pow(decimals as u32)
+}
+
+fn add_custody(
+    backend: &mut TestBackend,
+    pool: &Pubkey,
+    decimals: u8,
+    is_stable: bool,
+    is_virtual: bool,
+    price: u64,
+    expo: i32,
+) -> (Pubkey, Pubkey) {
+    let mint = Pubkey::new_unique();
+    let custody_key = pda::get_custody_pda(pool, &mint).0;
+    let oracle_key = pda::get_custom_oracle_account_pda(pool, &mint).0;
+
+    let permissions = Permissions {
+        allow_close_position: true,
+        ..Permissions::default()
+    };
+    let custody = Custody {
+        pool: *pool,
+        mint,
+        token_account: pda::get_custody_token_account_pda(pool, &mint).0,
+        token_program: anchor_spl::token::ID,
+        decimals,
+        is_stable,
+        is_virtual,
+        oracle: OracleParams {
+            oracle_account: oracle_key,
+            oracle_type: OracleType::Custom,
+            oracle_authority: Pubkey::default(),
+            max_price_error: 100,
+            max_price_age_sec: 60,
+        },
+        pricing: PricingParams {
+            use_ema: false,
+            trade_spread_long: 100,
+            trade_spread_short: 100,
+            min_initial_leverage: 10_000,
+            max_initial_leverage: 100_000,
+            max_leverage: 100_000,
+            max_payoff_mult: 10_000,
+            ..PricingParams::default()
+        },
+        permissions,
+        fees: Fees {
+            mode: FeesMode::Linear,
+            ratio_mult: 20_000,
+            utilization_mult: 20_000,
+            close_position: 100,
+            liquidation: 50,
+            protocol_share: 25,
+            ..Fees::default()
+        },
+        ..Custody::default()
+    };
+    backend.add_account(custody_key, &custody, Custody::LEN);
+
+    let oracle = CustomOracle {
+        price,
+        expo,
+        conf: 0,
+        ema: price,
+        publish_time: CURTIME,
+    };
+    backend.add_account(oracle_key, &oracle, CustomOracle::LEN);
+
+    (mint, custody_key)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_position(
+    backend: &mut TestBackend,
+    pool: &Pubkey,
+    custody: &Pubkey,
+    collateral_custody: &Pubkey,
+    side: Side,
+    price: u64,
+    size_usd: u64,
+    collateral_usd: u64,
+) -> Pubkey {
+    let owner = Pubkey::new_unique();
+    let (key, bump) = pda::get_position_pda(&owner, pool, custody, side, 0);
+    let position = Position {
+        owner,
+        pool: *pool,
+        custody: *custody,
+        collateral_custody: *collateral_custody,
+        side,
+        price: scale(price, Perpetuals::PRICE_DECIMALS),
+        size_usd: scale(size_usd, Perpetuals::USD_DECIMALS),
+        collateral_usd: scale(collateral_usd, Perpetuals::USD_DECIMALS),
+        bump,
+        ..Position::default()
+    };
+    backend.add_account(key, &position, Position::LEN);
+    key
+}
+
+fn get_fixture() -> Fixture {
+    let mut backend = TestBackend::default();
+
+    let perpetuals = Perpetuals {
+        permissions: Permissions {
+            allow_close_position: true,
+            ..Permissions::default()
+        },
+        ..Perpetuals::default()
+    };
+    backend.add_account(pda::get_perpetuals_pda().0, &perpetuals, Perpetuals::LEN);
+
+    backend.accounts.insert(
+        sysvar::clock::ID,
+        Account::new_data(
+            1,
+            &Clock {
+                unix_timestamp: CURTIME,
+                ..Clock::default()
+            },
+            &sysvar::ID,
+        )
+        .unwrap(),
+    );
+
+    let pool = pda::get_pool_pda("TestPool1").0;
+    let (_, sol_custody) = add_custody(&mut backend, &pool, 9, false, false, 25_000_000, -3);
+    let (usdc_mint, usdc_custody) = add_custody(&mut backend, &pool, 6, true, false, 1_000_000, -6);
+    let (_, btc_custody) = add_custody(&mut backend, &pool, 8, false, true, 30_000_000, -3);
+
+    let ratios = TokenRatios {
+        target: 3_333,
+        min: 0,
+        max: 10_000,
+    };
+    let pool_account = Pool {
+        name: "TestPool1".to_string(),
+        custodies: vec![sol_custody, usdc_custody, btc_custody],
+        ratios: vec![ratios; 3],
+        ..Pool::default()
+    };
+    backend.add_account(pool, &pool_account, Pool::LEN);
+
+    // x4 leverage at the current price
+    let healthy_position = add_position(
+        &mut backend,
+        &pool,
+        &sol_custody,
+        &sol_custody,
+        Side::Long,
+        25_000,
+        100_000,
+        25_000,
+    );
+    // price dropped from 30k to 25k, loss exceeds collateral
+    let long_position = add_position(
+        &mut backend,
+        &pool,
+        &sol_custody,
+        &sol_custody,
+        Side::Long,
+        30_000,
+        100_000,
+        10_000,
+    );
+    // short synthetic asset collateralized with stablecoin, price went up from 20k to 30k
+    let virtual_position = add_position(
+        &mut backend,
+        &pool,
+        &btc_custody,
+        &usdc_custody,
+        Side::Short,
+        20_000,
+        100_000,
+        10_000,
+    );
+
+    Fixture {
+        backend,
+        pool,
+        usdc_mint,
+        healthy_position,
+        long_position,
+        virtual_position,
+    }
+}
+
+fn get_liquidated_positions(keeper: &Keeper<TestBackend>) -> Vec<(Pubkey, Pubkey)> {
+    keeper
+        .backend()
+        .transactions
+        .borrow()
+        .iter()
+        .map(|ixs| {
+            let liquidate = ixs.last().unwrap();
+            assert_eq!(liquidate.program_id, perpetuals::id());
+            assert_eq!(
+                liquidate.data[..8],
+                perpetuals::instruction::Liquidate::DISCRIMINATOR
+            );
+            // position and owner's receiving token account
+            (liquidate.accounts[6].pubkey, liquidate.accounts[1].pubkey)
+        })
+        .collect()
+}
+
+#[test]
+fn test_liquidate() {
+    let fixture = get_fixture();
+    let payer = Keypair::new();
+    let keeper = Keeper::new(fixture.backend, payer, KeeperConfig::default());
+
+    assert_eq!(
+        keeper.run_once().unwrap(),
+        KeeperStats {
+            positions: 3,
+            undercollateralized: 2,
+            liquidated: 2,
+        }
+    );
+
+    let liquidated = get_liquidated_positions(&keeper);
+    assert_eq!(liquidated.len(), 2);
+    assert!(liquidated
+        .iter()
+        .all(|(position, _)| *position != fixture.healthy_position));
+    assert!(liquidated
+        .iter()
+        .any(|(position, _)| *position == fixture.long_position));
+
+    // collateral of the virtual custody position is returned in stablecoins
+    let (_, receiving_account) = liquidated
+        .iter()
+        .find(|(position, _)| *position == fixture.virtual_position)
+        .unwrap();
+    let virtual_position: Position =
+        deserialize(&keeper.backend().accounts[&fixture.virtual_position].data).unwrap();
+    assert_eq!(
+        *receiving_account,
+        get_associated_token_address(&virtual_position.owner, &fixture.usdc_mint)
+    );
+}
+
+#[test]
+fn test_legacy_position() {
+    let mut fixture = get_fixture();
+    let long_position: Position =
+        deserialize(&fixture.backend.accounts[&fixture.long_position].data).unwrap();
+
+    // same position opened before per-owner indexes were added, the owner has
+    // opened two indexed positions since
+    let owner = Pubkey::new_unique();
+    let (legacy_key, legacy_bump) = pda::get_legacy_position_pda(
+        &owner,
+        &long_position.pool,
+        &long_position.custody,
+        long_position.side,
+    );
+    let legacy_position = LegacyPosition {
+        owner,
+        pool: long_position.pool,
+        custody: long_position.custody,
+        collateral_custody: long_position.collateral_custody,
+        side: long_position.side,
+        price: long_position.price,
+        size_usd: long_position.size_usd,
+        collateral_usd: long_position.collateral_usd,
+        bump: legacy_bump,
+        ..LegacyPosition::default()
+    };
+    let mut data = Position::DISCRIMINATOR.to_vec();
+    legacy_position.serialize(&mut data).unwrap();
+    data.resize(LegacyPosition::LEN, 0);
+    fixture.backend.accounts.insert(
+        legacy_key,
+        Account {
+            lamports: 1_000_000_000,
+            data,
+            owner: perpetuals::id(),
+            executable: false,
+            rent_epoch: 0,
+        },
+    );
+    let counter = PositionCounter {
+        owner,
+        next_index: 2,
+        bump: pda::get_position_counter_pda(&owner).1,
+    };
+    fixture.backend.add_account(
+        pda::get_position_counter_pda(&owner).0,
+        &counter,
+        PositionCounter::LEN,
+    );
+
+    let keeper = Keeper::new(fixture.backend, Keypair::new(), KeeperConfig::default());
+    assert_eq!(
+        keeper.run_once().unwrap(),
+        KeeperStats {
+            positions: 4,
+            undercollateralized: 3,
+            liquidated: 3,
+        }
+    );
+
+    // legacy position is upgraded to the next index and liquidated in the same transaction
+    let new_position_key = pda::get_position_pda(
+        &owner,
+        &long_position.pool,
+        &long_position.custody,
+        long_position.side,
+        2,
+    )
+    .0;
+    assert!(get_liquidated_positions(&keeper)
+        .iter()
+        .any(|(position, _)| *position == new_position_key));
+
+    let transactions = keeper.backend().transactions.borrow();
+    let ixs = transactions
+        .iter()
+        .find(|ixs| ixs.last().unwrap().accounts[6].pubkey == new_position_key)
+        .unwrap();
+    let upgrade = &ixs[ixs.len() - 2];
+    assert_eq!(
+        upgrade.data[..8],
+        perpetuals::instruction::UpgradePosition::DISCRIMINATOR
+    );
+    assert!(upgrade
+        .accounts
+        .iter()
+        .any(|account| account.pubkey == legacy_key));
+}
+
+#[test]
+fn test_dry_run() {
+    let fixture = get_fixture();
+    let keeper = Keeper::new(
+        fixture.backend,
+        Keypair::new(),
+        KeeperConfig {
+            dry_run: true,
+            ..KeeperConfig::default()
+        },
+    );
+
+    assert_eq!(
+        keeper.run_once().unwrap(),
+        KeeperStats {
+            positions: 3,
+            undercollateralized: 2,
+            liquidated: 0,
+        }
+    );
+    assert!(keeper.backend().transactions.borrow().is_empty());
+}
+
+#[test]
+fn test_pool_filter() {
+    let keeper = Keeper::new(
+        get_fixture().backend,
+        Keypair::new(),
+        KeeperConfig {
+            pool: Some(pda::get_pool_pda("TestPool2").0),
+            ..KeeperConfig::default()
+        },
+    );
+    assert_eq!(keeper.run_once().unwrap(), KeeperStats::default());
+
+    let fixture = get_fixture();
+    let keeper = Keeper::new(
+        fixture.backend,
+        Keypair::new(),
+        KeeperConfig {
+            pool: Some(fixture.pool),
+            ..KeeperConfig::default()
+        },
+    );
+    assert_eq!(keeper.run_once().unwrap().positions, 3);
+}
+
+#[test]
+fn test_priority_fee() {
+    let keeper = Keeper::new(
+        get_fixture().backend,
+        Keypair::new(),
+        KeeperConfig {
+            priority_fee: 10_000,
+            compute_unit_limit: Some(200_000),
+            ..KeeperConfig::default()
+        },
+    );
+    assert_eq!(keeper.run_once().unwrap().liquidated, 2);
+
+    for ixs in keeper.backend().transactions.borrow().iter() {
+        assert_eq!(ixs[0].program_id, compute_budget::id());
+        assert_eq!(ixs[1].program_id, compute_budget::id());
+    }
+}
+
+#[test]
+fn test_is_liquidatable() {
+    let fixture = get_fixture();
+    let accounts = &fixture.backend.accounts;
+    let load = |key: &Pubkey| &accounts[key];
+
+    for (position_key, expected) in [
+        (fixture.healthy_position, false),
+        (fixture.long_position, true),
+        (fixture.virtual_position, true),
+    ] {
+        let position: Position = deserialize(&load(&position_key).data).unwrap();
+        let pool: Pool = deserialize(&load(&position.pool).data).unwrap();
+        let custody: Custody = deserialize(&load(&position.custody).data).unwrap();
+        let collateral_custody: Custody =
+            deserialize(&load(&position.collateral_custody).data).unwrap();
+
+        assert_eq!(
+            is_liquidatable(
+                &pool,
+                &position,
+                &custody,
+                (
+                    &custody.oracle.oracle_account,
+                    load(&custody.oracle.oracle_account)
+                ),
+                &collateral_custody,
+                (
+                    &collateral_custody.oracle.oracle_account,
+                    load(&collateral_custody.oracle.oracle_account)
+                ),
+                CURTIME,
+            )
+            .unwrap(),
+            expected
+        );
+    }
+}
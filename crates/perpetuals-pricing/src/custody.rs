@@ -0,0 +1,221 @@
+//! Custody state used for pricing, fees and borrow rate calculations
+
+use crate::{
+    error::Result,
+    math,
+    position::{Position, Side},
+    BPS_POWER, RATE_POWER,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum FeesMode {
+    Fixed,
+    #[default]
+    Linear,
+    Optimal,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Fees {
+    pub mode: FeesMode,
+    // fees have implied BPS_DECIMALS decimals
+    pub ratio_mult: u64,
+    pub utilization_mult: u64,
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub stable_swap_in: u64,
+    pub stable_swap_out: u64,
+    pub add_liquidity: u64,
+    pub remove_liquidity: u64,
+    pub open_position: u64,
+    pub close_position: u64,
+    pub liquidation: u64,
+    pub protocol_share: u64,
+    // configs for optimal fee mode
+    pub fee_max: u64,
+    pub fee_optimal: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct TradeStats {
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    // open interest
+    pub oi_long_usd: u64,
+    pub oi_short_usd: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Assets {
+    // collateral debt
+    pub collateral: u64,
+    // protocol_fees are part of the collected fees that is reserved for the protocol
+    pub protocol_fees: u64,
+    // owned = total_assets - collateral + collected_fees - protocol_fees
+    pub owned: u64,
+    // locked funds for pnl payoff
+    pub locked: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct PricingParams {
+    pub use_ema: bool,
+    // whether to account for unrealized pnl in assets under management calculations
+    pub use_unrealized_pnl_in_aum: bool,
+    // pricing params have implied BPS_DECIMALS decimals (except ended with _usd)
+    pub trade_spread_long: u64,
+    pub trade_spread_short: u64,
+    pub swap_spread: u64,
+    pub min_initial_leverage: u64,
+    pub max_initial_leverage: u64,
+    pub max_leverage: u64,
+    // max_user_profit = position_size * max_payoff_mult
+    pub max_payoff_mult: u64,
+    pub max_utilization: u64,
+    // USD denominated values always have implied USD_DECIMALS decimals
+    pub max_position_locked_usd: u64,
+    pub max_total_locked_usd: u64,
+    // price impact for a trade that moves open interest skew by the full custody depth
+    pub price_impact_mult: u64,
+    pub max_price_impact: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct BorrowRateParams {
+    // borrow rate params have implied RATE_DECIMALS decimals
+    pub base_rate: u64,
+    pub slope1: u64,
+    pub slope2: u64,
+    pub optimal_utilization: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct BorrowRateState {
+    // borrow rates have implied RATE_DECIMALS decimals
+    pub current_rate: u64,
+    pub cumulative_interest: u128,
+    pub last_update: i64,
+}
+
+/// Subset of the on-chain custody account that affects pricing
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Custody {
+    pub decimals: u8,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub pricing: PricingParams,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+    pub assets: Assets,
+    pub trade_stats: TradeStats,
+    pub borrow_rate_state: BorrowRateState,
+}
+
+impl Custody {
+    pub fn get_locked_amount(&self, size: u64, side: Side) -> Result<u64> {
+        let max_payoff_mult = if side == Side::Short {
+            core::cmp::min(BPS_POWER, self.pricing.max_payoff_mult as u128)
+        } else {
+            self.pricing.max_payoff_mult as u128
+        };
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(size as u128, max_payoff_mult)?,
+            BPS_POWER,
+        )?)
+    }
+
+    pub fn get_interest_amount_usd(&self, position: &Position, curtime: i64) -> Result<u64> {
+        if position.borrow_size_usd == 0 || self.is_virtual {
+            return Ok(0);
+        }
+
+        let cumulative_interest = self.get_cumulative_interest(curtime)?;
+
+        let position_interest = if cumulative_interest > position.cumulative_interest_snapshot {
+            math::checked_sub(cumulative_interest, position.cumulative_interest_snapshot)?
+        } else {
+            return Ok(0);
+        };
+
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(position_interest, position.borrow_size_usd as u128)?,
+            RATE_POWER,
+        )?)
+    }
+
+    pub fn get_cumulative_interest(&self, curtime: i64) -> Result<u128> {
+        if curtime > self.borrow_rate_state.last_update {
+            let cumulative_interest = math::checked_ceil_div(
+                math::checked_mul(
+                    math::checked_sub(curtime, self.borrow_rate_state.last_update)? as u128,
+                    self.borrow_rate_state.current_rate as u128,
+                )?,
+                3600,
+            )?;
+            math::checked_add(
+                self.borrow_rate_state.cumulative_interest,
+                cumulative_interest,
+            )
+        } else {
+            Ok(self.borrow_rate_state.cumulative_interest)
+        }
+    }
+
+    pub fn update_borrow_rate(&mut self, curtime: i64) -> Result<()> {
+        // if current_utilization < optimal_utilization:
+        //   rate = base_rate + (current_utilization / optimal_utilization) * slope1
+        // else:
+        //   rate = base_rate + slope1 + (current_utilization - optimal_utilization) / (1 - optimal_utilization) * slope2
+
+        if self.assets.owned == 0 {
+            self.borrow_rate_state.current_rate = 0;
+            self.borrow_rate_state.last_update =
+                core::cmp::max(curtime, self.borrow_rate_state.last_update);
+            return Ok(());
+        }
+
+        if curtime > self.borrow_rate_state.last_update {
+            // compute interest accumulated since previous update
+            self.borrow_rate_state.cumulative_interest = self.get_cumulative_interest(curtime)?;
+            self.borrow_rate_state.last_update = curtime;
+        }
+
+        // get current utilization
+        let current_utilization = math::checked_div(
+            math::checked_mul(self.assets.locked as u128, RATE_POWER)?,
+            self.assets.owned as u128,
+        )?;
+
+        // compute and save new borrow rate
+        let hourly_rate = if current_utilization < (self.borrow_rate.optimal_utilization as u128)
+            || (self.borrow_rate.optimal_utilization as u128) >= RATE_POWER
+        {
+            math::checked_div(
+                math::checked_mul(current_utilization, self.borrow_rate.slope1 as u128)?,
+                self.borrow_rate.optimal_utilization as u128,
+            )?
+        } else {
+            math::checked_add(
+                self.borrow_rate.slope1 as u128,
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_sub(
+                            current_utilization,
+                            self.borrow_rate.optimal_utilization as u128,
+                        )?,
+                        self.borrow_rate.slope2 as u128,
+                    )?,
+                    RATE_POWER - self.borrow_rate.optimal_utilization as u128,
+                )?,
+            )?
+        };
+        let hourly_rate = math::checked_add(
+            math::checked_as_u64(hourly_rate)?,
+            self.borrow_rate.base_rate,
+        )?;
+
+        self.borrow_rate_state.current_rate = hourly_rate;
+
+        Ok(())
+    }
+}
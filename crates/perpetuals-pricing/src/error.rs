@@ -0,0 +1,26 @@
+//! Error types
+
+use core::fmt;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PricingError {
+    MathOverflow,
+    InvalidArgument,
+    MaxPriceSlippage,
+    TokenRatioOutOfRange,
+    InstructionNotAllowed,
+}
+
+pub type Result<T> = core::result::Result<T, PricingError>;
+
+impl fmt::Display for PricingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MathOverflow => "Overflow in arithmetic operation",
+            Self::InvalidArgument => "Invalid argument",
+            Self::MaxPriceSlippage => "Price slippage limit exceeded",
+            Self::TokenRatioOutOfRange => "Token ratio out of range",
+            Self::InstructionNotAllowed => "Instruction is not allowed at this time",
+        })
+    }
+}
@@ -0,0 +1,37 @@
+//! Pricing engine of the Solana Perpetuals Exchange.
+//!
+//! Pure integer math behind entry/exit prices, fees, PnL, liquidation prices,
+//! swaps and borrow rates. The on-chain program delegates to this crate, so
+//! quotes computed off-chain are bit-identical to the ones the program would
+//! produce for the same pool, custody and oracle state.
+//!
+//! The crate is `no_std` and only requires `alloc`.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod custody;
+pub mod error;
+pub mod math;
+pub mod oracle;
+pub mod pool;
+pub mod position;
+
+pub use {
+    custody::{
+        Assets, BorrowRateParams, BorrowRateState, Custody, Fees, FeesMode, PricingParams,
+        TradeStats,
+    },
+    error::{PricingError, Result},
+    oracle::OraclePrice,
+    pool::{FeeTier, Pool, TokenRatios},
+    position::{Position, Side},
+};
+
+pub const BPS_DECIMALS: u8 = 4;
+pub const BPS_POWER: u128 = 10u64.pow(BPS_DECIMALS as u32) as u128;
+pub const PRICE_DECIMALS: u8 = 6;
+pub const USD_DECIMALS: u8 = 6;
+pub const RATE_DECIMALS: u8 = 9;
+pub const RATE_POWER: u128 = 10u64.pow(RATE_DECIMALS as u32) as u128;
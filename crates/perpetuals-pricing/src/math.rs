@@ -0,0 +1,270 @@
+//! Checked integer math routines.
+
+use crate::error::{PricingError, Result};
+
+pub fn checked_add<T>(arg1: T, arg2: T) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    arg1.checked_add(&arg2).ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_sub<T>(arg1: T, arg2: T) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    arg1.checked_sub(&arg2).ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_mul<T>(arg1: T, arg2: T) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    arg1.checked_mul(&arg2).ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_div<T>(arg1: T, arg2: T) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    arg1.checked_div(&arg2).ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_ceil_div<T>(arg1: T, arg2: T) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    if arg1 > T::zero() {
+        if arg1 == arg2 && arg2 != T::zero() {
+            return Ok(T::one());
+        }
+        (arg1 - T::one())
+            .checked_div(&arg2)
+            .map(|res| res + T::one())
+            .ok_or(PricingError::MathOverflow)
+    } else {
+        arg1.checked_div(&arg2).ok_or(PricingError::MathOverflow)
+    }
+}
+
+pub fn checked_pow<T>(arg: T, exp: usize) -> Result<T>
+where
+    T: num_traits::PrimInt,
+{
+    num_traits::checked_pow(arg, exp).ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_as_u64<T>(arg: T) -> Result<u64>
+where
+    T: num_traits::ToPrimitive,
+{
+    arg.to_u64().ok_or(PricingError::MathOverflow)
+}
+
+pub fn checked_decimal_div(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+) -> Result<u64> {
+    checked_decimal_div_impl(
+        coefficient1,
+        exponent1,
+        coefficient2,
+        exponent2,
+        target_exponent,
+        false,
+    )
+}
+
+pub fn checked_decimal_ceil_div(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+) -> Result<u64> {
+    checked_decimal_div_impl(
+        coefficient1,
+        exponent1,
+        coefficient2,
+        exponent2,
+        target_exponent,
+        true,
+    )
+}
+
+pub fn checked_decimal_mul(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+) -> Result<u64> {
+    checked_decimal_mul_impl(
+        coefficient1,
+        exponent1,
+        coefficient2,
+        exponent2,
+        target_exponent,
+        false,
+    )
+}
+
+pub fn checked_decimal_ceil_mul(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+) -> Result<u64> {
+    checked_decimal_mul_impl(
+        coefficient1,
+        exponent1,
+        coefficient2,
+        exponent2,
+        target_exponent,
+        true,
+    )
+}
+
+pub fn scale_to_exponent(arg: u64, exponent: i32, target_exponent: i32) -> Result<u64> {
+    if target_exponent == exponent {
+        return Ok(arg);
+    }
+    let delta = checked_sub(target_exponent, exponent)?;
+    if delta > 0 {
+        checked_div(arg, checked_pow(10, delta as usize)?)
+    } else {
+        checked_mul(arg, checked_pow(10, (-delta) as usize)?)
+    }
+}
+
+// private helpers
+fn checked_decimal_div_impl(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+    round_up: bool,
+) -> Result<u64> {
+    if coefficient2 == 0 {
+        return Err(PricingError::MathOverflow);
+    }
+    if coefficient1 == 0 {
+        return Ok(0);
+    }
+    // compute scale factor for the dividend
+    let mut scale_factor = 0;
+    let mut target_power = checked_sub(checked_sub(exponent1, exponent2)?, target_exponent)?;
+    if exponent1 > 0 {
+        scale_factor = checked_add(scale_factor, exponent1)?;
+    }
+    if exponent2 < 0 {
+        scale_factor = checked_sub(scale_factor, exponent2)?;
+        target_power = checked_add(target_power, exponent2)?;
+    }
+    if target_exponent < 0 {
+        scale_factor = checked_sub(scale_factor, target_exponent)?;
+        target_power = checked_add(target_power, target_exponent)?;
+    }
+    let scaled_coeff1 = if scale_factor > 0 {
+        checked_mul(
+            coefficient1 as u128,
+            checked_pow(10u128, scale_factor as usize)?,
+        )?
+    } else {
+        coefficient1 as u128
+    };
+    let quotient = if round_up {
+        checked_ceil_div(scaled_coeff1, coefficient2 as u128)?
+    } else {
+        checked_div(scaled_coeff1, coefficient2 as u128)?
+    };
+
+    if target_power >= 0 {
+        checked_as_u64(checked_mul(
+            quotient,
+            checked_pow(10u128, target_power as usize)?,
+        )?)
+    } else {
+        checked_as_u64(checked_div(
+            quotient,
+            checked_pow(10u128, (-target_power) as usize)?,
+        )?)
+    }
+}
+
+fn checked_decimal_mul_impl(
+    coefficient1: u64,
+    exponent1: i32,
+    coefficient2: u64,
+    exponent2: i32,
+    target_exponent: i32,
+    round_up: bool,
+) -> Result<u64> {
+    if coefficient1 == 0 || coefficient2 == 0 {
+        return Ok(0);
+    }
+    let target_power = checked_sub(checked_add(exponent1, exponent2)?, target_exponent)?;
+    let product = checked_mul(coefficient1 as u128, coefficient2 as u128)?;
+    if target_power >= 0 {
+        checked_as_u64(checked_mul(
+            product,
+            checked_pow(10u128, target_power as usize)?,
+        )?)
+    } else if round_up {
+        checked_as_u64(checked_ceil_div(
+            product,
+            checked_pow(10u128, (-target_power) as usize)?,
+        )?)
+    } else {
+        checked_as_u64(checked_div(
+            product,
+            checked_pow(10u128, (-target_power) as usize)?,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checked_decimal_div() {
+        assert_eq!(
+            2_000_000,
+            checked_decimal_div(1_000, -6, 500, -6, -6).unwrap()
+        );
+        assert_eq!(
+            2_000_000_000,
+            checked_decimal_div(1_000, -6, 500, -9, -6).unwrap()
+        );
+        assert_eq!(2_000, checked_decimal_div(1_000, -9, 500, -6, -6).unwrap());
+        assert_eq!(
+            1_000_000,
+            checked_decimal_div(u64::MAX, -6, u64::MAX, -6, -6).unwrap()
+        );
+        assert_eq!(0, checked_decimal_div(0, -6, u64::MAX, -6, -6).unwrap());
+
+        assert_eq!(
+            Err(PricingError::MathOverflow),
+            checked_decimal_div(1_000_000, -6, 0, -6, -6)
+        );
+        assert_eq!(
+            Err(PricingError::MathOverflow),
+            checked_decimal_div(u64::MAX, -6, 1, -6, -6)
+        );
+    }
+
+    #[test]
+    fn test_checked_decimal_rounding() {
+        assert_eq!(333_333, checked_decimal_div(1, 0, 3, 0, -6).unwrap());
+        assert_eq!(333_334, checked_decimal_ceil_div(1, 0, 3, 0, -6).unwrap());
+
+        assert_eq!(1, checked_decimal_mul(15, -1, 1, 0, 0).unwrap());
+        assert_eq!(2, checked_decimal_ceil_mul(15, -1, 1, 0, 0).unwrap());
+    }
+}
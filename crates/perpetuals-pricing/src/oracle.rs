@@ -0,0 +1,183 @@
+//! Oracle price representation and arithmetic
+
+use {
+    crate::{error::Result, math, USD_DECIMALS},
+    core::cmp::Ordering,
+};
+
+const ORACLE_EXPONENT_SCALE: i32 = -9;
+const ORACLE_PRICE_SCALE: u64 = 1_000_000_000;
+const ORACLE_MAX_PRICE: u64 = (1 << 28) - 1;
+
+#[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
+pub struct OraclePrice {
+    pub price: u64,
+    pub exponent: i32,
+}
+
+impl PartialOrd for OraclePrice {
+    fn partial_cmp(&self, other: &OraclePrice) -> Option<Ordering> {
+        let (lhs, rhs) = if self.exponent == other.exponent {
+            (self.price, other.price)
+        } else if self.exponent < other.exponent {
+            if let Ok(scaled_price) = other.scale_to_exponent(self.exponent) {
+                (self.price, scaled_price.price)
+            } else {
+                return None;
+            }
+        } else if let Ok(scaled_price) = self.scale_to_exponent(other.exponent) {
+            (scaled_price.price, other.price)
+        } else {
+            return None;
+        };
+        lhs.partial_cmp(&rhs)
+    }
+}
+
+impl OraclePrice {
+    pub fn new(price: u64, exponent: i32) -> Self {
+        Self { price, exponent }
+    }
+
+    pub fn new_from_token(amount_and_decimals: (u64, u8)) -> Self {
+        Self {
+            price: amount_and_decimals.0,
+            exponent: -(amount_and_decimals.1 as i32),
+        }
+    }
+
+    /// Returns 1 USD reference price
+    pub fn one_usd() -> Self {
+        Self {
+            price: 10u64.pow(USD_DECIMALS as u32),
+            exponent: -(USD_DECIMALS as i32),
+        }
+    }
+
+    // Converts token amount to USD with implied USD_DECIMALS decimals using oracle price
+    pub fn get_asset_amount_usd(&self, token_amount: u64, token_decimals: u8) -> Result<u64> {
+        if token_amount == 0 || self.price == 0 {
+            return Ok(0);
+        }
+        math::checked_decimal_mul(
+            token_amount,
+            -(token_decimals as i32),
+            self.price,
+            self.exponent,
+            -(USD_DECIMALS as i32),
+        )
+    }
+
+    // Converts USD amount with implied USD_DECIMALS decimals to token amount
+    pub fn get_token_amount(&self, asset_amount_usd: u64, token_decimals: u8) -> Result<u64> {
+        if asset_amount_usd == 0 || self.price == 0 {
+            return Ok(0);
+        }
+        math::checked_decimal_div(
+            asset_amount_usd,
+            -(USD_DECIMALS as i32),
+            self.price,
+            self.exponent,
+            -(token_decimals as i32),
+        )
+    }
+
+    /// Returns price with mantissa normalized to be less than ORACLE_MAX_PRICE
+    pub fn normalize(&self) -> Result<OraclePrice> {
+        let mut p = self.price;
+        let mut e = self.exponent;
+
+        while p > ORACLE_MAX_PRICE {
+            p = math::checked_div(p, 10)?;
+            e = math::checked_add(e, 1)?;
+        }
+
+        Ok(OraclePrice {
+            price: p,
+            exponent: e,
+        })
+    }
+
+    pub fn checked_div(&self, other: &OraclePrice) -> Result<OraclePrice> {
+        let base = self.normalize()?;
+        let other = other.normalize()?;
+
+        Ok(OraclePrice {
+            price: math::checked_div(
+                math::checked_mul(base.price, ORACLE_PRICE_SCALE)?,
+                other.price,
+            )?,
+            exponent: math::checked_sub(
+                math::checked_add(base.exponent, ORACLE_EXPONENT_SCALE)?,
+                other.exponent,
+            )?,
+        })
+    }
+
+    pub fn checked_mul(&self, other: &OraclePrice) -> Result<OraclePrice> {
+        Ok(OraclePrice {
+            price: math::checked_mul(self.price, other.price)?,
+            exponent: math::checked_add(self.exponent, other.exponent)?,
+        })
+    }
+
+    pub fn scale_to_exponent(&self, target_exponent: i32) -> Result<OraclePrice> {
+        Ok(OraclePrice {
+            price: math::scale_to_exponent(self.price, self.exponent, target_exponent)?,
+            exponent: target_exponent,
+        })
+    }
+
+    pub fn get_min_price(&self, other: &OraclePrice, is_stable: bool) -> Result<OraclePrice> {
+        let min_price = if self < other { self } else { other };
+        if is_stable {
+            if min_price.exponent > 0 {
+                if min_price.price == 0 {
+                    return Ok(*min_price);
+                } else {
+                    return Ok(OraclePrice {
+                        price: 1000000u64,
+                        exponent: -6,
+                    });
+                }
+            }
+            let one_usd = math::checked_pow(10u64, (-min_price.exponent) as usize)?;
+            if min_price.price > one_usd {
+                Ok(OraclePrice {
+                    price: one_usd,
+                    exponent: min_price.exponent,
+                })
+            } else {
+                Ok(*min_price)
+            }
+        } else {
+            Ok(*min_price)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scale_to_exponent() {
+        let price = OraclePrice::new(12300, -3);
+        assert_eq!(
+            OraclePrice::new(12300000, -6),
+            price.scale_to_exponent(-6).unwrap()
+        );
+        assert_eq!(
+            OraclePrice::new(123, -1),
+            price.scale_to_exponent(-1).unwrap()
+        );
+        assert_eq!(OraclePrice::new(1, 1), price.scale_to_exponent(1).unwrap());
+    }
+
+    #[test]
+    fn test_compare() {
+        assert!(OraclePrice::new(12300, -3) < OraclePrice::new(12301, -3));
+        assert!(OraclePrice::new(12300, -3) == OraclePrice::new(12300, -3));
+        assert!(OraclePrice::new(12300, -3) > OraclePrice::new(12299000, -6));
+    }
+}
@@ -0,0 +1,1150 @@
+//! Pool pricing, fees and PnL routines
+
+use {
+    crate::{
+        custody::{Custody, FeesMode},
+        error::{PricingError, Result},
+        math,
+        oracle::OraclePrice,
+        position::{Position, Side},
+        BPS_DECIMALS, BPS_POWER, PRICE_DECIMALS, RATE_POWER, USD_DECIMALS,
+    },
+    alloc::vec::Vec,
+    core::cmp::Ordering,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct TokenRatios {
+    pub target: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct FeeTier {
+    // trader's rolling volume required to qualify, implied USD_DECIMALS decimals
+    pub min_volume_usd: u64,
+    // open/close fee discount, implied BPS_DECIMALS decimals
+    pub discount: u64,
+}
+
+/// Subset of the on-chain pool account that affects pricing
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct Pool {
+    pub ratios: Vec<TokenRatios>,
+    pub aum_usd: u128,
+    // volume-based fee tiers, sorted by min_volume_usd
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+/// All returned prices are scaled to PRICE_DECIMALS.
+/// All returned amounts are scaled to corresponding custody decimals.
+///
+impl Pool {
+    pub fn get_entry_price(
+        &self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        side: Side,
+        size: u64,
+        custody: &Custody,
+    ) -> Result<u64> {
+        let size_usd = token_price.get_asset_amount_usd(size, custody.decimals)?;
+        let price_impact = self.get_price_impact(token_price, side, size_usd, true, custody)?;
+
+        let price = self.get_price(
+            token_price,
+            token_ema_price,
+            side,
+            Self::get_spread_with_impact(
+                if side == Side::Long {
+                    custody.pricing.trade_spread_long
+                } else {
+                    custody.pricing.trade_spread_short
+                },
+                price_impact,
+            )?,
+        )?;
+        if price.price == 0 {
+            return Err(PricingError::MaxPriceSlippage);
+        }
+
+        Ok(price.scale_to_exponent(-(PRICE_DECIMALS as i32))?.price)
+    }
+
+    /// Returns open/close fee discount for the given trader's rolling volume
+    pub fn get_fee_discount(&self, volume_usd: u64) -> u64 {
+        self.fee_tiers
+            .iter()
+            .rev()
+            .find(|tier| volume_usd >= tier.min_volume_usd)
+            .map_or(0, |tier| tier.discount)
+    }
+
+    pub fn get_entry_fee(
+        &self,
+        base_fee: u64,
+        size: u64,
+        locked_amount: u64,
+        collateral_custody: &Custody,
+        fee_discount: u64,
+    ) -> Result<u64> {
+        // The "optimal" algorithm is always used to compute the fee for entering a position.
+        // entry_fee = custody.fees.open_position * utilization_fee * size
+        // where utilization_fee = 1 + custody.fees.utilization_mult * (new_utilization - optimal_utilization) / (1 - optimal_utilization);
+
+        let mut size_fee = Self::get_fee_amount(base_fee, size)?;
+
+        let new_utilization = if collateral_custody.assets.owned > 0 {
+            // utilization = (assets_locked + locked_amount) / assets_owned
+            core::cmp::min(
+                RATE_POWER,
+                math::checked_div(
+                    math::checked_mul(
+                        math::checked_add(collateral_custody.assets.locked, locked_amount)? as u128,
+                        RATE_POWER,
+                    )?,
+                    collateral_custody.assets.owned as u128,
+                )?,
+            )
+        } else {
+            RATE_POWER
+        };
+
+        if new_utilization > collateral_custody.borrow_rate.optimal_utilization as u128 {
+            let utilization_fee = math::checked_add(
+                BPS_POWER,
+                math::checked_div(
+                    math::checked_mul(
+                        collateral_custody.fees.utilization_mult as u128,
+                        math::checked_sub(
+                            new_utilization,
+                            collateral_custody.borrow_rate.optimal_utilization as u128,
+                        )?,
+                    )?,
+                    math::checked_sub(
+                        RATE_POWER,
+                        collateral_custody.borrow_rate.optimal_utilization as u128,
+                    )?,
+                )?,
+            )?;
+            size_fee = math::checked_as_u64(math::checked_div(
+                math::checked_mul(size_fee as u128, utilization_fee)?,
+                BPS_POWER,
+            )?)?;
+        }
+
+        Self::get_discounted_fee(size_fee, fee_discount)
+    }
+
+    pub fn get_exit_price(
+        &self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        side: Side,
+        size_usd: u64,
+        custody: &Custody,
+    ) -> Result<u64> {
+        let price_impact = self.get_price_impact(token_price, side, size_usd, false, custody)?;
+
+        let price = self.get_price(
+            token_price,
+            token_ema_price,
+            if side == Side::Long {
+                Side::Short
+            } else {
+                Side::Long
+            },
+            Self::get_spread_with_impact(
+                if side == Side::Long {
+                    custody.pricing.trade_spread_short
+                } else {
+                    custody.pricing.trade_spread_long
+                },
+                price_impact,
+            )?,
+        )?;
+
+        Ok(price.scale_to_exponent(-(PRICE_DECIMALS as i32))?.price)
+    }
+
+    pub fn get_exit_fee(&self, size: u64, custody: &Custody, fee_discount: u64) -> Result<u64> {
+        Self::get_discounted_fee(
+            Self::get_fee_amount(custody.fees.close_position, size)?,
+            fee_discount,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_close_amount(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64, u64)> {
+        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            liquidation,
+            fee_discount,
+        )?;
+
+        let available_amount_usd = if profit_usd > 0 {
+            math::checked_add(position.collateral_usd, profit_usd)?
+        } else if loss_usd < position.collateral_usd {
+            math::checked_sub(position.collateral_usd, loss_usd)?
+        } else {
+            0
+        };
+
+        let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
+            collateral_token_price
+        } else {
+            collateral_token_ema_price
+        };
+        let close_amount = max_collateral_price
+            .get_token_amount(available_amount_usd, collateral_custody.decimals)?;
+        let max_amount = math::checked_add(
+            position.locked_amount.saturating_sub(fee_amount),
+            position.collateral_amount,
+        )?;
+
+        Ok((
+            core::cmp::min(max_amount, close_amount),
+            fee_amount,
+            profit_usd,
+            loss_usd,
+        ))
+    }
+
+    pub fn get_swap_price(
+        &self,
+        token_in_price: &OraclePrice,
+        token_in_ema_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        token_out_ema_price: &OraclePrice,
+        custody_in: &Custody,
+    ) -> Result<OraclePrice> {
+        let min_price = if token_in_price < token_in_ema_price {
+            token_in_price
+        } else {
+            token_in_ema_price
+        };
+
+        let max_price = if token_out_price > token_out_ema_price {
+            token_out_price
+        } else {
+            token_out_ema_price
+        };
+
+        let pair_price = min_price.checked_div(max_price)?;
+
+        self.get_price(
+            &pair_price,
+            &pair_price,
+            Side::Short,
+            custody_in.pricing.swap_spread,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_swap_amount(
+        &self,
+        token_in_price: &OraclePrice,
+        token_in_ema_price: &OraclePrice,
+        token_out_price: &OraclePrice,
+        token_out_ema_price: &OraclePrice,
+        custody_in: &Custody,
+        custody_out: &Custody,
+        amount_in: u64,
+    ) -> Result<u64> {
+        let swap_price = self.get_swap_price(
+            token_in_price,
+            token_in_ema_price,
+            token_out_price,
+            token_out_ema_price,
+            custody_in,
+        )?;
+
+        math::checked_decimal_mul(
+            amount_in,
+            -(custody_in.decimals as i32),
+            swap_price.price,
+            swap_price.exponent,
+            -(custody_out.decimals as i32),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_swap_fees(
+        &self,
+        token_id_in: usize,
+        token_id_out: usize,
+        amount_in: u64,
+        amount_out: u64,
+        custody_in: &Custody,
+        token_price_in: &OraclePrice,
+        custody_out: &Custody,
+        token_price_out: &OraclePrice,
+    ) -> Result<(u64, u64)> {
+        let stable_swap = custody_in.is_stable && custody_out.is_stable;
+
+        let swap_in_fee = self.get_fee(
+            token_id_in,
+            if stable_swap {
+                custody_in.fees.stable_swap_in
+            } else {
+                custody_in.fees.swap_in
+            },
+            amount_in,
+            0u64,
+            custody_in,
+            token_price_in,
+        )?;
+
+        let swap_out_fee = self.get_fee(
+            token_id_out,
+            if stable_swap {
+                custody_out.fees.stable_swap_out
+            } else {
+                custody_out.fees.swap_out
+            },
+            0u64,
+            amount_out,
+            custody_out,
+            token_price_out,
+        )?;
+
+        Ok((swap_in_fee, swap_out_fee))
+    }
+
+    pub fn get_add_liquidity_fee(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        self.get_fee(
+            token_id,
+            custody.fees.add_liquidity,
+            amount,
+            0u64,
+            custody,
+            token_price,
+        )
+    }
+
+    pub fn get_remove_liquidity_fee(
+        &self,
+        token_id: usize,
+        amount: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        self.get_fee(
+            token_id,
+            custody.fees.remove_liquidity,
+            0u64,
+            amount,
+            custody,
+            token_price,
+        )
+    }
+
+    pub fn get_liquidation_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
+        Self::get_fee_amount(custody.fees.liquidation, size)
+    }
+
+    pub fn check_token_ratio(
+        &self,
+        token_id: usize,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<bool> {
+        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
+
+        if new_ratio < self.ratios[token_id].min {
+            Ok(new_ratio >= self.get_current_ratio(custody, token_price)?)
+        } else if new_ratio > self.ratios[token_id].max {
+            Ok(new_ratio <= self.get_current_ratio(custody, token_price)?)
+        } else {
+            Ok(true)
+        }
+    }
+
+    pub fn check_available_amount(&self, amount: u64, custody: &Custody) -> Result<bool> {
+        let available_amount = math::checked_sub(
+            math::checked_add(custody.assets.owned, custody.assets.collateral)?,
+            custody.assets.locked,
+        )?;
+        Ok(available_amount >= amount)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_leverage(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+    ) -> Result<u64> {
+        let (profit_usd, loss_usd, _) = self.get_pnl_usd(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+            false,
+            0,
+        )?;
+
+        let current_margin_usd = if profit_usd > 0 {
+            math::checked_add(position.collateral_usd, profit_usd)?
+        } else if loss_usd <= position.collateral_usd {
+            math::checked_sub(position.collateral_usd, loss_usd)?
+        } else {
+            0
+        };
+
+        if current_margin_usd > 0 {
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(position.size_usd as u128, BPS_POWER)?,
+                current_margin_usd as u128,
+            )?)
+        } else {
+            Ok(u64::MAX)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_leverage(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        initial: bool,
+    ) -> Result<bool> {
+        let current_leverage = self.get_leverage(
+            position,
+            token_price,
+            token_ema_price,
+            custody,
+            collateral_token_price,
+            collateral_token_ema_price,
+            collateral_custody,
+            curtime,
+        )?;
+
+        Ok(current_leverage <= custody.pricing.max_leverage
+            && (!initial
+                || (current_leverage >= custody.pricing.min_initial_leverage
+                    && current_leverage <= custody.pricing.max_initial_leverage)))
+    }
+
+    pub fn get_liquidation_price(
+        &self,
+        position: &Position,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_custody: &Custody,
+        curtime: i64,
+    ) -> Result<u64> {
+        // liq_price = pos_price +- (collateral + unreal_profit - unreal_loss - exit_fee - interest - size/max_leverage) * pos_price / size
+
+        if position.size_usd == 0 || position.price == 0 {
+            return Ok(0);
+        }
+
+        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
+        let exit_fee_tokens = self.get_exit_fee(size, custody, 0)?;
+        let exit_fee_usd =
+            token_ema_price.get_asset_amount_usd(exit_fee_tokens, custody.decimals)?;
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+        let unrealized_loss_usd = math::checked_add(
+            math::checked_add(exit_fee_usd, interest_usd)?,
+            position.unrealized_loss_usd,
+        )?;
+
+        let max_loss_usd = math::checked_as_u64(math::checked_div(
+            math::checked_mul(position.size_usd as u128, BPS_POWER)?,
+            custody.pricing.max_leverage as u128,
+        )?)?;
+        let max_loss_usd = math::checked_add(max_loss_usd, unrealized_loss_usd)?;
+
+        let margin_usd =
+            math::checked_add(position.collateral_usd, position.unrealized_profit_usd)?;
+
+        let max_price_diff = if max_loss_usd >= margin_usd {
+            math::checked_sub(max_loss_usd, margin_usd)?
+        } else {
+            math::checked_sub(margin_usd, max_loss_usd)?
+        };
+
+        let position_price = math::scale_to_exponent(
+            position.price,
+            -(PRICE_DECIMALS as i32),
+            -(USD_DECIMALS as i32),
+        )?;
+
+        let max_price_diff = math::checked_as_u64(math::checked_div(
+            math::checked_mul(max_price_diff as u128, position_price as u128)?,
+            position.size_usd as u128,
+        )?)?;
+
+        let max_price_diff = math::scale_to_exponent(
+            max_price_diff,
+            -(USD_DECIMALS as i32),
+            -(PRICE_DECIMALS as i32),
+        )?;
+
+        if position.side == Side::Long {
+            if max_loss_usd >= margin_usd {
+                math::checked_add(position.price, max_price_diff)
+            } else if position.price > max_price_diff {
+                math::checked_sub(position.price, max_price_diff)
+            } else {
+                Ok(0)
+            }
+        } else if max_loss_usd >= margin_usd {
+            if position.price > max_price_diff {
+                math::checked_sub(position.price, max_price_diff)
+            } else {
+                Ok(0)
+            }
+        } else {
+            math::checked_add(position.price, max_price_diff)
+        }
+    }
+
+    // returns (profit_usd, loss_usd, fee_amount)
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_pnl_usd(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64)> {
+        if position.size_usd == 0 || position.price == 0 {
+            return Ok((0, 0, 0));
+        }
+
+        let exit_price = self.get_exit_price(
+            token_price,
+            token_ema_price,
+            position.side,
+            position.size_usd,
+            custody,
+        )?;
+
+        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
+
+        let exit_fee = if liquidation {
+            self.get_liquidation_fee(size, custody)?
+        } else {
+            self.get_exit_fee(size, custody, fee_discount)?
+        };
+
+        let exit_fee_usd = token_ema_price.get_asset_amount_usd(exit_fee, custody.decimals)?;
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+        let unrealized_loss_usd = math::checked_add(
+            math::checked_add(exit_fee_usd, interest_usd)?,
+            position.unrealized_loss_usd,
+        )?;
+
+        let (price_diff_profit, price_diff_loss) = if position.side == Side::Long {
+            if exit_price > position.price {
+                (math::checked_sub(exit_price, position.price)?, 0u64)
+            } else {
+                (0u64, math::checked_sub(position.price, exit_price)?)
+            }
+        } else if exit_price < position.price {
+            (math::checked_sub(position.price, exit_price)?, 0u64)
+        } else {
+            (0u64, math::checked_sub(exit_price, position.price)?)
+        };
+
+        let position_price = math::scale_to_exponent(
+            position.price,
+            -(PRICE_DECIMALS as i32),
+            -(USD_DECIMALS as i32),
+        )?;
+
+        if price_diff_profit > 0 {
+            let potential_profit_usd = math::checked_as_u64(math::checked_div(
+                math::checked_mul(position.size_usd as u128, price_diff_profit as u128)?,
+                position_price as u128,
+            )?)?;
+
+            let potential_profit_usd =
+                math::checked_add(potential_profit_usd, position.unrealized_profit_usd)?;
+
+            if potential_profit_usd >= unrealized_loss_usd {
+                let cur_profit_usd = math::checked_sub(potential_profit_usd, unrealized_loss_usd)?;
+                let min_collateral_price = if collateral_custody.is_virtual {
+                    // if collateral_custody is virtual it means this function is called from get_assets_under_management_usd()
+                    // (to calculate unrealized pnl of all open positions) and actual collateral custody is a stablecoin.
+                    // we need to use 1USD reference price for such positions
+                    OraclePrice::one_usd()
+                } else {
+                    collateral_token_price
+                        .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
+                };
+                let max_profit_usd = if curtime <= position.open_time {
+                    0
+                } else {
+                    min_collateral_price
+                        .get_asset_amount_usd(position.locked_amount, collateral_custody.decimals)?
+                };
+                Ok((
+                    core::cmp::min(max_profit_usd, cur_profit_usd),
+                    0u64,
+                    exit_fee,
+                ))
+            } else {
+                Ok((
+                    0u64,
+                    math::checked_sub(unrealized_loss_usd, potential_profit_usd)?,
+                    exit_fee,
+                ))
+            }
+        } else {
+            let potential_loss_usd = math::checked_as_u64(math::checked_ceil_div(
+                math::checked_mul(position.size_usd as u128, price_diff_loss as u128)?,
+                position_price as u128,
+            )?)?;
+
+            let potential_loss_usd = math::checked_add(potential_loss_usd, unrealized_loss_usd)?;
+
+            if potential_loss_usd >= position.unrealized_profit_usd {
+                Ok((
+                    0u64,
+                    math::checked_sub(potential_loss_usd, position.unrealized_profit_usd)?,
+                    exit_fee,
+                ))
+            } else {
+                let cur_profit_usd =
+                    math::checked_sub(position.unrealized_profit_usd, potential_loss_usd)?;
+                let min_collateral_price = if collateral_custody.is_virtual {
+                    OraclePrice::one_usd()
+                } else {
+                    collateral_token_price
+                        .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
+                };
+                let max_profit_usd = if curtime <= position.open_time {
+                    0
+                } else {
+                    min_collateral_price
+                        .get_asset_amount_usd(position.locked_amount, collateral_custody.decimals)?
+                };
+                Ok((
+                    core::cmp::min(max_profit_usd, cur_profit_usd),
+                    0u64,
+                    exit_fee,
+                ))
+            }
+        }
+    }
+
+    pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
+        if fee == 0 || amount == 0 {
+            return Ok(0);
+        }
+        math::checked_as_u64(math::checked_ceil_div(
+            math::checked_mul(amount as u128, fee as u128)?,
+            BPS_POWER,
+        )?)
+    }
+
+    pub fn get_discounted_fee(fee_amount: u64, discount: u64) -> Result<u64> {
+        if discount == 0 {
+            return Ok(fee_amount);
+        }
+        math::checked_sub(
+            fee_amount,
+            math::checked_as_u64(math::checked_div(
+                math::checked_mul(fee_amount as u128, discount as u128)?,
+                BPS_POWER,
+            )?)?,
+        )
+    }
+
+    pub fn get_current_ratio(&self, custody: &Custody, token_price: &OraclePrice) -> Result<u64> {
+        if self.aum_usd == 0 || custody.is_virtual {
+            return Ok(0);
+        }
+        let ratio = math::checked_as_u64(math::checked_div(
+            math::checked_mul(
+                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128,
+                BPS_POWER,
+            )?,
+            self.aum_usd,
+        )?)?;
+        Ok(core::cmp::min(ratio, BPS_POWER as u64))
+    }
+
+    pub fn get_new_ratio(
+        &self,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        if custody.is_virtual {
+            return Ok(0);
+        }
+        let (new_token_aum_usd, new_pool_aum_usd) = if amount_add > 0 && amount_remove > 0 {
+            return Err(PricingError::InvalidArgument);
+        } else if amount_add == 0 && amount_remove == 0 {
+            (
+                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128,
+                self.aum_usd,
+            )
+        } else if amount_add > 0 {
+            let added_aum_usd =
+                token_price.get_asset_amount_usd(amount_add, custody.decimals)? as u128;
+
+            (
+                token_price.get_asset_amount_usd(
+                    math::checked_add(custody.assets.owned, amount_add)?,
+                    custody.decimals,
+                )? as u128,
+                math::checked_add(self.aum_usd, added_aum_usd)?,
+            )
+        } else {
+            let removed_aum_usd =
+                token_price.get_asset_amount_usd(amount_remove, custody.decimals)? as u128;
+
+            if removed_aum_usd >= self.aum_usd || amount_remove >= custody.assets.owned {
+                (0, 0)
+            } else {
+                (
+                    token_price.get_asset_amount_usd(
+                        math::checked_sub(custody.assets.owned, amount_remove)?,
+                        custody.decimals,
+                    )? as u128,
+                    math::checked_sub(self.aum_usd, removed_aum_usd)?,
+                )
+            }
+        };
+        if new_token_aum_usd == 0 || new_pool_aum_usd == 0 {
+            return Ok(0);
+        }
+
+        let ratio = math::checked_as_u64(math::checked_div(
+            math::checked_mul(new_token_aum_usd, BPS_POWER)?,
+            new_pool_aum_usd,
+        )?)?;
+        Ok(core::cmp::min(ratio, BPS_POWER as u64))
+    }
+
+    /// Returns price impact in BPS for opening (is_increase) or closing a position of the given size.
+    /// Impact is proportional to the change in open interest skew relative to custody depth,
+    /// trades that reduce the skew get a negative impact (rebate).
+    pub fn get_price_impact(
+        &self,
+        token_price: &OraclePrice,
+        side: Side,
+        size_usd: u64,
+        is_increase: bool,
+        custody: &Custody,
+    ) -> Result<i64> {
+        let max_price_impact = custody.pricing.max_price_impact as i128;
+        if custody.pricing.price_impact_mult == 0 || max_price_impact == 0 || size_usd == 0 {
+            return Ok(0);
+        }
+
+        let oi_long_usd = custody.trade_stats.oi_long_usd as i128;
+        let oi_short_usd = custody.trade_stats.oi_short_usd as i128;
+        let size_usd = size_usd as i128;
+
+        let (new_oi_long_usd, new_oi_short_usd) = match (side, is_increase) {
+            (Side::Long, true) => (math::checked_add(oi_long_usd, size_usd)?, oi_short_usd),
+            (Side::Long, false) => (core::cmp::max(0, oi_long_usd - size_usd), oi_short_usd),
+            (_, true) => (oi_long_usd, math::checked_add(oi_short_usd, size_usd)?),
+            (_, false) => (oi_long_usd, core::cmp::max(0, oi_short_usd - size_usd)),
+        };
+
+        let skew_delta_usd = math::checked_sub(
+            (new_oi_long_usd - new_oi_short_usd).abs(),
+            (oi_long_usd - oi_short_usd).abs(),
+        )?;
+        if skew_delta_usd == 0 {
+            return Ok(0);
+        }
+
+        let depth_usd = token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)?;
+        let price_impact = if depth_usd == 0 {
+            // no liquidity to absorb the skew, apply the cap
+            skew_delta_usd.signum() * max_price_impact
+        } else {
+            math::checked_div(
+                math::checked_mul(skew_delta_usd, custody.pricing.price_impact_mult as i128)?,
+                depth_usd as i128,
+            )?
+        };
+
+        Ok(price_impact.clamp(-max_price_impact, max_price_impact) as i64)
+    }
+
+    // rebates can offset the spread but never move execution price past the oracle price
+    pub fn get_spread_with_impact(spread: u64, price_impact: i64) -> Result<u64> {
+        math::checked_as_u64(core::cmp::max(
+            0,
+            math::checked_add(spread as i64, price_impact)?,
+        ))
+    }
+
+    pub fn get_price(
+        &self,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        side: Side,
+        spread: u64,
+    ) -> Result<OraclePrice> {
+        if side == Side::Long {
+            let max_price = if token_price > token_ema_price {
+                token_price
+            } else {
+                token_ema_price
+            };
+
+            Ok(OraclePrice {
+                price: math::checked_add(
+                    max_price.price,
+                    math::checked_decimal_ceil_mul(
+                        max_price.price,
+                        max_price.exponent,
+                        spread,
+                        -(BPS_DECIMALS as i32),
+                        max_price.exponent,
+                    )?,
+                )?,
+                exponent: max_price.exponent,
+            })
+        } else {
+            let min_price = if token_price < token_ema_price {
+                token_price
+            } else {
+                token_ema_price
+            };
+
+            let spread = math::checked_decimal_mul(
+                min_price.price,
+                min_price.exponent,
+                spread,
+                -(BPS_DECIMALS as i32),
+                min_price.exponent,
+            )?;
+
+            let price = if spread < min_price.price {
+                math::checked_sub(min_price.price, spread)?
+            } else {
+                0
+            };
+
+            Ok(OraclePrice {
+                price,
+                exponent: min_price.exponent,
+            })
+        }
+    }
+
+    pub fn get_fee(
+        &self,
+        token_id: usize,
+        base_fee: u64,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        if custody.is_virtual {
+            return Err(PricingError::InstructionNotAllowed);
+        }
+
+        if custody.fees.mode == FeesMode::Fixed {
+            Self::get_fee_amount(base_fee, core::cmp::max(amount_add, amount_remove))
+        } else if custody.fees.mode == FeesMode::Linear {
+            self.get_fee_linear(
+                token_id,
+                base_fee,
+                amount_add,
+                amount_remove,
+                custody,
+                token_price,
+            )
+        } else {
+            self.get_fee_optimal(
+                token_id,
+                base_fee,
+                amount_add,
+                amount_remove,
+                custody,
+                token_price,
+            )
+        }
+    }
+
+    pub fn get_fee_linear(
+        &self,
+        token_id: usize,
+        base_fee: u64,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        // if token ratio is improved:
+        //    fee = base_fee / ratio_fee
+        // otherwise:
+        //    fee = base_fee * ratio_fee
+        // where:
+        //   if new_ratio < ratios.target:
+        //     ratio_fee = 1 + custody.fees.ratio_mult * (ratios.target - new_ratio) / (ratios.target - ratios.min);
+        //   otherwise:
+        //     ratio_fee = 1 + custody.fees.ratio_mult * (new_ratio - ratios.target) / (ratios.max - ratios.target);
+
+        let ratios = &self.ratios[token_id];
+        let current_ratio = self.get_current_ratio(custody, token_price)?;
+        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
+
+        let improved = match new_ratio.cmp(&ratios.target) {
+            Ordering::Less => {
+                new_ratio > current_ratio
+                    || (current_ratio > ratios.target
+                        && current_ratio - ratios.target > ratios.target - new_ratio)
+            }
+            Ordering::Greater => {
+                new_ratio < current_ratio
+                    || (current_ratio < ratios.target
+                        && ratios.target - current_ratio > new_ratio - ratios.target)
+            }
+            Ordering::Equal => current_ratio != ratios.target,
+        };
+
+        let ratio_fee = if new_ratio <= ratios.target {
+            if ratios.target == ratios.min {
+                BPS_POWER
+            } else {
+                math::checked_add(
+                    BPS_POWER,
+                    math::checked_div(
+                        math::checked_mul(
+                            custody.fees.ratio_mult as u128,
+                            math::checked_sub(ratios.target, new_ratio)? as u128,
+                        )?,
+                        math::checked_sub(ratios.target, ratios.min)? as u128,
+                    )?,
+                )?
+            }
+        } else if ratios.target == ratios.max {
+            BPS_POWER
+        } else {
+            math::checked_add(
+                BPS_POWER,
+                math::checked_div(
+                    math::checked_mul(
+                        custody.fees.ratio_mult as u128,
+                        math::checked_sub(new_ratio, ratios.target)? as u128,
+                    )?,
+                    math::checked_sub(ratios.max, ratios.target)? as u128,
+                )?,
+            )?
+        };
+
+        let fee = if improved {
+            math::checked_div(math::checked_mul(base_fee as u128, BPS_POWER)?, ratio_fee)?
+        } else {
+            math::checked_div(math::checked_mul(base_fee as u128, ratio_fee)?, BPS_POWER)?
+        };
+
+        Self::get_fee_amount(
+            math::checked_as_u64(fee)?,
+            core::cmp::max(amount_add, amount_remove),
+        )
+    }
+
+    pub fn get_fee_optimal(
+        &self,
+        token_id: usize,
+        base_fee: u64,
+        amount_add: u64,
+        amount_remove: u64,
+        custody: &Custody,
+        token_price: &OraclePrice,
+    ) -> Result<u64> {
+        // Fee calculations must temporarily be in i64 because of negative slope.
+        let fee_max: i64 = custody.fees.fee_max as i64;
+        let fee_optimal: i64 = custody.fees.fee_optimal as i64;
+
+        let target_ratio: i64 = self.ratios[token_id].target as i64;
+        let min_ratio: i64 = self.ratios[token_id].min as i64;
+        let max_ratio: i64 = self.ratios[token_id].max as i64;
+        let post_lp_ratio: i64 =
+            self.get_new_ratio(amount_add, amount_remove, custody, token_price)? as i64;
+
+        let base_fee: i64 = base_fee as i64;
+
+        let slope_denominator: i64 = if post_lp_ratio > target_ratio {
+            math::checked_sub(max_ratio, target_ratio)?
+        } else {
+            math::checked_sub(target_ratio, min_ratio)?
+        };
+
+        let slope_numerator: i64 = if amount_add != 0 {
+            if post_lp_ratio > max_ratio {
+                return Err(PricingError::TokenRatioOutOfRange);
+            }
+            fee_max - fee_optimal
+        } else {
+            if post_lp_ratio < min_ratio {
+                return Err(PricingError::TokenRatioOutOfRange);
+            }
+            fee_optimal - fee_max
+        };
+
+        // Delay applying slope_denominator until the very end to avoid losing precision.
+        // b = fee_optimal - target_ratio * slope
+        // lp_fee = slope * post_lp_ratio + b
+        let b: i64 = math::checked_sub(
+            math::checked_mul(fee_optimal, slope_denominator)?,
+            math::checked_mul(target_ratio, slope_numerator)?,
+        )?;
+        let lp_fee: i64 = math::checked_div(
+            math::checked_add(math::checked_mul(slope_numerator, post_lp_ratio)?, b)?,
+            slope_denominator,
+        )?;
+
+        Self::get_fee_amount(
+            math::checked_as_u64(math::checked_add(lp_fee, base_fee)?)?,
+            core::cmp::max(amount_add, amount_remove),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        crate::custody::{Fees, PricingParams},
+    };
+
+    fn get_fixture() -> (Pool, Custody, Position, OraclePrice, OraclePrice) {
+        let custody = Custody {
+            decimals: 9,
+            pricing: PricingParams {
+                trade_spread_long: 100,
+                trade_spread_short: 100,
+                max_leverage: 100_000,
+                max_payoff_mult: 10_000,
+                ..PricingParams::default()
+            },
+            fees: Fees {
+                open_position: 100,
+                liquidation: 50,
+                ..Fees::default()
+            },
+            ..Custody::default()
+        };
+
+        let position = Position {
+            side: Side::Long,
+            price: 24_500_000_000,
+            size_usd: 100_000_000_000,
+            borrow_size_usd: 100_000_000_000,
+            collateral_usd: 25_000_000_000,
+            locked_amount: 4_000_000_000,
+            collateral_amount: 1_000_000_000,
+            ..Position::default()
+        };
+
+        (
+            Pool::default(),
+            custody,
+            position,
+            OraclePrice::new(25_000_000, -3),
+            OraclePrice::new(25_300_000, -3),
+        )
+    }
+
+    #[test]
+    fn test_quote() {
+        let (pool, custody, position, token_price, token_ema_price) = get_fixture();
+
+        assert_eq!(
+            25_553_000_000,
+            pool.get_entry_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                1_000_000_000,
+                &custody
+            )
+            .unwrap()
+        );
+
+        assert_eq!(
+            (1_020_408_163, 0, 0),
+            pool.get_pnl_usd(
+                &position,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                &token_price,
+                &token_ema_price,
+                &custody,
+                1,
+                false,
+                0
+            )
+            .unwrap()
+        );
+
+        // zero price after spread is rejected
+        assert_eq!(
+            Err(PricingError::MaxPriceSlippage),
+            pool.get_entry_price(
+                &OraclePrice::new(0, -3),
+                &OraclePrice::new(0, -3),
+                Side::Long,
+                1_000_000_000,
+                &custody
+            )
+        );
+    }
+}
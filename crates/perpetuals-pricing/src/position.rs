@@ -0,0 +1,35 @@
+//! Position state used for PnL and liquidation price calculations
+
+use crate::{error::Result, math, BPS_POWER};
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub enum Side {
+    #[default]
+    None,
+    Long,
+    Short,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct Position {
+    pub open_time: i64,
+    pub side: Side,
+    pub price: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub collateral_usd: u64,
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    pub cumulative_interest_snapshot: u128,
+    pub locked_amount: u64,
+    pub collateral_amount: u64,
+}
+
+impl Position {
+    pub fn get_initial_leverage(&self) -> Result<u64> {
+        math::checked_as_u64(math::checked_div(
+            math::checked_mul(self.size_usd as u128, BPS_POWER)?,
+            self.collateral_usd as u128,
+        )?)
+    }
+}
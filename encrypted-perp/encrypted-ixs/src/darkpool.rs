@@ -4,179 +4,778 @@ use arcis_imports::*;
 mod circuits {
     use arcis_imports::*;
 
-    #[derive(Clone, Debug)]
+    // Number of order slots in a market order book, must match MAX_ORDERS in mgk_program
+    pub const MAX_ORDERS: usize = 8;
+    // Every fill exhausts at least one order, and the last two orders can be
+    // exhausted by the same fill
+    pub const MAX_MATCHES: usize = MAX_ORDERS - 1;
+    // Basis points of the oracle price band and of custody leverage limits
+    pub const BPS_POWER: u128 = 10_000;
+    // Upper bound on collateral token decimals
+    pub const MAX_DECIMALS: usize = 18;
+
+    // Order validation reason codes, must match DarkOrderReason in the client
+    pub const ORDER_VALID: u8 = 0;
+    pub const INVALID_SIDE: u8 = 1;
+    pub const INVALID_SIZE: u8 = 2;
+    pub const INVALID_PRICE: u8 = 3;
+    pub const INSUFFICIENT_COLLATERAL: u8 = 4;
+    pub const LEVERAGE_TOO_LOW: u8 = 5;
+    pub const LEVERAGE_TOO_HIGH: u8 = 6;
+    pub const INSUFFICIENT_ESCROW: u8 = 7;
+
+    // Secret part of an order. The owner and the market (pool, custody and
+    // collateral custody) are public and stored in the order book account.
+    #[derive(Clone, Copy, Debug)]
     pub struct DarkOrder {
-        pub owner: [u8; 32],         // Pubkey of order owner
         pub side: u8,                // 0 = long, 1 = short
         pub size_usd: u64,           // Position size in USD (6 decimals)
-        pub collateral_amount: u64,   // Collateral amount
+        pub collateral_amount: u64,  // Collateral amount
         pub max_price: u64,          // Maximum acceptable price (for longs) or minimum (for shorts)
         pub leverage: u64,           // Leverage multiplier
-        pub pool: [u8; 32],          // Pool pubkey
-        pub custody: [u8; 32],       // Custody pubkey (target asset)
-        pub collateral_custody: [u8; 32], // Collateral custody pubkey
         pub timestamp: u64,          // Order timestamp
         pub nonce: u64,              // Unique order identifier
     }
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Copy, Debug)]
     pub struct OrderMatch {
-        pub order_a: DarkOrder,
-        pub order_b: DarkOrder,
+        pub is_match: bool,          // Slot pair matched
+        pub slot_a: u8,              // Order book slot of the long order
+        pub slot_b: u8,              // Order book slot of the short order
         pub matched_size: u64,
-        pub execution_price: u64,
-        pub timestamp: u64,
+        pub execution_price: u64,    // Limit price of the resting order
+        pub collateral_a: u64,       // Collateral of the long order backing the fill
+        pub collateral_b: u64,       // Collateral of the short order backing the fill
     }
 
-    #[derive(Clone, Debug)]
+    #[derive(Clone, Copy, Debug)]
     pub struct MatchResult {
-        pub matches: Vec<OrderMatch>,
+        pub matches: [OrderMatch; MAX_MATCHES],
+        pub match_count: u64,
         pub total_volume: u64,
         pub average_price: u64,
         pub timestamp: u64,
     }
 
-    #[derive(Clone, Debug)]
+    // Order of one side of the book, ranked by price-time priority
+    #[derive(Clone, Copy, Debug)]
+    pub struct BookEntry {
+        pub slot: u8,
+        pub active: bool,
+        pub price: u64,
+        pub timestamp: u64,
+        pub remaining: u64,
+        pub size: u64,
+        pub collateral: u64,
+    }
+
+    #[derive(Clone, Copy, Debug)]
     pub struct OrderBook {
-        pub orders: Vec<DarkOrder>,
+        pub orders: [DarkOrder; MAX_ORDERS],
+        pub active: [bool; MAX_ORDERS],
         pub last_update: u64,
     }
 
+    impl DarkOrder {
+        pub fn empty() -> Self {
+            Self {
+                side: 0,
+                size_usd: 0,
+                collateral_amount: 0,
+                max_price: 0,
+                leverage: 0,
+                timestamp: 0,
+                nonce: 0,
+            }
+        }
+    }
+
+    impl OrderMatch {
+        pub fn empty() -> Self {
+            Self {
+                is_match: false,
+                slot_a: 0,
+                slot_b: 0,
+                matched_size: 0,
+                execution_price: 0,
+                collateral_a: 0,
+                collateral_b: 0,
+            }
+        }
+    }
+
+    impl BookEntry {
+        pub fn empty() -> Self {
+            Self {
+                slot: 0,
+                active: false,
+                price: 0,
+                timestamp: 0,
+                remaining: 0,
+                size: 0,
+                collateral: 0,
+            }
+        }
+    }
+
     impl OrderBook {
         pub fn new() -> Self {
             Self {
-                orders: Vec::new(),
+                orders: [DarkOrder::empty(); MAX_ORDERS],
+                active: [false; MAX_ORDERS],
                 last_update: 0,
             }
         }
 
-        pub fn add_order(&mut self, order: DarkOrder) {
-            self.orders.push(order);
+        pub fn add_order(&mut self, slot: usize, order: DarkOrder) {
+            self.orders[slot] = order;
+            self.active[slot] = true;
             self.last_update = order.timestamp;
         }
 
         pub fn remove_order(&mut self, nonce: u64) {
-            self.orders.retain(|order| order.nonce != nonce);
+            for i in 0..MAX_ORDERS {
+                if self.orders[i].nonce == nonce {
+                    self.active[i] = false;
+                }
+            }
         }
     }
 
-    // Submit encrypted order to the dark pool
+    // Validate an encrypted order and re-encrypt it for the MXE, so it can be
+    // stored in the order book slot and read by later matching computations.
+    // The leverage limits and the open position fee come from the position
+    // custody and the collateral price from the collateral custody oracle.
+    // The escrow amount is the
+    // public deposit backing the order, which must cover its collateral. The
+    // reason code is encrypted for the order owner, and an invalid order is
+    // stored with a zero size so it never matches; the owner cancels it to
+    // free the slot and get the deposit back. The slot and the order id of the
+    // submission are returned so the callback ignores a slot that was released
+    // and reused in the meantime.
+    #[allow(clippy::too_many_arguments)]
     #[instruction]
     pub fn submit_dark_order(
-        order_context: Enc<Shared, DarkOrder>
-    ) -> Enc<Shared, bool> {
+        order_context: Enc<Shared, DarkOrder>,
+        slot: u8,
+        order_id: u64,
+        escrow_amount: u64,
+        min_initial_leverage: u64,
+        max_initial_leverage: u64,
+        open_position_fee: u64,
+        collateral_price: u64,
+        collateral_decimals: u8,
+    ) -> (u8, u64, Enc<Shared, u8>, Enc<Mxe, DarkOrder>) {
         let order = order_context.to_arcis();
-        
-        // Basic validation in encrypted environment
-        let is_valid = order.size_usd > 0 
-            && order.collateral_amount > 0 
-            && order.leverage > 0 
-            && order.leverage <= 100  // Max 100x leverage
-            && order.max_price > 0
-            && (order.side == 0 || order.side == 1);
 
-        order_context.owner.from_arcis(is_valid)
+        let reason = validate_order(
+            &order,
+            escrow_amount,
+            min_initial_leverage,
+            max_initial_leverage,
+            open_position_fee,
+            collateral_price,
+            collateral_decimals,
+        );
+        let stored = DarkOrder {
+            size_usd: if reason == ORDER_VALID { order.size_usd } else { 0 },
+            ..order
+        };
+
+        (
+            slot,
+            order_id,
+            order_context.owner.from_arcis(reason),
+            Mxe::get().from_arcis(stored),
+        )
+    }
+
+    // Same leverage math as the perpetuals program, with the collateral
+    // converted to USD at the oracle price and leverage in basis points.
+    // Settlement takes the open position fee out of the collateral before
+    // its leverage check, so the fee is netted out here as well. The
+    // utilization surcharge on top of the base fee is not known until
+    // settlement, which can still reject an order right at the limit.
+    // Earlier checks take precedence over later ones in the reason code.
+    pub fn validate_order(
+        order: &DarkOrder,
+        escrow_amount: u64,
+        min_initial_leverage: u64,
+        max_initial_leverage: u64,
+        open_position_fee: u64,
+        collateral_price: u64,
+        collateral_decimals: u8,
+    ) -> u8 {
+        let mut collateral_scale: u128 = 1;
+        for i in 0..MAX_DECIMALS {
+            if i < collateral_decimals as usize {
+                collateral_scale *= 10;
+            }
+        }
+        let collateral_usd =
+            order.collateral_amount as u128 * collateral_price as u128 / collateral_scale;
+        let fee_usd = order.size_usd as u128 * open_position_fee as u128 / BPS_POWER;
+        // saturating_sub is not supported by Arcis
+        #[allow(clippy::implicit_saturating_sub)]
+        let collateral_usd = if collateral_usd > fee_usd {
+            collateral_usd - fee_usd
+        } else {
+            0
+        };
+        let leverage = if collateral_usd > 0 {
+            order.size_usd as u128 * BPS_POWER / collateral_usd
+        } else {
+            u128::MAX
+        };
+
+        let mut reason = ORDER_VALID;
+        if leverage > max_initial_leverage as u128 {
+            reason = LEVERAGE_TOO_HIGH;
+        }
+        if leverage < min_initial_leverage as u128 {
+            reason = LEVERAGE_TOO_LOW;
+        }
+        if order.collateral_amount > escrow_amount {
+            reason = INSUFFICIENT_ESCROW;
+        }
+        if collateral_usd == 0 {
+            reason = INSUFFICIENT_COLLATERAL;
+        }
+        if order.max_price == 0 {
+            reason = INVALID_PRICE;
+        }
+        if order.size_usd == 0 {
+            reason = INVALID_SIZE;
+        }
+        if order.side > 1 {
+            reason = INVALID_SIDE;
+        }
+        reason
     }
 
-    // Match orders in encrypted environment
+    // Match the orders stored in a market order book, empty and expired slots
+    // are skipped. Time priority uses the submission time recorded on chain
+    // instead of the client supplied timestamp. Matches executing further than
+    // price_band_bps from the custody oracle price are refused. Every match
+    // is revealed for settlement: the long and short slots, the size, the
+    // execution price and the collateral of both sides backing the fill, one
+    // array per field with a zero size for unused entries. Also returns
+    // whether each slot has been completely filled and the residual orders
+    // re-encrypted for the MXE, so partially filled orders stay in the order
+    // book.
+    #[allow(clippy::type_complexity)]
     #[instruction]
     pub fn match_dark_orders(
-        orders_context: Enc<Shared, Vec<DarkOrder>>
-    ) -> Enc<Shared, MatchResult> {
-        let orders = orders_context.to_arcis();
-        let mut matches = Vec::new();
+        orders_context: [Enc<Mxe, DarkOrder>; MAX_ORDERS],
+        active: [bool; MAX_ORDERS],
+        submitted_at: [u64; MAX_ORDERS],
+        oracle_price: u64,
+        price_band_bps: u64,
+        current_time: u64,
+    ) -> (
+        [u8; MAX_MATCHES],
+        [u8; MAX_MATCHES],
+        [u64; MAX_MATCHES],
+        [u64; MAX_MATCHES],
+        [u64; MAX_MATCHES],
+        [u64; MAX_MATCHES],
+        [bool; MAX_ORDERS],
+        [Enc<Mxe, DarkOrder>; MAX_ORDERS],
+    ) {
+        let mut order_book = OrderBook::new();
+        for i in 0..MAX_ORDERS {
+            if active[i] {
+                let mut order = orders_context[i].to_arcis();
+                order.timestamp = submitted_at[i];
+                order_book.add_order(i, order);
+            }
+        }
+
+        let (min_price, max_price) = price_band(oracle_price, price_band_bps);
+        let (match_result, residuals) =
+            match_orders(&order_book, current_time, min_price, max_price);
+
+        let mut filled = [false; MAX_ORDERS];
+        for i in 0..MAX_ORDERS {
+            filled[i] = (active[i] && residuals[i].size_usd == 0).reveal();
+        }
+
+        let mut long_slots = [0u8; MAX_MATCHES];
+        let mut short_slots = [0u8; MAX_MATCHES];
+        let mut sizes = [0u64; MAX_MATCHES];
+        let mut prices = [0u64; MAX_MATCHES];
+        let mut long_collateral = [0u64; MAX_MATCHES];
+        let mut short_collateral = [0u64; MAX_MATCHES];
+        for (i, order_match) in match_result.matches.iter().enumerate() {
+            long_slots[i] = order_match.slot_a;
+            short_slots[i] = order_match.slot_b;
+            sizes[i] = order_match.matched_size;
+            prices[i] = order_match.execution_price;
+            long_collateral[i] = order_match.collateral_a;
+            short_collateral[i] = order_match.collateral_b;
+        }
+
+        (
+            long_slots.reveal(),
+            short_slots.reveal(),
+            sizes.reveal(),
+            prices.reveal(),
+            long_collateral.reveal(),
+            short_collateral.reveal(),
+            filled,
+            // One MXE nonce per slot, Enc is not Copy so the array is spelled out
+            [
+                Mxe::get().from_arcis(residuals[0]),
+                Mxe::get().from_arcis(residuals[1]),
+                Mxe::get().from_arcis(residuals[2]),
+                Mxe::get().from_arcis(residuals[3]),
+                Mxe::get().from_arcis(residuals[4]),
+                Mxe::get().from_arcis(residuals[5]),
+                Mxe::get().from_arcis(residuals[6]),
+                Mxe::get().from_arcis(residuals[7]),
+            ],
+        )
+    }
+
+    // Price-time priority matching. Longs are ranked by descending and shorts
+    // by ascending limit price, ties are broken by time. The best long is
+    // filled against the best short at the price of the resting (earliest)
+    // order until one of them is exhausted, then the next order of that side
+    // takes its place. A resting order priced outside of [min_price, max_price]
+    // can't trade at its own price and is skipped. Returns the match result
+    // and the residual orders.
+    pub fn match_orders(
+        order_book: &OrderBook,
+        current_time: u64,
+        min_price: u64,
+        max_price: u64,
+    ) -> (MatchResult, [DarkOrder; MAX_ORDERS]) {
+        let mut bids = [BookEntry::empty(); MAX_ORDERS];
+        let mut asks = [BookEntry::empty(); MAX_ORDERS];
+        for (i, order) in order_book.orders.iter().enumerate() {
+            let entry = BookEntry {
+                slot: i as u8,
+                active: order_book.active[i] && order.size_usd > 0,
+                price: order.max_price,
+                timestamp: order.timestamp,
+                remaining: order.size_usd,
+                size: order.size_usd,
+                collateral: order.collateral_amount,
+            };
+            bids[i] = BookEntry {
+                active: entry.active && order.side == 0,
+                ..entry
+            };
+            asks[i] = BookEntry {
+                active: entry.active && order.side == 1,
+                ..entry
+            };
+        }
+        sort_entries(&mut bids, true);
+        sort_entries(&mut asks, false);
+
+        let mut matches = [OrderMatch::empty(); MAX_MATCHES];
+        let mut match_count = 0u64;
         let mut total_volume = 0u64;
-        let mut total_value = 0u64;
-        let current_time = 1600000000u64; // Placeholder timestamp
-
-        // Simple matching algorithm - match opposing sides
-        for i in 0..orders.len() {
-            for j in (i + 1)..orders.len() {
-                let order_a = &orders[i];
-                let order_b = &orders[j];
-
-                // Check if orders can match (opposite sides, compatible prices)
-                if can_match(order_a, order_b) {
-                    let execution_price = calculate_execution_price(order_a, order_b);
-                    let matched_size = order_a.size_usd.min(order_b.size_usd);
-
-                    let order_match = OrderMatch {
-                        order_a: order_a.clone(),
-                        order_b: order_b.clone(),
-                        matched_size,
-                        execution_price,
-                        timestamp: current_time,
-                    };
-
-                    total_volume += matched_size;
-                    total_value += matched_size * execution_price;
-                    matches.push(order_match);
+        let mut total_value = 0u128;
+
+        // The positions of the best remaining orders are secret, every step
+        // reads and updates all entries of both sides
+        let mut bid_index = 0u8;
+        let mut ask_index = 0u8;
+        for order_match in matches.iter_mut() {
+            let mut bid = BookEntry::empty();
+            let mut ask = BookEntry::empty();
+            for i in 0..MAX_ORDERS {
+                if bid_index == i as u8 {
+                    bid = bids[i];
+                }
+                if ask_index == i as u8 {
+                    ask = asks[i];
                 }
             }
+
+            let is_crossing = bid.active && ask.active && bid.price >= ask.price;
+            let execution_price = calculate_execution_price(&bid, &ask);
+            let in_band = execution_price >= min_price && execution_price <= max_price;
+            let is_match = is_crossing && in_band;
+            let skip = is_crossing && !in_band;
+            let bid_rests = is_resting(&bid, &ask);
+            let matched_size = if is_match {
+                bid.remaining.min(ask.remaining)
+            } else {
+                0
+            };
+            let collateral_a = locked_collateral(&bid, bid.remaining)
+                - locked_collateral(&bid, bid.remaining - matched_size);
+            let collateral_b = locked_collateral(&ask, ask.remaining)
+                - locked_collateral(&ask, ask.remaining - matched_size);
+
+            for i in 0..MAX_ORDERS {
+                if bid_index == i as u8 {
+                    bids[i].remaining -= matched_size;
+                }
+                if ask_index == i as u8 {
+                    asks[i].remaining -= matched_size;
+                }
+            }
+            // Exhausted orders give way to the next one of their side. A cross
+            // off the band is priced by the resting order, so only that order
+            // is passed over and the other one is tried against the next
+            // entry. The skipped order keeps its size for the next run, within
+            // this one it only loses crosses as the aggressor against an older
+            // order further down the book. Each skip uses one of the steps.
+            if (is_match && bid.remaining == matched_size) || (skip && bid_rests) {
+                bid_index += 1;
+            }
+            if (is_match && ask.remaining == matched_size) || (skip && !bid_rests) {
+                ask_index += 1;
+            }
+
+            *order_match = OrderMatch {
+                is_match,
+                slot_a: bid.slot,
+                slot_b: ask.slot,
+                matched_size,
+                execution_price,
+                collateral_a,
+                collateral_b,
+            };
+
+            if is_match {
+                match_count += 1;
+            }
+            total_volume += matched_size;
+            total_value += matched_size as u128 * execution_price as u128;
         }
 
         let average_price = if total_volume > 0 {
-            total_value / total_volume
+            (total_value / total_volume as u128) as u64
         } else {
             0
         };
 
-        let result = MatchResult {
-            matches,
-            total_volume,
-            average_price,
-            timestamp: current_time,
+        // Map the remaining sizes back to the order book slots
+        let mut remaining = [0u64; MAX_ORDERS];
+        for (i, order) in order_book.orders.iter().enumerate() {
+            if order_book.active[i] {
+                remaining[i] = order.size_usd;
+            }
+            for j in 0..MAX_ORDERS {
+                if bids[j].slot == i as u8 && order.side == 0 {
+                    remaining[i] = remaining[i].min(bids[j].remaining);
+                }
+                if asks[j].slot == i as u8 && order.side == 1 {
+                    remaining[i] = remaining[i].min(asks[j].remaining);
+                }
+            }
+        }
+
+        let mut residuals = order_book.orders;
+        for i in 0..MAX_ORDERS {
+            residuals[i] = residual_order(&order_book.orders[i], remaining[i]);
+        }
+
+        (
+            MatchResult {
+                matches,
+                match_count,
+                total_volume,
+                average_price,
+                timestamp: current_time,
+            },
+            residuals,
+        )
+    }
+
+    // Whether entry_a ranks before entry_b. Active entries come first, longs
+    // (bids) by descending and shorts (asks) by ascending price, then by time
+    // and slot.
+    fn has_priority(entry_a: &BookEntry, entry_b: &BookEntry, is_bid: bool) -> bool {
+        let better_price = if is_bid {
+            entry_a.price > entry_b.price
+        } else {
+            entry_a.price < entry_b.price
         };
+        let earlier = entry_a.timestamp < entry_b.timestamp
+            || (entry_a.timestamp == entry_b.timestamp && entry_a.slot < entry_b.slot);
+        let better = better_price || (entry_a.price == entry_b.price && earlier);
 
-        orders_context.owner.from_arcis(result)
+        entry_a.active && (!entry_b.active || better)
     }
 
-    // Helper function to check if two orders can match
-    fn can_match(order_a: &DarkOrder, order_b: &DarkOrder) -> bool {
-        // Orders must be on opposite sides
-        if order_a.side == order_b.side {
-            return false;
+    // Odd-even transposition sort: MAX_ORDERS rounds of compare-exchanges of
+    // neighbouring entries. The network is fixed, so the sequence of operations
+    // doesn't depend on the orders.
+    fn sort_entries(entries: &mut [BookEntry; MAX_ORDERS], is_bid: bool) {
+        for round in 0..MAX_ORDERS {
+            for i in 0..(MAX_ORDERS - 1) {
+                if i % 2 == round % 2 {
+                    let swap = has_priority(&entries[i + 1], &entries[i], is_bid);
+                    let first = if swap { entries[i + 1] } else { entries[i] };
+                    let second = if swap { entries[i] } else { entries[i + 1] };
+                    entries[i] = first;
+                    entries[i + 1] = second;
+                }
+            }
         }
+    }
 
-        // Orders must be for the same pool and custody
-        if order_a.pool != order_b.pool || order_a.custody != order_b.custody {
-            return false;
+    // Order left after a partial fill, collateral is reduced in proportion to
+    // the filled size so the leverage of the order is unchanged
+    fn residual_order(order: &DarkOrder, remaining: u64) -> DarkOrder {
+        let collateral_amount = if order.size_usd > 0 {
+            (order.collateral_amount as u128 * remaining as u128 / order.size_usd as u128) as u64
+        } else {
+            0
+        };
+
+        DarkOrder {
+            size_usd: remaining,
+            collateral_amount,
+            ..*order
         }
+    }
 
-        // Price compatibility check
-        match (order_a.side, order_b.side) {
-            (0, 1) => order_a.max_price >= order_b.max_price, // long vs short
-            (1, 0) => order_b.max_price >= order_a.max_price, // short vs long
-            _ => false,
+    // Collateral backing the remaining size of an order, rounded down like the
+    // residual order so the fills of an order add up to the collateral it
+    // gives up
+    fn locked_collateral(entry: &BookEntry, remaining: u64) -> u64 {
+        if entry.size > 0 {
+            (entry.collateral as u128 * remaining as u128 / entry.size as u128) as u64
+        } else {
+            0
         }
     }
 
-    // Calculate execution price for matched orders
-    fn calculate_execution_price(order_a: &DarkOrder, order_b: &DarkOrder) -> u64 {
-        // Use midpoint of the two limit prices
-        (order_a.max_price + order_b.max_price) / 2
+    // Whether the bid is the resting order of the pair, the earliest of the two
+    fn is_resting(bid: &BookEntry, ask: &BookEntry) -> bool {
+        bid.timestamp < ask.timestamp || (bid.timestamp == ask.timestamp && bid.slot < ask.slot)
+    }
+
+    // Matched orders execute at the limit price of the resting order
+    fn calculate_execution_price(bid: &BookEntry, ask: &BookEntry) -> u64 {
+        if is_resting(bid, ask) {
+            bid.price
+        } else {
+            ask.price
+        }
+    }
+
+    // Uniform price batch auction over the orders of a market order book. The
+    // clearing price maximizes the matched volume, the crossing orders of the
+    // smaller side are filled completely and the other side is filled pro-rata.
+    // The clearing price must be within price_band_bps of the oracle price.
+    // The fill of every order is revealed by side with the collateral backing
+    // it, all at the clearing price. The program pairs them into settlement
+    // records, the residual orders are re-encrypted for the MXE. Pro-rata
+    // fills are proportional to the order sizes, so they reveal the relative
+    // sizes of the orders of the rationed side (see DARKPOOL_README.md).
+    #[allow(clippy::type_complexity)]
+    #[instruction]
+    pub fn run_batch_auction(
+        orders_context: [Enc<Mxe, DarkOrder>; MAX_ORDERS],
+        active: [bool; MAX_ORDERS],
+        oracle_price: u64,
+        price_band_bps: u64,
+    ) -> (
+        u64,
+        u64,
+        [u64; MAX_ORDERS],
+        [u64; MAX_ORDERS],
+        [u64; MAX_ORDERS],
+        [bool; MAX_ORDERS],
+        [Enc<Mxe, DarkOrder>; MAX_ORDERS],
+    ) {
+        let mut order_book = OrderBook::new();
+        for i in 0..MAX_ORDERS {
+            if active[i] {
+                order_book.add_order(i, orders_context[i].to_arcis());
+            }
+        }
+
+        let (min_price, max_price) = price_band(oracle_price, price_band_bps);
+        let (clearing_price, volume, residuals) = clear_auction(&order_book, min_price, max_price);
+
+        let mut long_fills = [0u64; MAX_ORDERS];
+        let mut short_fills = [0u64; MAX_ORDERS];
+        let mut fill_collateral = [0u64; MAX_ORDERS];
+        let mut filled = [false; MAX_ORDERS];
+        for i in 0..MAX_ORDERS {
+            let order = &order_book.orders[i];
+            let fill = order.size_usd - residuals[i].size_usd;
+            if order.side == 0 {
+                long_fills[i] = fill;
+            } else {
+                short_fills[i] = fill;
+            }
+            fill_collateral[i] = order.collateral_amount - residuals[i].collateral_amount;
+            filled[i] = (active[i] && residuals[i].size_usd == 0).reveal();
+        }
+
+        (
+            clearing_price.reveal(),
+            volume.reveal(),
+            long_fills.reveal(),
+            short_fills.reveal(),
+            fill_collateral.reveal(),
+            filled,
+            [
+                Mxe::get().from_arcis(residuals[0]),
+                Mxe::get().from_arcis(residuals[1]),
+                Mxe::get().from_arcis(residuals[2]),
+                Mxe::get().from_arcis(residuals[3]),
+                Mxe::get().from_arcis(residuals[4]),
+                Mxe::get().from_arcis(residuals[5]),
+                Mxe::get().from_arcis(residuals[6]),
+                Mxe::get().from_arcis(residuals[7]),
+            ],
+        )
+    }
+
+    // Band limits are public, price_band_bps is at most BPS_POWER
+    fn price_band(oracle_price: u64, price_band_bps: u64) -> (u64, u64) {
+        let band = (oracle_price as u128 * price_band_bps as u128 / BPS_POWER) as u64;
+        (oracle_price - band, oracle_price + band)
+    }
+
+    // Returns the clearing price within [min_price, max_price], the matched
+    // volume and the residual orders
+    pub fn clear_auction(
+        order_book: &OrderBook,
+        min_price: u64,
+        max_price: u64,
+    ) -> (u64, u64, [DarkOrder; MAX_ORDERS]) {
+        let mut sizes = [0u64; MAX_ORDERS];
+        for (i, order) in order_book.orders.iter().enumerate() {
+            if order_book.active[i] {
+                sizes[i] = order.size_usd;
+            }
+        }
+
+        // Candidate prices are the limit prices of the orders within the band
+        // and the upper band limit. Between two limit prices the matched
+        // volume is highest at the upper one, so the upper band limit stands
+        // in for the limit prices above the band. Ties on volume are broken by the smallest imbalance, then by the
+        // lowest price.
+        let mut candidates = [0u64; MAX_ORDERS + 1];
+        let mut in_band = [true; MAX_ORDERS + 1];
+        for (i, order) in order_book.orders.iter().enumerate() {
+            candidates[i] = order.max_price;
+            in_band[i] =
+                order.size_usd > 0 && order.max_price >= min_price && order.max_price <= max_price;
+        }
+        candidates[MAX_ORDERS] = max_price;
+
+        let mut clearing_price = 0u64;
+        let mut volume = 0u64;
+        let mut imbalance = 0u64;
+        let mut found = false;
+        for (i, price) in candidates.iter().enumerate() {
+            let price = *price;
+            let (demand, supply) = crossing_volume(order_book, &sizes, price);
+            let candidate_volume = demand.min(supply);
+            let candidate_imbalance = demand.max(supply) - candidate_volume;
+
+            let better = candidate_volume > volume
+                || (candidate_volume == volume
+                    && (candidate_imbalance < imbalance
+                        || (candidate_imbalance == imbalance && price < clearing_price)));
+            if in_band[i] && (!found || better) {
+                clearing_price = price;
+                volume = candidate_volume;
+                imbalance = candidate_imbalance;
+                found = true;
+            }
+        }
+        if volume == 0 {
+            clearing_price = 0;
+        }
+
+        // Pro-rata fills rounded down, the units lost to rounding go to the
+        // first orders of the side in slot order, so both sides fill exactly
+        // the matched volume
+        let (demand, supply) = crossing_volume(order_book, &sizes, clearing_price);
+        let mut fills = [0u64; MAX_ORDERS];
+        let mut long_filled = 0u64;
+        let mut short_filled = 0u64;
+        for (i, order) in order_book.orders.iter().enumerate() {
+            let is_long = order.side == 0;
+            let side_total = if is_long { demand } else { supply };
+            if crosses(order, clearing_price) {
+                fills[i] = (sizes[i] as u128 * volume as u128 / side_total.max(1) as u128) as u64;
+            }
+            if is_long {
+                long_filled += fills[i];
+            } else {
+                short_filled += fills[i];
+            }
+        }
+        let mut long_left = volume - long_filled.min(volume);
+        let mut short_left = volume - short_filled.min(volume);
+        for (i, order) in order_book.orders.iter().enumerate() {
+            let can_take = crosses(order, clearing_price) && fills[i] < sizes[i];
+            if can_take && order.side == 0 && long_left > 0 {
+                fills[i] += 1;
+                long_left -= 1;
+            }
+            if can_take && order.side == 1 && short_left > 0 {
+                fills[i] += 1;
+                short_left -= 1;
+            }
+        }
+
+        let mut residuals = order_book.orders;
+        for i in 0..MAX_ORDERS {
+            residuals[i] = residual_order(&order_book.orders[i], sizes[i] - fills[i]);
+        }
+
+        (clearing_price, volume, residuals)
+    }
+
+    // Whether an order would trade at the given price
+    fn crosses(order: &DarkOrder, price: u64) -> bool {
+        (order.side == 0 && order.max_price >= price)
+            || (order.side == 1 && order.max_price <= price)
+    }
+
+    // Long (demand) and short (supply) sizes willing to trade at the given price
+    fn crossing_volume(
+        order_book: &OrderBook,
+        sizes: &[u64; MAX_ORDERS],
+        price: u64,
+    ) -> (u64, u64) {
+        let mut demand = 0u64;
+        let mut supply = 0u64;
+        for (i, order) in order_book.orders.iter().enumerate() {
+            if crosses(order, price) && order.side == 0 {
+                demand += sizes[i];
+            }
+            if crosses(order, price) && order.side == 1 {
+                supply += sizes[i];
+            }
+        }
+
+        (demand, supply)
     }
 
     // Batch process multiple order submissions
     #[instruction]
     pub fn batch_process_orders(
-        batch_context: Enc<Shared, Vec<DarkOrder>>
+        batch_context: Enc<Shared, [DarkOrder; MAX_ORDERS]>
     ) -> Enc<Shared, MatchResult> {
         let orders = batch_context.to_arcis();
         let mut order_book = OrderBook::new();
 
         // Add all valid orders to the order book
-        for order in orders {
+        for (i, order) in orders.iter().enumerate() {
             if order.size_usd > 0 && order.collateral_amount > 0 {
-                order_book.add_order(order);
+                order_book.add_order(i, *order);
             }
         }
 
-        // Create context for matching
-        let orders_for_matching = batch_context.owner.from_arcis(order_book.orders);
-        
-        // Use the matching function
-        match_dark_orders(Enc::new(orders_for_matching, batch_context.owner))
+        let (match_result, _) = match_orders(&order_book, order_book.last_update, 0, u64::MAX);
+        batch_context.owner.from_arcis(match_result)
     }
 
     // Calculate position metrics in encrypted environment
@@ -185,7 +784,7 @@ mod circuits {
         position_data: Enc<Shared, (u64, u64, u64)> // (size_usd, collateral, price)
     ) -> Enc<Shared, (u64, u64)> { // (pnl, liquidation_price)
         let (size_usd, collateral, entry_price) = position_data.to_arcis();
-        
+
         // Simplified PnL calculation (would need current price in real implementation)
         let current_price = entry_price; // Placeholder
         let pnl = if current_price > entry_price {
@@ -196,6 +795,8 @@ mod circuits {
 
         // Simplified liquidation price calculation (90% of collateral)
         let liquidation_threshold = (collateral * 90) / 100;
+        // saturating_sub is not supported by Arcis
+        #[allow(clippy::implicit_saturating_sub)]
         let liquidation_price = if entry_price > liquidation_threshold {
             entry_price - liquidation_threshold
         } else {
@@ -205,3 +806,595 @@ mod circuits {
         position_data.owner.from_arcis((pnl, liquidation_price))
     }
 }
+
+
+// The #[encrypted] module is also compiled as plain Rust, so the circuit
+// helpers are executed here in plaintext. The oblivious matching is checked
+// against a straightforward sort and walk of the book.
+#[cfg(test)]
+mod tests {
+    use super::circuits::*;
+    use proptest::prelude::*;
+
+    const NO_BAND: (u64, u64) = (0, u64::MAX);
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Fill {
+        long_slot: usize,
+        short_slot: usize,
+        matched_size: u64,
+        execution_price: u64,
+        long_collateral: u64,
+        short_collateral: u64,
+    }
+
+    // Matches of the circuit in execution order, and the residual orders
+    fn run_matching(
+        order_book: &OrderBook,
+        band: (u64, u64),
+    ) -> (Vec<Fill>, [DarkOrder; MAX_ORDERS]) {
+        let (match_result, residuals) = match_orders(order_book, 0, band.0, band.1);
+        let fills: Vec<Fill> = match_result
+            .matches
+            .iter()
+            .filter(|order_match| order_match.is_match)
+            .map(|order_match| Fill {
+                long_slot: order_match.slot_a as usize,
+                short_slot: order_match.slot_b as usize,
+                matched_size: order_match.matched_size,
+                execution_price: order_match.execution_price,
+                long_collateral: order_match.collateral_a,
+                short_collateral: order_match.collateral_b,
+            })
+            .collect();
+        assert_eq!(match_result.match_count, fills.len() as u64);
+        assert_eq!(
+            match_result.total_volume,
+            fills.iter().map(|fill| fill.matched_size).sum::<u64>()
+        );
+
+        (fills, residuals)
+    }
+
+    // Clearing price, volume and size filled in every slot by the auction
+    fn run_auction(order_book: &OrderBook, band: (u64, u64)) -> (u64, u64, [u64; MAX_ORDERS]) {
+        let (clearing_price, volume, residuals) = clear_auction(order_book, band.0, band.1);
+        let mut fills = [0u64; MAX_ORDERS];
+        for i in 0..MAX_ORDERS {
+            fills[i] = order_book.orders[i].size_usd - residuals[i].size_usd;
+        }
+
+        (clearing_price, volume, fills)
+    }
+
+    fn locked_collateral(order: &DarkOrder, remaining: u64) -> u64 {
+        if order.size_usd > 0 {
+            (order.collateral_amount as u128 * remaining as u128 / order.size_usd as u128) as u64
+        } else {
+            0
+        }
+    }
+
+    // Sort both sides of the book and walk them
+    fn naive_match_orders(
+        order_book: &OrderBook,
+        band: (u64, u64),
+    ) -> (Vec<Fill>, [u64; MAX_ORDERS]) {
+        let orders = &order_book.orders;
+        let mut remaining = [0u64; MAX_ORDERS];
+        for i in 0..MAX_ORDERS {
+            if order_book.active[i] {
+                remaining[i] = orders[i].size_usd;
+            }
+        }
+
+        let side = |side: u8| {
+            (0..MAX_ORDERS)
+                .filter(|i| remaining[*i] > 0 && orders[*i].side == side)
+                .collect::<Vec<_>>()
+        };
+        let mut bids = side(0);
+        let mut asks = side(1);
+        bids.sort_by_key(|i| {
+            (
+                std::cmp::Reverse(orders[*i].max_price),
+                orders[*i].timestamp,
+                *i,
+            )
+        });
+        asks.sort_by_key(|i| (orders[*i].max_price, orders[*i].timestamp, *i));
+
+        let mut fills = Vec::new();
+        let (mut b, mut a) = (0, 0);
+        while b < bids.len() && a < asks.len() {
+            let (bid, ask) = (bids[b], asks[a]);
+            if orders[bid].max_price < orders[ask].max_price {
+                break;
+            }
+            let bid_rests = (orders[bid].timestamp, bid) < (orders[ask].timestamp, ask);
+            let execution_price = if bid_rests {
+                orders[bid].max_price
+            } else {
+                orders[ask].max_price
+            };
+            // The resting order is priced off the band, skip it
+            if execution_price < band.0 || execution_price > band.1 {
+                if bid_rests {
+                    b += 1;
+                } else {
+                    a += 1;
+                }
+                continue;
+            }
+            let matched_size = remaining[bid].min(remaining[ask]);
+            let long_collateral = locked_collateral(&orders[bid], remaining[bid])
+                - locked_collateral(&orders[bid], remaining[bid] - matched_size);
+            let short_collateral = locked_collateral(&orders[ask], remaining[ask])
+                - locked_collateral(&orders[ask], remaining[ask] - matched_size);
+            remaining[bid] -= matched_size;
+            remaining[ask] -= matched_size;
+            fills.push(Fill {
+                long_slot: bid,
+                short_slot: ask,
+                matched_size,
+                execution_price,
+                long_collateral,
+                short_collateral,
+            });
+            if remaining[bid] == 0 {
+                b += 1;
+            }
+            if remaining[ask] == 0 {
+                a += 1;
+            }
+        }
+
+        (fills, remaining)
+    }
+
+    fn crosses(order: &DarkOrder, price: u64) -> bool {
+        (order.side == 0 && order.max_price >= price)
+            || (order.side == 1 && order.max_price <= price)
+    }
+
+    // Long (demand) and short (supply) sizes willing to trade at the given price
+    fn crossing_volume(order_book: &OrderBook, price: u64) -> (u64, u64) {
+        let mut demand = 0u64;
+        let mut supply = 0u64;
+        for (order, active) in order_book.orders.iter().zip(order_book.active.iter()) {
+            if *active && crosses(order, price) && order.side == 0 {
+                demand += order.size_usd;
+            }
+            if *active && crosses(order, price) && order.side == 1 {
+                supply += order.size_usd;
+            }
+        }
+
+        (demand, supply)
+    }
+
+    fn order(side: u8, size_usd: u64, max_price: u64, timestamp: u64) -> DarkOrder {
+        DarkOrder {
+            side,
+            size_usd,
+            collateral_amount: size_usd / 10,
+            max_price,
+            timestamp,
+            ..DarkOrder::empty()
+        }
+    }
+
+    // Order book of the active slots, as loaded by the circuits
+    fn masked_book(orders: &[DarkOrder; MAX_ORDERS], active: &[bool; MAX_ORDERS]) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        for i in 0..MAX_ORDERS {
+            if active[i] {
+                order_book.add_order(i, orders[i]);
+            }
+        }
+        order_book
+    }
+
+    fn book(orders: &[DarkOrder]) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        for (slot, order) in orders.iter().enumerate() {
+            order_book.add_order(slot, *order);
+        }
+        order_book
+    }
+
+    fn order_strategy() -> impl Strategy<Value = DarkOrder> {
+        (
+            0u8..2,
+            1u64..1_000_000_000,
+            1u64..1_000_000_000,
+            95u64..105,
+            0u64..4,
+        )
+            .prop_map(
+                |(side, size_usd, collateral_amount, max_price, timestamp)| DarkOrder {
+                    side,
+                    size_usd,
+                    collateral_amount,
+                    max_price,
+                    timestamp,
+                    ..DarkOrder::empty()
+                },
+            )
+    }
+
+    fn band_strategy() -> impl Strategy<Value = (u64, u64)> {
+        prop_oneof![Just(NO_BAND), (90u64..101, 100u64..110)]
+    }
+
+    #[test]
+    fn test_partial_fill() {
+        let order_book = book(&[
+            order(0, 1_000, 100, 1),
+            order(1, 400, 99, 2),
+            order(1, 300, 98, 3),
+        ]);
+
+        let (fills, residuals) = run_matching(&order_book, NO_BAND);
+
+        // The cheaper short has priority, both execute at the resting long price
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[0].short_slot, fills[0].matched_size), (2, 300));
+        assert_eq!((fills[1].short_slot, fills[1].matched_size), (1, 400));
+        assert_eq!(fills[0].execution_price, 100);
+        assert_eq!(fills[1].execution_price, 100);
+        // Each fill carries the collateral released by both orders
+        assert_eq!(
+            (fills[0].long_collateral, fills[0].short_collateral),
+            (30, 30)
+        );
+        assert_eq!(
+            (fills[1].long_collateral, fills[1].short_collateral),
+            (40, 40)
+        );
+        assert_eq!(residuals[0].size_usd, 300);
+        assert_eq!(residuals[0].collateral_amount, 30);
+        assert_eq!(residuals[1].size_usd, 0);
+        assert_eq!(residuals[2].size_usd, 0);
+    }
+
+    #[test]
+    fn test_order_not_matched_twice() {
+        let order_book = book(&[
+            order(1, 500, 100, 1),
+            order(0, 500, 100, 2),
+            order(0, 500, 100, 3),
+        ]);
+
+        let (fills, residuals) = run_matching(&order_book, NO_BAND);
+
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].long_slot, fills[0].short_slot), (1, 0));
+        assert_eq!(residuals[0].size_usd, 0);
+        assert_eq!(residuals[2].size_usd, 500);
+    }
+
+    #[test]
+    fn test_price_time_priority() {
+        let order_book = book(&[
+            order(0, 100, 101, 5),
+            order(0, 100, 103, 6),
+            order(0, 100, 101, 4),
+            order(1, 250, 97, 7),
+        ]);
+
+        let (fills, residuals) = run_matching(&order_book, NO_BAND);
+
+        // Best price first, then the earlier order at the same price, each at
+        // its own resting price
+        let filled: Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.long_slot, fill.matched_size, fill.execution_price))
+            .collect();
+        assert_eq!(filled, vec![(1, 100, 103), (2, 100, 101), (0, 50, 101)]);
+        assert_eq!(residuals[0].size_usd, 50);
+        assert_eq!(residuals[3].size_usd, 0);
+    }
+
+    #[test]
+    fn test_execution_at_resting_price() {
+        let order_book = book(&[order(1, 100, 95, 1), order(0, 100, 105, 2)]);
+        let (fills, _) = run_matching(&order_book, NO_BAND);
+        assert_eq!(fills[0].execution_price, 95);
+
+        let order_book = book(&[order(1, 100, 95, 2), order(0, 100, 105, 1)]);
+        let (fills, _) = run_matching(&order_book, NO_BAND);
+        assert_eq!(fills[0].execution_price, 105);
+    }
+
+    #[test]
+    fn test_price_band() {
+        let order_book = book(&[
+            order(0, 100, 110, 1),
+            order(0, 100, 101, 2),
+            order(1, 150, 99, 3),
+        ]);
+
+        // The long resting at 110 is off the band and skipped, the next long
+        // matches at its price
+        let (fills, residuals) = run_matching(&order_book, (95, 105));
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].long_slot, fills[0].execution_price), (1, 101));
+        assert_eq!(residuals[0].size_usd, 100);
+        assert_eq!(residuals[2].size_usd, 50);
+
+        // The same long can still be filled as the aggressor
+        let order_book = book(&[order(1, 100, 100, 1), order(0, 100, 110, 2)]);
+        let (fills, _) = run_matching(&order_book, (95, 105));
+        assert_eq!(fills[0].execution_price, 100);
+    }
+
+    #[test]
+    fn test_price_band_skips_resting_order() {
+        let order_book = book(&[
+            order(0, 100, 110, 1),
+            order(1, 100, 99, 2),
+            order(1, 100, 100, 3),
+            order(0, 100, 102, 4),
+            order(0, 100, 101, 5),
+        ]);
+
+        let (fills, residuals) = run_matching(&order_book, (95, 105));
+
+        // Only the long resting at 110 is passed over, both shorts then match
+        // the younger longs at their own resting prices
+        let filled: Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.long_slot, fill.short_slot, fill.execution_price))
+            .collect();
+        assert_eq!(filled, vec![(3, 1, 99), (4, 2, 100)]);
+        assert_eq!(residuals[0].size_usd, 100);
+        assert_eq!(residuals[0].collateral_amount, 10);
+        assert!(residuals[1..5].iter().all(|order| order.size_usd == 0));
+    }
+
+    #[test]
+    fn test_batch_auction() {
+        let order_book = book(&[
+            order(0, 300, 102, 1),
+            order(0, 300, 100, 2),
+            order(1, 200, 99, 3),
+            order(1, 200, 101, 4),
+        ]);
+
+        let (clearing_price, volume, fills) = run_auction(&order_book, NO_BAND);
+
+        // 101 and 102 both clear 300 with the same imbalance, the long at 102
+        // is filled and the shorts are filled pro-rata
+        assert_eq!((clearing_price, volume), (101, 300));
+        assert_eq!(fills, [300, 0, 150, 150, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_batch_auction_no_cross() {
+        let order_book = book(&[order(0, 300, 99, 1), order(1, 300, 100, 2)]);
+
+        assert_eq!(run_auction(&order_book, NO_BAND), (0, 0, [0; MAX_ORDERS]));
+    }
+
+    #[test]
+    fn test_batch_auction_price_band() {
+        let order_book = book(&[
+            order(0, 300, 102, 1),
+            order(0, 300, 100, 2),
+            order(1, 200, 99, 3),
+            order(1, 200, 101, 4),
+        ]);
+
+        // 101 clears the most volume but is off the band, 99 and 100 both
+        // clear the short at 99 against both longs pro-rata
+        let (clearing_price, volume, fills) = run_auction(&order_book, (95, 100));
+        assert_eq!((clearing_price, volume), (99, 200));
+        assert_eq!(fills, [100, 100, 200, 0, 0, 0, 0, 0]);
+
+        // With every limit price off the band the orders still cross at the
+        // upper band limit
+        let order_book = book(&[order(0, 300, 110, 1), order(1, 200, 90, 2)]);
+        let (clearing_price, volume, fills) = run_auction(&order_book, (98, 102));
+        assert_eq!((clearing_price, volume), (102, 200));
+        assert_eq!(fills, [200, 200, 0, 0, 0, 0, 0, 0]);
+
+        // Nothing crosses within the band
+        let order_book = book(&[order(0, 300, 97, 1), order(1, 200, 96, 2)]);
+        assert_eq!(run_auction(&order_book, (98, 102)), (0, 0, [0; MAX_ORDERS]));
+    }
+
+    #[test]
+    fn test_margin_check() {
+        // 1x to 100x, collateral token with 9 decimals priced at $20, fully
+        // escrowed collateral
+        let collateral = |amount: u64, size_usd: u64| DarkOrder {
+            collateral_amount: amount,
+            ..order(0, size_usd, 100, 0)
+        };
+        let check = |order: &DarkOrder, open_position_fee: u64| {
+            validate_order(
+                order,
+                order.collateral_amount,
+                10_000,
+                1_000_000,
+                open_position_fee,
+                20_000_000,
+                9,
+            )
+        };
+
+        // $1,000 position on 5 tokens ($100 of collateral) is 10x
+        let valid = collateral(5_000_000_000, 1_000_000_000);
+        assert_eq!(check(&valid, 0), ORDER_VALID);
+        // $1,000 position on 0.25 tokens ($5 of collateral) is 200x
+        let too_high = collateral(250_000_000, 1_000_000_000);
+        assert_eq!(check(&too_high, 0), LEVERAGE_TOO_HIGH);
+        // $50 position on $100 of collateral is 0.5x
+        let too_low = collateral(5_000_000_000, 50_000_000);
+        assert_eq!(check(&too_low, 0), LEVERAGE_TOO_LOW);
+        // Collateral worth less than one micro-dollar
+        let dust = collateral(1, 1_000_000_000);
+        assert_eq!(check(&dust, 0), INSUFFICIENT_COLLATERAL);
+
+        // $1,000 position on 0.52 tokens ($10.40 of collateral) is 96x, a
+        // 0.1% open fee leaves $9.40 and pushes it to 106x
+        let near_max = collateral(520_000_000, 1_000_000_000);
+        assert_eq!(check(&near_max, 0), ORDER_VALID);
+        assert_eq!(check(&near_max, 10), LEVERAGE_TOO_HIGH);
+        // A 1% open fee on $1,000 takes all of $10 of collateral
+        let fee_only = collateral(500_000_000, 1_000_000_000);
+        assert_eq!(check(&fee_only, 0), ORDER_VALID);
+        assert_eq!(check(&fee_only, 100), INSUFFICIENT_COLLATERAL);
+    }
+
+    #[test]
+    fn test_order_fields_checked_first() {
+        let check = |order: &DarkOrder, escrow_amount: u64| {
+            validate_order(order, escrow_amount, 10_000, 1_000_000, 0, 1_000_000, 6)
+        };
+        let mut order = order(2, 0, 0, 0);
+
+        assert_eq!(check(&order, 0), INVALID_SIDE);
+        order.side = 1;
+        assert_eq!(check(&order, 0), INVALID_SIZE);
+        order.size_usd = 1_000;
+        assert_eq!(check(&order, 0), INVALID_PRICE);
+        order.max_price = 100;
+        assert_eq!(check(&order, 0), INSUFFICIENT_COLLATERAL);
+        order.collateral_amount = 100;
+        assert_eq!(check(&order, 0), INSUFFICIENT_ESCROW);
+        assert_eq!(check(&order, 99), INSUFFICIENT_ESCROW);
+        assert_eq!(check(&order, 100), ORDER_VALID);
+    }
+
+    proptest! {
+        #[test]
+        fn test_matches_naive_engine(
+            orders in prop::array::uniform8(order_strategy()),
+            active in prop::array::uniform8(any::<bool>()),
+            band in band_strategy(),
+        ) {
+            let order_book = masked_book(&orders, &active);
+            let (fills, residuals) = run_matching(&order_book, band);
+            let (expected_fills, expected_remaining) = naive_match_orders(&order_book, band);
+
+            prop_assert_eq!(fills, expected_fills);
+            for i in 0..MAX_ORDERS {
+                prop_assert_eq!(residuals[i].size_usd, expected_remaining[i]);
+            }
+        }
+
+        #[test]
+        fn test_volume_conservation(
+            orders in prop::array::uniform8(order_strategy()),
+            active in prop::array::uniform8(any::<bool>()),
+            band in band_strategy(),
+        ) {
+            let order_book = masked_book(&orders, &active);
+            let (fills, residuals) = run_matching(&order_book, band);
+            prop_assert!(fills.len() <= MAX_MATCHES);
+
+            let mut filled = [0u64; MAX_ORDERS];
+            let mut collateral = [0u64; MAX_ORDERS];
+            let mut total_volume = 0u64;
+            for fill in fills.iter() {
+                prop_assert!(active[fill.long_slot] && active[fill.short_slot]);
+                prop_assert_eq!(orders[fill.long_slot].side, 0);
+                prop_assert_eq!(orders[fill.short_slot].side, 1);
+                prop_assert!(fill.matched_size > 0);
+                prop_assert!(fill.execution_price >= band.0 && fill.execution_price <= band.1);
+                filled[fill.long_slot] += fill.matched_size;
+                filled[fill.short_slot] += fill.matched_size;
+                collateral[fill.long_slot] += fill.long_collateral;
+                collateral[fill.short_slot] += fill.short_collateral;
+                total_volume += fill.matched_size;
+            }
+
+            // Filled plus residual size of every order equals its size, the
+            // same holds for the collateral backing the fills, and the size
+            // consumed on each side equals the matched volume
+            let mut long_volume = 0u64;
+            let mut short_volume = 0u64;
+            for i in 0..MAX_ORDERS {
+                if active[i] {
+                    prop_assert_eq!(filled[i] + residuals[i].size_usd, orders[i].size_usd);
+                    prop_assert_eq!(
+                        collateral[i] + residuals[i].collateral_amount,
+                        orders[i].collateral_amount
+                    );
+                    let consumed = orders[i].size_usd - residuals[i].size_usd;
+                    if orders[i].side == 0 {
+                        long_volume += consumed;
+                    } else {
+                        short_volume += consumed;
+                    }
+                } else {
+                    prop_assert_eq!(filled[i], 0);
+                    prop_assert_eq!(residuals[i].size_usd, 0);
+                }
+            }
+            prop_assert_eq!(long_volume, total_volume);
+            prop_assert_eq!(short_volume, total_volume);
+
+            // No crossing orders are left in the book, unless they are priced
+            // off the band
+            if band != NO_BAND {
+                return Ok(());
+            }
+            let best = |side: u8| {
+                (0..MAX_ORDERS)
+                    .filter(move |i| residuals[*i].size_usd > 0 && orders[*i].side == side)
+            };
+            if let (Some(bid), Some(ask)) = (
+                best(0).map(|i| orders[i].max_price).max(),
+                best(1).map(|i| orders[i].max_price).min(),
+            ) {
+                prop_assert!(bid < ask);
+            }
+        }
+
+        #[test]
+        fn test_batch_auction_clearing(
+            orders in prop::array::uniform8(order_strategy()),
+            active in prop::array::uniform8(any::<bool>()),
+            band in band_strategy(),
+        ) {
+            let order_book = masked_book(&orders, &active);
+            let (clearing_price, volume, fills) = run_auction(&order_book, band);
+
+            // No price within the band clears more volume
+            if volume > 0 {
+                prop_assert!(clearing_price >= band.0 && clearing_price <= band.1);
+            }
+            for price in band.0.max(90)..=band.1.min(110) {
+                let (demand, supply) = crossing_volume(&order_book, price);
+                prop_assert!(demand.min(supply) <= volume);
+            }
+
+            // Both sides fill exactly the volume at the clearing price, within
+            // the order sizes and one unit of the exact pro-rata share
+            let (demand, supply) = crossing_volume(&order_book, clearing_price);
+            let mut long_volume = 0u64;
+            let mut short_volume = 0u64;
+            for i in 0..MAX_ORDERS {
+                let order = &order_book.orders[i];
+                prop_assert!(fills[i] <= order.size_usd);
+                if !active[i] || !crosses(order, clearing_price) {
+                    prop_assert_eq!(fills[i], 0);
+                    continue;
+                }
+                let side_total = if order.side == 0 { demand } else { supply };
+                let share = order.size_usd as u128 * volume as u128 / side_total as u128;
+                prop_assert!(fills[i] as u128 == share || fills[i] as u128 == share + 1);
+                if order.side == 0 {
+                    long_volume += fills[i];
+                } else {
+                    short_volume += fills[i];
+                }
+            }
+            prop_assert_eq!(long_volume, volume);
+            prop_assert_eq!(short_volume, volume);
+        }
+    }
+}
@@ -1,213 +1,824 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::Instruction,
+    program::{get_return_data, invoke, invoke_signed},
+};
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
 use arcium_anchor::prelude::*;
+use arcium_client::idl::arcium::types::CallbackAccount;
+
+use crate::{ID, ID_CONST};
 
 const COMP_DEF_OFFSET_SUBMIT_DARK_ORDER: u32 = comp_def_offset("submit_dark_order");
 const COMP_DEF_OFFSET_MATCH_DARK_ORDERS: u32 = comp_def_offset("match_dark_orders");
-const COMP_DEF_OFFSET_BATCH_PROCESS_ORDERS: u32 = comp_def_offset("batch_process_orders");
-
-declare_id!("DarkP00LMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL");
+const COMP_DEF_OFFSET_RUN_BATCH_AUCTION: u32 = comp_def_offset("run_batch_auction");
+
+// Number of order slots in a market order book, must match MAX_ORDERS in the circuits
+pub const MAX_ORDERS: usize = 8;
+// Maximum number of fills of a matching computation, must match MAX_MATCHES in the circuits
+pub const MAX_MATCHES: usize = MAX_ORDERS - 1;
+// Number of trade records of a settlement queue
+pub const MAX_PENDING_TRADES: usize = 2 * MAX_MATCHES;
+// Age after which a trade that could not be settled can be dropped from the
+// settlement queue, releasing the collateral backing it
+pub const TRADE_EXPIRY_SECONDS: i64 = 24 * 60 * 60;
+// Number of encrypted fields of a DarkOrder
+pub const DARK_ORDER_FIELDS: usize = 7;
+pub const BPS_POWER: u64 = 10_000;
+// Anchor discriminator of the perpetuals get_oracle_price instruction,
+// sha256("global:get_oracle_price")[..8]
+const GET_ORACLE_PRICE_DISCRIMINATOR: [u8; 8] = [200, 20, 0, 106, 56, 210, 230, 140];
+// Anchor discriminator of the perpetuals settle_dark_pool_trade instruction,
+// sha256("global:settle_dark_pool_trade")[..8]
+const SETTLE_DARK_POOL_TRADE_DISCRIMINATOR: [u8; 8] = [182, 2, 45, 98, 175, 10, 242, 103];
+// Anchor discriminator of the perpetuals Custody account,
+// sha256("account:Custody")[..8]
+const CUSTODY_DISCRIMINATOR: [u8; 8] = [1, 184, 48, 81, 93, 131, 63, 145];
+
+// ===== Computation Definition Initializers =====
+
+pub fn init_submit_dark_order_comp_def(
+    ctx: Context<InitSubmitDarkOrderCompDef>
+) -> Result<()> {
+    init_comp_def(ctx.accounts, true, 0, None, None)?;
+    Ok(())
+}
 
-#[arcium_program]
-pub mod darkpool_perpetuals {
-    use super::*;
+pub fn init_match_dark_orders_comp_def(
+    ctx: Context<InitMatchDarkOrdersCompDef>
+) -> Result<()> {
+    init_comp_def(ctx.accounts, true, 0, None, None)?;
+    Ok(())
+}
 
-    // ===== Computation Definition Initializers =====
+pub fn init_batch_process_orders_comp_def(
+    ctx: Context<InitBatchProcessOrdersCompDef>
+) -> Result<()> {
+    init_comp_def(ctx.accounts, true, 0, None, None)?;
+    Ok(())
+}
 
-    pub fn init_submit_dark_order_comp_def(
-        ctx: Context<InitSubmitDarkOrderCompDef>
-    ) -> Result<()> {
-        init_comp_def(ctx.accounts, true, 0, None, None)?;
-        Ok(())
-    }
+pub fn init_run_batch_auction_comp_def(
+    ctx: Context<InitRunBatchAuctionCompDef>
+) -> Result<()> {
+    init_comp_def(ctx.accounts, true, 0, None, None)?;
+    Ok(())
+}
 
-    pub fn init_match_dark_orders_comp_def(
-        ctx: Context<InitMatchDarkOrdersCompDef>
-    ) -> Result<()> {
-        init_comp_def(ctx.accounts, true, 0, None, None)?;
-        Ok(())
-    }
+// ===== Order Submission =====
+
+pub fn submit_dark_order(
+    ctx: Context<SubmitDarkOrder>,
+    computation_offset: u64,
+    encrypted_order: [[u8; 32]; DARK_ORDER_FIELDS], // Encrypted DarkOrder fields
+    pub_key: [u8; 32],
+    nonce: u128,
+    expires_at: i64,    // 0 = good till cancelled
+    escrow_amount: u64, // Collateral tokens deposited in escrow for the order
+) -> Result<()> {
+    // Margin is checked inside the computation against the leverage limits
+    // of the position custody, with the collateral valued at its oracle price
+    let custody = read_custody(&ctx.accounts.custody, &ctx.accounts.perpetuals_program)?;
+    let collateral_custody = read_custody(
+        &ctx.accounts.collateral_custody,
+        &ctx.accounts.perpetuals_program,
+    )?;
+    require_keys_eq!(
+        ctx.accounts.collateral_mint.key(),
+        collateral_custody.mint,
+        ErrorCode::InvalidCustody
+    );
+    let collateral_price = get_oracle_price(
+        &ctx.accounts.perpetuals_program,
+        &ctx.accounts.perpetuals,
+        &ctx.accounts.pool,
+        &ctx.accounts.collateral_custody,
+        &ctx.accounts.collateral_custody_oracle_account,
+    )?;
+
+    // Store order metadata in darkpool account for tracking
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.total_orders += 1;
+    darkpool.last_order_time = Clock::get()?.unix_timestamp;
+    let order_id = darkpool.total_orders;
+    let timestamp = darkpool.last_order_time;
+
+    require!(
+        expires_at == 0 || expires_at > timestamp,
+        ErrorCode::InvalidExpiration
+    );
+
+    // Fund the escrow, the computation checks that the deposit covers the
+    // order collateral. Tokens withheld by the mint's transfer fee don't
+    // back the order.
+    let escrow_before = ctx.accounts.escrow.amount;
+    transfer_checked(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.funding_account.to_account_info(),
+                mint: ctx.accounts.collateral_mint.to_account_info(),
+                to: ctx.accounts.escrow.to_account_info(),
+                authority: ctx.accounts.owner.to_account_info(),
+            },
+        ),
+        escrow_amount,
+        ctx.accounts.collateral_mint.decimals,
+    )?;
+    ctx.accounts.escrow.reload()?;
+    let escrowed = ctx.accounts.escrow.amount - escrow_before;
+
+    // Reserve an order book slot, the callback stores the order in it
+    // once it has been validated
+    let owner = ctx.accounts.owner.key();
+    let slot = ctx.accounts.order_book.reserve_slot(
+        owner,
+        order_id,
+        pub_key,
+        timestamp,
+        expires_at,
+        escrowed,
+    )?;
+
+    let mut args = vec![
+        Argument::ArcisPubkey(pub_key),
+        Argument::PlaintextU128(nonce),
+        Argument::EncryptedU8(encrypted_order[0]),
+    ];
+    args.extend(
+        encrypted_order[1..]
+            .iter()
+            .map(|field| Argument::EncryptedU64(*field)),
+    );
+    args.push(Argument::PlaintextU8(slot as u8));
+    args.push(Argument::PlaintextU64(order_id));
+    args.push(Argument::PlaintextU64(escrowed));
+    args.push(Argument::PlaintextU64(custody.pricing.min_initial_leverage));
+    args.push(Argument::PlaintextU64(custody.pricing.max_initial_leverage));
+    args.push(Argument::PlaintextU64(custody.fees.open_position));
+    args.push(Argument::PlaintextU64(collateral_price));
+    args.push(Argument::PlaintextU8(collateral_custody.decimals));
+
+    let callback_accounts = vec![CallbackAccount {
+        pubkey: ctx.accounts.order_book.key(),
+        is_writable: true,
+    }];
+
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        args,
+        callback_accounts,
+        None,
+    )?;
+
+    emit!(DarkOrderSubmitted {
+        owner,
+        order_book: ctx.accounts.order_book.key(),
+        slot: slot as u8,
+        computation_offset,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-    pub fn init_batch_process_orders_comp_def(
-        ctx: Context<InitBatchProcessOrdersCompDef>
-    ) -> Result<()> {
-        init_comp_def(ctx.accounts, true, 0, None, None)?;
-        Ok(())
+pub fn submit_dark_order_callback(
+    ctx: Context<SubmitDarkOrderCallback>,
+    output: ComputationOutputs<SubmitDarkOrderOutput>,
+) -> Result<()> {
+    let (slot, order_id, reason, order) = match output {
+        ComputationOutputs::Success(SubmitDarkOrderOutput {
+            field_0:
+                SubmitDarkOrderTupleStruct0 {
+                    field_0: slot,
+                    field_1: order_id,
+                    field_2: reason,
+                    field_3: order,
+                },
+        }) => (slot as usize, order_id, reason, order),
+        _ => {
+            // The failed submission can't be told apart from the other
+            // pending ones, they are all released so no slot stays reserved.
+            // The owners withdraw their escrow and submit again.
+            let order_book = &mut ctx.accounts.order_book;
+            let timestamp = Clock::get()?.unix_timestamp;
+            for (slot, owner) in order_book.release_pending_slots() {
+                emit!(DarkOrderRemoved {
+                    owner,
+                    order_book: order_book.key(),
+                    slot: slot as u8,
+                    expired: false,
+                    timestamp,
+                });
+            }
+            return Ok(());
+        }
+    };
+
+    // The slot is always filled so validity is not revealed, an invalid
+    // order is stored with a zero size and never matches. Only the owner
+    // can decrypt the reason code and cancel the order.
+    let order_book = &mut ctx.accounts.order_book;
+    let owner = order_book.get_slot(slot)?.owner;
+    if !order_book.fill_slot(slot, order_id, order.nonce, order.ciphertexts)? {
+        msg!("Order slot {} was released, ignoring the validation", slot);
+        return Ok(());
     }
 
-    // ===== Order Submission =====
+    emit!(DarkOrderValidated {
+        owner,
+        order_book: order_book.key(),
+        slot: slot as u8,
+        encryption_key: reason.encryption_key,
+        reason_nonce: reason.nonce,
+        reason: reason.ciphertexts[0],
+    });
 
-    pub fn submit_dark_order(
-        ctx: Context<SubmitDarkOrder>,
-        computation_offset: u64,
-        encrypted_order: [u8; 256], // Encrypted DarkOrder struct
-        pub_key: [u8; 32],
-        nonce: u128,
-    ) -> Result<()> {
-        // Store order metadata in darkpool account for tracking
-        let darkpool = &mut ctx.accounts.darkpool;
-        darkpool.total_orders += 1;
-        darkpool.last_order_time = Clock::get()?.unix_timestamp;
-
-        let args = vec![
-            Argument::ArcisPubkey(pub_key),
-            Argument::PlaintextU128(nonce),
-            Argument::EncryptedBytes(encrypted_order.to_vec()),
-        ];
-
-        queue_computation(ctx.accounts, computation_offset, args, vec![], None)?;
-        
-        emit!(DarkOrderSubmitted {
-            owner: ctx.accounts.owner.key(),
-            computation_offset,
-            timestamp: darkpool.last_order_time,
-        });
+    Ok(())
+}
 
-        Ok(())
-    }
+// ===== Order Removal =====
+
+pub fn cancel_dark_order(ctx: Context<CancelDarkOrder>, slot: u8) -> Result<()> {
+    let order_book = &mut ctx.accounts.order_book;
+    order_book.cancel_slot(slot as usize, ctx.accounts.owner.key())?;
+
+    let timestamp = Clock::get()?.unix_timestamp;
+    emit!(DarkOrderRemoved {
+        owner: ctx.accounts.owner.key(),
+        order_book: order_book.key(),
+        slot,
+        expired: false,
+        timestamp,
+    });
+
+    // Return the collateral no longer backing an order or a pending trade
+    let amount = refundable_amount(
+        ctx.accounts.escrow.amount,
+        &ctx.accounts.order_book,
+        &ctx.accounts.settlement_queue,
+        ctx.accounts.owner.key(),
+    );
+    refund_escrow(
+        &ctx.accounts.darkpool,
+        &ctx.accounts.escrow,
+        &ctx.accounts.receiving_account,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    emit!(EscrowRefunded {
+        owner: ctx.accounts.owner.key(),
+        order_book: ctx.accounts.order_book.key(),
+        amount,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-    #[arcium_callback(encrypted_ix = "submit_dark_order")]
-    pub fn submit_dark_order_callback(
-        ctx: Context<SubmitDarkOrderCallback>,
-        output: ComputationOutputs<SubmitDarkOrderOutput>,
-    ) -> Result<()> {
-        let is_valid = match output {
-            ComputationOutputs::Success(SubmitDarkOrderOutput { field_0: result }) => {
-                // Decrypt the validation result
-                result.ciphertexts.len() > 0
-            },
-            _ => return Err(ErrorCode::OrderValidationFailed.into()),
-        };
+// Withdraw the escrow left over by filled, expired or rejected orders
+pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>) -> Result<()> {
+    let amount = refundable_amount(
+        ctx.accounts.escrow.amount,
+        &ctx.accounts.order_book,
+        &ctx.accounts.settlement_queue,
+        ctx.accounts.owner.key(),
+    );
+    require!(amount > 0, ErrorCode::NothingToWithdraw);
+
+    refund_escrow(
+        &ctx.accounts.darkpool,
+        &ctx.accounts.escrow,
+        &ctx.accounts.receiving_account,
+        &ctx.accounts.collateral_mint,
+        &ctx.accounts.token_program,
+        amount,
+    )?;
+
+    emit!(EscrowRefunded {
+        owner: ctx.accounts.owner.key(),
+        order_book: ctx.accounts.order_book.key(),
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
 
-        if !is_valid {
-            return Err(ErrorCode::InvalidOrderParameters.into());
-        }
+// Anyone can remove an order once it has expired
+pub fn expire_dark_order(ctx: Context<ExpireDarkOrder>, slot: u8) -> Result<()> {
+    let timestamp = Clock::get()?.unix_timestamp;
+
+    let order_book = &mut ctx.accounts.order_book;
+    let order = order_book.get_slot(slot as usize)?;
+    require!(
+        order.status == OrderStatus::Active,
+        ErrorCode::OrderNotActive
+    );
+    require!(order.is_expired(timestamp), ErrorCode::OrderNotExpired);
+    let owner = order.owner;
+
+    order_book.release_slot(slot as usize)?;
+
+    emit!(DarkOrderRemoved {
+        owner,
+        order_book: order_book.key(),
+        slot,
+        expired: true,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-        emit!(DarkOrderValidated {
-            owner: ctx.accounts.owner.key(),
-            is_valid,
-        });
+// ===== Order Matching =====
+
+pub fn match_dark_orders(ctx: Context<MatchDarkOrders>, computation_offset: u64) -> Result<()> {
+    let timestamp = Clock::get()?.unix_timestamp;
+    let oracle_price = get_oracle_price(
+        &ctx.accounts.perpetuals_program,
+        &ctx.accounts.perpetuals,
+        &ctx.accounts.pool,
+        &ctx.accounts.custody,
+        &ctx.accounts.custody_oracle_account,
+    )?;
+
+    // Every fill of the computation gets a settlement record
+    ctx.accounts.settlement_queue.reserve_trades()?;
+
+    // Expired orders are skipped, they stay in the order book until removed.
+    // Included orders are locked until the callback writes back the residuals.
+    let order_book = &mut ctx.accounts.order_book;
+    let active = order_book.start_matching(timestamp)?;
+
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.total_matches += 1;
+    darkpool.last_match_time = timestamp;
+
+    let mut args = order_arguments(order_book, &active);
+    // On chain submission times are used for time priority
+    args.extend(
+        order_book
+            .orders
+            .iter()
+            .map(|order| Argument::PlaintextU64(order.submitted_at as u64)),
+    );
+    args.push(Argument::PlaintextU64(oracle_price));
+    args.push(Argument::PlaintextU64(order_book.price_band_bps));
+    args.push(Argument::PlaintextU64(timestamp as u64));
+
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: ctx.accounts.darkpool.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.settlement_queue.key(),
+            is_writable: true,
+        },
+    ];
+
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        args,
+        callback_accounts,
+        None,
+    )?;
+
+    emit!(DarkOrderMatching {
+        order_book: ctx.accounts.order_book.key(),
+        computation_offset,
+        oracle_price,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-        Ok(())
+pub fn match_dark_orders_callback(
+    ctx: Context<MatchDarkOrdersCallback>,
+    output: ComputationOutputs<MatchDarkOrdersOutput>,
+) -> Result<()> {
+    let MatchDarkOrdersTupleStruct0 {
+        field_0: long_slots,
+        field_1: short_slots,
+        field_2: sizes,
+        field_3: prices,
+        field_4: long_collateral,
+        field_5: short_collateral,
+        field_6: filled,
+        field_7: residuals,
+    } = match output {
+        ComputationOutputs::Success(MatchDarkOrdersOutput { field_0 }) => field_0,
+        _ => {
+            // Unlock the orders and the reserved records, the orders can be
+            // matched again
+            ctx.accounts.order_book.abort_matching();
+            ctx.accounts.settlement_queue.release_reserved_trades();
+            emit!(DarkOrderMatchingFailed {
+                order_book: ctx.accounts.order_book.key(),
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            return Ok(());
+        }
+    };
+    let timestamp = Clock::get()?.unix_timestamp;
+
+    // Record the revealed fills for settlement. The owners are read before
+    // the slots of filled orders are released.
+    let order_book = &mut ctx.accounts.order_book;
+    let settlement_queue = &mut ctx.accounts.settlement_queue;
+    settlement_queue.release_reserved_trades();
+    let mut total_matches = 0u64;
+    let mut total_volume = 0u64;
+    let mut total_value = 0u128;
+    for (i, size_usd) in sizes.iter().enumerate() {
+        if *size_usd == 0 {
+            continue;
+        }
+        let long_slot = long_slots[i] as usize;
+        let short_slot = short_slots[i] as usize;
+        // The collateral of the fill stays in escrow until settlement
+        order_book.take_escrow(long_slot, long_collateral[i])?;
+        order_book.take_escrow(short_slot, short_collateral[i])?;
+        settlement_queue.push_trade(DarkTrade {
+            trader_a: order_book.get_slot(long_slot)?.owner,
+            trader_b: order_book.get_slot(short_slot)?.owner,
+            size_usd: *size_usd,
+            price: prices[i],
+            collateral_a: long_collateral[i],
+            collateral_b: short_collateral[i],
+            timestamp,
+            ..DarkTrade::default()
+        })?;
+        total_matches += 1;
+        total_volume += size_usd;
+        total_value += *size_usd as u128 * prices[i] as u128;
     }
 
-    // ===== Order Matching =====
-
-    pub fn match_dark_orders(
-        ctx: Context<MatchDarkOrders>,
-        computation_offset: u64,
-        encrypted_orders: Vec<u8>, // Batch of encrypted orders
-        pub_key: [u8; 32],
-        nonce: u128,
-    ) -> Result<()> {
-        let darkpool = &mut ctx.accounts.darkpool;
-        darkpool.total_matches += 1;
-        darkpool.last_match_time = Clock::get()?.unix_timestamp;
+    // Filled orders free their slot, partially filled orders keep it with
+    // the remaining size
+    for (slot, residual) in residuals.iter().enumerate() {
+        if order_book.orders[slot].status != OrderStatus::Matching {
+            continue;
+        }
+        if filled[slot] {
+            order_book.release_slot(slot)?;
+        } else {
+            order_book.update_slot(slot, residual.nonce, residual.ciphertexts)?;
+        }
+    }
 
-        let args = vec![
-            Argument::ArcisPubkey(pub_key),
-            Argument::PlaintextU128(nonce),
-            Argument::EncryptedBytes(encrypted_orders),
-        ];
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.total_volume += total_volume;
+
+    emit!(DarkOrdersMatched {
+        order_book: order_book.key(),
+        filled_orders: filled.iter().filter(|filled| **filled).count() as u8,
+        total_matches,
+        total_volume,
+        average_price: if total_volume > 0 {
+            (total_value / total_volume as u128) as u64
+        } else {
+            0
+        },
+        timestamp,
+    });
+
+    Ok(())
+}
 
-        queue_computation(ctx.accounts, computation_offset, args, vec![], None)?;
+// ===== Batch Auction =====
+
+// Clear all the orders of an order book at a single price, at most once
+// per epoch. The clearing price is bounded by the price band of the order
+// book around the oracle price.
+pub fn run_batch_auction(ctx: Context<RunBatchAuction>, computation_offset: u64) -> Result<()> {
+    let timestamp = Clock::get()?.unix_timestamp;
+    let oracle_price = get_oracle_price(
+        &ctx.accounts.perpetuals_program,
+        &ctx.accounts.perpetuals,
+        &ctx.accounts.pool,
+        &ctx.accounts.custody,
+        &ctx.accounts.custody_oracle_account,
+    )?;
+
+    let auction_epoch = &mut ctx.accounts.auction_epoch;
+    require!(!auction_epoch.in_progress, ErrorCode::AuctionInProgress);
+    require!(
+        timestamp >= auction_epoch.epoch_start + auction_epoch.epoch_duration,
+        ErrorCode::EpochNotEnded
+    );
+    auction_epoch.in_progress = true;
+    let epoch = auction_epoch.epoch;
+
+    // Every fill of the computation gets a settlement record
+    ctx.accounts.settlement_queue.reserve_trades()?;
+
+    let order_book = &mut ctx.accounts.order_book;
+    let active = order_book.start_matching(timestamp)?;
+    let mut args = order_arguments(order_book, &active);
+    args.push(Argument::PlaintextU64(oracle_price));
+    args.push(Argument::PlaintextU64(order_book.price_band_bps));
+
+    let callback_accounts = vec![
+        CallbackAccount {
+            pubkey: ctx.accounts.darkpool.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.order_book.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.settlement_queue.key(),
+            is_writable: true,
+        },
+        CallbackAccount {
+            pubkey: ctx.accounts.auction_epoch.key(),
+            is_writable: true,
+        },
+    ];
+
+    queue_computation(
+        ctx.accounts,
+        computation_offset,
+        args,
+        callback_accounts,
+        None,
+    )?;
+
+    emit!(BatchAuctionStarted {
+        order_book: ctx.accounts.order_book.key(),
+        epoch,
+        computation_offset,
+        oracle_price,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-        emit!(DarkOrderMatching {
-            computation_offset,
-            timestamp: darkpool.last_match_time,
-        });
+pub fn run_batch_auction_callback(
+    ctx: Context<RunBatchAuctionCallback>,
+    output: ComputationOutputs<RunBatchAuctionOutput>,
+) -> Result<()> {
+    let RunBatchAuctionTupleStruct0 {
+        field_0: clearing_price,
+        field_1: volume,
+        field_2: long_fills,
+        field_3: short_fills,
+        field_4: fill_collateral,
+        field_5: filled,
+        field_6: residuals,
+    } = match output {
+        ComputationOutputs::Success(RunBatchAuctionOutput { field_0 }) => field_0,
+        _ => {
+            // Unlock the orders and the reserved records, the epoch isn't
+            // cleared so the auction can be run again right away
+            ctx.accounts.order_book.abort_matching();
+            ctx.accounts.settlement_queue.release_reserved_trades();
+            let auction_epoch = &mut ctx.accounts.auction_epoch;
+            auction_epoch.in_progress = false;
+            emit!(BatchAuctionFailed {
+                order_book: ctx.accounts.order_book.key(),
+                epoch: auction_epoch.epoch,
+                timestamp: Clock::get()?.unix_timestamp,
+            });
+            return Ok(());
+        }
+    };
+    let timestamp = Clock::get()?.unix_timestamp;
+
+    // Record the fills for settlement, all at the clearing price. The owners
+    // are read before the slots of filled orders are released.
+    let order_book = &mut ctx.accounts.order_book;
+    let settlement_queue = &mut ctx.accounts.settlement_queue;
+    settlement_queue.release_reserved_trades();
+    for fill in pair_auction_fills(&long_fills, &short_fills, &fill_collateral) {
+        // The collateral of the fill stays in escrow until settlement
+        order_book.take_escrow(fill.long_slot, fill.collateral_a)?;
+        order_book.take_escrow(fill.short_slot, fill.collateral_b)?;
+        settlement_queue.push_trade(DarkTrade {
+            trader_a: order_book.get_slot(fill.long_slot)?.owner,
+            trader_b: order_book.get_slot(fill.short_slot)?.owner,
+            size_usd: fill.size_usd,
+            price: clearing_price,
+            collateral_a: fill.collateral_a,
+            collateral_b: fill.collateral_b,
+            timestamp,
+            ..DarkTrade::default()
+        })?;
+    }
 
-        Ok(())
+    // Filled orders free their slot, partially filled orders keep it with
+    // the remaining size
+    for (slot, residual) in residuals.iter().enumerate() {
+        if order_book.orders[slot].status != OrderStatus::Matching {
+            continue;
+        }
+        if filled[slot] {
+            order_book.release_slot(slot)?;
+        } else {
+            order_book.update_slot(slot, residual.nonce, residual.ciphertexts)?;
+        }
     }
 
-    #[arcium_callback(encrypted_ix = "match_dark_orders")]
-    pub fn match_dark_orders_callback(
-        ctx: Context<MatchDarkOrdersCallback>,
-        output: ComputationOutputs<MatchDarkOrdersOutput>,
-    ) -> Result<()> {
-        let match_result = match output {
-            ComputationOutputs::Success(MatchDarkOrdersOutput { field_0: result }) => result,
-            _ => return Err(ErrorCode::MatchingFailed.into()),
-        };
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.total_matches += 1;
+    darkpool.total_volume += volume;
+    darkpool.last_match_time = timestamp;
+
+    let auction_epoch = &mut ctx.accounts.auction_epoch;
+    let epoch = auction_epoch.epoch;
+    auction_epoch.epoch += 1;
+    auction_epoch.epoch_start = timestamp;
+    auction_epoch.in_progress = false;
+    auction_epoch.last_clearing_price = clearing_price;
+    auction_epoch.last_volume = volume;
+
+    emit!(BatchAuctionCleared {
+        order_book: order_book.key(),
+        epoch,
+        clearing_price,
+        volume,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-        // Update darkpool statistics
-        let darkpool = &mut ctx.accounts.darkpool;
-        darkpool.total_volume += extract_volume_from_result(&match_result);
-        
-        // Emit event for external settlement processing
-        emit!(DarkOrdersMatched {
-            total_matches: extract_match_count(&match_result),
-            total_volume: extract_volume_from_result(&match_result),
-            average_price: extract_average_price(&match_result),
-            timestamp: Clock::get()?.unix_timestamp,
-        });
+// ===== Settlement Integration =====
+
+// Settle a trade recorded by a matching callback. The record is written
+// from the revealed computation output, so anyone can trigger settlement.
+pub fn settle_dark_pool_trade(ctx: Context<SettleDarkPoolTrade>, trade_id: u64) -> Result<()> {
+    let trade = ctx.accounts.settlement_queue.take_trade(trade_id)?;
+    require_keys_eq!(
+        ctx.accounts.long_owner.key(),
+        trade.trader_a,
+        ErrorCode::TraderMismatch
+    );
+    require_keys_eq!(
+        ctx.accounts.short_owner.key(),
+        trade.trader_b,
+        ErrorCode::TraderMismatch
+    );
+
+    // Open both positions on the perpetuals program, signed by the
+    // darkpool PDA so that no relayer has to be trusted with the trade
+    settle_perpetuals_trade(ctx.accounts, &trade)?;
+
+    let order_book = &ctx.accounts.order_book;
+    emit!(DarkPoolTradeSettlement {
+        trade_id,
+        trader_a: trade.trader_a,
+        trader_b: trade.trader_b,
+        size_usd: trade.size_usd,
+        price: trade.price,
+        collateral_a: trade.collateral_a,
+        collateral_b: trade.collateral_b,
+        pool: order_book.pool,
+        custody: order_book.custody,
+        collateral_custody: order_book.collateral_custody,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.total_settlements += 1;
+
+    Ok(())
+}
 
-        Ok(())
-    }
+// Anyone can drop a trade that is still pending TRADE_EXPIRY_SECONDS after
+// it was matched, e.g. because the perpetuals program keeps rejecting it.
+// The collateral backing the trade becomes refundable with withdraw_escrow.
+pub fn expire_dark_trade(ctx: Context<ExpireDarkTrade>, trade_id: u64) -> Result<()> {
+    let timestamp = Clock::get()?.unix_timestamp;
+    let trade = ctx
+        .accounts
+        .settlement_queue
+        .expire_trade(trade_id, timestamp)?;
+
+    emit!(DarkTradeExpired {
+        trade_id,
+        order_book: ctx.accounts.order_book.key(),
+        trader_a: trade.trader_a,
+        trader_b: trade.trader_b,
+        collateral_a: trade.collateral_a,
+        collateral_b: trade.collateral_b,
+        timestamp,
+    });
+
+    Ok(())
+}
 
-    // ===== Settlement Integration =====
+// ===== Administration =====
+
+pub fn initialize_darkpool(
+    ctx: Context<InitializeDarkpool>,
+    params: InitializeDarkpoolParams,
+) -> Result<()> {
+    let darkpool = &mut ctx.accounts.darkpool;
+    darkpool.authority = ctx.accounts.authority.key();
+    darkpool.perpetuals_program = params.perpetuals_program;
+    darkpool.min_order_size = params.min_order_size;
+    darkpool.max_order_size = params.max_order_size;
+    darkpool.fee_rate = params.fee_rate;
+    darkpool.total_orders = 0;
+    darkpool.total_matches = 0;
+    darkpool.total_settlements = 0;
+    darkpool.total_volume = 0;
+    darkpool.last_order_time = 0;
+    darkpool.last_match_time = 0;
+    darkpool.bump = ctx.bumps.darkpool;
+
+    emit!(DarkpoolInitialized {
+        darkpool: darkpool.key(),
+        authority: darkpool.authority,
+        perpetuals_program: darkpool.perpetuals_program,
+    });
+
+    Ok(())
+}
 
-    pub fn settle_dark_pool_trades(
-        ctx: Context<SettleDarkPoolTrades>,
-        settlement_data: SettlementData,
-    ) -> Result<()> {
-        // Verify the settlement data comes from our darkpool matching
-        require!(
-            settlement_data.darkpool == ctx.accounts.darkpool.key(),
-            ErrorCode::InvalidSettlementData
-        );
+pub fn initialize_order_book(
+    ctx: Context<InitializeOrderBook>,
+    params: InitializeOrderBookParams,
+) -> Result<()> {
+    require!(
+        params.price_band_bps <= BPS_POWER,
+        ErrorCode::InvalidPriceBand
+    );
+
+    let order_book = &mut ctx.accounts.order_book;
+    order_book.darkpool = ctx.accounts.darkpool.key();
+    order_book.pool = params.pool;
+    order_book.custody = params.custody;
+    order_book.collateral_custody = params.collateral_custody;
+    order_book.active_orders = 0;
+    order_book.price_band_bps = params.price_band_bps;
+    order_book.bump = ctx.bumps.order_book;
+    order_book.orders = [DarkOrderSlot::default(); MAX_ORDERS];
+
+    emit!(OrderBookInitialized {
+        order_book: order_book.key(),
+        pool: order_book.pool,
+        custody: order_book.custody,
+        collateral_custody: order_book.collateral_custody,
+    });
+
+    Ok(())
+}
 
-        // Process each trade settlement
-        for trade in settlement_data.trades {
-            // Validate trade parameters
-            require!(trade.size_usd > 0, ErrorCode::InvalidTradeSize);
-            require!(trade.price > 0, ErrorCode::InvalidTradePrice);
-
-            // Emit settlement event that can be picked up by perpetuals program
-            emit!(DarkPoolTradeSettlement {
-                trader_a: trade.trader_a,
-                trader_b: trade.trader_b,
-                size_usd: trade.size_usd,
-                price: trade.price,
-                pool: trade.pool,
-                custody: trade.custody,
-                timestamp: Clock::get()?.unix_timestamp,
-            });
-        }
+pub fn set_price_band(ctx: Context<SetPriceBand>, params: SetPriceBandParams) -> Result<()> {
+    require!(
+        params.price_band_bps <= BPS_POWER,
+        ErrorCode::InvalidPriceBand
+    );
 
-        let darkpool = &mut ctx.accounts.darkpool;
-        darkpool.total_settlements += settlement_data.trades.len() as u64;
+    ctx.accounts.order_book.price_band_bps = params.price_band_bps;
 
-        Ok(())
-    }
+    Ok(())
+}
 
-    // ===== Administration =====
+pub fn initialize_auction_epoch(
+    ctx: Context<InitializeAuctionEpoch>,
+    params: InitializeAuctionEpochParams,
+) -> Result<()> {
+    require!(params.epoch_duration >= 0, ErrorCode::InvalidEpochDuration);
+
+    let auction_epoch = &mut ctx.accounts.auction_epoch;
+    auction_epoch.order_book = ctx.accounts.order_book.key();
+    auction_epoch.epoch = 0;
+    auction_epoch.epoch_duration = params.epoch_duration;
+    auction_epoch.epoch_start = Clock::get()?.unix_timestamp;
+    auction_epoch.in_progress = false;
+    auction_epoch.last_clearing_price = 0;
+    auction_epoch.last_volume = 0;
+    auction_epoch.bump = ctx.bumps.auction_epoch;
+
+    Ok(())
+}
 
-    pub fn initialize_darkpool(
-        ctx: Context<InitializeDarkpool>,
-        params: InitializeDarkpoolParams,
-    ) -> Result<()> {
-        let darkpool = &mut ctx.accounts.darkpool;
-        darkpool.authority = ctx.accounts.authority.key();
-        darkpool.perpetuals_program = params.perpetuals_program;
-        darkpool.min_order_size = params.min_order_size;
-        darkpool.max_order_size = params.max_order_size;
-        darkpool.fee_rate = params.fee_rate;
-        darkpool.total_orders = 0;
-        darkpool.total_matches = 0;
-        darkpool.total_settlements = 0;
-        darkpool.total_volume = 0;
-        darkpool.last_order_time = 0;
-        darkpool.last_match_time = 0;
-        darkpool.bump = ctx.bumps.darkpool;
-
-        emit!(DarkpoolInitialized {
-            darkpool: darkpool.key(),
-            authority: darkpool.authority,
-            perpetuals_program: darkpool.perpetuals_program,
-        });
+pub fn initialize_settlement_queue(ctx: Context<InitializeSettlementQueue>) -> Result<()> {
+    let settlement_queue = &mut ctx.accounts.settlement_queue;
+    settlement_queue.order_book = ctx.accounts.order_book.key();
+    settlement_queue.next_trade_id = 1;
+    settlement_queue.reserved_trades = 0;
+    settlement_queue.bump = ctx.bumps.settlement_queue;
 
-        Ok(())
-    }
+    Ok(())
 }
 
 // ===== Account Structures =====
@@ -233,6 +844,362 @@ impl Darkpool {
     pub const LEN: usize = 8 + std::mem::size_of::<Darkpool>();
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq)]
+pub enum OrderStatus {
+    #[default]
+    Empty,
+    Pending, // Reserved at submission, waiting for the validation callback
+    Active,
+    Matching, // Included in a queued matching computation
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct DarkOrderSlot {
+    pub owner: Pubkey,
+    pub encryption_key: [u8; 32], // Owner's x25519 public key
+    pub status: OrderStatus,
+    pub order_id: u64, // Number of the submission, echoed by the validation computation
+    pub submitted_at: i64,
+    pub expires_at: i64,    // 0 = good till cancelled
+    pub escrow_amount: u64, // Escrowed collateral not yet taken by a fill
+    pub nonce: u128,        // MXE nonce of the ciphertexts
+    pub ciphertexts: [[u8; 32]; DARK_ORDER_FIELDS],
+}
+
+impl DarkOrderSlot {
+    pub const CIPHERTEXTS_LEN: usize = 32 * DARK_ORDER_FIELDS;
+    pub const LEN: usize = 32 + 32 + 1 + 8 + 8 + 8 + 8 + 16 + Self::CIPHERTEXTS_LEN;
+    // Offset of the ciphertexts in a serialized slot
+    const CIPHERTEXTS_OFFSET: usize = Self::LEN - Self::CIPHERTEXTS_LEN;
+
+    pub fn is_expired(&self, timestamp: i64) -> bool {
+        self.expires_at != 0 && timestamp >= self.expires_at
+    }
+}
+
+// Encrypted orders of a market, one account per pool, custody and collateral custody
+#[account]
+#[derive(Default, Debug)]
+pub struct DarkOrderBook {
+    pub darkpool: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub active_orders: u64,
+    // Maximum distance of execution prices from the custody oracle price
+    pub price_band_bps: u64,
+    pub bump: u8,
+    pub orders: [DarkOrderSlot; MAX_ORDERS],
+}
+
+impl DarkOrderBook {
+    // Offset of the order slots in the account data
+    const ORDERS_OFFSET: usize = 8 + 32 * 4 + 8 + 8 + 1;
+    pub const LEN: usize = Self::ORDERS_OFFSET + DarkOrderSlot::LEN * MAX_ORDERS;
+
+    pub fn ciphertexts_offset(slot: usize) -> u32 {
+        (Self::ORDERS_OFFSET + slot * DarkOrderSlot::LEN + DarkOrderSlot::CIPHERTEXTS_OFFSET) as u32
+    }
+
+    pub fn get_slot(&self, slot: usize) -> Result<&DarkOrderSlot> {
+        self.orders
+            .get(slot)
+            .ok_or_else(|| ErrorCode::InvalidOrderSlot.into())
+    }
+
+    pub fn reserve_slot(
+        &mut self,
+        owner: Pubkey,
+        order_id: u64,
+        encryption_key: [u8; 32],
+        timestamp: i64,
+        expires_at: i64,
+        escrow_amount: u64,
+    ) -> Result<usize> {
+        let slot = self
+            .orders
+            .iter()
+            .position(|order| order.status == OrderStatus::Empty)
+            .ok_or(ErrorCode::OrderBookFull)?;
+
+        self.orders[slot] = DarkOrderSlot {
+            owner,
+            encryption_key,
+            status: OrderStatus::Pending,
+            order_id,
+            submitted_at: timestamp,
+            expires_at,
+            escrow_amount,
+            ..DarkOrderSlot::default()
+        };
+
+        Ok(slot)
+    }
+
+    // Store a validated order, returns false if the slot no longer waits for
+    // this submission, i.e. it was released by a failed validation
+    pub fn fill_slot(
+        &mut self,
+        slot: usize,
+        order_id: u64,
+        nonce: u128,
+        ciphertexts: [[u8; 32]; DARK_ORDER_FIELDS],
+    ) -> Result<bool> {
+        let order = self.get_slot(slot)?;
+        if order.status != OrderStatus::Pending || order.order_id != order_id {
+            return Ok(false);
+        }
+
+        let order = &mut self.orders[slot];
+        order.status = OrderStatus::Active;
+        order.nonce = nonce;
+        order.ciphertexts = ciphertexts;
+        self.active_orders += 1;
+
+        Ok(true)
+    }
+
+    pub fn release_slot(&mut self, slot: usize) -> Result<()> {
+        if matches!(
+            self.get_slot(slot)?.status,
+            OrderStatus::Active | OrderStatus::Matching
+        ) {
+            self.active_orders -= 1;
+        }
+        self.orders[slot] = DarkOrderSlot::default();
+
+        Ok(())
+    }
+
+    // Free the slots waiting for a validation callback and return them with
+    // their owners. A failed computation doesn't tell which slot it was for.
+    pub fn release_pending_slots(&mut self) -> Vec<(usize, Pubkey)> {
+        let mut released = vec![];
+        for (slot, order) in self.orders.iter_mut().enumerate() {
+            if order.status == OrderStatus::Pending {
+                released.push((slot, order.owner));
+                *order = DarkOrderSlot::default();
+            }
+        }
+        released
+    }
+
+    // Remove an order of its owner, orders locked by a queued computation
+    // can't be cancelled
+    pub fn cancel_slot(&mut self, slot: usize, owner: Pubkey) -> Result<()> {
+        let order = self.get_slot(slot)?;
+        require!(
+            order.status == OrderStatus::Active,
+            ErrorCode::OrderNotActive
+        );
+        require_keys_eq!(order.owner, owner, ErrorCode::InvalidOrderOwner);
+
+        self.release_slot(slot)
+    }
+
+    // Lock the active, non expired orders for a matching computation and
+    // return the mask of the locked slots
+    pub fn start_matching(&mut self, timestamp: i64) -> Result<[bool; MAX_ORDERS]> {
+        let mut active = [false; MAX_ORDERS];
+        for (slot, order) in self.orders.iter().enumerate() {
+            active[slot] = order.status == OrderStatus::Active && !order.is_expired(timestamp);
+        }
+        require!(
+            active.iter().filter(|active| **active).count() >= 2,
+            ErrorCode::NotEnoughOrders
+        );
+
+        for (slot, order) in self.orders.iter_mut().enumerate() {
+            if active[slot] {
+                order.status = OrderStatus::Matching;
+            }
+        }
+
+        Ok(active)
+    }
+
+    // Unlock the orders of a failed matching computation, they are unchanged
+    pub fn abort_matching(&mut self) {
+        for order in self.orders.iter_mut() {
+            if order.status == OrderStatus::Matching {
+                order.status = OrderStatus::Active;
+            }
+        }
+    }
+
+    // Store the residual of a partially filled order and unlock it
+    pub fn update_slot(
+        &mut self,
+        slot: usize,
+        nonce: u128,
+        ciphertexts: [[u8; 32]; DARK_ORDER_FIELDS],
+    ) -> Result<()> {
+        require!(
+            self.get_slot(slot)?.status == OrderStatus::Matching,
+            ErrorCode::InvalidOrderSlot
+        );
+
+        let order = &mut self.orders[slot];
+        order.status = OrderStatus::Active;
+        order.nonce = nonce;
+        order.ciphertexts = ciphertexts;
+
+        Ok(())
+    }
+
+    // Collateral of a filled order moves from its escrow share to a
+    // settlement record
+    pub fn take_escrow(&mut self, slot: usize, amount: u64) -> Result<()> {
+        let order = self
+            .orders
+            .get_mut(slot)
+            .ok_or(ErrorCode::InvalidOrderSlot)?;
+        order.escrow_amount = order.escrow_amount.saturating_sub(amount);
+
+        Ok(())
+    }
+
+    // Escrowed collateral still backing the orders of an owner
+    pub fn escrowed_amount(&self, owner: Pubkey) -> u64 {
+        self.orders
+            .iter()
+            .filter(|order| order.status != OrderStatus::Empty && order.owner == owner)
+            .map(|order| order.escrow_amount)
+            .sum()
+    }
+}
+
+// Batch auction rounds of an order book
+#[account]
+#[derive(Default, Debug)]
+pub struct AuctionEpoch {
+    pub order_book: Pubkey,
+    pub epoch: u64,          // Number of cleared auctions
+    pub epoch_duration: i64, // Minimum time between two auctions, in seconds
+    pub epoch_start: i64,
+    pub in_progress: bool, // An auction computation is queued
+    pub last_clearing_price: u64,
+    pub last_volume: u64,
+    pub bump: u8,
+}
+
+impl AuctionEpoch {
+    pub const LEN: usize = 8 + std::mem::size_of::<AuctionEpoch>();
+}
+
+// Settlement record of a fill, revealed by a matching computation
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug)]
+pub struct DarkTrade {
+    pub trade_id: u64,
+    pub pending: bool,    // false if the entry is free
+    pub trader_a: Pubkey, // Owner of the long order
+    pub trader_b: Pubkey, // Owner of the short order
+    pub size_usd: u64,
+    pub price: u64,
+    pub collateral_a: u64, // Collateral of the long order backing the trade
+    pub collateral_b: u64, // Collateral of the short order backing the trade
+    pub timestamp: i64,
+}
+
+impl DarkTrade {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8 * 4 + 8;
+}
+
+// Trades of an order book waiting to be settled in the perpetuals program.
+// The pool, custody and collateral custody of the trades are the ones of the
+// order book.
+#[account]
+#[derive(Default, Debug)]
+pub struct SettlementQueue {
+    pub order_book: Pubkey,
+    pub next_trade_id: u64,
+    // Entries reserved for the fills of queued matching computations
+    pub reserved_trades: u64,
+    pub bump: u8,
+    pub trades: [DarkTrade; MAX_PENDING_TRADES],
+}
+
+impl SettlementQueue {
+    pub const LEN: usize = 8 + 32 + 8 + 8 + 1 + DarkTrade::LEN * MAX_PENDING_TRADES;
+
+    // Make sure the callback of a matching computation can record all its
+    // fills, a failing callback would leave the orders locked
+    pub fn reserve_trades(&mut self) -> Result<()> {
+        let free = self.trades.iter().filter(|trade| !trade.pending).count() as u64;
+        require!(
+            free >= self.reserved_trades + MAX_MATCHES as u64,
+            ErrorCode::SettlementQueueFull
+        );
+        self.reserved_trades += MAX_MATCHES as u64;
+
+        Ok(())
+    }
+
+    pub fn release_reserved_trades(&mut self) {
+        self.reserved_trades = self.reserved_trades.saturating_sub(MAX_MATCHES as u64);
+    }
+
+    pub fn push_trade(&mut self, trade: DarkTrade) -> Result<u64> {
+        let entry = self
+            .trades
+            .iter_mut()
+            .find(|trade| !trade.pending)
+            .ok_or(ErrorCode::SettlementQueueFull)?;
+
+        let trade_id = self.next_trade_id;
+        *entry = DarkTrade {
+            trade_id,
+            pending: true,
+            ..trade
+        };
+        self.next_trade_id += 1;
+
+        Ok(trade_id)
+    }
+
+    pub fn take_trade(&mut self, trade_id: u64) -> Result<DarkTrade> {
+        let entry = self
+            .trades
+            .iter_mut()
+            .find(|trade| trade.pending && trade.trade_id == trade_id)
+            .ok_or(ErrorCode::TradeNotFound)?;
+
+        Ok(std::mem::take(entry))
+    }
+
+    pub fn expire_trade(&mut self, trade_id: u64, timestamp: i64) -> Result<DarkTrade> {
+        let entry = self
+            .trades
+            .iter_mut()
+            .find(|trade| trade.pending && trade.trade_id == trade_id)
+            .ok_or(ErrorCode::TradeNotFound)?;
+        require!(
+            timestamp >= entry.timestamp.saturating_add(TRADE_EXPIRY_SECONDS),
+            ErrorCode::TradeNotExpired
+        );
+
+        Ok(std::mem::take(entry))
+    }
+
+    // Escrowed collateral of an owner backing trades waiting for settlement
+    pub fn pending_collateral(&self, owner: Pubkey) -> u64 {
+        self.trades
+            .iter()
+            .filter(|trade| trade.pending)
+            .map(|trade| {
+                let mut collateral = 0;
+                if trade.trader_a == owner {
+                    collateral += trade.collateral_a;
+                }
+                if trade.trader_b == owner {
+                    collateral += trade.collateral_b;
+                }
+                collateral
+            })
+            .sum()
+    }
+}
+
 // ===== Instruction Parameters =====
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -244,19 +1211,21 @@ pub struct InitializeDarkpoolParams {
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct SettlementData {
-    pub darkpool: Pubkey,
-    pub trades: Vec<TradeSettlement>,
+pub struct InitializeOrderBookParams {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub price_band_bps: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct TradeSettlement {
-    pub trader_a: Pubkey,
-    pub trader_b: Pubkey,
-    pub size_usd: u64,
-    pub price: u64,
-    pub pool: Pubkey,
-    pub custody: Pubkey,
+pub struct SetPriceBandParams {
+    pub price_band_bps: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct InitializeAuctionEpochParams {
+    pub epoch_duration: i64,
 }
 
 // ===== Account Validation =====
@@ -278,93 +1247,671 @@ pub struct InitializeDarkpool<'info> {
     pub system_program: Program<'info, System>,
 }
 
-#[queue_computation_accounts("submit_dark_order", owner)]
 #[derive(Accounts)]
-#[instruction(computation_offset: u64)]
-pub struct SubmitDarkOrder<'info> {
+#[instruction(params: InitializeOrderBookParams)]
+pub struct InitializeOrderBook<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
         seeds = [b"darkpool"],
-        bump = darkpool.bump
+        bump = darkpool.bump,
+        has_one = authority
     )]
     pub darkpool: Account<'info, Darkpool>,
 
     #[account(
-        address = derive_mxe_pda!()
+        init,
+        payer = authority,
+        space = DarkOrderBook::LEN,
+        seeds = [b"order_book",
+                 darkpool.key().as_ref(),
+                 params.pool.as_ref(),
+                 params.custody.as_ref(),
+                 params.collateral_custody.as_ref()],
+        bump
     )]
-    pub mxe_account: Account<'info, MXEAccount>,
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPriceBand<'info> {
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
-        address = derive_mempool_pda!()
+        seeds = [b"darkpool"],
+        bump = darkpool.bump,
+        has_one = authority
     )]
-    /// CHECK: mempool_account, checked by the arcium program.
-    pub mempool_account: UncheckedAccount<'info>,
+    pub darkpool: Account<'info, Darkpool>,
 
     #[account(
         mut,
-        address = derive_execpool_pda!()
+        has_one = darkpool
     )]
-    /// CHECK: executing_pool, checked by the arcium program.
-    pub executing_pool: UncheckedAccount<'info>,
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAuctionEpoch<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
 
     #[account(
-        mut,
-        address = derive_comp_pda!(computation_offset)
+        seeds = [b"darkpool"],
+        bump = darkpool.bump,
+        has_one = authority
     )]
-    /// CHECK: computation_account, checked by the arcium program.
-    pub computation_account: UncheckedAccount<'info>,
+    pub darkpool: Account<'info, Darkpool>,
 
     #[account(
-        address = derive_comp_def_pda!(COMP_DEF_OFFSET_SUBMIT_DARK_ORDER)
+        has_one = darkpool
     )]
-    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
 
     #[account(
-        mut,
-        address = derive_cluster_pda!(mxe_account)
+        init,
+        payer = authority,
+        space = AuctionEpoch::LEN,
+        seeds = [b"auction_epoch",
+                 order_book.key().as_ref()],
+        bump
     )]
-    pub cluster_account: Account<'info, Cluster>,
+    pub auction_epoch: Account<'info, AuctionEpoch>,
 
-    #[account(
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeSettlementQueue<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump,
+        has_one = authority
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    #[account(
+        init,
+        payer = authority,
+        space = SettlementQueue::LEN,
+        seeds = [b"settlement_queue",
+                 order_book.key().as_ref()],
+        bump
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[queue_computation_accounts("submit_dark_order", owner)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct SubmitDarkOrder<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    /// CHECK: perpetuals program configured in the darkpool
+    #[account(
+        address = darkpool.perpetuals_program
+    )]
+    pub perpetuals_program: UncheckedAccount<'info>,
+
+    /// CHECK: perpetuals account, checked by the perpetuals program
+    pub perpetuals: UncheckedAccount<'info>,
+
+    /// CHECK: pool of the order book, checked by the perpetuals program
+    #[account(
+        address = order_book.pool
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: custody of the order book, owner and discriminator checked on read
+    #[account(
+        address = order_book.custody
+    )]
+    pub custody: UncheckedAccount<'info>,
+
+    /// CHECK: collateral custody of the order book, owner and discriminator
+    /// checked on read
+    #[account(
+        address = order_book.collateral_custody
+    )]
+    pub collateral_custody: UncheckedAccount<'info>,
+
+    /// CHECK: oracle account of the collateral custody, checked by the
+    /// perpetuals program
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = owner,
+        token::token_program = token_program
+    )]
+    pub funding_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Collateral of the owner's orders on the order book, held by the darkpool
+    #[account(
+        init_if_needed,
+        payer = owner,
+        seeds = [b"escrow", order_book.key().as_ref(), owner.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = darkpool,
+        token::token_program = token_program
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    pub collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    #[account(
+        address = derive_mxe_pda!()
+    )]
+    pub mxe_account: Account<'info, MXEAccount>,
+
+    #[account(
+        mut,
+        address = derive_mempool_pda!()
+    )]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_execpool_pda!()
+    )]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset)
+    )]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_SUBMIT_DARK_ORDER)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(
+        mut,
+        address = derive_cluster_pda!(mxe_account)
+    )]
+    pub cluster_account: Account<'info, Cluster>,
+
+    #[account(
+        mut,
+        address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+    )]
+    pub pool_account: Account<'info, FeePool>,
+
+    #[account(
+        address = ARCIUM_CLOCK_ACCOUNT_ADDRESS
+    )]
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+#[callback_accounts("submit_dark_order", owner)]
+#[derive(Accounts)]
+pub struct SubmitDarkOrderCallback<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_SUBMIT_DARK_ORDER)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    #[account(mut)]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDarkOrder<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    #[account(
+        has_one = order_book
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order_book.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::token_program = token_program
+    )]
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = escrow.mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEscrow<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    #[account(
+        has_one = order_book
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order_book.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::token_program = token_program
+    )]
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = escrow.mint
+    )]
+    pub collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireDarkOrder<'info> {
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+}
+
+#[queue_computation_accounts("match_dark_orders", matcher)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct MatchDarkOrders<'info> {
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    #[account(
+        mut,
+        seeds = [b"settlement_queue",
+                 order_book.key().as_ref()],
+        bump = settlement_queue.bump
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    /// CHECK: perpetuals program configured in the darkpool
+    #[account(
+        address = darkpool.perpetuals_program
+    )]
+    pub perpetuals_program: UncheckedAccount<'info>,
+
+    /// CHECK: perpetuals account, checked by the perpetuals program
+    pub perpetuals: UncheckedAccount<'info>,
+
+    /// CHECK: pool of the order book, checked by the perpetuals program
+    #[account(
+        address = order_book.pool
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: custody of the order book, checked by the perpetuals program
+    #[account(
+        address = order_book.custody
+    )]
+    pub custody: UncheckedAccount<'info>,
+
+    /// CHECK: oracle account of the custody, checked by the perpetuals program
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        address = derive_mxe_pda!()
+    )]
+    pub mxe_account: Account<'info, MXEAccount>,
+
+    #[account(
+        mut,
+        address = derive_mempool_pda!()
+    )]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_execpool_pda!()
+    )]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset)
+    )]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_DARK_ORDERS)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(
+        mut,
+        address = derive_cluster_pda!(mxe_account)
+    )]
+    pub cluster_account: Account<'info, Cluster>,
+
+    #[account(
         mut,
         address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
     )]
-    pub pool_account: Account<'info, FeePool>,
+    pub pool_account: Account<'info, FeePool>,
+
+    #[account(
+        address = ARCIUM_CLOCK_ACCOUNT_ADDRESS
+    )]
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+#[callback_accounts("match_dark_orders", matcher)]
+#[derive(Accounts)]
+pub struct MatchDarkOrdersCallback<'info> {
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_DARK_ORDERS)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    // Callback accounts, in the order passed to queue_computation
+    #[account(
+        mut,
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+    #[account(
+        mut,
+        has_one = order_book
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+}
+
+#[queue_computation_accounts("run_batch_auction", matcher)]
+#[derive(Accounts)]
+#[instruction(computation_offset: u64)]
+pub struct RunBatchAuction<'info> {
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+
+    #[account(
+        mut,
+        seeds = [b"auction_epoch",
+                 order_book.key().as_ref()],
+        bump = auction_epoch.bump
+    )]
+    pub auction_epoch: Account<'info, AuctionEpoch>,
+
+    #[account(
+        mut,
+        seeds = [b"settlement_queue",
+                 order_book.key().as_ref()],
+        bump = settlement_queue.bump
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    /// CHECK: perpetuals program configured in the darkpool
+    #[account(
+        address = darkpool.perpetuals_program
+    )]
+    pub perpetuals_program: UncheckedAccount<'info>,
+
+    /// CHECK: perpetuals account, checked by the perpetuals program
+    pub perpetuals: UncheckedAccount<'info>,
+
+    /// CHECK: pool of the order book, checked by the perpetuals program
+    #[account(
+        address = order_book.pool
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: custody of the order book, checked by the perpetuals program
+    #[account(
+        address = order_book.custody
+    )]
+    pub custody: UncheckedAccount<'info>,
+
+    /// CHECK: oracle account of the custody, checked by the perpetuals program
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    #[account(
+        address = derive_mxe_pda!()
+    )]
+    pub mxe_account: Account<'info, MXEAccount>,
+
+    #[account(
+        mut,
+        address = derive_mempool_pda!()
+    )]
+    /// CHECK: mempool_account, checked by the arcium program.
+    pub mempool_account: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_execpool_pda!()
+    )]
+    /// CHECK: executing_pool, checked by the arcium program.
+    pub executing_pool: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        address = derive_comp_pda!(computation_offset)
+    )]
+    /// CHECK: computation_account, checked by the arcium program.
+    pub computation_account: UncheckedAccount<'info>,
+
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_RUN_BATCH_AUCTION)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+
+    #[account(
+        mut,
+        address = derive_cluster_pda!(mxe_account)
+    )]
+    pub cluster_account: Account<'info, Cluster>,
+
+    #[account(
+        mut,
+        address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+    )]
+    pub pool_account: Account<'info, FeePool>,
+
+    #[account(
+        address = ARCIUM_CLOCK_ACCOUNT_ADDRESS
+    )]
+    pub clock_account: Account<'info, ClockAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub arcium_program: Program<'info, Arcium>,
+}
+
+#[callback_accounts("run_batch_auction", matcher)]
+#[derive(Accounts)]
+pub struct RunBatchAuctionCallback<'info> {
+    #[account(mut)]
+    pub matcher: Signer<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    #[account(
+        address = derive_comp_def_pda!(COMP_DEF_OFFSET_RUN_BATCH_AUCTION)
+    )]
+    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
+    /// CHECK: instructions_sysvar, checked by the account constraint
+    pub instructions_sysvar: AccountInfo<'info>,
+    // Callback accounts, in the order passed to queue_computation
+    #[account(
+        mut,
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
+    #[account(
+        mut,
+        has_one = darkpool
+    )]
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
+    #[account(
+        mut,
+        has_one = order_book
+    )]
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+    #[account(
+        mut,
+        has_one = order_book
+    )]
+    pub auction_epoch: Account<'info, AuctionEpoch>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireDarkTrade<'info> {
+    pub caller: Signer<'info>,
+
+    #[account(
+        seeds = [b"darkpool"],
+        bump = darkpool.bump
+    )]
+    pub darkpool: Account<'info, Darkpool>,
 
     #[account(
-        address = ARCIUM_CLOCK_ACCOUNT_ADDRESS
+        has_one = darkpool
     )]
-    pub clock_account: Account<'info, ClockAccount>,
-
-    pub system_program: Program<'info, System>,
-    pub arcium_program: Program<'info, Arcium>,
-}
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
 
-#[callback_accounts("submit_dark_order", owner)]
-#[derive(Accounts)]
-pub struct SubmitDarkOrderCallback<'info> {
-    #[account(mut)]
-    pub owner: Signer<'info>,
-    pub arcium_program: Program<'info, Arcium>,
     #[account(
-        address = derive_comp_def_pda!(COMP_DEF_OFFSET_SUBMIT_DARK_ORDER)
+        mut,
+        has_one = order_book
     )]
-    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
-    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
-    /// CHECK: instructions_sysvar, checked by the account constraint
-    pub instructions_sysvar: AccountInfo<'info>,
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
 }
 
-#[queue_computation_accounts("match_dark_orders", matcher)]
 #[derive(Accounts)]
-#[instruction(computation_offset: u64)]
-pub struct MatchDarkOrders<'info> {
+pub struct SettleDarkPoolTrade<'info> {
+    // Pays for the position accounts created by the perpetuals program
     #[account(mut)]
-    pub matcher: Signer<'info>,
+    pub settler: Signer<'info>,
 
     #[account(
         mut,
@@ -374,92 +1921,103 @@ pub struct MatchDarkOrders<'info> {
     pub darkpool: Account<'info, Darkpool>,
 
     #[account(
-        address = derive_mxe_pda!()
+        has_one = darkpool
     )]
-    pub mxe_account: Account<'info, MXEAccount>,
+    pub order_book: Box<Account<'info, DarkOrderBook>>,
 
     #[account(
         mut,
-        address = derive_mempool_pda!()
+        has_one = order_book
     )]
-    /// CHECK: mempool_account, checked by the arcium program.
-    pub mempool_account: UncheckedAccount<'info>,
+    pub settlement_queue: Box<Account<'info, SettlementQueue>>,
+
+    /// CHECK: long trader, checked against the settled trade
+    pub long_owner: UncheckedAccount<'info>,
 
+    /// CHECK: short trader, checked against the settled trade
+    pub short_owner: UncheckedAccount<'info>,
+
+    // Escrowed collateral of the long trader
     #[account(
         mut,
-        address = derive_execpool_pda!()
+        seeds = [b"escrow", order_book.key().as_ref(), long_owner.key().as_ref()],
+        bump
     )]
-    /// CHECK: executing_pool, checked by the arcium program.
-    pub executing_pool: UncheckedAccount<'info>,
+    pub escrow_long: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    // Escrowed collateral of the short trader
     #[account(
         mut,
-        address = derive_comp_pda!(computation_offset)
+        seeds = [b"escrow", order_book.key().as_ref(), short_owner.key().as_ref()],
+        bump
     )]
-    /// CHECK: computation_account, checked by the arcium program.
-    pub computation_account: UncheckedAccount<'info>,
+    pub escrow_short: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    /// CHECK: perpetuals program configured in the darkpool
     #[account(
-        address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_DARK_ORDERS)
+        address = darkpool.perpetuals_program
     )]
-    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
+    pub perpetuals_program: UncheckedAccount<'info>,
 
-    #[account(
-        mut,
-        address = derive_cluster_pda!(mxe_account)
-    )]
-    pub cluster_account: Account<'info, Cluster>,
+    /// CHECK: perpetuals account, checked by the perpetuals program
+    pub perpetuals: UncheckedAccount<'info>,
 
+    /// CHECK: pool of the order book, checked by the perpetuals program
     #[account(
         mut,
-        address = ARCIUM_FEE_POOL_ACCOUNT_ADDRESS,
+        address = order_book.pool
     )]
-    pub pool_account: Account<'info, FeePool>,
+    pub pool: UncheckedAccount<'info>,
 
-    #[account(
-        address = ARCIUM_CLOCK_ACCOUNT_ADDRESS
-    )]
-    pub clock_account: Account<'info, ClockAccount>,
+    /// CHECK: position counter of the long trader, checked by the perpetuals program
+    #[account(mut)]
+    pub position_counter_long: UncheckedAccount<'info>,
 
-    pub system_program: Program<'info, System>,
-    pub arcium_program: Program<'info, Arcium>,
-}
+    /// CHECK: new long position, checked by the perpetuals program
+    #[account(mut)]
+    pub position_long: UncheckedAccount<'info>,
 
-#[callback_accounts("match_dark_orders", matcher)]
-#[derive(Accounts)]
-pub struct MatchDarkOrdersCallback<'info> {
+    /// CHECK: position counter of the short trader, checked by the perpetuals program
     #[account(mut)]
-    pub matcher: Signer<'info>,
+    pub position_counter_short: UncheckedAccount<'info>,
+
+    /// CHECK: new short position, checked by the perpetuals program
+    #[account(mut)]
+    pub position_short: UncheckedAccount<'info>,
 
+    /// CHECK: custody of the order book, checked by the perpetuals program
     #[account(
         mut,
-        seeds = [b"darkpool"],
-        bump = darkpool.bump
+        address = order_book.custody
     )]
-    pub darkpool: Account<'info, Darkpool>,
+    pub custody: UncheckedAccount<'info>,
 
-    pub arcium_program: Program<'info, Arcium>,
+    /// CHECK: oracle account of the custody, checked by the perpetuals program
+    pub custody_oracle_account: UncheckedAccount<'info>,
+
+    /// CHECK: collateral custody of the order book, checked by the perpetuals program
     #[account(
-        address = derive_comp_def_pda!(COMP_DEF_OFFSET_MATCH_DARK_ORDERS)
+        mut,
+        address = order_book.collateral_custody
     )]
-    pub comp_def_account: Account<'info, ComputationDefinitionAccount>,
-    #[account(address = ::anchor_lang::solana_program::sysvar::instructions::ID)]
-    /// CHECK: instructions_sysvar, checked by the account constraint
-    pub instructions_sysvar: AccountInfo<'info>,
-}
+    pub collateral_custody: UncheckedAccount<'info>,
 
-#[derive(Accounts)]
-pub struct SettleDarkPoolTrades<'info> {
+    /// CHECK: oracle account of the collateral custody, checked by the
+    /// perpetuals program
+    pub collateral_custody_oracle_account: UncheckedAccount<'info>,
+
+    /// CHECK: token account of the collateral custody, checked by the
+    /// perpetuals program
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub collateral_custody_token_account: UncheckedAccount<'info>,
 
-    #[account(
-        mut,
-        seeds = [b"darkpool"],
-        bump = darkpool.bump,
-        has_one = authority
-    )]
-    pub darkpool: Account<'info, Darkpool>,
+    /// CHECK: collateral mint, checked by the perpetuals program
+    pub collateral_custody_token_mint: UncheckedAccount<'info>,
+
+    /// CHECK: token program of the collateral mint, checked by the perpetuals program
+    pub token_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
 // Computation definition account structures
@@ -514,11 +2072,30 @@ pub struct InitBatchProcessOrdersCompDef<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[init_computation_definition_accounts("run_batch_auction", payer)]
+#[derive(Accounts)]
+pub struct InitRunBatchAuctionCompDef<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        address = derive_mxe_pda!()
+    )]
+    pub mxe_account: Box<Account<'info, MXEAccount>>,
+    #[account(mut)]
+    /// CHECK: comp_def_account, checked by arcium program.
+    pub comp_def_account: UncheckedAccount<'info>,
+    pub arcium_program: Program<'info, Arcium>,
+    pub system_program: Program<'info, System>,
+}
+
 // ===== Events =====
 
 #[event]
 pub struct DarkOrderSubmitted {
     pub owner: Pubkey,
+    pub order_book: Pubkey,
+    pub slot: u8,
     pub computation_offset: u64,
     pub timestamp: i64,
 }
@@ -526,31 +2103,102 @@ pub struct DarkOrderSubmitted {
 #[event]
 pub struct DarkOrderValidated {
     pub owner: Pubkey,
-    pub is_valid: bool,
+    pub order_book: Pubkey,
+    pub slot: u8,
+    pub encryption_key: [u8; 32],
+    pub reason_nonce: u128,
+    pub reason: [u8; 32], // Reason code encrypted for the order owner, 0 = valid
+}
+
+#[event]
+pub struct DarkOrderRemoved {
+    pub owner: Pubkey,
+    pub order_book: Pubkey,
+    pub slot: u8,
+    pub expired: bool, // false if cancelled by the owner or released by a failed validation
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowRefunded {
+    pub owner: Pubkey,
+    pub order_book: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
 }
 
 #[event]
 pub struct DarkOrderMatching {
+    pub order_book: Pubkey,
     pub computation_offset: u64,
+    pub oracle_price: u64,
     pub timestamp: i64,
 }
 
 #[event]
 pub struct DarkOrdersMatched {
+    pub order_book: Pubkey,
+    pub filled_orders: u8,
     pub total_matches: u64,
     pub total_volume: u64,
     pub average_price: u64,
     pub timestamp: i64,
 }
 
+#[event]
+pub struct DarkOrderMatchingFailed {
+    pub order_book: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchAuctionStarted {
+    pub order_book: Pubkey,
+    pub epoch: u64,
+    pub computation_offset: u64,
+    pub oracle_price: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchAuctionCleared {
+    pub order_book: Pubkey,
+    pub epoch: u64,
+    pub clearing_price: u64, // 0 if no orders crossed
+    pub volume: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BatchAuctionFailed {
+    pub order_book: Pubkey,
+    pub epoch: u64,
+    pub timestamp: i64,
+}
+
 #[event]
 pub struct DarkPoolTradeSettlement {
-    pub trader_a: Pubkey,
-    pub trader_b: Pubkey,
+    pub trade_id: u64,
+    pub trader_a: Pubkey, // Long
+    pub trader_b: Pubkey, // Short
     pub size_usd: u64,
     pub price: u64,
+    pub collateral_a: u64,
+    pub collateral_b: u64,
     pub pool: Pubkey,
     pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DarkTradeExpired {
+    pub trade_id: u64,
+    pub order_book: Pubkey,
+    pub trader_a: Pubkey, // Long
+    pub trader_b: Pubkey, // Short
+    pub collateral_a: u64,
+    pub collateral_b: u64,
     pub timestamp: i64,
 }
 
@@ -561,6 +2209,14 @@ pub struct DarkpoolInitialized {
     pub perpetuals_program: Pubkey,
 }
 
+#[event]
+pub struct OrderBookInitialized {
+    pub order_book: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+}
+
 // ===== Error Codes =====
 
 #[error_code]
@@ -577,24 +2233,880 @@ pub enum ErrorCode {
     InvalidTradeSize,
     #[msg("Invalid trade price")]
     InvalidTradePrice,
+    #[msg("Order book is full")]
+    OrderBookFull,
+    #[msg("Invalid order book slot")]
+    InvalidOrderSlot,
+    #[msg("Not enough orders to match")]
+    NotEnoughOrders,
+    #[msg("Invalid order expiration time")]
+    InvalidExpiration,
+    #[msg("Order is not active")]
+    OrderNotActive,
+    #[msg("Order is owned by another account")]
+    InvalidOrderOwner,
+    #[msg("Order has not expired")]
+    OrderNotExpired,
+    #[msg("Invalid auction epoch duration")]
+    InvalidEpochDuration,
+    #[msg("Auction epoch has not ended")]
+    EpochNotEnded,
+    #[msg("Auction already in progress")]
+    AuctionInProgress,
+    #[msg("Invalid price band")]
+    InvalidPriceBand,
+    #[msg("Invalid oracle price")]
+    InvalidOraclePrice,
+    #[msg("Invalid perpetuals custody account")]
+    InvalidCustody,
+    #[msg("Settlement queue is full")]
+    SettlementQueueFull,
+    #[msg("Trade not found in the settlement queue")]
+    TradeNotFound,
+    #[msg("Trade has not expired")]
+    TradeNotExpired,
+    #[msg("Account does not belong to a trader of the settled trade")]
+    TraderMismatch,
+    #[msg("No escrowed collateral to withdraw")]
+    NothingToWithdraw,
+    #[msg("The computation was aborted")]
+    AbortedComputation,
+    #[msg("Cluster not set")]
+    ClusterNotSet,
 }
 
 // ===== Helper Functions =====
 
-fn extract_volume_from_result(result: &EncryptedBytes) -> u64 {
-    // Extract volume from encrypted result
-    // This is a placeholder - in real implementation would decrypt and parse
-    0
+// Custody oracle price with PRICE_DECIMALS decimals, read by the perpetuals
+// program so the same OraclePrice validation (staleness and confidence)
+// applies to dark trades
+fn get_oracle_price<'info>(
+    perpetuals_program: &AccountInfo<'info>,
+    perpetuals: &AccountInfo<'info>,
+    pool: &AccountInfo<'info>,
+    custody: &AccountInfo<'info>,
+    custody_oracle_account: &AccountInfo<'info>,
+) -> Result<u64> {
+    let mut data = GET_ORACLE_PRICE_DISCRIMINATOR.to_vec();
+    data.push(0); // GetOraclePriceParams { ema: false }
+
+    let ix = Instruction {
+        program_id: perpetuals_program.key(),
+        accounts: vec![
+            AccountMeta::new_readonly(perpetuals.key(), false),
+            AccountMeta::new_readonly(pool.key(), false),
+            AccountMeta::new_readonly(custody.key(), false),
+            AccountMeta::new_readonly(custody_oracle_account.key(), false),
+        ],
+        data,
+    };
+    invoke(
+        &ix,
+        &[
+            perpetuals.clone(),
+            pool.clone(),
+            custody.clone(),
+            custody_oracle_account.clone(),
+            perpetuals_program.clone(),
+        ],
+    )?;
+
+    let (program_id, return_data) = get_return_data().ok_or(ErrorCode::InvalidOraclePrice)?;
+    require_keys_eq!(
+        program_id,
+        perpetuals_program.key(),
+        ErrorCode::InvalidOraclePrice
+    );
+    let price = u64::try_from_slice(&return_data)?;
+    require!(price > 0, ErrorCode::InvalidOraclePrice);
+
+    Ok(price)
+}
+
+// Opens the positions of a dark trade on the perpetuals program. The darkpool
+// PDA signs the CPI, which is how perpetuals authenticates the caller, and
+// moves the collateral out of the traders' escrow accounts it owns.
+fn settle_perpetuals_trade<'info>(
+    accounts: &SettleDarkPoolTrade<'info>,
+    trade: &DarkTrade,
+) -> Result<()> {
+    let params = PerpetualsSettleDarkPoolTradeParams {
+        trade_id: trade.trade_id,
+        size_usd: trade.size_usd,
+        price: trade.price,
+        collateral_long: trade.collateral_a,
+        collateral_short: trade.collateral_b,
+        // the band was checked at matching time, perpetuals checks it again
+        // against the oracle price at settlement time
+        max_price_slippage: accounts.order_book.price_band_bps as u16,
+    };
+    let mut data = SETTLE_DARK_POOL_TRADE_DISCRIMINATOR.to_vec();
+    params.serialize(&mut data)?;
+
+    let account_infos = [
+        accounts.settler.to_account_info(),
+        accounts.darkpool.to_account_info(),
+        accounts.long_owner.to_account_info(),
+        accounts.short_owner.to_account_info(),
+        accounts.escrow_long.to_account_info(),
+        accounts.escrow_short.to_account_info(),
+        accounts.perpetuals.to_account_info(),
+        accounts.pool.to_account_info(),
+        accounts.position_counter_long.to_account_info(),
+        accounts.position_long.to_account_info(),
+        accounts.position_counter_short.to_account_info(),
+        accounts.position_short.to_account_info(),
+        accounts.custody.to_account_info(),
+        accounts.custody_oracle_account.to_account_info(),
+        accounts.collateral_custody.to_account_info(),
+        accounts.collateral_custody_oracle_account.to_account_info(),
+        accounts.collateral_custody_token_account.to_account_info(),
+        accounts.collateral_custody_token_mint.to_account_info(),
+        accounts.system_program.to_account_info(),
+        accounts.token_program.to_account_info(),
+    ];
+    // same order as the perpetuals SettleDarkPoolTrade accounts, the darkpool
+    // PDA is the darkpool_authority signer
+    let ix = Instruction {
+        program_id: accounts.perpetuals_program.key(),
+        accounts: account_infos
+            .iter()
+            .map(|info| AccountMeta {
+                pubkey: info.key(),
+                is_signer: info.key() == accounts.darkpool.key() || info.is_signer,
+                is_writable: info.is_writable,
+            })
+            .collect(),
+        data,
+    };
+
+    let mut infos = account_infos.to_vec();
+    infos.push(accounts.perpetuals_program.to_account_info());
+    invoke_signed(&ix, &infos, &[&[b"darkpool", &[accounts.darkpool.bump]]])?;
+
+    Ok(())
+}
+
+// Escrow balance of an owner not backing an order or a pending trade
+fn refundable_amount(
+    escrow_balance: u64,
+    order_book: &DarkOrderBook,
+    settlement_queue: &SettlementQueue,
+    owner: Pubkey,
+) -> u64 {
+    let locked = order_book.escrowed_amount(owner) + settlement_queue.pending_collateral(owner);
+    escrow_balance.saturating_sub(locked)
+}
+
+// Fill of a batch auction between a long and a short order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuctionFill {
+    pub long_slot: usize,
+    pub short_slot: usize,
+    pub size_usd: u64,
+    pub collateral_a: u64,
+    pub collateral_b: u64,
+}
+
+// Pair the revealed fills of a batch auction, longs and shorts are walked in
+// slot order. Both sides fill the same volume, so at most MAX_MATCHES pairs
+// are produced. The collateral backing the fill of an order is split across
+// its pairs in proportion to their size, the shares add up to the total.
+fn pair_auction_fills(
+    long_fills: &[u64; MAX_ORDERS],
+    short_fills: &[u64; MAX_ORDERS],
+    fill_collateral: &[u64; MAX_ORDERS],
+) -> Vec<AuctionFill> {
+    let collateral_share = |slot: usize, fill: u64, taken: u64, size_usd: u64| {
+        let collateral = fill_collateral[slot] as u128;
+        (collateral * (taken + size_usd) as u128 / fill as u128
+            - collateral * taken as u128 / fill as u128) as u64
+    };
+    let longs: Vec<usize> = (0..MAX_ORDERS)
+        .filter(|slot| long_fills[*slot] > 0)
+        .collect();
+    let shorts: Vec<usize> = (0..MAX_ORDERS)
+        .filter(|slot| short_fills[*slot] > 0)
+        .collect();
+
+    let mut fills = Vec::with_capacity(MAX_MATCHES);
+    let (mut long, mut short) = (0, 0);
+    let (mut long_taken, mut short_taken) = (0u64, 0u64);
+    while long < longs.len() && short < shorts.len() {
+        let (long_slot, short_slot) = (longs[long], shorts[short]);
+        let long_fill = long_fills[long_slot];
+        let short_fill = short_fills[short_slot];
+        let size_usd = (long_fill - long_taken).min(short_fill - short_taken);
+
+        fills.push(AuctionFill {
+            long_slot,
+            short_slot,
+            size_usd,
+            collateral_a: collateral_share(long_slot, long_fill, long_taken, size_usd),
+            collateral_b: collateral_share(short_slot, short_fill, short_taken, size_usd),
+        });
+
+        long_taken += size_usd;
+        short_taken += size_usd;
+        if long_taken == long_fill {
+            long += 1;
+            long_taken = 0;
+        }
+        if short_taken == short_fill {
+            short += 1;
+            short_taken = 0;
+        }
+    }
+
+    fills
+}
+
+fn refund_escrow<'info>(
+    darkpool: &Account<'info, Darkpool>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    receiving_account: &InterfaceAccount<'info, TokenAccount>,
+    collateral_mint: &InterfaceAccount<'info, Mint>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            TransferChecked {
+                from: escrow.to_account_info(),
+                mint: collateral_mint.to_account_info(),
+                to: receiving_account.to_account_info(),
+                authority: darkpool.to_account_info(),
+            },
+            &[&[b"darkpool", &[darkpool.bump]]],
+        ),
+        amount,
+        collateral_mint.decimals,
+    )
+}
+
+// Params of the perpetuals settle_dark_pool_trade instruction, mirrored like
+// PerpetualsCustody and kept in sync with settle_dark_pool_trade.rs
+#[derive(AnchorSerialize, Clone, Copy, Debug)]
+pub struct PerpetualsSettleDarkPoolTradeParams {
+    pub trade_id: u64,
+    pub size_usd: u64,
+    pub price: u64,
+    pub collateral_long: u64,
+    pub collateral_short: u64,
+    pub max_price_slippage: u16,
+}
+
+// Leading fields of a perpetuals Custody account, up to the open position fee.
+// The darkpool can't depend on the perpetuals crate, so the layout is
+// mirrored here and must be kept in sync with state/custody.rs.
+#[derive(AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PerpetualsCustody {
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub decimals: u8,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: PerpetualsOracleParams,
+    pub pricing: PerpetualsPricingParams,
+    pub permissions: PerpetualsPermissions,
+    pub fees: PerpetualsFees,
+}
+
+#[derive(AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PerpetualsOracleParams {
+    pub oracle_account: Pubkey,
+    pub oracle_type: u8,
+    pub oracle_authority: Pubkey,
+    pub max_price_error: u64,
+    pub max_price_age_sec: u32,
+}
+
+#[derive(AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PerpetualsPricingParams {
+    pub use_ema: bool,
+    pub use_unrealized_pnl_in_aum: bool,
+    pub trade_spread_long: u64,
+    pub trade_spread_short: u64,
+    pub swap_spread: u64,
+    pub min_initial_leverage: u64,
+    pub max_initial_leverage: u64,
+    pub max_leverage: u64,
+    pub max_payoff_mult: u64,
+    pub max_utilization: u64,
+    pub max_position_locked_usd: u64,
+    pub max_total_locked_usd: u64,
+    pub price_impact_mult: u64,
+    pub max_price_impact: u64,
+}
+
+#[derive(AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PerpetualsPermissions {
+    pub allow_swap: bool,
+    pub allow_add_liquidity: bool,
+    pub allow_remove_liquidity: bool,
+    pub allow_open_position: bool,
+    pub allow_close_position: bool,
+    pub allow_pnl_withdrawal: bool,
+    pub allow_collateral_withdrawal: bool,
+    pub allow_size_change: bool,
+}
+
+#[derive(AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PerpetualsFees {
+    pub mode: u8,
+    pub ratio_mult: u64,
+    pub utilization_mult: u64,
+    pub swap_in: u64,
+    pub swap_out: u64,
+    pub stable_swap_in: u64,
+    pub stable_swap_out: u64,
+    pub add_liquidity: u64,
+    pub remove_liquidity: u64,
+    pub open_position: u64,
+}
+
+fn read_custody(
+    custody: &AccountInfo,
+    perpetuals_program: &AccountInfo,
+) -> Result<PerpetualsCustody> {
+    require_keys_eq!(
+        *custody.owner,
+        perpetuals_program.key(),
+        ErrorCode::InvalidCustody
+    );
+    let data = custody.try_borrow_data()?;
+    require!(
+        data.len() > 8 && data[..8] == CUSTODY_DISCRIMINATOR,
+        ErrorCode::InvalidCustody
+    );
+
+    PerpetualsCustody::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidCustody.into())
 }
 
-fn extract_match_count(result: &EncryptedBytes) -> u64 {
-    // Extract match count from encrypted result
-    // This is a placeholder - in real implementation would decrypt and parse
-    0
+// Orders are read by the computation from the order book account, every slot
+// is encrypted with its own MXE nonce. Followed by the mask of the slots to
+// match.
+fn order_arguments(
+    order_book: &Account<DarkOrderBook>,
+    active: &[bool; MAX_ORDERS],
+) -> Vec<Argument> {
+    let mut args = Vec::with_capacity(4 * MAX_ORDERS + 3);
+    for (slot, order) in order_book.orders.iter().enumerate() {
+        args.push(Argument::PlaintextU128(order.nonce));
+        args.push(Argument::Account(
+            order_book.key(),
+            DarkOrderBook::ciphertexts_offset(slot),
+            DarkOrderSlot::CIPHERTEXTS_LEN as u32,
+        ));
+    }
+    args.extend(active.iter().map(|active| Argument::PlaintextBool(*active)));
+
+    args
 }
 
-fn extract_average_price(result: &EncryptedBytes) -> u64 {
-    // Extract average price from encrypted result
-    // This is a placeholder - in real implementation would decrypt and parse
-    0
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active_order(book: &mut DarkOrderBook, owner: Pubkey, escrow_amount: u64) -> usize {
+        let slot = book
+            .reserve_slot(owner, 1, [1; 32], 100, 0, escrow_amount)
+            .unwrap();
+        assert!(book
+            .fill_slot(slot, 1, 7, [[2; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+        slot
+    }
+
+    fn trade(
+        trader_a: Pubkey,
+        trader_b: Pubkey,
+        collateral_a: u64,
+        collateral_b: u64,
+    ) -> DarkTrade {
+        DarkTrade {
+            trader_a,
+            trader_b,
+            size_usd: 1_000,
+            price: 100,
+            collateral_a,
+            collateral_b,
+            ..DarkTrade::default()
+        }
+    }
+
+    #[test]
+    fn test_reserve_slot() {
+        let mut book = DarkOrderBook::default();
+        let owner = Pubkey::new_unique();
+
+        for expected in 0..MAX_ORDERS {
+            let slot = book.reserve_slot(owner, 1, [1; 32], 100, 200, 50).unwrap();
+            assert_eq!(slot, expected);
+
+            let order = book.get_slot(slot).unwrap();
+            assert_eq!(order.status, OrderStatus::Pending);
+            assert_eq!(order.owner, owner);
+            assert_eq!(order.submitted_at, 100);
+            assert_eq!(order.expires_at, 200);
+            assert_eq!(order.escrow_amount, 50);
+        }
+        // pending orders are not active until validated
+        assert_eq!(book.active_orders, 0);
+
+        assert_eq!(
+            book.reserve_slot(owner, 1, [1; 32], 100, 200, 50)
+                .unwrap_err(),
+            ErrorCode::OrderBookFull.into()
+        );
+
+        // released slots are reused
+        book.release_slot(3).unwrap();
+        assert_eq!(
+            book.reserve_slot(owner, 1, [1; 32], 100, 200, 50).unwrap(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_fill_slot() {
+        let mut book = DarkOrderBook::default();
+        let owner = Pubkey::new_unique();
+
+        let slot = book.reserve_slot(owner, 1, [1; 32], 100, 0, 50).unwrap();
+        assert!(book
+            .fill_slot(slot, 1, 7, [[2; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+
+        let order = book.get_slot(slot).unwrap();
+        assert_eq!(order.status, OrderStatus::Active);
+        assert_eq!(order.order_id, 1);
+        assert_eq!(order.nonce, 7);
+        assert_eq!(order.ciphertexts, [[2; 32]; DARK_ORDER_FIELDS]);
+        assert_eq!(book.active_orders, 1);
+
+        // only pending slots can be filled
+        assert!(!book
+            .fill_slot(slot, 1, 8, [[3; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+        assert!(!book
+            .fill_slot(1, 1, 8, [[3; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+        assert_eq!(
+            book.fill_slot(MAX_ORDERS, 1, 8, [[3; 32]; DARK_ORDER_FIELDS])
+                .unwrap_err(),
+            ErrorCode::InvalidOrderSlot.into()
+        );
+        assert_eq!(book.get_slot(slot).unwrap().nonce, 7);
+        assert_eq!(book.active_orders, 1);
+
+        // the validation of a released slot doesn't fill the submission
+        // that reused it
+        let slot = book.reserve_slot(owner, 2, [1; 32], 100, 0, 50).unwrap();
+        book.release_pending_slots();
+        let reused = book.reserve_slot(owner, 3, [1; 32], 100, 0, 50).unwrap();
+        assert_eq!(reused, slot);
+        assert!(!book
+            .fill_slot(slot, 2, 8, [[3; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+        assert_eq!(book.get_slot(slot).unwrap().status, OrderStatus::Pending);
+        assert!(book
+            .fill_slot(slot, 3, 9, [[4; 32]; DARK_ORDER_FIELDS])
+            .unwrap());
+        assert_eq!(book.get_slot(slot).unwrap().nonce, 9);
+        assert_eq!(book.active_orders, 2);
+    }
+
+    #[test]
+    fn test_release_pending_slots() {
+        let mut book = DarkOrderBook::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        let alice_pending = book.reserve_slot(alice, 1, [1; 32], 100, 0, 10).unwrap();
+        let active = active_order(&mut book, alice, 20);
+        let bob_pending = book.reserve_slot(bob, 3, [1; 32], 100, 0, 30).unwrap();
+
+        assert_eq!(
+            book.release_pending_slots(),
+            vec![(alice_pending, alice), (bob_pending, bob)]
+        );
+        assert_eq!(
+            book.get_slot(alice_pending).unwrap().status,
+            OrderStatus::Empty
+        );
+        assert_eq!(
+            book.get_slot(bob_pending).unwrap().status,
+            OrderStatus::Empty
+        );
+        assert_eq!(book.get_slot(active).unwrap().status, OrderStatus::Active);
+        assert_eq!(book.active_orders, 1);
+
+        // the escrow of released slots becomes refundable
+        assert_eq!(book.escrowed_amount(alice), 20);
+        assert_eq!(book.escrowed_amount(bob), 0);
+        assert!(book.release_pending_slots().is_empty());
+    }
+
+    #[test]
+    fn test_abort_matching() {
+        let mut book = DarkOrderBook::default();
+        let owner = Pubkey::new_unique();
+
+        let pending = book.reserve_slot(owner, 1, [1; 32], 100, 0, 50).unwrap();
+        let first = active_order(&mut book, owner, 50);
+        let second = active_order(&mut book, owner, 50);
+        let mut queue = SettlementQueue::default();
+        queue.reserve_trades().unwrap();
+        book.start_matching(100).unwrap();
+
+        // a failed computation unlocks the orders and the reserved records
+        book.abort_matching();
+        queue.release_reserved_trades();
+        assert_eq!(queue.reserved_trades, 0);
+        for slot in [first, second] {
+            let order = book.get_slot(slot).unwrap();
+            assert_eq!(order.status, OrderStatus::Active);
+            assert_eq!(order.nonce, 7);
+        }
+        assert_eq!(book.get_slot(pending).unwrap().status, OrderStatus::Pending);
+        assert_eq!(book.active_orders, 2);
+
+        // the orders can be cancelled or matched again
+        book.cancel_slot(first, owner).unwrap();
+        active_order(&mut book, owner, 50);
+        let active = book.start_matching(100).unwrap();
+        assert_eq!(active.iter().filter(|active| **active).count(), 2);
+    }
+
+    #[test]
+    fn test_release_slot() {
+        let mut book = DarkOrderBook::default();
+        let owner = Pubkey::new_unique();
+
+        let pending = book.reserve_slot(owner, 1, [1; 32], 100, 0, 50).unwrap();
+        let active = active_order(&mut book, owner, 50);
+        let matching = active_order(&mut book, owner, 50);
+        active_order(&mut book, owner, 50);
+        book.start_matching(100).unwrap();
+        assert_eq!(book.active_orders, 3);
+
+        book.release_slot(pending).unwrap();
+        assert_eq!(book.active_orders, 3);
+
+        book.release_slot(matching).unwrap();
+        assert_eq!(book.active_orders, 2);
+
+        book.release_slot(active).unwrap();
+        assert_eq!(book.active_orders, 1);
+
+        for slot in [pending, active, matching] {
+            let order = book.get_slot(slot).unwrap();
+            assert_eq!(order.status, OrderStatus::Empty);
+            assert_eq!(order.owner, Pubkey::default());
+            assert_eq!(order.escrow_amount, 0);
+        }
+
+        // releasing an empty slot leaves the counter untouched
+        book.release_slot(pending).unwrap();
+        assert_eq!(book.active_orders, 1);
+
+        assert_eq!(
+            book.release_slot(MAX_ORDERS).unwrap_err(),
+            ErrorCode::InvalidOrderSlot.into()
+        );
+    }
+
+    #[test]
+    fn test_escrowed_amount() {
+        let mut book = DarkOrderBook::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        book.reserve_slot(alice, 1, [1; 32], 100, 0, 10).unwrap();
+        let alice_active = active_order(&mut book, alice, 20);
+        active_order(&mut book, bob, 40);
+        assert_eq!(book.escrowed_amount(alice), 30);
+        assert_eq!(book.escrowed_amount(bob), 40);
+        assert_eq!(book.escrowed_amount(Pubkey::new_unique()), 0);
+
+        // fills move collateral out of the order escrow share
+        book.take_escrow(alice_active, 15).unwrap();
+        assert_eq!(book.escrowed_amount(alice), 15);
+        book.take_escrow(alice_active, 15).unwrap();
+        assert_eq!(book.get_slot(alice_active).unwrap().escrow_amount, 0);
+        assert_eq!(book.escrowed_amount(alice), 10);
+
+        book.release_slot(alice_active).unwrap();
+        assert_eq!(book.escrowed_amount(alice), 10);
+        assert_eq!(book.escrowed_amount(bob), 40);
+    }
+
+    #[test]
+    fn test_settlement_queue() {
+        let mut queue = SettlementQueue {
+            next_trade_id: 1,
+            ..SettlementQueue::default()
+        };
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        let first = queue.push_trade(trade(alice, bob, 10, 20)).unwrap();
+        let second = queue.push_trade(trade(bob, alice, 30, 40)).unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(queue.pending_collateral(alice), 50);
+        assert_eq!(queue.pending_collateral(bob), 50);
+
+        let taken = queue.take_trade(first).unwrap();
+        assert_eq!(taken.trade_id, first);
+        assert!(taken.pending);
+        assert_eq!(taken.trader_a, alice);
+        assert_eq!(taken.collateral_b, 20);
+        assert_eq!(queue.pending_collateral(alice), 40);
+        assert_eq!(queue.pending_collateral(bob), 30);
+
+        // trades are settled once
+        assert_eq!(
+            queue.take_trade(first).unwrap_err(),
+            ErrorCode::TradeNotFound.into()
+        );
+        assert_eq!(
+            queue.take_trade(42).unwrap_err(),
+            ErrorCode::TradeNotFound.into()
+        );
+
+        // freed entries are reused with new trade ids
+        assert_eq!(queue.push_trade(trade(alice, bob, 1, 1)).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_expire_trade() {
+        let mut queue = SettlementQueue::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        let stuck = queue
+            .push_trade(DarkTrade {
+                timestamp: 1_000,
+                ..trade(alice, bob, 10, 20)
+            })
+            .unwrap();
+        queue.push_trade(trade(alice, bob, 1, 2)).unwrap();
+        assert_eq!(queue.pending_collateral(alice), 11);
+
+        let expiry = 1_000 + TRADE_EXPIRY_SECONDS;
+        assert_eq!(
+            queue.expire_trade(stuck, expiry - 1).unwrap_err(),
+            ErrorCode::TradeNotExpired.into()
+        );
+        let expired = queue.expire_trade(stuck, expiry).unwrap();
+        assert_eq!((expired.collateral_a, expired.collateral_b), (10, 20));
+
+        // the collateral of the dropped trade is refundable again
+        assert_eq!(queue.pending_collateral(alice), 1);
+        assert_eq!(queue.pending_collateral(bob), 2);
+        assert_eq!(
+            refundable_amount(11, &DarkOrderBook::default(), &queue, alice),
+            10
+        );
+
+        // an expired trade can't be settled or dropped again
+        assert_eq!(
+            queue.take_trade(stuck).unwrap_err(),
+            ErrorCode::TradeNotFound.into()
+        );
+        assert_eq!(
+            queue.expire_trade(stuck, expiry).unwrap_err(),
+            ErrorCode::TradeNotFound.into()
+        );
+    }
+
+    #[test]
+    fn test_settlement_queue_full() {
+        let mut queue = SettlementQueue::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        queue.reserve_trades().unwrap();
+        queue.reserve_trades().unwrap();
+        assert_eq!(
+            queue.reserve_trades().unwrap_err(),
+            ErrorCode::SettlementQueueFull.into()
+        );
+        queue.release_reserved_trades();
+
+        for _ in 0..MAX_PENDING_TRADES {
+            queue.push_trade(trade(alice, bob, 1, 2)).unwrap();
+        }
+        assert_eq!(
+            queue.push_trade(trade(alice, bob, 1, 2)).unwrap_err(),
+            ErrorCode::SettlementQueueFull.into()
+        );
+        assert_eq!(
+            queue.reserve_trades().unwrap_err(),
+            ErrorCode::SettlementQueueFull.into()
+        );
+        assert_eq!(queue.pending_collateral(alice), MAX_PENDING_TRADES as u64);
+        assert_eq!(queue.pending_collateral(bob), 2 * MAX_PENDING_TRADES as u64);
+    }
+
+    #[test]
+    fn test_refundable_amount() {
+        let mut book = DarkOrderBook::default();
+        let mut queue = SettlementQueue::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        active_order(&mut book, alice, 100);
+        active_order(&mut book, bob, 500);
+        queue.push_trade(trade(alice, bob, 30, 70)).unwrap();
+
+        assert_eq!(refundable_amount(200, &book, &queue, alice), 70);
+        assert_eq!(refundable_amount(130, &book, &queue, alice), 0);
+        // the balance can't be lower than the locked collateral, never underflow
+        assert_eq!(refundable_amount(50, &book, &queue, alice), 0);
+        assert_eq!(refundable_amount(570, &book, &queue, bob), 0);
+        assert_eq!(
+            refundable_amount(25, &book, &queue, Pubkey::new_unique()),
+            25
+        );
+    }
+
+    #[test]
+    fn test_cancel_refund_with_matching_order() {
+        let mut book = DarkOrderBook::default();
+        let queue = SettlementQueue::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        let locked = active_order(&mut book, alice, 200);
+        active_order(&mut book, bob, 500);
+        book.start_matching(100).unwrap();
+        let cancelled = active_order(&mut book, alice, 100);
+        let mut balance = 300;
+
+        // orders included in the queued computation can't be cancelled
+        assert_eq!(
+            book.cancel_slot(locked, alice).unwrap_err(),
+            ErrorCode::OrderNotActive.into()
+        );
+        assert_eq!(
+            book.cancel_slot(cancelled, bob).unwrap_err(),
+            ErrorCode::InvalidOrderOwner.into()
+        );
+
+        // only the escrow of the cancelled order is refunded
+        book.cancel_slot(cancelled, alice).unwrap();
+        let refund = refundable_amount(balance, &book, &queue, alice);
+        assert_eq!(refund, 100);
+        balance -= refund;
+
+        assert_eq!(refundable_amount(balance, &book, &queue, alice), 0);
+        assert_eq!(book.get_slot(locked).unwrap().status, OrderStatus::Matching);
+        assert_eq!(book.escrowed_amount(alice), 200);
+    }
+
+    #[test]
+    fn test_cancel_refund_with_queued_trade() {
+        let mut book = DarkOrderBook::default();
+        let mut queue = SettlementQueue::default();
+        let alice = Pubkey::new_unique();
+        let bob = Pubkey::new_unique();
+
+        let alice_slot = active_order(&mut book, alice, 100);
+        let bob_slot = active_order(&mut book, bob, 100);
+        let mut balance = 100;
+
+        // partial fill, the filled collateral backs the queued trade
+        book.start_matching(100).unwrap();
+        book.take_escrow(alice_slot, 40).unwrap();
+        book.take_escrow(bob_slot, 40).unwrap();
+        let trade_id = queue.push_trade(trade(alice, bob, 40, 40)).unwrap();
+        book.update_slot(alice_slot, 8, [[3; 32]; DARK_ORDER_FIELDS])
+            .unwrap();
+
+        book.cancel_slot(alice_slot, alice).unwrap();
+        let refund = refundable_amount(balance, &book, &queue, alice);
+        assert_eq!(refund, 60);
+        balance -= refund;
+
+        // collateral of the pending trade stays in the escrow
+        assert_eq!(refundable_amount(balance, &book, &queue, alice), 0);
+
+        // and leaves it at settlement
+        queue.take_trade(trade_id).unwrap();
+        balance -= 40;
+        assert_eq!(refundable_amount(balance, &book, &queue, alice), 0);
+    }
+
+    #[test]
+    fn test_pair_auction_fills() {
+        let mut long_fills = [0; MAX_ORDERS];
+        let mut short_fills = [0; MAX_ORDERS];
+        let mut fill_collateral = [0; MAX_ORDERS];
+        assert!(pair_auction_fills(&long_fills, &short_fills, &fill_collateral).is_empty());
+
+        // One long filled against two shorts, its collateral is split with
+        // the rounding remainder in the last pair
+        long_fills[0] = 300;
+        short_fills[2] = 150;
+        short_fills[3] = 150;
+        fill_collateral[0] = 31;
+        fill_collateral[2] = 15;
+        fill_collateral[3] = 15;
+        assert_eq!(
+            pair_auction_fills(&long_fills, &short_fills, &fill_collateral),
+            vec![
+                AuctionFill {
+                    long_slot: 0,
+                    short_slot: 2,
+                    size_usd: 150,
+                    collateral_a: 15,
+                    collateral_b: 15,
+                },
+                AuctionFill {
+                    long_slot: 0,
+                    short_slot: 3,
+                    size_usd: 150,
+                    collateral_a: 16,
+                    collateral_b: 15,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pair_auction_fills_conservation() {
+        let long_fills = [0, 100, 0, 0, 250, 0, 0, 0];
+        let short_fills = [200, 0, 0, 0, 0, 0, 150, 0];
+        let fill_collateral = [21, 10, 0, 0, 33, 0, 17, 0];
+
+        let fills = pair_auction_fills(&long_fills, &short_fills, &fill_collateral);
+
+        let pairs: Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.long_slot, fill.short_slot, fill.size_usd))
+            .collect();
+        assert_eq!(pairs, vec![(1, 0, 100), (4, 0, 100), (4, 6, 150)]);
+
+        // Every order trades its whole fill backed by all of its collateral
+        let mut size = [0; MAX_ORDERS];
+        let mut collateral = [0; MAX_ORDERS];
+        for fill in fills.iter() {
+            size[fill.long_slot] += fill.size_usd;
+            size[fill.short_slot] += fill.size_usd;
+            collateral[fill.long_slot] += fill.collateral_a;
+            collateral[fill.short_slot] += fill.collateral_b;
+        }
+        for slot in 0..MAX_ORDERS {
+            assert_eq!(size[slot], long_fills[slot] + short_fills[slot]);
+            assert_eq!(collateral[slot], fill_collateral[slot]);
+        }
+    }
 }
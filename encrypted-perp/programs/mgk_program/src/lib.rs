@@ -4,6 +4,8 @@ use arcium_anchor::prelude::*;
 pub mod darkpool;
 
 use darkpool::*;
+// shadows the anchor and arcium prelude error codes
+use darkpool::ErrorCode;
 
 const COMP_DEF_OFFSET_ADD_TOGETHER: u32 = comp_def_offset("add_together");
 
@@ -12,7 +14,6 @@ declare_id!("BbtSLsMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL");
 #[arcium_program]
 pub mod mgk_program {
     use super::*;
-    use darkpool::*;
 
     // ===== Original Add Together Functions =====
     
@@ -65,6 +66,28 @@ pub mod mgk_program {
         darkpool::initialize_darkpool(ctx, params)
     }
 
+    pub fn initialize_order_book(
+        ctx: Context<InitializeOrderBook>,
+        params: InitializeOrderBookParams,
+    ) -> Result<()> {
+        darkpool::initialize_order_book(ctx, params)
+    }
+
+    pub fn set_price_band(ctx: Context<SetPriceBand>, params: SetPriceBandParams) -> Result<()> {
+        darkpool::set_price_band(ctx, params)
+    }
+
+    pub fn initialize_auction_epoch(
+        ctx: Context<InitializeAuctionEpoch>,
+        params: InitializeAuctionEpochParams,
+    ) -> Result<()> {
+        darkpool::initialize_auction_epoch(ctx, params)
+    }
+
+    pub fn initialize_settlement_queue(ctx: Context<InitializeSettlementQueue>) -> Result<()> {
+        darkpool::initialize_settlement_queue(ctx)
+    }
+
     pub fn init_submit_dark_order_comp_def(
         ctx: Context<InitSubmitDarkOrderCompDef>
     ) -> Result<()> {
@@ -83,14 +106,30 @@ pub mod mgk_program {
         darkpool::init_batch_process_orders_comp_def(ctx)
     }
 
+    pub fn init_run_batch_auction_comp_def(
+        ctx: Context<InitRunBatchAuctionCompDef>
+    ) -> Result<()> {
+        darkpool::init_run_batch_auction_comp_def(ctx)
+    }
+
     pub fn submit_dark_order(
         ctx: Context<SubmitDarkOrder>,
         computation_offset: u64,
-        encrypted_order: [u8; 256],
+        encrypted_order: [[u8; 32]; DARK_ORDER_FIELDS],
         pub_key: [u8; 32],
         nonce: u128,
+        expires_at: i64,
+        escrow_amount: u64,
     ) -> Result<()> {
-        darkpool::submit_dark_order(ctx, computation_offset, encrypted_order, pub_key, nonce)
+        darkpool::submit_dark_order(
+            ctx,
+            computation_offset,
+            encrypted_order,
+            pub_key,
+            nonce,
+            expires_at,
+            escrow_amount,
+        )
     }
 
     #[arcium_callback(encrypted_ix = "submit_dark_order")]
@@ -101,14 +140,20 @@ pub mod mgk_program {
         darkpool::submit_dark_order_callback(ctx, output)
     }
 
-    pub fn match_dark_orders(
-        ctx: Context<MatchDarkOrders>,
-        computation_offset: u64,
-        encrypted_orders: Vec<u8>,
-        pub_key: [u8; 32],
-        nonce: u128,
-    ) -> Result<()> {
-        darkpool::match_dark_orders(ctx, computation_offset, encrypted_orders, pub_key, nonce)
+    pub fn cancel_dark_order(ctx: Context<CancelDarkOrder>, slot: u8) -> Result<()> {
+        darkpool::cancel_dark_order(ctx, slot)
+    }
+
+    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>) -> Result<()> {
+        darkpool::withdraw_escrow(ctx)
+    }
+
+    pub fn expire_dark_order(ctx: Context<ExpireDarkOrder>, slot: u8) -> Result<()> {
+        darkpool::expire_dark_order(ctx, slot)
+    }
+
+    pub fn match_dark_orders(ctx: Context<MatchDarkOrders>, computation_offset: u64) -> Result<()> {
+        darkpool::match_dark_orders(ctx, computation_offset)
     }
 
     #[arcium_callback(encrypted_ix = "match_dark_orders")]
@@ -119,11 +164,24 @@ pub mod mgk_program {
         darkpool::match_dark_orders_callback(ctx, output)
     }
 
-    pub fn settle_dark_pool_trades(
-        ctx: Context<SettleDarkPoolTrades>,
-        settlement_data: SettlementData,
+    pub fn run_batch_auction(ctx: Context<RunBatchAuction>, computation_offset: u64) -> Result<()> {
+        darkpool::run_batch_auction(ctx, computation_offset)
+    }
+
+    #[arcium_callback(encrypted_ix = "run_batch_auction")]
+    pub fn run_batch_auction_callback(
+        ctx: Context<RunBatchAuctionCallback>,
+        output: ComputationOutputs<RunBatchAuctionOutput>,
     ) -> Result<()> {
-        darkpool::settle_dark_pool_trades(ctx, settlement_data)
+        darkpool::run_batch_auction_callback(ctx, output)
+    }
+
+    pub fn settle_dark_pool_trade(ctx: Context<SettleDarkPoolTrade>, trade_id: u64) -> Result<()> {
+        darkpool::settle_dark_pool_trade(ctx, trade_id)
+    }
+
+    pub fn expire_dark_trade(ctx: Context<ExpireDarkTrade>, trade_id: u64) -> Result<()> {
+        darkpool::expire_dark_trade(ctx, trade_id)
     }
 }
 
@@ -215,11 +273,3 @@ pub struct SumEvent {
     pub sum: [u8; 32],
     pub nonce: [u8; 16],
 }
-
-#[error_code]
-pub enum ErrorCode {
-    #[msg("The computation was aborted")]
-    AbortedComputation,
-    #[msg("Cluster not set")]
-    ClusterNotSet,
-}
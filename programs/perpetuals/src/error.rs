@@ -1,6 +1,6 @@
 //! Error types
 
-use anchor_lang::prelude::*;
+use {anchor_lang::prelude::*, perpetuals_pricing::PricingError};
 
 #[error_code]
 pub enum PerpetualsError {
@@ -77,4 +77,29 @@ pub enum PerpetualsError {
     InvalidPositionSize,
     #[msg("Invalid price")]
     InvalidPrice,
+    #[msg("Admin instructions must be executed through a timelocked proposal")]
+    TimelockRequired,
+    #[msg("Proposal is not approved or timelock has not expired")]
+    ProposalNotExecutable,
+    #[msg("Proposal has already been approved")]
+    ProposalAlreadyApproved,
+    #[msg("Invalid proposal instruction")]
+    InvalidProposalInstruction,
+    #[msg("Instruction must be signed by the position owner or its trading delegate")]
+    InvalidPositionAuthority,
+    #[msg("Trading delegate limit exceeded")]
+    DelegateLimitExceeded,
+    #[msg("Token mint extensions are not supported")]
+    UnsupportedTokenMint,
+}
+
+/// Maps pricing engine errors to the errors returned by the program
+pub fn map_pricing_error(err: PricingError) -> Error {
+    match err {
+        PricingError::MathOverflow => PerpetualsError::MathOverflow.into(),
+        PricingError::InvalidArgument => ProgramError::InvalidArgument.into(),
+        PricingError::MaxPriceSlippage => PerpetualsError::MaxPriceSlippage.into(),
+        PricingError::TokenRatioOutOfRange => PerpetualsError::TokenRatioOutOfRange.into(),
+        PricingError::InstructionNotAllowed => PerpetualsError::InstructionNotAllowed.into(),
+    }
 }
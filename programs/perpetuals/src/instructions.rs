@@ -1,15 +1,25 @@
 // admin instructions
 pub mod add_custody;
 pub mod add_pool;
+pub mod approve_proposal;
+pub mod cancel_proposal;
+pub mod create_proposal;
+pub mod execute_proposal;
 pub mod init;
+pub mod migrate_custody;
 pub mod remove_custody;
 pub mod remove_pool;
 pub mod set_admin_signers;
 pub mod set_custody_config;
+pub mod set_custody_risk;
 pub mod set_custom_oracle_price;
+pub mod set_fee_tiers;
 pub mod set_permissions;
+pub mod set_role_signers;
+pub mod set_timelock_delay;
 pub mod settle_dark_pool_trade;
 pub mod upgrade_custody;
+pub mod upgrade_multisig;
 pub mod withdraw_fees;
 pub mod withdraw_sol_fees;
 
@@ -35,20 +45,28 @@ pub mod liquidate;
 pub mod open_position;
 pub mod remove_collateral;
 pub mod remove_liquidity;
+pub mod remove_trading_delegate;
 pub mod set_custom_oracle_price_permissionless;
+pub mod set_trading_delegate;
 pub mod swap;
+pub mod transfer_position;
 pub mod update_pool_aum;
+pub mod upgrade_position;
 
 // bring everything in scope
 pub use {
-    add_collateral::*, add_custody::*, add_liquidity::*, add_pool::*, close_position::*,
+    add_collateral::*, add_custody::*, add_liquidity::*, add_pool::*, approve_proposal::*,
+    cancel_proposal::*, close_position::*, create_proposal::*, execute_proposal::*,
     get_add_liquidity_amount_and_fee::*, get_assets_under_management::*,
     get_entry_price_and_fee::*, get_exit_price_and_fee::*, get_liquidation_price::*,
     get_liquidation_state::*, get_lp_token_price::*, get_oracle_price::*, get_pnl::*,
     get_remove_liquidity_amount_and_fee::*, get_swap_amount_and_fees::*, init::*, liquidate::*,
-    open_position::*, remove_collateral::*, remove_custody::*, remove_liquidity::*, remove_pool::*,
-    set_admin_signers::*, set_custody_config::*, set_custom_oracle_price::*,
-    set_custom_oracle_price_permissionless::*, set_permissions::*, set_test_time::*, 
-    settle_dark_pool_trade::*, swap::*, update_pool_aum::*, upgrade_custody::*, 
-    withdraw_fees::*, withdraw_sol_fees::*,
+    migrate_custody::*, open_position::*, remove_collateral::*, remove_custody::*,
+    remove_liquidity::*, remove_pool::*, remove_trading_delegate::*, set_admin_signers::*,
+    set_custody_config::*, set_custody_risk::*, set_custom_oracle_price::*,
+    set_custom_oracle_price_permissionless::*, set_fee_tiers::*, set_permissions::*,
+    set_role_signers::*, set_test_time::*, set_timelock_delay::*, set_trading_delegate::*,
+    settle_dark_pool_trade::*, swap::*, transfer_position::*, update_pool_aum::*,
+    upgrade_custody::*, upgrade_multisig::*, upgrade_position::*, withdraw_fees::*,
+    withdraw_sol_fees::*,
 };
@@ -10,10 +10,11 @@ use {
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            trading_delegate::TradingDelegate,
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -21,14 +22,18 @@ use {
 #[instruction(params: AddCollateralParams)]
 pub struct AddCollateral<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, must be the authority unless a trading delegate is used
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = funding_account.mint == custody.mint,
         has_one = owner
     )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
+    pub funding_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -58,7 +63,8 @@ pub struct AddCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -94,14 +100,27 @@ pub struct AddCollateral<'info> {
                  collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
+
+    #[account(
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_delegate.bump
+    )]
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct AddCollateralParams {
-    collateral: u64,
+    pub collateral: u64,
 }
 
 pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams) -> Result<()> {
@@ -116,9 +135,22 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let position = ctx.accounts.position.as_mut();
     let pool = ctx.accounts.pool.as_mut();
 
-    // compute position price
+    // check trading authority
     let curtime = perpetuals.get_time()?;
+    let trading_delegate = TradingDelegate::get_delegate(
+        &ctx.accounts.owner.key(),
+        &ctx.accounts.authority.key(),
+        ctx.accounts.trading_delegate.as_deref().map(|delegate| &**delegate),
+        curtime,
+    )?;
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.permissions.allow_add_collateral,
+            PerpetualsError::InstructionNotAllowed
+        );
+    }
 
+    // compute position price
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
@@ -154,9 +186,15 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    // compute amount to transfer
-    let collateral_usd = min_collateral_price
-        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
+    // compute amount to transfer, tokens withheld by the mint's transfer fee
+    // never reach the custody
+    let transfer_fee = Perpetuals::get_transfer_fee(
+        &ctx.accounts.collateral_custody_token_mint.to_account_info(),
+        params.collateral,
+    )?;
+    let collateral = math::checked_sub(params.collateral, transfer_fee)?;
+    let collateral_usd =
+        min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
     msg!("Amount in: {}", params.collateral);
     msg!("Collateral added in USD: {}", collateral_usd);
 
@@ -164,7 +202,7 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     msg!("Update existing position");
     position.update_time = perpetuals.get_time()?;
     position.collateral_usd = math::checked_add(position.collateral_usd, collateral_usd)?;
-    position.collateral_amount = math::checked_add(position.collateral_amount, params.collateral)?;
+    position.collateral_amount = math::checked_add(position.collateral_amount, collateral)?;
 
     // check position risk
     msg!("Check position risks");
@@ -190,7 +228,8 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
         ctx.accounts
             .collateral_custody_token_account
             .to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.collateral,
     )?;
@@ -198,12 +237,40 @@ pub fn add_collateral(ctx: Context<AddCollateral>, params: &AddCollateralParams)
     // update custody stats
     msg!("Update custody stats");
     collateral_custody.assets.collateral =
-        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
+        math::checked_add(collateral_custody.assets.collateral, collateral)?;
 
     // if custody and collateral_custody accounts are the same, ensure that data is in sync
     if position.side == Side::Long && !custody.is_virtual {
         *custody = collateral_custody.clone();
     }
 
+    emit!(CollateralAdded {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        position: position.key(),
+        amount: collateral,
+        amount_usd: collateral_usd,
+        collateral_usd: position.collateral_usd,
+        collateral_amount: position.collateral_amount,
+        timestamp: curtime,
+    });
+
     Ok(())
 }
+
+#[event]
+pub struct CollateralAdded {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub amount_usd: u64,
+    // position collateral after the update
+    pub collateral_usd: u64,
+    pub collateral_amount: u64,
+    pub timestamp: i64,
+}
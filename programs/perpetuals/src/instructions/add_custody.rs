@@ -8,11 +8,11 @@ use {
             multisig::{AdminInstruction, Multisig},
             oracle::OracleParams,
             perpetuals::{Permissions, Perpetuals},
-            pool::{Pool, TokenRatios},
+            pool::{FeeTier, Pool, TokenRatios},
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Mint, Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -43,7 +43,8 @@ pub struct AddCustody<'info> {
     #[account(
         mut,
         realloc = Pool::LEN + (pool.custodies.len() + 1) * std::mem::size_of::<Pubkey>() +
-                              (pool.ratios.len() + 1) * std::mem::size_of::<TokenRatios>(),
+                              (pool.ratios.len() + 1) * std::mem::size_of::<TokenRatios>() +
+                              pool.fee_tiers.len() * std::mem::size_of::<FeeTier>(),
         realloc::payer = admin,
         realloc::zero = false,
         seeds = [b"pool",
@@ -68,18 +69,20 @@ pub struct AddCustody<'info> {
         payer = admin,
         token::mint = custody_token_mint,
         token::authority = transfer_authority,
+        token::token_program = token_program,
         seeds = [b"custody_token_account",
                  pool.key().as_ref(),
                  custody_token_mint.key().as_ref()],
         bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account()]
-    pub custody_token_mint: Box<Account<'info, Mint>>,
+    pub custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    // token program of the custody mint, SPL Token or Token-2022
+    token_program: Interface<'info, TokenInterface>,
     rent: Sysvar<'info, Rent>,
 }
 
@@ -103,6 +106,9 @@ pub fn add_custody<'info>(
     if params.ratios.len() != ctx.accounts.pool.ratios.len() + 1 {
         return Err(ProgramError::InvalidArgument.into());
     }
+    if !Perpetuals::is_supported_mint(&ctx.accounts.custody_token_mint.to_account_info())? {
+        return err!(PerpetualsError::UnsupportedTokenMint);
+    }
 
     // validate signatures
     let mut multisig = ctx.accounts.multisig.load_mut()?;
@@ -138,6 +144,7 @@ pub fn add_custody<'info>(
     custody.pool = pool.key();
     custody.mint = ctx.accounts.custody_token_mint.key();
     custody.token_account = ctx.accounts.custody_token_account.key();
+    custody.token_program = ctx.accounts.token_program.key();
     custody.decimals = ctx.accounts.custody_token_mint.decimals;
     custody.is_stable = params.is_stable;
     custody.is_virtual = params.is_virtual;
@@ -155,8 +162,37 @@ pub fn add_custody<'info>(
         .ok_or(ProgramError::InvalidSeeds)?;
 
     if !custody.validate() {
-        err!(PerpetualsError::InvalidCustodyConfig)
-    } else {
-        Ok(0)
+        return err!(PerpetualsError::InvalidCustodyConfig);
     }
+
+    emit!(CustodyAdded {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        mint: ctx.accounts.custody_token_mint.key(),
+        is_stable: params.is_stable,
+        is_virtual: params.is_virtual,
+        oracle: params.oracle,
+        pricing: params.pricing,
+        permissions: params.permissions,
+        fees: params.fees,
+        borrow_rate: params.borrow_rate,
+        ratios: params.ratios.clone(),
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct CustodyAdded {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub mint: Pubkey,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: OracleParams,
+    pub pricing: PricingParams,
+    pub permissions: Permissions,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+    pub ratios: Vec<TokenRatios>,
 }
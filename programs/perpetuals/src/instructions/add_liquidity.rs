@@ -12,7 +12,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Mint, Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -27,14 +27,14 @@ pub struct AddLiquidity<'info> {
         constraint = funding_account.mint == custody.mint,
         has_one = owner
     )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
+    pub funding_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = lp_token_account.mint == lp_token_mint.key(),
         has_one = owner
     )]
-    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+    pub lp_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -79,7 +79,12 @@ pub struct AddLiquidity<'info> {
                  custody.mint.as_ref()],
         bump = custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = custody_token_mint.key() == custody.mint
+    )]
+    pub custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
@@ -87,9 +92,10 @@ pub struct AddLiquidity<'info> {
                  pool.key().as_ref()],
         bump = pool.lp_token_bump
     )]
-    pub lp_token_mint: Box<Account<'info, Mint>>,
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
+    lp_token_program: Interface<'info, TokenInterface>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -121,12 +127,20 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
     let pool = ctx.accounts.pool.as_mut();
     let token_id = pool.get_token_id(&custody.key())?;
 
+    // tokens withheld by the mint's transfer fee never reach the custody
+    let transfer_fee = Perpetuals::get_transfer_fee(
+        &ctx.accounts.custody_token_mint.to_account_info(),
+        params.amount_in,
+    )?;
+    let amount_in = math::checked_sub(params.amount_in, transfer_fee)?;
+
     // calculate fee
     let curtime = perpetuals.get_time()?;
 
     // Refresh pool.aum_usm to adapt to token price change
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+    let aum_usd_before = pool.aum_usd;
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
@@ -148,14 +162,13 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         token_ema_price
     };
 
-    let fee_amount =
-        pool.get_add_liquidity_fee(token_id, params.amount_in, custody, &token_ema_price)?;
+    let fee_amount = pool.get_add_liquidity_fee(token_id, amount_in, custody, &token_ema_price)?;
     msg!("Collected fee: {}", fee_amount);
 
     // check pool constraints
     msg!("Check pool constraints");
     let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee)?;
+    let deposit_amount = math::checked_sub(amount_in, protocol_fee)?;
     require!(
         pool.check_token_ratio(token_id, deposit_amount, 0, custody, &token_ema_price)?,
         PerpetualsError::TokenRatioOutOfRange
@@ -167,6 +180,7 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         ctx.accounts.funding_account.to_account_info(),
         ctx.accounts.custody_token_account.to_account_info(),
         ctx.accounts.owner.to_account_info(),
+        ctx.accounts.custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.amount_in,
     )?;
@@ -177,7 +191,7 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         pool.get_assets_under_management_usd(AumCalcMode::Max, ctx.remaining_accounts, curtime)?;
 
     // compute amount of lp tokens to mint
-    let no_fee_amount = math::checked_sub(params.amount_in, fee_amount)?;
+    let no_fee_amount = math::checked_sub(amount_in, fee_amount)?;
     require_gte!(
         no_fee_amount,
         1u64,
@@ -209,21 +223,23 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
         ctx.accounts.lp_token_mint.to_account_info(),
         ctx.accounts.lp_token_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.lp_token_program.to_account_info(),
         lp_amount,
     )?;
 
     // update custody stats
     msg!("Update custody stats");
+    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
     custody.collected_fees.add_liquidity_usd = custody
         .collected_fees
         .add_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+        .wrapping_add(fee_amount_usd);
 
+    let amount_in_usd = token_ema_price.get_asset_amount_usd(amount_in, custody.decimals)?;
     custody.volume_stats.add_liquidity_usd = custody
         .volume_stats
         .add_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(params.amount_in, custody.decimals)?);
+        .wrapping_add(amount_in_usd);
 
     custody.assets.protocol_fees = math::checked_add(custody.assets.protocol_fees, protocol_fee)?;
 
@@ -237,5 +253,34 @@ pub fn add_liquidity(ctx: Context<AddLiquidity>, params: &AddLiquidityParams) ->
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
 
+    emit!(LiquidityAdded {
+        owner: ctx.accounts.owner.key(),
+        pool: pool.key(),
+        custody: custody.key(),
+        amount_in,
+        amount_in_usd,
+        fee_amount,
+        fee_amount_usd,
+        lp_amount,
+        aum_usd_before,
+        aum_usd_after: pool.aum_usd,
+        timestamp: curtime,
+    });
+
     Ok(())
 }
+
+#[event]
+pub struct LiquidityAdded {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub amount_in: u64,
+    pub amount_in_usd: u64,
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub lp_amount: u64,
+    pub aum_usd_before: u128,
+    pub aum_usd_after: u128,
+    pub timestamp: i64,
+}
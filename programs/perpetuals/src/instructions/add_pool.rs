@@ -10,7 +10,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Mint, Token},
+    anchor_spl::token_interface::{Mint, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -64,14 +64,16 @@ pub struct AddPool<'info> {
         mint::authority = transfer_authority,
         mint::freeze_authority = transfer_authority,
         mint::decimals = Perpetuals::LP_DECIMALS,
+        mint::token_program = token_program,
         seeds = [b"lp_token_mint",
                  pool.key().as_ref()],
         bump
     )]
-    pub lp_token_mint: Box<Account<'info, Mint>>,
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    // token program of the LP token mint, SPL Token or Token-2022
+    token_program: Interface<'info, TokenInterface>,
     rent: Sysvar<'info, Rent>,
 }
 
@@ -121,6 +123,7 @@ pub fn add_pool<'info>(
         .bumps
         .get("lp_token_mint")
         .ok_or(ProgramError::InvalidSeeds)?;
+    pool.lp_token_program = ctx.accounts.token_program.key();
 
     if !pool.validate() {
         return err!(PerpetualsError::InvalidPoolConfig);
@@ -128,5 +131,18 @@ pub fn add_pool<'info>(
 
     perpetuals.pools.push(ctx.accounts.pool.key());
 
+    emit!(PoolAdded {
+        pool: ctx.accounts.pool.key(),
+        name: params.name.clone(),
+        lp_token_mint: ctx.accounts.lp_token_mint.key(),
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct PoolAdded {
+    pub pool: Pubkey,
+    pub name: String,
+    pub lp_token_mint: Pubkey,
+}
@@ -0,0 +1,64 @@
+//! ApproveProposal instruction handler
+
+use {
+    crate::state::{multisig::Multisig, perpetuals::Perpetuals, proposal::Proposal},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct ApproveProposal<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        seeds = [b"proposal",
+                 multisig.load()?.get_role()?.get_seed(),
+                 proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Box<Account<'info, Proposal>>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ApproveProposalParams {}
+
+pub fn approve_proposal(
+    ctx: Context<ApproveProposal>,
+    _params: &ApproveProposalParams,
+) -> Result<u8> {
+    let multisig = ctx.accounts.multisig.load()?;
+    let proposal = ctx.accounts.proposal.as_mut();
+
+    let approvals_left = proposal.approve(
+        ctx.accounts.admin.key,
+        &multisig,
+        ctx.accounts.perpetuals.get_time()?,
+    )?;
+    if approvals_left > 0 {
+        msg!(
+            "Proposal has been approved but more approvals are required: {}",
+            approvals_left
+        );
+    } else {
+        msg!(
+            "Proposal {} can be executed at {}",
+            proposal.id,
+            proposal.eta
+        );
+    }
+
+    Ok(approvals_left)
+}
@@ -0,0 +1,57 @@
+//! CancelProposal instruction handler
+
+use {
+    crate::state::{multisig::Multisig, proposal::Proposal},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        has_one = proposer,
+        seeds = [b"proposal",
+                 multisig.load()?.get_role()?.get_seed(),
+                 proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump
+    )]
+    pub proposal: Box<Account<'info, Proposal>>,
+
+    /// CHECK: proposal rent receiver
+    #[account(mut)]
+    pub proposer: AccountInfo<'info>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CancelProposalParams {}
+
+pub fn cancel_proposal(ctx: Context<CancelProposal>, _params: &CancelProposalParams) -> Result<u8> {
+    let multisig = ctx.accounts.multisig.load()?;
+    let proposal = ctx.accounts.proposal.as_mut();
+
+    let cancellations_left = proposal.cancel(ctx.accounts.admin.key, &multisig)?;
+    if cancellations_left > 0 {
+        msg!(
+            "Proposal {} is queued, more cancellations are required: {}",
+            proposal.id,
+            cancellations_left
+        );
+        return Ok(cancellations_left);
+    }
+
+    msg!("Proposal {} has been cancelled", proposal.id);
+    ctx.accounts
+        .proposal
+        .close(ctx.accounts.proposer.to_account_info())?;
+
+    Ok(0)
+}
@@ -3,30 +3,35 @@
 use {
     crate::{
         error::PerpetualsError,
-        math,
         state::{
             custody::Custody,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            trading_delegate::TradingDelegate,
+            trading_stats::TradingStats,
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, must be the authority unless a trading delegate is used
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = receiving_account.mint == collateral_custody.mint,
         has_one = owner
     )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -56,12 +61,24 @@ pub struct ClosePosition<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump,
         close = owner
     )]
     pub position: Box<Account<'info, Position>>,
 
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = TradingStats::LEN,
+        seeds = [b"trading_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub trading_stats: Box<Account<'info, TradingStats>>,
+
     #[account(
         mut,
         constraint = position.custody == custody.key()
@@ -93,9 +110,23 @@ pub struct ClosePosition<'info> {
                  collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    token_program: Program<'info, Token>,
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
+
+    #[account(
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_delegate.bump
+    )]
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -114,6 +145,24 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         PerpetualsError::InstructionNotAllowed
     );
 
+    // check trading authority
+    let curtime = perpetuals.get_time()?;
+    let trading_delegate = TradingDelegate::get_delegate(
+        &ctx.accounts.owner.key(),
+        &ctx.accounts.authority.key(),
+        ctx.accounts
+            .trading_delegate
+            .as_deref()
+            .map(|delegate| &**delegate),
+        curtime,
+    )?;
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.permissions.allow_close_position,
+            PerpetualsError::InstructionNotAllowed
+        );
+    }
+
     // validate inputs
     msg!("Validate inputs");
     if params.price == 0 {
@@ -123,8 +172,6 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
     let pool = ctx.accounts.pool.as_mut();
 
     // compute exit price
-    let curtime = perpetuals.get_time()?;
-
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
@@ -157,17 +204,9 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         collateral_custody.pricing.use_ema,
     )?;
 
-    let exit_price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
-    msg!("Exit price: {}", exit_price);
-
-    if position.side == Side::Long {
-        require_gte!(exit_price, params.price, PerpetualsError::MaxPriceSlippage);
-    } else {
-        require_gte!(params.price, exit_price, PerpetualsError::MaxPriceSlippage);
-    }
-
-    msg!("Settle position");
-    let (transfer_amount, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+    let trading_stats = ctx.accounts.trading_stats.as_mut();
+    let fee_discount = pool.get_fee_discount(trading_stats.get_rolling_volume_usd(curtime)?);
+    let exit = pool.get_exit_amounts(
         position,
         &token_price,
         &token_ema_price,
@@ -177,27 +216,24 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
         collateral_custody,
         curtime,
         false,
+        fee_discount,
     )?;
+    msg!("Exit price: {}", exit.price);
 
-    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
-    if position.side == Side::Short || custody.is_virtual {
-        fee_amount = collateral_token_ema_price
-            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    if position.side == Side::Long {
+        require_gte!(exit.price, params.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(params.price, exit.price, PerpetualsError::MaxPriceSlippage);
     }
 
-    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
-    msg!("Collected fee: {}", fee_amount);
-    msg!("Amount out: {}", transfer_amount);
-
-    // unlock pool funds
-    collateral_custody.unlock_funds(position.locked_amount)?;
+    msg!("Settle position");
+    msg!("Net profit: {}, loss: {}", exit.profit_usd, exit.loss_usd);
+    msg!("Collected fee: {}", exit.fee_amount);
+    msg!("Amount out: {}", exit.amount_out);
 
-    // check pool constraints
-    msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(transfer_amount, collateral_custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
+    // unlock pool funds and update custody stats
+    msg!("Update custody stats");
+    pool.remove_position(position, &exit, custody, collateral_custody, curtime, false)?;
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -207,97 +243,69 @@ pub fn close_position(ctx: Context<ClosePosition>, params: &ClosePositionParams)
             .to_account_info(),
         ctx.accounts.receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
-        transfer_amount,
+        exit.amount_out,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    collateral_custody.collected_fees.close_position_usd = collateral_custody
-        .collected_fees
-        .close_position_usd
-        .wrapping_add(fee_amount_usd);
-
-    if transfer_amount > position.collateral_amount {
-        let amount_lost = transfer_amount.saturating_sub(position.collateral_amount);
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(transfer_amount);
-        collateral_custody.assets.owned =
-            math::checked_add(collateral_custody.assets.owned, amount_gained)?;
-    }
-    collateral_custody.assets.collateral = math::checked_sub(
-        collateral_custody.assets.collateral,
-        position.collateral_amount,
-    )?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, collateral_custody)? {
-        collateral_custody.assets.protocol_fees =
-            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
-
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
-    }
-
-    // if custody and collateral_custody accounts are the same, ensure that data is in sync
-    if position.side == Side::Long && !custody.is_virtual {
-        collateral_custody.volume_stats.close_position_usd = collateral_custody
-            .volume_stats
-            .close_position_usd
-            .wrapping_add(position.size_usd);
-
-        if position.side == Side::Long {
-            collateral_custody.trade_stats.oi_long_usd = collateral_custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            collateral_custody.trade_stats.oi_short_usd = collateral_custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        collateral_custody.trade_stats.profit_usd = collateral_custody
-            .trade_stats
-            .profit_usd
-            .wrapping_add(profit_usd);
-        collateral_custody.trade_stats.loss_usd = collateral_custody
-            .trade_stats
-            .loss_usd
-            .wrapping_add(loss_usd);
-
-        collateral_custody.remove_position(position, curtime, None)?;
-        collateral_custody.update_borrow_rate(curtime)?;
-        *custody = collateral_custody.clone();
-    } else {
-        custody.volume_stats.close_position_usd = custody
-            .volume_stats
-            .close_position_usd
-            .wrapping_add(position.size_usd);
-
-        if position.side == Side::Long {
-            custody.trade_stats.oi_long_usd = custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            custody.trade_stats.oi_short_usd = custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-        custody.remove_position(position, curtime, Some(collateral_custody))?;
-        collateral_custody.update_borrow_rate(curtime)?;
+    // update trader's volume for fee tiers
+    if trading_stats.owner == Pubkey::default() {
+        trading_stats.owner = ctx.accounts.owner.key();
+        trading_stats.pool = pool.key();
+        trading_stats.bump = *ctx
+            .bumps
+            .get("trading_stats")
+            .ok_or(ProgramError::InvalidSeeds)?;
     }
+    trading_stats.add_volume(position.size_usd, curtime)?;
+
+    emit!(PositionClosed {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        position: position.key(),
+        side: position.side,
+        entry_price: position.price,
+        exit_price: exit.price,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        collateral_amount: position.collateral_amount,
+        profit_usd: exit.profit_usd,
+        loss_usd: exit.loss_usd,
+        interest_usd: exit.interest_usd,
+        fee_amount: exit.fee_amount,
+        fee_amount_usd: exit.fee_amount_usd,
+        fee_discount,
+        transfer_amount: exit.amount_out,
+        timestamp: curtime,
+    });
 
     Ok(())
 }
+
+#[event]
+pub struct PositionClosed {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub position: Pubkey,
+    pub side: Side,
+    pub entry_price: u64,
+    pub exit_price: u64,
+    pub size_usd: u64,
+    pub collateral_usd: u64,
+    pub collateral_amount: u64,
+    // net of fees and interest
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub interest_usd: u64,
+    // fee amount is in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub fee_discount: u64,
+    // collateral tokens returned to the owner
+    pub transfer_amount: u64,
+    pub timestamp: i64,
+}
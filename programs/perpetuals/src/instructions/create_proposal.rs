@@ -0,0 +1,102 @@
+//! CreateProposal instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            multisig::Multisig,
+            perpetuals::Perpetuals,
+            proposal::{Proposal, ProposalAccount},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: CreateProposalParams)]
+pub struct CreateProposal<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Proposal::get_size(
+            params.instruction_data.len(),
+            params.instruction_accounts.len()
+        ),
+        seeds = [b"proposal",
+                 multisig.load()?.get_role()?.get_seed(),
+                 params.id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Box<Account<'info, Proposal>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct CreateProposalParams {
+    // must match the multisig proposals counter
+    pub id: u64,
+    pub instruction_data: Vec<u8>,
+    pub instruction_accounts: Vec<ProposalAccount>,
+}
+
+pub fn create_proposal(ctx: Context<CreateProposal>, params: &CreateProposalParams) -> Result<u8> {
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+    if params.id != multisig.num_proposals {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    multisig.num_proposals = multisig.num_proposals.wrapping_add(1);
+
+    // record proposal data
+    let proposal = ctx.accounts.proposal.as_mut();
+    proposal.id = params.id;
+    proposal.proposer = ctx.accounts.admin.key();
+    proposal.instruction_data = params.instruction_data.clone();
+    proposal.instruction_accounts = params.instruction_accounts.clone();
+    proposal.bump = *ctx
+        .bumps
+        .get("proposal")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    if !proposal.validate() {
+        return err!(PerpetualsError::InvalidProposalInstruction);
+    }
+
+    // proposer approves the proposal
+    let approvals_left = proposal.approve(
+        ctx.accounts.admin.key,
+        &multisig,
+        ctx.accounts.perpetuals.get_time()?,
+    )?;
+    if approvals_left > 0 {
+        msg!(
+            "Proposal {} has been created but more approvals are required: {}",
+            proposal.id,
+            approvals_left
+        );
+    } else {
+        msg!(
+            "Proposal {} can be executed at {}",
+            proposal.id,
+            proposal.eta
+        );
+    }
+
+    Ok(approvals_left)
+}
@@ -0,0 +1,107 @@
+//! ExecuteProposal instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{multisig::Multisig, perpetuals::Perpetuals, proposal::Proposal},
+    },
+    anchor_lang::prelude::*,
+    solana_program::{instruction::Instruction, program},
+};
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account()]
+    pub executor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = proposer,
+        seeds = [b"proposal",
+                 multisig.load()?.get_role()?.get_seed(),
+                 proposal.id.to_le_bytes().as_ref()],
+        bump = proposal.bump,
+        close = proposer
+    )]
+    pub proposal: Box<Account<'info, Proposal>>,
+
+    /// CHECK: proposal rent receiver
+    #[account(mut)]
+    pub proposer: AccountInfo<'info>,
+
+    /// CHECK: empty PDA, signs proposal instruction on behalf of the role signers,
+    /// must be funded if the instruction allocates accounts
+    #[account(
+        mut,
+        seeds = [b"timelock_authority", multisig.load()?.get_role()?.get_seed()],
+        bump
+    )]
+    pub timelock_authority: AccountInfo<'info>,
+
+    pub perpetuals_program: Program<'info, crate::program::Perpetuals>,
+    // remaining accounts: proposal instruction accounts in the original order
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct ExecuteProposalParams {}
+
+pub fn execute_proposal<'info>(
+    ctx: Context<'_, '_, '_, 'info, ExecuteProposal<'info>>,
+    _params: &ExecuteProposalParams,
+) -> Result<()> {
+    let proposal = ctx.accounts.proposal.as_ref();
+
+    // check timelock
+    let role_seed = {
+        let multisig = ctx.accounts.multisig.load()?;
+        if !proposal.is_executable(&multisig, ctx.accounts.perpetuals.get_time()?)? {
+            return err!(PerpetualsError::ProposalNotExecutable);
+        }
+        multisig.get_role()?.get_seed()
+    };
+
+    // check instruction accounts
+    if ctx.remaining_accounts.len() != proposal.instruction_accounts.len()
+        || ctx
+            .remaining_accounts
+            .iter()
+            .zip(proposal.instruction_accounts.iter())
+            .any(|(info, account)| info.key != &account.pubkey)
+    {
+        return err!(PerpetualsError::InvalidProposalInstruction);
+    }
+
+    // execute
+    msg!("Execute proposal {}", proposal.id);
+    let instruction = Instruction {
+        program_id: crate::ID,
+        accounts: proposal.get_account_metas(ctx.accounts.timelock_authority.key),
+        data: proposal.instruction_data.clone(),
+    };
+
+    let mut account_infos = ctx.remaining_accounts.to_vec();
+    account_infos.push(ctx.accounts.perpetuals_program.to_account_info());
+
+    let authority_bump = *ctx
+        .bumps
+        .get("timelock_authority")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    let authority_seeds: &[&[&[u8]]] = &[&[b"timelock_authority", role_seed, &[authority_bump]]];
+
+    program::invoke_signed(&instruction, &account_infos, authority_seeds)?;
+
+    Ok(())
+}
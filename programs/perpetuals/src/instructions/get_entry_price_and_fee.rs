@@ -7,6 +7,7 @@ use {
         perpetuals::{NewPositionPricesAndFee, Perpetuals},
         pool::Pool,
         position::{Position, Side},
+        trading_stats::TradingStats,
     },
     anchor_lang::prelude::*,
     solana_program::program_error::ProgramError,
@@ -54,13 +55,25 @@ pub struct GetEntryPriceAndFee<'info> {
         constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
     )]
     pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    /// CHECK: trader the position is quoted for, only used to derive trading stats address
+    pub owner: AccountInfo<'info>,
+
+    // optional, trader's stats to apply volume-based fee discount
+    #[account(
+        seeds = [b"trading_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_stats.bump
+    )]
+    pub trading_stats: Option<Box<Account<'info, TradingStats>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetEntryPriceAndFeeParams {
-    collateral: u64,
-    size: u64,
-    side: Side,
+    pub collateral: u64,
+    pub size: u64,
+    pub side: Side,
 }
 
 pub fn get_entry_price_and_fee(
@@ -113,29 +126,31 @@ pub fn get_entry_price_and_fee(
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let entry_price = pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
-
-    let position_oracle_price = OraclePrice {
-        price: entry_price,
-        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+    let fee_discount = if let Some(trading_stats) = &ctx.accounts.trading_stats {
+        pool.get_fee_discount(trading_stats.get_rolling_volume_usd(curtime)?)
+    } else {
+        0
     };
-    let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
+
+    let entry = pool.get_entry_amounts(
+        params.side,
+        params.size,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
+        collateral_custody,
+        fee_discount,
+    )?;
+
     let collateral_usd = min_collateral_price
         .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
 
-    let locked_amount = if params.side == Side::Short || custody.is_virtual {
-        custody.get_locked_amount(
-            min_collateral_price.get_token_amount(size_usd, collateral_custody.decimals)?,
-            params.side,
-        )?
-    } else {
-        custody.get_locked_amount(params.size, params.side)?
-    };
-
     let position = Position {
         side: params.side,
-        price: entry_price,
-        size_usd,
+        price: entry.price,
+        size_usd: entry.size_usd,
         collateral_usd,
         cumulative_interest_snapshot: collateral_custody.get_cumulative_interest(curtime)?,
         ..Position::default()
@@ -149,22 +164,10 @@ pub fn get_entry_price_and_fee(
         curtime,
     )?;
 
-    let mut fee = pool.get_entry_fee(
-        custody.fees.open_position,
-        params.size,
-        locked_amount,
-        collateral_custody,
-    )?;
-
-    if params.side == Side::Short || custody.is_virtual {
-        let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee, custody.decimals)?;
-        fee = collateral_token_ema_price
-            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
-    }
-
     Ok(NewPositionPricesAndFee {
-        entry_price,
+        entry_price: entry.price,
         liquidation_price,
-        fee,
+        fee: entry.fee_amount,
+        fee_discount,
     })
 }
@@ -7,6 +7,7 @@ use {
         perpetuals::{Perpetuals, PriceAndFee},
         pool::Pool,
         position::{Position, Side},
+        trading_stats::TradingStats,
     },
     anchor_lang::prelude::*,
 };
@@ -31,7 +32,8 @@ pub struct GetExitPriceAndFee<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -63,6 +65,15 @@ pub struct GetExitPriceAndFee<'info> {
         constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
     )]
     pub collateral_custody_oracle_account: AccountInfo<'info>,
+
+    // optional, trader's stats to apply volume-based fee discount
+    #[account(
+        seeds = [b"trading_stats",
+                 position.owner.as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_stats.bump
+    )]
+    pub trading_stats: Option<Box<Account<'info, TradingStats>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -102,11 +113,23 @@ pub fn get_exit_price_and_fee(
         collateral_custody.pricing.use_ema,
     )?;
 
-    let price = pool.get_exit_price(&token_price, &token_ema_price, position.side, custody)?;
+    let price = pool.get_exit_price(
+        &token_price,
+        &token_ema_price,
+        position.side,
+        position.size_usd,
+        custody,
+    )?;
 
     let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
 
-    let mut fee = pool.get_exit_fee(size, custody)?;
+    let fee_discount = if let Some(trading_stats) = &ctx.accounts.trading_stats {
+        pool.get_fee_discount(trading_stats.get_rolling_volume_usd(curtime)?)
+    } else {
+        0
+    };
+
+    let mut fee = pool.get_exit_fee(size, custody, fee_discount)?;
 
     if position.side == Side::Short || custody.is_virtual {
         let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee, custody.decimals)?;
@@ -31,7 +31,8 @@ pub struct GetLiquidationPrice<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -64,8 +65,8 @@ pub struct GetLiquidationPrice<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetLiquidationPriceParams {
-    add_collateral: u64,
-    remove_collateral: u64,
+    pub add_collateral: u64,
+    pub remove_collateral: u64,
 }
 
 pub fn get_liquidation_price(
@@ -28,7 +28,8 @@ pub struct GetLiquidationState<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
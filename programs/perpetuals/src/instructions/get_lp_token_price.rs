@@ -9,7 +9,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::Mint,
+    anchor_spl::token_interface::Mint,
     num_traits::Zero,
 };
 
@@ -33,7 +33,7 @@ pub struct GetLpTokenPrice<'info> {
                  pool.key().as_ref()],
         bump = pool.lp_token_bump
     )]
-    pub lp_token_mint: Box<Account<'info, Mint>>,
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -37,7 +37,7 @@ pub struct GetOraclePrice<'info> {
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetOraclePriceParams {
-    ema: bool,
+    pub ema: bool,
 }
 
 pub fn get_oracle_price(
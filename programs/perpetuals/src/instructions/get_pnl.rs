@@ -31,7 +31,8 @@ pub struct GetPnl<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -116,6 +117,7 @@ pub fn get_pnl(ctx: Context<GetPnl>, _params: &GetPnlParams) -> Result<ProfitAnd
         collateral_custody,
         curtime,
         false,
+        0,
     )?;
 
     Ok(ProfitAndLoss { profit, loss })
@@ -11,7 +11,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::Mint,
+    anchor_spl::token_interface::Mint,
     solana_program::program_error::ProgramError,
 };
 
@@ -49,12 +49,12 @@ pub struct GetRemoveLiquidityAmountAndFee<'info> {
                  pool.key().as_ref()],
         bump = pool.lp_token_bump
     )]
-    pub lp_token_mint: Box<Account<'info, Mint>>,
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct GetRemoveLiquidityAmountAndFeeParams {
-    lp_amount_in: u64,
+    pub lp_amount_in: u64,
 }
 
 pub fn get_remove_liquidity_amount_and_fee(
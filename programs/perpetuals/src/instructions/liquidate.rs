@@ -3,7 +3,6 @@
 use {
     crate::{
         error::PerpetualsError,
-        math,
         state::{
             custody::Custody,
             oracle::OraclePrice,
@@ -13,7 +12,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -26,14 +25,14 @@ pub struct Liquidate<'info> {
         constraint = receiving_account.mint == collateral_custody.mint,
         constraint = receiving_account.owner == position.owner
     )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = rewards_receiving_account.mint == collateral_custody.mint,
         constraint = rewards_receiving_account.owner == signer.key()
     )]
-    pub rewards_receiving_account: Box<Account<'info, TokenAccount>>,
+    pub rewards_receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -62,7 +61,8 @@ pub struct Liquidate<'info> {
                  position.owner.as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump,
         close = signer
     )]
@@ -99,9 +99,14 @@ pub struct Liquidate<'info> {
                  collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    token_program: Program<'info, Token>,
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -173,7 +178,7 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
     );
 
     msg!("Settle position");
-    let (total_amount_out, mut fee_amount, profit_usd, loss_usd) = pool.get_close_amount(
+    let exit = pool.get_exit_amounts(
         position,
         &token_price,
         &token_ema_price,
@@ -183,32 +188,17 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
         collateral_custody,
         curtime,
         true,
+        0,
     )?;
 
-    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
-    if position.side == Side::Short || custody.is_virtual {
-        fee_amount = collateral_token_ema_price
-            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
-    }
-
-    msg!("Net profit: {}, loss: {}", profit_usd, loss_usd);
-    msg!("Collected fee: {}", fee_amount);
+    msg!("Net profit: {}, loss: {}", exit.profit_usd, exit.loss_usd);
+    msg!("Collected fee: {}", exit.fee_amount);
+    msg!("Amount out: {}", exit.amount_out);
+    msg!("Reward: {}", exit.reward);
 
-    let reward = Pool::get_fee_amount(custody.fees.liquidation, total_amount_out)?;
-    let user_amount = math::checked_sub(total_amount_out, reward)?;
-
-    msg!("Amount out: {}", user_amount);
-    msg!("Reward: {}", reward);
-
-    // unlock pool funds
-    collateral_custody.unlock_funds(position.locked_amount)?;
-
-    // check pool constraints
-    msg!("Check pool constraints");
-    require!(
-        pool.check_available_amount(total_amount_out, collateral_custody)?,
-        PerpetualsError::CustodyAmountLimit
-    );
+    // unlock pool funds and update custody stats
+    msg!("Update custody stats");
+    pool.remove_position(position, &exit, custody, collateral_custody, curtime, true)?;
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -218,8 +208,9 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
             .to_account_info(),
         ctx.accounts.receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
-        user_amount,
+        exit.amount_out,
     )?;
 
     perpetuals.transfer_tokens(
@@ -228,95 +219,59 @@ pub fn liquidate(ctx: Context<Liquidate>, _params: &LiquidateParams) -> Result<(
             .to_account_info(),
         ctx.accounts.rewards_receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
-        reward,
+        exit.reward,
     )?;
 
-    // update custody stats
-    msg!("Update custody stats");
-    collateral_custody.collected_fees.liquidation_usd = collateral_custody
-        .collected_fees
-        .liquidation_usd
-        .wrapping_add(fee_amount_usd);
-
-    if total_amount_out > position.collateral_amount {
-        let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
-    } else {
-        let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
-        collateral_custody.assets.owned =
-            math::checked_add(collateral_custody.assets.owned, amount_gained)?;
-    }
-    collateral_custody.assets.collateral = math::checked_sub(
-        collateral_custody.assets.collateral,
-        position.collateral_amount,
-    )?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-
-    // Pay protocol_fee from custody if possible, otherwise no protocol_fee
-    if pool.check_available_amount(protocol_fee, collateral_custody)? {
-        collateral_custody.assets.protocol_fees =
-            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
-
-        collateral_custody.assets.owned =
-            math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
-    }
-
-    // if custody and collateral_custody accounts are the same, ensure that data is in sync
-    if position.side == Side::Long && !custody.is_virtual {
-        collateral_custody.volume_stats.liquidation_usd = math::checked_add(
-            collateral_custody.volume_stats.liquidation_usd,
-            position.size_usd,
-        )?;
-
-        if position.side == Side::Long {
-            collateral_custody.trade_stats.oi_long_usd = collateral_custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            collateral_custody.trade_stats.oi_short_usd = collateral_custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        collateral_custody.trade_stats.profit_usd = collateral_custody
-            .trade_stats
-            .profit_usd
-            .wrapping_add(profit_usd);
-        collateral_custody.trade_stats.loss_usd = collateral_custody
-            .trade_stats
-            .loss_usd
-            .wrapping_add(loss_usd);
-
-        collateral_custody.remove_position(position, curtime, None)?;
-        collateral_custody.update_borrow_rate(curtime)?;
-        *custody = collateral_custody.clone();
-    } else {
-        custody.volume_stats.liquidation_usd =
-            math::checked_add(custody.volume_stats.liquidation_usd, position.size_usd)?;
-
-        if position.side == Side::Long {
-            custody.trade_stats.oi_long_usd = custody
-                .trade_stats
-                .oi_long_usd
-                .saturating_sub(position.size_usd);
-        } else {
-            custody.trade_stats.oi_short_usd = custody
-                .trade_stats
-                .oi_short_usd
-                .saturating_sub(position.size_usd);
-        }
-
-        custody.trade_stats.profit_usd = custody.trade_stats.profit_usd.wrapping_add(profit_usd);
-        custody.trade_stats.loss_usd = custody.trade_stats.loss_usd.wrapping_add(loss_usd);
-
-        custody.remove_position(position, curtime, Some(collateral_custody))?;
-        collateral_custody.update_borrow_rate(curtime)?;
-    }
+    emit!(PositionLiquidated {
+        owner: position.owner,
+        liquidator: ctx.accounts.signer.key(),
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        position: position.key(),
+        side: position.side,
+        entry_price: position.price,
+        exit_price: exit.price,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        collateral_amount: position.collateral_amount,
+        profit_usd: exit.profit_usd,
+        loss_usd: exit.loss_usd,
+        interest_usd: exit.interest_usd,
+        fee_amount: exit.fee_amount,
+        fee_amount_usd: exit.fee_amount_usd,
+        reward: exit.reward,
+        transfer_amount: exit.amount_out,
+        timestamp: curtime,
+    });
 
     Ok(())
 }
+
+#[event]
+pub struct PositionLiquidated {
+    pub owner: Pubkey,
+    pub liquidator: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub position: Pubkey,
+    pub side: Side,
+    pub entry_price: u64,
+    pub exit_price: u64,
+    pub size_usd: u64,
+    pub collateral_usd: u64,
+    pub collateral_amount: u64,
+    // net of fees and interest
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+    pub interest_usd: u64,
+    // fee, reward and transfer amounts are in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub reward: u64,
+    pub transfer_amount: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,139 @@
+//! MigrateCustody instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        instructions::BpfWriter,
+        state::{
+            custody::{Custody, LegacyCustody},
+            multisig::{AdminInstruction, Multisig},
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct MigrateCustody<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    /// CHECK: Legacy custody account, address is validated by the handler
+    pub custody: AccountInfo<'info>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct MigrateCustodyParams {}
+
+pub fn migrate_custody<'info>(
+    ctx: Context<'_, '_, '_, 'info, MigrateCustody<'info>>,
+    params: &MigrateCustodyParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::MigrateCustody, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // load legacy custody data
+    msg!("Load legacy custody");
+    let custody_account = &ctx.accounts.custody;
+    if custody_account.owner != &crate::ID {
+        return Err(ProgramError::IllegalOwner.into());
+    }
+    if custody_account.try_data_len()? != LegacyCustody::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let legacy_custody = Account::<LegacyCustody>::try_from_unchecked(custody_account)?;
+    let custody_address = Pubkey::create_program_address(
+        &[
+            b"custody",
+            ctx.accounts.pool.key().as_ref(),
+            legacy_custody.mint.as_ref(),
+            &[legacy_custody.bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)?;
+    if legacy_custody.pool != ctx.accounts.pool.key() || custody_address != custody_account.key() {
+        return Err(ProgramError::InvalidSeeds.into());
+    }
+
+    // update custody data
+    let custody_data = Custody {
+        pool: legacy_custody.pool,
+        mint: legacy_custody.mint,
+        token_account: legacy_custody.token_account,
+        decimals: legacy_custody.decimals,
+        is_stable: legacy_custody.is_stable,
+        is_virtual: legacy_custody.is_virtual,
+        oracle: legacy_custody.oracle,
+        pricing: legacy_custody.pricing.into(),
+        permissions: legacy_custody.permissions,
+        fees: legacy_custody.fees,
+        borrow_rate: legacy_custody.borrow_rate,
+        assets: legacy_custody.assets,
+        collected_fees: legacy_custody.collected_fees,
+        volume_stats: legacy_custody.volume_stats,
+        trade_stats: legacy_custody.trade_stats,
+        long_positions: legacy_custody.long_positions,
+        short_positions: legacy_custody.short_positions,
+        borrow_rate_state: legacy_custody.borrow_rate_state,
+        bump: legacy_custody.bump,
+        token_account_bump: legacy_custody.token_account_bump,
+        // token-2022 custodies were not supported before the migration
+        token_program: anchor_spl::token::ID,
+    };
+
+    if !custody_data.validate() {
+        return err!(PerpetualsError::InvalidCustodyConfig);
+    }
+
+    msg!("Resize custody account");
+    Perpetuals::realloc(
+        ctx.accounts.admin.to_account_info(),
+        ctx.accounts.custody.clone(),
+        ctx.accounts.system_program.to_account_info(),
+        Custody::LEN,
+        true,
+    )?;
+
+    msg!("Re-initialize the custody");
+    if custody_account.try_data_len()? != Custody::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let mut data = custody_account.try_borrow_mut_data()?;
+    let dst: &mut [u8] = &mut data;
+    let mut writer = BpfWriter::new(dst);
+    custody_data.try_serialize(&mut writer)?;
+
+    Ok(0)
+}
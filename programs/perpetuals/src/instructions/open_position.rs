@@ -10,10 +10,13 @@ use {
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            position_counter::PositionCounter,
+            trading_delegate::TradingDelegate,
+            trading_stats::TradingStats,
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -21,14 +24,18 @@ use {
 #[instruction(params: OpenPositionParams)]
 pub struct OpenPosition<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, must be the authority unless a trading delegate is used
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = funding_account.mint == collateral_custody.mint,
         has_one = owner
     )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
+    pub funding_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -51,19 +58,41 @@ pub struct OpenPosition<'info> {
     )]
     pub pool: Box<Account<'info, Pool>>,
 
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = PositionCounter::LEN,
+        seeds = [b"position_counter",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub position_counter: Box<Account<'info, PositionCounter>>,
+
     #[account(
         init,
-        payer = owner,
+        payer = authority,
         space = Position::LEN,
         seeds = [b"position",
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[params.side as u8]],
+                 &[params.side as u8],
+                 &position_counter.next_index.to_le_bytes()],
         bump
     )]
     pub position: Box<Account<'info, Position>>,
 
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = TradingStats::LEN,
+        seeds = [b"trading_stats",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub trading_stats: Box<Account<'info, TradingStats>>,
+
     #[account(
         mut,
         seeds = [b"custody",
@@ -101,10 +130,23 @@ pub struct OpenPosition<'info> {
                  collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
+
+    #[account(
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_delegate.bump
+    )]
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -128,6 +170,24 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         PerpetualsError::InstructionNotAllowed
     );
 
+    // check trading authority
+    let curtime = perpetuals.get_time()?;
+    let trading_delegate = TradingDelegate::get_delegate(
+        &ctx.accounts.owner.key(),
+        &ctx.accounts.authority.key(),
+        ctx.accounts
+            .trading_delegate
+            .as_deref()
+            .map(|delegate| &**delegate),
+        curtime,
+    )?;
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.permissions.allow_open_position,
+            PerpetualsError::InstructionNotAllowed
+        );
+    }
+
     // validate inputs
     msg!("Validate inputs");
     if params.price == 0 || params.collateral == 0 || params.size == 0 || params.side == Side::None
@@ -148,8 +208,6 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let pool = ctx.accounts.pool.as_mut();
 
     // compute position price
-    let curtime = perpetuals.get_time()?;
-
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
@@ -185,75 +243,41 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     let min_collateral_price = collateral_token_price
         .get_min_price(&collateral_token_ema_price, collateral_custody.is_stable)?;
 
-    let position_price =
-        pool.get_entry_price(&token_price, &token_ema_price, params.side, custody)?;
-    msg!("Entry price: {}", position_price);
-
-    if params.side == Side::Long {
-        require_gte!(
-            params.price,
-            position_price,
-            PerpetualsError::MaxPriceSlippage
-        );
-    } else {
-        require_gte!(
-            position_price,
-            params.price,
-            PerpetualsError::MaxPriceSlippage
-        );
-    }
-
-    // compute position parameters
-    let position_oracle_price = OraclePrice {
-        price: position_price,
-        exponent: -(Perpetuals::PRICE_DECIMALS as i32),
-    };
-    let size_usd = position_oracle_price.get_asset_amount_usd(params.size, custody.decimals)?;
-    let collateral_usd = min_collateral_price
-        .get_asset_amount_usd(params.collateral, collateral_custody.decimals)?;
-
-    let locked_amount = if use_collateral_custody {
-        custody.get_locked_amount(
-            min_collateral_price.get_token_amount(size_usd, collateral_custody.decimals)?,
-            params.side,
-        )?
-    } else {
-        custody.get_locked_amount(params.size, params.side)?
-    };
-
-    let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
-        if use_collateral_custody {
-            let max_collateral_price = if collateral_token_price < collateral_token_ema_price {
-                collateral_token_ema_price
-            } else {
-                collateral_token_price
-            };
-            max_collateral_price.get_asset_amount_usd(locked_amount, collateral_custody.decimals)?
-        } else {
-            position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
-        }
-    } else {
-        size_usd
-    };
-
-    // compute fee
-    let mut fee_amount = pool.get_entry_fee(
-        custody.fees.open_position,
+    let trading_stats = ctx.accounts.trading_stats.as_mut();
+    let fee_discount = pool.get_fee_discount(trading_stats.get_rolling_volume_usd(curtime)?);
+    let entry = pool.get_entry_amounts(
+        params.side,
         params.size,
-        locked_amount,
+        &token_price,
+        &token_ema_price,
+        custody,
+        &collateral_token_price,
+        &collateral_token_ema_price,
         collateral_custody,
+        fee_discount,
     )?;
-    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
-    if use_collateral_custody {
-        fee_amount = collateral_token_ema_price
-            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+    msg!("Entry price: {}", entry.price);
+
+    if params.side == Side::Long {
+        require_gte!(params.price, entry.price, PerpetualsError::MaxPriceSlippage);
+    } else {
+        require_gte!(entry.price, params.price, PerpetualsError::MaxPriceSlippage);
     }
-    msg!("Collected fee: {}", fee_amount);
+    msg!("Collected fee: {}", entry.fee_amount);
 
     // compute amount to transfer
-    let transfer_amount = math::checked_add(params.collateral, fee_amount)?;
+    let transfer_amount = math::checked_add(params.collateral, entry.fee_amount)?;
     msg!("Amount in: {}", transfer_amount);
 
+    // tokens withheld by the mint's transfer fee are taken from the collateral
+    let transfer_fee = Perpetuals::get_transfer_fee(
+        &ctx.accounts.collateral_custody_token_mint.to_account_info(),
+        transfer_amount,
+    )?;
+    let collateral = math::checked_sub(params.collateral, transfer_fee)?;
+    let collateral_usd =
+        min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
+
     // init new position
     msg!("Initialize new position");
     position.owner = ctx.accounts.owner.key();
@@ -263,20 +287,30 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
     position.open_time = perpetuals.get_time()?;
     position.update_time = 0;
     position.side = params.side;
-    position.price = position_price;
-    position.size_usd = size_usd;
-    position.borrow_size_usd = borrow_size_usd;
+    position.price = entry.price;
+    position.size_usd = entry.size_usd;
+    position.borrow_size_usd = entry.borrow_size_usd;
     position.collateral_usd = collateral_usd;
     position.unrealized_profit_usd = 0;
     position.unrealized_loss_usd = 0;
     position.cumulative_interest_snapshot = collateral_custody.get_cumulative_interest(curtime)?;
-    position.locked_amount = locked_amount;
-    position.collateral_amount = params.collateral;
+    position.locked_amount = entry.locked_amount;
+    position.collateral_amount = collateral;
     position.bump = *ctx
         .bumps
         .get("position")
         .ok_or(ProgramError::InvalidSeeds)?;
 
+    let position_counter = ctx.accounts.position_counter.as_mut();
+    if position_counter.owner == Pubkey::default() {
+        position_counter.owner = ctx.accounts.owner.key();
+        position_counter.bump = *ctx
+            .bumps
+            .get("position_counter")
+            .ok_or(ProgramError::InvalidSeeds)?;
+    }
+    position.index = position_counter.take_index()?;
+
     // check position risk
     msg!("Check position risks");
     require!(
@@ -298,8 +332,12 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         PerpetualsError::MaxLeverage
     );
 
-    // lock funds for potential profit payoff
-    collateral_custody.lock_funds(position.locked_amount)?;
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.check_position(position)?,
+            PerpetualsError::DelegateLimitExceeded
+        );
+    }
 
     // transfer tokens
     msg!("Transfer tokens");
@@ -308,65 +346,73 @@ pub fn open_position(ctx: Context<OpenPosition>, params: &OpenPositionParams) ->
         ctx.accounts
             .collateral_custody_token_account
             .to_account_info(),
-        ctx.accounts.owner.to_account_info(),
+        ctx.accounts.authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         transfer_amount,
     )?;
 
     // update custody stats
     msg!("Update custody stats");
-    collateral_custody.collected_fees.open_position_usd = collateral_custody
-        .collected_fees
-        .open_position_usd
-        .wrapping_add(fee_amount_usd);
-
-    collateral_custody.assets.collateral =
-        math::checked_add(collateral_custody.assets.collateral, params.collateral)?;
-
-    let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
-    collateral_custody.assets.protocol_fees =
-        math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
-
-    // if custody and collateral_custody accounts are the same, ensure that data is in sync
-    if position.side == Side::Long && !custody.is_virtual {
-        collateral_custody.volume_stats.open_position_usd = collateral_custody
-            .volume_stats
-            .open_position_usd
-            .wrapping_add(size_usd);
-
-        if params.side == Side::Long {
-            collateral_custody.trade_stats.oi_long_usd =
-                math::checked_add(collateral_custody.trade_stats.oi_long_usd, size_usd)?;
-        } else {
-            collateral_custody.trade_stats.oi_short_usd =
-                math::checked_add(collateral_custody.trade_stats.oi_short_usd, size_usd)?;
-        }
-
-        collateral_custody.add_position(position, &token_ema_price, curtime, None)?;
-        collateral_custody.update_borrow_rate(curtime)?;
-        *custody = collateral_custody.clone();
-    } else {
-        custody.volume_stats.open_position_usd = custody
-            .volume_stats
-            .open_position_usd
-            .wrapping_add(size_usd);
-
-        if params.side == Side::Long {
-            custody.trade_stats.oi_long_usd =
-                math::checked_add(custody.trade_stats.oi_long_usd, size_usd)?;
-        } else {
-            custody.trade_stats.oi_short_usd =
-                math::checked_add(custody.trade_stats.oi_short_usd, size_usd)?;
-        }
-
-        custody.add_position(
-            position,
-            &token_ema_price,
-            curtime,
-            Some(collateral_custody),
-        )?;
-        collateral_custody.update_borrow_rate(curtime)?;
+    pool.add_position(
+        position,
+        &entry,
+        &token_ema_price,
+        custody,
+        collateral_custody,
+        curtime,
+    )?;
+
+    // update trader's volume for fee tiers
+    if trading_stats.owner == Pubkey::default() {
+        trading_stats.owner = ctx.accounts.owner.key();
+        trading_stats.pool = pool.key();
+        trading_stats.bump = *ctx
+            .bumps
+            .get("trading_stats")
+            .ok_or(ProgramError::InvalidSeeds)?;
     }
+    trading_stats.add_volume(entry.size_usd, curtime)?;
+
+    emit!(PositionOpened {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        position: position.key(),
+        index: position.index,
+        side: position.side,
+        price: position.price,
+        size_usd: position.size_usd,
+        collateral_usd: position.collateral_usd,
+        collateral_amount: position.collateral_amount,
+        locked_amount: position.locked_amount,
+        fee_amount: entry.fee_amount,
+        fee_amount_usd: entry.fee_amount_usd,
+        fee_discount,
+        timestamp: curtime,
+    });
 
     Ok(())
 }
+
+#[event]
+pub struct PositionOpened {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub position: Pubkey,
+    pub index: u64,
+    pub side: Side,
+    pub price: u64,
+    pub size_usd: u64,
+    pub collateral_usd: u64,
+    pub collateral_amount: u64,
+    pub locked_amount: u64,
+    // fee amount is in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub fee_discount: u64,
+    pub timestamp: i64,
+}
@@ -10,10 +10,11 @@ use {
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            trading_delegate::TradingDelegate,
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -21,14 +22,18 @@ use {
 #[instruction(params: RemoveCollateralParams)]
 pub struct RemoveCollateral<'info> {
     #[account(mut)]
-    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+
+    /// CHECK: position owner, must be the authority unless a trading delegate is used
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
 
     #[account(
         mut,
         constraint = receiving_account.mint == custody.mint,
         has_one = owner
     )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -58,7 +63,8 @@ pub struct RemoveCollateral<'info> {
                  owner.key().as_ref(),
                  pool.key().as_ref(),
                  custody.key().as_ref(),
-                 &[position.side as u8]],
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
         bump = position.bump
     )]
     pub position: Box<Account<'info, Position>>,
@@ -94,14 +100,27 @@ pub struct RemoveCollateral<'info> {
                  collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
+    )]
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    token_program: Interface<'info, TokenInterface>,
 
-    token_program: Program<'info, Token>,
+    #[account(
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump = trading_delegate.bump
+    )]
+    pub trading_delegate: Option<Box<Account<'info, TradingDelegate>>>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
 pub struct RemoveCollateralParams {
-    collateral_usd: u64,
+    pub collateral_usd: u64,
 }
 
 pub fn remove_collateral(
@@ -119,6 +138,21 @@ pub fn remove_collateral(
         PerpetualsError::InstructionNotAllowed
     );
 
+    // check trading authority
+    let curtime = perpetuals.get_time()?;
+    let trading_delegate = TradingDelegate::get_delegate(
+        &ctx.accounts.owner.key(),
+        &ctx.accounts.authority.key(),
+        ctx.accounts.trading_delegate.as_deref().map(|delegate| &**delegate),
+        curtime,
+    )?;
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.permissions.allow_remove_collateral,
+            PerpetualsError::InstructionNotAllowed
+        );
+    }
+
     // validate inputs
     msg!("Validate inputs");
     let position = ctx.accounts.position.as_mut();
@@ -128,8 +162,6 @@ pub fn remove_collateral(
     let pool = ctx.accounts.pool.as_mut();
 
     // compute position price
-    let curtime = perpetuals.get_time()?;
-
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
         &custody.oracle,
@@ -199,6 +231,13 @@ pub fn remove_collateral(
         PerpetualsError::MaxLeverage
     );
 
+    if let Some(trading_delegate) = trading_delegate {
+        require!(
+            trading_delegate.check_position(position)?,
+            PerpetualsError::DelegateLimitExceeded
+        );
+    }
+
     // transfer tokens
     msg!("Transfer tokens");
     perpetuals.transfer_tokens(
@@ -207,6 +246,7 @@ pub fn remove_collateral(
             .to_account_info(),
         ctx.accounts.receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.collateral_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         collateral,
     )?;
@@ -221,5 +261,33 @@ pub fn remove_collateral(
         *custody = collateral_custody.clone();
     }
 
+    emit!(CollateralRemoved {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        collateral_custody: position.collateral_custody,
+        position: position.key(),
+        amount: collateral,
+        amount_usd: params.collateral_usd,
+        collateral_usd: position.collateral_usd,
+        collateral_amount: position.collateral_amount,
+        timestamp: curtime,
+    });
+
     Ok(())
 }
+
+#[event]
+pub struct CollateralRemoved {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+    pub position: Pubkey,
+    pub amount: u64,
+    pub amount_usd: u64,
+    // position collateral after the update
+    pub collateral_usd: u64,
+    pub collateral_amount: u64,
+    pub timestamp: i64,
+}
@@ -7,11 +7,11 @@ use {
             custody::Custody,
             multisig::{AdminInstruction, Multisig},
             perpetuals::Perpetuals,
-            pool::{Pool, TokenRatios},
+            pool::{FeeTier, Pool, TokenRatios},
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -43,7 +43,8 @@ pub struct RemoveCustody<'info> {
     #[account(
         mut,
         realloc = Pool::LEN + (pool.custodies.len() - 1) * std::mem::size_of::<Pubkey>() +
-                              (pool.ratios.len() - 1) * std::mem::size_of::<TokenRatios>(),
+                              (pool.ratios.len() - 1) * std::mem::size_of::<TokenRatios>() +
+                              pool.fee_tiers.len() * std::mem::size_of::<FeeTier>(),
         realloc::payer = admin,
         realloc::zero = false,
         seeds = [b"pool",
@@ -69,10 +70,10 @@ pub struct RemoveCustody<'info> {
                  custody.mint.as_ref()],
         bump = custody.token_account_bump,
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     system_program: Program<'info, System>,
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -132,5 +133,18 @@ pub fn remove_custody<'info>(
         ]],
     )?;
 
+    emit!(CustodyRemoved {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        ratios: params.ratios.clone(),
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct CustodyRemoved {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub ratios: Vec<TokenRatios>,
+}
@@ -12,7 +12,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Mint, Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -27,14 +27,14 @@ pub struct RemoveLiquidity<'info> {
         constraint = receiving_account.mint == custody.mint,
         has_one = owner
     )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = lp_token_account.mint == lp_token_mint.key(),
         has_one = owner
     )]
-    pub lp_token_account: Box<Account<'info, TokenAccount>>,
+    pub lp_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -79,7 +79,12 @@ pub struct RemoveLiquidity<'info> {
                  custody.mint.as_ref()],
         bump = custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = custody_token_mint.key() == custody.mint
+    )]
+    pub custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
@@ -87,9 +92,10 @@ pub struct RemoveLiquidity<'info> {
                  pool.key().as_ref()],
         bump = pool.lp_token_bump
     )]
-    pub lp_token_mint: Box<Account<'info, Mint>>,
+    pub lp_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
+    lp_token_program: Interface<'info, TokenInterface>,
     // remaining accounts:
     //   pool.tokens.len() custody accounts (read-only, unsigned)
     //   pool.tokens.len() custody oracles (read-only, unsigned)
@@ -131,6 +137,7 @@ pub fn remove_liquidity(
     // Refresh pool.aum_usm to adapt to token price change
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
+    let aum_usd_before = pool.aum_usd;
 
     let token_price = OraclePrice::new_from_oracle(
         &ctx.accounts.custody_oracle_account.to_account_info(),
@@ -171,8 +178,13 @@ pub fn remove_liquidity(
     let transfer_amount = math::checked_sub(remove_amount, fee_amount)?;
     msg!("Amount out: {}", transfer_amount);
 
+    // slippage is checked against the amount left after the mint's transfer fee
+    let transfer_fee = Perpetuals::get_transfer_fee(
+        &ctx.accounts.custody_token_mint.to_account_info(),
+        transfer_amount,
+    )?;
     require!(
-        transfer_amount >= params.min_amount_out,
+        math::checked_sub(transfer_amount, transfer_fee)? >= params.min_amount_out,
         PerpetualsError::MaxPriceSlippage
     );
 
@@ -196,6 +208,7 @@ pub fn remove_liquidity(
         ctx.accounts.custody_token_account.to_account_info(),
         ctx.accounts.receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         transfer_amount,
     )?;
@@ -206,16 +219,17 @@ pub fn remove_liquidity(
         ctx.accounts.lp_token_mint.to_account_info(),
         ctx.accounts.lp_token_account.to_account_info(),
         ctx.accounts.owner.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.lp_token_program.to_account_info(),
         params.lp_amount_in,
     )?;
 
     // update custody stats
     msg!("Update custody stats");
+    let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
     custody.collected_fees.remove_liquidity_usd = custody
         .collected_fees
         .remove_liquidity_usd
-        .wrapping_add(token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?);
+        .wrapping_add(fee_amount_usd);
 
     custody.volume_stats.remove_liquidity_usd = custody
         .volume_stats
@@ -234,5 +248,37 @@ pub fn remove_liquidity(
     pool.aum_usd =
         pool.get_assets_under_management_usd(AumCalcMode::EMA, ctx.remaining_accounts, curtime)?;
 
+    emit!(LiquidityRemoved {
+        owner: ctx.accounts.owner.key(),
+        pool: pool.key(),
+        custody: custody.key(),
+        lp_amount_in: params.lp_amount_in,
+        remove_amount,
+        remove_amount_usd,
+        fee_amount,
+        fee_amount_usd,
+        transfer_amount,
+        aum_usd_before,
+        aum_usd_after: pool.aum_usd,
+        timestamp: curtime,
+    });
+
     Ok(())
 }
+
+#[event]
+pub struct LiquidityRemoved {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub lp_amount_in: u64,
+    // token amount redeemed before fees
+    pub remove_amount: u64,
+    pub remove_amount_usd: u64,
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub transfer_amount: u64,
+    pub aum_usd_before: u128,
+    pub aum_usd_after: u128,
+    pub timestamp: i64,
+}
@@ -91,5 +91,14 @@ pub fn remove_pool<'info>(
         .ok_or(PerpetualsError::InvalidPoolState)?;
     perpetuals.pools.remove(pool_idx);
 
+    emit!(PoolRemoved {
+        pool: ctx.accounts.pool.key(),
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct PoolRemoved {
+    pub pool: Pubkey,
+}
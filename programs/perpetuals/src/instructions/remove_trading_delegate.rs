@@ -0,0 +1,50 @@
+//! RemoveTradingDelegate instruction handler
+
+use {
+    crate::state::{perpetuals::Perpetuals, trading_delegate::TradingDelegate},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct RemoveTradingDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 trading_delegate.pool.as_ref()],
+        bump = trading_delegate.bump,
+        close = owner
+    )]
+    pub trading_delegate: Box<Account<'info, TradingDelegate>>,
+}
+
+pub fn remove_trading_delegate(ctx: Context<RemoveTradingDelegate>) -> Result<()> {
+    let trading_delegate = ctx.accounts.trading_delegate.as_ref();
+
+    emit!(TradingDelegateRemoved {
+        owner: trading_delegate.owner,
+        pool: trading_delegate.pool,
+        delegate: trading_delegate.delegate,
+        timestamp: ctx.accounts.perpetuals.get_time()?,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct TradingDelegateRemoved {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub delegate: Pubkey,
+    pub timestamp: i64,
+}
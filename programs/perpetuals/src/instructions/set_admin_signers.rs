@@ -47,5 +47,16 @@ pub fn set_admin_signers<'info>(
     // set new admin signers
     multisig.set_signers(ctx.remaining_accounts, params.min_signatures)?;
 
+    emit!(AdminSignersUpdated {
+        signers: multisig.signers[..multisig.num_signers as usize].to_vec(),
+        min_signatures: params.min_signatures,
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct AdminSignersUpdated {
+    pub signers: Vec<Pubkey>,
+    pub min_signatures: u8,
+}
@@ -21,8 +21,10 @@ pub struct SetCustodyConfig<'info> {
 
     #[account(
         mut,
-        seeds = [b"multisig"],
-        bump = multisig.load()?.bump
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::SetCustodyConfig)
+            @ PerpetualsError::MultisigAccountNotAuthorized
     )]
     pub multisig: AccountLoader<'info, Multisig>,
 
@@ -99,8 +101,35 @@ pub fn set_custody_config<'info>(
     custody.borrow_rate = params.borrow_rate;
 
     if !custody.validate() {
-        err!(PerpetualsError::InvalidCustodyConfig)
-    } else {
-        Ok(0)
+        return err!(PerpetualsError::InvalidCustodyConfig);
     }
+
+    emit!(CustodyConfigUpdated {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        is_stable: params.is_stable,
+        is_virtual: params.is_virtual,
+        oracle: params.oracle,
+        pricing: params.pricing,
+        permissions: params.permissions,
+        fees: params.fees,
+        borrow_rate: params.borrow_rate,
+        ratios: params.ratios.clone(),
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct CustodyConfigUpdated {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: OracleParams,
+    pub pricing: PricingParams,
+    pub permissions: Permissions,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+    pub ratios: Vec<TokenRatios>,
 }
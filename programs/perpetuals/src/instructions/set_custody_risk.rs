@@ -0,0 +1,103 @@
+//! SetCustodyRisk instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::{BorrowRateParams, Custody, Fees, PricingParams},
+            multisig::{AdminInstruction, Multisig},
+            pool::Pool,
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetCustodyRisk<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::SetCustodyRisk)
+            @ PerpetualsError::MultisigAccountNotAuthorized
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump
+    )]
+    pub custody: Box<Account<'info, Custody>>,
+}
+
+// subset of the custody config the risk manager is allowed to update,
+// oracle, permissions and token ratios stay with the admin
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SetCustodyRiskParams {
+    pub pricing: PricingParams,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+}
+
+pub fn set_custody_risk<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetCustodyRisk<'info>>,
+    params: &SetCustodyRiskParams,
+) -> Result<u8> {
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetCustodyRisk, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update custody data
+    let custody = ctx.accounts.custody.as_mut();
+    custody.pricing = params.pricing;
+    custody.fees = params.fees;
+    custody.borrow_rate = params.borrow_rate;
+
+    if !custody.validate() {
+        return err!(PerpetualsError::InvalidCustodyConfig);
+    }
+
+    emit!(CustodyRiskUpdated {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        pricing: params.pricing,
+        fees: params.fees,
+        borrow_rate: params.borrow_rate,
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct CustodyRiskUpdated {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub pricing: PricingParams,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+}
@@ -1,12 +1,15 @@
 //! SetCustomOraclePrice instruction handler
 
 use {
-    crate::state::{
-        custody::Custody,
-        multisig::{AdminInstruction, Multisig},
-        oracle::CustomOracle,
-        perpetuals::Perpetuals,
-        pool::Pool,
+    crate::{
+        error::PerpetualsError,
+        state::{
+            custody::Custody,
+            multisig::{AdminInstruction, Multisig},
+            oracle::CustomOracle,
+            perpetuals::Perpetuals,
+            pool::Pool,
+        },
     },
     anchor_lang::prelude::*,
 };
@@ -18,8 +21,10 @@ pub struct SetCustomOraclePrice<'info> {
 
     #[account(
         mut,
-        seeds = [b"multisig"],
-        bump = multisig.load()?.bump
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::SetCustomOraclePrice)
+            @ PerpetualsError::MultisigAccountNotAuthorized
     )]
     pub multisig: AccountLoader<'info, Multisig>,
 
@@ -96,5 +101,27 @@ pub fn set_custom_oracle_price<'info>(
         params.ema,
         params.publish_time,
     );
+
+    emit!(CustomOraclePriceUpdated {
+        custody: ctx.accounts.custody.key(),
+        oracle_account: ctx.accounts.oracle_account.key(),
+        price: params.price,
+        expo: params.expo,
+        conf: params.conf,
+        ema: params.ema,
+        publish_time: params.publish_time,
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct CustomOraclePriceUpdated {
+    pub custody: Pubkey,
+    pub oracle_account: Pubkey,
+    pub price: u64,
+    pub expo: i32,
+    pub conf: u64,
+    pub ema: u64,
+    pub publish_time: i64,
+}
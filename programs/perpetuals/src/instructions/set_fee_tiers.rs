@@ -0,0 +1,95 @@
+//! SetFeeTiers instruction handler
+
+use {
+    crate::{
+        error::PerpetualsError,
+        state::{
+            multisig::{AdminInstruction, Multisig},
+            pool::{FeeTier, Pool, TokenRatios},
+        },
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: SetFeeTiersParams)]
+pub struct SetFeeTiers<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::SetFeeTiers)
+            @ PerpetualsError::MultisigAccountNotAuthorized
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        mut,
+        realloc = Pool::LEN + pool.custodies.len() * std::mem::size_of::<Pubkey>() +
+                              pool.ratios.len() * std::mem::size_of::<TokenRatios>() +
+                              params.fee_tiers.len() * std::mem::size_of::<FeeTier>(),
+        realloc::payer = admin,
+        realloc::zero = false,
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetFeeTiersParams {
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+pub fn set_fee_tiers<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetFeeTiers<'info>>,
+    params: &SetFeeTiersParams,
+) -> Result<u8> {
+    // validate inputs
+    if params.fee_tiers.len() > Pool::MAX_FEE_TIERS {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetFeeTiers, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // update pool data
+    let pool = ctx.accounts.pool.as_mut();
+    pool.fee_tiers = params.fee_tiers.clone();
+
+    if !pool.validate() {
+        return err!(PerpetualsError::InvalidPoolConfig);
+    }
+
+    emit!(FeeTiersUpdated {
+        pool: ctx.accounts.pool.key(),
+        fee_tiers: params.fee_tiers.clone(),
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct FeeTiersUpdated {
+    pub pool: Pubkey,
+    pub fee_tiers: Vec<FeeTier>,
+}
@@ -4,8 +4,8 @@ use {
     crate::{
         error::PerpetualsError,
         state::{
-            multisig::{AdminInstruction, Multisig},
-            perpetuals::Perpetuals,
+            multisig::{AdminInstruction, AdminRole, Multisig},
+            perpetuals::{Permissions, Perpetuals},
         },
     },
     anchor_lang::prelude::*,
@@ -18,8 +18,10 @@ pub struct SetPermissions<'info> {
 
     #[account(
         mut,
-        seeds = [b"multisig"],
-        bump = multisig.load()?.bump
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::SetPermissions)
+            @ PerpetualsError::MultisigAccountNotAuthorized
     )]
     pub multisig: AccountLoader<'info, Multisig>,
 
@@ -65,18 +67,41 @@ pub fn set_permissions<'info>(
 
     // update permissions
     let perpetuals = ctx.accounts.perpetuals.as_mut();
-    perpetuals.permissions.allow_swap = params.allow_swap;
-    perpetuals.permissions.allow_add_liquidity = params.allow_add_liquidity;
-    perpetuals.permissions.allow_remove_liquidity = params.allow_remove_liquidity;
-    perpetuals.permissions.allow_open_position = params.allow_open_position;
-    perpetuals.permissions.allow_close_position = params.allow_close_position;
-    perpetuals.permissions.allow_pnl_withdrawal = params.allow_pnl_withdrawal;
-    perpetuals.permissions.allow_collateral_withdrawal = params.allow_collateral_withdrawal;
-    perpetuals.permissions.allow_size_change = params.allow_size_change;
+    let permissions = Permissions {
+        allow_swap: params.allow_swap,
+        allow_add_liquidity: params.allow_add_liquidity,
+        allow_remove_liquidity: params.allow_remove_liquidity,
+        allow_open_position: params.allow_open_position,
+        allow_close_position: params.allow_close_position,
+        allow_pnl_withdrawal: params.allow_pnl_withdrawal,
+        allow_collateral_withdrawal: params.allow_collateral_withdrawal,
+        allow_size_change: params.allow_size_change,
+    };
+
+    // guardian can pause but not resume
+    if multisig.get_role()? == AdminRole::Guardian
+        && !permissions.is_restriction_of(&perpetuals.permissions)
+    {
+        return err!(PerpetualsError::MultisigAccountNotAuthorized);
+    }
+    perpetuals.permissions = permissions;
 
     if !perpetuals.validate() {
-        err!(PerpetualsError::InvalidPerpetualsConfig)
-    } else {
-        Ok(0)
+        return err!(PerpetualsError::InvalidPerpetualsConfig);
     }
+
+    emit!(PermissionsUpdated {
+        admin: ctx.accounts.admin.key(),
+        role: multisig.role,
+        permissions,
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct PermissionsUpdated {
+    pub admin: Pubkey,
+    pub role: u8,
+    pub permissions: Permissions,
 }
@@ -0,0 +1,99 @@
+//! SetRoleSigners instruction handler
+
+use {
+    crate::state::multisig::{AdminInstruction, AdminRole, Multisig},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: SetRoleSignersParams)]
+pub struct SetRoleSigners<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        space = Multisig::LEN,
+        seeds = [b"multisig", params.role.get_seed()],
+        bump
+    )]
+    pub role_multisig: AccountLoader<'info, Multisig>,
+
+    system_program: Program<'info, System>,
+    // remaining accounts: 1 to Multisig::MAX_SIGNERS role signers (read-only, unsigned)
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetRoleSignersParams {
+    pub role: AdminRole,
+    pub min_signatures: u8,
+}
+
+pub fn set_role_signers<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetRoleSigners<'info>>,
+    params: &SetRoleSignersParams,
+) -> Result<u8> {
+    // validate inputs, admin signers are managed with set_admin_signers
+    if params.role == AdminRole::Admin {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetRoleSigners, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    // set new role signers
+    let mut role_multisig = if let Ok(role_multisig) = ctx.accounts.role_multisig.load_init() {
+        role_multisig
+    } else {
+        ctx.accounts.role_multisig.load_mut()?
+    };
+
+    role_multisig.set_signers(ctx.remaining_accounts, params.min_signatures)?;
+    role_multisig.role = params.role as u8;
+    // role signers go through admin proposals while the timelock is enabled
+    role_multisig.timelock_delay = if AdminRole::TIMELOCKED_ROLES.contains(&params.role) {
+        multisig.timelock_delay
+    } else {
+        0
+    };
+    role_multisig.bump = *ctx
+        .bumps
+        .get("role_multisig")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    emit!(RoleSignersUpdated {
+        role: params.role as u8,
+        signers: role_multisig.signers[..role_multisig.num_signers as usize].to_vec(),
+        min_signatures: params.min_signatures,
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct RoleSignersUpdated {
+    pub role: u8,
+    pub signers: Vec<Pubkey>,
+    pub min_signatures: u8,
+}
@@ -0,0 +1,89 @@
+//! SetTimelockDelay instruction handler
+
+use {
+    crate::state::multisig::{AdminInstruction, AdminRole, Multisig},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetTimelockDelay<'info> {
+    #[account()]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump = multisig.load()?.bump
+    )]
+    pub multisig: AccountLoader<'info, Multisig>,
+    // remaining accounts: signer sets of AdminRole::TIMELOCKED_ROLES (write, unsigned),
+    // in the same order, whether they have been created or not
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct SetTimelockDelayParams {
+    // zero disables the timelock, admin instructions are executed
+    // as soon as enough signatures are collected
+    pub timelock_delay: i64,
+}
+
+pub fn set_timelock_delay<'info>(
+    ctx: Context<'_, '_, '_, 'info, SetTimelockDelay<'info>>,
+    params: &SetTimelockDelayParams,
+) -> Result<u8> {
+    // validate inputs
+    if params.timelock_delay < 0 || params.timelock_delay > Multisig::MAX_TIMELOCK_DELAY {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    if ctx.remaining_accounts.len() != AdminRole::TIMELOCKED_ROLES.len() {
+        return Err(ProgramError::NotEnoughAccountKeys.into());
+    }
+    for (role, role_multisig) in AdminRole::TIMELOCKED_ROLES
+        .iter()
+        .zip(ctx.remaining_accounts)
+    {
+        if role_multisig.key != &Multisig::get_role_multisig_address(*role) {
+            return Err(ProgramError::InvalidSeeds.into());
+        }
+    }
+
+    // validate signatures
+    let mut multisig = ctx.accounts.multisig.load_mut()?;
+
+    let signatures_left = multisig.sign_multisig(
+        &ctx.accounts.admin,
+        &Multisig::get_account_infos(&ctx)[1..],
+        &Multisig::get_instruction_data(AdminInstruction::SetTimelockDelay, params)?,
+    )?;
+    if signatures_left > 0 {
+        msg!(
+            "Instruction has been signed but more signatures are required: {}",
+            signatures_left
+        );
+        return Ok(signatures_left);
+    }
+
+    multisig.timelock_delay = params.timelock_delay;
+
+    // keep role signer sets in sync, they are created by set_role_signers with
+    // the current delay
+    for role_multisig in ctx.remaining_accounts {
+        if role_multisig.owner != &crate::ID {
+            continue;
+        }
+        AccountLoader::<Multisig>::try_from(role_multisig)?
+            .load_mut()?
+            .timelock_delay = params.timelock_delay;
+    }
+
+    emit!(TimelockDelayUpdated {
+        timelock_delay: params.timelock_delay,
+    });
+
+    Ok(0)
+}
+
+#[event]
+pub struct TimelockDelayUpdated {
+    pub timelock_delay: i64,
+}
@@ -0,0 +1,100 @@
+//! SetTradingDelegate instruction handler
+
+use {
+    crate::state::{
+        perpetuals::Perpetuals,
+        pool::Pool,
+        trading_delegate::{DelegatePermissions, TradingDelegate},
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct SetTradingDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = TradingDelegate::LEN,
+        seeds = [b"trading_delegate",
+                 owner.key().as_ref(),
+                 pool.key().as_ref()],
+        bump
+    )]
+    pub trading_delegate: Box<Account<'info, TradingDelegate>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SetTradingDelegateParams {
+    pub delegate: Pubkey,
+    pub permissions: DelegatePermissions,
+    pub max_size_usd: u64,
+    pub max_leverage: u64,
+    pub expiration_time: i64,
+}
+
+pub fn set_trading_delegate(
+    ctx: Context<SetTradingDelegate>,
+    params: &SetTradingDelegateParams,
+) -> Result<()> {
+    // validate inputs
+    if params.delegate == Pubkey::default() || params.delegate == ctx.accounts.owner.key() {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+
+    // record delegate
+    let trading_delegate = ctx.accounts.trading_delegate.as_mut();
+    trading_delegate.owner = ctx.accounts.owner.key();
+    trading_delegate.pool = ctx.accounts.pool.key();
+    trading_delegate.delegate = params.delegate;
+    trading_delegate.permissions = params.permissions;
+    trading_delegate.max_size_usd = params.max_size_usd;
+    trading_delegate.max_leverage = params.max_leverage;
+    trading_delegate.expiration_time = params.expiration_time;
+    trading_delegate.bump = *ctx
+        .bumps
+        .get("trading_delegate")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    emit!(TradingDelegateSet {
+        owner: trading_delegate.owner,
+        pool: trading_delegate.pool,
+        delegate: trading_delegate.delegate,
+        permissions: trading_delegate.permissions,
+        max_size_usd: trading_delegate.max_size_usd,
+        max_leverage: trading_delegate.max_leverage,
+        expiration_time: trading_delegate.expiration_time,
+        timestamp: ctx.accounts.perpetuals.get_time()?,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct TradingDelegateSet {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: DelegatePermissions,
+    pub max_size_usd: u64,
+    pub max_leverage: u64,
+    pub expiration_time: i64,
+    pub timestamp: i64,
+}
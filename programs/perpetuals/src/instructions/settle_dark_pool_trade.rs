@@ -1,52 +1,65 @@
-//! Settlement Bridge for Darkpool Integration
-//! 
-//! This module handles the settlement of darkpool trades with the main perpetuals program.
-//! It listens for darkpool trade events and executes the corresponding position changes
-//! in the perpetuals system.
+//! SettleDarkPoolTrade instruction handler
+//!
+//! Opens the long and short positions of a trade matched by the darkpool program.
+//! The instruction can only be reached through a CPI from the darkpool program:
+//! the darkpool PDA must sign, and a PDA can only be signed for by the program
+//! that derived it.
 
 use {
     crate::{
         error::PerpetualsError,
-        instructions::*,
+        instructions::open_position::PositionOpened,
+        math,
         state::{
             custody::Custody,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             pool::Pool,
             position::{Position, Side},
+            position_counter::PositionCounter,
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
+    solana_program::{program_error::ProgramError, pubkey},
 };
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct DarkPoolTradeData {
-    pub trader_a: Pubkey,
-    pub trader_b: Pubkey,
-    pub side_a: Side, // Side for trader A
-    pub side_b: Side, // Side for trader B (opposite of A)
-    pub size_usd: u64,
-    pub price: u64,
-    pub pool: Pubkey,
-    pub custody: Pubkey,
-    pub collateral_custody: Pubkey,
-    pub timestamp: i64,
-    pub darkpool_signature: [u8; 64], // Signature from darkpool program
-}
+/// Darkpool program allowed to settle trades
+pub const DARKPOOL_PROGRAM_ID: Pubkey = pubkey!("BbtSLsMv22PMhdoSiUqm9Ee9VzVL8zsaDLFkGQKrdKL");
 
 #[derive(Accounts)]
 #[instruction(params: SettleDarkPoolTradeParams)]
 pub struct SettleDarkPoolTrade<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub payer: Signer<'info>,
+
+    // darkpool state PDA, signed by the darkpool program in the CPI
+    #[account(
+        seeds = [b"darkpool"],
+        bump,
+        seeds::program = DARKPOOL_PROGRAM_ID
+    )]
+    pub darkpool_authority: Signer<'info>,
+
+    /// CHECK: owner of the long position, matched by the darkpool program
+    pub long_owner: AccountInfo<'info>,
+
+    /// CHECK: owner of the short position, matched by the darkpool program
+    pub short_owner: AccountInfo<'info>,
+
+    // collateral of the long side, the darkpool authority must be allowed to transfer it
+    #[account(
+        mut,
+        constraint = funding_account_long.mint == collateral_custody.mint
+    )]
+    pub funding_account_long: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// CHECK: empty PDA, authority for token accounts
+    // collateral of the short side, the darkpool authority must be allowed to transfer it
     #[account(
-        seeds = [b"transfer_authority"],
-        bump = perpetuals.transfer_authority_bump
+        mut,
+        constraint = funding_account_short.mint == collateral_custody.mint
     )]
-    pub transfer_authority: AccountInfo<'info>,
+    pub funding_account_short: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         seeds = [b"perpetuals"],
@@ -56,371 +69,493 @@ pub struct SettleDarkPoolTrade<'info> {
 
     #[account(
         mut,
-        seeds = [b"pool", pool.name.as_bytes()],
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
         bump = pool.bump
     )]
     pub pool: Box<Account<'info, Pool>>,
 
     #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), custody.mint.as_ref()],
-        bump = custody.bump
+        init_if_needed,
+        payer = payer,
+        space = PositionCounter::LEN,
+        seeds = [b"position_counter",
+                 long_owner.key().as_ref()],
+        bump
     )]
-    pub custody: Box<Account<'info, Custody>>,
+    pub position_counter_long: Box<Account<'info, PositionCounter>>,
 
     #[account(
-        mut,
-        seeds = [b"custody", pool.key().as_ref(), collateral_custody.mint.as_ref()],
-        bump = collateral_custody.bump
+        init,
+        payer = payer,
+        space = Position::LEN,
+        seeds = [b"position",
+                 long_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[Side::Long as u8],
+                 &position_counter_long.next_index.to_le_bytes()],
+        bump
     )]
-    pub collateral_custody: Box<Account<'info, Custody>>,
+    pub position_long: Box<Account<'info, Position>>,
 
-    /// CHECK: oracle account for the position token
     #[account(
-        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
+        init_if_needed,
+        payer = payer,
+        space = PositionCounter::LEN,
+        seeds = [b"position_counter",
+                 short_owner.key().as_ref()],
+        bump
     )]
-    pub custody_oracle_account: AccountInfo<'info>,
+    pub position_counter_short: Box<Account<'info, PositionCounter>>,
 
-    /// CHECK: oracle account for the collateral token
     #[account(
-        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
+        init,
+        payer = payer,
+        space = Position::LEN,
+        seeds = [b"position",
+                 short_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 custody.key().as_ref(),
+                 &[Side::Short as u8],
+                 &position_counter_short.next_index.to_le_bytes()],
+        bump
     )]
-    pub collateral_custody_oracle_account: AccountInfo<'info>,
+    pub position_short: Box<Account<'info, Position>>,
 
-    // Position accounts for both traders
     #[account(
         mut,
-        seeds = [
-            b"position",
-            params.trade_data.trader_a.as_ref(),
-            pool.key().as_ref(),
-            custody.key().as_ref(),
-            &[params.trade_data.side_a as u8]
-        ],
-        bump
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 custody.mint.as_ref()],
+        bump = custody.bump
     )]
-    pub position_a: Box<Account<'info, Position>>,
+    pub custody: Box<Account<'info, Custody>>,
 
+    /// CHECK: oracle account for the position token
     #[account(
-        mut,
-        seeds = [
-            b"position",
-            params.trade_data.trader_b.as_ref(),
-            pool.key().as_ref(),
-            custody.key().as_ref(),
-            &[params.trade_data.side_b as u8]
-        ],
-        bump
+        constraint = custody_oracle_account.key() == custody.oracle.oracle_account
     )]
-    pub position_b: Box<Account<'info, Position>>,
+    pub custody_oracle_account: AccountInfo<'info>,
 
-    // Funding accounts for both traders
     #[account(
         mut,
-        constraint = funding_account_a.mint == collateral_custody.mint,
-        constraint = funding_account_a.owner == params.trade_data.trader_a
+        seeds = [b"custody",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
+        bump = collateral_custody.bump
     )]
-    pub funding_account_a: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody: Box<Account<'info, Custody>>,
 
+    /// CHECK: oracle account for the collateral token
     #[account(
-        mut,
-        constraint = funding_account_b.mint == collateral_custody.mint,
-        constraint = funding_account_b.owner == params.trade_data.trader_b
+        constraint = collateral_custody_oracle_account.key() == collateral_custody.oracle.oracle_account
     )]
-    pub funding_account_b: Box<Account<'info, TokenAccount>>,
+    pub collateral_custody_oracle_account: AccountInfo<'info>,
 
     #[account(
         mut,
-        seeds = [b"custody_token_account", pool.key().as_ref(), collateral_custody.mint.as_ref()],
+        seeds = [b"custody_token_account",
+                 pool.key().as_ref(),
+                 collateral_custody.mint.as_ref()],
         bump = collateral_custody.token_account_bump
     )]
-    pub collateral_custody_token_account: Box<Account<'info, TokenAccount>>,
-
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    pub collateral_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    /// CHECK: Darkpool program for verification
     #[account(
-        constraint = darkpool_program.key() == params.expected_darkpool_program
+        constraint = collateral_custody_token_mint.key() == collateral_custody.mint
     )]
-    pub darkpool_program: AccountInfo<'info>,
+    pub collateral_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    system_program: Program<'info, System>,
+    token_program: Interface<'info, TokenInterface>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
 pub struct SettleDarkPoolTradeParams {
-    pub trade_data: DarkPoolTradeData,
-    pub expected_darkpool_program: Pubkey,
-    pub collateral_amount_a: u64,
-    pub collateral_amount_b: u64,
-    pub max_price_slippage: u16, // in bps
+    pub trade_id: u64,
+    pub size_usd: u64,
+    pub price: u64,
+    // collateral tokens committed by each side, entry fees included
+    pub collateral_long: u64,
+    pub collateral_short: u64,
+    // max deviation of the matched price from the oracle price, in bps
+    pub max_price_slippage: u16,
+}
+
+// oracle prices shared by both sides of a trade
+struct DarkTradePrices {
+    token_price: OraclePrice,
+    token_ema_price: OraclePrice,
+    collateral_token_price: OraclePrice,
+    collateral_token_ema_price: OraclePrice,
 }
 
 pub fn settle_dark_pool_trade(
     ctx: Context<SettleDarkPoolTrade>,
     params: &SettleDarkPoolTradeParams,
 ) -> Result<()> {
-    msg!("Settling darkpool trade");
-
-    // Verify the trade data signature
-    verify_darkpool_signature(&params.trade_data)?;
-
-    // Verify trade parameters
+    // check permissions
+    msg!("Check permissions");
+    let perpetuals = ctx.accounts.perpetuals.as_ref();
+    let custody = ctx.accounts.custody.as_ref();
+    let collateral_custody = ctx.accounts.collateral_custody.as_ref();
     require!(
-        params.trade_data.size_usd > 0,
-        PerpetualsError::InvalidPositionSize
+        perpetuals.permissions.allow_open_position
+            && custody.permissions.allow_open_position
+            && !custody.is_stable,
+        PerpetualsError::InstructionNotAllowed
     );
+
+    // validate inputs
+    msg!("Validate inputs");
+    require!(params.size_usd > 0, PerpetualsError::InvalidPositionSize);
+    require!(params.price > 0, PerpetualsError::InvalidPrice);
+    if params.collateral_long == 0 || params.collateral_short == 0 {
+        return Err(ProgramError::InvalidArgument.into());
+    }
+    require_keys_neq!(
+        ctx.accounts.long_owner.key(),
+        ctx.accounts.short_owner.key()
+    );
+
+    // both sides post the collateral token escrowed by the order book, which
+    // open_position only allows for a virtual custody backed by a stable
+    // collateral custody. A long on a real custody is collateralized in the
+    // custody token: close_position, liquidate and add_collateral treat its
+    // fees, payoff and stats in that token and would misprice a long backed
+    // by the stable collateral custody.
     require!(
-        params.trade_data.price > 0,
-        PerpetualsError::InvalidPrice
+        custody.is_virtual,
+        PerpetualsError::InvalidCollateralCustody
     );
+    require_keys_neq!(custody.key(), collateral_custody.key());
     require!(
-        params.trade_data.side_a != params.trade_data.side_b,
-        PerpetualsError::InvalidTradeSides
+        collateral_custody.is_stable && !collateral_custody.is_virtual,
+        PerpetualsError::InvalidCollateralCustody
     );
 
-    // Get current oracle prices
-    let custody_oracle_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.custody.oracle,
-        &ctx.accounts.custody_oracle_account,
-        false,
-    )?;
+    // check the matched price against the oracle
+    let curtime = perpetuals.get_time()?;
+    let prices = DarkTradePrices {
+        token_price: OraclePrice::new_from_oracle(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            &custody.oracle,
+            curtime,
+            false,
+        )?,
+        token_ema_price: OraclePrice::new_from_oracle(
+            &ctx.accounts.custody_oracle_account.to_account_info(),
+            &custody.oracle,
+            curtime,
+            custody.pricing.use_ema,
+        )?,
+        collateral_token_price: OraclePrice::new_from_oracle(
+            &ctx.accounts
+                .collateral_custody_oracle_account
+                .to_account_info(),
+            &collateral_custody.oracle,
+            curtime,
+            false,
+        )?,
+        collateral_token_ema_price: OraclePrice::new_from_oracle(
+            &ctx.accounts
+                .collateral_custody_oracle_account
+                .to_account_info(),
+            &collateral_custody.oracle,
+            curtime,
+            collateral_custody.pricing.use_ema,
+        )?,
+    };
 
-    let collateral_oracle_price = OraclePrice::new_from_oracle(
-        &ctx.accounts.collateral_custody.oracle,
-        &ctx.accounts.collateral_custody_oracle_account,
-        false,
+    let oracle_price = prices
+        .token_price
+        .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
+        .price;
+    let slippage = math::checked_div(
+        math::checked_mul(
+            oracle_price.abs_diff(params.price) as u128,
+            Perpetuals::BPS_POWER,
+        )?,
+        oracle_price as u128,
     )?;
-
-    // Verify price is within acceptable slippage
-    let max_slippage = params.max_price_slippage as u64;
-    let price_diff = if custody_oracle_price.price > params.trade_data.price {
-        custody_oracle_price.price - params.trade_data.price
-    } else {
-        params.trade_data.price - custody_oracle_price.price
-    };
-    
-    let slippage_bps = (price_diff * 10000) / custody_oracle_price.price;
     require!(
-        slippage_bps <= max_slippage,
+        slippage <= params.max_price_slippage as u128,
         PerpetualsError::PriceSlippageTooHigh
     );
 
-    // Process position updates for both traders
-    settle_trader_position(
-        &params.trade_data,
-        &mut ctx.accounts.position_a,
-        &mut ctx.accounts.funding_account_a,
-        &mut ctx.accounts.collateral_custody_token_account,
-        params.collateral_amount_a,
-        params.trade_data.side_a,
-        &ctx.accounts.pool,
-        &ctx.accounts.custody,
-        &ctx.accounts.collateral_custody,
-        &custody_oracle_price,
-        &collateral_oracle_price,
-        &ctx.accounts.transfer_authority,
-        &ctx.accounts.token_program,
+    // open both positions
+    let position_long_bump = *ctx
+        .bumps
+        .get("position_long")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    let position_short_bump = *ctx
+        .bumps
+        .get("position_short")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    let position_counter_long_bump = *ctx
+        .bumps
+        .get("position_counter_long")
+        .ok_or(ProgramError::InvalidSeeds)?;
+    let position_counter_short_bump = *ctx
+        .bumps
+        .get("position_counter_short")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    ctx.accounts.open_dark_position(
+        Side::Long,
+        params,
+        &prices,
+        curtime,
+        position_long_bump,
+        position_counter_long_bump,
     )?;
-
-    settle_trader_position(
-        &params.trade_data,
-        &mut ctx.accounts.position_b,
-        &mut ctx.accounts.funding_account_b,
-        &mut ctx.accounts.collateral_custody_token_account,
-        params.collateral_amount_b,
-        params.trade_data.side_b,
-        &ctx.accounts.pool,
-        &ctx.accounts.custody,
-        &ctx.accounts.collateral_custody,
-        &custody_oracle_price,
-        &collateral_oracle_price,
-        &ctx.accounts.transfer_authority,
-        &ctx.accounts.token_program,
+    ctx.accounts.open_dark_position(
+        Side::Short,
+        params,
+        &prices,
+        curtime,
+        position_short_bump,
+        position_counter_short_bump,
     )?;
 
-    // Update pool and custody statistics
-    ctx.accounts.custody.trade_stats.volume_usd += params.trade_data.size_usd;
-
     emit!(DarkPoolTradeSettled {
-        trader_a: params.trade_data.trader_a,
-        trader_b: params.trade_data.trader_b,
-        size_usd: params.trade_data.size_usd,
-        price: params.trade_data.price,
-        pool: params.trade_data.pool,
-        custody: params.trade_data.custody,
-        timestamp: Clock::get()?.unix_timestamp,
+        trade_id: params.trade_id,
+        long_owner: ctx.accounts.long_owner.key(),
+        short_owner: ctx.accounts.short_owner.key(),
+        position_long: ctx.accounts.position_long.key(),
+        position_short: ctx.accounts.position_short.key(),
+        size_usd: params.size_usd,
+        price: params.price,
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        timestamp: curtime,
     });
 
-    msg!("Darkpool trade settled successfully");
     Ok(())
 }
 
-fn settle_trader_position(
-    trade_data: &DarkPoolTradeData,
-    position: &mut Account<Position>,
-    funding_account: &mut Account<TokenAccount>,
-    custody_token_account: &mut Account<TokenAccount>,
-    collateral_amount: u64,
-    side: Side,
-    pool: &Account<Pool>,
-    custody: &Account<Custody>,
-    collateral_custody: &Account<Custody>,
-    custody_oracle_price: &OraclePrice,
-    collateral_oracle_price: &OraclePrice,
-    transfer_authority: &AccountInfo,
-    token_program: &Program<Token>,
-) -> Result<()> {
-    let current_time = Clock::get()?.unix_timestamp;
+impl<'info> SettleDarkPoolTrade<'info> {
+    // Opens one side of the trade at the matched price. Mirrors open_position,
+    // except that the entry fee is paid out of the committed collateral and
+    // fee tiers are not applied.
+    fn open_dark_position(
+        &mut self,
+        side: Side,
+        params: &SettleDarkPoolTradeParams,
+        prices: &DarkTradePrices,
+        curtime: i64,
+        position_bump: u8,
+        position_counter_bump: u8,
+    ) -> Result<()> {
+        let (owner, funding_account, position_counter, position, collateral_amount) =
+            if side == Side::Long {
+                (
+                    self.long_owner.key(),
+                    &self.funding_account_long,
+                    self.position_counter_long.as_mut(),
+                    self.position_long.as_mut(),
+                    params.collateral_long,
+                )
+            } else {
+                (
+                    self.short_owner.key(),
+                    &self.funding_account_short,
+                    self.position_counter_short.as_mut(),
+                    self.position_short.as_mut(),
+                    params.collateral_short,
+                )
+            };
+        let perpetuals = self.perpetuals.as_ref();
+        let pool = self.pool.as_ref();
+        let custody = self.custody.as_mut();
+        let collateral_custody = self.collateral_custody.as_mut();
+
+        // compute position parameters
+        let position_oracle_price = OraclePrice {
+            price: params.price,
+            exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        };
+        let min_collateral_price = prices.collateral_token_price.get_min_price(
+            &prices.collateral_token_ema_price,
+            collateral_custody.is_stable,
+        )?;
+        let size = position_oracle_price.get_token_amount(params.size_usd, custody.decimals)?;
+        let locked_amount = custody.get_locked_amount(
+            min_collateral_price.get_token_amount(params.size_usd, collateral_custody.decimals)?,
+            side,
+        )?;
 
-    // Update position if it exists, or initialize if new
-    if position.size_usd == 0 {
-        // New position
-        position.owner = trade_data.trader_a;
-        position.pool = pool.key();
-        position.custody = custody.key();
-        position.collateral_custody = collateral_custody.key();
-        position.open_time = current_time;
-        position.side = side;
-        position.price = trade_data.price;
-        position.size_usd = trade_data.size_usd;
-        position.collateral_amount = collateral_amount;
-        position.collateral_usd = pool.get_usd_amount(
-            collateral_amount,
-            collateral_oracle_price,
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            let max_collateral_price =
+                if prices.collateral_token_price < prices.collateral_token_ema_price {
+                    prices.collateral_token_ema_price
+                } else {
+                    prices.collateral_token_price
+                };
+            max_collateral_price.get_asset_amount_usd(locked_amount, collateral_custody.decimals)?
+        } else {
+            params.size_usd
+        };
+
+        // compute fee
+        let fee_amount = pool.get_entry_fee(
+            custody.fees.open_position,
+            size,
+            locked_amount,
             collateral_custody,
+            0,
         )?;
-    } else {
-        // Update existing position
-        let new_total_size = position.size_usd + trade_data.size_usd;
-        let weighted_price = ((position.price as u128 * position.size_usd as u128) + 
-                             (trade_data.price as u128 * trade_data.size_usd as u128)) / 
-                             new_total_size as u128;
-        
-        position.size_usd = new_total_size;
-        position.price = weighted_price as u64;
-        position.collateral_amount += collateral_amount;
-        position.collateral_usd += pool.get_usd_amount(
+        let fee_amount_usd = prices
+            .token_ema_price
+            .get_asset_amount_usd(fee_amount, custody.decimals)?;
+        let fee_amount = prices
+            .collateral_token_ema_price
+            .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        msg!("Collected fee: {}", fee_amount);
+
+        // fee and tokens withheld by the mint's transfer fee are taken from the collateral
+        let transfer_fee = Perpetuals::get_transfer_fee(
+            &self.collateral_custody_token_mint.to_account_info(),
             collateral_amount,
-            collateral_oracle_price,
-            collateral_custody,
         )?;
-    }
-
-    position.update_time = current_time;
-
-    // Transfer collateral from trader to custody
-    if collateral_amount > 0 {
-        anchor_spl::token::transfer(
-            CpiContext::new_with_signer(
-                token_program.to_account_info(),
-                anchor_spl::token::Transfer {
-                    from: funding_account.to_account_info(),
-                    to: custody_token_account.to_account_info(),
-                    authority: transfer_authority.to_account_info(),
-                },
-                &[&[
-                    b"transfer_authority",
-                    &[ctx.bumps.transfer_authority],
-                ]],
-            ),
+        let collateral = math::checked_sub(
             collateral_amount,
+            math::checked_add(fee_amount, transfer_fee)?,
         )?;
-    }
-
-    Ok(())
-}
+        let collateral_usd =
+            min_collateral_price.get_asset_amount_usd(collateral, collateral_custody.decimals)?;
 
-fn verify_darkpool_signature(trade_data: &DarkPoolTradeData) -> Result<()> {
-    // Implement signature verification logic here
-    // This would typically involve:
-    // 1. Reconstructing the message from trade_data
-    // 2. Verifying the signature against the darkpool program's expected signer
-    // 3. Checking timestamp validity to prevent replay attacks
-    
-    // For now, we'll do basic validation
-    require!(
-        trade_data.darkpool_signature != [0u8; 64],
-        PerpetualsError::InvalidSignature
-    );
-    
-    // Check timestamp is recent (within 5 minutes)
-    let current_time = Clock::get()?.unix_timestamp;
-    require!(
-        current_time - trade_data.timestamp < 300,
-        PerpetualsError::TradeDataTooOld
-    );
-
-    Ok(())
-}
-
-// ===== Batch Settlement for Multiple Trades =====
-
-#[derive(Accounts)]
-#[instruction(params: BatchSettleDarkPoolTradesParams)]
-pub struct BatchSettleDarkPoolTrades<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
-
-    #[account(
-        seeds = [b"perpetuals"],
-        bump = perpetuals.perpetuals_bump
-    )]
-    pub perpetuals: Box<Account<'info, Perpetuals>>,
-
-    // Additional accounts would be determined dynamically based on trades
-    pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize)]
-pub struct BatchSettleDarkPoolTradesParams {
-    pub trades: Vec<DarkPoolTradeData>,
-    pub expected_darkpool_program: Pubkey,
-}
+        // init new position
+        msg!("Initialize new position");
+        position.owner = owner;
+        position.pool = pool.key();
+        position.custody = custody.key();
+        position.collateral_custody = collateral_custody.key();
+        position.open_time = curtime;
+        position.update_time = 0;
+        position.side = side;
+        position.price = params.price;
+        position.size_usd = params.size_usd;
+        position.borrow_size_usd = borrow_size_usd;
+        position.collateral_usd = collateral_usd;
+        position.unrealized_profit_usd = 0;
+        position.unrealized_loss_usd = 0;
+        position.cumulative_interest_snapshot =
+            collateral_custody.get_cumulative_interest(curtime)?;
+        position.locked_amount = locked_amount;
+        position.collateral_amount = collateral;
+        position.bump = position_bump;
+
+        if position_counter.owner == Pubkey::default() {
+            position_counter.owner = owner;
+            position_counter.bump = position_counter_bump;
+        }
+        position.index = position_counter.take_index()?;
+
+        // check position risk
+        msg!("Check position risks");
+        require!(
+            position.locked_amount > 0,
+            PerpetualsError::InsufficientAmountReturned
+        );
+        require!(
+            pool.check_leverage(
+                position,
+                &prices.token_price,
+                &prices.token_ema_price,
+                custody,
+                &prices.collateral_token_price,
+                &prices.collateral_token_ema_price,
+                collateral_custody,
+                curtime,
+                true
+            )?,
+            PerpetualsError::MaxLeverage
+        );
+
+        // lock funds for potential profit payoff
+        collateral_custody.lock_funds(position.locked_amount)?;
+
+        // transfer tokens, the darkpool signature is extended to this CPI
+        msg!("Transfer tokens");
+        perpetuals.transfer_tokens_from_user(
+            funding_account.to_account_info(),
+            self.collateral_custody_token_account.to_account_info(),
+            self.darkpool_authority.to_account_info(),
+            self.collateral_custody_token_mint.to_account_info(),
+            self.token_program.to_account_info(),
+            collateral_amount,
+        )?;
 
-pub fn batch_settle_dark_pool_trades(
-    ctx: Context<BatchSettleDarkPoolTrades>,
-    params: &BatchSettleDarkPoolTradesParams,
-) -> Result<()> {
-    msg!("Batch settling {} darkpool trades", params.trades.len());
-
-    // Process each trade
-    for trade in &params.trades {
-        // Verify signature for each trade
-        verify_darkpool_signature(trade)?;
-
-        // Emit event for each trade (actual settlement would require remaining accounts)
-        emit!(DarkPoolTradeQueued {
-            trader_a: trade.trader_a,
-            trader_b: trade.trader_b,
-            size_usd: trade.size_usd,
-            price: trade.price,
-            timestamp: trade.timestamp,
+        // update custody stats
+        msg!("Update custody stats");
+        collateral_custody.collected_fees.open_position_usd = collateral_custody
+            .collected_fees
+            .open_position_usd
+            .wrapping_add(fee_amount_usd);
+
+        collateral_custody.assets.collateral =
+            math::checked_add(collateral_custody.assets.collateral, collateral)?;
+
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, fee_amount)?;
+        collateral_custody.assets.protocol_fees =
+            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+        custody.volume_stats.open_position_usd = custody
+            .volume_stats
+            .open_position_usd
+            .wrapping_add(params.size_usd);
+
+        if side == Side::Long {
+            custody.trade_stats.oi_long_usd =
+                math::checked_add(custody.trade_stats.oi_long_usd, params.size_usd)?;
+        } else {
+            custody.trade_stats.oi_short_usd =
+                math::checked_add(custody.trade_stats.oi_short_usd, params.size_usd)?;
+        }
+
+        custody.add_position(
+            position,
+            &prices.token_ema_price,
+            curtime,
+            Some(collateral_custody),
+        )?;
+        collateral_custody.update_borrow_rate(curtime)?;
+
+        emit!(PositionOpened {
+            owner: position.owner,
+            pool: position.pool,
+            custody: position.custody,
+            collateral_custody: position.collateral_custody,
+            position: position.key(),
+            index: position.index,
+            side: position.side,
+            price: position.price,
+            size_usd: position.size_usd,
+            collateral_usd: position.collateral_usd,
+            collateral_amount: position.collateral_amount,
+            locked_amount: position.locked_amount,
+            fee_amount,
+            fee_amount_usd,
+            fee_discount: 0,
+            timestamp: curtime,
         });
-    }
 
-    msg!("Batch settlement queued successfully");
-    Ok(())
+        Ok(())
+    }
 }
 
-// ===== Events =====
-
 #[event]
 pub struct DarkPoolTradeSettled {
-    pub trader_a: Pubkey,
-    pub trader_b: Pubkey,
+    pub trade_id: u64,
+    pub long_owner: Pubkey,
+    pub short_owner: Pubkey,
+    pub position_long: Pubkey,
+    pub position_short: Pubkey,
     pub size_usd: u64,
     pub price: u64,
     pub pool: Pubkey,
     pub custody: Pubkey,
     pub timestamp: i64,
 }
-
-#[event]
-pub struct DarkPoolTradeQueued {
-    pub trader_a: Pubkey,
-    pub trader_b: Pubkey,
-    pub size_usd: u64,
-    pub price: u64,
-    pub timestamp: i64,
-}
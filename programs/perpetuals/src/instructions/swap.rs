@@ -7,7 +7,7 @@ use {
         state::{custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::Pool},
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
     solana_program::program_error::ProgramError,
 };
 
@@ -22,14 +22,14 @@ pub struct Swap<'info> {
         constraint = funding_account.mint == receiving_custody.mint,
         has_one = owner
     )]
-    pub funding_account: Box<Account<'info, TokenAccount>>,
+    pub funding_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         constraint = receiving_account.mint == dispensing_custody.mint,
         has_one = owner
     )]
-    pub receiving_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: empty PDA, authority for token accounts
     #[account(
@@ -74,7 +74,12 @@ pub struct Swap<'info> {
                  receiving_custody.mint.as_ref()],
         bump = receiving_custody.token_account_bump
     )]
-    pub receiving_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = receiving_custody_token_mint.key() == receiving_custody.mint
+    )]
+    pub receiving_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
@@ -98,9 +103,17 @@ pub struct Swap<'info> {
                  dispensing_custody.mint.as_ref()],
         bump = dispensing_custody.token_account_bump
     )]
-    pub dispensing_custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub dispensing_custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = dispensing_custody_token_mint.key() == dispensing_custody.mint
+    )]
+    pub dispensing_custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
-    token_program: Program<'info, Token>,
+    // token program of the receiving custody mint
+    token_program: Interface<'info, TokenInterface>,
+    // token program of the dispensing custody mint
+    dispensing_token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
@@ -131,6 +144,13 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     }
     require_keys_neq!(receiving_custody.key(), dispensing_custody.key());
 
+    // tokens withheld by the mint's transfer fee never reach the custody
+    let transfer_fee_in = Perpetuals::get_transfer_fee(
+        &ctx.accounts.receiving_custody_token_mint.to_account_info(),
+        params.amount_in,
+    )?;
+    let amount_in = math::checked_sub(params.amount_in, transfer_fee_in)?;
+
     // compute token amount returned to the user
     let pool = ctx.accounts.pool.as_mut();
     let curtime = perpetuals.get_time()?;
@@ -181,14 +201,14 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
         &dispensed_token_ema_price,
         receiving_custody,
         dispensing_custody,
-        params.amount_in,
+        amount_in,
     )?;
 
     // calculate fee
     let fees = pool.get_swap_fees(
         token_id_in,
         token_id_out,
-        params.amount_in,
+        amount_in,
         amount_out,
         receiving_custody,
         &received_token_price,
@@ -200,8 +220,13 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     // check returned amount
     let no_fee_amount = math::checked_sub(amount_out, fees.1)?;
     msg!("Amount out: {}", no_fee_amount);
-    require_gte!(
+    // the user gets the amount left after the mint's transfer fee
+    let transfer_fee_out = Perpetuals::get_transfer_fee(
+        &ctx.accounts.dispensing_custody_token_mint.to_account_info(),
         no_fee_amount,
+    )?;
+    require_gte!(
+        math::checked_sub(no_fee_amount, transfer_fee_out)?,
         params.min_amount_out,
         PerpetualsError::InsufficientAmountReturned
     );
@@ -210,7 +235,7 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     msg!("Check pool constraints");
     let protocol_fee_in = Pool::get_fee_amount(receiving_custody.fees.protocol_share, fees.0)?;
     let protocol_fee_out = Pool::get_fee_amount(dispensing_custody.fees.protocol_share, fees.1)?;
-    let deposit_amount = math::checked_sub(params.amount_in, protocol_fee_in)?;
+    let deposit_amount = math::checked_sub(amount_in, protocol_fee_in)?;
     let withdrawal_amount = math::checked_add(no_fee_amount, protocol_fee_out)?;
 
     require!(
@@ -245,6 +270,7 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
             .receiving_custody_token_account
             .to_account_info(),
         ctx.accounts.owner.to_account_info(),
+        ctx.accounts.receiving_custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.amount_in,
     )?;
@@ -255,20 +281,26 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
             .to_account_info(),
         ctx.accounts.receiving_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
-        ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.dispensing_custody_token_mint.to_account_info(),
+        ctx.accounts.dispensing_token_program.to_account_info(),
         no_fee_amount,
     )?;
 
     // update custody stats
     msg!("Update custody stats");
-    receiving_custody.volume_stats.swap_usd = receiving_custody.volume_stats.swap_usd.wrapping_add(
-        received_token_price.get_asset_amount_usd(params.amount_in, receiving_custody.decimals)?,
-    );
-
-    receiving_custody.collected_fees.swap_usd =
-        receiving_custody.collected_fees.swap_usd.wrapping_add(
-            received_token_price.get_asset_amount_usd(fees.0, receiving_custody.decimals)?,
-        );
+    let amount_in_usd =
+        received_token_price.get_asset_amount_usd(amount_in, receiving_custody.decimals)?;
+    receiving_custody.volume_stats.swap_usd = receiving_custody
+        .volume_stats
+        .swap_usd
+        .wrapping_add(amount_in_usd);
+
+    let fee_in_usd =
+        received_token_price.get_asset_amount_usd(fees.0, receiving_custody.decimals)?;
+    receiving_custody.collected_fees.swap_usd = receiving_custody
+        .collected_fees
+        .swap_usd
+        .wrapping_add(fee_in_usd);
 
     receiving_custody.assets.owned =
         math::checked_add(receiving_custody.assets.owned, deposit_amount)?;
@@ -276,15 +308,19 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     receiving_custody.assets.protocol_fees =
         math::checked_add(receiving_custody.assets.protocol_fees, protocol_fee_in)?;
 
-    dispensing_custody.collected_fees.swap_usd =
-        dispensing_custody.collected_fees.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(fees.1, dispensing_custody.decimals)?,
-        );
+    let fee_out_usd =
+        dispensed_token_price.get_asset_amount_usd(fees.1, dispensing_custody.decimals)?;
+    dispensing_custody.collected_fees.swap_usd = dispensing_custody
+        .collected_fees
+        .swap_usd
+        .wrapping_add(fee_out_usd);
 
-    dispensing_custody.volume_stats.swap_usd =
-        dispensing_custody.volume_stats.swap_usd.wrapping_add(
-            dispensed_token_price.get_asset_amount_usd(amount_out, dispensing_custody.decimals)?,
-        );
+    let amount_out_usd =
+        dispensed_token_price.get_asset_amount_usd(amount_out, dispensing_custody.decimals)?;
+    dispensing_custody.volume_stats.swap_usd = dispensing_custody
+        .volume_stats
+        .swap_usd
+        .wrapping_add(amount_out_usd);
 
     dispensing_custody.assets.protocol_fees =
         math::checked_add(dispensing_custody.assets.protocol_fees, protocol_fee_out)?;
@@ -295,5 +331,42 @@ pub fn swap(ctx: Context<Swap>, params: &SwapParams) -> Result<()> {
     receiving_custody.update_borrow_rate(curtime)?;
     dispensing_custody.update_borrow_rate(curtime)?;
 
+    emit!(TokensSwapped {
+        owner: ctx.accounts.owner.key(),
+        pool: pool.key(),
+        receiving_custody: receiving_custody.key(),
+        dispensing_custody: dispensing_custody.key(),
+        amount_in,
+        amount_in_usd,
+        amount_out,
+        amount_out_usd,
+        transfer_amount: no_fee_amount,
+        fee_in: fees.0,
+        fee_in_usd,
+        fee_out: fees.1,
+        fee_out_usd,
+        timestamp: curtime,
+    });
+
     Ok(())
 }
+
+#[event]
+pub struct TokensSwapped {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub receiving_custody: Pubkey,
+    pub dispensing_custody: Pubkey,
+    pub amount_in: u64,
+    pub amount_in_usd: u64,
+    // amount out before fees
+    pub amount_out: u64,
+    pub amount_out_usd: u64,
+    // amount transferred to the user
+    pub transfer_amount: u64,
+    pub fee_in: u64,
+    pub fee_in_usd: u64,
+    pub fee_out: u64,
+    pub fee_out_usd: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,145 @@
+//! TransferPosition instruction handler
+
+use {
+    crate::state::{
+        perpetuals::Perpetuals,
+        pool::Pool,
+        position::{Position, Side},
+        position_counter::PositionCounter,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: wallet of the new position owner
+    pub new_owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(
+        mut,
+        has_one = owner,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 &position.index.to_le_bytes()],
+        bump = position.bump,
+        close = owner
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = PositionCounter::LEN,
+        seeds = [b"position_counter",
+                 new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_position_counter: Box<Account<'info, PositionCounter>>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = Position::LEN,
+        seeds = [b"position",
+                 new_owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 position.custody.as_ref(),
+                 &[position.side as u8],
+                 &new_position_counter.next_index.to_le_bytes()],
+        bump
+    )]
+    pub new_position: Box<Account<'info, Position>>,
+
+    system_program: Program<'info, System>,
+}
+
+pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+    // validate inputs
+    msg!("Validate inputs");
+    require_keys_neq!(ctx.accounts.owner.key(), ctx.accounts.new_owner.key());
+
+    // move position to the new owner, custody stats are not affected
+    msg!("Transfer position");
+    let position = ctx.accounts.position.as_ref();
+    let new_position = ctx.accounts.new_position.as_mut();
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    new_position.owner = ctx.accounts.new_owner.key();
+    new_position.pool = position.pool;
+    new_position.custody = position.custody;
+    new_position.collateral_custody = position.collateral_custody;
+    new_position.open_time = position.open_time;
+    new_position.update_time = position.update_time;
+    new_position.side = position.side;
+    new_position.price = position.price;
+    new_position.size_usd = position.size_usd;
+    new_position.borrow_size_usd = position.borrow_size_usd;
+    new_position.collateral_usd = position.collateral_usd;
+    new_position.unrealized_profit_usd = position.unrealized_profit_usd;
+    new_position.unrealized_loss_usd = position.unrealized_loss_usd;
+    new_position.cumulative_interest_snapshot = position.cumulative_interest_snapshot;
+    new_position.locked_amount = position.locked_amount;
+    new_position.collateral_amount = position.collateral_amount;
+    new_position.bump = *ctx
+        .bumps
+        .get("new_position")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    let new_position_counter = ctx.accounts.new_position_counter.as_mut();
+    if new_position_counter.owner == Pubkey::default() {
+        new_position_counter.owner = ctx.accounts.new_owner.key();
+        new_position_counter.bump = *ctx
+            .bumps
+            .get("new_position_counter")
+            .ok_or(ProgramError::InvalidSeeds)?;
+    }
+    new_position.index = new_position_counter.take_index()?;
+
+    emit!(PositionTransferred {
+        owner: position.owner,
+        new_owner: new_position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        position: position.key(),
+        new_position: new_position.key(),
+        side: position.side,
+        size_usd: position.size_usd,
+        collateral_amount: position.collateral_amount,
+        timestamp: curtime,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct PositionTransferred {
+    pub owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub position: Pubkey,
+    pub new_position: Pubkey,
+    pub side: Side,
+    pub size_usd: u64,
+    pub collateral_amount: u64,
+    pub timestamp: i64,
+}
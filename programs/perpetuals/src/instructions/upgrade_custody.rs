@@ -131,7 +131,7 @@ pub fn upgrade_custody<'info>(
         is_stable: deprecated_custody.is_stable,
         is_virtual: false,
         oracle: deprecated_custody.oracle,
-        pricing: deprecated_custody.pricing,
+        pricing: deprecated_custody.pricing.into(),
         permissions: deprecated_custody.permissions,
         fees: deprecated_custody.fees,
         borrow_rate: deprecated_custody.borrow_rate,
@@ -144,6 +144,8 @@ pub fn upgrade_custody<'info>(
         borrow_rate_state: deprecated_custody.borrow_rate_state,
         bump: deprecated_custody.bump,
         token_account_bump: deprecated_custody.token_account_bump,
+        // token-2022 custodies were not supported before the upgrade
+        token_program: anchor_spl::token::ID,
     };
 
     if !custody_data.validate() {
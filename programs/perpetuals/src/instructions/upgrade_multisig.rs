@@ -0,0 +1,100 @@
+//! UpgradeMultisig instruction handler
+
+use {
+    crate::state::{
+        multisig::{AdminRole, DeprecatedMultisig, Multisig},
+        perpetuals::Perpetuals,
+    },
+    anchor_lang::{prelude::*, Discriminator},
+};
+
+#[derive(Accounts)]
+pub struct UpgradeMultisig<'info> {
+    #[account(mut)]
+    pub upgrade_authority: Signer<'info>,
+
+    /// CHECK: Deprecated multisig account
+    #[account(
+        mut,
+        seeds = [b"multisig"],
+        bump
+    )]
+    pub multisig: AccountInfo<'info>,
+
+    /// CHECK: ProgramData account, doesn't work in tests
+    #[account()]
+    pub perpetuals_program_data: AccountInfo<'info /*, ProgramData*/>,
+
+    pub perpetuals_program: Program<'info, crate::program::Perpetuals>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradeMultisigParams {}
+
+pub fn upgrade_multisig(
+    ctx: Context<UpgradeMultisig>,
+    _params: &UpgradeMultisigParams,
+) -> Result<()> {
+    // deprecated multisig can't be used to sign the upgrade, so it is authorized
+    // by the program upgrade authority the same way as init
+    Perpetuals::validate_upgrade_authority(
+        ctx.accounts.upgrade_authority.key(),
+        &ctx.accounts.perpetuals_program_data.to_account_info(),
+        &ctx.accounts.perpetuals_program,
+    )?;
+
+    // load deprecated multisig data
+    msg!("Load deprecated multisig");
+    let multisig_account = &ctx.accounts.multisig;
+    if multisig_account.owner != &crate::ID {
+        return Err(ProgramError::IllegalOwner.into());
+    }
+    if multisig_account.try_data_len()? != DeprecatedMultisig::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let deprecated_multisig = {
+        let data = multisig_account.try_borrow_data()?;
+        // deprecated layout was stored under the same account discriminator
+        if data[..8] != Multisig::DISCRIMINATOR {
+            return Err(ProgramError::InvalidAccountData.into());
+        }
+        *bytemuck::try_from_bytes::<DeprecatedMultisig>(&data[8..])
+            .map_err(|_| ProgramError::InvalidAccountData)?
+    };
+
+    // pending signatures are dropped as the old hash can't be converted
+    let multisig_data = Multisig {
+        num_signers: deprecated_multisig.num_signers,
+        num_signed: 0,
+        min_signatures: deprecated_multisig.min_signatures,
+        instruction_accounts_len: 0,
+        instruction_data_len: 0,
+        instruction_hash: [0; 32],
+        signers: deprecated_multisig.signers,
+        signed: [0; Multisig::MAX_SIGNERS],
+        bump: deprecated_multisig.bump,
+        timelock_delay: 0,
+        num_proposals: 0,
+        role: AdminRole::Admin as u8,
+    };
+
+    msg!("Resize multisig account");
+    Perpetuals::realloc(
+        ctx.accounts.upgrade_authority.to_account_info(),
+        ctx.accounts.multisig.clone(),
+        ctx.accounts.system_program.to_account_info(),
+        Multisig::LEN,
+        true,
+    )?;
+
+    msg!("Re-initialize the multisig");
+    if multisig_account.try_data_len()? != Multisig::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let mut data = multisig_account.try_borrow_mut_data()?;
+    data[8..].copy_from_slice(bytemuck::bytes_of(&multisig_data));
+
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! UpgradePosition instruction handler
+
+use {
+    crate::state::{
+        perpetuals::Perpetuals,
+        pool::Pool,
+        position::{LegacyPosition, Position, Side},
+        position_counter::PositionCounter,
+    },
+    anchor_lang::prelude::*,
+};
+
+#[derive(Accounts)]
+#[instruction(params: UpgradePositionParams)]
+pub struct UpgradePosition<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// CHECK: wallet of the position owner
+    pub owner: AccountInfo<'info>,
+
+    #[account(
+        seeds = [b"perpetuals"],
+        bump = perpetuals.perpetuals_bump
+    )]
+    pub perpetuals: Box<Account<'info, Perpetuals>>,
+
+    #[account(
+        seeds = [b"pool",
+                 pool.name.as_bytes()],
+        bump = pool.bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+
+    #[account(mut)]
+    /// CHECK: Legacy position account, address is validated by the handler
+    pub legacy_position: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = PositionCounter::LEN,
+        seeds = [b"position_counter",
+                 owner.key().as_ref()],
+        bump
+    )]
+    pub position_counter: Box<Account<'info, PositionCounter>>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = Position::LEN,
+        seeds = [b"position",
+                 owner.key().as_ref(),
+                 pool.key().as_ref(),
+                 params.custody.as_ref(),
+                 &[params.side as u8],
+                 &position_counter.next_index.to_le_bytes()],
+        bump
+    )]
+    pub position: Box<Account<'info, Position>>,
+
+    system_program: Program<'info, System>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct UpgradePositionParams {
+    // custody and side of the legacy position
+    pub custody: Pubkey,
+    pub side: Side,
+}
+
+pub fn upgrade_position(
+    ctx: Context<UpgradePosition>,
+    params: &UpgradePositionParams,
+) -> Result<()> {
+    // load legacy position data
+    msg!("Load legacy position");
+    let legacy_position_account = &ctx.accounts.legacy_position;
+    if legacy_position_account.owner != &crate::ID {
+        return Err(ProgramError::IllegalOwner.into());
+    }
+    if legacy_position_account.try_data_len()? != LegacyPosition::LEN {
+        return Err(ProgramError::InvalidAccountData.into());
+    }
+    let legacy_position = Account::<LegacyPosition>::try_from_unchecked(legacy_position_account)?;
+    let legacy_position_address = Pubkey::create_program_address(
+        &[
+            b"position",
+            ctx.accounts.owner.key().as_ref(),
+            ctx.accounts.pool.key().as_ref(),
+            params.custody.as_ref(),
+            &[params.side as u8],
+            &[legacy_position.bump],
+        ],
+        &crate::ID,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)?;
+    if legacy_position.owner != ctx.accounts.owner.key()
+        || legacy_position.pool != ctx.accounts.pool.key()
+        || legacy_position.custody != params.custody
+        || legacy_position.side != params.side
+        || legacy_position_address != legacy_position_account.key()
+    {
+        return Err(ProgramError::InvalidSeeds.into());
+    }
+
+    // re-create the position under the next index of the owner, custody
+    // stats are not affected
+    msg!("Re-create position");
+    let position = ctx.accounts.position.as_mut();
+    let curtime = ctx.accounts.perpetuals.get_time()?;
+
+    position.owner = legacy_position.owner;
+    position.pool = legacy_position.pool;
+    position.custody = legacy_position.custody;
+    position.collateral_custody = legacy_position.collateral_custody;
+    position.open_time = legacy_position.open_time;
+    position.update_time = legacy_position.update_time;
+    position.side = legacy_position.side;
+    position.price = legacy_position.price;
+    position.size_usd = legacy_position.size_usd;
+    position.borrow_size_usd = legacy_position.borrow_size_usd;
+    position.collateral_usd = legacy_position.collateral_usd;
+    position.unrealized_profit_usd = legacy_position.unrealized_profit_usd;
+    position.unrealized_loss_usd = legacy_position.unrealized_loss_usd;
+    position.cumulative_interest_snapshot = legacy_position.cumulative_interest_snapshot;
+    position.locked_amount = legacy_position.locked_amount;
+    position.collateral_amount = legacy_position.collateral_amount;
+    position.bump = *ctx
+        .bumps
+        .get("position")
+        .ok_or(ProgramError::InvalidSeeds)?;
+
+    let position_counter = ctx.accounts.position_counter.as_mut();
+    if position_counter.owner == Pubkey::default() {
+        position_counter.owner = ctx.accounts.owner.key();
+        position_counter.bump = *ctx
+            .bumps
+            .get("position_counter")
+            .ok_or(ProgramError::InvalidSeeds)?;
+    }
+    position.index = position_counter.take_index()?;
+
+    // the payer funds the new account and takes the rent of the legacy one
+    msg!("Close legacy position");
+    legacy_position.close(ctx.accounts.payer.to_account_info())?;
+
+    emit!(PositionUpgraded {
+        owner: position.owner,
+        pool: position.pool,
+        custody: position.custody,
+        legacy_position: legacy_position_account.key(),
+        position: position.key(),
+        index: position.index,
+        side: position.side,
+        timestamp: curtime,
+    });
+
+    Ok(())
+}
+
+#[event]
+pub struct PositionUpgraded {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub legacy_position: Pubkey,
+    pub position: Pubkey,
+    pub index: u64,
+    pub side: Side,
+    pub timestamp: i64,
+}
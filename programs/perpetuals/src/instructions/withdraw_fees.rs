@@ -2,6 +2,7 @@
 
 use {
     crate::{
+        error::PerpetualsError,
         math,
         state::{
             custody::Custody,
@@ -11,7 +12,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
-    anchor_spl::token::{Token, TokenAccount},
+    anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface},
 };
 
 #[derive(Accounts)]
@@ -21,8 +22,10 @@ pub struct WithdrawFees<'info> {
 
     #[account(
         mut,
-        seeds = [b"multisig"],
-        bump = multisig.load()?.bump
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::WithdrawFees)
+            @ PerpetualsError::MultisigAccountNotAuthorized
     )]
     pub multisig: AccountLoader<'info, Multisig>,
 
@@ -63,15 +66,20 @@ pub struct WithdrawFees<'info> {
                  custody.mint.as_ref()],
         bump = custody.token_account_bump
     )]
-    pub custody_token_account: Box<Account<'info, TokenAccount>>,
+    pub custody_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        constraint = custody_token_mint.key() == custody.mint
+    )]
+    pub custody_token_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
         constraint = receiving_token_account.mint == custody_token_account.mint
     )]
-    pub receiving_token_account: Box<Account<'info, TokenAccount>>,
+    pub receiving_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    token_program: Program<'info, Token>,
+    token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize)]
@@ -122,9 +130,27 @@ pub fn withdraw_fees<'info>(
         ctx.accounts.custody_token_account.to_account_info(),
         ctx.accounts.receiving_token_account.to_account_info(),
         ctx.accounts.transfer_authority.to_account_info(),
+        ctx.accounts.custody_token_mint.to_account_info(),
         ctx.accounts.token_program.to_account_info(),
         params.amount,
     )?;
 
+    emit!(FeesWithdrawn {
+        pool: ctx.accounts.pool.key(),
+        custody: ctx.accounts.custody.key(),
+        receiving_account: ctx.accounts.receiving_token_account.key(),
+        amount: params.amount,
+        protocol_fees_left: ctx.accounts.custody.assets.protocol_fees,
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct FeesWithdrawn {
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub receiving_account: Pubkey,
+    pub amount: u64,
+    pub protocol_fees_left: u64,
+}
@@ -2,6 +2,7 @@
 
 use {
     crate::{
+        error::PerpetualsError,
         math,
         state::{
             multisig::{AdminInstruction, Multisig},
@@ -19,8 +20,10 @@ pub struct WithdrawSolFees<'info> {
 
     #[account(
         mut,
-        seeds = [b"multisig"],
-        bump = multisig.load()?.bump
+        seeds = [b"multisig", multisig.load()?.get_role()?.get_seed()],
+        bump = multisig.load()?.bump,
+        constraint = multisig.load()?.is_authorized(AdminInstruction::WithdrawSolFees)
+            @ PerpetualsError::MultisigAccountNotAuthorized
     )]
     pub multisig: AccountLoader<'info, Multisig>,
 
@@ -100,5 +103,16 @@ pub fn withdraw_sol_fees<'info>(
         params.amount,
     )?;
 
+    emit!(SolFeesWithdrawn {
+        receiving_account: ctx.accounts.receiving_account.key(),
+        amount: params.amount,
+    });
+
     Ok(0)
 }
+
+#[event]
+pub struct SolFeesWithdrawn {
+    pub receiving_account: Pubkey,
+    pub amount: u64,
+}
@@ -70,6 +70,13 @@ pub mod perpetuals {
         instructions::set_admin_signers(ctx, &params)
     }
 
+    pub fn set_role_signers<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetRoleSigners<'info>>,
+        params: SetRoleSignersParams,
+    ) -> Result<u8> {
+        instructions::set_role_signers(ctx, &params)
+    }
+
     pub fn set_custody_config<'info>(
         ctx: Context<'_, '_, '_, 'info, SetCustodyConfig<'info>>,
         params: SetCustodyConfigParams,
@@ -77,6 +84,13 @@ pub mod perpetuals {
         instructions::set_custody_config(ctx, &params)
     }
 
+    pub fn set_custody_risk<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetCustodyRisk<'info>>,
+        params: SetCustodyRiskParams,
+    ) -> Result<u8> {
+        instructions::set_custody_risk(ctx, &params)
+    }
+
     pub fn set_permissions<'info>(
         ctx: Context<'_, '_, '_, 'info, SetPermissions<'info>>,
         params: SetPermissionsParams,
@@ -105,6 +119,55 @@ pub mod perpetuals {
         instructions::upgrade_custody(ctx, &params)
     }
 
+    pub fn migrate_custody<'info>(
+        ctx: Context<'_, '_, '_, 'info, MigrateCustody<'info>>,
+        params: MigrateCustodyParams,
+    ) -> Result<u8> {
+        instructions::migrate_custody(ctx, &params)
+    }
+
+    pub fn upgrade_multisig(
+        ctx: Context<UpgradeMultisig>,
+        params: UpgradeMultisigParams,
+    ) -> Result<()> {
+        instructions::upgrade_multisig(ctx, &params)
+    }
+
+    pub fn set_fee_tiers<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetFeeTiers<'info>>,
+        params: SetFeeTiersParams,
+    ) -> Result<u8> {
+        instructions::set_fee_tiers(ctx, &params)
+    }
+
+    pub fn set_timelock_delay<'info>(
+        ctx: Context<'_, '_, '_, 'info, SetTimelockDelay<'info>>,
+        params: SetTimelockDelayParams,
+    ) -> Result<u8> {
+        instructions::set_timelock_delay(ctx, &params)
+    }
+
+    pub fn create_proposal(
+        ctx: Context<CreateProposal>,
+        params: CreateProposalParams,
+    ) -> Result<u8> {
+        instructions::create_proposal(ctx, &params)
+    }
+
+    pub fn approve_proposal(
+        ctx: Context<ApproveProposal>,
+        params: ApproveProposalParams,
+    ) -> Result<u8> {
+        instructions::approve_proposal(ctx, &params)
+    }
+
+    pub fn cancel_proposal(
+        ctx: Context<CancelProposal>,
+        params: CancelProposalParams,
+    ) -> Result<u8> {
+        instructions::cancel_proposal(ctx, &params)
+    }
+
     pub fn set_custom_oracle_price<'info>(
         ctx: Context<'_, '_, '_, 'info, SetCustomOraclePrice<'info>>,
         params: SetCustomOraclePriceParams,
@@ -157,6 +220,28 @@ pub mod perpetuals {
         instructions::close_position(ctx, &params)
     }
 
+    pub fn transfer_position(ctx: Context<TransferPosition>) -> Result<()> {
+        instructions::transfer_position(ctx)
+    }
+
+    pub fn upgrade_position(
+        ctx: Context<UpgradePosition>,
+        params: UpgradePositionParams,
+    ) -> Result<()> {
+        instructions::upgrade_position(ctx, &params)
+    }
+
+    pub fn set_trading_delegate(
+        ctx: Context<SetTradingDelegate>,
+        params: SetTradingDelegateParams,
+    ) -> Result<()> {
+        instructions::set_trading_delegate(ctx, &params)
+    }
+
+    pub fn remove_trading_delegate(ctx: Context<RemoveTradingDelegate>) -> Result<()> {
+        instructions::remove_trading_delegate(ctx)
+    }
+
     pub fn liquidate(ctx: Context<Liquidate>, params: LiquidateParams) -> Result<()> {
         instructions::liquidate(ctx, &params)
     }
@@ -248,6 +333,14 @@ pub mod perpetuals {
         instructions::set_custom_oracle_price_permissionless(ctx, &params)
     }
 
+    // Executes approved admin proposal after its timelock expires.
+    pub fn execute_proposal<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteProposal<'info>>,
+        params: ExecuteProposalParams,
+    ) -> Result<()> {
+        instructions::execute_proposal(ctx, &params)
+    }
+
     // ===== Darkpool Settlement Instructions =====
 
     pub fn settle_dark_pool_trade(
@@ -256,11 +349,4 @@ pub mod perpetuals {
     ) -> Result<()> {
         instructions::settle_dark_pool_trade(ctx, &params)
     }
-
-    pub fn batch_settle_dark_pool_trades(
-        ctx: Context<BatchSettleDarkPoolTrades>,
-        params: BatchSettleDarkPoolTradesParams,
-    ) -> Result<()> {
-        instructions::batch_settle_dark_pool_trades(ctx, &params)
-    }
 }
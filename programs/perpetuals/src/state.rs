@@ -6,3 +6,7 @@ pub mod oracle;
 pub mod perpetuals;
 pub mod pool;
 pub mod position;
+pub mod position_counter;
+pub mod proposal;
+pub mod trading_delegate;
+pub mod trading_stats;
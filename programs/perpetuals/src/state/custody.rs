@@ -1,6 +1,6 @@
 use {
     crate::{
-        error::PerpetualsError,
+        error::{map_pricing_error, PerpetualsError},
         math,
         state::{
             oracle::{OracleParams, OraclePrice, OracleType},
@@ -9,6 +9,7 @@ use {
         },
     },
     anchor_lang::prelude::*,
+    perpetuals_pricing as pricing,
 };
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
@@ -98,6 +99,9 @@ pub struct PricingParams {
     // USD denominated values always have implied USD_DECIMALS decimals
     pub max_position_locked_usd: u64,
     pub max_total_locked_usd: u64,
+    // price impact for a trade that moves open interest skew by the full custody depth
+    pub price_impact_mult: u64,
+    pub max_price_impact: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -158,21 +162,31 @@ pub struct Custody {
     // bumps for address validation
     pub bump: u8,
     pub token_account_bump: u8,
+
+    // SPL Token or Token-2022 program that owns the mint and token account,
+    // appended so earlier fields keep their offsets
+    pub token_program: Pubkey,
 }
 
+// pricing params before price impact was added, embedded in deprecated custodies
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
 pub struct DeprecatedPricingParams {
     pub use_ema: bool,
     // whether to account for unrealized pnl in assets under management calculations
     pub use_unrealized_pnl_in_aum: bool,
-    // pricing params have implied BPS_DECIMALS decimals
+    // pricing params have implied BPS_DECIMALS decimals (except ended with _usd)
     pub trade_spread_long: u64,
     pub trade_spread_short: u64,
     pub swap_spread: u64,
     pub min_initial_leverage: u64,
+    pub max_initial_leverage: u64,
     pub max_leverage: u64,
     // max_user_profit = position_size * max_payoff_mult
     pub max_payoff_mult: u64,
+    pub max_utilization: u64,
+    // USD denominated values always have implied USD_DECIMALS decimals
+    pub max_position_locked_usd: u64,
+    pub max_total_locked_usd: u64,
 }
 
 #[account]
@@ -185,7 +199,39 @@ pub struct DeprecatedCustody {
     pub decimals: u8,
     pub is_stable: bool,
     pub oracle: OracleParams,
-    pub pricing: PricingParams,
+    pub pricing: DeprecatedPricingParams,
+    pub permissions: Permissions,
+    pub fees: Fees,
+    pub borrow_rate: BorrowRateParams,
+
+    // dynamic variables
+    pub assets: Assets,
+    pub collected_fees: FeesStats,
+    pub volume_stats: VolumeStats,
+    pub trade_stats: TradeStats,
+    pub long_positions: PositionStats,
+    pub short_positions: PositionStats,
+    pub borrow_rate_state: BorrowRateState,
+
+    // bumps for address validation
+    pub bump: u8,
+    pub token_account_bump: u8,
+}
+
+// custody layout before price impact params and token programs were added,
+// can't be told apart from DeprecatedCustody by its size
+#[account]
+#[derive(Default, Debug)]
+pub struct LegacyCustody {
+    // static parameters
+    pub pool: Pubkey,
+    pub mint: Pubkey,
+    pub token_account: Pubkey,
+    pub decimals: u8,
+    pub is_stable: bool,
+    pub is_virtual: bool,
+    pub oracle: OracleParams,
+    pub pricing: DeprecatedPricingParams,
     pub permissions: Permissions,
     pub fees: Fees,
     pub borrow_rate: BorrowRateParams,
@@ -243,6 +289,7 @@ impl PricingParams {
             && (self.swap_spread as u128) < Perpetuals::BPS_POWER
             && (self.max_utilization as u128) <= Perpetuals::BPS_POWER
             && self.max_position_locked_usd <= self.max_total_locked_usd
+            && (self.max_price_impact as u128) < Perpetuals::BPS_POWER
     }
 }
 
@@ -305,108 +352,29 @@ impl Custody {
     }
 
     pub fn get_locked_amount(&self, size: u64, side: Side) -> Result<u64> {
-        let max_payoff_mult = if side == Side::Short {
-            std::cmp::min(Perpetuals::BPS_POWER, self.pricing.max_payoff_mult as u128)
-        } else {
-            self.pricing.max_payoff_mult as u128
-        };
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(size as u128, max_payoff_mult)?,
-            Perpetuals::BPS_POWER,
-        )?)
+        pricing::Custody::from(self)
+            .get_locked_amount(size, side.into())
+            .map_err(map_pricing_error)
     }
 
     pub fn get_interest_amount_usd(&self, position: &Position, curtime: i64) -> Result<u64> {
-        if position.borrow_size_usd == 0 || self.is_virtual {
-            return Ok(0);
-        }
-
-        let cumulative_interest = self.get_cumulative_interest(curtime)?;
-
-        let position_interest = if cumulative_interest > position.cumulative_interest_snapshot {
-            math::checked_sub(cumulative_interest, position.cumulative_interest_snapshot)?
-        } else {
-            return Ok(0);
-        };
-
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(position_interest, position.borrow_size_usd as u128)?,
-            Perpetuals::RATE_POWER,
-        )?)
+        pricing::Custody::from(self)
+            .get_interest_amount_usd(&position.into(), curtime)
+            .map_err(map_pricing_error)
     }
 
     pub fn get_cumulative_interest(&self, curtime: i64) -> Result<u128> {
-        if curtime > self.borrow_rate_state.last_update {
-            let cumulative_interest = math::checked_ceil_div(
-                math::checked_mul(
-                    math::checked_sub(curtime, self.borrow_rate_state.last_update)? as u128,
-                    self.borrow_rate_state.current_rate as u128,
-                )?,
-                3600,
-            )?;
-            math::checked_add(
-                self.borrow_rate_state.cumulative_interest,
-                cumulative_interest,
-            )
-        } else {
-            Ok(self.borrow_rate_state.cumulative_interest)
-        }
+        pricing::Custody::from(self)
+            .get_cumulative_interest(curtime)
+            .map_err(map_pricing_error)
     }
 
     pub fn update_borrow_rate(&mut self, curtime: i64) -> Result<()> {
-        // if current_utilization < optimal_utilization:
-        //   rate = base_rate + (current_utilization / optimal_utilization) * slope1
-        // else:
-        //   rate = base_rate + slope1 + (current_utilization - optimal_utilization) / (1 - optimal_utilization) * slope2
-
-        if self.assets.owned == 0 {
-            self.borrow_rate_state.current_rate = 0;
-            self.borrow_rate_state.last_update =
-                std::cmp::max(curtime, self.borrow_rate_state.last_update);
-            return Ok(());
-        }
-
-        if curtime > self.borrow_rate_state.last_update {
-            // compute interest accumulated since previous update
-            self.borrow_rate_state.cumulative_interest = self.get_cumulative_interest(curtime)?;
-            self.borrow_rate_state.last_update = curtime;
-        }
-
-        // get current utilization
-        let current_utilization = math::checked_div(
-            math::checked_mul(self.assets.locked as u128, Perpetuals::RATE_POWER)?,
-            self.assets.owned as u128,
-        )?;
-
-        // compute and save new borrow rate
-        let hourly_rate = if current_utilization < (self.borrow_rate.optimal_utilization as u128)
-            || (self.borrow_rate.optimal_utilization as u128) >= Perpetuals::RATE_POWER
-        {
-            math::checked_div(
-                math::checked_mul(current_utilization, self.borrow_rate.slope1 as u128)?,
-                self.borrow_rate.optimal_utilization as u128,
-            )?
-        } else {
-            math::checked_add(
-                self.borrow_rate.slope1 as u128,
-                math::checked_div(
-                    math::checked_mul(
-                        math::checked_sub(
-                            current_utilization,
-                            self.borrow_rate.optimal_utilization as u128,
-                        )?,
-                        self.borrow_rate.slope2 as u128,
-                    )?,
-                    Perpetuals::RATE_POWER - self.borrow_rate.optimal_utilization as u128,
-                )?,
-            )?
-        };
-        let hourly_rate = math::checked_add(
-            math::checked_as_u64(hourly_rate)?,
-            self.borrow_rate.base_rate,
-        )?;
-
-        self.borrow_rate_state.current_rate = hourly_rate;
+        let mut custody = pricing::Custody::from(&*self);
+        custody
+            .update_borrow_rate(curtime)
+            .map_err(map_pricing_error)?;
+        self.borrow_rate_state = custody.borrow_rate_state.into();
 
         Ok(())
     }
@@ -547,41 +515,41 @@ impl Custody {
             &mut self.short_positions
         };
 
+        // the collateral custody is updated even if this was the last position
         if stats.open_positions == 1 {
             *stats = PositionStats::default();
-            return Ok(());
-        }
-
-        // update borrowed size and cumulative interest only if trading token custody is the collateral custody
-        if collateral_custody.is_none() {
-            stats.cumulative_interest_usd =
-                math::checked_add(stats.cumulative_interest_usd, interest_usd)?;
-            stats.cumulative_interest_usd = stats
-                .cumulative_interest_usd
-                .saturating_sub(position_interest_usd);
-            stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
-            stats.borrow_size_usd =
-                math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
-        }
+        } else {
+            // update borrowed size and cumulative interest only if trading token custody is the collateral custody
+            if collateral_custody.is_none() {
+                stats.cumulative_interest_usd =
+                    math::checked_add(stats.cumulative_interest_usd, interest_usd)?;
+                stats.cumulative_interest_usd = stats
+                    .cumulative_interest_usd
+                    .saturating_sub(position_interest_usd);
+                stats.cumulative_interest_snapshot = cumulative_interest_snapshot;
+                stats.borrow_size_usd =
+                    math::checked_sub(stats.borrow_size_usd, position.borrow_size_usd)?;
+            }
 
-        stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
-        stats.size_usd = math::checked_sub(stats.size_usd, position.size_usd)?;
-        stats.locked_amount = math::checked_sub(stats.locked_amount, position.locked_amount)?;
+            stats.open_positions = math::checked_sub(stats.open_positions, 1)?;
+            stats.size_usd = math::checked_sub(stats.size_usd, position.size_usd)?;
+            stats.locked_amount = math::checked_sub(stats.locked_amount, position.locked_amount)?;
 
-        let position_price = math::scale_to_exponent(
-            position.price,
-            -(Perpetuals::PRICE_DECIMALS as i32),
-            -(Perpetuals::USD_DECIMALS as i32),
-        )?;
-        let quantity = math::checked_div(
-            math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
-            position_price as u128,
-        )?;
-        stats.weighted_price = math::checked_sub(
-            stats.weighted_price,
-            math::checked_mul(position.price as u128, quantity)?,
-        )?;
-        stats.total_quantity = math::checked_sub(stats.total_quantity, quantity)?;
+            let position_price = math::scale_to_exponent(
+                position.price,
+                -(Perpetuals::PRICE_DECIMALS as i32),
+                -(Perpetuals::USD_DECIMALS as i32),
+            )?;
+            let quantity = math::checked_div(
+                math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
+                position_price as u128,
+            )?;
+            stats.weighted_price = math::checked_sub(
+                stats.weighted_price,
+                math::checked_mul(position.price as u128, quantity)?,
+            )?;
+            stats.total_quantity = math::checked_sub(stats.total_quantity, quantity)?;
+        }
 
         // update collateral custody for interest tracking
         if let Some(custody) = collateral_custody {
@@ -616,10 +584,158 @@ impl Custody {
     }
 }
 
+impl From<FeesMode> for pricing::FeesMode {
+    fn from(mode: FeesMode) -> Self {
+        match mode {
+            FeesMode::Fixed => Self::Fixed,
+            FeesMode::Linear => Self::Linear,
+            FeesMode::Optimal => Self::Optimal,
+        }
+    }
+}
+
+impl From<Fees> for pricing::Fees {
+    fn from(fees: Fees) -> Self {
+        Self {
+            mode: fees.mode.into(),
+            ratio_mult: fees.ratio_mult,
+            utilization_mult: fees.utilization_mult,
+            swap_in: fees.swap_in,
+            swap_out: fees.swap_out,
+            stable_swap_in: fees.stable_swap_in,
+            stable_swap_out: fees.stable_swap_out,
+            add_liquidity: fees.add_liquidity,
+            remove_liquidity: fees.remove_liquidity,
+            open_position: fees.open_position,
+            close_position: fees.close_position,
+            liquidation: fees.liquidation,
+            protocol_share: fees.protocol_share,
+            fee_max: fees.fee_max,
+            fee_optimal: fees.fee_optimal,
+        }
+    }
+}
+
+impl From<TradeStats> for pricing::TradeStats {
+    fn from(stats: TradeStats) -> Self {
+        Self {
+            profit_usd: stats.profit_usd,
+            loss_usd: stats.loss_usd,
+            oi_long_usd: stats.oi_long_usd,
+            oi_short_usd: stats.oi_short_usd,
+        }
+    }
+}
+
+impl From<Assets> for pricing::Assets {
+    fn from(assets: Assets) -> Self {
+        Self {
+            collateral: assets.collateral,
+            protocol_fees: assets.protocol_fees,
+            owned: assets.owned,
+            locked: assets.locked,
+        }
+    }
+}
+
+impl From<PricingParams> for pricing::PricingParams {
+    fn from(params: PricingParams) -> Self {
+        Self {
+            use_ema: params.use_ema,
+            use_unrealized_pnl_in_aum: params.use_unrealized_pnl_in_aum,
+            trade_spread_long: params.trade_spread_long,
+            trade_spread_short: params.trade_spread_short,
+            swap_spread: params.swap_spread,
+            min_initial_leverage: params.min_initial_leverage,
+            max_initial_leverage: params.max_initial_leverage,
+            max_leverage: params.max_leverage,
+            max_payoff_mult: params.max_payoff_mult,
+            max_utilization: params.max_utilization,
+            max_position_locked_usd: params.max_position_locked_usd,
+            max_total_locked_usd: params.max_total_locked_usd,
+            price_impact_mult: params.price_impact_mult,
+            max_price_impact: params.max_price_impact,
+        }
+    }
+}
+
+impl From<BorrowRateParams> for pricing::BorrowRateParams {
+    fn from(params: BorrowRateParams) -> Self {
+        Self {
+            base_rate: params.base_rate,
+            slope1: params.slope1,
+            slope2: params.slope2,
+            optimal_utilization: params.optimal_utilization,
+        }
+    }
+}
+
+impl From<BorrowRateState> for pricing::BorrowRateState {
+    fn from(state: BorrowRateState) -> Self {
+        Self {
+            current_rate: state.current_rate,
+            cumulative_interest: state.cumulative_interest,
+            last_update: state.last_update,
+        }
+    }
+}
+
+impl From<pricing::BorrowRateState> for BorrowRateState {
+    fn from(state: pricing::BorrowRateState) -> Self {
+        Self {
+            current_rate: state.current_rate,
+            cumulative_interest: state.cumulative_interest,
+            last_update: state.last_update,
+        }
+    }
+}
+
+impl From<&Custody> for pricing::Custody {
+    fn from(custody: &Custody) -> Self {
+        Self {
+            decimals: custody.decimals,
+            is_stable: custody.is_stable,
+            is_virtual: custody.is_virtual,
+            pricing: custody.pricing.into(),
+            fees: custody.fees.into(),
+            borrow_rate: custody.borrow_rate.into(),
+            assets: custody.assets.into(),
+            trade_stats: custody.trade_stats.into(),
+            borrow_rate_state: custody.borrow_rate_state.into(),
+        }
+    }
+}
+
 impl DeprecatedCustody {
     pub const LEN: usize = 8 + std::mem::size_of::<DeprecatedCustody>();
 }
 
+impl LegacyCustody {
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyCustody>();
+}
+
+impl From<DeprecatedPricingParams> for PricingParams {
+    fn from(params: DeprecatedPricingParams) -> Self {
+        Self {
+            use_ema: params.use_ema,
+            use_unrealized_pnl_in_aum: params.use_unrealized_pnl_in_aum,
+            trade_spread_long: params.trade_spread_long,
+            trade_spread_short: params.trade_spread_short,
+            swap_spread: params.swap_spread,
+            min_initial_leverage: params.min_initial_leverage,
+            max_initial_leverage: params.max_initial_leverage,
+            max_leverage: params.max_leverage,
+            max_payoff_mult: params.max_payoff_mult,
+            max_utilization: params.max_utilization,
+            max_position_locked_usd: params.max_position_locked_usd,
+            max_total_locked_usd: params.max_total_locked_usd,
+            // price impact is disabled until configured
+            price_impact_mult: 0,
+            max_price_impact: 0,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -740,4 +856,59 @@ mod test {
         custody.update_borrow_rate(3600).unwrap();
         assert_eq!(custody.borrow_rate_state.current_rate, 199400);
     }
+
+    #[test]
+    fn test_remove_last_position() {
+        let mut custody = get_fixture();
+        let mut collateral_custody = get_fixture();
+        let position = Position {
+            side: Side::Short,
+            price: 25_000_000,
+            size_usd: 100_000_000,
+            borrow_size_usd: 100_000_000,
+            locked_amount: 400,
+            ..Position::default()
+        };
+        let token_price = OraclePrice::new(2_500_000_000, -8);
+
+        custody
+            .add_position(&position, &token_price, 0, Some(&mut collateral_custody))
+            .unwrap();
+        assert_eq!(custody.short_positions.open_positions, 1);
+        assert_eq!(collateral_custody.short_positions.open_positions, 1);
+        assert_eq!(
+            collateral_custody.short_positions.borrow_size_usd,
+            100_000_000
+        );
+
+        custody
+            .remove_position(&position, 0, Some(&mut collateral_custody))
+            .unwrap();
+        assert_eq!(custody.short_positions, PositionStats::default());
+        assert_eq!(collateral_custody.short_positions, PositionStats::default());
+    }
+
+    #[test]
+    fn test_token_program_offset() {
+        let custody = Custody {
+            token_program: anchor_spl::token_2022::ID,
+            ..get_fixture()
+        };
+        let data = custody.try_to_vec().unwrap();
+
+        // token program is the last field, earlier offsets match the pre token-2022 layout
+        assert_eq!(
+            data[data.len() - 32..],
+            anchor_spl::token_2022::ID.to_bytes()
+        );
+        assert_eq!(
+            data[..data.len() - 32],
+            Custody {
+                token_program: Pubkey::default(),
+                ..custody
+            }
+            .try_to_vec()
+            .unwrap()[..data.len() - 32]
+        );
+    }
 }
@@ -2,15 +2,35 @@
 
 use {
     crate::{error::PerpetualsError, math},
-    ahash::AHasher,
     anchor_lang::prelude::*,
-    std::hash::Hasher,
+    solana_program::hash::Hasher,
 };
 
 #[repr(C, packed)]
 #[account(zero_copy)]
 #[derive(Default)]
 pub struct Multisig {
+    pub num_signers: u8,
+    pub num_signed: u8,
+    pub min_signatures: u8,
+    pub instruction_accounts_len: u8,
+    pub instruction_data_len: u16,
+    pub instruction_hash: [u8; 32],
+    pub signers: [Pubkey; 6], // Multisig::MAX_SIGNERS
+    pub signed: [u8; 6],      // Multisig::MAX_SIGNERS
+    pub bump: u8,
+    // delay between proposal approval and execution, admin instructions
+    // can only be executed through proposals if non-zero
+    pub timelock_delay: i64,
+    pub num_proposals: u64,
+    // AdminRole this signer set is authorized for
+    pub role: u8,
+}
+
+#[repr(C, packed)]
+#[account(zero_copy)]
+#[derive(Default)]
+pub struct DeprecatedMultisig {
     pub num_signers: u8,
     pub num_signed: u8,
     pub min_signatures: u8,
@@ -36,27 +56,107 @@ pub enum AdminInstruction {
     SetCustomOraclePrice,
     SetTestTime,
     UpgradeCustody,
+    SetFeeTiers,
+    SetTimelockDelay,
+    SetRoleSigners,
+    MigrateCustody,
+    SetCustodyRisk,
+}
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
+pub enum AdminRole {
+    // root signer set, authorized for all admin instructions
+    Admin,
+    // can only revoke permissions, i.e. pause trading
+    Guardian,
+    // custody pricing, fees and borrow rate, fee tiers
+    RiskManager,
+    // fee withdrawals
+    Treasurer,
+    // custom oracle price updates
+    OracleOperator,
+}
+
+impl AdminRole {
+    /// Roles sharing the admin multisig timelock delay, their signer sets go through
+    /// their own proposals. The guardian can pause trading without delay
+    pub const TIMELOCKED_ROLES: [AdminRole; 3] = [
+        AdminRole::RiskManager,
+        AdminRole::Treasurer,
+        AdminRole::OracleOperator,
+    ];
+
+    /// Returns PDA seed of the role signer set, admin multisig is stored at [b"multisig"]
+    pub fn get_seed(&self) -> &'static [u8] {
+        match self {
+            AdminRole::Admin => b"",
+            AdminRole::Guardian => b"guardian",
+            AdminRole::RiskManager => b"risk_manager",
+            AdminRole::Treasurer => b"treasurer",
+            AdminRole::OracleOperator => b"oracle_operator",
+        }
+    }
+
+    pub fn is_authorized(&self, instruction: AdminInstruction) -> bool {
+        match self {
+            AdminRole::Admin => true,
+            AdminRole::Guardian => matches!(instruction, AdminInstruction::SetPermissions),
+            AdminRole::RiskManager => matches!(
+                instruction,
+                AdminInstruction::SetCustodyRisk
+                    | AdminInstruction::SetBorrowRate
+                    | AdminInstruction::SetFeeTiers
+            ),
+            AdminRole::Treasurer => matches!(
+                instruction,
+                AdminInstruction::WithdrawFees | AdminInstruction::WithdrawSolFees
+            ),
+            AdminRole::OracleOperator => {
+                matches!(instruction, AdminInstruction::SetCustomOraclePrice)
+            }
+        }
+    }
+}
+
+impl TryFrom<u8> for AdminRole {
+    type Error = Error;
+
+    fn try_from(role: u8) -> Result<Self> {
+        match role {
+            0 => Ok(AdminRole::Admin),
+            1 => Ok(AdminRole::Guardian),
+            2 => Ok(AdminRole::RiskManager),
+            3 => Ok(AdminRole::Treasurer),
+            4 => Ok(AdminRole::OracleOperator),
+            _ => err!(PerpetualsError::MultisigAccountNotAuthorized),
+        }
+    }
+}
+
+impl DeprecatedMultisig {
+    pub const LEN: usize = 8 + std::mem::size_of::<DeprecatedMultisig>();
 }
 
 impl Multisig {
     pub const MAX_SIGNERS: usize = 6;
     pub const LEN: usize = 8 + std::mem::size_of::<Multisig>();
+    pub const MAX_TIMELOCK_DELAY: i64 = 30 * 86_400;
 
-    /// Returns instruction accounts and data hash.
-    /// Hash is not cryptographic and is meant to perform a fast check that admins are signing
-    /// the same instruction.
+    /// Returns SHA-256 hash of instruction accounts and data.
+    /// Instruction data is expected to end with AdminInstruction discriminator
+    /// (see get_instruction_data()).
     pub fn get_instruction_hash(
         instruction_accounts: &[AccountInfo],
         instruction_data: &[u8],
-    ) -> u64 {
-        let mut hasher = AHasher::default();
+    ) -> [u8; 32] {
+        let mut hasher = Hasher::default();
         for account in instruction_accounts {
-            hasher.write(account.key.as_ref());
+            hasher.hash(account.key.as_ref());
         }
         if !instruction_data.is_empty() {
-            hasher.write(instruction_data);
+            hasher.hash(instruction_data);
         }
-        hasher.finish()
+        hasher.result().to_bytes()
     }
 
     /// Returns all accounts for the given context
@@ -120,10 +220,13 @@ impl Multisig {
             min_signatures,
             instruction_accounts_len: 0,
             instruction_data_len: 0,
-            instruction_hash: 0,
+            instruction_hash: [0; 32],
             signers,
             signed,
             bump: self.bump,
+            timelock_delay: self.timelock_delay,
+            num_proposals: self.num_proposals,
+            role: self.role,
         };
 
         Ok(())
@@ -142,6 +245,14 @@ impl Multisig {
             return Err(ProgramError::MissingRequiredSignature.into());
         }
 
+        // approved proposals are executed on behalf of the timelock authority of the role
+        if signer_account.key == &Multisig::get_timelock_authority(self.get_role()?) {
+            return Ok(0);
+        }
+        if self.timelock_delay > 0 {
+            return err!(PerpetualsError::TimelockRequired);
+        }
+
         // find index of current signer or return error if not found
         let signer_idx = if let Ok(idx) = self.get_signer_index(signer_account.key) {
             idx
@@ -228,8 +339,59 @@ impl Multisig {
         err!(PerpetualsError::MultisigAccountNotAuthorized)
     }
 
+    pub fn get_role(&self) -> Result<AdminRole> {
+        AdminRole::try_from(self.role)
+    }
+
+    /// Checks if this signer set is allowed to sign the given instruction
+    pub fn is_authorized(&self, instruction: AdminInstruction) -> bool {
+        self.get_role()
+            .is_ok_and(|role| role.is_authorized(instruction))
+    }
+
+    /// Returns address of the PDA that signs admin instructions from executed proposals
+    /// of the role signer set
+    pub fn get_timelock_authority(role: AdminRole) -> Pubkey {
+        Pubkey::find_program_address(&[b"timelock_authority", role.get_seed()], &crate::ID).0
+    }
+
+    /// Returns address of the signer set of the given role
+    pub fn get_role_multisig_address(role: AdminRole) -> Pubkey {
+        Pubkey::find_program_address(&[b"multisig", role.get_seed()], &crate::ID).0
+    }
+
     /// Checks if provided account is one of multisig signers
     pub fn is_signer(&self, key: &Pubkey) -> Result<bool> {
         Ok(self.get_signer_index(key).is_ok())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_role_authorization() {
+        let multisig = Multisig {
+            role: AdminRole::Treasurer as u8,
+            ..Default::default()
+        };
+        assert_eq!(multisig.get_role().unwrap(), AdminRole::Treasurer);
+        assert!(multisig.is_authorized(AdminInstruction::WithdrawFees));
+        assert!(!multisig.is_authorized(AdminInstruction::SetCustodyConfig));
+
+        assert!(Multisig::default().is_authorized(AdminInstruction::SetAdminSigners));
+        assert!(AdminRole::Guardian.is_authorized(AdminInstruction::SetPermissions));
+        assert!(!AdminRole::Guardian.is_authorized(AdminInstruction::SetRoleSigners));
+        assert!(AdminRole::RiskManager.is_authorized(AdminInstruction::SetCustodyRisk));
+        assert!(!AdminRole::RiskManager.is_authorized(AdminInstruction::SetCustodyConfig));
+        assert!(AdminRole::OracleOperator.is_authorized(AdminInstruction::SetCustomOraclePrice));
+
+        let multisig = Multisig {
+            role: 10,
+            ..Default::default()
+        };
+        assert!(multisig.get_role().is_err());
+        assert!(!multisig.is_authorized(AdminInstruction::SetPermissions));
+    }
+}
@@ -1,15 +1,16 @@
 //! Oracle price service handling
 
 use {
-    crate::{error::PerpetualsError, math, state::perpetuals::Perpetuals},
+    crate::{
+        error::{map_pricing_error, PerpetualsError},
+        math,
+        state::perpetuals::Perpetuals,
+    },
     anchor_lang::prelude::*,
     core::cmp::Ordering,
+    perpetuals_pricing as pricing,
 };
 
-const ORACLE_EXPONENT_SCALE: i32 = -9;
-const ORACLE_PRICE_SCALE: u64 = 1_000_000_000;
-const ORACLE_MAX_PRICE: u64 = (1 << 28) - 1;
-
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
 pub enum OracleType {
     None,
@@ -63,20 +64,25 @@ impl CustomOracle {
 
 impl PartialOrd for OraclePrice {
     fn partial_cmp(&self, other: &OraclePrice) -> Option<Ordering> {
-        let (lhs, rhs) = if self.exponent == other.exponent {
-            (self.price, other.price)
-        } else if self.exponent < other.exponent {
-            if let Ok(scaled_price) = other.scale_to_exponent(self.exponent) {
-                (self.price, scaled_price.price)
-            } else {
-                return None;
-            }
-        } else if let Ok(scaled_price) = self.scale_to_exponent(other.exponent) {
-            (scaled_price.price, other.price)
-        } else {
-            return None;
-        };
-        lhs.partial_cmp(&rhs)
+        pricing::OraclePrice::from(*self).partial_cmp(&(*other).into())
+    }
+}
+
+impl From<OraclePrice> for pricing::OraclePrice {
+    fn from(price: OraclePrice) -> Self {
+        Self {
+            price: price.price,
+            exponent: price.exponent,
+        }
+    }
+}
+
+impl From<pricing::OraclePrice> for OraclePrice {
+    fn from(price: pricing::OraclePrice) -> Self {
+        Self {
+            price: price.price,
+            exponent: price.exponent,
+        }
     }
 }
 
@@ -120,87 +126,45 @@ impl OraclePrice {
 
     // Converts token amount to USD with implied USD_DECIMALS decimals using oracle price
     pub fn get_asset_amount_usd(&self, token_amount: u64, token_decimals: u8) -> Result<u64> {
-        if token_amount == 0 || self.price == 0 {
-            return Ok(0);
-        }
-        math::checked_decimal_mul(
-            token_amount,
-            -(token_decimals as i32),
-            self.price,
-            self.exponent,
-            -(Perpetuals::USD_DECIMALS as i32),
-        )
+        pricing::OraclePrice::from(*self)
+            .get_asset_amount_usd(token_amount, token_decimals)
+            .map_err(map_pricing_error)
     }
 
     // Converts USD amount with implied USD_DECIMALS decimals to token amount
     pub fn get_token_amount(&self, asset_amount_usd: u64, token_decimals: u8) -> Result<u64> {
-        if asset_amount_usd == 0 || self.price == 0 {
-            return Ok(0);
-        }
-        math::checked_decimal_div(
-            asset_amount_usd,
-            -(Perpetuals::USD_DECIMALS as i32),
-            self.price,
-            self.exponent,
-            -(token_decimals as i32),
-        )
+        pricing::OraclePrice::from(*self)
+            .get_token_amount(asset_amount_usd, token_decimals)
+            .map_err(map_pricing_error)
     }
 
     /// Returns price with mantissa normalized to be less than ORACLE_MAX_PRICE
     pub fn normalize(&self) -> Result<OraclePrice> {
-        let mut p = self.price;
-        let mut e = self.exponent;
-
-        while p > ORACLE_MAX_PRICE {
-            p = math::checked_div(p, 10)?;
-            e = math::checked_add(e, 1)?;
-        }
-
-        Ok(OraclePrice {
-            price: p,
-            exponent: e,
-        })
+        pricing::OraclePrice::from(*self)
+            .normalize()
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     pub fn checked_div(&self, other: &OraclePrice) -> Result<OraclePrice> {
-        let base = self.normalize()?;
-        let other = other.normalize()?;
-
-        Ok(OraclePrice {
-            price: math::checked_div(
-                math::checked_mul(base.price, ORACLE_PRICE_SCALE)?,
-                other.price,
-            )?,
-            exponent: math::checked_sub(
-                math::checked_add(base.exponent, ORACLE_EXPONENT_SCALE)?,
-                other.exponent,
-            )?,
-        })
+        pricing::OraclePrice::from(*self)
+            .checked_div(&(*other).into())
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     pub fn checked_mul(&self, other: &OraclePrice) -> Result<OraclePrice> {
-        Ok(OraclePrice {
-            price: math::checked_mul(self.price, other.price)?,
-            exponent: math::checked_add(self.exponent, other.exponent)?,
-        })
+        pricing::OraclePrice::from(*self)
+            .checked_mul(&(*other).into())
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     pub fn scale_to_exponent(&self, target_exponent: i32) -> Result<OraclePrice> {
-        if target_exponent == self.exponent {
-            return Ok(*self);
-        }
-        let delta = math::checked_sub(target_exponent, self.exponent)?;
-        if delta > 0 {
-            Ok(OraclePrice {
-                price: math::checked_div(self.price, math::checked_pow(10, delta as usize)?)?,
-                exponent: target_exponent,
-            })
-        } else {
-            Ok(OraclePrice {
-                price: math::checked_mul(self.price, math::checked_pow(10, (-delta) as usize)?)?,
-                exponent: target_exponent,
-            })
-        }
+        pricing::OraclePrice::from(*self)
+            .scale_to_exponent(target_exponent)
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     pub fn checked_as_f64(&self) -> Result<f64> {
@@ -211,30 +175,10 @@ impl OraclePrice {
     }
 
     pub fn get_min_price(&self, other: &OraclePrice, is_stable: bool) -> Result<OraclePrice> {
-        let min_price = if self < other { self } else { other };
-        if is_stable {
-            if min_price.exponent > 0 {
-                if min_price.price == 0 {
-                    return Ok(*min_price);
-                } else {
-                    return Ok(OraclePrice {
-                        price: 1000000u64,
-                        exponent: -6,
-                    });
-                }
-            }
-            let one_usd = math::checked_pow(10u64, (-min_price.exponent) as usize)?;
-            if min_price.price > one_usd {
-                Ok(OraclePrice {
-                    price: one_usd,
-                    exponent: min_price.exponent,
-                })
-            } else {
-                Ok(*min_price)
-            }
-        } else {
-            Ok(*min_price)
-        }
+        pricing::OraclePrice::from(*self)
+            .get_min_price(&(*other).into(), is_stable)
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     // private helpers
@@ -1,6 +1,17 @@
 use {
+    crate::error::PerpetualsError,
     anchor_lang::prelude::*,
-    anchor_spl::token::{Burn, MintTo, Transfer},
+    anchor_spl::{
+        token_2022::spl_token_2022::{
+            self,
+            extension::{
+                transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+                StateWithExtensions,
+            },
+        },
+        token_interface::{Burn, MintTo, TransferChecked},
+    },
+    perpetuals_pricing as pricing,
 };
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -20,6 +31,8 @@ pub struct NewPositionPricesAndFee {
     pub entry_price: u64,
     pub liquidation_price: u64,
     pub fee: u64,
+    // volume-based discount already applied to the fee
+    pub fee_discount: u64,
 }
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
@@ -65,15 +78,29 @@ impl anchor_lang::Id for Perpetuals {
     }
 }
 
+impl Permissions {
+    /// Returns true if no permission is enabled that is disabled in current
+    pub fn is_restriction_of(&self, current: &Permissions) -> bool {
+        (!self.allow_swap || current.allow_swap)
+            && (!self.allow_add_liquidity || current.allow_add_liquidity)
+            && (!self.allow_remove_liquidity || current.allow_remove_liquidity)
+            && (!self.allow_open_position || current.allow_open_position)
+            && (!self.allow_close_position || current.allow_close_position)
+            && (!self.allow_pnl_withdrawal || current.allow_pnl_withdrawal)
+            && (!self.allow_collateral_withdrawal || current.allow_collateral_withdrawal)
+            && (!self.allow_size_change || current.allow_size_change)
+    }
+}
+
 impl Perpetuals {
     pub const LEN: usize = 8 + std::mem::size_of::<Perpetuals>();
-    pub const BPS_DECIMALS: u8 = 4;
-    pub const BPS_POWER: u128 = 10u64.pow(Self::BPS_DECIMALS as u32) as u128;
-    pub const PRICE_DECIMALS: u8 = 6;
-    pub const USD_DECIMALS: u8 = 6;
+    pub const BPS_DECIMALS: u8 = pricing::BPS_DECIMALS;
+    pub const BPS_POWER: u128 = pricing::BPS_POWER;
+    pub const PRICE_DECIMALS: u8 = pricing::PRICE_DECIMALS;
+    pub const USD_DECIMALS: u8 = pricing::USD_DECIMALS;
     pub const LP_DECIMALS: u8 = Self::USD_DECIMALS;
-    pub const RATE_DECIMALS: u8 = 9;
-    pub const RATE_POWER: u128 = 10u64.pow(Self::RATE_DECIMALS as u32) as u128;
+    pub const RATE_DECIMALS: u8 = pricing::RATE_DECIMALS;
+    pub const RATE_POWER: u128 = pricing::RATE_POWER;
 
     pub fn validate(&self) -> bool {
         true
@@ -125,23 +152,26 @@ impl Perpetuals {
         from: AccountInfo<'info>,
         to: AccountInfo<'info>,
         authority: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
         token_program: AccountInfo<'info>,
         amount: u64,
     ) -> Result<()> {
         let authority_seeds: &[&[&[u8]]] =
             &[&[b"transfer_authority", &[self.transfer_authority_bump]]];
 
+        let decimals = Self::get_mint_decimals(&mint)?;
         let context = CpiContext::new(
             token_program,
-            Transfer {
+            TransferChecked {
                 from,
+                mint,
                 to,
                 authority,
             },
         )
         .with_signer(authority_seeds);
 
-        anchor_spl::token::transfer(context, amount)
+        anchor_spl::token_interface::transfer_checked(context, amount, decimals)
     }
 
     pub fn transfer_tokens_from_user<'info>(
@@ -149,18 +179,21 @@ impl Perpetuals {
         from: AccountInfo<'info>,
         to: AccountInfo<'info>,
         authority: AccountInfo<'info>,
+        mint: AccountInfo<'info>,
         token_program: AccountInfo<'info>,
         amount: u64,
     ) -> Result<()> {
+        let decimals = Self::get_mint_decimals(&mint)?;
         let context = CpiContext::new(
             token_program,
-            Transfer {
+            TransferChecked {
                 from,
+                mint,
                 to,
                 authority,
             },
         );
-        anchor_spl::token::transfer(context, amount)
+        anchor_spl::token_interface::transfer_checked(context, amount, decimals)
     }
 
     pub fn mint_tokens<'info>(
@@ -184,7 +217,7 @@ impl Perpetuals {
         )
         .with_signer(authority_seeds);
 
-        anchor_spl::token::mint_to(context, amount)
+        anchor_spl::token_interface::mint_to(context, amount)
     }
 
     pub fn burn_tokens<'info>(
@@ -204,7 +237,62 @@ impl Perpetuals {
             },
         );
 
-        anchor_spl::token::burn(context, amount)
+        anchor_spl::token_interface::burn(context, amount)
+    }
+
+    pub fn get_mint_decimals(mint: &AccountInfo) -> Result<u8> {
+        let mint_data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+        Ok(mint_state.base.decimals)
+    }
+
+    /// Returns the amount withheld by the Token-2022 transfer fee extension
+    /// when `amount` tokens of the mint are transferred
+    pub fn get_transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+        if mint.owner != &spl_token_2022::ID {
+            return Ok(0);
+        }
+        Self::get_epoch_transfer_fee(mint, Clock::get()?.epoch, amount)
+    }
+
+    /// Same as `get_transfer_fee` at the given epoch, for off-chain simulations
+    pub fn get_epoch_transfer_fee(mint: &AccountInfo, epoch: u64, amount: u64) -> Result<u64> {
+        if mint.owner != &spl_token_2022::ID {
+            return Ok(0);
+        }
+        let mint_data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+        if let Ok(transfer_fee_config) = mint_state.get_extension::<TransferFeeConfig>() {
+            transfer_fee_config
+                .calculate_epoch_fee(epoch, amount)
+                .ok_or_else(|| PerpetualsError::MathOverflow.into())
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Returns false if the mint has Token-2022 extensions that break custody
+    /// accounting, e.g. non-transferable, confidential or interest-bearing mints
+    pub fn is_supported_mint(mint: &AccountInfo) -> Result<bool> {
+        if mint.owner != &spl_token_2022::ID {
+            return Ok(true);
+        }
+        let mint_data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+
+        // extensions unknown to this program version fail to parse
+        if let Ok(extensions) = mint_state.get_extension_types() {
+            Ok(extensions.iter().all(|extension| {
+                matches!(
+                    extension,
+                    ExtensionType::TransferFeeConfig | ExtensionType::MintCloseAuthority
+                )
+            }))
+        } else {
+            Ok(false)
+        }
     }
 
     pub fn is_empty_account(account_info: &AccountInfo) -> Result<bool> {
@@ -218,14 +306,14 @@ impl Perpetuals {
         authority: AccountInfo<'info>,
         seeds: &[&[&[u8]]],
     ) -> Result<()> {
-        let cpi_accounts = anchor_spl::token::CloseAccount {
+        let cpi_accounts = anchor_spl::token_interface::CloseAccount {
             account: token_account,
             destination: receiver,
             authority,
         };
         let cpi_context = anchor_lang::context::CpiContext::new(token_program, cpi_accounts);
 
-        anchor_spl::token::close_account(cpi_context.with_signer(seeds))
+        anchor_spl::token_interface::close_account(cpi_context.with_signer(seeds))
     }
 
     pub fn transfer_sol_from_owned<'a>(
@@ -1,16 +1,16 @@
 use {
     crate::{
-        error::PerpetualsError,
+        error::{map_pricing_error, PerpetualsError},
         math,
         state::{
-            custody::{Custody, FeesMode},
+            custody::Custody,
             oracle::OraclePrice,
             perpetuals::Perpetuals,
             position::{Position, Side},
         },
     },
     anchor_lang::prelude::*,
-    std::cmp::Ordering,
+    perpetuals_pricing as pricing,
 };
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
@@ -28,6 +28,43 @@ pub struct TokenRatios {
     pub max: u64,
 }
 
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct FeeTier {
+    // trader's rolling volume required to qualify, implied USD_DECIMALS decimals
+    pub min_volume_usd: u64,
+    // open/close fee discount, implied BPS_DECIMALS decimals
+    pub discount: u64,
+}
+
+/// Price, size and entry fee of a new position
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct EntryAmounts {
+    pub price: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub locked_amount: u64,
+    // fee amount is in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+}
+
+/// Settlement of a closed or liquidated position
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug)]
+pub struct ExitAmounts {
+    pub price: u64,
+    // amount returned to the owner, in collateral tokens
+    pub amount_out: u64,
+    // liquidation reward, in collateral tokens
+    pub reward: u64,
+    // fee amount is in collateral tokens
+    pub fee_amount: u64,
+    pub fee_amount_usd: u64,
+    pub interest_usd: u64,
+    // net of fees and interest
+    pub profit_usd: u64,
+    pub loss_usd: u64,
+}
+
 #[account]
 #[derive(Default, Debug)]
 pub struct Pool {
@@ -39,6 +76,11 @@ pub struct Pool {
     pub bump: u8,
     pub lp_token_bump: u8,
     pub inception_time: i64,
+    // SPL Token or Token-2022 program of the LP token mint
+    pub lp_token_program: Pubkey,
+
+    // volume-based fee tiers, sorted by min_volume_usd
+    pub fee_tiers: Vec<FeeTier>,
 }
 
 impl TokenRatios {
@@ -51,12 +93,19 @@ impl TokenRatios {
     }
 }
 
+impl FeeTier {
+    pub fn validate(&self) -> bool {
+        (self.discount as u128) <= Perpetuals::BPS_POWER
+    }
+}
+
 /// Token Pool
 /// All returned prices are scaled to PRICE_DECIMALS.
 /// All returned amounts are scaled to corresponding custody decimals.
 ///
 impl Pool {
     pub const LEN: usize = 8 + 64 + std::mem::size_of::<Pool>();
+    pub const MAX_FEE_TIERS: usize = 8;
 
     pub fn validate(&self) -> bool {
         for ratio in &self.ratios {
@@ -65,6 +114,17 @@ impl Pool {
             }
         }
 
+        // check fee tiers are valid and strictly ordered by volume
+        if self.fee_tiers.len() > Pool::MAX_FEE_TIERS
+            || self.fee_tiers.iter().any(|tier| !tier.validate())
+            || self
+                .fee_tiers
+                .windows(2)
+                .any(|w| w[0].min_volume_usd >= w[1].min_volume_usd)
+        {
+            return false;
+        }
+
         // check target ratios add up to 1
         if !self.ratios.is_empty()
             && self
@@ -99,23 +159,23 @@ impl Pool {
         token_price: &OraclePrice,
         token_ema_price: &OraclePrice,
         side: Side,
+        size: u64,
         custody: &Custody,
     ) -> Result<u64> {
-        let price = self.get_price(
-            token_price,
-            token_ema_price,
-            side,
-            if side == Side::Long {
-                custody.pricing.trade_spread_long
-            } else {
-                custody.pricing.trade_spread_short
-            },
-        )?;
-        require_gt!(price.price, 0, PerpetualsError::MaxPriceSlippage);
+        pricing::Pool::from(self)
+            .get_entry_price(
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                side.into(),
+                size,
+                &custody.into(),
+            )
+            .map_err(map_pricing_error)
+    }
 
-        Ok(price
-            .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
-            .price)
+    /// Returns open/close fee discount for the given trader's rolling volume
+    pub fn get_fee_discount(&self, volume_usd: u64) -> u64 {
+        pricing::Pool::from(self).get_fee_discount(volume_usd)
     }
 
     pub fn get_entry_fee(
@@ -124,53 +184,17 @@ impl Pool {
         size: u64,
         locked_amount: u64,
         collateral_custody: &Custody,
+        fee_discount: u64,
     ) -> Result<u64> {
-        // The "optimal" algorithm is always used to compute the fee for entering a position.
-        // entry_fee = custody.fees.open_position * utilization_fee * size
-        // where utilization_fee = 1 + custody.fees.utilization_mult * (new_utilization - optimal_utilization) / (1 - optimal_utilization);
-
-        let mut size_fee = Self::get_fee_amount(base_fee, size)?;
-
-        let new_utilization = if collateral_custody.assets.owned > 0 {
-            // utilization = (assets_locked + locked_amount) / assets_owned
-            std::cmp::min(
-                Perpetuals::RATE_POWER,
-                math::checked_div(
-                    math::checked_mul(
-                        math::checked_add(collateral_custody.assets.locked, locked_amount)? as u128,
-                        Perpetuals::RATE_POWER,
-                    )?,
-                    collateral_custody.assets.owned as u128,
-                )?,
+        pricing::Pool::from(self)
+            .get_entry_fee(
+                base_fee,
+                size,
+                locked_amount,
+                &collateral_custody.into(),
+                fee_discount,
             )
-        } else {
-            Perpetuals::RATE_POWER
-        };
-
-        if new_utilization > collateral_custody.borrow_rate.optimal_utilization as u128 {
-            let utilization_fee = math::checked_add(
-                Perpetuals::BPS_POWER,
-                math::checked_div(
-                    math::checked_mul(
-                        collateral_custody.fees.utilization_mult as u128,
-                        math::checked_sub(
-                            new_utilization,
-                            collateral_custody.borrow_rate.optimal_utilization as u128,
-                        )?,
-                    )?,
-                    math::checked_sub(
-                        Perpetuals::RATE_POWER,
-                        collateral_custody.borrow_rate.optimal_utilization as u128,
-                    )?,
-                )?,
-            )?;
-            size_fee = math::checked_as_u64(math::checked_div(
-                math::checked_mul(size_fee as u128, utilization_fee)?,
-                Perpetuals::BPS_POWER,
-            )?)?;
-        }
-
-        Ok(size_fee)
+            .map_err(map_pricing_error)
     }
 
     pub fn get_exit_price(
@@ -178,34 +202,195 @@ impl Pool {
         token_price: &OraclePrice,
         token_ema_price: &OraclePrice,
         side: Side,
+        size_usd: u64,
         custody: &Custody,
     ) -> Result<u64> {
-        let price = self.get_price(
-            token_price,
-            token_ema_price,
-            if side == Side::Long {
-                Side::Short
-            } else {
-                Side::Long
-            },
-            if side == Side::Long {
-                custody.pricing.trade_spread_short
+        pricing::Pool::from(self)
+            .get_exit_price(
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                side.into(),
+                size_usd,
+                &custody.into(),
+            )
+            .map_err(map_pricing_error)
+    }
+
+    pub fn get_exit_fee(&self, size: u64, custody: &Custody, fee_discount: u64) -> Result<u64> {
+        pricing::Pool::from(self)
+            .get_exit_fee(size, &custody.into(), fee_discount)
+            .map_err(map_pricing_error)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_close_amount(
+        &self,
+        position: &Position,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        curtime: i64,
+        liquidation: bool,
+        fee_discount: u64,
+    ) -> Result<(u64, u64, u64, u64)> {
+        pricing::Pool::from(self)
+            .get_close_amount(
+                &position.into(),
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                &custody.into(),
+                &(*collateral_token_price).into(),
+                &(*collateral_token_ema_price).into(),
+                &collateral_custody.into(),
+                curtime,
+                liquidation,
+                fee_discount,
+            )
+            .map_err(map_pricing_error)
+    }
+
+    /// Prices a new position of `size` tokens, shared by `open_position` and
+    /// off-chain simulations
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_entry_amounts(
+        &self,
+        side: Side,
+        size: u64,
+        token_price: &OraclePrice,
+        token_ema_price: &OraclePrice,
+        custody: &Custody,
+        collateral_token_price: &OraclePrice,
+        collateral_token_ema_price: &OraclePrice,
+        collateral_custody: &Custody,
+        fee_discount: u64,
+    ) -> Result<EntryAmounts> {
+        let use_collateral_custody = side == Side::Short || custody.is_virtual;
+        let min_collateral_price = collateral_token_price
+            .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?;
+
+        let price = self.get_entry_price(token_price, token_ema_price, side, size, custody)?;
+
+        let position_oracle_price = OraclePrice {
+            price,
+            exponent: -(Perpetuals::PRICE_DECIMALS as i32),
+        };
+        let size_usd = position_oracle_price.get_asset_amount_usd(size, custody.decimals)?;
+        let locked_amount = if use_collateral_custody {
+            custody.get_locked_amount(
+                min_collateral_price.get_token_amount(size_usd, collateral_custody.decimals)?,
+                side,
+            )?
+        } else {
+            custody.get_locked_amount(size, side)?
+        };
+
+        let borrow_size_usd = if custody.pricing.max_payoff_mult as u128 != Perpetuals::BPS_POWER {
+            if use_collateral_custody {
+                let max_collateral_price = if collateral_token_price < collateral_token_ema_price {
+                    collateral_token_ema_price
+                } else {
+                    collateral_token_price
+                };
+                max_collateral_price
+                    .get_asset_amount_usd(locked_amount, collateral_custody.decimals)?
             } else {
-                custody.pricing.trade_spread_long
-            },
+                position_oracle_price.get_asset_amount_usd(locked_amount, custody.decimals)?
+            }
+        } else {
+            size_usd
+        };
+
+        let mut fee_amount = self.get_entry_fee(
+            custody.fees.open_position,
+            size,
+            locked_amount,
+            collateral_custody,
+            fee_discount,
         )?;
+        let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+        if use_collateral_custody {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        }
 
-        Ok(price
-            .scale_to_exponent(-(Perpetuals::PRICE_DECIMALS as i32))?
-            .price)
+        Ok(EntryAmounts {
+            price,
+            size_usd,
+            borrow_size_usd,
+            locked_amount,
+            fee_amount,
+            fee_amount_usd,
+        })
     }
 
-    pub fn get_exit_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
-        Self::get_fee_amount(custody.fees.close_position, size)
+    /// Locks funds for the new position and records it in custody stats,
+    /// `position.collateral_amount` must be net of token transfer fees
+    pub fn add_position(
+        &self,
+        position: &Position,
+        entry: &EntryAmounts,
+        token_ema_price: &OraclePrice,
+        custody: &mut Custody,
+        collateral_custody: &mut Custody,
+        curtime: i64,
+    ) -> Result<()> {
+        // lock funds for potential profit payoff
+        collateral_custody.lock_funds(position.locked_amount)?;
+
+        collateral_custody.collected_fees.open_position_usd = collateral_custody
+            .collected_fees
+            .open_position_usd
+            .wrapping_add(entry.fee_amount_usd);
+
+        collateral_custody.assets.collateral = math::checked_add(
+            collateral_custody.assets.collateral,
+            position.collateral_amount,
+        )?;
+
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, entry.fee_amount)?;
+        collateral_custody.assets.protocol_fees =
+            math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        let same_custody = position.side == Side::Long && !custody.is_virtual;
+        let stats_custody = if same_custody {
+            &mut *collateral_custody
+        } else {
+            &mut *custody
+        };
+
+        stats_custody.volume_stats.open_position_usd = stats_custody
+            .volume_stats
+            .open_position_usd
+            .wrapping_add(position.size_usd);
+
+        if position.side == Side::Long {
+            stats_custody.trade_stats.oi_long_usd =
+                math::checked_add(stats_custody.trade_stats.oi_long_usd, position.size_usd)?;
+        } else {
+            stats_custody.trade_stats.oi_short_usd =
+                math::checked_add(stats_custody.trade_stats.oi_short_usd, position.size_usd)?;
+        }
+
+        if same_custody {
+            collateral_custody.add_position(position, token_ema_price, curtime, None)?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            *custody = collateral_custody.clone();
+        } else {
+            custody.add_position(position, token_ema_price, curtime, Some(collateral_custody))?;
+            collateral_custody.update_borrow_rate(curtime)?;
+        }
+
+        Ok(())
     }
 
+    /// Settles a position at the current exit price, shared by `close_position`,
+    /// `liquidate` and off-chain simulations
     #[allow(clippy::too_many_arguments)]
-    pub fn get_close_amount(
+    pub fn get_exit_amounts(
         &self,
         position: &Position,
         token_price: &OraclePrice,
@@ -216,8 +401,17 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
-    ) -> Result<(u64, u64, u64, u64)> {
-        let (profit_usd, loss_usd, fee_amount) = self.get_pnl_usd(
+        fee_discount: u64,
+    ) -> Result<ExitAmounts> {
+        let price = self.get_exit_price(
+            token_price,
+            token_ema_price,
+            position.side,
+            position.size_usd,
+            custody,
+        )?;
+
+        let (total_amount_out, mut fee_amount, profit_usd, loss_usd) = self.get_close_amount(
             position,
             token_price,
             token_ema_price,
@@ -227,34 +421,143 @@ impl Pool {
             collateral_custody,
             curtime,
             liquidation,
+            fee_discount,
         )?;
 
-        let available_amount_usd = if profit_usd > 0 {
-            math::checked_add(position.collateral_usd, profit_usd)?
-        } else if loss_usd < position.collateral_usd {
-            math::checked_sub(position.collateral_usd, loss_usd)?
+        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
+        let fee_amount_usd = token_ema_price.get_asset_amount_usd(fee_amount, custody.decimals)?;
+        if position.side == Side::Short || custody.is_virtual {
+            fee_amount = collateral_token_ema_price
+                .get_token_amount(fee_amount_usd, collateral_custody.decimals)?;
+        }
+
+        let reward = if liquidation {
+            Pool::get_fee_amount(custody.fees.liquidation, total_amount_out)?
         } else {
             0
         };
 
-        let max_collateral_price = if collateral_token_price > collateral_token_ema_price {
-            collateral_token_price
+        Ok(ExitAmounts {
+            price,
+            amount_out: math::checked_sub(total_amount_out, reward)?,
+            reward,
+            fee_amount,
+            fee_amount_usd,
+            interest_usd,
+            profit_usd,
+            loss_usd,
+        })
+    }
+
+    /// Unlocks funds of the settled position and removes it from custody stats
+    pub fn remove_position(
+        &self,
+        position: &Position,
+        exit: &ExitAmounts,
+        custody: &mut Custody,
+        collateral_custody: &mut Custody,
+        curtime: i64,
+        liquidation: bool,
+    ) -> Result<()> {
+        let total_amount_out = math::checked_add(exit.amount_out, exit.reward)?;
+
+        // unlock pool funds
+        collateral_custody.unlock_funds(position.locked_amount)?;
+
+        // check pool constraints
+        require!(
+            self.check_available_amount(total_amount_out, collateral_custody)?,
+            PerpetualsError::CustodyAmountLimit
+        );
+
+        if liquidation {
+            collateral_custody.collected_fees.liquidation_usd = collateral_custody
+                .collected_fees
+                .liquidation_usd
+                .wrapping_add(exit.fee_amount_usd);
         } else {
-            collateral_token_ema_price
-        };
-        let close_amount = max_collateral_price
-            .get_token_amount(available_amount_usd, collateral_custody.decimals)?;
-        let max_amount = math::checked_add(
-            position.locked_amount.saturating_sub(fee_amount),
+            collateral_custody.collected_fees.close_position_usd = collateral_custody
+                .collected_fees
+                .close_position_usd
+                .wrapping_add(exit.fee_amount_usd);
+        }
+
+        if total_amount_out > position.collateral_amount {
+            let amount_lost = total_amount_out.saturating_sub(position.collateral_amount);
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, amount_lost)?;
+        } else {
+            let amount_gained = position.collateral_amount.saturating_sub(total_amount_out);
+            collateral_custody.assets.owned =
+                math::checked_add(collateral_custody.assets.owned, amount_gained)?;
+        }
+        collateral_custody.assets.collateral = math::checked_sub(
+            collateral_custody.assets.collateral,
             position.collateral_amount,
         )?;
 
-        Ok((
-            std::cmp::min(max_amount, close_amount),
-            fee_amount,
-            profit_usd,
-            loss_usd,
-        ))
+        let protocol_fee = Pool::get_fee_amount(custody.fees.protocol_share, exit.fee_amount)?;
+
+        // Pay protocol_fee from custody if possible, otherwise no protocol_fee
+        if self.check_available_amount(protocol_fee, collateral_custody)? {
+            collateral_custody.assets.protocol_fees =
+                math::checked_add(collateral_custody.assets.protocol_fees, protocol_fee)?;
+
+            collateral_custody.assets.owned =
+                math::checked_sub(collateral_custody.assets.owned, protocol_fee)?;
+        }
+
+        // if custody and collateral_custody accounts are the same, ensure that data is in sync
+        let same_custody = position.side == Side::Long && !custody.is_virtual;
+        let stats_custody = if same_custody {
+            &mut *collateral_custody
+        } else {
+            &mut *custody
+        };
+
+        if liquidation {
+            stats_custody.volume_stats.liquidation_usd = math::checked_add(
+                stats_custody.volume_stats.liquidation_usd,
+                position.size_usd,
+            )?;
+        } else {
+            stats_custody.volume_stats.close_position_usd = stats_custody
+                .volume_stats
+                .close_position_usd
+                .wrapping_add(position.size_usd);
+        }
+
+        if position.side == Side::Long {
+            stats_custody.trade_stats.oi_long_usd = stats_custody
+                .trade_stats
+                .oi_long_usd
+                .saturating_sub(position.size_usd);
+        } else {
+            stats_custody.trade_stats.oi_short_usd = stats_custody
+                .trade_stats
+                .oi_short_usd
+                .saturating_sub(position.size_usd);
+        }
+
+        stats_custody.trade_stats.profit_usd = stats_custody
+            .trade_stats
+            .profit_usd
+            .wrapping_add(exit.profit_usd);
+        stats_custody.trade_stats.loss_usd = stats_custody
+            .trade_stats
+            .loss_usd
+            .wrapping_add(exit.loss_usd);
+
+        if same_custody {
+            collateral_custody.remove_position(position, curtime, None)?;
+            collateral_custody.update_borrow_rate(curtime)?;
+            *custody = collateral_custody.clone();
+        } else {
+            custody.remove_position(position, curtime, Some(collateral_custody))?;
+            collateral_custody.update_borrow_rate(curtime)?;
+        }
+
+        Ok(())
     }
 
     pub fn get_swap_price(
@@ -265,26 +568,16 @@ impl Pool {
         token_out_ema_price: &OraclePrice,
         custody_in: &Custody,
     ) -> Result<OraclePrice> {
-        let min_price = if token_in_price < token_in_ema_price {
-            token_in_price
-        } else {
-            token_in_ema_price
-        };
-
-        let max_price = if token_out_price > token_out_ema_price {
-            token_out_price
-        } else {
-            token_out_ema_price
-        };
-
-        let pair_price = min_price.checked_div(max_price)?;
-
-        self.get_price(
-            &pair_price,
-            &pair_price,
-            Side::Short,
-            custody_in.pricing.swap_spread,
-        )
+        pricing::Pool::from(self)
+            .get_swap_price(
+                &(*token_in_price).into(),
+                &(*token_in_ema_price).into(),
+                &(*token_out_price).into(),
+                &(*token_out_ema_price).into(),
+                &custody_in.into(),
+            )
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -298,21 +591,17 @@ impl Pool {
         custody_out: &Custody,
         amount_in: u64,
     ) -> Result<u64> {
-        let swap_price = self.get_swap_price(
-            token_in_price,
-            token_in_ema_price,
-            token_out_price,
-            token_out_ema_price,
-            custody_in,
-        )?;
-
-        math::checked_decimal_mul(
-            amount_in,
-            -(custody_in.decimals as i32),
-            swap_price.price,
-            swap_price.exponent,
-            -(custody_out.decimals as i32),
-        )
+        pricing::Pool::from(self)
+            .get_swap_amount(
+                &(*token_in_price).into(),
+                &(*token_in_ema_price).into(),
+                &(*token_out_price).into(),
+                &(*token_out_ema_price).into(),
+                &custody_in.into(),
+                &custody_out.into(),
+                amount_in,
+            )
+            .map_err(map_pricing_error)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -327,35 +616,18 @@ impl Pool {
         custody_out: &Custody,
         token_price_out: &OraclePrice,
     ) -> Result<(u64, u64)> {
-        let stable_swap = custody_in.is_stable && custody_out.is_stable;
-
-        let swap_in_fee = self.get_fee(
-            token_id_in,
-            if stable_swap {
-                custody_in.fees.stable_swap_in
-            } else {
-                custody_in.fees.swap_in
-            },
-            amount_in,
-            0u64,
-            custody_in,
-            token_price_in,
-        )?;
-
-        let swap_out_fee = self.get_fee(
-            token_id_out,
-            if stable_swap {
-                custody_out.fees.stable_swap_out
-            } else {
-                custody_out.fees.swap_out
-            },
-            0u64,
-            amount_out,
-            custody_out,
-            token_price_out,
-        )?;
-
-        Ok((swap_in_fee, swap_out_fee))
+        pricing::Pool::from(self)
+            .get_swap_fees(
+                token_id_in,
+                token_id_out,
+                amount_in,
+                amount_out,
+                &custody_in.into(),
+                &(*token_price_in).into(),
+                &custody_out.into(),
+                &(*token_price_out).into(),
+            )
+            .map_err(map_pricing_error)
     }
 
     pub fn get_add_liquidity_fee(
@@ -365,14 +637,9 @@ impl Pool {
         custody: &Custody,
         token_price: &OraclePrice,
     ) -> Result<u64> {
-        self.get_fee(
-            token_id,
-            custody.fees.add_liquidity,
-            amount,
-            0u64,
-            custody,
-            token_price,
-        )
+        pricing::Pool::from(self)
+            .get_add_liquidity_fee(token_id, amount, &custody.into(), &(*token_price).into())
+            .map_err(map_pricing_error)
     }
 
     pub fn get_remove_liquidity_fee(
@@ -382,18 +649,15 @@ impl Pool {
         custody: &Custody,
         token_price: &OraclePrice,
     ) -> Result<u64> {
-        self.get_fee(
-            token_id,
-            custody.fees.remove_liquidity,
-            0u64,
-            amount,
-            custody,
-            token_price,
-        )
+        pricing::Pool::from(self)
+            .get_remove_liquidity_fee(token_id, amount, &custody.into(), &(*token_price).into())
+            .map_err(map_pricing_error)
     }
 
     pub fn get_liquidation_fee(&self, size: u64, custody: &Custody) -> Result<u64> {
-        Self::get_fee_amount(custody.fees.liquidation, size)
+        pricing::Pool::from(self)
+            .get_liquidation_fee(size, &custody.into())
+            .map_err(map_pricing_error)
     }
 
     pub fn check_token_ratio(
@@ -404,23 +668,21 @@ impl Pool {
         custody: &Custody,
         token_price: &OraclePrice,
     ) -> Result<bool> {
-        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
-
-        if new_ratio < self.ratios[token_id].min {
-            Ok(new_ratio >= self.get_current_ratio(custody, token_price)?)
-        } else if new_ratio > self.ratios[token_id].max {
-            Ok(new_ratio <= self.get_current_ratio(custody, token_price)?)
-        } else {
-            Ok(true)
-        }
+        pricing::Pool::from(self)
+            .check_token_ratio(
+                token_id,
+                amount_add,
+                amount_remove,
+                &custody.into(),
+                &(*token_price).into(),
+            )
+            .map_err(map_pricing_error)
     }
 
     pub fn check_available_amount(&self, amount: u64, custody: &Custody) -> Result<bool> {
-        let available_amount = math::checked_sub(
-            math::checked_add(custody.assets.owned, custody.assets.collateral)?,
-            custody.assets.locked,
-        )?;
-        Ok(available_amount >= amount)
+        pricing::Pool::from(self)
+            .check_available_amount(amount, &custody.into())
+            .map_err(map_pricing_error)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -435,34 +697,18 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
     ) -> Result<u64> {
-        let (profit_usd, loss_usd, _) = self.get_pnl_usd(
-            position,
-            token_price,
-            token_ema_price,
-            custody,
-            collateral_token_price,
-            collateral_token_ema_price,
-            collateral_custody,
-            curtime,
-            false,
-        )?;
-
-        let current_margin_usd = if profit_usd > 0 {
-            math::checked_add(position.collateral_usd, profit_usd)?
-        } else if loss_usd <= position.collateral_usd {
-            math::checked_sub(position.collateral_usd, loss_usd)?
-        } else {
-            0
-        };
-
-        if current_margin_usd > 0 {
-            math::checked_as_u64(math::checked_div(
-                math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
-                current_margin_usd as u128,
-            )?)
-        } else {
-            Ok(u64::MAX)
-        }
+        pricing::Pool::from(self)
+            .get_leverage(
+                &position.into(),
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                &custody.into(),
+                &(*collateral_token_price).into(),
+                &(*collateral_token_ema_price).into(),
+                &collateral_custody.into(),
+                curtime,
+            )
+            .map_err(map_pricing_error)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -478,21 +724,19 @@ impl Pool {
         curtime: i64,
         initial: bool,
     ) -> Result<bool> {
-        let current_leverage = self.get_leverage(
-            position,
-            token_price,
-            token_ema_price,
-            custody,
-            collateral_token_price,
-            collateral_token_ema_price,
-            collateral_custody,
-            curtime,
-        )?;
-
-        Ok(current_leverage <= custody.pricing.max_leverage
-            && (!initial
-                || (current_leverage >= custody.pricing.min_initial_leverage
-                    && current_leverage <= custody.pricing.max_initial_leverage)))
+        pricing::Pool::from(self)
+            .check_leverage(
+                &position.into(),
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                &custody.into(),
+                &(*collateral_token_price).into(),
+                &(*collateral_token_ema_price).into(),
+                &collateral_custody.into(),
+                curtime,
+                initial,
+            )
+            .map_err(map_pricing_error)
     }
 
     pub fn get_liquidation_price(
@@ -503,71 +747,15 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
     ) -> Result<u64> {
-        // liq_price = pos_price +- (collateral + unreal_profit - unreal_loss - exit_fee - interest - size/max_leverage) * pos_price / size
-
-        if position.size_usd == 0 || position.price == 0 {
-            return Ok(0);
-        }
-
-        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
-        let exit_fee_tokens = self.get_exit_fee(size, custody)?;
-        let exit_fee_usd =
-            token_ema_price.get_asset_amount_usd(exit_fee_tokens, custody.decimals)?;
-        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
-        let unrealized_loss_usd = math::checked_add(
-            math::checked_add(exit_fee_usd, interest_usd)?,
-            position.unrealized_loss_usd,
-        )?;
-
-        let max_loss_usd = math::checked_as_u64(math::checked_div(
-            math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER)?,
-            custody.pricing.max_leverage as u128,
-        )?)?;
-        let max_loss_usd = math::checked_add(max_loss_usd, unrealized_loss_usd)?;
-
-        let margin_usd =
-            math::checked_add(position.collateral_usd, position.unrealized_profit_usd)?;
-
-        let max_price_diff = if max_loss_usd >= margin_usd {
-            math::checked_sub(max_loss_usd, margin_usd)?
-        } else {
-            math::checked_sub(margin_usd, max_loss_usd)?
-        };
-
-        let position_price = math::scale_to_exponent(
-            position.price,
-            -(Perpetuals::PRICE_DECIMALS as i32),
-            -(Perpetuals::USD_DECIMALS as i32),
-        )?;
-
-        let max_price_diff = math::checked_as_u64(math::checked_div(
-            math::checked_mul(max_price_diff as u128, position_price as u128)?,
-            position.size_usd as u128,
-        )?)?;
-
-        let max_price_diff = math::scale_to_exponent(
-            max_price_diff,
-            -(Perpetuals::USD_DECIMALS as i32),
-            -(Perpetuals::PRICE_DECIMALS as i32),
-        )?;
-
-        if position.side == Side::Long {
-            if max_loss_usd >= margin_usd {
-                math::checked_add(position.price, max_price_diff)
-            } else if position.price > max_price_diff {
-                math::checked_sub(position.price, max_price_diff)
-            } else {
-                Ok(0)
-            }
-        } else if max_loss_usd >= margin_usd {
-            if position.price > max_price_diff {
-                math::checked_sub(position.price, max_price_diff)
-            } else {
-                Ok(0)
-            }
-        } else {
-            math::checked_add(position.price, max_price_diff)
-        }
+        pricing::Pool::from(self)
+            .get_liquidation_price(
+                &position.into(),
+                &(*token_ema_price).into(),
+                &custody.into(),
+                &collateral_custody.into(),
+                curtime,
+            )
+            .map_err(map_pricing_error)
     }
 
     // returns (profit_usd, loss_usd, fee_amount)
@@ -583,127 +771,22 @@ impl Pool {
         collateral_custody: &Custody,
         curtime: i64,
         liquidation: bool,
+        fee_discount: u64,
     ) -> Result<(u64, u64, u64)> {
-        if position.size_usd == 0 || position.price == 0 {
-            return Ok((0, 0, 0));
-        }
-
-        let exit_price =
-            self.get_exit_price(token_price, token_ema_price, position.side, custody)?;
-
-        let size = token_ema_price.get_token_amount(position.size_usd, custody.decimals)?;
-
-        let exit_fee = if liquidation {
-            self.get_liquidation_fee(size, custody)?
-        } else {
-            self.get_exit_fee(size, custody)?
-        };
-
-        let exit_fee_usd = token_ema_price.get_asset_amount_usd(exit_fee, custody.decimals)?;
-        let interest_usd = collateral_custody.get_interest_amount_usd(position, curtime)?;
-        let unrealized_loss_usd = math::checked_add(
-            math::checked_add(exit_fee_usd, interest_usd)?,
-            position.unrealized_loss_usd,
-        )?;
-
-        let (price_diff_profit, price_diff_loss) = if position.side == Side::Long {
-            if exit_price > position.price {
-                (math::checked_sub(exit_price, position.price)?, 0u64)
-            } else {
-                (0u64, math::checked_sub(position.price, exit_price)?)
-            }
-        } else if exit_price < position.price {
-            (math::checked_sub(position.price, exit_price)?, 0u64)
-        } else {
-            (0u64, math::checked_sub(exit_price, position.price)?)
-        };
-
-        let position_price = math::scale_to_exponent(
-            position.price,
-            -(Perpetuals::PRICE_DECIMALS as i32),
-            -(Perpetuals::USD_DECIMALS as i32),
-        )?;
-
-        if price_diff_profit > 0 {
-            let potential_profit_usd = math::checked_as_u64(math::checked_div(
-                math::checked_mul(position.size_usd as u128, price_diff_profit as u128)?,
-                position_price as u128,
-            )?)?;
-
-            let potential_profit_usd =
-                math::checked_add(potential_profit_usd, position.unrealized_profit_usd)?;
-
-            if potential_profit_usd >= unrealized_loss_usd {
-                let cur_profit_usd = math::checked_sub(potential_profit_usd, unrealized_loss_usd)?;
-                let min_collateral_price = if collateral_custody.is_virtual {
-                    // if collateral_custody is virtual it means this function is called from get_assets_under_management_usd()
-                    // (to calculate unrealized pnl of all open positions) and actual collateral custody is a stablecoin.
-                    // we need to use 1USD reference price for such positions
-                    OraclePrice {
-                        price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
-                        exponent: -(Perpetuals::USD_DECIMALS as i32),
-                    }
-                } else {
-                    collateral_token_price
-                        .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
-                };
-                let max_profit_usd = if curtime <= position.open_time {
-                    0
-                } else {
-                    min_collateral_price
-                        .get_asset_amount_usd(position.locked_amount, collateral_custody.decimals)?
-                };
-                Ok((
-                    std::cmp::min(max_profit_usd, cur_profit_usd),
-                    0u64,
-                    exit_fee,
-                ))
-            } else {
-                Ok((
-                    0u64,
-                    math::checked_sub(unrealized_loss_usd, potential_profit_usd)?,
-                    exit_fee,
-                ))
-            }
-        } else {
-            let potential_loss_usd = math::checked_as_u64(math::checked_ceil_div(
-                math::checked_mul(position.size_usd as u128, price_diff_loss as u128)?,
-                position_price as u128,
-            )?)?;
-
-            let potential_loss_usd = math::checked_add(potential_loss_usd, unrealized_loss_usd)?;
-
-            if potential_loss_usd >= position.unrealized_profit_usd {
-                Ok((
-                    0u64,
-                    math::checked_sub(potential_loss_usd, position.unrealized_profit_usd)?,
-                    exit_fee,
-                ))
-            } else {
-                let cur_profit_usd =
-                    math::checked_sub(position.unrealized_profit_usd, potential_loss_usd)?;
-                let min_collateral_price = if collateral_custody.is_virtual {
-                    OraclePrice {
-                        price: 10u64.pow(Perpetuals::USD_DECIMALS as u32),
-                        exponent: -(Perpetuals::USD_DECIMALS as i32),
-                    }
-                } else {
-                    collateral_token_price
-                        .get_min_price(collateral_token_ema_price, collateral_custody.is_stable)?
-                };
-                let max_profit_usd = if curtime <= position.open_time {
-                    0
-                } else {
-                    min_collateral_price
-                        .get_asset_amount_usd(position.locked_amount, collateral_custody.decimals)?
-                };
-                Ok((
-                    std::cmp::min(max_profit_usd, cur_profit_usd),
-                    0u64,
-                    exit_fee,
-                ))
-            }
-        }
+        pricing::Pool::from(self)
+            .get_pnl_usd(
+                &position.into(),
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                &custody.into(),
+                &(*collateral_token_price).into(),
+                &(*collateral_token_ema_price).into(),
+                &collateral_custody.into(),
+                curtime,
+                liquidation,
+                fee_discount,
+            )
+            .map_err(map_pricing_error)
     }
 
     pub fn get_assets_under_management_usd(
@@ -786,6 +869,7 @@ impl Pool {
                         &custody,
                         curtime,
                         false,
+                        0,
                     )?;
                     let (short_profit, short_loss, _) = self.get_pnl_usd(
                         &custody.get_collective_position(Side::Short)?,
@@ -797,6 +881,7 @@ impl Pool {
                         &custody,
                         curtime,
                         false,
+                        0,
                     )?;
 
                     // adjust pool amount by collective profit/loss
@@ -812,30 +897,37 @@ impl Pool {
     }
 
     pub fn get_fee_amount(fee: u64, amount: u64) -> Result<u64> {
-        if fee == 0 || amount == 0 {
-            return Ok(0);
-        }
-        math::checked_as_u64(math::checked_ceil_div(
-            math::checked_mul(amount as u128, fee as u128)?,
-            Perpetuals::BPS_POWER,
-        )?)
+        pricing::Pool::get_fee_amount(fee, amount).map_err(map_pricing_error)
     }
 
-    // private helpers
-    fn get_current_ratio(&self, custody: &Custody, token_price: &OraclePrice) -> Result<u64> {
-        if self.aum_usd == 0 || custody.is_virtual {
-            return Ok(0);
-        }
-        let ratio = math::checked_as_u64(math::checked_div(
-            math::checked_mul(
-                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128,
-                Perpetuals::BPS_POWER,
-            )?,
-            self.aum_usd,
-        )?)?;
-        Ok(std::cmp::min(ratio, Perpetuals::BPS_POWER as u64))
+    pub fn get_discounted_fee(fee_amount: u64, discount: u64) -> Result<u64> {
+        pricing::Pool::get_discounted_fee(fee_amount, discount).map_err(map_pricing_error)
+    }
+
+    /// Returns price impact in BPS for opening (is_increase) or closing a position of the given size.
+    /// Impact is proportional to the change in open interest skew relative to custody depth,
+    /// trades that reduce the skew get a negative impact (rebate).
+    pub fn get_price_impact(
+        &self,
+        token_price: &OraclePrice,
+        side: Side,
+        size_usd: u64,
+        is_increase: bool,
+        custody: &Custody,
+    ) -> Result<i64> {
+        pricing::Pool::from(self)
+            .get_price_impact(
+                &(*token_price).into(),
+                side.into(),
+                size_usd,
+                is_increase,
+                &custody.into(),
+            )
+            .map_err(map_pricing_error)
     }
 
+    // private helpers
+    #[cfg(test)]
     fn get_new_ratio(
         &self,
         amount_add: u64,
@@ -843,54 +935,17 @@ impl Pool {
         custody: &Custody,
         token_price: &OraclePrice,
     ) -> Result<u64> {
-        if custody.is_virtual {
-            return Ok(0);
-        }
-        let (new_token_aum_usd, new_pool_aum_usd) = if amount_add > 0 && amount_remove > 0 {
-            return Err(ProgramError::InvalidArgument.into());
-        } else if amount_add == 0 && amount_remove == 0 {
-            (
-                token_price.get_asset_amount_usd(custody.assets.owned, custody.decimals)? as u128,
-                self.aum_usd,
-            )
-        } else if amount_add > 0 {
-            let added_aum_usd =
-                token_price.get_asset_amount_usd(amount_add, custody.decimals)? as u128;
-
-            (
-                token_price.get_asset_amount_usd(
-                    math::checked_add(custody.assets.owned, amount_add)?,
-                    custody.decimals,
-                )? as u128,
-                math::checked_add(self.aum_usd, added_aum_usd)?,
-            )
-        } else {
-            let removed_aum_usd =
-                token_price.get_asset_amount_usd(amount_remove, custody.decimals)? as u128;
-
-            if removed_aum_usd >= self.aum_usd || amount_remove >= custody.assets.owned {
-                (0, 0)
-            } else {
-                (
-                    token_price.get_asset_amount_usd(
-                        math::checked_sub(custody.assets.owned, amount_remove)?,
-                        custody.decimals,
-                    )? as u128,
-                    math::checked_sub(self.aum_usd, removed_aum_usd)?,
-                )
-            }
-        };
-        if new_token_aum_usd == 0 || new_pool_aum_usd == 0 {
-            return Ok(0);
-        }
-
-        let ratio = math::checked_as_u64(math::checked_div(
-            math::checked_mul(new_token_aum_usd, Perpetuals::BPS_POWER)?,
-            new_pool_aum_usd,
-        )?)?;
-        Ok(std::cmp::min(ratio, Perpetuals::BPS_POWER as u64))
+        pricing::Pool::from(self)
+            .get_new_ratio(
+                amount_add,
+                amount_remove,
+                &custody.into(),
+                &(*token_price).into(),
+            )
+            .map_err(map_pricing_error)
     }
 
+    #[cfg(test)]
     fn get_price(
         &self,
         token_price: &OraclePrice,
@@ -898,54 +953,18 @@ impl Pool {
         side: Side,
         spread: u64,
     ) -> Result<OraclePrice> {
-        if side == Side::Long {
-            let max_price = if token_price > token_ema_price {
-                token_price
-            } else {
-                token_ema_price
-            };
-
-            Ok(OraclePrice {
-                price: math::checked_add(
-                    max_price.price,
-                    math::checked_decimal_ceil_mul(
-                        max_price.price,
-                        max_price.exponent,
-                        spread,
-                        -(Perpetuals::BPS_DECIMALS as i32),
-                        max_price.exponent,
-                    )?,
-                )?,
-                exponent: max_price.exponent,
-            })
-        } else {
-            let min_price = if token_price < token_ema_price {
-                token_price
-            } else {
-                token_ema_price
-            };
-
-            let spread = math::checked_decimal_mul(
-                min_price.price,
-                min_price.exponent,
+        pricing::Pool::from(self)
+            .get_price(
+                &(*token_price).into(),
+                &(*token_ema_price).into(),
+                side.into(),
                 spread,
-                -(Perpetuals::BPS_DECIMALS as i32),
-                min_price.exponent,
-            )?;
-
-            let price = if spread < min_price.price {
-                math::checked_sub(min_price.price, spread)?
-            } else {
-                0
-            };
-
-            Ok(OraclePrice {
-                price,
-                exponent: min_price.exponent,
-            })
-        }
+            )
+            .map(Into::into)
+            .map_err(map_pricing_error)
     }
 
+    #[cfg(test)]
     fn get_fee(
         &self,
         token_id: usize,
@@ -955,171 +974,45 @@ impl Pool {
         custody: &Custody,
         token_price: &OraclePrice,
     ) -> Result<u64> {
-        require!(!custody.is_virtual, PerpetualsError::InstructionNotAllowed);
-
-        if custody.fees.mode == FeesMode::Fixed {
-            Self::get_fee_amount(base_fee, std::cmp::max(amount_add, amount_remove))
-        } else if custody.fees.mode == FeesMode::Linear {
-            self.get_fee_linear(
-                token_id,
-                base_fee,
-                amount_add,
-                amount_remove,
-                custody,
-                token_price,
-            )
-        } else {
-            self.get_fee_optimal(
+        pricing::Pool::from(self)
+            .get_fee(
                 token_id,
                 base_fee,
                 amount_add,
                 amount_remove,
-                custody,
-                token_price,
+                &custody.into(),
+                &(*token_price).into(),
             )
-        }
+            .map_err(map_pricing_error)
     }
+}
 
-    fn get_fee_linear(
-        &self,
-        token_id: usize,
-        base_fee: u64,
-        amount_add: u64,
-        amount_remove: u64,
-        custody: &Custody,
-        token_price: &OraclePrice,
-    ) -> Result<u64> {
-        // if token ratio is improved:
-        //    fee = base_fee / ratio_fee
-        // otherwise:
-        //    fee = base_fee * ratio_fee
-        // where:
-        //   if new_ratio < ratios.target:
-        //     ratio_fee = 1 + custody.fees.ratio_mult * (ratios.target - new_ratio) / (ratios.target - ratios.min);
-        //   otherwise:
-        //     ratio_fee = 1 + custody.fees.ratio_mult * (new_ratio - ratios.target) / (ratios.max - ratios.target);
-
-        let ratios = &self.ratios[token_id];
-        let current_ratio = self.get_current_ratio(custody, token_price)?;
-        let new_ratio = self.get_new_ratio(amount_add, amount_remove, custody, token_price)?;
-
-        let improved = match new_ratio.cmp(&ratios.target) {
-            Ordering::Less => {
-                new_ratio > current_ratio
-                    || (current_ratio > ratios.target
-                        && current_ratio - ratios.target > ratios.target - new_ratio)
-            }
-            Ordering::Greater => {
-                new_ratio < current_ratio
-                    || (current_ratio < ratios.target
-                        && ratios.target - current_ratio > new_ratio - ratios.target)
-            }
-            Ordering::Equal => current_ratio != ratios.target,
-        };
-
-        let ratio_fee = if new_ratio <= ratios.target {
-            if ratios.target == ratios.min {
-                Perpetuals::BPS_POWER
-            } else {
-                math::checked_add(
-                    Perpetuals::BPS_POWER,
-                    math::checked_div(
-                        math::checked_mul(
-                            custody.fees.ratio_mult as u128,
-                            math::checked_sub(ratios.target, new_ratio)? as u128,
-                        )?,
-                        math::checked_sub(ratios.target, ratios.min)? as u128,
-                    )?,
-                )?
-            }
-        } else if ratios.target == ratios.max {
-            Perpetuals::BPS_POWER
-        } else {
-            math::checked_add(
-                Perpetuals::BPS_POWER,
-                math::checked_div(
-                    math::checked_mul(
-                        custody.fees.ratio_mult as u128,
-                        math::checked_sub(new_ratio, ratios.target)? as u128,
-                    )?,
-                    math::checked_sub(ratios.max, ratios.target)? as u128,
-                )?,
-            )?
-        };
-
-        let fee = if improved {
-            math::checked_div(
-                math::checked_mul(base_fee as u128, Perpetuals::BPS_POWER)?,
-                ratio_fee,
-            )?
-        } else {
-            math::checked_div(
-                math::checked_mul(base_fee as u128, ratio_fee)?,
-                Perpetuals::BPS_POWER,
-            )?
-        };
-
-        Self::get_fee_amount(
-            math::checked_as_u64(fee)?,
-            std::cmp::max(amount_add, amount_remove),
-        )
+impl From<TokenRatios> for pricing::TokenRatios {
+    fn from(ratios: TokenRatios) -> Self {
+        Self {
+            target: ratios.target,
+            min: ratios.min,
+            max: ratios.max,
+        }
     }
+}
 
-    fn get_fee_optimal(
-        &self,
-        token_id: usize,
-        base_fee: u64,
-        amount_add: u64,
-        amount_remove: u64,
-        custody: &Custody,
-        token_price: &OraclePrice,
-    ) -> Result<u64> {
-        // Fee calculations must temporarily be in i64 because of negative slope.
-        let fee_max: i64 = custody.fees.fee_max as i64;
-        let fee_optimal: i64 = custody.fees.fee_optimal as i64;
-
-        let target_ratio: i64 = self.ratios[token_id].target as i64;
-        let min_ratio: i64 = self.ratios[token_id].min as i64;
-        let max_ratio: i64 = self.ratios[token_id].max as i64;
-        let post_lp_ratio: i64 =
-            self.get_new_ratio(amount_add, amount_remove, custody, token_price)? as i64;
-
-        let base_fee: i64 = base_fee as i64;
-
-        let slope_denominator: i64 = if post_lp_ratio > target_ratio {
-            math::checked_sub(max_ratio, target_ratio)?
-        } else {
-            math::checked_sub(target_ratio, min_ratio)?
-        };
-
-        let slope_numerator: i64 = if amount_add != 0 {
-            if post_lp_ratio > max_ratio {
-                return err!(PerpetualsError::TokenRatioOutOfRange);
-            }
-            fee_max - fee_optimal
-        } else {
-            if post_lp_ratio < min_ratio {
-                return err!(PerpetualsError::TokenRatioOutOfRange);
-            }
-            fee_optimal - fee_max
-        };
-
-        // Delay applying slope_denominator until the very end to avoid losing precision.
-        // b = fee_optimal - target_ratio * slope
-        // lp_fee = slope * post_lp_ratio + b
-        let b: i64 = math::checked_sub(
-            math::checked_mul(fee_optimal, slope_denominator)?,
-            math::checked_mul(target_ratio, slope_numerator)?,
-        )?;
-        let lp_fee: i64 = math::checked_div(
-            math::checked_add(math::checked_mul(slope_numerator, post_lp_ratio)?, b)?,
-            slope_denominator,
-        )?;
+impl From<FeeTier> for pricing::FeeTier {
+    fn from(tier: FeeTier) -> Self {
+        Self {
+            min_volume_usd: tier.min_volume_usd,
+            discount: tier.discount,
+        }
+    }
+}
 
-        Self::get_fee_amount(
-            math::checked_as_u64(math::checked_add(lp_fee, base_fee)?)?,
-            std::cmp::max(amount_add, amount_remove),
-        )
+impl From<&Pool> for pricing::Pool {
+    fn from(pool: &Pool) -> Self {
+        Self {
+            ratios: pool.ratios.iter().map(|&x| x.into()).collect(),
+            aum_usd: pool.aum_usd,
+            fee_tiers: pool.fee_tiers.iter().map(|&x| x.into()).collect(),
+        }
     }
 }
 
@@ -1128,7 +1021,7 @@ mod test {
     use {
         super::*,
         crate::state::{
-            custody::{BorrowRateParams, Fees, PricingParams},
+            custody::{BorrowRateParams, Fees, FeesMode, PricingParams, TradeStats},
             oracle::{OracleParams, OracleType},
             perpetuals::Permissions,
         },
@@ -1162,6 +1055,8 @@ mod test {
             max_utilization: 0,
             max_position_locked_usd: 0,
             max_total_locked_usd: 0,
+            price_impact_mult: 0,
+            max_price_impact: 0,
         };
 
         let permissions = Permissions {
@@ -1354,7 +1249,8 @@ mod test {
                 custody.fees.open_position,
                 0,
                 custody.get_locked_amount(0, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1365,7 +1261,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1376,7 +1273,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1387,7 +1285,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1398,7 +1297,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1413,7 +1313,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1424,7 +1325,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1435,7 +1337,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1446,7 +1349,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1459,7 +1363,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1470,7 +1375,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1481,7 +1387,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1492,7 +1399,8 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1506,7 +1414,8 @@ mod test {
                 custody.fees.open_position,
                 100_000,
                 custody.get_locked_amount(100_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1517,7 +1426,8 @@ mod test {
                 custody.fees.open_position,
                 150_000,
                 custody.get_locked_amount(150_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1528,7 +1438,8 @@ mod test {
                 custody.fees.open_position,
                 200_000,
                 custody.get_locked_amount(200_000, Side::Long).unwrap(),
-                &custody
+                &custody,
+                0
             )
             .unwrap()
         );
@@ -1539,12 +1450,226 @@ mod test {
                 custody.fees.open_position,
                 300_000,
                 custody.get_locked_amount(300_000, Side::Long).unwrap(),
+                &custody,
+                0
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_get_price_impact() {
+        let (pool, mut custody, _position, token_price, token_ema_price) = get_fixture();
+
+        // $2.5M depth, 10% impact at full depth capped at 2%
+        custody.assets.owned = scale(100, 9);
+        custody.pricing.price_impact_mult = 1_000;
+        custody.pricing.max_price_impact = 200;
+
+        // disabled
+        assert_eq!(
+            0,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(250_000, Perpetuals::USD_DECIMALS),
+                true,
+                &Custody {
+                    pricing: PricingParams {
+                        price_impact_mult: 0,
+                        ..custody.pricing
+                    },
+                    ..custody
+                }
+            )
+            .unwrap()
+        );
+
+        // balanced open interest, trade increases skew
+        assert_eq!(
+            100,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(250_000, Perpetuals::USD_DECIMALS),
+                true,
+                &custody
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            25_806_000_000,
+            pool.get_entry_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                scale(10, 9),
+                &custody
+            )
+            .unwrap()
+        );
+
+        // impact is capped
+        assert_eq!(
+            200,
+            pool.get_price_impact(
+                &token_price,
+                Side::Short,
+                scale(2_500_000, Perpetuals::USD_DECIMALS),
+                true,
+                &custody
+            )
+            .unwrap()
+        );
+
+        // trade reduces skew and gets a rebate, which can't go past the oracle price
+        custody.trade_stats.oi_short_usd = scale(500_000, Perpetuals::USD_DECIMALS);
+        assert_eq!(
+            -100,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(250_000, Perpetuals::USD_DECIMALS),
+                true,
+                &custody
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            25_300_000_000,
+            pool.get_entry_price(
+                &token_price,
+                &token_ema_price,
+                Side::Long,
+                scale(10, 9),
+                &custody
+            )
+            .unwrap()
+        );
+
+        // trade crosses the skew, only the net change is charged
+        assert_eq!(
+            0,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(1_000_000, Perpetuals::USD_DECIMALS),
+                true,
+                &custody
+            )
+            .unwrap()
+        );
+
+        // closing shorts reduces skew
+        assert_eq!(
+            -40,
+            pool.get_price_impact(
+                &token_price,
+                Side::Short,
+                scale(100_000, Perpetuals::USD_DECIMALS),
+                false,
+                &custody
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            25_451_800_000,
+            pool.get_exit_price(
+                &token_price,
+                &token_ema_price,
+                Side::Short,
+                scale(100_000, Perpetuals::USD_DECIMALS),
+                &custody
+            )
+            .unwrap()
+        );
+
+        // closing longs increases skew
+        assert_eq!(
+            40,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(100_000, Perpetuals::USD_DECIMALS),
+                false,
+                &Custody {
+                    trade_stats: TradeStats {
+                        oi_long_usd: scale(100_000, Perpetuals::USD_DECIMALS),
+                        ..custody.trade_stats
+                    },
+                    ..custody
+                }
+            )
+            .unwrap()
+        );
+
+        // no depth, cap applies
+        custody.assets.owned = 0;
+        assert_eq!(
+            -200,
+            pool.get_price_impact(
+                &token_price,
+                Side::Long,
+                scale(1_000, Perpetuals::USD_DECIMALS),
+                true,
                 &custody
             )
             .unwrap()
         );
     }
 
+    #[test]
+    fn test_get_fee_discount() {
+        let (_pool, mut custody, _position, _token_price, _token_ema_price) = get_fixture();
+
+        let mut pool = Pool {
+            name: "Test Pool".to_string(),
+            fee_tiers: vec![
+                FeeTier {
+                    min_volume_usd: scale(1_000_000, Perpetuals::USD_DECIMALS),
+                    discount: 1_000,
+                },
+                FeeTier {
+                    min_volume_usd: scale(10_000_000, Perpetuals::USD_DECIMALS),
+                    discount: 2_500,
+                },
+            ],
+            ..Default::default()
+        };
+        assert!(pool.validate());
+
+        assert_eq!(0, pool.get_fee_discount(0));
+        assert_eq!(
+            1_000,
+            pool.get_fee_discount(scale(1_000_000, Perpetuals::USD_DECIMALS))
+        );
+        assert_eq!(
+            2_500,
+            pool.get_fee_discount(scale(50_000_000, Perpetuals::USD_DECIMALS))
+        );
+
+        custody.assets.owned = 200_000;
+        custody.borrow_rate.optimal_utilization = 500_000_000;
+        assert_eq!(
+            750,
+            pool.get_entry_fee(
+                custody.fees.open_position,
+                100_000,
+                custody.get_locked_amount(100_000, Side::Long).unwrap(),
+                &custody,
+                2_500
+            )
+            .unwrap()
+        );
+
+        custody.fees.close_position = 100;
+        assert_eq!(900, pool.get_exit_fee(100_000, &custody, 1_000).unwrap());
+
+        // tiers must be ordered by volume
+        pool.fee_tiers.swap(0, 1);
+        assert!(!pool.validate());
+    }
+
     #[test]
     fn test_get_fee() {
         let (mut pool, mut custody, _position, token_price, _token_ema_price) = get_fixture();
@@ -1754,7 +1879,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1772,7 +1898,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1790,7 +1917,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1986,7 +2114,8 @@ mod test {
                 &token_ema_price,
                 &custody,
                 1,
-                false
+                false,
+                0
             )
             .unwrap()
         );
@@ -1,7 +1,4 @@
-use {
-    crate::{math, state::perpetuals::Perpetuals},
-    anchor_lang::prelude::*,
-};
+use {crate::error::map_pricing_error, anchor_lang::prelude::*, perpetuals_pricing as pricing};
 
 #[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Debug)]
 pub enum Side {
@@ -37,6 +34,34 @@ pub struct Position {
     pub custody: Pubkey,
     pub collateral_custody: Pubkey,
 
+    pub open_time: i64,
+    pub update_time: i64,
+    pub side: Side,
+    pub price: u64,
+    pub size_usd: u64,
+    pub borrow_size_usd: u64,
+    pub collateral_usd: u64,
+    pub unrealized_profit_usd: u64,
+    pub unrealized_loss_usd: u64,
+    pub cumulative_interest_snapshot: u128,
+    pub locked_amount: u64,
+    pub collateral_amount: u64,
+    // distinguishes positions of the same owner, custody and side
+    pub index: u64,
+
+    pub bump: u8,
+}
+
+// position layout before per-owner indexes were added, the address didn't
+// include an index either
+#[account]
+#[derive(Default, Debug)]
+pub struct LegacyPosition {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub custody: Pubkey,
+    pub collateral_custody: Pubkey,
+
     pub open_time: i64,
     pub update_time: i64,
     pub side: Side,
@@ -57,9 +82,40 @@ impl Position {
     pub const LEN: usize = 8 + std::mem::size_of::<Position>();
 
     pub fn get_initial_leverage(&self) -> Result<u64> {
-        math::checked_as_u64(math::checked_div(
-            math::checked_mul(self.size_usd as u128, Perpetuals::BPS_POWER)?,
-            self.collateral_usd as u128,
-        )?)
+        pricing::Position::from(self)
+            .get_initial_leverage()
+            .map_err(map_pricing_error)
+    }
+}
+
+impl LegacyPosition {
+    pub const LEN: usize = 8 + std::mem::size_of::<LegacyPosition>();
+}
+
+impl From<Side> for pricing::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::None => Self::None,
+            Side::Long => Self::Long,
+            Side::Short => Self::Short,
+        }
+    }
+}
+
+impl From<&Position> for pricing::Position {
+    fn from(position: &Position) -> Self {
+        Self {
+            open_time: position.open_time,
+            side: position.side.into(),
+            price: position.price,
+            size_usd: position.size_usd,
+            borrow_size_usd: position.borrow_size_usd,
+            collateral_usd: position.collateral_usd,
+            unrealized_profit_usd: position.unrealized_profit_usd,
+            unrealized_loss_usd: position.unrealized_loss_usd,
+            cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+            locked_amount: position.locked_amount,
+            collateral_amount: position.collateral_amount,
+        }
     }
 }
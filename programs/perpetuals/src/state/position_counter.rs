@@ -0,0 +1,24 @@
+//! Per-trader counter used to derive addresses of independent positions
+
+use {crate::math, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct PositionCounter {
+    pub owner: Pubkey,
+    // index of the next position opened by the owner, indexes are never reused
+    pub next_index: u64,
+
+    pub bump: u8,
+}
+
+impl PositionCounter {
+    pub const LEN: usize = 8 + std::mem::size_of::<PositionCounter>();
+
+    /// Returns the index of a new position and advances the counter
+    pub fn take_index(&mut self) -> Result<u64> {
+        let index = self.next_index;
+        self.next_index = math::checked_add(self.next_index, 1)?;
+        Ok(index)
+    }
+}
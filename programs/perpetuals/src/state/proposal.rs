@@ -0,0 +1,248 @@
+//! Timelocked admin proposal state and routines
+
+use {
+    crate::{error::PerpetualsError, math, state::multisig::Multisig},
+    anchor_lang::prelude::*,
+    solana_program::instruction::AccountMeta,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct ProposalAccount {
+    pub pubkey: Pubkey,
+    pub is_writable: bool,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Pubkey,
+    // serialized perpetuals program instruction and its accounts
+    pub instruction_data: Vec<u8>,
+    pub instruction_accounts: Vec<ProposalAccount>,
+    pub approvals: [Pubkey; 6], // Multisig::MAX_SIGNERS
+    pub num_approvals: u8,
+    // earliest execution time, set once enough approvals are collected
+    pub eta: i64,
+    pub bump: u8,
+    // once queued, a proposal is cancelled by as many admins as required to approve it
+    pub cancellations: [Pubkey; 6], // Multisig::MAX_SIGNERS
+    pub num_cancellations: u8,
+}
+
+impl Proposal {
+    pub const LEN: usize = 8 + std::mem::size_of::<Proposal>();
+    pub const MAX_INSTRUCTION_DATA_LEN: usize = 1024;
+    pub const MAX_INSTRUCTION_ACCOUNTS: usize = 32;
+
+    pub fn get_size(instruction_data_len: usize, instruction_accounts_len: usize) -> usize {
+        Proposal::LEN
+            + instruction_data_len
+            + instruction_accounts_len * std::mem::size_of::<ProposalAccount>()
+    }
+
+    pub fn validate(&self) -> bool {
+        // instruction must at least contain anchor discriminator
+        self.instruction_data.len() >= 8
+            && self.instruction_data.len() <= Proposal::MAX_INSTRUCTION_DATA_LEN
+            && !self.instruction_accounts.is_empty()
+            && self.instruction_accounts.len() <= Proposal::MAX_INSTRUCTION_ACCOUNTS
+    }
+
+    /// Records admin approval and returns Ok(0) if the proposal has been queued for execution
+    /// or Ok(approvals_left) otherwise.
+    pub fn approve(&mut self, signer: &Pubkey, multisig: &Multisig, curtime: i64) -> Result<u8> {
+        if !multisig.is_signer(signer)? {
+            return err!(PerpetualsError::MultisigAccountNotAuthorized);
+        }
+        if self.eta > 0 {
+            return err!(PerpetualsError::ProposalAlreadyApproved);
+        }
+        (self.approvals, self.num_approvals) =
+            Proposal::add_vote(self.get_approvals(), signer, multisig)?;
+
+        let approvals_left = self.get_approvals_left(multisig)?;
+        if approvals_left == 0 {
+            self.eta = math::checked_add(curtime, multisig.timelock_delay)?;
+        }
+
+        Ok(approvals_left)
+    }
+
+    /// Records admin cancellation and returns Ok(0) if the proposal must be closed
+    /// or Ok(cancellations_left) otherwise. Any admin can cancel a pending proposal,
+    /// a queued one requires as many cancellations as approvals.
+    pub fn cancel(&mut self, signer: &Pubkey, multisig: &Multisig) -> Result<u8> {
+        if !multisig.is_signer(signer)? {
+            return err!(PerpetualsError::MultisigAccountNotAuthorized);
+        }
+        if self.eta == 0 {
+            return Ok(0);
+        }
+
+        (self.cancellations, self.num_cancellations) =
+            Proposal::add_vote(self.get_cancellations(), signer, multisig)?;
+
+        Ok(multisig
+            .min_signatures
+            .saturating_sub(self.num_cancellations))
+    }
+
+    /// Returns the number of approvals from current multisig signers still required
+    pub fn get_approvals_left(&self, multisig: &Multisig) -> Result<u8> {
+        let mut num_approvals = 0u8;
+        for approval in self.get_approvals() {
+            if multisig.is_signer(approval)? {
+                num_approvals = math::checked_add(num_approvals, 1)?;
+            }
+        }
+        Ok(multisig.min_signatures.saturating_sub(num_approvals))
+    }
+
+    pub fn is_executable(&self, multisig: &Multisig, curtime: i64) -> Result<bool> {
+        Ok(self.eta > 0 && curtime >= self.eta && self.get_approvals_left(multisig)? == 0)
+    }
+
+    /// Returns instruction accounts, timelock authority is the only signer
+    pub fn get_account_metas(&self, timelock_authority: &Pubkey) -> Vec<AccountMeta> {
+        self.instruction_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: account.pubkey,
+                is_signer: &account.pubkey == timelock_authority,
+                is_writable: account.is_writable,
+            })
+            .collect()
+    }
+
+    fn get_approvals(&self) -> &[Pubkey] {
+        &self.approvals[..self.num_approvals as usize]
+    }
+
+    fn get_cancellations(&self) -> &[Pubkey] {
+        &self.cancellations[..self.num_cancellations as usize]
+    }
+
+    /// Appends signer to the votes, dropping votes of admins that were removed from the multisig since
+    fn add_vote(
+        votes: &[Pubkey],
+        signer: &Pubkey,
+        multisig: &Multisig,
+    ) -> Result<([Pubkey; Multisig::MAX_SIGNERS], u8)> {
+        if votes.contains(signer) {
+            return err!(PerpetualsError::MultisigAlreadySigned);
+        }
+
+        let mut new_votes: [Pubkey; Multisig::MAX_SIGNERS] = Default::default();
+        let mut num_votes = 0;
+        for vote in votes {
+            if multisig.is_signer(vote)? {
+                new_votes[num_votes] = *vote;
+                num_votes += 1;
+            }
+        }
+        new_votes[num_votes] = *signer;
+
+        Ok((new_votes, math::checked_add(num_votes as u8, 1)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get_multisig(signers: &[Pubkey], min_signatures: u8) -> Multisig {
+        let mut multisig = Multisig {
+            num_signers: signers.len() as u8,
+            min_signatures,
+            timelock_delay: 3_600,
+            ..Default::default()
+        };
+        let mut multisig_signers: [Pubkey; Multisig::MAX_SIGNERS] = Default::default();
+        multisig_signers[..signers.len()].copy_from_slice(signers);
+        multisig.signers = multisig_signers;
+        multisig
+    }
+
+    #[test]
+    fn test_approve() {
+        let signers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let multisig = get_multisig(&signers, 2);
+        let mut proposal = Proposal::default();
+
+        assert!(proposal
+            .approve(&Pubkey::new_unique(), &multisig, 100)
+            .is_err());
+
+        assert_eq!(proposal.approve(&signers[0], &multisig, 100).unwrap(), 1);
+        assert!(proposal.approve(&signers[0], &multisig, 100).is_err());
+        assert_eq!(proposal.eta, 0);
+        assert!(!proposal.is_executable(&multisig, 10_000).unwrap());
+
+        assert_eq!(proposal.approve(&signers[1], &multisig, 200).unwrap(), 0);
+        assert_eq!(proposal.eta, 3_800);
+        assert!(proposal.approve(&signers[2], &multisig, 300).is_err());
+
+        assert!(!proposal.is_executable(&multisig, 3_799).unwrap());
+        assert!(proposal.is_executable(&multisig, 3_800).unwrap());
+
+        // approval of a removed signer no longer counts
+        let multisig = get_multisig(&signers[1..], 2);
+        assert_eq!(proposal.get_approvals_left(&multisig).unwrap(), 1);
+        assert!(!proposal.is_executable(&multisig, 3_800).unwrap());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let signers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let multisig = get_multisig(&signers, 2);
+        let mut proposal = Proposal::default();
+
+        // a pending proposal is cancelled by any admin
+        assert!(proposal.cancel(&Pubkey::new_unique(), &multisig).is_err());
+        assert_eq!(proposal.cancel(&signers[0], &multisig).unwrap(), 0);
+
+        // a queued one requires the approval quorum
+        proposal.approve(&signers[0], &multisig, 100).unwrap();
+        proposal.approve(&signers[1], &multisig, 100).unwrap();
+
+        assert_eq!(proposal.cancel(&signers[2], &multisig).unwrap(), 1);
+        assert!(proposal.cancel(&signers[2], &multisig).is_err());
+
+        // cancellation of a removed signer no longer counts
+        let new_signers = [signers[0], signers[1], Pubkey::new_unique()];
+        let multisig = get_multisig(&new_signers, 2);
+        assert_eq!(proposal.cancel(&signers[0], &multisig).unwrap(), 1);
+        assert_eq!(proposal.cancel(&signers[1], &multisig).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_get_account_metas() {
+        let authority = Pubkey::new_unique();
+        let proposal = Proposal {
+            instruction_accounts: vec![
+                ProposalAccount {
+                    pubkey: authority,
+                    is_writable: true,
+                },
+                ProposalAccount {
+                    pubkey: Pubkey::new_unique(),
+                    is_writable: false,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let metas = proposal.get_account_metas(&authority);
+        assert!(metas[0].is_signer && metas[0].is_writable);
+        assert!(!metas[1].is_signer && !metas[1].is_writable);
+    }
+}
@@ -0,0 +1,164 @@
+//! Trading authority delegated by a trader to a separate key
+
+use {
+    crate::{error::PerpetualsError, state::position::Position},
+    anchor_lang::prelude::*,
+};
+
+#[derive(Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize, Default, Debug)]
+pub struct DelegatePermissions {
+    pub allow_open_position: bool,
+    pub allow_close_position: bool,
+    pub allow_add_collateral: bool,
+    pub allow_remove_collateral: bool,
+}
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TradingDelegate {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    pub delegate: Pubkey,
+    pub permissions: DelegatePermissions,
+    // limits for positions opened or modified by the delegate, zero means no limit
+    pub max_size_usd: u64,
+    pub max_leverage: u64,
+    // zero means the delegate never expires
+    pub expiration_time: i64,
+
+    pub bump: u8,
+}
+
+impl TradingDelegate {
+    pub const LEN: usize = 8 + std::mem::size_of::<TradingDelegate>();
+
+    /// Checks that the instruction is signed either by the owner or by its active
+    /// delegate. Returns the delegate in the latter case.
+    pub fn get_delegate<'a>(
+        owner: &Pubkey,
+        authority: &Pubkey,
+        trading_delegate: Option<&'a TradingDelegate>,
+        curtime: i64,
+    ) -> Result<Option<&'a TradingDelegate>> {
+        if authority == owner {
+            return Ok(None);
+        }
+
+        match trading_delegate {
+            Some(trading_delegate)
+                if trading_delegate.owner == *owner
+                    && trading_delegate.delegate == *authority
+                    && trading_delegate.is_active(curtime) =>
+            {
+                Ok(Some(trading_delegate))
+            }
+            _ => err!(PerpetualsError::InvalidPositionAuthority),
+        }
+    }
+
+    pub fn is_active(&self, curtime: i64) -> bool {
+        self.expiration_time == 0 || curtime < self.expiration_time
+    }
+
+    /// Checks the position against the delegate limits
+    pub fn check_position(&self, position: &Position) -> Result<bool> {
+        if self.max_size_usd > 0 && position.size_usd > self.max_size_usd {
+            return Ok(false);
+        }
+        if self.max_leverage > 0 && position.get_initial_leverage()? > self.max_leverage {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::state::perpetuals::Perpetuals};
+
+    fn get_fixture() -> (TradingDelegate, Position) {
+        let owner = Pubkey::new_unique();
+        let trading_delegate = TradingDelegate {
+            owner,
+            delegate: Pubkey::new_unique(),
+            max_size_usd: 10_000_000_000,
+            max_leverage: 5 * Perpetuals::BPS_POWER as u64,
+            expiration_time: 1_000,
+            ..TradingDelegate::default()
+        };
+        let position = Position {
+            owner,
+            size_usd: 10_000_000_000,
+            collateral_usd: 2_000_000_000,
+            ..Position::default()
+        };
+        (trading_delegate, position)
+    }
+
+    #[test]
+    fn test_get_delegate() {
+        let (trading_delegate, _) = get_fixture();
+        let owner = trading_delegate.owner;
+        let delegate = trading_delegate.delegate;
+
+        // owner doesn't need a delegate
+        assert!(TradingDelegate::get_delegate(&owner, &owner, None, 0)
+            .unwrap()
+            .is_none());
+        assert!(
+            TradingDelegate::get_delegate(&owner, &owner, Some(&trading_delegate), 2_000)
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(
+            TradingDelegate::get_delegate(&owner, &delegate, Some(&trading_delegate), 999)
+                .unwrap()
+                .is_some()
+        );
+
+        // expired
+        assert!(
+            TradingDelegate::get_delegate(&owner, &delegate, Some(&trading_delegate), 1_000)
+                .is_err()
+        );
+
+        // missing delegate account or another signer
+        assert!(TradingDelegate::get_delegate(&owner, &delegate, None, 0).is_err());
+        assert!(TradingDelegate::get_delegate(
+            &owner,
+            &Pubkey::new_unique(),
+            Some(&trading_delegate),
+            0
+        )
+        .is_err());
+
+        // delegate of another owner
+        assert!(TradingDelegate::get_delegate(
+            &Pubkey::new_unique(),
+            &delegate,
+            Some(&trading_delegate),
+            0
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_check_position() {
+        let (mut trading_delegate, mut position) = get_fixture();
+
+        assert!(trading_delegate.check_position(&position).unwrap());
+
+        position.size_usd += 1;
+        assert!(!trading_delegate.check_position(&position).unwrap());
+
+        position.size_usd -= 1;
+        position.collateral_usd -= 1_000_000;
+        assert!(!trading_delegate.check_position(&position).unwrap());
+
+        trading_delegate.max_size_usd = 0;
+        trading_delegate.max_leverage = 0;
+        position.size_usd *= 10;
+        assert!(trading_delegate.check_position(&position).unwrap());
+    }
+}
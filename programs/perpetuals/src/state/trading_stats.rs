@@ -0,0 +1,99 @@
+//! Per-trader volume statistics used for fee tier discounts
+
+use {crate::math, anchor_lang::prelude::*};
+
+#[account]
+#[derive(Default, Debug)]
+pub struct TradingStats {
+    pub owner: Pubkey,
+    pub pool: Pubkey,
+    // open and close notional per day, indexed by day % VOLUME_WINDOW_DAYS
+    // USD denominated values always have implied USD_DECIMALS decimals
+    pub daily_volume_usd: [u64; 30], // TradingStats::VOLUME_WINDOW_DAYS
+    pub last_update_day: i64,
+
+    pub bump: u8,
+}
+
+impl TradingStats {
+    pub const LEN: usize = 8 + std::mem::size_of::<TradingStats>();
+    pub const VOLUME_WINDOW_DAYS: usize = 30;
+    pub const SECONDS_PER_DAY: i64 = 86_400;
+
+    /// Returns notional traded over the last VOLUME_WINDOW_DAYS days, including the current one
+    pub fn get_rolling_volume_usd(&self, curtime: i64) -> Result<u64> {
+        let elapsed_days = std::cmp::max(
+            0,
+            math::checked_sub(Self::get_day(curtime), self.last_update_day)?,
+        );
+
+        // skip buckets that will be recycled on the next update
+        let mut volume_usd = 0u64;
+        for age in 0..(Self::VOLUME_WINDOW_DAYS as i64 - elapsed_days) {
+            let idx = Self::get_bucket_index(math::checked_sub(self.last_update_day, age)?);
+            volume_usd = math::checked_add(volume_usd, self.daily_volume_usd[idx])?;
+        }
+
+        Ok(volume_usd)
+    }
+
+    /// Records traded notional, expiring buckets that fell out of the window
+    pub fn add_volume(&mut self, volume_usd: u64, curtime: i64) -> Result<()> {
+        let today = Self::get_day(curtime);
+
+        if today > self.last_update_day {
+            let elapsed_days = math::checked_sub(today, self.last_update_day)?;
+            if elapsed_days >= Self::VOLUME_WINDOW_DAYS as i64 {
+                self.daily_volume_usd = Default::default();
+            } else {
+                for day in (self.last_update_day + 1)..=today {
+                    self.daily_volume_usd[Self::get_bucket_index(day)] = 0;
+                }
+            }
+            self.last_update_day = today;
+        }
+
+        let idx = Self::get_bucket_index(self.last_update_day);
+        self.daily_volume_usd[idx] = self.daily_volume_usd[idx].saturating_add(volume_usd);
+
+        Ok(())
+    }
+
+    fn get_day(curtime: i64) -> i64 {
+        curtime.div_euclid(Self::SECONDS_PER_DAY)
+    }
+
+    fn get_bucket_index(day: i64) -> usize {
+        day.rem_euclid(Self::VOLUME_WINDOW_DAYS as i64) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: i64 = TradingStats::SECONDS_PER_DAY;
+
+    #[test]
+    fn test_rolling_volume() {
+        let mut stats = TradingStats::default();
+        let start = 1_000 * DAY;
+
+        stats.add_volume(100, start).unwrap();
+        stats.add_volume(50, start + DAY / 2).unwrap();
+        assert_eq!(stats.get_rolling_volume_usd(start).unwrap(), 150);
+
+        stats.add_volume(200, start + 10 * DAY).unwrap();
+        assert_eq!(stats.get_rolling_volume_usd(start + 10 * DAY).unwrap(), 350);
+
+        // first day drops out of the window
+        assert_eq!(stats.get_rolling_volume_usd(start + 30 * DAY).unwrap(), 200);
+        stats.add_volume(0, start + 30 * DAY).unwrap();
+        assert_eq!(stats.get_rolling_volume_usd(start + 30 * DAY).unwrap(), 200);
+
+        // everything expired
+        assert_eq!(stats.get_rolling_volume_usd(start + 40 * DAY).unwrap(), 0);
+        stats.add_volume(10, start + 100 * DAY).unwrap();
+        assert_eq!(stats.get_rolling_volume_usd(start + 100 * DAY).unwrap(), 10);
+    }
+}
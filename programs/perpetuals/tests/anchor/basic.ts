@@ -125,6 +125,7 @@ describe("perpetuals", () => {
       bump: tc.pool.bump,
       lpTokenBump: pool.lpTokenBump,
       inceptionTime: new BN(0),
+      lpTokenProgram: spl.TOKEN_PROGRAM_ID,
     };
     expect(JSON.stringify(pool)).to.equal(JSON.stringify(poolExpected));
 
@@ -155,6 +156,8 @@ describe("perpetuals", () => {
       maxUtilization: new BN(10000),
       maxPositionLockedUsd: new BN(1000000000),
       maxTotalLockedUsd: new BN(1000000000),
+      priceImpactMult: new BN(0),
+      maxPriceImpact: new BN(0),
     };
     permissions = {
       allowSwap: true,
@@ -250,6 +253,8 @@ describe("perpetuals", () => {
         maxUtilization: "10000",
         maxPositionLockedUsd: "1000000000",
         maxTotalLockedUsd: "1000000000",
+        priceImpactMult: "0",
+        maxPriceImpact: "0",
       },
       permissions: {
         allowSwap: true,
@@ -341,6 +346,7 @@ describe("perpetuals", () => {
       },
       bump: token.bump,
       tokenAccountBump: token.tokenAccountBump,
+      tokenProgram: spl.TOKEN_PROGRAM_ID,
     };
     expect(JSON.stringify(token)).to.equal(JSON.stringify(tokenExpected));
 
@@ -578,7 +584,6 @@ describe("perpetuals", () => {
       "long",
       tc.users[0],
       tc.users[0].tokenAccounts[0],
-      tc.users[0].positionAccountsLong[0],
       tc.custodies[0]
     );
 
@@ -602,6 +607,7 @@ describe("perpetuals", () => {
       cumulativeInterestSnapshot: "0",
       lockedAmount: "7000000000",
       collateralAmount: "1000000000",
+      index: "0",
       bump: position.bump,
     };
 
@@ -649,7 +655,6 @@ describe("perpetuals", () => {
       "long",
       tc.users[0],
       tc.users[0].tokenAccounts[0],
-      tc.users[0].positionAccountsLong[0],
       tc.custodies[0]
     );
     await tc.setCustomOraclePrice(80, tc.custodies[0]);
@@ -157,21 +157,12 @@ export class TestClient {
         );
         tokenAccounts.push(tokenAccount);
 
-        let positionAccount = this.findProgramAddress("position", [
-          wallet.publicKey,
-          this.pool.publicKey,
-          custody.custody,
-          [1],
-        ]).publicKey;
-        positionAccountsLong.push(positionAccount);
-
-        positionAccount = this.findProgramAddress("position", [
-          wallet.publicKey,
-          this.pool.publicKey,
-          custody.custody,
-          [2],
-        ]).publicKey;
-        positionAccountsShort.push(positionAccount);
+        positionAccountsLong.push(
+          this.getPositionKey(wallet.publicKey, custody, "long", 0)
+        );
+        positionAccountsShort.push(
+          this.getPositionKey(wallet.publicKey, custody, "short", 0)
+        );
       }
 
       this.users.push({
@@ -247,6 +238,28 @@ export class TestClient {
     return { publicKey: res[0], bump: res[1] };
   };
 
+  getPositionKey = (
+    owner: PublicKey,
+    custody,
+    side: PositionSide,
+    index: number
+  ) => {
+    return this.findProgramAddress("position", [
+      owner,
+      this.pool.publicKey,
+      custody.custody,
+      side === "long" ? [1] : [2],
+      new BN(index).toArray("le", 8),
+    ]).publicKey;
+  };
+
+  getNextPositionIndex = async (owner: PublicKey) => {
+    let positionCounter = await this.program.account.positionCounter.fetchNullable(
+      this.findProgramAddress("position_counter", [owner]).publicKey
+    );
+    return positionCounter ? positionCounter.nextIndex.toNumber() : 0;
+  };
+
   confirmTx = async (txSignature: anchor.web3.TransactionSignature) => {
     const latestBlockHash = await this.provider.connection.getLatestBlockhash();
 
@@ -634,6 +647,7 @@ export class TestClient {
             pool: this.pool.publicKey,
             custody: custody.custody,
             custodyTokenAccount: custody.tokenAccount,
+            custodyTokenMint: custody.mint.publicKey,
             receivingTokenAccount: receivingTokenAccount,
             tokenProgram: spl.TOKEN_PROGRAM_ID,
           })
@@ -833,10 +847,13 @@ export class TestClient {
           receivingCustody: custodyIn.custody,
           receivingCustodyOracleAccount: custodyIn.oracleAccount,
           receivingCustodyTokenAccount: custodyIn.tokenAccount,
+          receivingCustodyTokenMint: custodyIn.mint.publicKey,
           dispensingCustody: custodyOut.custody,
           dispensingCustodyOracleAccount: custodyOut.oracleAccount,
           dispensingCustodyTokenAccount: custodyOut.tokenAccount,
+          dispensingCustodyTokenMint: custodyOut.mint.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          dispensingTokenProgram: spl.TOKEN_PROGRAM_ID,
         })
         .signers([user.wallet])
         .rpc();
@@ -871,8 +888,10 @@ export class TestClient {
           custody: custody.custody,
           custodyOracleAccount: custody.oracleAccount,
           custodyTokenAccount: custody.tokenAccount,
+          custodyTokenMint: custody.mint.publicKey,
           lpTokenMint: this.lpToken.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          lpTokenProgram: spl.TOKEN_PROGRAM_ID,
         })
         .remainingAccounts(this.custodyMetas)
         .signers([user.wallet])
@@ -908,8 +927,10 @@ export class TestClient {
           custody: custody.custody,
           custodyOracleAccount: custody.oracleAccount,
           custodyTokenAccount: custody.tokenAccount,
+          custodyTokenMint: custody.mint.publicKey,
           lpTokenMint: this.lpToken.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          lpTokenProgram: spl.TOKEN_PROGRAM_ID,
         })
         .remainingAccounts(this.custodyMetas)
         .signers([user.wallet])
@@ -929,9 +950,22 @@ export class TestClient {
     side: PositionSide,
     user,
     fundingAccount: PublicKey,
-    positionAccount: PublicKey,
     custody
   ) => {
+    // new positions get the next index of the owner's position counter
+    let positionAccount = this.getPositionKey(
+      user.wallet.publicKey,
+      custody,
+      side,
+      await this.getNextPositionIndex(user.wallet.publicKey)
+    );
+    let custodyIdx = this.custodies.indexOf(custody);
+    if (side === "long") {
+      user.positionAccountsLong[custodyIdx] = positionAccount;
+    } else {
+      user.positionAccountsShort[custodyIdx] = positionAccount;
+    }
+
     try {
       await this.program.methods
         .openPosition({
@@ -941,19 +975,25 @@ export class TestClient {
           side: side === "long" ? { long: {} } : { short: {} },
         })
         .accounts({
+          authority: user.wallet.publicKey,
           owner: user.wallet.publicKey,
           fundingAccount,
           transferAuthority: this.authority.publicKey,
           perpetuals: this.perpetuals.publicKey,
           pool: this.pool.publicKey,
+          positionCounter: this.findProgramAddress("position_counter", [
+            user.wallet.publicKey,
+          ]).publicKey,
           position: positionAccount,
           custody: custody.custody,
           custodyOracleAccount: custody.oracleAccount,
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
           collateralCustodyTokenAccount: custody.tokenAccount,
+          collateralCustodyTokenMint: custody.mint.publicKey,
           systemProgram: SystemProgram.programId,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          tradingDelegate: null,
         })
         .signers([user.wallet])
         .rpc();
@@ -963,6 +1003,7 @@ export class TestClient {
       }
       throw err;
     }
+    return positionAccount;
   };
 
   addCollateral = async (
@@ -978,6 +1019,7 @@ export class TestClient {
           collateral,
         })
         .accounts({
+          authority: user.wallet.publicKey,
           owner: user.wallet.publicKey,
           fundingAccount,
           transferAuthority: this.authority.publicKey,
@@ -989,7 +1031,9 @@ export class TestClient {
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
           collateralCustodyTokenAccount: custody.tokenAccount,
+          collateralCustodyTokenMint: custody.mint.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          tradingDelegate: null,
         })
         .signers([user.wallet])
         .rpc();
@@ -1014,6 +1058,7 @@ export class TestClient {
           collateralUsd,
         })
         .accounts({
+          authority: user.wallet.publicKey,
           owner: user.wallet.publicKey,
           receivingAccount,
           transferAuthority: this.authority.publicKey,
@@ -1025,7 +1070,9 @@ export class TestClient {
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
           collateralCustodyTokenAccount: custody.tokenAccount,
+          collateralCustodyTokenMint: custody.mint.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          tradingDelegate: null,
         })
         .signers([user.wallet])
         .rpc();
@@ -1050,6 +1097,7 @@ export class TestClient {
           price: new BN(price),
         })
         .accounts({
+          authority: user.wallet.publicKey,
           owner: user.wallet.publicKey,
           receivingAccount,
           transferAuthority: this.authority.publicKey,
@@ -1061,7 +1109,9 @@ export class TestClient {
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
           collateralCustodyTokenAccount: custody.tokenAccount,
+          collateralCustodyTokenMint: custody.mint.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
+          tradingDelegate: null,
         })
         .signers([user.wallet])
         .rpc();
@@ -1095,6 +1145,7 @@ export class TestClient {
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
           collateralCustodyTokenAccount: custody.tokenAccount,
+          collateralCustodyTokenMint: custody.mint.publicKey,
           tokenProgram: spl.TOKEN_PROGRAM_ID,
         })
         .signers([user.wallet])
@@ -1121,6 +1172,7 @@ export class TestClient {
           custodyOracleAccount: custody.oracleAccount,
           collateralCustody: custody.custody,
           collateralCustodyOracleAccount: custody.oracleAccount,
+          owner: this.provider.wallet.publicKey,
         })
         .view();
     } catch (err) {
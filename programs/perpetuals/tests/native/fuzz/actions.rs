@@ -0,0 +1,722 @@
+use {
+    super::{FuzzSetup, STABLE_CUSTODY},
+    crate::{instructions, utils},
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::{
+            AddCollateralParams, AddLiquidityParams, ClosePositionParams, LiquidateParams,
+            OpenPositionParams, RemoveCollateralParams, RemoveLiquidityParams,
+            SetCustomOraclePriceParams, SwapParams,
+        },
+        state::{
+            oracle::CustomOracle,
+            position::{Position, Side},
+        },
+    },
+    perpetuals_client::pda,
+    proptest::prelude::*,
+    solana_program_test::BanksClientError,
+    solana_sdk::signer::{keypair::Keypair, Signer},
+};
+
+// Amounts are generated as a share of the user balance or position, in BPS,
+// so that shrunk sequences remain meaningful. Users can hold several positions
+// per custody and side, `position` selects one of them.
+#[derive(Clone, Debug)]
+pub enum Action {
+    AddLiquidity {
+        user: usize,
+        custody: usize,
+        amount_bps: u64,
+    },
+    RemoveLiquidity {
+        user: usize,
+        custody: usize,
+        lp_amount_bps: u64,
+    },
+    Swap {
+        user: usize,
+        custody_in: usize,
+        custody_out: usize,
+        amount_bps: u64,
+    },
+    OpenPosition {
+        user: usize,
+        custody: usize,
+        side: Side,
+        collateral_bps: u64,
+        leverage: u64,
+    },
+    AddCollateral {
+        user: usize,
+        custody: usize,
+        side: Side,
+        position: usize,
+        amount_bps: u64,
+    },
+    RemoveCollateral {
+        user: usize,
+        custody: usize,
+        side: Side,
+        position: usize,
+        collateral_usd_bps: u64,
+    },
+    ClosePosition {
+        user: usize,
+        custody: usize,
+        side: Side,
+        position: usize,
+    },
+    Liquidate {
+        user: usize,
+        custody: usize,
+        side: Side,
+        position: usize,
+    },
+    MovePrice {
+        custody: usize,
+        change_bps: i64,
+    },
+    Warp {
+        seconds: i64,
+    },
+}
+
+// Positions a single action can select from
+const MAX_POSITIONS: usize = 4;
+
+pub fn action_strategy(users: usize, custodies: usize) -> impl Strategy<Value = Action> {
+    let user = 0..users;
+    let custody = 0..custodies;
+    // positions can't be opened in the stable custody, it is the short collateral
+    let trading_custody = (STABLE_CUSTODY + 1)..custodies;
+    let side = prop_oneof![Just(Side::Long), Just(Side::Short)];
+    let position = 0..MAX_POSITIONS;
+    let bps = 1..=10_000u64;
+
+    prop_oneof![
+        1 => (user.clone(), custody.clone(), bps.clone()).prop_map(|(user, custody, amount_bps)| {
+            Action::AddLiquidity {
+                user,
+                custody,
+                amount_bps,
+            }
+        }),
+        1 => (user.clone(), custody.clone(), bps.clone()).prop_map(
+            |(user, custody, lp_amount_bps)| Action::RemoveLiquidity {
+                user,
+                custody,
+                lp_amount_bps,
+            }
+        ),
+        1 => (user.clone(), custody.clone(), 1..custodies, bps.clone()).prop_map(
+            move |(user, custody_in, offset, amount_bps)| Action::Swap {
+                user,
+                custody_in,
+                custody_out: (custody_in + offset) % custodies,
+                amount_bps,
+            }
+        ),
+        3 => (
+            user.clone(),
+            trading_custody.clone(),
+            side.clone(),
+            1..=2_000u64,
+            1..=12u64
+        )
+            .prop_map(|(user, custody, side, collateral_bps, leverage)| {
+                Action::OpenPosition {
+                    user,
+                    custody,
+                    side,
+                    collateral_bps,
+                    leverage,
+                }
+            }),
+        1 => (
+            user.clone(),
+            trading_custody.clone(),
+            side.clone(),
+            position.clone(),
+            1..=2_000u64
+        )
+            .prop_map(|(user, custody, side, position, amount_bps)| {
+                Action::AddCollateral {
+                    user,
+                    custody,
+                    side,
+                    position,
+                    amount_bps,
+                }
+            }),
+        1 => (
+            user.clone(),
+            trading_custody.clone(),
+            side.clone(),
+            position.clone(),
+            bps
+        )
+            .prop_map(|(user, custody, side, position, collateral_usd_bps)| {
+                Action::RemoveCollateral {
+                    user,
+                    custody,
+                    side,
+                    position,
+                    collateral_usd_bps,
+                }
+            }),
+        2 => (user.clone(), trading_custody.clone(), side.clone(), position.clone()).prop_map(
+            |(user, custody, side, position)| Action::ClosePosition {
+                user,
+                custody,
+                side,
+                position,
+            }
+        ),
+        2 => (user, trading_custody.clone(), side, position).prop_map(
+            |(user, custody, side, position)| Action::Liquidate {
+                user,
+                custody,
+                side,
+                position,
+            }
+        ),
+        3 => (trading_custody, -2_000..=2_000i64)
+            .prop_map(|(custody, change_bps)| Action::MovePrice { custody, change_bps }),
+        1 => (1..=3 * 86_400i64).prop_map(|seconds| Action::Warp { seconds }),
+    ]
+}
+
+// Instruction errors are expected, only invariant violations fail the sequence
+pub async fn execute(
+    setup: &FuzzSetup,
+    action: &Action,
+) -> std::result::Result<(), BanksClientError> {
+    match *action {
+        Action::AddLiquidity {
+            user,
+            custody,
+            amount_bps,
+        } => {
+            let owner = setup.user(user);
+            let mint = setup.mint(custody);
+            let balance = get_balance(setup, &owner.pubkey(), &mint).await;
+
+            let accounts = perpetuals::accounts::AddLiquidity {
+                owner: owner.pubkey(),
+                funding_account: utils::find_associated_token_account(&owner.pubkey(), &mint).0,
+                lp_token_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &setup.test_setup.lp_token_mint_pda,
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                custody_token_account: setup.custody_token_account(custody),
+                custody_token_mint: mint,
+                lp_token_mint: setup.test_setup.lp_token_mint_pda,
+                token_program: anchor_spl::token::ID,
+                lp_token_program: anchor_spl::token::ID,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                with_pool_accounts(setup, accounts.to_account_metas(None)),
+                perpetuals::instruction::AddLiquidity {
+                    params: AddLiquidityParams {
+                        amount_in: get_share(balance, amount_bps),
+                        min_lp_amount_out: 1,
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::RemoveLiquidity {
+            user,
+            custody,
+            lp_amount_bps,
+        } => {
+            let owner = setup.user(user);
+            let mint = setup.mint(custody);
+            let lp_balance =
+                get_balance(setup, &owner.pubkey(), &setup.test_setup.lp_token_mint_pda).await;
+
+            let accounts = perpetuals::accounts::RemoveLiquidity {
+                owner: owner.pubkey(),
+                receiving_account: utils::find_associated_token_account(&owner.pubkey(), &mint).0,
+                lp_token_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &setup.test_setup.lp_token_mint_pda,
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                custody_token_account: setup.custody_token_account(custody),
+                custody_token_mint: mint,
+                lp_token_mint: setup.test_setup.lp_token_mint_pda,
+                token_program: anchor_spl::token::ID,
+                lp_token_program: anchor_spl::token::ID,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                with_pool_accounts(setup, accounts.to_account_metas(None)),
+                perpetuals::instruction::RemoveLiquidity {
+                    params: RemoveLiquidityParams {
+                        lp_amount_in: get_share(lp_balance, lp_amount_bps),
+                        min_amount_out: 1,
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::Swap {
+            user,
+            custody_in,
+            custody_out,
+            amount_bps,
+        } => {
+            let owner = setup.user(user);
+            let mint_in = setup.mint(custody_in);
+            let balance = get_balance(setup, &owner.pubkey(), &mint_in).await;
+
+            let accounts = perpetuals::accounts::Swap {
+                owner: owner.pubkey(),
+                funding_account: utils::find_associated_token_account(&owner.pubkey(), &mint_in).0,
+                receiving_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &setup.mint(custody_out),
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                receiving_custody: setup.custody(custody_in),
+                receiving_custody_oracle_account: setup.oracle(custody_in),
+                receiving_custody_token_account: setup.custody_token_account(custody_in),
+                receiving_custody_token_mint: mint_in,
+                dispensing_custody: setup.custody(custody_out),
+                dispensing_custody_oracle_account: setup.oracle(custody_out),
+                dispensing_custody_token_account: setup.custody_token_account(custody_out),
+                dispensing_custody_token_mint: setup.mint(custody_out),
+                token_program: anchor_spl::token::ID,
+                dispensing_token_program: anchor_spl::token::ID,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::Swap {
+                    params: SwapParams {
+                        amount_in: get_share(balance, amount_bps),
+                        min_amount_out: 1,
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::OpenPosition {
+            user,
+            custody,
+            side,
+            collateral_bps,
+            leverage,
+        } => {
+            let owner = setup.user(user);
+            let collateral_custody = get_collateral_custody(custody, side);
+            let collateral_mint = setup.mint(collateral_custody);
+            let balance = get_balance(setup, &owner.pubkey(), &collateral_mint).await;
+            let collateral = get_share(balance, collateral_bps);
+
+            // size in trading tokens, oracle prices are stored with the token decimals
+            let size = if custody == collateral_custody {
+                collateral.saturating_mul(leverage)
+            } else {
+                let price = get_oracle(setup, custody).await.price;
+                let collateral_price = get_oracle(setup, collateral_custody).await.price;
+                let decimals = setup.decimals(custody);
+                let collateral_decimals = setup.decimals(collateral_custody);
+                let size = collateral as u128
+                    * leverage as u128
+                    * collateral_price as u128
+                    * 10u128.pow(decimals as u32 * 2)
+                    / (price as u128 * 10u128.pow(collateral_decimals as u32 * 2));
+                u64::try_from(size).unwrap_or(u64::MAX)
+            };
+
+            let accounts = perpetuals::accounts::OpenPosition {
+                authority: owner.pubkey(),
+                owner: owner.pubkey(),
+                funding_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &collateral_mint,
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                position_counter: pda::get_position_counter_pda(&owner.pubkey()).0,
+                position: setup.position(
+                    user,
+                    custody,
+                    side,
+                    setup.next_position_index(user).await,
+                ),
+                trading_stats: pda::get_trading_stats_pda(
+                    &owner.pubkey(),
+                    &setup.test_setup.pool_pda,
+                )
+                .0,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                collateral_custody: setup.custody(collateral_custody),
+                collateral_custody_oracle_account: setup.oracle(collateral_custody),
+                collateral_custody_token_account: setup.custody_token_account(collateral_custody),
+                collateral_custody_token_mint: setup.mint(collateral_custody),
+                system_program: anchor_lang::system_program::ID,
+                token_program: anchor_spl::token::ID,
+                trading_delegate: None,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::OpenPosition {
+                    params: OpenPositionParams {
+                        price: if side == Side::Long { u64::MAX } else { 1 },
+                        collateral,
+                        size,
+                        side,
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::AddCollateral {
+            user,
+            custody,
+            side,
+            position,
+            amount_bps,
+        } => {
+            let owner = setup.user(user);
+            let collateral_custody = get_collateral_custody(custody, side);
+            let collateral_mint = setup.mint(collateral_custody);
+            let balance = get_balance(setup, &owner.pubkey(), &collateral_mint).await;
+
+            let accounts = perpetuals::accounts::AddCollateral {
+                authority: owner.pubkey(),
+                owner: owner.pubkey(),
+                funding_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &collateral_mint,
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                position: setup.select_position(user, custody, side, position).await,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                collateral_custody: setup.custody(collateral_custody),
+                collateral_custody_oracle_account: setup.oracle(collateral_custody),
+                collateral_custody_token_account: setup.custody_token_account(collateral_custody),
+                collateral_custody_token_mint: setup.mint(collateral_custody),
+                token_program: anchor_spl::token::ID,
+                trading_delegate: None,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::AddCollateral {
+                    params: AddCollateralParams {
+                        collateral: get_share(balance, amount_bps),
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::RemoveCollateral {
+            user,
+            custody,
+            side,
+            position,
+            collateral_usd_bps,
+        } => {
+            let owner = setup.user(user);
+            let collateral_custody = get_collateral_custody(custody, side);
+            let position_pda = setup.select_position(user, custody, side, position).await;
+            let collateral_usd = utils::try_get_account::<Position>(
+                &setup.test_setup.program_test_ctx,
+                position_pda,
+            )
+            .await
+            .map_or(1, |position| {
+                get_share(position.collateral_usd, collateral_usd_bps)
+            });
+
+            let accounts = perpetuals::accounts::RemoveCollateral {
+                authority: owner.pubkey(),
+                owner: owner.pubkey(),
+                receiving_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &setup.mint(collateral_custody),
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                position: position_pda,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                collateral_custody: setup.custody(collateral_custody),
+                collateral_custody_oracle_account: setup.oracle(collateral_custody),
+                collateral_custody_token_account: setup.custody_token_account(collateral_custody),
+                collateral_custody_token_mint: setup.mint(collateral_custody),
+                token_program: anchor_spl::token::ID,
+                trading_delegate: None,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::RemoveCollateral {
+                    params: RemoveCollateralParams { collateral_usd },
+                },
+            )
+            .await
+        }
+
+        Action::ClosePosition {
+            user,
+            custody,
+            side,
+            position,
+        } => {
+            let owner = setup.user(user);
+            let collateral_custody = get_collateral_custody(custody, side);
+
+            let accounts = perpetuals::accounts::ClosePosition {
+                authority: owner.pubkey(),
+                owner: owner.pubkey(),
+                receiving_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &setup.mint(collateral_custody),
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                position: setup.select_position(user, custody, side, position).await,
+                trading_stats: pda::get_trading_stats_pda(
+                    &owner.pubkey(),
+                    &setup.test_setup.pool_pda,
+                )
+                .0,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                collateral_custody: setup.custody(collateral_custody),
+                collateral_custody_oracle_account: setup.oracle(collateral_custody),
+                collateral_custody_token_account: setup.custody_token_account(collateral_custody),
+                collateral_custody_token_mint: setup.mint(collateral_custody),
+                system_program: anchor_lang::system_program::ID,
+                token_program: anchor_spl::token::ID,
+                trading_delegate: None,
+            };
+
+            execute_ix(
+                setup,
+                owner,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::ClosePosition {
+                    params: ClosePositionParams {
+                        price: if side == Side::Long { 1 } else { u64::MAX },
+                    },
+                },
+            )
+            .await
+        }
+
+        Action::Liquidate {
+            user,
+            custody,
+            side,
+            position,
+        } => {
+            let owner = setup.user(user);
+            let liquidator = setup.liquidator();
+            let collateral_custody = get_collateral_custody(custody, side);
+            let collateral_mint = setup.mint(collateral_custody);
+
+            let accounts = perpetuals::accounts::Liquidate {
+                signer: liquidator.pubkey(),
+                rewards_receiving_account: utils::find_associated_token_account(
+                    &liquidator.pubkey(),
+                    &collateral_mint,
+                )
+                .0,
+                receiving_account: utils::find_associated_token_account(
+                    &owner.pubkey(),
+                    &collateral_mint,
+                )
+                .0,
+                transfer_authority: pda::get_transfer_authority_pda().0,
+                perpetuals: pda::get_perpetuals_pda().0,
+                pool: setup.test_setup.pool_pda,
+                position: setup.select_position(user, custody, side, position).await,
+                custody: setup.custody(custody),
+                custody_oracle_account: setup.oracle(custody),
+                collateral_custody: setup.custody(collateral_custody),
+                collateral_custody_oracle_account: setup.oracle(collateral_custody),
+                collateral_custody_token_account: setup.custody_token_account(collateral_custody),
+                collateral_custody_token_mint: setup.mint(collateral_custody),
+                token_program: anchor_spl::token::ID,
+            };
+
+            execute_ix(
+                setup,
+                liquidator,
+                accounts.to_account_metas(None),
+                perpetuals::instruction::Liquidate {
+                    params: LiquidateParams {},
+                },
+            )
+            .await
+        }
+
+        Action::MovePrice {
+            custody,
+            change_bps,
+        } => {
+            let oracle = get_oracle(setup, custody).await;
+            let price = (oracle.price as i128 * (10_000 + change_bps as i128) / 10_000).max(1);
+
+            set_oracle_price(setup, custody, price as u64).await
+        }
+
+        Action::Warp { seconds } => {
+            utils::warp_forward(&setup.test_setup.program_test_ctx, seconds).await;
+
+            // republish prices so that oracles don't go stale
+            for custody in 0..setup.test_setup.custodies_info.len() {
+                let price = get_oracle(setup, custody).await.price;
+                set_oracle_price(setup, custody, price).await?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+// Shorts use the stable custody as collateral
+fn get_collateral_custody(custody: usize, side: Side) -> usize {
+    if side == Side::Short {
+        STABLE_CUSTODY
+    } else {
+        custody
+    }
+}
+
+fn get_share(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * bps as u128 / 10_000) as u64
+}
+
+async fn get_balance(setup: &FuzzSetup, owner: &Pubkey, mint: &Pubkey) -> u64 {
+    utils::get_token_account_balance(
+        &setup.test_setup.program_test_ctx,
+        utils::find_associated_token_account(owner, mint).0,
+    )
+    .await
+}
+
+async fn get_oracle(setup: &FuzzSetup, custody: usize) -> CustomOracle {
+    utils::get_account::<CustomOracle>(&setup.test_setup.program_test_ctx, setup.oracle(custody))
+        .await
+}
+
+async fn set_oracle_price(
+    setup: &FuzzSetup,
+    custody: usize,
+    price: u64,
+) -> std::result::Result<(), BanksClientError> {
+    let test_setup = &setup.test_setup;
+    let oracle = get_oracle(setup, custody).await;
+    let publish_time = utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await;
+
+    instructions::test_set_custom_oracle_price(
+        &test_setup.program_test_ctx,
+        test_setup.get_multisig_member_keypair_by_name("admin_a"),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &setup.custody(custody),
+        &setup.oracle(custody),
+        SetCustomOraclePriceParams {
+            price,
+            expo: oracle.expo,
+            conf: oracle.conf,
+            ema: price,
+            publish_time,
+        },
+        &test_setup.get_multisig_signers(),
+    )
+    .await
+}
+
+// Adds pool custodies and their oracles as remaining accounts
+fn with_pool_accounts(setup: &FuzzSetup, mut accounts_meta: Vec<AccountMeta>) -> Vec<AccountMeta> {
+    let custodies = &setup.test_setup.custodies_info;
+
+    accounts_meta.extend(custodies.iter().map(|info| AccountMeta {
+        pubkey: info.custody_pda,
+        is_signer: false,
+        is_writable: false,
+    }));
+    accounts_meta.extend(custodies.iter().map(|info| AccountMeta {
+        pubkey: info.custom_oracle_pda,
+        is_signer: false,
+        is_writable: false,
+    }));
+
+    accounts_meta
+}
+
+async fn execute_ix<T: anchor_lang::InstructionData>(
+    setup: &FuzzSetup,
+    signer: &Keypair,
+    accounts_meta: Vec<AccountMeta>,
+    args: T,
+) -> std::result::Result<(), BanksClientError> {
+    let payer = &setup.test_setup.payer_keypair;
+
+    utils::create_and_execute_perpetuals_ix(
+        &setup.test_setup.program_test_ctx,
+        accounts_meta,
+        args,
+        Some(&payer.pubkey()),
+        &[signer, payer],
+        None,
+        None,
+    )
+    .await
+}
@@ -0,0 +1,154 @@
+use {
+    super::{FuzzSetup, MINTS, STABLE_CUSTODY, USERS},
+    crate::{instructions, utils},
+    anchor_lang::prelude::Pubkey,
+    perpetuals::{
+        math,
+        state::{
+            custody::{Custody, PositionStats},
+            perpetuals::Perpetuals,
+            position::{Position, Side},
+        },
+    },
+};
+
+// Checks pool invariants against the current on-chain state
+pub async fn check(setup: &FuzzSetup) -> Result<(), String> {
+    let test_setup = &setup.test_setup;
+    let ctx = &test_setup.program_test_ctx;
+
+    let mut positions: Vec<Position> = Vec::new();
+    for user in 0..USERS.len() {
+        for custody in STABLE_CUSTODY + 1..MINTS.len() {
+            for side in [Side::Long, Side::Short] {
+                positions.extend(
+                    setup
+                        .open_positions(user, custody, side)
+                        .await
+                        .into_iter()
+                        .map(|(_, position)| position),
+                );
+            }
+        }
+    }
+
+    for (idx, mint) in MINTS.iter().enumerate() {
+        let custody_pda = setup.custody(idx);
+        let custody = utils::get_account::<Custody>(ctx, custody_pda).await;
+        let assets = &custody.assets;
+
+        // custody tokens cover pool assets, collateral and protocol fees
+        let balance = utils::get_token_account_balance(ctx, setup.custody_token_account(idx)).await;
+        let reserved =
+            assets.owned as u128 + assets.collateral as u128 + assets.protocol_fees as u128;
+        if (balance as u128) < reserved {
+            return Err(format!(
+                "{}: token balance {} is less than owned + collateral + protocol fees {} ({:?})",
+                mint.name, balance, reserved, assets
+            ));
+        }
+
+        if assets.locked > assets.owned {
+            return Err(format!(
+                "{}: locked {} exceeds owned {}",
+                mint.name, assets.locked, assets.owned
+            ));
+        }
+
+        let collateral: u64 = positions
+            .iter()
+            .filter(|position| position.collateral_custody == custody_pda)
+            .map(|position| position.collateral_amount)
+            .sum();
+        if collateral != assets.collateral {
+            return Err(format!(
+                "{}: collateral {} doesn't match open positions collateral {}",
+                mint.name, assets.collateral, collateral
+            ));
+        }
+
+        for side in [Side::Long, Side::Short] {
+            let stats = if side == Side::Long {
+                custody.long_positions
+            } else {
+                custody.short_positions
+            };
+            let expected = get_position_stats(&positions, &custody_pda, side, &stats)?;
+            if stats != expected {
+                return Err(format!(
+                    "{} {:?} stats don't match open positions\nactual: {:?}\nexpected: {:?}",
+                    mint.name, side, stats, expected
+                ));
+            }
+        }
+    }
+
+    // LP token price is computed from the pool AUM and fails if it goes negative
+    instructions::test_get_lp_token_price(
+        ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &test_setup.lp_token_mint_pda,
+    )
+    .await
+    .map_err(|err| format!("LP token price: {err}"))?;
+
+    Ok(())
+}
+
+// Expected stats from open positions, interest accumulators are taken as is.
+// Positions with a separate collateral custody are counted in both custodies,
+// and their borrowed size is tracked by the collateral custody.
+fn get_position_stats(
+    positions: &[Position],
+    custody: &Pubkey,
+    side: Side,
+    stats: &PositionStats,
+) -> Result<PositionStats, String> {
+    let mut expected = PositionStats {
+        cumulative_interest_usd: stats.cumulative_interest_usd,
+        cumulative_interest_snapshot: stats.cumulative_interest_snapshot,
+        ..PositionStats::default()
+    };
+
+    for position in positions.iter().filter(|position| position.side == side) {
+        if position.custody == *custody || position.collateral_custody == *custody {
+            expected.open_positions += 1;
+        }
+        if position.collateral_custody == *custody {
+            expected.borrow_size_usd += position.borrow_size_usd;
+        }
+        if position.custody == *custody {
+            expected.size_usd += position.size_usd;
+            expected.locked_amount += position.locked_amount;
+
+            let quantity = get_quantity(position)
+                .ok_or_else(|| format!("Invalid position price {}", position.price))?;
+            expected.total_quantity += quantity;
+            expected.weighted_price += position.price as u128 * quantity;
+        }
+    }
+
+    // stats are reset when the last position is removed
+    if expected.open_positions == 0 {
+        return Ok(PositionStats::default());
+    }
+
+    Ok(expected)
+}
+
+// Same as in Custody::add_position
+fn get_quantity(position: &Position) -> Option<u128> {
+    let position_price = math::scale_to_exponent(
+        position.price,
+        -(Perpetuals::PRICE_DECIMALS as i32),
+        -(Perpetuals::USD_DECIMALS as i32),
+    )
+    .ok()?;
+
+    math::checked_div(
+        math::checked_mul(position.size_usd as u128, Perpetuals::BPS_POWER).ok()?,
+        position_price as u128,
+    )
+    .ok()
+}
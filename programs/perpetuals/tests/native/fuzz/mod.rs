@@ -0,0 +1,270 @@
+// Stateful fuzzing of pool invariants.
+//
+// Random sequences of liquidity, swap, position, liquidation and oracle
+// actions are executed against a fresh pool, invariants are checked after
+// every step and failing sequences are shrunk to a minimal reproduction.
+//
+// PROPTEST_CASES sets the number of sequences and PROPTEST_RNG_SEED replays a run.
+
+pub mod actions;
+pub mod invariants;
+
+use {
+    crate::utils,
+    actions::Action,
+    anchor_lang::prelude::Pubkey,
+    maplit::hashmap,
+    perpetuals::state::position::{Position, Side},
+    perpetuals_client::pda,
+    proptest::{
+        collection,
+        test_runner::{Config, TestCaseError, TestCaseResult, TestError, TestRunner},
+    },
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    std::collections::HashMap,
+};
+
+pub struct MintSetup {
+    pub name: &'static str,
+    pub decimals: u8,
+    pub price: u64,
+    pub liquidity: u64,
+    pub user_balance: u64,
+}
+
+// The stable custody is used as collateral for shorts
+pub const STABLE_CUSTODY: usize = 0;
+
+pub const MINTS: [MintSetup; 3] = [
+    MintSetup {
+        name: "usdc",
+        decimals: 6,
+        price: 1,
+        liquidity: 300_000,
+        user_balance: 100_000,
+    },
+    MintSetup {
+        name: "eth",
+        decimals: 9,
+        price: 1_500,
+        liquidity: 200,
+        user_balance: 50,
+    },
+    MintSetup {
+        name: "btc",
+        decimals: 8,
+        price: 30_000,
+        liquidity: 10,
+        user_balance: 3,
+    },
+];
+
+pub const USERS: [&str; 3] = ["alice", "bob", "carol"];
+
+const LIQUIDITY_PROVIDER: &str = "lp";
+const LIQUIDATOR: &str = "keeper";
+
+const DEFAULT_CASES: u32 = 16;
+const MAX_ACTIONS: usize = 40;
+
+pub struct FuzzSetup {
+    pub test_setup: utils::TestSetup,
+}
+
+impl FuzzSetup {
+    pub async fn new() -> FuzzSetup {
+        let balances: HashMap<&str, u64> = MINTS
+            .iter()
+            .map(|mint| (mint.name, utils::scale(mint.user_balance, mint.decimals)))
+            .collect();
+
+        let mut users: Vec<utils::UserParam> = USERS
+            .iter()
+            .map(|name| utils::UserParam {
+                name,
+                token_balances: balances.clone(),
+            })
+            .collect();
+        users.push(utils::UserParam {
+            name: LIQUIDITY_PROVIDER,
+            token_balances: MINTS
+                .iter()
+                .map(|mint| (mint.name, utils::scale(mint.liquidity, mint.decimals)))
+                .collect(),
+        });
+        users.push(utils::UserParam {
+            name: LIQUIDATOR,
+            token_balances: hashmap! {},
+        });
+
+        let test_setup = utils::TestSetup::new(
+            users,
+            MINTS
+                .iter()
+                .map(|mint| utils::MintParam {
+                    name: mint.name,
+                    decimals: mint.decimals,
+                })
+                .collect(),
+            vec!["admin_a"],
+            "fuzz_pool",
+            MINTS
+                .iter()
+                .enumerate()
+                .map(|(idx, mint)| utils::SetupCustodyWithLiquidityParams {
+                    setup_custody_params: utils::SetupCustodyParams {
+                        mint_name: mint.name,
+                        is_stable: idx == STABLE_CUSTODY,
+                        is_virtual: false,
+                        target_ratio: utils::ratio_from_percentage(100.0 / MINTS.len() as f64),
+                        min_ratio: utils::ratio_from_percentage(0.0),
+                        max_ratio: utils::ratio_from_percentage(100.0),
+                        initial_price: utils::scale(mint.price, mint.decimals),
+                        initial_conf: utils::scale_f64(mint.price as f64 / 1_000.0, mint.decimals),
+                        pricing_params: None,
+                        permissions: None,
+                        fees: None,
+                        borrow_rate: None,
+                    },
+                    liquidity_amount: utils::scale(mint.liquidity, mint.decimals),
+                    payer_user_name: LIQUIDITY_PROVIDER,
+                })
+                .collect(),
+        )
+        .await;
+
+        FuzzSetup { test_setup }
+    }
+
+    pub fn user(&self, user: usize) -> &Keypair {
+        self.test_setup.get_user_keypair_by_name(USERS[user])
+    }
+
+    pub fn liquidator(&self) -> &Keypair {
+        self.test_setup.get_user_keypair_by_name(LIQUIDATOR)
+    }
+
+    pub fn mint(&self, custody: usize) -> Pubkey {
+        self.test_setup.get_mint_by_name(MINTS[custody].name)
+    }
+
+    pub fn decimals(&self, custody: usize) -> u8 {
+        MINTS[custody].decimals
+    }
+
+    pub fn custody(&self, custody: usize) -> Pubkey {
+        self.test_setup.custodies_info[custody].custody_pda
+    }
+
+    pub fn oracle(&self, custody: usize) -> Pubkey {
+        self.test_setup.custodies_info[custody].custom_oracle_pda
+    }
+
+    pub fn custody_token_account(&self, custody: usize) -> Pubkey {
+        pda::get_custody_token_account_pda(&self.test_setup.pool_pda, &self.mint(custody)).0
+    }
+
+    pub fn position(&self, user: usize, custody: usize, side: Side, index: u64) -> Pubkey {
+        pda::get_position_pda(
+            &self.user(user).pubkey(),
+            &self.test_setup.pool_pda,
+            &self.custody(custody),
+            side,
+            index,
+        )
+        .0
+    }
+
+    pub async fn next_position_index(&self, user: usize) -> u64 {
+        utils::get_next_position_index(&self.test_setup.program_test_ctx, &self.user(user).pubkey())
+            .await
+    }
+
+    // Open positions of the user, ordered by index
+    pub async fn open_positions(
+        &self,
+        user: usize,
+        custody: usize,
+        side: Side,
+    ) -> Vec<(Pubkey, Position)> {
+        let mut positions = vec![];
+        for index in 0..self.next_position_index(user).await {
+            let position_pda = self.position(user, custody, side, index);
+            if let Some(position) =
+                utils::try_get_account::<Position>(&self.test_setup.program_test_ctx, position_pda)
+                    .await
+            {
+                positions.push((position_pda, position));
+            }
+        }
+        positions
+    }
+
+    // Picks one of the open positions, or a position that doesn't exist if there are none
+    pub async fn select_position(
+        &self,
+        user: usize,
+        custody: usize,
+        side: Side,
+        selector: usize,
+    ) -> Pubkey {
+        let positions = self.open_positions(user, custody, side).await;
+        if positions.is_empty() {
+            self.position(user, custody, side, self.next_position_index(user).await)
+        } else {
+            positions[selector % positions.len()].0
+        }
+    }
+}
+
+pub fn pool_invariants() {
+    let mut config = Config {
+        failure_persistence: None,
+        ..Config::default()
+    };
+    if std::env::var("PROPTEST_CASES").is_err() {
+        config.cases = DEFAULT_CASES;
+    }
+
+    let strategy = collection::vec(
+        actions::action_strategy(USERS.len(), MINTS.len()),
+        1..=MAX_ACTIONS,
+    );
+
+    let result = TestRunner::new(config).run(&strategy, |actions| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run_actions(&actions))
+    });
+
+    match result {
+        Ok(()) => {}
+        Err(TestError::Fail(reason, actions)) => {
+            panic!("{reason}\nMinimal failing sequence: {actions:#?}")
+        }
+        Err(TestError::Abort(reason)) => panic!("Fuzzing aborted: {reason}"),
+    }
+}
+
+async fn run_actions(actions: &[Action]) -> TestCaseResult {
+    let setup = FuzzSetup::new().await;
+
+    invariants::check(&setup)
+        .await
+        .map_err(|err| TestCaseError::fail(format!("Initial state: {err}")))?;
+
+    for (step, action) in actions.iter().enumerate() {
+        utils::refresh_blockhash(&setup.test_setup.program_test_ctx).await;
+
+        // rejected instructions are expected, only the resulting state matters
+        let _ = actions::execute(&setup, action).await;
+
+        invariants::check(&setup)
+            .await
+            .map_err(|err| TestCaseError::fail(format!("Step {step} {action:?}: {err}")))?;
+    }
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{prelude::Pubkey, InstructionData, ToAccountMetas},
     perpetuals::state::{custody::Custody, pool::Pool},
+    perpetuals_client::pda,
     solana_program::instruction::AccountMeta,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
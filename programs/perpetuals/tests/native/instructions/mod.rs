@@ -2,20 +2,43 @@ pub mod get_update_pool_ix;
 pub mod test_add_custody;
 pub mod test_add_liquidity;
 pub mod test_add_pool;
+pub mod test_approve_proposal;
+pub mod test_cancel_proposal;
 pub mod test_close_position;
+pub mod test_create_proposal;
+pub mod test_execute_proposal;
+pub mod test_get_entry_price_and_fee;
+pub mod test_get_exit_price_and_fee;
 pub mod test_get_lp_token_price;
+pub mod test_get_pnl;
 pub mod test_init;
 pub mod test_liquidate;
+pub mod test_migrate_custody;
 pub mod test_open_position;
 pub mod test_remove_liquidity;
 pub mod test_set_custody_config;
 pub mod test_set_custom_oracle_price;
+pub mod test_set_fee_tiers;
+pub mod test_set_role_signers;
+pub mod test_set_timelock_delay;
+pub mod test_set_trading_delegate;
+pub mod test_settle_dark_pool_trade;
 pub mod test_swap;
+pub mod test_transfer_position;
 pub mod test_update_pool_aum;
+pub mod test_upgrade_custody;
+pub mod test_upgrade_multisig;
+pub mod test_upgrade_position;
 
 pub use {
     get_update_pool_ix::*, test_add_custody::*, test_add_liquidity::*, test_add_pool::*,
-    test_close_position::*, test_get_lp_token_price::*, test_init::*, test_liquidate::*,
-    test_open_position::*, test_remove_liquidity::*, test_set_custody_config::*,
-    test_set_custom_oracle_price::*, test_swap::*, test_update_pool_aum::*,
+    test_approve_proposal::*, test_cancel_proposal::*, test_close_position::*,
+    test_create_proposal::*, test_execute_proposal::*, test_get_entry_price_and_fee::*,
+    test_get_exit_price_and_fee::*, test_get_lp_token_price::*, test_get_pnl::*, test_init::*,
+    test_liquidate::*, test_migrate_custody::*, test_open_position::*, test_remove_liquidity::*,
+    test_set_custody_config::*, test_set_custom_oracle_price::*, test_set_fee_tiers::*,
+    test_set_role_signers::*, test_set_timelock_delay::*, test_set_trading_delegate::*,
+    test_settle_dark_pool_trade::*, test_swap::*, test_transfer_position::*,
+    test_update_pool_aum::*, test_upgrade_custody::*, test_upgrade_multisig::*,
+    test_upgrade_position::*,
 };
@@ -1,5 +1,5 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{
         prelude::{AccountMeta, Pubkey},
         ToAccountMetas,
@@ -8,6 +8,7 @@ use {
         instructions::AddCustodyParams,
         state::{custody::Custody, multisig::Multisig, pool::Pool},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -32,6 +33,8 @@ pub async fn test_add_custody(
     let (custody_token_account_pda, custody_token_account_bump) =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint);
 
+    let token_program = utils::get_token_program(program_test_ctx, *custody_token_mint).await;
+
     let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
 
     // One Tx per multisig signer
@@ -49,7 +52,7 @@ pub async fn test_add_custody(
                 custody_token_account: custody_token_account_pda,
                 custody_token_mint: *custody_token_mint,
                 system_program: anchor_lang::system_program::ID,
-                token_program: anchor_spl::token::ID,
+                token_program,
                 rent: solana_program::sysvar::rent::ID,
             };
 
@@ -86,6 +89,7 @@ pub async fn test_add_custody(
         assert_eq!(custody_account.pool, *pool_pda);
         assert_eq!(custody_account.mint, *custody_token_mint);
         assert_eq!(custody_account.token_account, custody_token_account_pda);
+        assert_eq!(custody_account.token_program, token_program);
         assert_eq!(custody_account.decimals, custody_token_decimals);
         assert_eq!(custody_account.is_stable, params.is_stable);
         assert_eq!(custody_account.oracle, params.oracle);
@@ -1,5 +1,5 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{
         prelude::{AccountMeta, Pubkey},
         ToAccountMetas,
@@ -8,6 +8,7 @@ use {
         instructions::AddLiquidityParams,
         state::{custody::Custody, pool::Pool},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -30,13 +31,24 @@ pub async fn test_add_liquidity(
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
     let lp_token_mint_pda = pda::get_lp_token_mint_pda(pool_pda).0;
 
-    let funding_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
-    let lp_token_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), &lp_token_mint_pda).0;
-
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
+    let lp_token_program = utils::get_account::<Pool>(program_test_ctx, *pool_pda)
+        .await
+        .lp_token_program;
+
+    let funding_account_address = utils::find_associated_token_account_for_program(
+        &owner.pubkey(),
+        custody_token_mint,
+        &custody_account.token_program,
+    )
+    .0;
+    let lp_token_account_address = utils::find_associated_token_account_for_program(
+        &owner.pubkey(),
+        &lp_token_mint_pda,
+        &lp_token_program,
+    )
+    .0;
 
     // Save account state before tx execution
     let owner_funding_account_before =
@@ -57,8 +69,10 @@ pub async fn test_add_liquidity(
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
             custody_token_account: custody_token_account_pda,
+            custody_token_mint: *custody_token_mint,
             lp_token_mint: lp_token_mint_pda,
-            token_program: anchor_spl::token::ID,
+            token_program: custody_account.token_program,
+            lp_token_program,
         };
 
         let mut accounts_meta = accounts.to_account_metas(None);
@@ -1,10 +1,11 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{prelude::AccountMeta, ToAccountMetas},
     perpetuals::{
         instructions::AddPoolParams,
         state::{multisig::Multisig, perpetuals::Perpetuals, pool::Pool},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     std::str::FromStr,
@@ -31,7 +32,7 @@ pub async fn test_add_pool(
     let multisig_pda = pda::get_multisig_pda().0;
     let transfer_authority_pda = pda::get_transfer_authority_pda().0;
     let perpetuals_pda = pda::get_perpetuals_pda().0;
-    let (pool_pda, pool_bump) = pda::get_pool_pda(String::from_str(pool_name).unwrap());
+    let (pool_pda, pool_bump) = pda::get_pool_pda(pool_name);
     let (lp_token_mint_pda, lp_token_mint_bump) = pda::get_lp_token_mint_pda(&pool_pda);
 
     let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
@@ -86,6 +87,7 @@ pub async fn test_add_pool(
     assert_eq!(pool_account.name.as_str(), pool_name);
     assert_eq!(pool_account.bump, pool_bump);
     assert_eq!(pool_account.lp_token_bump, lp_token_mint_bump);
+    assert_eq!(pool_account.lp_token_program, anchor_spl::token::ID);
 
     let perpetuals_account =
         utils::get_account::<Perpetuals>(program_test_ctx, perpetuals_pda).await;
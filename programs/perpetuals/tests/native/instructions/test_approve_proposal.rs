@@ -0,0 +1,50 @@
+use {
+    crate::utils,
+    perpetuals::{
+        instructions::ApproveProposalParams,
+        state::{multisig::AdminRole, proposal::Proposal},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_approve_proposal(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+    role: AdminRole,
+    id: u64,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let proposal_pda = pda::get_proposal_pda(role, id).0;
+    let proposal_before = utils::get_account::<Proposal>(program_test_ctx, proposal_pda).await;
+
+    let ix =
+        client_instructions::approve_proposal(&admin.pubkey(), role, id, ApproveProposalParams {});
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::ApproveProposal {
+            params: ApproveProposalParams {},
+        },
+        Some(&payer.pubkey()),
+        &[admin, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let proposal = utils::get_account::<Proposal>(program_test_ctx, proposal_pda).await;
+
+    assert_eq!(proposal.num_approvals, proposal_before.num_approvals + 1);
+    assert_eq!(
+        proposal.approvals[proposal_before.num_approvals as usize],
+        admin.pubkey()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use {
+    crate::utils,
+    perpetuals::{
+        instructions::CancelProposalParams,
+        state::{multisig::AdminRole, proposal::Proposal},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+// Returns true if the proposal has been closed
+pub async fn test_cancel_proposal(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+    role: AdminRole,
+    id: u64,
+) -> std::result::Result<bool, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let proposal_pda = pda::get_proposal_pda(role, id).0;
+    let proposal = utils::get_account::<Proposal>(program_test_ctx, proposal_pda).await;
+
+    let ix = client_instructions::cancel_proposal(
+        &admin.pubkey(),
+        role,
+        id,
+        &proposal.proposer,
+        CancelProposalParams {},
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::CancelProposal {
+            params: CancelProposalParams {},
+        },
+        Some(&payer.pubkey()),
+        &[admin, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(
+        utils::try_get_account::<Proposal>(program_test_ctx, proposal_pda)
+            .await
+            .is_none(),
+    )
+}
@@ -1,8 +1,9 @@
 use {
-    super::get_update_pool_ix,
-    crate::utils::{self, pda},
+    super::{get_trading_delegate, get_update_pool_ix},
+    crate::utils,
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
     perpetuals::{instructions::ClosePositionParams, state::custody::Custody},
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -16,6 +17,32 @@ pub async fn test_close_position(
     custody_token_mint: &Pubkey,
     position_pda: &Pubkey,
     params: ClosePositionParams,
+) -> std::result::Result<(), BanksClientError> {
+    test_delegated_close_position(
+        program_test_ctx,
+        owner,
+        &owner.pubkey(),
+        payer,
+        pool_pda,
+        custody_token_mint,
+        position_pda,
+        params,
+    )
+    .await
+}
+
+// Closes a position of `owner` signed by `authority`, either the owner or its trading delegate.
+// Funds are always returned to the owner.
+#[allow(clippy::too_many_arguments)]
+pub async fn test_delegated_close_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    authority: &Keypair,
+    owner: &Pubkey,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    position_pda: &Pubkey,
+    params: ClosePositionParams,
 ) -> std::result::Result<(), BanksClientError> {
     // ==== WHEN ==============================================================
 
@@ -26,12 +53,16 @@ pub async fn test_close_position(
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
 
-    let receiving_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
-
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    let receiving_account_address = utils::find_associated_token_account_for_program(
+        owner,
+        custody_token_mint,
+        &custody_account.token_program,
+    )
+    .0;
+
     // Save account state before tx execution
     let owner_receiving_account_before =
         utils::get_token_account(program_test_ctx, receiving_account_address).await;
@@ -42,23 +73,28 @@ pub async fn test_close_position(
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::ClosePosition {
-            owner: owner.pubkey(),
+            authority: authority.pubkey(),
+            owner: *owner,
             receiving_account: receiving_account_address,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
             position: *position_pda,
+            trading_stats: pda::get_trading_stats_pda(owner, pool_pda).0,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
-            token_program: anchor_spl::token::ID,
+            collateral_custody_token_mint: *custody_token_mint,
+            system_program: anchor_lang::system_program::ID,
+            token_program: custody_account.token_program,
+            trading_delegate: get_trading_delegate(&authority.pubkey(), owner, pool_pda),
         }
         .to_account_metas(None),
         perpetuals::instruction::ClosePosition { params },
         Some(&payer.pubkey()),
-        &[owner, payer],
+        &[authority, payer],
         Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
         Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
     )
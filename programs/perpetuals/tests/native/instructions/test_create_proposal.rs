@@ -0,0 +1,75 @@
+use {
+    crate::utils,
+    anchor_lang::ToAccountMetas,
+    perpetuals::{
+        instructions::CreateProposalParams,
+        state::{
+            multisig::{AdminRole, Multisig},
+            proposal::{Proposal, ProposalAccount},
+        },
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::{
+        instruction::Instruction,
+        signer::{keypair::Keypair, Signer},
+    },
+    tokio::sync::RwLock,
+};
+
+// Proposes the admin instruction, built with the timelock authority of the role as admin
+pub async fn test_create_proposal(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    proposer: &Keypair,
+    payer: &Keypair,
+    role: AdminRole,
+    instruction: Instruction,
+) -> std::result::Result<u64, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_role_multisig_pda(role).0;
+    let id = utils::get_account::<Multisig>(program_test_ctx, multisig_pda)
+        .await
+        .num_proposals;
+
+    let params = CreateProposalParams {
+        id,
+        instruction_data: instruction.data,
+        instruction_accounts: instruction
+            .accounts
+            .iter()
+            .map(|account| ProposalAccount {
+                pubkey: account.pubkey,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+    };
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::CreateProposal {
+            admin: proposer.pubkey(),
+            multisig: multisig_pda,
+            perpetuals: pda::get_perpetuals_pda().0,
+            proposal: pda::get_proposal_pda(role, id).0,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::CreateProposal { params },
+        Some(&payer.pubkey()),
+        &[proposer, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let proposal =
+        utils::get_account::<Proposal>(program_test_ctx, pda::get_proposal_pda(role, id).0).await;
+
+    assert_eq!(proposal.id, id);
+    assert_eq!(proposal.proposer, proposer.pubkey());
+    assert_eq!(proposal.approvals[0], proposer.pubkey());
+    assert_eq!(proposal.num_approvals, 1);
+
+    Ok(id)
+}
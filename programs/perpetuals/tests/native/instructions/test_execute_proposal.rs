@@ -0,0 +1,53 @@
+use {
+    crate::utils,
+    perpetuals::{
+        instructions::ExecuteProposalParams,
+        state::{multisig::AdminRole, proposal::Proposal},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_execute_proposal(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    executor: &Keypair,
+    payer: &Keypair,
+    role: AdminRole,
+    id: u64,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let proposal_pda = pda::get_proposal_pda(role, id).0;
+    let proposal = utils::get_account::<Proposal>(program_test_ctx, proposal_pda).await;
+
+    let ix = client_instructions::execute_proposal(
+        &executor.pubkey(),
+        role,
+        &proposal,
+        ExecuteProposalParams {},
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::ExecuteProposal {
+            params: ExecuteProposalParams {},
+        },
+        Some(&payer.pubkey()),
+        &[executor, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // Executed proposals are closed
+    assert!(
+        utils::try_get_account::<Proposal>(program_test_ctx, proposal_pda)
+            .await
+            .is_none()
+    );
+
+    Ok(())
+}
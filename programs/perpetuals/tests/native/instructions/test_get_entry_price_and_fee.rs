@@ -0,0 +1,48 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetEntryPriceAndFeeParams,
+        state::{custody::Custody, perpetuals::NewPositionPricesAndFee},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+// Quotes a new position of `owner`, `trading_stats` is passed to get the fee discount
+pub async fn test_get_entry_price_and_fee(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    trading_stats: Option<Pubkey>,
+    params: GetEntryPriceAndFeeParams,
+) -> std::result::Result<NewPositionPricesAndFee, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let custody = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+
+    let result: NewPositionPricesAndFee = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        perpetuals::accounts::GetEntryPriceAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool_pda,
+            custody: custody_pda,
+            custody_oracle_account: custody.oracle.oracle_account,
+            collateral_custody: custody_pda,
+            collateral_custody_oracle_account: custody.oracle.oracle_account,
+            owner: *owner,
+            trading_stats,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::GetEntryPriceAndFee { params },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
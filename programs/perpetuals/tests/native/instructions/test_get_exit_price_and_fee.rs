@@ -0,0 +1,50 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetExitPriceAndFeeParams,
+        state::{custody::Custody, perpetuals::PriceAndFee, position::Position},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+// Quotes the position close, `trading_stats` is passed to get the fee discount
+pub async fn test_get_exit_price_and_fee(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+    trading_stats: Option<Pubkey>,
+) -> std::result::Result<PriceAndFee, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let position = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let custody = utils::get_account::<Custody>(program_test_ctx, position.custody).await;
+    let collateral_custody =
+        utils::get_account::<Custody>(program_test_ctx, position.collateral_custody).await;
+
+    let result: PriceAndFee = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        perpetuals::accounts::GetExitPriceAndFee {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool_pda,
+            position: *position_pda,
+            custody: position.custody,
+            custody_oracle_account: custody.oracle.oracle_account,
+            collateral_custody: position.collateral_custody,
+            collateral_custody_oracle_account: collateral_custody.oracle.oracle_account,
+            trading_stats,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::GetExitPriceAndFee {
+            params: GetExitPriceAndFeeParams {},
+        },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
@@ -1,10 +1,11 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
     perpetuals::{
         instructions::GetLpTokenPriceParams,
         state::{custody::Custody, pool::Pool},
     },
+    perpetuals_client::pda,
     solana_program::instruction::AccountMeta,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::keypair::Keypair,
@@ -0,0 +1,47 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::GetPnlParams,
+        state::{custody::Custody, perpetuals::ProfitAndLoss, position::Position},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::keypair::Keypair,
+    tokio::sync::RwLock,
+};
+
+pub async fn test_get_pnl(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+) -> std::result::Result<ProfitAndLoss, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let position = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+    let custody = utils::get_account::<Custody>(program_test_ctx, position.custody).await;
+    let collateral_custody =
+        utils::get_account::<Custody>(program_test_ctx, position.collateral_custody).await;
+
+    let result: ProfitAndLoss = utils::create_and_simulate_perpetuals_view_ix(
+        program_test_ctx,
+        perpetuals::accounts::GetPnl {
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool_pda,
+            position: *position_pda,
+            custody: position.custody,
+            custody_oracle_account: custody.oracle.oracle_account,
+            collateral_custody: position.collateral_custody,
+            collateral_custody_oracle_account: collateral_custody.oracle.oracle_account,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::GetPnl {
+            params: GetPnlParams {},
+        },
+        payer,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    Ok(result)
+}
@@ -1,10 +1,11 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{prelude::AccountMeta, ToAccountMetas},
     perpetuals::{
         instructions::InitParams,
         state::{multisig::Multisig, perpetuals::Perpetuals},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -1,11 +1,12 @@
 use {
     super::get_update_pool_ix,
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
     perpetuals::{
         instructions::LiquidateParams,
         state::{custody::Custody, position::Position},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -33,15 +34,23 @@ pub async fn test_liquidate(
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
 
-    let receiving_account_address =
-        utils::find_associated_token_account(&owner, custody_token_mint).0;
-
-    let rewards_receiving_account_address =
-        utils::find_associated_token_account(&liquidator.pubkey(), custody_token_mint).0;
-
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    let receiving_account_address = utils::find_associated_token_account_for_program(
+        &owner,
+        custody_token_mint,
+        &custody_account.token_program,
+    )
+    .0;
+
+    let rewards_receiving_account_address = utils::find_associated_token_account_for_program(
+        &liquidator.pubkey(),
+        custody_token_mint,
+        &custody_account.token_program,
+    )
+    .0;
+
     // Save account state before tx execution
     let receiving_account_before =
         utils::get_token_account(program_test_ctx, receiving_account_address).await;
@@ -65,7 +74,8 @@ pub async fn test_liquidate(
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
-            token_program: anchor_spl::token::ID,
+            collateral_custody_token_mint: *custody_token_mint,
+            token_program: custody_account.token_program,
         }
         .to_account_metas(None),
         perpetuals::instruction::Liquidate {
@@ -0,0 +1,79 @@
+use {
+    crate::utils,
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::MigrateCustodyParams,
+        state::{custody::Custody, multisig::Multisig},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_migrate_custody(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<Custody, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::MigrateCustody {
+                admin: signer.pubkey(),
+                multisig: multisig_pda,
+                pool: *pool_pda,
+                custody: *custody_pda,
+                system_program: anchor_lang::system_program::ID,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            accounts_meta.push(AccountMeta {
+                pubkey: signer.pubkey(),
+                is_signer: true,
+                is_writable: false,
+            });
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::MigrateCustody {
+                params: MigrateCustodyParams {},
+            },
+            Some(&payer.pubkey()),
+            &[signer, payer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    // The account is resized to the current layout
+    let custody_account = {
+        let mut ctx = program_test_ctx.write().await;
+        ctx.banks_client
+            .get_account(*custody_pda)
+            .await
+            .unwrap()
+            .unwrap()
+    };
+    assert_eq!(custody_account.data.len(), Custody::LEN);
+
+    Ok(utils::get_account::<Custody>(program_test_ctx, *custody_pda).await)
+}
@@ -1,11 +1,12 @@
 use {
-    super::get_update_pool_ix,
-    crate::utils::{self, pda},
+    super::{get_trading_delegate, get_update_pool_ix},
+    crate::utils,
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
     perpetuals::{
         instructions::OpenPositionParams,
         state::{custody::Custody, position::Position},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -18,6 +19,28 @@ pub async fn test_open_position(
     pool_pda: &Pubkey,
     custody_token_mint: &Pubkey,
     params: OpenPositionParams,
+) -> std::result::Result<(solana_sdk::pubkey::Pubkey, u8), BanksClientError> {
+    test_delegated_open_position(
+        program_test_ctx,
+        owner,
+        &owner.pubkey(),
+        payer,
+        pool_pda,
+        custody_token_mint,
+        params,
+    )
+    .await
+}
+
+// Opens a position of `owner` signed by `authority`, either the owner or its trading delegate
+pub async fn test_delegated_open_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    authority: &Keypair,
+    owner: &Pubkey,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    params: OpenPositionParams,
 ) -> std::result::Result<(solana_sdk::pubkey::Pubkey, u8), BanksClientError> {
     // ==== WHEN ==============================================================
 
@@ -28,42 +51,56 @@ pub async fn test_open_position(
     let custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, custody_token_mint).0;
 
+    let position_index = utils::get_next_position_index(program_test_ctx, owner).await;
     let (position_pda, position_bump) =
-        pda::get_position_pda(&owner.pubkey(), pool_pda, &custody_pda, params.side);
+        pda::get_position_pda(owner, pool_pda, &custody_pda, params.side, position_index);
 
-    let funding_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), custody_token_mint).0;
+    let trading_stats_pda = pda::get_trading_stats_pda(owner, pool_pda).0;
 
     let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
     let custody_oracle_account_address = custody_account.oracle.oracle_account;
 
+    let funding_account_address = utils::find_associated_token_account_for_program(
+        owner,
+        custody_token_mint,
+        &custody_account.token_program,
+    )
+    .0;
+
     // Save account state before tx execution
     let owner_funding_account_before =
         utils::get_token_account(program_test_ctx, funding_account_address).await;
     let custody_token_account_before =
         utils::get_token_account(program_test_ctx, custody_token_account_pda).await;
+    let custody_withheld_amount_before =
+        utils::get_token_account_withheld_amount(program_test_ctx, custody_token_account_pda).await;
 
     utils::create_and_execute_perpetuals_ix(
         program_test_ctx,
         perpetuals::accounts::OpenPosition {
-            owner: owner.pubkey(),
+            authority: authority.pubkey(),
+            owner: *owner,
             funding_account: funding_account_address,
             transfer_authority: transfer_authority_pda,
             perpetuals: perpetuals_pda,
             pool: *pool_pda,
+            position_counter: pda::get_position_counter_pda(owner).0,
             position: position_pda,
+            trading_stats: trading_stats_pda,
             custody: custody_pda,
             custody_oracle_account: custody_oracle_account_address,
             collateral_custody: custody_pda,
             collateral_custody_oracle_account: custody_oracle_account_address,
             collateral_custody_token_account: custody_token_account_pda,
+            collateral_custody_token_mint: *custody_token_mint,
             system_program: anchor_lang::system_program::ID,
-            token_program: anchor_spl::token::ID,
+            token_program: custody_account.token_program,
+            trading_delegate: get_trading_delegate(&authority.pubkey(), owner, pool_pda),
         }
         .to_account_metas(None),
         perpetuals::instruction::OpenPosition { params },
         Some(&payer.pubkey()),
-        &[owner, payer],
+        &[authority, payer],
         Some(get_update_pool_ix(program_test_ctx, payer, pool_pda).await?),
         None,
     )
@@ -85,7 +122,7 @@ pub async fn test_open_position(
     {
         let position_account = utils::get_account::<Position>(program_test_ctx, position_pda).await;
 
-        assert_eq!(position_account.owner, owner.pubkey());
+        assert_eq!(position_account.owner, *owner);
         assert_eq!(position_account.pool, *pool_pda);
         assert_eq!(position_account.custody, custody_pda);
         // Need to handle test/not test case
@@ -97,8 +134,22 @@ pub async fn test_open_position(
         assert_eq!(position_account.side, params.side);
         assert_eq!(position_account.unrealized_profit_usd, 0);
         assert_eq!(position_account.unrealized_loss_usd, 0);
-        assert_eq!(position_account.collateral_amount, params.collateral);
+        // Token-2022 transfer fee, if any, is paid out of the collateral
+        let transfer_fee =
+            utils::get_token_account_withheld_amount(program_test_ctx, custody_token_account_pda)
+                .await
+                - custody_withheld_amount_before;
+        assert_eq!(
+            position_account.collateral_amount,
+            params.collateral - transfer_fee
+        );
+        assert_eq!(position_account.index, position_index);
         assert_eq!(position_account.bump, position_bump);
+
+        assert_eq!(
+            utils::get_next_position_index(program_test_ctx, owner).await,
+            position_index + 1
+        );
     }
 
     Ok((position_pda, position_bump))
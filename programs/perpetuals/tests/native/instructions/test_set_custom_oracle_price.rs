@@ -1,5 +1,5 @@
 use {
-    crate::utils::{self, pda},
+    crate::utils,
     anchor_lang::{
         prelude::{AccountMeta, Pubkey},
         ToAccountMetas,
@@ -8,6 +8,7 @@ use {
         instructions::SetCustomOraclePriceParams,
         state::{multisig::Multisig, oracle::CustomOracle},
     },
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
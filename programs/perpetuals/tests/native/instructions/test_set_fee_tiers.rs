@@ -0,0 +1,73 @@
+use {
+    crate::utils,
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    perpetuals::{
+        instructions::SetFeeTiersParams,
+        state::{multisig::Multisig, pool::Pool},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_fee_tiers(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    params: SetFeeTiersParams,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::SetFeeTiers {
+                admin: signer.pubkey(),
+                multisig: multisig_pda,
+                pool: *pool_pda,
+                system_program: anchor_lang::system_program::ID,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            accounts_meta.push(AccountMeta {
+                pubkey: signer.pubkey(),
+                is_signer: true,
+                is_writable: false,
+            });
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::SetFeeTiers {
+                params: SetFeeTiersParams {
+                    fee_tiers: params.fee_tiers.clone(),
+                },
+            },
+            Some(&payer.pubkey()),
+            &[signer, payer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+
+    assert_eq!(pool_account.fee_tiers, params.fee_tiers);
+
+    Ok(())
+}
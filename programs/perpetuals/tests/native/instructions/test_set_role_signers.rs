@@ -0,0 +1,86 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::AccountMeta, ToAccountMetas},
+    perpetuals::{instructions::SetRoleSignersParams, state::multisig::Multisig},
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_role_signers(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    params: SetRoleSignersParams,
+    role_signers: &[&Keypair],
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let role_multisig_pda = pda::get_role_multisig_pda(params.role).0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::SetRoleSigners {
+                admin: signer.pubkey(),
+                multisig: multisig_pda,
+                role_multisig: role_multisig_pda,
+                system_program: anchor_lang::system_program::ID,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            for role_signer in role_signers {
+                accounts_meta.push(AccountMeta {
+                    pubkey: role_signer.pubkey(),
+                    is_signer: false,
+                    is_writable: false,
+                });
+            }
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::SetRoleSigners {
+                params: SetRoleSignersParams {
+                    role: params.role,
+                    min_signatures: params.min_signatures,
+                },
+            },
+            Some(&payer.pubkey()),
+            &[signer, payer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+    let role_multisig_account =
+        utils::get_account::<Multisig>(program_test_ctx, role_multisig_pda).await;
+
+    assert_eq!(role_multisig_account.role, params.role as u8);
+    assert_eq!(
+        role_multisig_account.num_signers as usize,
+        role_signers.len()
+    );
+    assert_eq!(role_multisig_account.min_signatures, params.min_signatures);
+    for role_signer in role_signers {
+        assert!(role_multisig_account
+            .is_signer(&role_signer.pubkey())
+            .unwrap());
+    }
+    assert_eq!({ role_multisig_account.timelock_delay }, {
+        multisig_account.timelock_delay
+    });
+
+    Ok(())
+}
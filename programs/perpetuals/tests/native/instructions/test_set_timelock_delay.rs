@@ -0,0 +1,81 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::AccountMeta, ToAccountMetas},
+    perpetuals::{
+        instructions::SetTimelockDelayParams,
+        state::multisig::{AdminRole, Multisig},
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_timelock_delay(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    params: SetTimelockDelayParams,
+    multisig_signers: &[&Keypair],
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // One Tx per multisig signer
+    for i in 0..multisig_account.min_signatures {
+        let signer: &Keypair = multisig_signers[i as usize];
+
+        let accounts_meta = {
+            let accounts = perpetuals::accounts::SetTimelockDelay {
+                admin: signer.pubkey(),
+                multisig: multisig_pda,
+            };
+
+            let mut accounts_meta = accounts.to_account_metas(None);
+
+            for role in AdminRole::TIMELOCKED_ROLES {
+                accounts_meta.push(AccountMeta {
+                    pubkey: pda::get_role_multisig_pda(role).0,
+                    is_signer: false,
+                    is_writable: true,
+                });
+            }
+
+            accounts_meta
+        };
+
+        utils::create_and_execute_perpetuals_ix(
+            program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::SetTimelockDelay {
+                params: SetTimelockDelayParams {
+                    timelock_delay: params.timelock_delay,
+                },
+            },
+            Some(&payer.pubkey()),
+            &[signer, payer],
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    // ==== THEN ==============================================================
+    let multisig_account = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    assert_eq!({ multisig_account.timelock_delay }, params.timelock_delay);
+
+    for role in AdminRole::TIMELOCKED_ROLES {
+        if let Some(role_multisig_account) =
+            utils::try_get_account::<Multisig>(program_test_ctx, pda::get_role_multisig_pda(role).0)
+                .await
+        {
+            assert_eq!(
+                { role_multisig_account.timelock_delay },
+                params.timelock_delay
+            );
+        }
+    }
+
+    Ok(())
+}
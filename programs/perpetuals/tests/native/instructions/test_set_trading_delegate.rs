@@ -0,0 +1,63 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::{
+        instructions::SetTradingDelegateParams, state::trading_delegate::TradingDelegate,
+    },
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_set_trading_delegate(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    params: SetTradingDelegateParams,
+) -> std::result::Result<(), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let trading_delegate_pda = pda::get_trading_delegate_pda(&owner.pubkey(), pool_pda).0;
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::SetTradingDelegate {
+            owner: owner.pubkey(),
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool_pda,
+            trading_delegate: trading_delegate_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::SetTradingDelegate { params },
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    let trading_delegate =
+        utils::get_account::<TradingDelegate>(program_test_ctx, trading_delegate_pda).await;
+
+    assert_eq!(trading_delegate.owner, owner.pubkey());
+    assert_eq!(trading_delegate.pool, *pool_pda);
+    assert_eq!(trading_delegate.delegate, params.delegate);
+    assert_eq!(trading_delegate.permissions, params.permissions);
+    assert_eq!(trading_delegate.max_size_usd, params.max_size_usd);
+    assert_eq!(trading_delegate.max_leverage, params.max_leverage);
+    assert_eq!(trading_delegate.expiration_time, params.expiration_time);
+
+    Ok(())
+}
+
+// Trading delegate account is only passed when the instruction isn't signed by the owner
+pub fn get_trading_delegate(
+    authority: &Pubkey,
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+) -> Option<Pubkey> {
+    (authority != owner).then(|| pda::get_trading_delegate_pda(owner, pool_pda).0)
+}
@@ -0,0 +1,280 @@
+use {
+    crate::utils,
+    anchor_lang::prelude::{AccountMeta, Pubkey},
+    perpetuals::{
+        instructions::{SettleDarkPoolTradeParams, DARKPOOL_PROGRAM_ID},
+        state::{
+            custody::Custody,
+            position::{Position, Side},
+        },
+    },
+    perpetuals_client::{
+        instructions::{self as client_instructions, CustodyKeys},
+        pda,
+    },
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::{
+        instruction::Instruction,
+        signer::{keypair::Keypair, Signer},
+    },
+    tokio::sync::RwLock,
+};
+
+// Collateral token account of the owner, the darkpool authority must be allowed to transfer from it
+pub async fn get_dark_pool_funding_account(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+) -> Pubkey {
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+
+    utils::find_associated_token_account_for_program(
+        owner,
+        collateral_custody_token_mint,
+        &collateral_custody_account.token_program,
+    )
+    .0
+}
+
+// Builds the perpetuals instruction the darkpool program invokes to settle a trade
+#[allow(clippy::too_many_arguments)]
+pub async fn get_settle_dark_pool_trade_ix(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    long_owner: &Pubkey,
+    short_owner: &Pubkey,
+    funding_account_long: &Pubkey,
+    funding_account_short: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: SettleDarkPoolTradeParams,
+) -> Instruction {
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let collateral_custody_account =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+
+    client_instructions::settle_dark_pool_trade(
+        &payer.pubkey(),
+        long_owner,
+        short_owner,
+        funding_account_long,
+        funding_account_short,
+        pool_pda,
+        &CustodyKeys::from(&custody_account),
+        &CustodyKeys::from(&collateral_custody_account),
+        utils::get_next_position_index(program_test_ctx, long_owner).await,
+        utils::get_next_position_index(program_test_ctx, short_owner).await,
+        params,
+    )
+}
+
+// Settles the trade the way the darkpool program does, through a CPI signed by its PDA
+#[allow(clippy::too_many_arguments)]
+pub async fn test_settle_dark_pool_trade(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    long_owner: &Pubkey,
+    short_owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_token_mint: &Pubkey,
+    collateral_custody_token_mint: &Pubkey,
+    params: SettleDarkPoolTradeParams,
+) -> std::result::Result<(Pubkey, Pubkey), BanksClientError> {
+    // ==== WHEN ==============================================================
+    let custody_pda = pda::get_custody_pda(pool_pda, custody_token_mint).0;
+    let collateral_custody_pda = pda::get_custody_pda(pool_pda, collateral_custody_token_mint).0;
+    let collateral_custody_token_account_pda =
+        pda::get_custody_token_account_pda(pool_pda, collateral_custody_token_mint).0;
+
+    let index_long = utils::get_next_position_index(program_test_ctx, long_owner).await;
+    let index_short = utils::get_next_position_index(program_test_ctx, short_owner).await;
+    let position_long_pda =
+        pda::get_position_pda(long_owner, pool_pda, &custody_pda, Side::Long, index_long).0;
+    let position_short_pda = pda::get_position_pda(
+        short_owner,
+        pool_pda,
+        &custody_pda,
+        Side::Short,
+        index_short,
+    )
+    .0;
+
+    let funding_account_long = get_dark_pool_funding_account(
+        program_test_ctx,
+        long_owner,
+        pool_pda,
+        collateral_custody_token_mint,
+    )
+    .await;
+    let funding_account_short = get_dark_pool_funding_account(
+        program_test_ctx,
+        short_owner,
+        pool_pda,
+        collateral_custody_token_mint,
+    )
+    .await;
+
+    let ix = get_settle_dark_pool_trade_ix(
+        program_test_ctx,
+        payer,
+        long_owner,
+        short_owner,
+        &funding_account_long,
+        &funding_account_short,
+        pool_pda,
+        custody_token_mint,
+        collateral_custody_token_mint,
+        params,
+    )
+    .await;
+
+    // Save account state before tx execution
+    let custody_before = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+    let collateral_custody_before =
+        utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+    let funding_account_long_before =
+        utils::get_token_account_balance(program_test_ctx, funding_account_long).await;
+    let funding_account_short_before =
+        utils::get_token_account_balance(program_test_ctx, funding_account_short).await;
+    let custody_token_account_before =
+        utils::get_token_account_balance(program_test_ctx, collateral_custody_token_account_pda)
+            .await;
+
+    // The darkpool stub forwards the instruction, the first account being the callee
+    let darkpool_authority_pda = pda::get_darkpool_authority_pda().0;
+    let mut accounts_meta = vec![AccountMeta::new_readonly(perpetuals::id(), false)];
+    accounts_meta.extend(ix.accounts.into_iter().map(|account_meta| AccountMeta {
+        is_signer: account_meta.is_signer && account_meta.pubkey != darkpool_authority_pda,
+        ..account_meta
+    }));
+
+    {
+        let mut ctx = program_test_ctx.write().await;
+        let last_blockhash = ctx.last_blockhash;
+
+        let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id: DARKPOOL_PROGRAM_ID,
+                accounts: accounts_meta,
+                data: ix.data,
+            }],
+            Some(&payer.pubkey()),
+            &[payer],
+            last_blockhash,
+        );
+
+        ctx.banks_client.process_transaction(tx).await?;
+    }
+
+    // ==== THEN ==============================================================
+    let position_long = utils::get_account::<Position>(program_test_ctx, position_long_pda).await;
+    let position_short = utils::get_account::<Position>(program_test_ctx, position_short_pda).await;
+
+    // Check the positions
+    for (position, owner, side, index, collateral) in [
+        (
+            &position_long,
+            long_owner,
+            Side::Long,
+            index_long,
+            params.collateral_long,
+        ),
+        (
+            &position_short,
+            short_owner,
+            Side::Short,
+            index_short,
+            params.collateral_short,
+        ),
+    ] {
+        assert_eq!(position.owner, *owner);
+        assert_eq!(position.pool, *pool_pda);
+        assert_eq!(position.custody, custody_pda);
+        assert_eq!(position.collateral_custody, collateral_custody_pda);
+        assert_eq!(position.side, side);
+        assert_eq!(position.price, params.price);
+        assert_eq!(position.size_usd, params.size_usd);
+        assert_eq!(position.index, index);
+        // entry fee is paid out of the committed collateral
+        assert!(position.collateral_amount > 0 && position.collateral_amount < collateral);
+        assert_eq!(
+            utils::get_next_position_index(program_test_ctx, owner).await,
+            index + 1
+        );
+    }
+
+    // Check the escrowed collateral moved to the custody
+    {
+        let funding_account_long_after =
+            utils::get_token_account_balance(program_test_ctx, funding_account_long).await;
+        let funding_account_short_after =
+            utils::get_token_account_balance(program_test_ctx, funding_account_short).await;
+        let custody_token_account_after = utils::get_token_account_balance(
+            program_test_ctx,
+            collateral_custody_token_account_pda,
+        )
+        .await;
+
+        assert_eq!(
+            funding_account_long_before - funding_account_long_after,
+            params.collateral_long
+        );
+        assert_eq!(
+            funding_account_short_before - funding_account_short_after,
+            params.collateral_short
+        );
+        assert_eq!(
+            custody_token_account_after - custody_token_account_before,
+            params.collateral_long + params.collateral_short
+        );
+    }
+
+    // Check custody stats
+    {
+        let custody_after = utils::get_account::<Custody>(program_test_ctx, custody_pda).await;
+        let collateral_custody_after =
+            utils::get_account::<Custody>(program_test_ctx, collateral_custody_pda).await;
+
+        assert_eq!(
+            custody_after.trade_stats.oi_long_usd - custody_before.trade_stats.oi_long_usd,
+            params.size_usd
+        );
+        assert_eq!(
+            custody_after.trade_stats.oi_short_usd - custody_before.trade_stats.oi_short_usd,
+            params.size_usd
+        );
+        assert_eq!(
+            custody_after.volume_stats.open_position_usd
+                - custody_before.volume_stats.open_position_usd,
+            2 * params.size_usd
+        );
+        assert_eq!(
+            custody_after.long_positions.open_positions
+                - custody_before.long_positions.open_positions,
+            1
+        );
+        assert_eq!(
+            custody_after.short_positions.open_positions
+                - custody_before.short_positions.open_positions,
+            1
+        );
+        assert_eq!(
+            collateral_custody_after.assets.collateral
+                - collateral_custody_before.assets.collateral,
+            position_long.collateral_amount + position_short.collateral_amount
+        );
+        assert_eq!(
+            collateral_custody_after.assets.locked - collateral_custody_before.assets.locked,
+            position_long.locked_amount + position_short.locked_amount
+        );
+    }
+
+    Ok((position_long_pda, position_short_pda))
+}
@@ -1,10 +1,8 @@
 use {
-    crate::{
-        instructions::get_update_pool_ix,
-        utils::{self, pda},
-    },
+    crate::{instructions::get_update_pool_ix, utils},
     anchor_lang::{prelude::Pubkey, ToAccountMetas},
     perpetuals::{instructions::SwapParams, state::custody::Custody},
+    perpetuals_client::pda,
     solana_program_test::{BanksClientError, ProgramTestContext},
     solana_sdk::signer::{keypair::Keypair, Signer},
     tokio::sync::RwLock,
@@ -32,11 +30,6 @@ pub async fn test_swap(
     let receiving_custody_token_account_pda =
         pda::get_custody_token_account_pda(pool_pda, receiving_custody_token_mint).0;
 
-    let funding_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), receiving_custody_token_mint).0;
-    let receiving_account_address =
-        utils::find_associated_token_account(&owner.pubkey(), dispensing_custody_token_mint).0;
-
     let dispensing_custody_account =
         utils::get_account::<Custody>(program_test_ctx, dispensing_custody_pda).await;
     let dispensing_custody_oracle_account_address =
@@ -46,6 +39,19 @@ pub async fn test_swap(
         utils::get_account::<Custody>(program_test_ctx, receiving_custody_pda).await;
     let receiving_custody_oracle_account_address = receiving_custody_account.oracle.oracle_account;
 
+    let funding_account_address = utils::find_associated_token_account_for_program(
+        &owner.pubkey(),
+        receiving_custody_token_mint,
+        &receiving_custody_account.token_program,
+    )
+    .0;
+    let receiving_account_address = utils::find_associated_token_account_for_program(
+        &owner.pubkey(),
+        dispensing_custody_token_mint,
+        &dispensing_custody_account.token_program,
+    )
+    .0;
+
     // Save account state before tx execution
     let owner_funding_account_before =
         utils::get_token_account(program_test_ctx, funding_account_address).await;
@@ -64,10 +70,13 @@ pub async fn test_swap(
             receiving_custody: receiving_custody_pda,
             receiving_custody_oracle_account: receiving_custody_oracle_account_address,
             receiving_custody_token_account: receiving_custody_token_account_pda,
+            receiving_custody_token_mint: *receiving_custody_token_mint,
             dispensing_custody: dispensing_custody_pda,
             dispensing_custody_oracle_account: dispensing_custody_oracle_account_address,
             dispensing_custody_token_account: dispensing_custody_token_account_pda,
-            token_program: anchor_spl::token::ID,
+            dispensing_custody_token_mint: *dispensing_custody_token_mint,
+            token_program: receiving_custody_account.token_program,
+            dispensing_token_program: dispensing_custody_account.token_program,
         }
         .to_account_metas(None),
         perpetuals::instruction::Swap { params },
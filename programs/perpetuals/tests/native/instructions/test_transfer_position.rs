@@ -0,0 +1,76 @@
+use {
+    crate::utils,
+    anchor_lang::{prelude::Pubkey, ToAccountMetas},
+    perpetuals::state::position::Position,
+    perpetuals_client::pda,
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_transfer_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    new_owner: &Pubkey,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    position_pda: &Pubkey,
+) -> std::result::Result<Pubkey, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let position_before = utils::get_account::<Position>(program_test_ctx, *position_pda).await;
+
+    let new_position_index = utils::get_next_position_index(program_test_ctx, new_owner).await;
+    let (new_position_pda, new_position_bump) = pda::get_position_pda(
+        new_owner,
+        pool_pda,
+        &position_before.custody,
+        position_before.side,
+        new_position_index,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        perpetuals::accounts::TransferPosition {
+            owner: owner.pubkey(),
+            new_owner: *new_owner,
+            perpetuals: pda::get_perpetuals_pda().0,
+            pool: *pool_pda,
+            position: *position_pda,
+            new_position_counter: pda::get_position_counter_pda(new_owner).0,
+            new_position: new_position_pda,
+            system_program: anchor_lang::system_program::ID,
+        }
+        .to_account_metas(None),
+        perpetuals::instruction::TransferPosition {},
+        Some(&payer.pubkey()),
+        &[owner, payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    assert!(
+        utils::try_get_account::<Position>(program_test_ctx, *position_pda)
+            .await
+            .is_none()
+    );
+
+    let new_position = utils::get_account::<Position>(program_test_ctx, new_position_pda).await;
+
+    assert_eq!(new_position.owner, *new_owner);
+    assert_eq!(new_position.pool, position_before.pool);
+    assert_eq!(new_position.custody, position_before.custody);
+    assert_eq!(new_position.side, position_before.side);
+    assert_eq!(new_position.price, position_before.price);
+    assert_eq!(new_position.size_usd, position_before.size_usd);
+    assert_eq!(
+        new_position.collateral_amount,
+        position_before.collateral_amount
+    );
+    assert_eq!(new_position.locked_amount, position_before.locked_amount);
+    assert_eq!(new_position.index, new_position_index);
+    assert_eq!(new_position.bump, new_position_bump);
+
+    Ok(new_position_pda)
+}
@@ -0,0 +1,59 @@
+use {
+    crate::utils,
+    perpetuals::{
+        instructions::UpgradeMultisigParams,
+        state::multisig::{AdminRole, Multisig},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_upgrade_multisig(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    upgrade_authority: &Keypair,
+) -> std::result::Result<Multisig, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let multisig_pda = pda::get_multisig_pda().0;
+
+    let ix = client_instructions::upgrade_multisig(
+        &upgrade_authority.pubkey(),
+        UpgradeMultisigParams {},
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::UpgradeMultisig {
+            params: UpgradeMultisigParams {},
+        },
+        Some(&upgrade_authority.pubkey()),
+        &[upgrade_authority],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    // The account is resized to the current layout
+    let multisig_account = {
+        let mut ctx = program_test_ctx.write().await;
+        ctx.banks_client
+            .get_account(multisig_pda)
+            .await
+            .unwrap()
+            .unwrap()
+    };
+    assert_eq!(multisig_account.data.len(), Multisig::LEN);
+
+    let multisig = utils::get_account::<Multisig>(program_test_ctx, multisig_pda).await;
+
+    // Pending signatures are dropped
+    assert_eq!(multisig.num_signed, 0);
+    assert_eq!(multisig.instruction_hash, [0; 32]);
+    assert_eq!({ multisig.timelock_delay }, 0);
+    assert_eq!(multisig.role, AdminRole::Admin as u8);
+
+    Ok(multisig)
+}
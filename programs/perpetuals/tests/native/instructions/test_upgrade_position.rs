@@ -0,0 +1,74 @@
+use {
+    crate::utils,
+    anchor_lang::prelude::Pubkey,
+    perpetuals::{
+        instructions::UpgradePositionParams,
+        state::position::{Position, Side},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+pub async fn test_upgrade_position(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    payer: &Keypair,
+    owner: &Pubkey,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+    side: Side,
+) -> std::result::Result<Pubkey, BanksClientError> {
+    // ==== WHEN ==============================================================
+    let legacy_position_pda = pda::get_legacy_position_pda(owner, pool_pda, custody_pda, side).0;
+    let index = utils::get_next_position_index(program_test_ctx, owner).await;
+    let (position_pda, position_bump) =
+        pda::get_position_pda(owner, pool_pda, custody_pda, side, index);
+
+    let ix = client_instructions::upgrade_position(
+        &payer.pubkey(),
+        owner,
+        pool_pda,
+        custody_pda,
+        side,
+        index,
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::UpgradePosition {
+            params: UpgradePositionParams {
+                custody: *custody_pda,
+                side,
+            },
+        },
+        Some(&payer.pubkey()),
+        &[payer],
+        None,
+        None,
+    )
+    .await?;
+
+    // ==== THEN ==============================================================
+    assert!(
+        utils::try_get_account::<Position>(program_test_ctx, legacy_position_pda)
+            .await
+            .is_none()
+    );
+
+    let position = utils::get_account::<Position>(program_test_ctx, position_pda).await;
+
+    assert_eq!(position.owner, *owner);
+    assert_eq!(position.pool, *pool_pda);
+    assert_eq!(position.custody, *custody_pda);
+    assert_eq!(position.side, side);
+    assert_eq!(position.index, index);
+    assert_eq!(position.bump, position_bump);
+    assert_eq!(
+        utils::get_next_position_index(program_test_ctx, owner).await,
+        index + 1
+    );
+
+    Ok(position_pda)
+}
@@ -1,3 +1,4 @@
+pub mod fuzz;
 pub mod instructions;
 pub mod tests_suite;
 pub mod utils;
@@ -15,6 +16,24 @@ pub async fn test_integration() {
     tests_suite::position::min_max_leverage().await;
     tests_suite::position::liquidate_position().await;
     tests_suite::position::max_user_profit().await;
+    tests_suite::position::multiple_positions().await;
+    tests_suite::position::trading_delegate().await;
+    tests_suite::position::transfer_position().await;
+    tests_suite::position::legacy_position().await;
+    tests_suite::position::dark_pool_trade().await;
+    tests_suite::position::fee_tiers().await;
 
     tests_suite::lp_token::lp_token_price().await;
+
+    tests_suite::token_2022::transfer_fee().await;
+
+    tests_suite::admin::role_timelock().await;
+    tests_suite::admin::custody_migration().await;
+    tests_suite::admin::timelock_proposal().await;
+    tests_suite::admin::multisig_upgrade().await;
+}
+
+#[test]
+pub fn test_fuzz_pool_invariants() {
+    fuzz::pool_invariants();
 }
@@ -0,0 +1,200 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::{AnchorSerialize, Discriminator},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::AddLiquidityParams,
+        state::custody::{
+            Custody, DeprecatedCustody, DeprecatedPricingParams, LegacyCustody, PricingParams,
+        },
+    },
+};
+
+const USDC_DECIMALS: u8 = 6;
+
+fn to_deprecated_pricing_params(pricing: PricingParams) -> DeprecatedPricingParams {
+    DeprecatedPricingParams {
+        use_ema: pricing.use_ema,
+        use_unrealized_pnl_in_aum: pricing.use_unrealized_pnl_in_aum,
+        trade_spread_long: pricing.trade_spread_long,
+        trade_spread_short: pricing.trade_spread_short,
+        swap_spread: pricing.swap_spread,
+        min_initial_leverage: pricing.min_initial_leverage,
+        max_initial_leverage: pricing.max_initial_leverage,
+        max_leverage: pricing.max_leverage,
+        max_payoff_mult: pricing.max_payoff_mult,
+        max_utilization: pricing.max_utilization,
+        max_position_locked_usd: pricing.max_position_locked_usd,
+        max_total_locked_usd: pricing.max_total_locked_usd,
+    }
+}
+
+// Accounts created by previous program versions kept the Custody discriminator
+fn serialize_custody<T: AnchorSerialize>(custody: &T, len: usize) -> Vec<u8> {
+    let mut data = Custody::discriminator().to_vec();
+    custody.serialize(&mut data).unwrap();
+    data.resize(len, 0);
+    data
+}
+
+pub async fn custody_migration() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(2_000, USDC_DECIMALS),
+            },
+        }],
+        vec![utils::MintParam {
+            name: "usdc",
+            decimals: USDC_DECIMALS,
+        }],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![utils::SetupCustodyWithLiquidityParams {
+            setup_custody_params: utils::SetupCustodyParams {
+                mint_name: "usdc",
+                is_stable: true,
+                is_virtual: false,
+                target_ratio: utils::ratio_from_percentage(100.0),
+                min_ratio: utils::ratio_from_percentage(0.0),
+                max_ratio: utils::ratio_from_percentage(100.0),
+                initial_price: utils::scale(1, USDC_DECIMALS),
+                initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                pricing_params: None,
+                permissions: None,
+                fees: None,
+                borrow_rate: None,
+            },
+            liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+            payer_user_name: "alice",
+        }],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let usdc_custody_pda = test_setup.custodies_info[0].custody_pda;
+
+    let custody =
+        utils::get_account::<Custody>(&test_setup.program_test_ctx, usdc_custody_pda).await;
+
+    // Custodies created before price impact params are migrated in place
+    {
+        let legacy_custody = LegacyCustody {
+            pool: custody.pool,
+            mint: custody.mint,
+            token_account: custody.token_account,
+            decimals: custody.decimals,
+            is_stable: custody.is_stable,
+            is_virtual: custody.is_virtual,
+            oracle: custody.oracle,
+            pricing: to_deprecated_pricing_params(custody.pricing),
+            permissions: custody.permissions,
+            fees: custody.fees,
+            borrow_rate: custody.borrow_rate,
+            assets: custody.assets,
+            collected_fees: custody.collected_fees,
+            volume_stats: custody.volume_stats,
+            trade_stats: custody.trade_stats,
+            long_positions: custody.long_positions,
+            short_positions: custody.short_positions,
+            borrow_rate_state: custody.borrow_rate_state,
+            bump: custody.bump,
+            token_account_bump: custody.token_account_bump,
+        };
+
+        utils::set_account_data(
+            &test_setup.program_test_ctx,
+            usdc_custody_pda,
+            serialize_custody(&legacy_custody, LegacyCustody::LEN),
+        )
+        .await;
+
+        let migrated_custody = instructions::test_migrate_custody(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &usdc_custody_pda,
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(migrated_custody, custody);
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Already migrated custodies are rejected
+    assert!(instructions::test_migrate_custody(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &usdc_custody_pda,
+        &multisig_signers,
+    )
+    .await
+    .is_err());
+
+    // Custodies created before virtual custodies are upgraded in place
+    {
+        let deprecated_custody = DeprecatedCustody {
+            pool: custody.pool,
+            mint: custody.mint,
+            token_account: custody.token_account,
+            decimals: custody.decimals,
+            is_stable: custody.is_stable,
+            oracle: custody.oracle,
+            pricing: to_deprecated_pricing_params(custody.pricing),
+            permissions: custody.permissions,
+            fees: custody.fees,
+            borrow_rate: custody.borrow_rate,
+            assets: custody.assets,
+            collected_fees: custody.collected_fees,
+            volume_stats: custody.volume_stats,
+            trade_stats: custody.trade_stats,
+            long_positions: custody.long_positions,
+            short_positions: custody.short_positions,
+            borrow_rate_state: custody.borrow_rate_state,
+            bump: custody.bump,
+            token_account_bump: custody.token_account_bump,
+        };
+
+        utils::set_account_data(
+            &test_setup.program_test_ctx,
+            usdc_custody_pda,
+            serialize_custody(&deprecated_custody, DeprecatedCustody::LEN),
+        )
+        .await;
+
+        let upgraded_custody = instructions::test_upgrade_custody(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &usdc_custody_pda,
+            &multisig_signers,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(upgraded_custody, custody);
+    }
+
+    // The custody keeps working after the upgrade
+    instructions::test_add_liquidity(
+        &test_setup.program_test_ctx,
+        alice,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        usdc_mint,
+        AddLiquidityParams {
+            amount_in: utils::scale(100, USDC_DECIMALS),
+            min_lp_amount_out: 1,
+        },
+    )
+    .await
+    .unwrap();
+}
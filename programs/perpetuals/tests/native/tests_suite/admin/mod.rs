@@ -0,0 +1,6 @@
+pub mod custody_migration;
+pub mod multisig_upgrade;
+pub mod role_timelock;
+pub mod timelock_proposal;
+
+pub use {custody_migration::*, multisig_upgrade::*, role_timelock::*, timelock_proposal::*};
@@ -0,0 +1,173 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::{AnchorSerialize, Discriminator},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::SetPermissionsParams,
+        state::{
+            multisig::{AdminInstruction, AdminRole, DeprecatedMultisig, Multisig},
+            perpetuals::Perpetuals,
+        },
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::{
+        hash::Hasher,
+        signer::{keypair::Keypair, Signer},
+    },
+    tokio::sync::RwLock,
+};
+
+const USDC_DECIMALS: u8 = 6;
+
+fn permissions_params() -> SetPermissionsParams {
+    SetPermissionsParams {
+        allow_swap: false,
+        allow_add_liquidity: true,
+        allow_remove_liquidity: true,
+        allow_open_position: true,
+        allow_close_position: true,
+        allow_pnl_withdrawal: true,
+        allow_collateral_withdrawal: true,
+        allow_size_change: true,
+    }
+}
+
+pub async fn multisig_upgrade() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(1_000, USDC_DECIMALS),
+            },
+        }],
+        vec![utils::MintParam {
+            name: "usdc",
+            decimals: USDC_DECIMALS,
+        }],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![utils::SetupCustodyWithLiquidityParams {
+            setup_custody_params: utils::SetupCustodyParams {
+                mint_name: "usdc",
+                is_stable: true,
+                is_virtual: false,
+                target_ratio: utils::ratio_from_percentage(100.0),
+                min_ratio: utils::ratio_from_percentage(0.0),
+                max_ratio: utils::ratio_from_percentage(100.0),
+                initial_price: utils::scale(1, USDC_DECIMALS),
+                initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                pricing_params: None,
+                permissions: None,
+                fees: None,
+                borrow_rate: None,
+            },
+            liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+            payer_user_name: "alice",
+        }],
+    )
+    .await;
+
+    let ctx = &test_setup.program_test_ctx;
+    let payer = &test_setup.payer_keypair;
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let admin_b = test_setup.get_multisig_member_keypair_by_name("admin_b");
+
+    let multisig_pda = pda::get_multisig_pda().0;
+    let perpetuals_pda = pda::get_perpetuals_pda().0;
+
+    // Rewrite the multisig with the layout of previous program versions,
+    // 2 out of 3 with a pending signature under the old u64 instruction hash
+    let multisig = utils::get_account::<Multisig>(ctx, multisig_pda).await;
+    let deprecated_multisig = DeprecatedMultisig {
+        num_signers: multisig.num_signers,
+        num_signed: 1,
+        min_signatures: 2,
+        instruction_accounts_len: 2,
+        instruction_data_len: 9,
+        instruction_hash: 0x1234_5678_9abc_def0,
+        signers: multisig.signers,
+        signed: [1, 0, 0, 0, 0, 0],
+        bump: multisig.bump,
+    };
+
+    let mut data = Multisig::discriminator().to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(&deprecated_multisig));
+    assert_eq!(data.len(), DeprecatedMultisig::LEN);
+    utils::set_account_data(ctx, multisig_pda, data).await;
+
+    let multisig = instructions::test_upgrade_multisig(ctx, &test_setup.root_authority_keypair)
+        .await
+        .unwrap();
+
+    assert_eq!(multisig.num_signers, deprecated_multisig.num_signers);
+    assert_eq!(multisig.min_signatures, 2);
+    assert_eq!(multisig.signers, deprecated_multisig.signers);
+    assert_eq!(multisig.signed, [0; Multisig::MAX_SIGNERS]);
+    assert_eq!(multisig.bump, deprecated_multisig.bump);
+
+    // An upgraded multisig can't be upgraded again
+    utils::warp_forward(ctx, 1).await;
+    assert!(
+        instructions::test_upgrade_multisig(ctx, &test_setup.root_authority_keypair)
+            .await
+            .is_err()
+    );
+
+    // Sign an admin instruction with the upgraded signer set
+    set_permissions(ctx, admin_a, payer).await.unwrap();
+
+    // The first signature is recorded under the SHA-256 instruction hash
+    {
+        let mut instruction_data = permissions_params().try_to_vec().unwrap();
+        instruction_data.push(AdminInstruction::SetPermissions as u8);
+
+        let mut hasher = Hasher::default();
+        hasher.hash(multisig_pda.as_ref());
+        hasher.hash(perpetuals_pda.as_ref());
+        hasher.hash(&instruction_data);
+
+        let multisig = utils::get_account::<Multisig>(ctx, multisig_pda).await;
+        assert_eq!(multisig.num_signed, 1);
+        assert_eq!(multisig.instruction_accounts_len, 2);
+        assert_eq!(
+            { multisig.instruction_data_len } as usize,
+            instruction_data.len()
+        );
+        assert_eq!(multisig.instruction_hash, hasher.result().to_bytes());
+
+        let perpetuals_account = utils::get_account::<Perpetuals>(ctx, perpetuals_pda).await;
+        assert!(perpetuals_account.permissions.allow_swap);
+    }
+
+    set_permissions(ctx, admin_b, payer).await.unwrap();
+
+    let perpetuals_account = utils::get_account::<Perpetuals>(ctx, perpetuals_pda).await;
+    assert!(!perpetuals_account.permissions.allow_swap);
+    assert!(perpetuals_account.permissions.allow_open_position);
+}
+
+async fn set_permissions(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    admin: &Keypair,
+    payer: &Keypair,
+) -> std::result::Result<(), BanksClientError> {
+    let ix = client_instructions::set_permissions(
+        &admin.pubkey(),
+        AdminRole::Admin,
+        permissions_params(),
+    );
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        ix.accounts,
+        perpetuals::instruction::SetPermissions {
+            params: permissions_params(),
+        },
+        Some(&payer.pubkey()),
+        &[admin, payer],
+        None,
+        None,
+    )
+    .await
+}
@@ -0,0 +1,306 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::{
+        prelude::{AccountMeta, Pubkey},
+        ToAccountMetas,
+    },
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            SetCustodyConfigParams, SetCustodyRiskParams, SetPermissionsParams,
+            SetRoleSignersParams, SetTimelockDelayParams,
+        },
+        state::{
+            custody::Custody,
+            multisig::{AdminRole, Multisig},
+            pool::Pool,
+        },
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_program_test::{BanksClientError, ProgramTestContext},
+    solana_sdk::signer::{keypair::Keypair, Signer},
+    tokio::sync::RwLock,
+};
+
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn role_timelock() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "risk_manager",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![utils::MintParam {
+            name: "usdc",
+            decimals: USDC_DECIMALS,
+        }],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![utils::SetupCustodyWithLiquidityParams {
+            setup_custody_params: utils::SetupCustodyParams {
+                mint_name: "usdc",
+                is_stable: true,
+                is_virtual: false,
+                target_ratio: utils::ratio_from_percentage(100.0),
+                min_ratio: utils::ratio_from_percentage(0.0),
+                max_ratio: utils::ratio_from_percentage(100.0),
+                initial_price: utils::scale(1, USDC_DECIMALS),
+                initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                pricing_params: None,
+                permissions: None,
+                fees: None,
+                borrow_rate: None,
+            },
+            liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+            payer_user_name: "alice",
+        }],
+    )
+    .await;
+
+    let risk_manager = test_setup.get_user_keypair_by_name("risk_manager");
+    let multisig_signers = test_setup.get_multisig_signers();
+    let usdc_custody_pda = test_setup.custodies_info[0].custody_pda;
+
+    instructions::test_set_role_signers(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        SetRoleSignersParams {
+            role: AdminRole::RiskManager,
+            min_signatures: 1,
+        },
+        &[risk_manager],
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    // The risk manager can't touch the oracle, permissions or ratios
+    assert!(risk_manager_set_custody_config(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &usdc_custody_pda,
+    )
+    .await
+    .is_err());
+
+    // Without a timelock the risk manager updates custody risk params directly
+    risk_manager_set_custody_risk(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &usdc_custody_pda,
+    )
+    .await
+    .unwrap();
+
+    instructions::test_set_timelock_delay(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        SetTimelockDelayParams {
+            timelock_delay: 3_600,
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    let risk_manager_multisig = utils::get_account::<Multisig>(
+        &test_setup.program_test_ctx,
+        pda::get_role_multisig_pda(AdminRole::RiskManager).0,
+    )
+    .await;
+    assert_eq!({ risk_manager_multisig.timelock_delay }, 3_600);
+
+    // Advance to get a fresh blockhash, the transaction would be deduplicated otherwise
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Once the delay is set, role signers can't skip the proposal flow
+    assert!(risk_manager_set_custody_risk(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &usdc_custody_pda,
+    )
+    .await
+    .is_err());
+
+    // The risk manager proposes with its own signer set instead, executed
+    // proposals are signed by the risk manager timelock authority
+    let risk_manager_authority = pda::get_timelock_authority_pda(AdminRole::RiskManager).0;
+    let custody_account =
+        utils::get_account::<Custody>(&test_setup.program_test_ctx, usdc_custody_pda).await;
+    let mut fees = custody_account.fees;
+    fees.open_position = custody_account.fees.open_position + 10;
+
+    let id = instructions::test_create_proposal(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        AdminRole::RiskManager,
+        client_instructions::set_custody_risk(
+            &risk_manager_authority,
+            AdminRole::RiskManager,
+            &test_setup.pool_pda,
+            &test_setup.get_mint_by_name("usdc"),
+            SetCustodyRiskParams {
+                pricing: custody_account.pricing,
+                fees,
+                borrow_rate: custody_account.borrow_rate,
+            },
+        ),
+    )
+    .await
+    .unwrap();
+
+    // The risk manager timelock authority isn't accepted by the admin signer set
+    let admin_id = instructions::test_create_proposal(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        AdminRole::RiskManager,
+        client_instructions::set_permissions(
+            &risk_manager_authority,
+            AdminRole::Admin,
+            SetPermissionsParams {
+                allow_swap: false,
+                allow_add_liquidity: false,
+                allow_remove_liquidity: false,
+                allow_open_position: false,
+                allow_close_position: false,
+                allow_pnl_withdrawal: false,
+                allow_collateral_withdrawal: false,
+                allow_size_change: false,
+            },
+        ),
+    )
+    .await
+    .unwrap();
+
+    utils::warp_forward(&test_setup.program_test_ctx, 3_600).await;
+
+    instructions::test_execute_proposal(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        AdminRole::RiskManager,
+        id,
+    )
+    .await
+    .unwrap();
+
+    let custody_account =
+        utils::get_account::<Custody>(&test_setup.program_test_ctx, usdc_custody_pda).await;
+    assert_eq!(custody_account.fees.open_position, fees.open_position);
+
+    assert!(instructions::test_execute_proposal(
+        &test_setup.program_test_ctx,
+        risk_manager,
+        &test_setup.payer_keypair,
+        AdminRole::RiskManager,
+        admin_id,
+    )
+    .await
+    .is_err());
+}
+
+// Re-applies the current custody risk params, signed by the risk manager signer set
+async fn risk_manager_set_custody_risk(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    risk_manager: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+) -> std::result::Result<(), BanksClientError> {
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, *custody_pda).await;
+
+    let mut accounts_meta = perpetuals::accounts::SetCustodyRisk {
+        admin: risk_manager.pubkey(),
+        multisig: pda::get_role_multisig_pda(AdminRole::RiskManager).0,
+        pool: *pool_pda,
+        custody: *custody_pda,
+    }
+    .to_account_metas(None);
+
+    accounts_meta.push(AccountMeta {
+        pubkey: risk_manager.pubkey(),
+        is_signer: true,
+        is_writable: false,
+    });
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::SetCustodyRisk {
+            params: SetCustodyRiskParams {
+                pricing: custody_account.pricing,
+                fees: custody_account.fees,
+                borrow_rate: custody_account.borrow_rate,
+            },
+        },
+        Some(&payer.pubkey()),
+        &[risk_manager, payer],
+        None,
+        None,
+    )
+    .await
+}
+
+// Re-applies the current custody config, signed by the risk manager signer set
+async fn risk_manager_set_custody_config(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    risk_manager: &Keypair,
+    payer: &Keypair,
+    pool_pda: &Pubkey,
+    custody_pda: &Pubkey,
+) -> std::result::Result<(), BanksClientError> {
+    let pool_account = utils::get_account::<Pool>(program_test_ctx, *pool_pda).await;
+    let custody_account = utils::get_account::<Custody>(program_test_ctx, *custody_pda).await;
+
+    let mut accounts_meta = perpetuals::accounts::SetCustodyConfig {
+        admin: risk_manager.pubkey(),
+        multisig: pda::get_role_multisig_pda(AdminRole::RiskManager).0,
+        pool: *pool_pda,
+        custody: *custody_pda,
+    }
+    .to_account_metas(None);
+
+    accounts_meta.push(AccountMeta {
+        pubkey: risk_manager.pubkey(),
+        is_signer: true,
+        is_writable: false,
+    });
+
+    utils::create_and_execute_perpetuals_ix(
+        program_test_ctx,
+        accounts_meta,
+        perpetuals::instruction::SetCustodyConfig {
+            params: SetCustodyConfigParams {
+                is_stable: custody_account.is_stable,
+                is_virtual: custody_account.is_virtual,
+                oracle: custody_account.oracle,
+                pricing: custody_account.pricing,
+                permissions: custody_account.permissions,
+                fees: custody_account.fees,
+                borrow_rate: custody_account.borrow_rate,
+                ratios: pool_account.ratios,
+            },
+        },
+        Some(&payer.pubkey()),
+        &[risk_manager, payer],
+        None,
+        None,
+    )
+    .await
+}
@@ -0,0 +1,215 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{SetAdminSignersParams, SetPermissionsParams, SetTimelockDelayParams},
+        state::{multisig::AdminRole, perpetuals::Perpetuals},
+    },
+    perpetuals_client::{instructions as client_instructions, pda},
+    solana_sdk::signer::Signer,
+};
+
+const USDC_DECIMALS: u8 = 6;
+
+fn permissions_params(allow_swap: bool) -> SetPermissionsParams {
+    SetPermissionsParams {
+        allow_swap,
+        allow_add_liquidity: true,
+        allow_remove_liquidity: true,
+        allow_open_position: true,
+        allow_close_position: true,
+        allow_pnl_withdrawal: true,
+        allow_collateral_withdrawal: true,
+        allow_size_change: true,
+    }
+}
+
+pub async fn timelock_proposal() {
+    let test_setup = utils::TestSetup::new(
+        vec![utils::UserParam {
+            name: "alice",
+            token_balances: hashmap! {
+                "usdc" => utils::scale(1_000, USDC_DECIMALS),
+            },
+        }],
+        vec![utils::MintParam {
+            name: "usdc",
+            decimals: USDC_DECIMALS,
+        }],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![utils::SetupCustodyWithLiquidityParams {
+            setup_custody_params: utils::SetupCustodyParams {
+                mint_name: "usdc",
+                is_stable: true,
+                is_virtual: false,
+                target_ratio: utils::ratio_from_percentage(100.0),
+                min_ratio: utils::ratio_from_percentage(0.0),
+                max_ratio: utils::ratio_from_percentage(100.0),
+                initial_price: utils::scale(1, USDC_DECIMALS),
+                initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                pricing_params: None,
+                permissions: None,
+                fees: None,
+                borrow_rate: None,
+            },
+            liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+            payer_user_name: "alice",
+        }],
+    )
+    .await;
+
+    let ctx = &test_setup.program_test_ctx;
+    let payer = &test_setup.payer_keypair;
+    let admin_a = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let admin_b = test_setup.get_multisig_member_keypair_by_name("admin_b");
+    let admin_c = test_setup.get_multisig_member_keypair_by_name("admin_c");
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    // Require 2 out of 3 admins
+    let ix = client_instructions::set_admin_signers(
+        &admin_a.pubkey(),
+        &[admin_a.pubkey(), admin_b.pubkey(), admin_c.pubkey()],
+        SetAdminSignersParams { min_signatures: 2 },
+    );
+    utils::create_and_execute_perpetuals_ix(
+        ctx,
+        ix.accounts,
+        perpetuals::instruction::SetAdminSigners {
+            params: SetAdminSignersParams { min_signatures: 2 },
+        },
+        Some(&payer.pubkey()),
+        &[admin_a, payer],
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+
+    instructions::test_set_timelock_delay(
+        ctx,
+        payer,
+        SetTimelockDelayParams {
+            timelock_delay: 3_600,
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    // Admin instructions are signed by the timelock authority once executed
+    let timelock_authority = pda::get_timelock_authority_pda(AdminRole::Admin).0;
+
+    // Propose, approve, wait for the ETA and execute
+    {
+        let id = instructions::test_create_proposal(
+            ctx,
+            admin_a,
+            payer,
+            AdminRole::Admin,
+            client_instructions::set_permissions(
+                &timelock_authority,
+                AdminRole::Admin,
+                permissions_params(false),
+            ),
+        )
+        .await
+        .unwrap();
+
+        // Missing approvals
+        assert!(
+            instructions::test_execute_proposal(ctx, payer, payer, AdminRole::Admin, id)
+                .await
+                .is_err()
+        );
+
+        instructions::test_approve_proposal(ctx, admin_b, payer, AdminRole::Admin, id)
+            .await
+            .unwrap();
+
+        // Advance to get a fresh blockhash, the transaction would be deduplicated otherwise
+        utils::warp_forward(ctx, 1).await;
+
+        // Queued but the ETA isn't reached yet
+        assert!(
+            instructions::test_execute_proposal(ctx, payer, payer, AdminRole::Admin, id)
+                .await
+                .is_err()
+        );
+
+        utils::warp_forward(ctx, 3_600).await;
+
+        instructions::test_execute_proposal(ctx, payer, payer, AdminRole::Admin, id)
+            .await
+            .unwrap();
+
+        let perpetuals_account =
+            utils::get_account::<Perpetuals>(ctx, pda::get_perpetuals_pda().0).await;
+        assert!(!perpetuals_account.permissions.allow_swap);
+        assert!(perpetuals_account.permissions.allow_open_position);
+    }
+
+    // A single admin can't cancel a proposal approved by a quorum
+    {
+        let id = instructions::test_create_proposal(
+            ctx,
+            admin_a,
+            payer,
+            AdminRole::Admin,
+            client_instructions::set_permissions(
+                &timelock_authority,
+                AdminRole::Admin,
+                permissions_params(true),
+            ),
+        )
+        .await
+        .unwrap();
+
+        instructions::test_approve_proposal(ctx, admin_b, payer, AdminRole::Admin, id)
+            .await
+            .unwrap();
+
+        assert!(
+            !instructions::test_cancel_proposal(ctx, admin_c, payer, AdminRole::Admin, id)
+                .await
+                .unwrap()
+        );
+
+        // The same admin can't vote twice
+        utils::warp_forward(ctx, 1).await;
+        assert!(
+            instructions::test_cancel_proposal(ctx, admin_c, payer, AdminRole::Admin, id)
+                .await
+                .is_err()
+        );
+
+        assert!(
+            instructions::test_cancel_proposal(ctx, admin_a, payer, AdminRole::Admin, id)
+                .await
+                .unwrap()
+        );
+    }
+
+    // Any admin can veto a proposal still collecting approvals
+    {
+        let id = instructions::test_create_proposal(
+            ctx,
+            admin_a,
+            payer,
+            AdminRole::Admin,
+            client_instructions::set_permissions(
+                &timelock_authority,
+                AdminRole::Admin,
+                permissions_params(true),
+            ),
+        )
+        .await
+        .unwrap();
+
+        assert!(
+            instructions::test_cancel_proposal(ctx, admin_c, payer, AdminRole::Admin, id)
+                .await
+                .unwrap()
+        );
+    }
+}
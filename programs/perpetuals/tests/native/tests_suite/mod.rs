@@ -1,7 +1,11 @@
+pub mod admin;
 pub mod basic_interactions;
 pub mod liquidity;
 pub mod lp_token;
 pub mod position;
 pub mod swap;
+pub mod token_2022;
 
-pub use {basic_interactions::*, liquidity::*, lp_token::*, position::*, swap::*};
+pub use {
+    admin::*, basic_interactions::*, liquidity::*, lp_token::*, position::*, swap::*, token_2022::*,
+};
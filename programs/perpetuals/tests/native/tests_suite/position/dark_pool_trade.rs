@@ -0,0 +1,194 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::prelude::AccountMeta,
+    maplit::hashmap,
+    perpetuals::instructions::SettleDarkPoolTradeParams,
+    perpetuals_client::pda,
+    solana_sdk::signer::{keypair::Keypair, Signer},
+};
+
+const SOL_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+const PRICE_DECIMALS: u8 = 6;
+
+pub async fn dark_pool_trade() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(10_000, USDC_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "sol",
+                decimals: SOL_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            // Dark trades are only settled on virtual custodies, collateralized in usdc
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "sol",
+                    is_stable: false,
+                    is_virtual: true,
+                    target_ratio: utils::ratio_from_percentage(0.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(100, SOL_DECIMALS),
+                    initial_conf: utils::scale_f64(0.1, SOL_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: 0,
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+    let sol_mint = &test_setup.get_mint_by_name("sol");
+
+    // Both traders let the darkpool authority move their collateral, as the
+    // order book escrow does
+    for trader in [martin, paul] {
+        utils::approve_token_delegate(
+            &test_setup.program_test_ctx,
+            trader,
+            &utils::find_associated_token_account(&trader.pubkey(), usdc_mint).0,
+            &pda::get_darkpool_authority_pda().0,
+            utils::scale(1_000, USDC_DECIMALS),
+        )
+        .await;
+    }
+
+    let trade_params = SettleDarkPoolTradeParams {
+        trade_id: 0,
+        size_usd: utils::scale(1_000, PRICE_DECIMALS),
+        price: utils::scale(100, PRICE_DECIMALS),
+        collateral_long: utils::scale(200, USDC_DECIMALS),
+        collateral_short: utils::scale(200, USDC_DECIMALS),
+        max_price_slippage: 50,
+    };
+
+    // Only the darkpool program can settle, any other authority is rejected
+    {
+        let fake_darkpool_authority = Keypair::new();
+        let darkpool_authority_pda = pda::get_darkpool_authority_pda().0;
+
+        let ix = instructions::get_settle_dark_pool_trade_ix(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &martin.pubkey(),
+            &paul.pubkey(),
+            &utils::find_associated_token_account(&martin.pubkey(), usdc_mint).0,
+            &utils::find_associated_token_account(&paul.pubkey(), usdc_mint).0,
+            &test_setup.pool_pda,
+            sol_mint,
+            usdc_mint,
+            trade_params,
+        )
+        .await;
+
+        let accounts_meta = ix
+            .accounts
+            .into_iter()
+            .map(|account_meta| {
+                if account_meta.pubkey == darkpool_authority_pda {
+                    AccountMeta::new_readonly(fake_darkpool_authority.pubkey(), true)
+                } else {
+                    account_meta
+                }
+            })
+            .collect();
+
+        assert!(utils::create_and_execute_perpetuals_ix(
+            &test_setup.program_test_ctx,
+            accounts_meta,
+            perpetuals::instruction::SettleDarkPoolTrade {
+                params: trade_params,
+            },
+            Some(&test_setup.payer_keypair.pubkey()),
+            &[&test_setup.payer_keypair, &fake_darkpool_authority],
+            None,
+            None,
+        )
+        .await
+        .is_err());
+    }
+
+    // The matched price must be within max_price_slippage of the oracle price
+    assert!(instructions::test_settle_dark_pool_trade(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &martin.pubkey(),
+        &paul.pubkey(),
+        &test_setup.pool_pda,
+        sol_mint,
+        usdc_mint,
+        SettleDarkPoolTradeParams {
+            price: utils::scale(101, PRICE_DECIMALS),
+            ..trade_params
+        },
+    )
+    .await
+    .is_err());
+
+    // Martin goes long and Paul goes short at the matched price
+    instructions::test_settle_dark_pool_trade(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &martin.pubkey(),
+        &paul.pubkey(),
+        &test_setup.pool_pda,
+        sol_mint,
+        usdc_mint,
+        trade_params,
+    )
+    .await
+    .unwrap();
+}
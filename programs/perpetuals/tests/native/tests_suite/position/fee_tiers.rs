@@ -0,0 +1,301 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            ClosePositionParams, GetEntryPriceAndFeeParams, OpenPositionParams, SetFeeTiersParams,
+        },
+        state::{
+            custody::Custody, oracle::OraclePrice, perpetuals::Perpetuals, pool::FeeTier,
+            position::Side,
+        },
+    },
+    perpetuals_client::pda,
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+// 50% fee discount
+const DISCOUNT: u64 = 5_000;
+
+fn apply_discount(fee: u64) -> u64 {
+    fee - fee * DISCOUNT / Perpetuals::BPS_POWER as u64
+}
+
+pub async fn fee_tiers() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "eth" => utils::scale(4, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+    let multisig_signers = test_setup.get_multisig_signers();
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+    let martin_trading_stats_pda =
+        pda::get_trading_stats_pda(&martin.pubkey(), &test_setup.pool_pda).0;
+
+    // Tiers must be sorted by increasing volume
+    assert!(instructions::test_set_fee_tiers(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        SetFeeTiersParams {
+            fee_tiers: vec![
+                FeeTier {
+                    min_volume_usd: utils::scale(1_000, USDC_DECIMALS),
+                    discount: 1_000,
+                },
+                FeeTier {
+                    min_volume_usd: utils::scale(1, USDC_DECIMALS),
+                    discount: DISCOUNT,
+                },
+            ],
+        },
+        &multisig_signers,
+    )
+    .await
+    .is_err());
+
+    // Any traded volume gets the discount
+    instructions::test_set_fee_tiers(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        SetFeeTiersParams {
+            fee_tiers: vec![FeeTier {
+                min_volume_usd: utils::scale(1, USDC_DECIMALS),
+                discount: DISCOUNT,
+            }],
+        },
+        &multisig_signers,
+    )
+    .await
+    .unwrap();
+
+    let open_params = OpenPositionParams {
+        // max price paid (slippage implied)
+        price: utils::scale(1_550, ETH_DECIMALS),
+        collateral: utils::scale(1, ETH_DECIMALS),
+        size: utils::scale(2, ETH_DECIMALS),
+        side: Side::Long,
+    };
+
+    let entry_params = || GetEntryPriceAndFeeParams {
+        collateral: open_params.collateral,
+        size: open_params.size,
+        side: open_params.side,
+    };
+
+    // Martin: first position, no volume yet
+    let first_position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // The quote is bound to the trader, someone else's stats are rejected
+    assert!(instructions::test_get_entry_price_and_fee(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &paul.pubkey(),
+        &test_setup.pool_pda,
+        eth_mint,
+        Some(martin_trading_stats_pda),
+        entry_params(),
+    )
+    .await
+    .is_err());
+
+    // Martin's volume qualifies for the discount on the next open
+    {
+        let quote = instructions::test_get_entry_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &martin.pubkey(),
+            &test_setup.pool_pda,
+            eth_mint,
+            Some(martin_trading_stats_pda),
+            entry_params(),
+        )
+        .await
+        .unwrap();
+
+        let full_fee_quote = instructions::test_get_entry_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &martin.pubkey(),
+            &test_setup.pool_pda,
+            eth_mint,
+            None,
+            entry_params(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote.fee_discount, DISCOUNT);
+        assert_eq!(full_fee_quote.fee_discount, 0);
+        assert_eq!(quote.fee, apply_discount(full_fee_quote.fee));
+
+        let funding_account = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+        let balance_before =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, funding_account).await;
+
+        instructions::test_open_position(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            open_params,
+        )
+        .await
+        .unwrap();
+
+        let balance_after =
+            utils::get_token_account_balance(&test_setup.program_test_ctx, funding_account).await;
+
+        // The discounted fee is charged on top of the collateral
+        assert_eq!(
+            balance_before - balance_after,
+            open_params.collateral + quote.fee
+        );
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // The discount also applies on close
+    {
+        let quote = instructions::test_get_exit_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &first_position_pda,
+            Some(martin_trading_stats_pda),
+        )
+        .await
+        .unwrap();
+
+        let full_fee_quote = instructions::test_get_exit_price_and_fee(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &first_position_pda,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(quote.fee, apply_discount(full_fee_quote.fee));
+
+        let eth_custody_before =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        instructions::test_close_position(
+            &test_setup.program_test_ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            eth_mint,
+            &first_position_pda,
+            ClosePositionParams {
+                // lowest exit price paid (slippage implied)
+                price: utils::scale(1_400, USDC_DECIMALS),
+            },
+        )
+        .await
+        .unwrap();
+
+        let eth_custody_after =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        let eth_price = OraclePrice::new(utils::scale(1_500, ETH_DECIMALS), -(ETH_DECIMALS as i32));
+        assert_eq!(
+            eth_custody_after.collected_fees.close_position_usd
+                - eth_custody_before.collected_fees.close_position_usd,
+            eth_price
+                .get_asset_amount_usd(quote.fee, ETH_DECIMALS)
+                .unwrap()
+        );
+    }
+}
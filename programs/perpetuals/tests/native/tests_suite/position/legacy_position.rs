@@ -0,0 +1,219 @@
+use {
+    crate::{instructions, utils},
+    anchor_lang::{AnchorSerialize, Discriminator},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::{
+            custody::Custody,
+            position::{LegacyPosition, Position, Side},
+        },
+    },
+    perpetuals_client::pda,
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn legacy_position() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    // Turn it into a position opened before per-owner indexes were added
+    let position = utils::get_account::<Position>(&test_setup.program_test_ctx, position_pda).await;
+    let (legacy_position_pda, legacy_position_bump) = pda::get_legacy_position_pda(
+        &martin.pubkey(),
+        &test_setup.pool_pda,
+        &eth_custody_pda,
+        Side::Long,
+    );
+    {
+        let legacy_position = LegacyPosition {
+            owner: position.owner,
+            pool: position.pool,
+            custody: position.custody,
+            collateral_custody: position.collateral_custody,
+            open_time: position.open_time,
+            update_time: position.update_time,
+            side: position.side,
+            price: position.price,
+            size_usd: position.size_usd,
+            borrow_size_usd: position.borrow_size_usd,
+            collateral_usd: position.collateral_usd,
+            unrealized_profit_usd: position.unrealized_profit_usd,
+            unrealized_loss_usd: position.unrealized_loss_usd,
+            cumulative_interest_snapshot: position.cumulative_interest_snapshot,
+            locked_amount: position.locked_amount,
+            collateral_amount: position.collateral_amount,
+            bump: legacy_position_bump,
+        };
+
+        // legacy accounts carry the discriminator of the current layout
+        let mut data = Position::discriminator().to_vec();
+        data.extend(legacy_position.try_to_vec().unwrap());
+        data.resize(LegacyPosition::LEN, 0);
+
+        utils::move_account(
+            &test_setup.program_test_ctx,
+            position_pda,
+            legacy_position_pda,
+            data,
+        )
+        .await;
+    }
+
+    let custody_before =
+        utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+    let new_position_pda = instructions::test_upgrade_position(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &martin.pubkey(),
+        &test_setup.pool_pda,
+        &eth_custody_pda,
+        Side::Long,
+    )
+    .await
+    .unwrap();
+
+    // Position is carried over and the pool is not affected by the upgrade
+    {
+        let new_position =
+            utils::get_account::<Position>(&test_setup.program_test_ctx, new_position_pda).await;
+
+        assert_eq!(new_position.open_time, position.open_time);
+        assert_eq!(new_position.price, position.price);
+        assert_eq!(new_position.size_usd, position.size_usd);
+        assert_eq!(new_position.collateral_usd, position.collateral_usd);
+        assert_eq!(new_position.locked_amount, position.locked_amount);
+        assert_eq!(new_position.collateral_amount, position.collateral_amount);
+
+        let custody_after =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        assert_eq!(custody_after.assets, custody_before.assets);
+        assert_eq!(custody_after.long_positions, custody_before.long_positions);
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Legacy position is gone, it can't be upgraded twice
+    assert!(instructions::test_upgrade_position(
+        &test_setup.program_test_ctx,
+        &test_setup.payer_keypair,
+        &martin.pubkey(),
+        &test_setup.pool_pda,
+        &eth_custody_pda,
+        Side::Long,
+    )
+    .await
+    .is_err());
+
+    // Martin closes the upgraded position
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &new_position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+}
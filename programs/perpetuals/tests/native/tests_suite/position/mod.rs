@@ -1,5 +1,15 @@
+pub mod dark_pool_trade;
+pub mod fee_tiers;
+pub mod legacy_position;
 pub mod liquidate_position;
 pub mod max_user_profit;
 pub mod min_max_leverage;
+pub mod multiple_positions;
+pub mod trading_delegate;
+pub mod transfer_position;
 
-pub use {liquidate_position::*, max_user_profit::*, min_max_leverage::*};
+pub use {
+    dark_pool_trade::*, fee_tiers::*, legacy_position::*, liquidate_position::*,
+    max_user_profit::*, min_max_leverage::*, multiple_positions::*, trading_delegate::*,
+    transfer_position::*,
+};
@@ -0,0 +1,208 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::{
+            custody::Custody,
+            position::{Position, Side},
+        },
+    },
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn multiple_positions() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(4, ETH_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+    let open_params = OpenPositionParams {
+        // max price paid (slippage implied)
+        price: utils::scale(1_550, ETH_DECIMALS),
+        collateral: utils::scale(1, ETH_DECIMALS),
+        size: utils::scale(2, ETH_DECIMALS),
+        side: Side::Long,
+    };
+
+    // Martin: Open 1 ETH long position x2
+    let first_position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Martin: Open an independent 1 ETH long position x5 in the same custody
+    let second_position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            size: utils::scale(5, ETH_DECIMALS),
+            ..open_params
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    assert_ne!(first_position_pda, second_position_pda);
+
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        assert_eq!(eth_custody.long_positions.open_positions, 2);
+    }
+
+    // View instructions work with any of the positions
+    {
+        let first_pnl = instructions::test_get_pnl(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &first_position_pda,
+        )
+        .await
+        .unwrap();
+
+        let second_pnl = instructions::test_get_pnl(
+            &test_setup.program_test_ctx,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            &second_position_pda,
+        )
+        .await
+        .unwrap();
+
+        assert!(second_pnl.loss > first_pnl.loss);
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Closing one position doesn't affect the other
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &first_position_pda,
+        ClosePositionParams {
+            // lowest exit price paid (slippage implied)
+            price: utils::scale(1_400, USDC_DECIMALS),
+        },
+    )
+    .await
+    .unwrap();
+
+    {
+        let eth_custody =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        assert_eq!(eth_custody.long_positions.open_positions, 1);
+        assert!(utils::try_get_account::<Position>(
+            &test_setup.program_test_ctx,
+            second_position_pda
+        )
+        .await
+        .is_some());
+    }
+
+    // Indexes of closed positions are not reused
+    let third_position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    assert_ne!(third_position_pda, first_position_pda);
+}
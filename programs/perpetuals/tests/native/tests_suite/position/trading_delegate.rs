@@ -0,0 +1,257 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams, SetTradingDelegateParams},
+        state::{perpetuals::Perpetuals, position::Side, trading_delegate::DelegatePermissions},
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn trading_delegate() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(3, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "bot",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let bot = test_setup.get_user_keypair_by_name("bot");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let martin_eth_pda = utils::find_associated_token_account(&martin.pubkey(), eth_mint).0;
+
+    let delegate_params = SetTradingDelegateParams {
+        delegate: bot.pubkey(),
+        permissions: DelegatePermissions {
+            allow_open_position: true,
+            allow_close_position: true,
+            allow_add_collateral: true,
+            allow_remove_collateral: false,
+        },
+        max_size_usd: utils::scale(10_000, Perpetuals::USD_DECIMALS),
+        max_leverage: 10 * Perpetuals::BPS_POWER as u64,
+        expiration_time: 0,
+    };
+
+    let open_params = OpenPositionParams {
+        // max price paid (slippage implied)
+        price: utils::scale(1_550, ETH_DECIMALS),
+        collateral: utils::scale(1, ETH_DECIMALS),
+        size: utils::scale(5, ETH_DECIMALS),
+        side: Side::Long,
+    };
+
+    // Bot can't trade before being delegated
+    assert!(instructions::test_delegated_open_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .is_err());
+
+    instructions::test_set_trading_delegate(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        delegate_params,
+    )
+    .await
+    .unwrap();
+
+    // Advance to get a fresh blockhash, the transaction would be deduplicated otherwise
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Bot can't spend Martin's tokens without an SPL token approval
+    assert!(instructions::test_delegated_open_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .is_err());
+
+    utils::approve_token_delegate(
+        &test_setup.program_test_ctx,
+        martin,
+        &martin_eth_pda,
+        &bot.pubkey(),
+        utils::scale(2, ETH_DECIMALS),
+    )
+    .await;
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    // Size above the delegate limit
+    assert!(instructions::test_delegated_open_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            size: utils::scale(7, ETH_DECIMALS),
+            ..open_params
+        },
+    )
+    .await
+    .is_err());
+
+    // Bot opens a position owned by Martin, funded from Martin's account
+    let position_pda = instructions::test_delegated_open_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .unwrap()
+    .0;
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    let close_params = ClosePositionParams {
+        // lowest exit price paid (slippage implied)
+        price: utils::scale(1_400, USDC_DECIMALS),
+    };
+
+    // Only the owner and its delegate can close the position
+    assert!(instructions::test_delegated_close_position(
+        &test_setup.program_test_ctx,
+        alice,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        close_params,
+    )
+    .await
+    .is_err());
+
+    // Funds are returned to Martin
+    instructions::test_delegated_close_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        close_params,
+    )
+    .await
+    .unwrap();
+
+    // Expired delegate can't trade anymore
+    instructions::test_set_trading_delegate(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        SetTradingDelegateParams {
+            expiration_time: utils::get_current_unix_timestamp(&test_setup.program_test_ctx).await,
+            ..delegate_params
+        },
+    )
+    .await
+    .unwrap();
+
+    assert!(instructions::test_delegated_open_position(
+        &test_setup.program_test_ctx,
+        bot,
+        &martin.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        open_params,
+    )
+    .await
+    .is_err());
+}
@@ -0,0 +1,169 @@
+use {
+    crate::{instructions, utils},
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{ClosePositionParams, OpenPositionParams},
+        state::{custody::Custody, position::Side},
+    },
+    solana_sdk::signer::Signer,
+};
+
+const ETH_DECIMALS: u8 = 9;
+const USDC_DECIMALS: u8 = 6;
+
+pub async fn transfer_position() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(100, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                    "eth" => utils::scale(2, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "paul",
+                token_balances: hashmap! {},
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(1_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(100.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(100, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let paul = test_setup.get_user_keypair_by_name("paul");
+
+    let eth_mint = &test_setup.get_mint_by_name("eth");
+    let eth_custody_pda = test_setup.custodies_info[1].custody_pda;
+
+    // Martin: Open 1 ETH long position x5
+    let position_pda = instructions::test_open_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        OpenPositionParams {
+            // max price paid (slippage implied)
+            price: utils::scale(1_550, ETH_DECIMALS),
+            collateral: utils::scale(1, ETH_DECIMALS),
+            size: utils::scale(5, ETH_DECIMALS),
+            side: Side::Long,
+        },
+    )
+    .await
+    .unwrap()
+    .0;
+
+    let custody_before =
+        utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+    let new_position_pda = instructions::test_transfer_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &paul.pubkey(),
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &position_pda,
+    )
+    .await
+    .unwrap();
+
+    // Pool is not affected by the transfer
+    {
+        let custody_after =
+            utils::get_account::<Custody>(&test_setup.program_test_ctx, eth_custody_pda).await;
+
+        assert_eq!(custody_after.assets, custody_before.assets);
+        assert_eq!(custody_after.long_positions, custody_before.long_positions);
+    }
+
+    utils::warp_forward(&test_setup.program_test_ctx, 1).await;
+
+    let close_params = ClosePositionParams {
+        // lowest exit price paid (slippage implied)
+        price: utils::scale(1_400, USDC_DECIMALS),
+    };
+
+    // Martin doesn't own the position anymore
+    assert!(instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        martin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &position_pda,
+        close_params,
+    )
+    .await
+    .is_err());
+
+    // Paul closes the position and receives the funds
+    instructions::test_close_position(
+        &test_setup.program_test_ctx,
+        paul,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        eth_mint,
+        &new_position_pda,
+        close_params,
+    )
+    .await
+    .unwrap();
+}
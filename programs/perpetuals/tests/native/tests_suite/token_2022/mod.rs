@@ -0,0 +1,3 @@
+pub mod transfer_fee;
+
+pub use transfer_fee::*;
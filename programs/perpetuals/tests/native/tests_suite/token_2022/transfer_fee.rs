@@ -0,0 +1,394 @@
+use {
+    crate::{instructions, utils},
+    anchor_spl::token_2022::spl_token_2022,
+    maplit::hashmap,
+    perpetuals::{
+        instructions::{
+            AddCustodyParams, AddLiquidityParams, ClosePositionParams, OpenPositionParams,
+            SetCustomOraclePriceParams, SwapParams,
+        },
+        state::{
+            custody::Custody,
+            pool::TokenRatios,
+            position::{Position, Side},
+        },
+    },
+    perpetuals_client::pda,
+    solana_program::pubkey::Pubkey,
+    solana_program_test::BanksClientError,
+    solana_sdk::signer::Signer,
+};
+
+const USDC_DECIMALS: u8 = 6;
+const ETH_DECIMALS: u8 = 9;
+const FEE_TOKEN_DECIMALS: u8 = 6;
+
+// 1% of every transfer is withheld by the mint
+const TRANSFER_FEE_BPS: u64 = 100;
+
+pub async fn transfer_fee() {
+    let test_setup = utils::TestSetup::new(
+        vec![
+            utils::UserParam {
+                name: "alice",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(10_000, USDC_DECIMALS),
+                    "eth" => utils::scale(10, ETH_DECIMALS),
+                },
+            },
+            utils::UserParam {
+                name: "martin",
+                token_balances: hashmap! {
+                    "usdc" => utils::scale(1_000, USDC_DECIMALS),
+                },
+            },
+        ],
+        vec![
+            utils::MintParam {
+                name: "usdc",
+                decimals: USDC_DECIMALS,
+            },
+            utils::MintParam {
+                name: "eth",
+                decimals: ETH_DECIMALS,
+            },
+        ],
+        vec!["admin_a", "admin_b", "admin_c"],
+        "main_pool",
+        vec![
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "usdc",
+                    is_stable: true,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1, USDC_DECIMALS),
+                    initial_conf: utils::scale_f64(0.01, USDC_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10_000, USDC_DECIMALS),
+                payer_user_name: "alice",
+            },
+            utils::SetupCustodyWithLiquidityParams {
+                setup_custody_params: utils::SetupCustodyParams {
+                    mint_name: "eth",
+                    is_stable: false,
+                    is_virtual: false,
+                    target_ratio: utils::ratio_from_percentage(50.0),
+                    min_ratio: utils::ratio_from_percentage(0.0),
+                    max_ratio: utils::ratio_from_percentage(100.0),
+                    initial_price: utils::scale(1_500, ETH_DECIMALS),
+                    initial_conf: utils::scale(10, ETH_DECIMALS),
+                    pricing_params: None,
+                    permissions: None,
+                    fees: None,
+                    borrow_rate: None,
+                },
+                liquidity_amount: utils::scale(10, ETH_DECIMALS),
+                payer_user_name: "alice",
+            },
+        ],
+    )
+    .await;
+
+    let ctx = &test_setup.program_test_ctx;
+    let alice = test_setup.get_user_keypair_by_name("alice");
+    let martin = test_setup.get_user_keypair_by_name("martin");
+    let mint_authority = &test_setup.root_authority_keypair;
+    let usdc_mint = &test_setup.get_mint_by_name("usdc");
+
+    // Mints with extensions breaking custody accounting are rejected
+    {
+        let non_transferable_mint = utils::create_token_2022_mint(
+            ctx,
+            &mint_authority.pubkey(),
+            utils::Token2022MintParams {
+                decimals: FEE_TOKEN_DECIMALS,
+                transfer_fee_basis_points: None,
+                non_transferable: true,
+            },
+        )
+        .await;
+
+        assert!(add_custody(&test_setup, &non_transferable_mint)
+            .await
+            .is_err());
+    }
+
+    let fee_token_mint = &utils::create_token_2022_mint(
+        ctx,
+        &mint_authority.pubkey(),
+        utils::Token2022MintParams {
+            decimals: FEE_TOKEN_DECIMALS,
+            transfer_fee_basis_points: Some(TRANSFER_FEE_BPS as u16),
+            non_transferable: false,
+        },
+    )
+    .await;
+    let fee_custody_pda = add_custody(&test_setup, fee_token_mint).await.unwrap();
+    let fee_custody_token_account_pda =
+        pda::get_custody_token_account_pda(&test_setup.pool_pda, fee_token_mint).0;
+
+    {
+        let custody = utils::get_account::<Custody>(ctx, fee_custody_pda).await;
+
+        assert_eq!(custody.token_program, spl_token_2022::ID);
+    }
+
+    utils::initialize_token_accounts_for_program(
+        ctx,
+        fee_token_mint,
+        &spl_token_2022::ID,
+        &[alice.pubkey(), martin.pubkey()],
+    )
+    .await;
+
+    for (user, amount) in [(alice, 10_000), (martin, 1_000)] {
+        utils::mint_token_2022_tokens(
+            ctx,
+            mint_authority,
+            fee_token_mint,
+            &utils::find_associated_token_account_for_program(
+                &user.pubkey(),
+                fee_token_mint,
+                &spl_token_2022::ID,
+            )
+            .0,
+            utils::scale(amount, FEE_TOKEN_DECIMALS),
+        )
+        .await;
+    }
+
+    // Liquidity is credited net of the transfer fee
+    {
+        let amount_in = utils::scale(1_000, FEE_TOKEN_DECIMALS);
+
+        instructions::test_add_liquidity(
+            ctx,
+            alice,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            fee_token_mint,
+            AddLiquidityParams {
+                amount_in,
+                min_lp_amount_out: 1,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            utils::get_token_account_withheld_amount(ctx, fee_custody_token_account_pda).await,
+            amount_in * TRANSFER_FEE_BPS / 10_000
+        );
+        let (balance, accounted) = get_custody_balance(&test_setup, &fee_custody_pda).await;
+        assert_eq!(balance, accounted);
+    }
+
+    // Swap in, the custody receives the amount left after the transfer fee
+    {
+        let amount_in = utils::scale(100, FEE_TOKEN_DECIMALS);
+        let withheld_before =
+            utils::get_token_account_withheld_amount(ctx, fee_custody_token_account_pda).await;
+
+        instructions::test_swap(
+            ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            usdc_mint,
+            // The program receives the fee token
+            fee_token_mint,
+            SwapParams {
+                amount_in,
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            utils::get_token_account_withheld_amount(ctx, fee_custody_token_account_pda).await
+                - withheld_before,
+            amount_in * TRANSFER_FEE_BPS / 10_000
+        );
+        let (balance, accounted) = get_custody_balance(&test_setup, &fee_custody_pda).await;
+        assert_eq!(balance, accounted);
+    }
+
+    utils::warp_forward(ctx, 1).await;
+
+    // Swap out, the transfer fee is withheld in the user account
+    {
+        let martin_fee_token_account = utils::find_associated_token_account_for_program(
+            &martin.pubkey(),
+            fee_token_mint,
+            &spl_token_2022::ID,
+        )
+        .0;
+        let balance_before = utils::get_token_account_balance(ctx, martin_fee_token_account).await;
+        let custody_balance_before =
+            utils::get_token_account_balance(ctx, fee_custody_token_account_pda).await;
+
+        instructions::test_swap(
+            ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            fee_token_mint,
+            // The program receives USDC
+            usdc_mint,
+            SwapParams {
+                amount_in: utils::scale(50, USDC_DECIMALS),
+                min_amount_out: 0,
+            },
+        )
+        .await
+        .unwrap();
+
+        let received =
+            utils::get_token_account_balance(ctx, martin_fee_token_account).await - balance_before;
+        let withheld =
+            utils::get_token_account_withheld_amount(ctx, martin_fee_token_account).await;
+        let dispensed = custody_balance_before
+            - utils::get_token_account_balance(ctx, fee_custody_token_account_pda).await;
+
+        assert!(withheld > 0);
+        assert_eq!(received + withheld, dispensed);
+        let (balance, accounted) = get_custody_balance(&test_setup, &fee_custody_pda).await;
+        assert_eq!(balance, accounted);
+    }
+
+    // Position collateral is recorded net of the transfer fee, checked by the helper
+    {
+        let collateral = utils::scale(100, FEE_TOKEN_DECIMALS);
+
+        let position_pda = instructions::test_open_position(
+            ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            fee_token_mint,
+            OpenPositionParams {
+                // max price paid (slippage implied)
+                price: utils::scale_f64(2.2, FEE_TOKEN_DECIMALS),
+                collateral,
+                size: utils::scale(200, FEE_TOKEN_DECIMALS),
+                side: Side::Long,
+            },
+        )
+        .await
+        .unwrap()
+        .0;
+
+        let position = utils::get_account::<Position>(ctx, position_pda).await;
+        let custody = utils::get_account::<Custody>(ctx, fee_custody_pda).await;
+
+        assert!(position.collateral_amount < collateral);
+        assert_eq!(custody.assets.collateral, position.collateral_amount);
+        // LP share of the entry fee stays in the custody without being accounted
+        let (balance, accounted) = get_custody_balance(&test_setup, &fee_custody_pda).await;
+        assert!(balance >= accounted);
+
+        utils::warp_forward(ctx, 1).await;
+
+        instructions::test_close_position(
+            ctx,
+            martin,
+            &test_setup.payer_keypair,
+            &test_setup.pool_pda,
+            fee_token_mint,
+            &position_pda,
+            ClosePositionParams {
+                // lowest exit price paid (slippage implied)
+                price: utils::scale_f64(1.8, FEE_TOKEN_DECIMALS),
+            },
+        )
+        .await
+        .unwrap();
+
+        let (balance, accounted) = get_custody_balance(&test_setup, &fee_custody_pda).await;
+        assert!(balance >= accounted);
+    }
+}
+
+// Adds a custody for a $2 token, all custodies keep unbounded ratios
+async fn add_custody(
+    test_setup: &utils::TestSetup,
+    mint: &Pubkey,
+) -> std::result::Result<Pubkey, BanksClientError> {
+    let ctx = &test_setup.program_test_ctx;
+    let admin = test_setup.get_multisig_member_keypair_by_name("admin_a");
+    let multisig_signers = test_setup.get_multisig_signers();
+    let oracle_pda = pda::get_custom_oracle_account_pda(&test_setup.pool_pda, mint).0;
+
+    let ratios = [3_333, 3_333, 3_334]
+        .into_iter()
+        .map(|target| TokenRatios {
+            target,
+            min: 0,
+            max: 10_000,
+        })
+        .collect();
+
+    let custody_pda = instructions::test_add_custody(
+        ctx,
+        admin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        mint,
+        FEE_TOKEN_DECIMALS,
+        AddCustodyParams {
+            is_stable: false,
+            is_virtual: false,
+            oracle: utils::oracle_params_regular(oracle_pda),
+            pricing: utils::pricing_params_regular(false),
+            permissions: utils::permissions_full(),
+            fees: utils::fees_linear_regular(),
+            borrow_rate: utils::borrow_rate_regular(),
+            ratios,
+        },
+        &multisig_signers,
+    )
+    .await?
+    .0;
+
+    let price = utils::scale(2, FEE_TOKEN_DECIMALS);
+    instructions::test_set_custom_oracle_price(
+        ctx,
+        admin,
+        &test_setup.payer_keypair,
+        &test_setup.pool_pda,
+        &custody_pda,
+        &oracle_pda,
+        SetCustomOraclePriceParams {
+            price,
+            expo: -(FEE_TOKEN_DECIMALS as i32),
+            conf: utils::scale_f64(0.01, FEE_TOKEN_DECIMALS),
+            ema: price,
+            publish_time: utils::get_current_unix_timestamp(ctx).await,
+        },
+        &multisig_signers,
+    )
+    .await?;
+
+    Ok(custody_pda)
+}
+
+// Returns tokens held by the custody and the amount its accounting expects,
+// tokens withheld by the transfer fee must never be counted
+async fn get_custody_balance(test_setup: &utils::TestSetup, custody_pda: &Pubkey) -> (u64, u64) {
+    let ctx = &test_setup.program_test_ctx;
+    let custody = utils::get_account::<Custody>(ctx, *custody_pda).await;
+    let balance = utils::get_token_account_balance(ctx, custody.token_account).await;
+
+    (
+        balance,
+        custody.assets.owned + custody.assets.collateral + custody.assets.protocol_fees,
+    )
+}
@@ -68,6 +68,8 @@ pub fn pricing_params_regular(use_ema: bool) -> PricingParams {
         max_utilization: 0,
         max_position_locked_usd: 0,
         max_total_locked_usd: 0,
+        price_impact_mult: 0,
+        max_price_impact: 0,
     }
 }
 
@@ -1,7 +1,6 @@
 pub mod fixtures;
-pub mod pda;
 pub mod test_setup;
 #[allow(clippy::module_inception)]
 pub mod utils;
 
-pub use {fixtures::*, pda::*, test_setup::*, utils::*};
+pub use {fixtures::*, test_setup::*, utils::*};
@@ -6,15 +6,19 @@ use {
     },
     bonfida_test_utils::ProgramTestExt,
     perpetuals::{
-        instructions::{AddCustodyParams, AddLiquidityParams, SetCustomOraclePriceParams},
+        instructions::{
+            AddCustodyParams, AddLiquidityParams, SetCustomOraclePriceParams, DARKPOOL_PROGRAM_ID,
+        },
         state::{
             custody::{BorrowRateParams, Fees, PricingParams},
             perpetuals::Permissions,
             pool::TokenRatios,
         },
     },
+    perpetuals_client::pda,
     solana_program::pubkey::Pubkey,
-    solana_program_test::{ProgramTest, ProgramTestContext},
+    solana_program_runtime::invoke_context::ProcessInstructionWithContext,
+    solana_program_test::{processor, ProgramTest, ProgramTestContext},
     solana_sdk::{signature::Keypair, signer::Signer},
     std::collections::HashMap,
     tokio::sync::RwLock,
@@ -105,7 +109,22 @@ impl TestSetup {
         pool_name: &str,
         custodies_params: Vec<SetupCustodyWithLiquidityParams<'_>>,
     ) -> TestSetup {
-        let mut program_test = ProgramTest::new("perpetuals", perpetuals::id(), None);
+        // Uses the BPF program with `cargo test-bpf`, the native entrypoint otherwise
+        let mut program_test = ProgramTest::new(
+            "perpetuals",
+            perpetuals::id(),
+            processor!(utils::process_instruction),
+        );
+
+        // Native stub of the darkpool program, the only signer of dark trade settlements.
+        // Added as a builtin so that it doesn't need a BPF build with `cargo test-bpf`
+        let darkpool_processor: Option<ProcessInstructionWithContext> =
+            processor!(utils::process_darkpool_instruction);
+        program_test.add_builtin_program(
+            "darkpool",
+            DARKPOOL_PROGRAM_ID,
+            darkpool_processor.unwrap(),
+        );
 
         // Initialize keypairs
         let keypairs: Vec<Keypair> = utils::create_and_fund_multiple_accounts(
@@ -260,7 +279,7 @@ impl TestSetup {
                     .unwrap();
 
                 let custom_oracle_pda =
-                    utils::get_custom_oracle_account(&pool_pda, &mint_info.pubkey).0;
+                    pda::get_custom_oracle_account_pda(&pool_pda, &mint_info.pubkey).0;
 
                 let target_ratio = 10_000 / (idx + 1) as u64;
 
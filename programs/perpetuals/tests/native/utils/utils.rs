@@ -1,17 +1,28 @@
 use {
     crate::instructions,
     anchor_lang::{prelude::*, InstructionData},
-    anchor_spl::token::spl_token,
+    anchor_spl::{
+        token::spl_token,
+        token_2022::spl_token_2022::{
+            self,
+            extension::{
+                transfer_fee::{self, TransferFeeAmount},
+                BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+            },
+        },
+    },
     bonfida_test_utils::ProgramTestContextExt,
     borsh::BorshDeserialize,
     perpetuals::{
         instructions::SetCustodyConfigParams,
         math,
-        state::{custody::Custody, perpetuals::Perpetuals, pool::TokenRatios},
-    },
-    solana_program::{
-        clock::DEFAULT_MS_PER_SLOT, epoch_schedule::DEFAULT_SLOTS_PER_EPOCH, program_pack::Pack,
+        state::{
+            custody::Custody, perpetuals::Perpetuals, pool::TokenRatios,
+            position_counter::PositionCounter,
+        },
     },
+    perpetuals_client::pda,
+    solana_program::{clock::DEFAULT_MS_PER_SLOT, epoch_schedule::DEFAULT_SLOTS_PER_EPOCH},
     solana_program_test::{BanksClientError, ProgramTest, ProgramTestContext},
     solana_sdk::{account, signature::Keypair, signer::Signer, signers::Signers},
     std::ops::{Div, Mul},
@@ -20,6 +31,45 @@ use {
 
 pub const ANCHOR_DISCRIMINATOR_SIZE: usize = 8;
 
+// Native entrypoint used when the program isn't built for BPF. Anchor ties account
+// infos to the 'info lifetime, so they are leaked to satisfy processor!.
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> solana_program::entrypoint::ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+
+    perpetuals::entry(program_id, accounts, data)
+}
+
+// Stands in for the darkpool program, which settles matched trades through a CPI
+// signed by its PDA. Forwards the instruction to perpetuals, the first account
+// being the perpetuals program.
+pub fn process_darkpool_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> solana_program::entrypoint::ProgramResult {
+    let (darkpool_authority, bump) = pda::get_darkpool_authority_pda();
+    assert_eq!(*program_id, perpetuals::instructions::DARKPOOL_PROGRAM_ID);
+
+    let ix = solana_sdk::instruction::Instruction {
+        program_id: perpetuals::id(),
+        accounts: accounts[1..]
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer || *account.key == darkpool_authority,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: data.to_vec(),
+    };
+
+    solana_program::program::invoke_signed(&ix, accounts, &[&[b"darkpool", &[bump]]])
+}
+
 pub fn create_and_fund_account(address: &Pubkey, program_test: &mut ProgramTest) {
     program_test.add_account(
         *address,
@@ -31,12 +81,16 @@ pub fn create_and_fund_account(address: &Pubkey, program_test: &mut ProgramTest)
 }
 
 pub fn find_associated_token_account(owner: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    find_associated_token_account_for_program(owner, mint, &anchor_spl::token::ID)
+}
+
+pub fn find_associated_token_account_for_program(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> (Pubkey, u8) {
     Pubkey::find_program_address(
-        &[
-            owner.as_ref(),
-            anchor_spl::token::ID.as_ref(),
-            mint.as_ref(),
-        ],
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
         &anchor_spl::associated_token::ID,
     )
 }
@@ -48,13 +102,16 @@ pub fn copy_keypair(keypair: &Keypair) -> Keypair {
 pub async fn get_token_account(
     program_test_ctx: &RwLock<ProgramTestContext>,
     key: Pubkey,
-) -> spl_token::state::Account {
+) -> spl_token_2022::state::Account {
     let mut ctx = program_test_ctx.write().await;
     let banks_client = &mut ctx.banks_client;
 
     let raw_account = banks_client.get_account(key).await.unwrap().unwrap();
 
-    spl_token::state::Account::unpack(&raw_account.data).unwrap()
+    // Token-2022 accounts have the same base layout, followed by extensions
+    StateWithExtensions::<spl_token_2022::state::Account>::unpack(&raw_account.data)
+        .unwrap()
+        .base
 }
 
 pub async fn get_token_account_balance(
@@ -64,6 +121,23 @@ pub async fn get_token_account_balance(
     get_token_account(program_test_ctx, key).await.amount
 }
 
+// Transfer fees withheld in a Token-2022 account, they aren't part of the balance
+pub async fn get_token_account_withheld_amount(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    key: Pubkey,
+) -> u64 {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    let raw_account = banks_client.get_account(key).await.unwrap().unwrap();
+    let account =
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&raw_account.data).unwrap();
+
+    account
+        .get_extension::<TransferFeeAmount>()
+        .map_or(0, |extension| extension.withheld_amount.into())
+}
+
 pub async fn get_account<T: anchor_lang::AccountDeserialize>(
     program_test_ctx: &RwLock<ProgramTestContext>,
     key: Pubkey,
@@ -76,6 +150,73 @@ pub async fn get_account<T: anchor_lang::AccountDeserialize>(
     T::try_deserialize(&mut account.data.as_slice()).unwrap()
 }
 
+// Returns None if the account doesn't exist, e.g. a closed position
+pub async fn try_get_account<T: anchor_lang::AccountDeserialize>(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    key: Pubkey,
+) -> Option<T> {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    let account = banks_client.get_account(key).await.unwrap()?;
+
+    Some(T::try_deserialize(&mut account.data.as_slice()).unwrap())
+}
+
+// Overwrites the account data, used to recreate accounts created by a previous program version
+pub async fn set_account_data(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    key: Pubkey,
+    data: Vec<u8>,
+) {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    let mut account = banks_client.get_account(key).await.unwrap().unwrap();
+    account.data = data;
+
+    ctx.set_account(&key, &account.into());
+}
+
+// Moves the account to a new address with the given data, used to recreate accounts whose
+// address was derived differently by a previous program version
+pub async fn move_account(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    from: Pubkey,
+    to: Pubkey,
+    data: Vec<u8>,
+) {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    let mut account = banks_client.get_account(from).await.unwrap().unwrap();
+    account.data = data;
+
+    ctx.set_account(&to, &account.into());
+    ctx.set_account(&from, &account::AccountSharedData::default());
+}
+
+// Returns the program owning the mint, SPL Token or Token-2022
+pub async fn get_token_program(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    mint: Pubkey,
+) -> Pubkey {
+    let mut ctx = program_test_ctx.write().await;
+    let banks_client = &mut ctx.banks_client;
+
+    banks_client.get_account(mint).await.unwrap().unwrap().owner
+}
+
+// Index the next position of the owner will be opened with
+pub async fn get_next_position_index(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Pubkey,
+) -> u64 {
+    try_get_account::<PositionCounter>(program_test_ctx, pda::get_position_counter_pda(owner).0)
+        .await
+        .map_or(0, |counter| counter.next_index)
+}
+
 pub async fn get_current_unix_timestamp(program_test_ctx: &RwLock<ProgramTestContext>) -> i64 {
     let mut ctx = program_test_ctx.write().await;
     let banks_client = &mut ctx.banks_client;
@@ -134,6 +275,182 @@ pub async fn mint_tokens(
         .unwrap();
 }
 
+pub struct Token2022MintParams {
+    pub decimals: u8,
+    pub transfer_fee_basis_points: Option<u16>,
+    pub non_transferable: bool,
+}
+
+// Creates a Token-2022 mint with the requested extensions, the transfer fee isn't capped
+pub async fn create_token_2022_mint(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    mint_authority: &Pubkey,
+    params: Token2022MintParams,
+) -> Pubkey {
+    let mut ctx = program_test_ctx.write().await;
+    let last_blockhash = ctx.last_blockhash;
+    let payer = copy_keypair(&ctx.payer);
+    let mint = Keypair::new();
+    let token_program = spl_token_2022::ID;
+
+    let mut extensions = vec![];
+    let mut ixs = vec![];
+    if let Some(transfer_fee_basis_points) = params.transfer_fee_basis_points {
+        extensions.push(ExtensionType::TransferFeeConfig);
+        ixs.push(
+            transfer_fee::instruction::initialize_transfer_fee_config(
+                &token_program,
+                &mint.pubkey(),
+                Some(mint_authority),
+                Some(mint_authority),
+                transfer_fee_basis_points,
+                u64::MAX,
+            )
+            .unwrap(),
+        );
+    }
+    if params.non_transferable {
+        extensions.push(ExtensionType::NonTransferable);
+        ixs.push(
+            spl_token_2022::instruction::initialize_non_transferable_mint(
+                &token_program,
+                &mint.pubkey(),
+            )
+            .unwrap(),
+        );
+    }
+    ixs.push(
+        spl_token_2022::instruction::initialize_mint2(
+            &token_program,
+            &mint.pubkey(),
+            mint_authority,
+            None,
+            params.decimals,
+        )
+        .unwrap(),
+    );
+
+    let space = ExtensionType::get_account_len::<spl_token_2022::state::Mint>(&extensions);
+    let rent = ctx.banks_client.get_rent().await.unwrap();
+    ixs.insert(
+        0,
+        solana_sdk::system_instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            &token_program,
+        ),
+    );
+
+    let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &ixs,
+        Some(&payer.pubkey()),
+        &[&payer, &mint],
+        last_blockhash,
+    );
+
+    ctx.banks_client.process_transaction(tx).await.unwrap();
+
+    mint.pubkey()
+}
+
+// Creates the associated token account of each owner, the mint can belong to either token program
+pub async fn initialize_token_accounts_for_program(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    owners: &[Pubkey],
+) {
+    let mut ctx = program_test_ctx.write().await;
+    let last_blockhash = ctx.last_blockhash;
+    let payer = copy_keypair(&ctx.payer);
+
+    let ixs: Vec<_> = owners
+        .iter()
+        .map(|owner| {
+            spl_associated_token_account::instruction::create_associated_token_account(
+                &payer.pubkey(),
+                owner,
+                mint,
+                token_program,
+            )
+        })
+        .collect();
+
+    let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &ixs,
+        Some(&payer.pubkey()),
+        &[&payer],
+        last_blockhash,
+    );
+
+    ctx.banks_client.process_transaction(tx).await.unwrap();
+}
+
+pub async fn mint_token_2022_tokens(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    mint_authority: &Keypair,
+    mint: &Pubkey,
+    token_account: &Pubkey,
+    amount: u64,
+) {
+    let mut ctx = program_test_ctx.write().await;
+    let last_blockhash = ctx.last_blockhash;
+    let payer = copy_keypair(&ctx.payer);
+
+    let ix = spl_token_2022::instruction::mint_to(
+        &spl_token_2022::ID,
+        mint,
+        token_account,
+        &mint_authority.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+
+    let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, mint_authority],
+        last_blockhash,
+    );
+
+    ctx.banks_client.process_transaction(tx).await.unwrap();
+}
+
+// Lets `delegate` transfer up to `amount` tokens out of the owner token account
+pub async fn approve_token_delegate(
+    program_test_ctx: &RwLock<ProgramTestContext>,
+    owner: &Keypair,
+    token_account: &Pubkey,
+    delegate: &Pubkey,
+    amount: u64,
+) {
+    let mut ctx = program_test_ctx.write().await;
+    let last_blockhash = ctx.last_blockhash;
+    let payer = copy_keypair(&ctx.payer);
+
+    let ix = spl_token::instruction::approve(
+        &spl_token::ID,
+        token_account,
+        delegate,
+        &owner.pubkey(),
+        &[],
+        amount,
+    )
+    .unwrap();
+
+    let tx = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &[ix],
+        Some(&payer.pubkey()),
+        &[&payer, owner],
+        last_blockhash,
+    );
+
+    ctx.banks_client.process_transaction(tx).await.unwrap();
+}
+
 pub async fn create_and_fund_multiple_accounts(
     program_test: &mut ProgramTest,
     number: usize,
@@ -182,14 +499,15 @@ pub async fn create_and_simulate_perpetuals_view_ix<T: InstructionData, U: Borsh
         return Err(result.err().unwrap());
     }
 
+    let result = result.unwrap();
+
+    // Failed simulations don't return any data
+    if let Some(Err(err)) = result.result {
+        return Err(BanksClientError::TransactionError(err));
+    }
+
     // Extract the returned data
-    let mut return_data: Vec<u8> = result
-        .unwrap()
-        .simulation_details
-        .unwrap()
-        .return_data
-        .unwrap()
-        .data;
+    let mut return_data: Vec<u8> = result.simulation_details.unwrap().return_data.unwrap().data;
 
     let result_expected_len = std::mem::size_of::<U>();
 
@@ -348,3 +666,13 @@ pub async fn warp_forward(ctx: &RwLock<ProgramTestContext>, seconds: i64) {
 
     ctx.last_blockhash = blockhash;
 }
+
+// The test bank registers a new blockhash every slot in real time, long running
+// tests have to refresh the cached one before it expires
+pub async fn refresh_blockhash(ctx: &RwLock<ProgramTestContext>) {
+    let mut ctx = ctx.write().await;
+
+    let blockhash = ctx.banks_client.get_latest_blockhash().await.unwrap();
+
+    ctx.last_blockhash = blockhash;
+}
@@ -17,11 +17,7 @@ import {
   wrapSolIfNeeded,
 } from "@/utils/transactionHelpers";
 import { BN } from "@coral-xyz/anchor";
-import {
-  getAssociatedTokenAddress,
-  NATIVE_MINT,
-  TOKEN_PROGRAM_ID,
-} from "@solana/spl-token";
+import { getAssociatedTokenAddress, NATIVE_MINT } from "@solana/spl-token";
 import { WalletContextState } from "@solana/wallet-adapter-react";
 import { Connection, TransactionInstruction } from "@solana/web3.js";
 
@@ -43,7 +39,9 @@ export async function changeCollateral(
 
   let userCustodyTokenAccount = await getAssociatedTokenAddress(
     custody.mint,
-    publicKey
+    publicKey,
+    false,
+    custody.tokenProgram
   );
 
   let preInstructions: TransactionInstruction[] = [];
@@ -81,6 +79,7 @@ export async function changeCollateral(
         collateral,
       })
       .accounts({
+        authority: publicKey,
         owner: publicKey,
         fundingAccount: userCustodyTokenAccount, // user token account for custody token account
         transferAuthority: TRANSFER_AUTHORITY,
@@ -90,7 +89,9 @@ export async function changeCollateral(
         custody: custody.address,
         custodyOracleAccount: custody.oracle.oracleAccount,
         custodyTokenAccount: custody.tokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralCustodyTokenMint: custody.mint,
+        tokenProgram: custody.tokenProgram,
+        tradingDelegate: null,
       });
   } else {
     if (position.token == TokenE.SOL) {
@@ -110,6 +111,7 @@ export async function changeCollateral(
         collateralUsd,
       })
       .accounts({
+        authority: publicKey,
         owner: publicKey,
         receivingAccount: userCustodyTokenAccount,
         transferAuthority: TRANSFER_AUTHORITY,
@@ -119,7 +121,9 @@ export async function changeCollateral(
         custody: custody.address,
         custodyOracleAccount: custody.oracle.oracleAccount,
         custodyTokenAccount: custody.tokenAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        collateralCustodyTokenMint: custody.mint,
+        tokenProgram: custody.tokenProgram,
+        tradingDelegate: null,
       });
   }
 
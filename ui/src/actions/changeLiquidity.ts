@@ -17,7 +17,7 @@ import {
   wrapSolIfNeeded,
 } from "@/utils/transactionHelpers";
 import { BN } from "@coral-xyz/anchor";
-import { getAssociatedTokenAddress, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { getAssociatedTokenAddress } from "@solana/spl-token";
 import { WalletContextState } from "@solana/wallet-adapter-react";
 import {
   Connection,
@@ -41,12 +41,16 @@ export async function changeLiquidity(
 
   let lpTokenAccount = await getAssociatedTokenAddress(
     pool.getLpTokenMint(),
-    publicKey
+    publicKey,
+    false,
+    pool.lpTokenProgram
   );
 
   let userCustodyTokenAccount = await getAssociatedTokenAddress(
     custody.mint,
-    publicKey
+    publicKey,
+    false,
+    custody.tokenProgram
   );
 
   let preInstructions: TransactionInstruction[] = [];
@@ -55,7 +59,8 @@ export async function changeLiquidity(
     publicKey,
     publicKey,
     pool.getLpTokenMint(),
-    connection
+    connection,
+    pool.lpTokenProgram
   );
 
   if (ataIx) preInstructions.push(ataIx);
@@ -65,7 +70,8 @@ export async function changeLiquidity(
       publicKey,
       publicKey,
       custody.mint,
-      connection
+      connection,
+      custody.tokenProgram
     );
 
     if (ataIx) preInstructions.push(ataIx);
@@ -111,8 +117,10 @@ export async function changeLiquidity(
         custody: custody.address,
         custodyOracleAccount: custody.oracle.oracleAccount,
         custodyTokenAccount: custody.tokenAccount,
+        custodyTokenMint: custody.mint,
         lpTokenMint: pool.getLpTokenMint(),
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram: custody.tokenProgram,
+        lpTokenProgram: pool.lpTokenProgram,
       })
       .remainingAccounts(pool.getCustodyMetas());
 
@@ -138,8 +146,10 @@ export async function changeLiquidity(
         custody: custody.address,
         custodyOracleAccount: custody.oracle.oracleAccount,
         custodyTokenAccount: custody.tokenAccount,
+        custodyTokenMint: custody.mint,
         lpTokenMint: pool.getLpTokenMint(),
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenProgram: custody.tokenProgram,
+        lpTokenProgram: pool.lpTokenProgram,
       })
       .remainingAccounts(pool.getCustodyMetas());
   }
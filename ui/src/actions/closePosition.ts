@@ -16,7 +16,7 @@ import {
   unwrapSolIfNeeded,
 } from "@/utils/transactionHelpers";
 import { BN } from "@coral-xyz/anchor";
-import { getAssociatedTokenAddress, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { getAssociatedTokenAddress } from "@solana/spl-token";
 import { WalletContextState } from "@solana/wallet-adapter-react";
 import { Connection, TransactionInstruction } from "@solana/web3.js";
 
@@ -41,7 +41,9 @@ export async function closePosition(
 
   let userCustodyTokenAccount = await getAssociatedTokenAddress(
     custody.mint,
-    publicKey
+    publicKey,
+    false,
+    custody.tokenProgram
   );
 
   let preInstructions: TransactionInstruction[] = [];
@@ -50,7 +52,8 @@ export async function closePosition(
     publicKey,
     publicKey,
     custody.mint,
-    connection
+    connection,
+    custody.tokenProgram
   );
 
   if (ataIx) preInstructions.push(ataIx);
@@ -64,6 +67,7 @@ export async function closePosition(
       price: adjustedPrice,
     })
     .accounts({
+      authority: publicKey,
       owner: publicKey,
       receivingAccount: userCustodyTokenAccount,
       transferAuthority: TRANSFER_AUTHORITY,
@@ -73,7 +77,9 @@ export async function closePosition(
       custody: custody.address,
       custodyOracleAccount: custody.oracle.oracleAccount,
       custodyTokenAccount: custody.tokenAccount,
-      tokenProgram: TOKEN_PROGRAM_ID,
+      collateralCustodyTokenMint: custody.mint,
+      tokenProgram: custody.tokenProgram,
+      tradingDelegate: null,
     })
     .preInstructions(preInstructions);
 
@@ -19,7 +19,7 @@ import {
 import { ViewHelper } from "@/utils/viewHelpers";
 import { BN } from "@coral-xyz/anchor";
 import { findProgramAddressSync } from "@coral-xyz/anchor/dist/cjs/utils/pubkey";
-import { TOKEN_PROGRAM_ID, getAssociatedTokenAddress } from "@solana/spl-token";
+import { getAssociatedTokenAddress } from "@solana/spl-token";
 import { WalletContextState } from "@solana/wallet-adapter-react";
 import {
   Connection,
@@ -54,9 +54,24 @@ export async function openPositionBuilder(
 
   let userCustodyTokenAccount = await getAssociatedTokenAddress(
     positionCustody.mint,
-    publicKey
+    publicKey,
+    false,
+    positionCustody.tokenProgram
   );
 
+  let positionCounterAccount = findProgramAddressSync(
+    [Buffer.from("position_counter"), publicKey.toBuffer()],
+    perpetual_program.programId
+  )[0];
+
+  let positionCounter =
+    await perpetual_program.account.positionCounter.fetchNullable(
+      positionCounterAccount
+    );
+  let positionIndex = positionCounter
+    ? positionCounter.nextIndex
+    : new BN(0);
+
   let positionAccount = findProgramAddressSync(
     [
       Buffer.from("position"),
@@ -65,6 +80,7 @@ export async function openPositionBuilder(
       positionCustody.address.toBuffer(),
       // @ts-ignore
       side.toString() == "Long" ? [1] : [2],
+      positionIndex.toArrayLike(Buffer, "le", 8),
     ],
     perpetual_program.programId
   )[0];
@@ -146,7 +162,8 @@ export async function openPositionBuilder(
       publicKey,
       publicKey,
       positionCustody.mint,
-      connection
+      connection,
+      positionCustody.tokenProgram
     );
 
     if (ataIx) preInstructions.push(ataIx);
@@ -174,17 +191,21 @@ export async function openPositionBuilder(
   };
 
   let methodBuilder = perpetual_program.methods.openPosition(params).accounts({
+    authority: publicKey,
     owner: publicKey,
     fundingAccount: userCustodyTokenAccount,
     transferAuthority: TRANSFER_AUTHORITY,
     perpetuals: PERPETUALS_ADDRESS,
     pool: pool.address,
+    positionCounter: positionCounterAccount,
     position: positionAccount,
     custody: positionCustody.address,
     custodyOracleAccount: positionCustody.oracle.oracleAccount,
     custodyTokenAccount: positionCustody.tokenAccount,
+    collateralCustodyTokenMint: positionCustody.mint,
     systemProgram: SystemProgram.programId,
-    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgram: positionCustody.tokenProgram,
+    tradingDelegate: null,
   });
 
   if (preInstructions) {
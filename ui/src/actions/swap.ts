@@ -16,7 +16,7 @@ import {
 } from "@/utils/transactionHelpers";
 import { BN } from "@coral-xyz/anchor";
 import { MethodsBuilder } from "@coral-xyz/anchor/dist/cjs/program/namespace/methods";
-import { getAssociatedTokenAddress, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { getAssociatedTokenAddress } from "@solana/spl-token";
 import { WalletContextState } from "@solana/wallet-adapter-react";
 import { Connection, TransactionInstruction } from "@solana/web3.js";
 
@@ -44,7 +44,9 @@ export async function swapTransactionBuilder(
 
   let fundingAccount = await getAssociatedTokenAddress(
     receivingCustody.mint,
-    publicKey
+    publicKey,
+    false,
+    receivingCustody.tokenProgram
   );
 
   const dispensingCustody = pool.getCustodyAccount(bottomToken)!;
@@ -52,7 +54,9 @@ export async function swapTransactionBuilder(
   console.log("receiving accoutn", dispensingCustody.getTokenE());
   let receivingAccount = await getAssociatedTokenAddress(
     dispensingCustody.mint,
-    publicKey
+    publicKey,
+    false,
+    dispensingCustody.tokenProgram
   );
 
   let preInstructions: TransactionInstruction[] = [];
@@ -63,7 +67,8 @@ export async function swapTransactionBuilder(
       publicKey,
       publicKey,
       receivingCustody.mint,
-      connection
+      connection,
+      receivingCustody.tokenProgram
     );
 
     if (ataIx) preInstructions.push(ataIx);
@@ -88,7 +93,8 @@ export async function swapTransactionBuilder(
     publicKey,
     publicKey,
     dispensingCustody.mint,
-    connection
+    connection,
+    dispensingCustody.tokenProgram
   );
 
   if (ataIx) preInstructions.push(ataIx);
@@ -146,12 +152,15 @@ export async function swapTransactionBuilder(
     receivingCustody: receivingCustody.address,
     receivingCustodyOracleAccount: receivingCustody.oracle.oracleAccount,
     receivingCustodyTokenAccount: receivingCustody.tokenAccount,
+    receivingCustodyTokenMint: receivingCustody.mint,
 
     dispensingCustody: dispensingCustody.address,
     dispensingCustodyOracleAccount: dispensingCustody.oracle.oracleAccount,
     dispensingCustodyTokenAccount: dispensingCustody.tokenAccount,
+    dispensingCustodyTokenMint: dispensingCustody.mint,
 
-    tokenProgram: TOKEN_PROGRAM_ID,
+    tokenProgram: receivingCustody.tokenProgram,
+    dispensingTokenProgram: dispensingCustody.tokenProgram,
   });
 
   if (preInstructions) {
@@ -40,6 +40,7 @@ export class CustodyAccount {
   // bumps for address validatio;
   public bump: number;
   public tokenAccountBump: number;
+  public tokenProgram: PublicKey;
 
   public address: PublicKey;
 
@@ -66,6 +67,7 @@ export class CustodyAccount {
 
     this.bump = custody.bump;
     this.tokenAccountBump = custody.tokenAccountBump;
+    this.tokenProgram = custody.tokenProgram;
 
     this.address = address;
   }
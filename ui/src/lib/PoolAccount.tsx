@@ -17,6 +17,7 @@ export class PoolAccount {
   public bump: number;
   public lpTokenBump: number;
   public inceptionTime: BN;
+  public lpTokenProgram: PublicKey;
 
   // public lpDecimals: number = 8;
   public address: PublicKey;
@@ -33,6 +34,7 @@ export class PoolAccount {
     this.bump = pool.bump;
     this.lpTokenBump = pool.lpTokenBump;
     this.inceptionTime = pool.inceptionTime;
+    this.lpTokenProgram = pool.lpTokenProgram;
 
     let tempCustodies: Record<string, CustodyAccount> = {};
     pool.custodies.forEach((custody: PublicKey) => {
@@ -10,6 +10,7 @@ export interface Pool {
   bump: number;
   lpTokenBump: number;
   inceptionTime: BN;
+  lpTokenProgram: PublicKey;
 }
 
 export interface TokenRatios {
@@ -40,6 +41,7 @@ export interface Custody {
 
   bump: number;
   tokenAccountBump: number;
+  tokenProgram: PublicKey;
 }
 
 export interface BorrowRateParams {
@@ -19,11 +19,14 @@ export async function createAtaIfNeeded(
   publicKey: PublicKey,
   payer: PublicKey,
   mint: PublicKey,
-  connection: Connection
+  connection: Connection,
+  tokenProgram: PublicKey = TOKEN_PROGRAM_ID
 ): Promise<TransactionInstruction | null> {
   const associatedTokenAccount = await getAssociatedTokenAddress(
     mint,
-    publicKey
+    publicKey,
+    false,
+    tokenProgram
   );
 
   console.log("creating ata", associatedTokenAccount.toString());
@@ -33,7 +36,8 @@ export async function createAtaIfNeeded(
       payer,
       associatedTokenAccount,
       publicKey,
-      mint
+      mint,
+      tokenProgram
     );
   }
 